@@ -47,14 +47,21 @@ pub fn run(matches: &ArgMatches) -> Result<Response, Error> {
             }
             ("info", Some(submatches)) => client.get_account(submatches),
             ("list", Some(submatches)) => client.get_accounts(submatches),
+            ("transactions", Some(submatches)) => client.get_account_transactions(submatches),
             ("update", Some(submatches)) => client.put_account(submatches),
             ("update-settings", Some(submatches)) => client.put_account_settings(submatches),
             _ => Err(Error::UsageErr("ilp-cli help accounts")),
         },
+        ("fees", Some(fees_matches)) => match fees_matches.subcommand() {
+            ("list", Some(submatches)) => client.get_fees(submatches),
+            ("set", Some(submatches)) => client.put_fees(submatches),
+            _ => Err(Error::UsageErr("ilp-cli help fees")),
+        },
         ("pay", Some(pay_matches)) => client.post_account_payments(pay_matches),
         ("rates", Some(rates_matches)) => match rates_matches.subcommand() {
             ("list", Some(submatches)) => client.get_rates(submatches),
             ("set-all", Some(submatches)) => client.put_rates(submatches),
+            ("history", Some(submatches)) => client.get_rate_history(submatches),
             _ => Err(Error::UsageErr("ilp-cli help rates")),
         },
         ("routes", Some(routes_matches)) => match routes_matches.subcommand() {
@@ -67,6 +74,9 @@ pub fn run(matches: &ArgMatches) -> Result<Response, Error> {
             ("set-all", Some(submatches)) => client.put_settlement_engines(submatches),
             _ => Err(Error::UsageErr("ilp-cli help settlement-engines")),
         },
+        ("reconciliation", Some(reconciliation_matches)) => {
+            client.get_reconciliation(reconciliation_matches)
+        }
         ("status", Some(status_matches)) => client.get_root(status_matches),
         ("logs", Some(log_level)) => client.put_tracing_level(log_level),
         ("testnet", Some(testnet_matches)) => match testnet_matches.subcommand() {
@@ -98,6 +108,18 @@ impl NodeClient<'_> {
             .map_err(Error::SendErr)
     }
 
+    // GET /accounts/:username/transactions
+    fn get_account_transactions(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, mut args) = extract_args(matches);
+        let user = args.remove("username").unwrap(); // infallible unwrap
+        self.client
+            .get(&format!("{}/accounts/{}/transactions", self.url, user))
+            .bearer_auth(auth)
+            .query(&args)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
     // POST /accounts
     fn post_accounts(&self, matches: &ArgMatches) -> Result<Response, Error> {
         let (auth, args) = extract_args(matches);
@@ -233,6 +255,28 @@ impl NodeClient<'_> {
             .map_err(Error::SendErr)
     }
 
+    // GET /fees
+    fn get_fees(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .get(&format!("{}/fees", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // PUT /fees
+    fn put_fees(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .put(&format!("{}/fees", self.url))
+            .bearer_auth(auth)
+            .header("Content-Type", "application/json")
+            .body(args["policy"].to_owned())
+            .send()
+            .map_err(Error::SendErr)
+    }
+
     // GET /rates
     fn get_rates(&self, _matches: &ArgMatches) -> Result<Response, Error> {
         self.client
@@ -252,6 +296,19 @@ impl NodeClient<'_> {
             .map_err(Error::SendErr)
     }
 
+    // GET /rates/history
+    fn get_rate_history(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let query: Vec<(&str, &str)> = ["asset", "from", "to", "limit"]
+            .iter()
+            .filter_map(|name| matches.value_of(name).map(|value| (*name, value)))
+            .collect();
+        self.client
+            .get(&format!("{}/rates/history", self.url))
+            .query(&query)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
     // GET /routes
     fn get_routes(&self, _matches: &ArgMatches) -> Result<Response, Error> {
         self.client
@@ -304,6 +361,16 @@ impl NodeClient<'_> {
             .map_err(Error::SendErr)
     }
 
+    // GET /reconciliation
+    fn get_reconciliation(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .get(&format!("{}/reconciliation", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
     // GET /
     fn get_root(&self, _matches: &ArgMatches) -> Result<Response, Error> {
         self.client
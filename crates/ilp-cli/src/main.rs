@@ -124,6 +124,14 @@ mod interface_tests {
         ]);
     }
 
+    #[test]
+    fn accounts_transactions() {
+        should_parse(&[
+            "ilp-cli accounts transactions alice --auth foo", // minimal
+            "ilp-cli accounts transactions alice --auth foo --from 1577836800000 --to 1580515200000 --before 42 --after 7 --limit 10", // maximal
+        ]);
+    }
+
     #[test]
     fn accounts_update_settings() {
         should_parse(&[
@@ -133,6 +141,21 @@ mod interface_tests {
         ]);
     }
 
+    #[test]
+    fn fees_list() {
+        should_parse(&[
+            "ilp-cli fees list --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn fees_set() {
+        should_parse(&[
+            r#"ilp-cli fees set --auth foo {}"#, // minimal
+            r#"ilp-cli fees set --auth foo {"pairs":[{"from":"USD","to":"EUR","spread":0.01}],"rounding":"down"}"#, // pairs
+        ]);
+    }
+
     #[test]
     fn pay() {
         should_parse(&[
@@ -147,6 +170,14 @@ mod interface_tests {
         ]);
     }
 
+    #[test]
+    fn rates_history() {
+        should_parse(&[
+            "ilp-cli rates history XRP", // minimal
+            "ilp-cli rates history XRP --from 1577836800000 --to 1580515200000 --limit 10", // maximal
+        ]);
+    }
+
     #[test]
     fn rates_set_all() {
         should_parse(&[
@@ -171,6 +202,13 @@ mod interface_tests {
         ]);
     }
 
+    #[test]
+    fn reconciliation() {
+        should_parse(&[
+            "ilp-cli reconciliation --auth foo", // minimal
+        ]);
+    }
+
     #[test]
     fn routes_set_all() {
         should_parse(&[
@@ -9,11 +9,14 @@ pub fn build<'a, 'b>() -> App<'a, 'b> {
             accounts_incoming_payments(),
             accounts_info(),
             accounts_list(),
+            accounts_transactions(),
             accounts_update(),
             accounts_update_settings(),
         ]),
+        fees().subcommands(vec![fees_list(), fees_set()]),
         pay(),
-        rates().subcommands(vec![rates_list(), rates_set_all()]),
+        rates().subcommands(vec![rates_list(), rates_set_all(), rates_history()]),
+        reconciliation(),
         routes().subcommands(vec![routes_list(), routes_set(), routes_set_all()]),
         settlement_engines().subcommands(vec![settlement_engines_set_all()]),
         status(),
@@ -263,6 +266,38 @@ fn accounts_list<'a, 'b>() -> App<'a, 'b> {
     AuthorizedSubCommand::with_name("list").about("List all accounts on this node")
 }
 
+fn accounts_transactions<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("transactions")
+        .about("Returns the history of the changes of an account's balance, newest first")
+        .args(&[
+            Arg::with_name("username")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The username of the account whose transactions to return"),
+            Arg::with_name("from")
+                .long("from")
+                .takes_value(true)
+                .help("Only return the transactions at or after this time, in milliseconds since the Unix epoch"),
+            Arg::with_name("to")
+                .long("to")
+                .takes_value(true)
+                .help("Only return the transactions before this time, in milliseconds since the Unix epoch"),
+            Arg::with_name("before")
+                .long("before")
+                .takes_value(true)
+                .help("Only return the transactions recorded before the one with this ID (the `next` value of the previous page)"),
+            Arg::with_name("after")
+                .long("after")
+                .takes_value(true)
+                .help("Only return the transactions recorded after the one with this ID"),
+            Arg::with_name("limit")
+                .long("limit")
+                .takes_value(true)
+                .help("The maximum number of transactions to return"),
+        ])
+}
+
 fn accounts_update_settings<'a, 'b>() -> App<'a, 'b> {
     AuthorizedSubCommand::with_name("update-settings")
         .about("Update account settings (limited fields only) on this node")
@@ -322,6 +357,27 @@ fn pay<'a, 'b>() -> App<'a, 'b> {
         ])
 }
 
+fn fees<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("fees").about("Operations for interacting with the spreads and fees")
+}
+
+fn fees_list<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("list")
+        .about("View the spreads and fees this node charges on the packets it forwards")
+}
+
+fn fees_set<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("set")
+        .about("Overwrite the spreads and fees this node charges on the packets it forwards")
+        .arg(
+            Arg::with_name("policy")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The fee policy, as a JSON object with the optional keys pairs, accounts, routing_relations and rounding"),
+        )
+}
+
 fn rates<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("rates").about("Operations for interacting with exchange rates")
 }
@@ -342,6 +398,30 @@ fn rates_set_all<'a, 'b>() -> App<'a, 'b> {
         )
 }
 
+fn rates_history<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("history")
+        .about("Returns the rates an asset had over time, oldest first")
+        .args(&[
+            Arg::with_name("asset")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The code of the asset whose rates to return"),
+            Arg::with_name("from")
+                .long("from")
+                .takes_value(true)
+                .help("Only return the rates recorded at or after this time, in milliseconds since the Unix epoch (the `next` value of the previous page)"),
+            Arg::with_name("to")
+                .long("to")
+                .takes_value(true)
+                .help("Only return the rates recorded before this time, in milliseconds since the Unix epoch"),
+            Arg::with_name("limit")
+                .long("limit")
+                .takes_value(true)
+                .help("The maximum number of rates to return"),
+        ])
+}
+
 fn routes<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("routes").about("Operations for interacting with the routing table")
 }
@@ -406,6 +486,12 @@ fn logs<'a, 'b>() -> App<'a, 'b> {
             .help("The desired log level (error, debug, trace)")])
 }
 
+fn reconciliation<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("reconciliation").about(
+        "Compare the balance of each account with its settlements and the rest of its history, and flag the discrepancies",
+    )
+}
+
 fn status<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("status").about("Query the status of the server")
 }
@@ -0,0 +1,204 @@
+//! Routing benchmark
+//! Tracks how long it takes to find the next hop for a packet in a routing table with 100k
+//! prefixes, which is about the size of the table of a node with many local accounts.
+//! The `longest_match` benchmarks measure the lookup alone while the `router` benchmarks
+//! measure the whole `Router` service, including fetching the account from the store.
+use async_trait::async_trait;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use interledger::errors::{AccountStoreError, AddressStoreError};
+use interledger::packet::{Address, FulfillBuilder, PrepareBuilder};
+use interledger::router::{Route, Router, RouterStore, RoutingTable};
+use interledger::service::{
+    outgoing_service_fn, Account, AccountStore, AddressStore, IncomingRequest, IncomingService,
+    Username,
+};
+use once_cell::sync::Lazy;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use tokio::runtime::Runtime;
+use uuid::Uuid;
+
+const ROUTE_COUNT: usize = 100_000;
+/// Number of distinct first segments below `g.`, so the table is both wide and deep
+const BRANCHES: usize = 1_000;
+
+static USERNAME: Lazy<Username> = Lazy::new(|| Username::from_str("bench").unwrap());
+static ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("g.connector").unwrap());
+
+/// Builds a table with the default route and `ROUTE_COUNT` prefixes such as `g.17.100017`
+fn routing_table() -> RoutingTable<Route> {
+    (0..ROUTE_COUNT)
+        .map(|i| {
+            (
+                format!("g.{}.{}", i % BRANCHES, i),
+                Route::from(Uuid::new_v4()),
+            )
+        })
+        .chain(std::iter::once((
+            String::new(),
+            Route::from(Uuid::new_v4()),
+        )))
+        .collect()
+}
+
+/// Addresses below routes in the table, e.g. `g.17.100017.alice.1234`
+fn hits() -> Vec<Address> {
+    (0..1_000)
+        .map(|i| i * 97 % ROUTE_COUNT)
+        .map(|i| Address::from_str(&format!("g.{}.{}.alice.{}", i % BRANCHES, i, i)).unwrap())
+        .collect()
+}
+
+/// Addresses which share a prefix with routes in the table but only match the
+/// default route, e.g. `g.17.1000170`
+fn misses() -> Vec<Address> {
+    (0..1_000)
+        .map(|i| i * 97 % ROUTE_COUNT)
+        .map(|i| Address::from_str(&format!("g.{}.{}0", i % BRANCHES, i)).unwrap())
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+struct BenchAccount(Uuid);
+
+impl Account for BenchAccount {
+    fn id(&self) -> Uuid {
+        self.0
+    }
+
+    fn username(&self) -> &Username {
+        &USERNAME
+    }
+
+    fn ilp_address(&self) -> &Address {
+        &ILP_ADDRESS
+    }
+
+    fn asset_scale(&self) -> u8 {
+        9
+    }
+
+    fn asset_code(&self) -> &str {
+        "XYZ"
+    }
+}
+
+#[derive(Clone)]
+struct BenchStore {
+    routes: Arc<RoutingTable<Route>>,
+}
+
+#[async_trait]
+impl AccountStore for BenchStore {
+    type Account = BenchAccount;
+
+    async fn get_accounts(
+        &self,
+        account_ids: Vec<Uuid>,
+    ) -> Result<Vec<BenchAccount>, AccountStoreError> {
+        Ok(account_ids.into_iter().map(BenchAccount).collect())
+    }
+
+    async fn get_account_id_from_username(
+        &self,
+        username: &Username,
+    ) -> Result<Uuid, AccountStoreError> {
+        Err(AccountStoreError::AccountNotFound(username.to_string()))
+    }
+}
+
+#[async_trait]
+impl AddressStore for BenchStore {
+    async fn set_ilp_address(&self, _ilp_address: Address) -> Result<(), AddressStoreError> {
+        Ok(())
+    }
+
+    async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+        Ok(())
+    }
+
+    fn get_ilp_address(&self) -> Address {
+        ILP_ADDRESS.clone()
+    }
+}
+
+impl RouterStore for BenchStore {
+    fn routing_table(&self) -> Arc<RoutingTable<Route>> {
+        self.routes.clone()
+    }
+}
+
+fn requests(destinations: Vec<Address>) -> Vec<IncomingRequest<BenchAccount>> {
+    destinations
+        .into_iter()
+        .map(|destination| IncomingRequest {
+            from: BenchAccount(Uuid::new_v4()),
+            prepare: PrepareBuilder {
+                destination,
+                amount: 100,
+                execution_condition: &[1; 32],
+                expires_at: UNIX_EPOCH,
+                data: &[],
+            }
+            .build(),
+        })
+        .collect()
+}
+
+fn longest_match(c: &mut Criterion) {
+    let table = routing_table();
+    let hits = hits();
+    let misses = misses();
+
+    c.bench_function("longest_match_100k_hits", |b| {
+        b.iter(|| {
+            for destination in &hits {
+                black_box(table.longest_match(destination));
+            }
+        })
+    });
+    c.bench_function("longest_match_100k_misses", |b| {
+        b.iter(|| {
+            for destination in &misses {
+                black_box(table.longest_match(destination));
+            }
+        })
+    });
+}
+
+fn router(c: &mut Criterion) {
+    let mut rt = Runtime::new().unwrap();
+    let store = BenchStore {
+        routes: Arc::new(routing_table()),
+    };
+    let router = Router::new(
+        store,
+        outgoing_service_fn(|_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        }),
+    );
+    let requests = requests(hits());
+
+    c.bench_function("router_100k_routes", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let mut router = router.clone();
+                for request in &requests {
+                    black_box(router.handle_request(request.clone()).await.unwrap());
+                }
+            })
+        })
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = longest_match, router
+}
+criterion_main!(benches);
@@ -1,4 +1,5 @@
 use interledger::{
+    btp::{BtpConnectionEvent, BtpConnectionStatus},
     ccp::CcpRoutingAccount,
     service::{
         Account, IlpResult, IncomingRequest, IncomingService, OutgoingRequest, OutgoingService,
@@ -6,6 +7,7 @@ use interledger::{
 };
 use metrics::{self, labels, recorder, Key};
 use std::time::Instant;
+use tokio::sync::broadcast::{self, RecvError};
 
 pub async fn incoming_metrics<A: Account + CcpRoutingAccount>(
     request: IncomingRequest<A>,
@@ -80,3 +82,21 @@ pub async fn outgoing_metrics<A: Account + CcpRoutingAccount>(
 
     result
 }
+
+/// Counts the state changes of the BTP connections the node dials, until the node shuts down
+pub async fn btp_connection_metrics(mut events: broadcast::Receiver<BtpConnectionEvent>) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+        let name = match event.state.status {
+            BtpConnectionStatus::Connected => "btp.connection.connected",
+            BtpConnectionStatus::Reconnecting => "btp.connection.reconnecting",
+            BtpConnectionStatus::Failed => "btp.connection.failed",
+        };
+        let labels = labels!("username" => event.username.to_string());
+        recorder().increment_counter(Key::from_name_and_labels(name, labels), 1);
+    }
+}
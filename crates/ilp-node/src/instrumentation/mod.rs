@@ -6,5 +6,5 @@ pub mod trace;
 #[cfg(feature = "monitoring")]
 pub mod prometheus;
 
-#[cfg(feature = "google-pubsub")]
-pub mod google_pubsub;
+#[cfg(feature = "packet-records")]
+pub mod packet_records;
@@ -0,0 +1,145 @@
+use super::{PacketRecord, Sink, SinkError};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+};
+use tracing::debug;
+
+/// Newline-delimited JSON file the records are appended to
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct FileSinkConfig {
+    pub path: PathBuf,
+    /// Size, in bytes, above which the file is rotated. Defaults to 100MB
+    #[serde(default = "FileSinkConfig::default_max_file_size")]
+    pub max_file_size: u64,
+    /// Number of rotated files which are kept, as `<path>.1` (the most recent)
+    /// to `<path>.<max_files>`. Defaults to 10
+    #[serde(default = "FileSinkConfig::default_max_files")]
+    pub max_files: u32,
+}
+
+impl FileSinkConfig {
+    fn default_max_file_size() -> u64 {
+        100 * 1024 * 1024
+    }
+
+    fn default_max_files() -> u32 {
+        10
+    }
+}
+
+fn rotated_path(path: &Path, index: u32) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", index));
+    rotated.into()
+}
+
+/// Ignores the error if the file does not exist
+fn ignore_not_found(result: std::io::Result<()>) -> std::io::Result<()> {
+    match result {
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+pub(super) struct FileSink {
+    config: FileSinkConfig,
+    file: File,
+    size: u64,
+}
+
+impl FileSink {
+    pub(super) async fn open(config: FileSinkConfig) -> Result<Self, SinkError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)
+            .await?;
+        let size = file.metadata().await?.len();
+        Ok(FileSink { config, file, size })
+    }
+
+    /// Renames the file to `<path>.1`, after shifting the files rotated before
+    /// and removing the oldest one
+    async fn rotate(&mut self) -> Result<(), SinkError> {
+        debug!(
+            "Rotating packet records file {}",
+            self.config.path.display()
+        );
+        self.file.flush().await?;
+        let path = &self.config.path;
+        if self.config.max_files == 0 {
+            ignore_not_found(fs::remove_file(path).await)?;
+        } else {
+            ignore_not_found(fs::remove_file(rotated_path(path, self.config.max_files)).await)?;
+            for index in (1..self.config.max_files).rev() {
+                ignore_not_found(
+                    fs::rename(rotated_path(path, index), rotated_path(path, index + 1)).await,
+                )?;
+            }
+            fs::rename(path, rotated_path(path, 1)).await?;
+        }
+
+        *self = FileSink::open(self.config.clone()).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for FileSink {
+    async fn write(&mut self, records: &[Arc<PacketRecord>]) -> Result<(), SinkError> {
+        let mut lines = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut lines, record.as_ref())?;
+            lines.push(b'\n');
+        }
+
+        if self.size > 0 && self.size + lines.len() as u64 > self.config.max_file_size {
+            self.rotate().await?;
+        }
+        self.file.write_all(&lines).await?;
+        self.file.flush().await?;
+        self.size += lines.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::record;
+    use super::*;
+
+    #[tokio::test]
+    async fn rotates_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("packets.ndjson");
+        let mut sink = FileSink::open(FileSinkConfig {
+            path: path.clone(),
+            max_file_size: 1,
+            max_files: 2,
+        })
+        .await
+        .unwrap();
+
+        for amount in 1..=4 {
+            sink.write(&[Arc::new(record(amount))]).await.unwrap();
+        }
+
+        let read_amount = |path: PathBuf| {
+            let contents = std::fs::read_to_string(path).unwrap();
+            let record: serde_json::Value = serde_json::from_str(contents.trim_end()).unwrap();
+            record["prevHopAmount"].as_u64().unwrap()
+        };
+        assert_eq!(read_amount(path.clone()), 4);
+        assert_eq!(read_amount(rotated_path(&path, 1)), 3);
+        assert_eq!(read_amount(rotated_path(&path, 2)), 2);
+        assert!(!rotated_path(&path, 3).exists());
+    }
+}
@@ -0,0 +1,97 @@
+use super::{PacketRecord, Sink, SinkError};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use yup_oauth2::{
+    authenticator::{Authenticator, DefaultHyperClient, HyperClientBuilder},
+    read_service_account_key, ServiceAccountAuthenticator,
+};
+
+static TOKEN_SCOPES: &[&str] = &["https://www.googleapis.com/auth/pubsub"];
+
+/// Configuration for the Google PubSub packet publisher
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct PubsubConfig {
+    /// Path to the Service Account Key JSON file.
+    /// You can obtain this file by logging into [console.cloud.google.com](https://console.cloud.google.com/)
+    pub service_account_credentials: String,
+    pub project_id: String,
+    pub topic: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PubsubMessage {
+    message_id: Option<String>,
+    data: Option<String>,
+    attributes: Option<HashMap<String, String>>,
+    publish_time: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PubsubRequest {
+    messages: Vec<PubsubMessage>,
+}
+
+type HyperConnector = <DefaultHyperClient as HyperClientBuilder>::Connector;
+
+pub(super) struct PubsubSink {
+    client: Client,
+    api_endpoint: String,
+    /// Fetches the access tokens and caches them
+    authenticator: Authenticator<HyperConnector>,
+}
+
+impl PubsubSink {
+    pub(super) async fn new(config: PubsubConfig) -> Result<Self, SinkError> {
+        let key = read_service_account_key(config.service_account_credentials.as_str()).await?;
+        let authenticator = ServiceAccountAuthenticator::builder(key).build().await?;
+        Ok(PubsubSink {
+            // TODO make sure the client uses HTTP/2
+            client: Client::new(),
+            api_endpoint: format!(
+                "https://pubsub.googleapis.com/v1/projects/{}/topics/{}:publish",
+                config.project_id, config.topic
+            ),
+            authenticator,
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for PubsubSink {
+    async fn write(&mut self, records: &[Arc<PacketRecord>]) -> Result<(), SinkError> {
+        let token = self
+            .authenticator
+            .token(TOKEN_SCOPES)
+            .await
+            .map_err(|err| SinkError::Refused(format!("could not fetch OAuth token: {}", err)))?;
+
+        let mut messages = Vec::with_capacity(records.len());
+        for record in records {
+            messages.push(PubsubMessage {
+                // TODO should there be an ID?
+                message_id: None,
+                data: Some(base64::encode(&serde_json::to_string(record.as_ref())?)),
+                attributes: None,
+                publish_time: None,
+            });
+        }
+        let response = self
+            .client
+            .post(self.api_endpoint.as_str())
+            .bearer_auth(token.as_str())
+            .json(&PubsubRequest { messages })
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            let body = response.text().await.unwrap_or_default();
+            Err(SinkError::Refused(format!("{} {}", status, body)))
+        }
+    }
+}
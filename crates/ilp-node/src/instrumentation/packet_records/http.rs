@@ -0,0 +1,132 @@
+use super::{PacketRecord, Sink, SinkError};
+use async_trait::async_trait;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    Client, RequestBuilder,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use url::Url;
+
+const DEFAULT_TIMEOUT_MS: u64 = 30_000;
+/// Content type of the Kafka REST proxy API (v2) for JSON records
+const KAFKA_JSON_CONTENT_TYPE: &str = "application/vnd.kafka.json.v2+json";
+
+/// HTTP endpoint which gets each batch of records POSTed as a JSON array
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct WebhookSinkConfig {
+    pub url: Url,
+    /// Extra headers sent with every request, for example to authenticate the node
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// How long to wait for the endpoint to respond. Defaults to 30 seconds
+    pub timeout_ms: Option<u64>,
+}
+
+/// Kafka topic, written through a [Kafka REST proxy](https://docs.confluent.io/platform/current/kafka-rest/)
+/// or any endpoint compatible with its v2 API
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct KafkaSinkConfig {
+    /// URL of the REST proxy, for example `http://localhost:8082`
+    pub url: Url,
+    pub topic: String,
+    /// How long to wait for the REST proxy to respond. Defaults to 30 seconds
+    pub timeout_ms: Option<u64>,
+}
+
+fn build_client(
+    headers: &HashMap<String, String>,
+    timeout_ms: Option<u64>,
+) -> Result<Client, SinkError> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| SinkError::Config(format!("invalid header {}: {}", name, err)))?;
+        let value = HeaderValue::from_str(value).map_err(|err| {
+            SinkError::Config(format!("invalid value of header {}: {}", name, err))
+        })?;
+        header_map.insert(name, value);
+    }
+    Ok(Client::builder()
+        .default_headers(header_map)
+        .timeout(Duration::from_millis(
+            timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS),
+        ))
+        .build()?)
+}
+
+/// Sends the request and fails if the endpoint does not accept it
+async fn post(request: RequestBuilder) -> Result<(), SinkError> {
+    let response = request.send().await?;
+    let status = response.status();
+    if status.is_success() {
+        Ok(())
+    } else {
+        let body = response.text().await.unwrap_or_default();
+        Err(SinkError::Refused(format!("{} {}", status, body)))
+    }
+}
+
+pub(super) struct WebhookSink {
+    url: Url,
+    client: Client,
+}
+
+impl WebhookSink {
+    pub(super) fn new(config: WebhookSinkConfig) -> Result<Self, SinkError> {
+        Ok(WebhookSink {
+            client: build_client(&config.headers, config.timeout_ms)?,
+            url: config.url,
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for WebhookSink {
+    async fn write(&mut self, records: &[Arc<PacketRecord>]) -> Result<(), SinkError> {
+        let records: Vec<&PacketRecord> = records.iter().map(AsRef::as_ref).collect();
+        post(self.client.post(self.url.clone()).json(&records)).await
+    }
+}
+
+pub(super) struct KafkaSink {
+    /// URL the records of the topic are posted to
+    url: Url,
+    client: Client,
+}
+
+impl KafkaSink {
+    pub(super) fn new(config: KafkaSinkConfig) -> Result<Self, SinkError> {
+        let mut url = config.url;
+        if url.cannot_be_a_base() {
+            return Err(SinkError::Config(format!("{} cannot be a base URL", url)));
+        }
+        url.path_segments_mut()
+            .unwrap()
+            .pop_if_empty()
+            .extend(&["topics", &config.topic]);
+        Ok(KafkaSink {
+            url,
+            client: build_client(&HashMap::new(), config.timeout_ms)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for KafkaSink {
+    async fn write(&mut self, records: &[Arc<PacketRecord>]) -> Result<(), SinkError> {
+        let records: Vec<_> = records
+            .iter()
+            .map(|record| json!({ "value": record.as_ref() }))
+            .collect();
+        let body = serde_json::to_vec(&json!({ "records": records }))?;
+        post(
+            self.client
+                .post(self.url.clone())
+                .header(CONTENT_TYPE, KAFKA_JSON_CONTENT_TYPE)
+                .body(body),
+        )
+        .await
+    }
+}
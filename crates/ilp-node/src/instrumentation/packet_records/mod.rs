@@ -0,0 +1,536 @@
+//! Records of the packets the node forwards, with their fulfillment or rejection,
+//! written to one or more sinks.
+//!
+//! Each sink has its own bounded buffer and task, which writes the records in batches.
+//! A sink which cannot keep up (or is down) does not slow the packets down: once its buffer
+//! is full, the new records are dropped for it and the number dropped is logged.
+mod file;
+#[cfg(feature = "google-pubsub")]
+mod google_pubsub;
+mod http;
+mod nats;
+
+pub use file::FileSinkConfig;
+#[cfg(feature = "google-pubsub")]
+pub use google_pubsub::PubsubConfig;
+pub use http::{KafkaSinkConfig, WebhookSinkConfig};
+pub use nats::NatsSinkConfig;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use interledger::{
+    packet::Address,
+    service::{Account, IlpResult, OutgoingRequest, Username},
+    service_util::{Conversion, ConversionListener},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::{delay_for, timeout_at, Instant},
+};
+use tracing::{error, info, warn};
+
+/// Number of times a batch is written before its records are dropped
+const MAX_WRITE_ATTEMPTS: u32 = 4;
+/// Delay before writing a batch again, which doubles after each failure
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Configuration of the packet records
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct PacketRecordsConfig {
+    /// Where the records are written. Each sink gets all of the records
+    pub sinks: Vec<SinkConfig>,
+    /// Maximum number of records written to a sink at once. Defaults to 100
+    #[serde(default = "PacketRecordsConfig::default_batch_size")]
+    pub batch_size: usize,
+    /// Maximum time, in milliseconds, that a record waits for its batch to fill up.
+    /// Defaults to 1000ms (1 second)
+    #[serde(default = "PacketRecordsConfig::default_flush_interval")]
+    pub flush_interval: u64,
+    /// Maximum number of records waiting to be written to each sink.
+    /// Defaults to 10000
+    #[serde(default = "PacketRecordsConfig::default_buffer_size")]
+    pub buffer_size: usize,
+}
+
+impl Default for PacketRecordsConfig {
+    fn default() -> Self {
+        PacketRecordsConfig {
+            sinks: Vec::new(),
+            batch_size: PacketRecordsConfig::default_batch_size(),
+            flush_interval: PacketRecordsConfig::default_flush_interval(),
+            buffer_size: PacketRecordsConfig::default_buffer_size(),
+        }
+    }
+}
+
+impl PacketRecordsConfig {
+    fn default_batch_size() -> usize {
+        100
+    }
+
+    fn default_flush_interval() -> u64 {
+        1000
+    }
+
+    fn default_buffer_size() -> usize {
+        10_000
+    }
+}
+
+/// A destination of the packet records
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkConfig {
+    /// Newline-delimited JSON file, which is rotated once it grows too large
+    File(FileSinkConfig),
+    /// HTTP endpoint which gets the batches POSTed as JSON arrays
+    Webhook(WebhookSinkConfig),
+    /// Kafka topic, written through a Kafka REST proxy
+    Kafka(KafkaSinkConfig),
+    /// NATS subject, with a message per record
+    Nats(NatsSinkConfig),
+    /// Google Cloud PubSub topic
+    #[cfg(feature = "google-pubsub")]
+    GooglePubsub(PubsubConfig),
+}
+
+impl SinkConfig {
+    /// Names the sink in the logs
+    fn describe(&self) -> String {
+        match self {
+            SinkConfig::File(config) => format!("file {}", config.path.display()),
+            SinkConfig::Webhook(config) => format!("webhook {}", config.url),
+            SinkConfig::Kafka(config) => format!("Kafka topic {}", config.topic),
+            SinkConfig::Nats(config) => format!("NATS subject {}", config.subject),
+            #[cfg(feature = "google-pubsub")]
+            SinkConfig::GooglePubsub(config) => format!("Google PubSub topic {}", config.topic),
+        }
+    }
+
+    async fn open(&self) -> Result<Box<dyn Sink>, SinkError> {
+        Ok(match self {
+            SinkConfig::File(config) => Box::new(file::FileSink::open(config.clone()).await?),
+            SinkConfig::Webhook(config) => Box::new(http::WebhookSink::new(config.clone())?),
+            SinkConfig::Kafka(config) => Box::new(http::KafkaSink::new(config.clone())?),
+            SinkConfig::Nats(config) => Box::new(nats::NatsSink::new(config.clone())),
+            #[cfg(feature = "google-pubsub")]
+            SinkConfig::GooglePubsub(config) => {
+                Box::new(google_pubsub::PubsubSink::new(config.clone()).await?)
+            }
+        })
+    }
+}
+
+/// Errors writing the packet records
+#[derive(Error, Debug)]
+pub enum SinkError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Http(#[from] reqwest::Error),
+    #[error("could not serialize the record: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("the sink refused the records: {0}")]
+    Refused(String),
+    #[error("invalid configuration: {0}")]
+    Config(String),
+}
+
+/// Writes batches of records to a destination
+#[async_trait]
+trait Sink: Send {
+    async fn write(&mut self, records: &[Arc<PacketRecord>]) -> Result<(), SinkError>;
+}
+
+/// Accounts and amounts of a packet forwarded by the node
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct Hops {
+    prev_hop_account: Username,
+    prev_hop_asset_code: String,
+    prev_hop_asset_scale: u8,
+    prev_hop_amount: u64,
+    next_hop_account: Username,
+    next_hop_asset_code: String,
+    next_hop_asset_scale: u8,
+    next_hop_amount: u64,
+    destination_ilp_address: Address,
+    /// Fee the node charged, in the previous hop's asset and scale
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fee: Option<u64>,
+    /// Exchange rate the amount was converted with, before the spread
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exchange_rate: Option<f64>,
+    /// Spread the node took on top of the exchange rate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spread: Option<f64>,
+}
+
+impl Hops {
+    fn new<A: Account>(request: &OutgoingRequest<A>, conversion: Option<&Conversion>) -> Self {
+        Hops {
+            prev_hop_account: request.from.username().clone(),
+            prev_hop_asset_code: request.from.asset_code().to_string(),
+            prev_hop_asset_scale: request.from.asset_scale(),
+            prev_hop_amount: request.original_amount,
+            next_hop_account: request.to.username().clone(),
+            next_hop_asset_code: request.to.asset_code().to_string(),
+            next_hop_asset_scale: request.to.asset_scale(),
+            next_hop_amount: request.prepare.amount(),
+            destination_ilp_address: request.prepare.destination(),
+            fee: conversion.map(|conversion| conversion.fee),
+            exchange_rate: conversion.map(|conversion| conversion.rate),
+            spread: conversion.map(|conversion| conversion.spread),
+        }
+    }
+}
+
+/// How the packet was answered
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "result", rename_all = "snake_case")]
+enum PacketResult {
+    Fulfilled {
+        /// Base64-encoded fulfillment
+        fulfillment: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Rejected {
+        error_code: String,
+        error_message: String,
+        triggered_by: Option<Address>,
+    },
+}
+
+impl From<&IlpResult> for PacketResult {
+    fn from(result: &IlpResult) -> Self {
+        match result {
+            Ok(fulfill) => PacketResult::Fulfilled {
+                fulfillment: base64::encode(fulfill.fulfillment()),
+            },
+            Err(reject) => PacketResult::Rejected {
+                error_code: reject.code().to_string(),
+                error_message: String::from_utf8_lossy(reject.message()).into_owned(),
+                triggered_by: reject.triggered_by(),
+            },
+        }
+    }
+}
+
+/// A packet forwarded by the node, with its fulfillment or rejection
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PacketRecord {
+    #[serde(flatten)]
+    hops: Hops,
+    #[serde(flatten)]
+    result: PacketResult,
+    /// When the packet was answered, in RFC 3339 format
+    timestamp: String,
+}
+
+enum Command {
+    Record(Arc<PacketRecord>),
+    /// Write the records received so far, then notify the sender
+    Flush(oneshot::Sender<()>),
+}
+
+struct SinkHandle {
+    commands: mpsc::Sender<Command>,
+    /// Records dropped since the last batch, because the buffer was full
+    dropped: Arc<AtomicU64>,
+}
+
+/// Sends the packet records to the tasks writing them to each sink
+#[derive(Clone)]
+pub struct PacketRecorder {
+    sinks: Arc<Vec<SinkHandle>>,
+}
+
+impl PacketRecorder {
+    /// Opens the sinks and starts the tasks writing the records to them
+    ///
+    /// # Errors
+    /// If one of the sinks cannot be opened, for example because its file cannot be created
+    pub async fn start(config: &PacketRecordsConfig) -> Result<Self, SinkError> {
+        let mut sinks = Vec::with_capacity(config.sinks.len());
+        for sink_config in &config.sinks {
+            let name = sink_config.describe();
+            let sink = sink_config.open().await.map_err(|err| {
+                error!(target: "interledger-node", "Could not open the packet records {}: {}", name, err);
+                err
+            })?;
+            let (commands, receiver) = mpsc::channel(config.buffer_size.max(1));
+            let dropped = Arc::new(AtomicU64::new(0));
+            tokio::spawn(write_records(
+                sink,
+                name.clone(),
+                receiver,
+                dropped.clone(),
+                config.batch_size.max(1),
+                Duration::from_millis(config.flush_interval),
+            ));
+            info!(target: "interledger-node", "Packet records will be written to {}", name);
+            sinks.push(SinkHandle { commands, dropped });
+        }
+        Ok(PacketRecorder {
+            sinks: Arc::new(sinks),
+        })
+    }
+
+    /// Buffers the record for each sink, or drops it for the sinks whose buffer is full
+    fn record(&self, record: PacketRecord) {
+        let record = Arc::new(record);
+        for sink in self.sinks.iter() {
+            if sink
+                .commands
+                .clone()
+                .try_send(Command::Record(record.clone()))
+                .is_err()
+            {
+                sink.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Resolves once the records buffered so far were written (or dropped after failing)
+    pub async fn flush(&self) {
+        for sink in self.sinks.iter() {
+            let (done, written) = oneshot::channel();
+            // The task only stops once all of the senders are gone
+            if sink
+                .commands
+                .clone()
+                .send(Command::Flush(done))
+                .await
+                .is_ok()
+            {
+                written.await.ok();
+            }
+        }
+    }
+}
+
+/// Writes the records to the sink in batches, until all of the senders are dropped
+async fn write_records(
+    mut sink: Box<dyn Sink>,
+    name: String,
+    mut commands: mpsc::Receiver<Command>,
+    dropped: Arc<AtomicU64>,
+    batch_size: usize,
+    flush_interval: Duration,
+) {
+    let mut batch = Vec::with_capacity(batch_size);
+    loop {
+        // The batch is written once it is full, after the interval since its first
+        // record or when it is flushed
+        let mut flushed = match commands.recv().await {
+            Some(Command::Record(record)) => {
+                batch.push(record);
+                None
+            }
+            Some(Command::Flush(done)) => Some(done),
+            None => return,
+        };
+        let deadline = Instant::now() + flush_interval;
+        while flushed.is_none() && batch.len() < batch_size {
+            match timeout_at(deadline, commands.recv()).await {
+                Ok(Some(Command::Record(record))) => batch.push(record),
+                Ok(Some(Command::Flush(done))) => flushed = Some(done),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        if !batch.is_empty() {
+            write_batch(&mut *sink, &name, &batch).await;
+            batch.clear();
+        }
+        let dropped = dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            warn!(target: "interledger-node",
+                "Dropped {} packet records for {} because its buffer was full",
+                dropped, name
+            );
+        }
+        if let Some(done) = flushed {
+            done.send(()).ok();
+        }
+    }
+}
+
+async fn write_batch(sink: &mut dyn Sink, name: &str, batch: &[Arc<PacketRecord>]) {
+    let mut delay = RETRY_DELAY;
+    for attempt in 1..=MAX_WRITE_ATTEMPTS {
+        match sink.write(batch).await {
+            Ok(()) => return,
+            Err(err) if attempt < MAX_WRITE_ATTEMPTS => {
+                warn!(target: "interledger-node",
+                    "Could not write {} packet records to {}, retrying in {:?}: {}",
+                    batch.len(), name, delay, err
+                );
+                delay_for(delay).await;
+                delay *= 2;
+            }
+            Err(err) => error!(target: "interledger-node",
+                "Dropped {} packet records after failing to write them to {} {} times: {}",
+                batch.len(), name, MAX_WRITE_ATTEMPTS, err
+            ),
+        }
+    }
+}
+
+/// Creates a listener for the `ExchangeRateService` that records the fulfilled and rejected
+/// packets, with their amounts once converted and the rates and fees they were converted with.
+///
+/// Packets addressed to `peer.*`, such as route updates and settlement messages, are not recorded.
+pub fn record_packets<A: Account + 'static>(recorder: PacketRecorder) -> ConversionListener<A> {
+    Arc::new(
+        move |request: &OutgoingRequest<A>, conversion: Option<&Conversion>, result: &IlpResult| {
+            if request.prepare.destination().scheme() == "peer" {
+                return;
+            }
+            recorder.record(PacketRecord {
+                hops: Hops::new(request, conversion),
+                result: PacketResult::from(result),
+                timestamp: Utc::now().to_rfc3339(),
+            });
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger::packet::{ErrorCode, RejectBuilder};
+    use serde_json::json;
+    use std::str::FromStr;
+
+    pub(super) fn record(amount: u64) -> PacketRecord {
+        PacketRecord {
+            hops: Hops {
+                prev_hop_account: Username::from_str("alice").unwrap(),
+                prev_hop_asset_code: "XYZ".to_owned(),
+                prev_hop_asset_scale: 9,
+                prev_hop_amount: amount,
+                next_hop_account: Username::from_str("bob").unwrap(),
+                next_hop_asset_code: "ABC".to_owned(),
+                next_hop_asset_scale: 6,
+                next_hop_amount: amount / 1000,
+                destination_ilp_address: Address::from_str("example.bob").unwrap(),
+                fee: None,
+                exchange_rate: None,
+                spread: None,
+            },
+            result: PacketResult::Rejected {
+                error_code: ErrorCode::F02_UNREACHABLE.to_string(),
+                error_message: "No route".to_owned(),
+                triggered_by: Some(Address::from_str("example.connector").unwrap()),
+            },
+            timestamp: "2020-01-01T00:00:00+00:00".to_owned(),
+        }
+    }
+
+    #[test]
+    fn serializes_rejections() {
+        let reject = RejectBuilder {
+            code: ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+            message: b"Exceeded maximum balance",
+            triggered_by: Some(&Address::from_str("example.connector").unwrap()),
+            data: &[],
+        }
+        .build();
+        let mut record = record(1000);
+        record.hops.fee = Some(2);
+        record.hops.exchange_rate = Some(0.002);
+        record.hops.spread = Some(0.01);
+        record.result = PacketResult::from(&Err(reject));
+        assert_eq!(
+            serde_json::to_value(&record).unwrap(),
+            json!({
+                "prevHopAccount": "alice",
+                "prevHopAssetCode": "XYZ",
+                "prevHopAssetScale": 9,
+                "prevHopAmount": 1000,
+                "nextHopAccount": "bob",
+                "nextHopAssetCode": "ABC",
+                "nextHopAssetScale": 6,
+                "nextHopAmount": 1,
+                "destinationIlpAddress": "example.bob",
+                "fee": 2,
+                "exchangeRate": 0.002,
+                "spread": 0.01,
+                "result": "rejected",
+                "errorCode": "T04",
+                "errorMessage": "Exceeded maximum balance",
+                "triggeredBy": "example.connector",
+                "timestamp": "2020-01-01T00:00:00+00:00",
+            })
+        );
+    }
+
+    /// Collects the batches it is given
+    struct TestSink(mpsc::UnboundedSender<usize>);
+
+    #[async_trait]
+    impl Sink for TestSink {
+        async fn write(&mut self, records: &[Arc<PacketRecord>]) -> Result<(), SinkError> {
+            self.0.send(records.len()).unwrap();
+            Ok(())
+        }
+    }
+
+    fn test_recorder(
+        batch_size: usize,
+        buffer_size: usize,
+    ) -> (PacketRecorder, mpsc::UnboundedReceiver<usize>) {
+        let (batches, written) = mpsc::unbounded_channel();
+        let (commands, receiver) = mpsc::channel(buffer_size);
+        let dropped = Arc::new(AtomicU64::new(0));
+        tokio::spawn(write_records(
+            Box::new(TestSink(batches)),
+            "test sink".to_owned(),
+            receiver,
+            dropped.clone(),
+            batch_size,
+            Duration::from_secs(60),
+        ));
+        let recorder = PacketRecorder {
+            sinks: Arc::new(vec![SinkHandle { commands, dropped }]),
+        };
+        (recorder, written)
+    }
+
+    #[tokio::test]
+    async fn writes_full_and_flushed_batches() {
+        let (recorder, mut written) = test_recorder(2, 10);
+        for amount in 0..3 {
+            recorder.record(record(amount));
+        }
+        assert_eq!(written.recv().await, Some(2));
+
+        recorder.flush().await;
+        assert_eq!(written.recv().await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn drops_records_once_buffer_is_full() {
+        let (recorder, mut written) = test_recorder(10, 2);
+        // The buffer holds 2 records and the sink task did not get to run yet
+        for amount in 0..5 {
+            recorder.record(record(amount));
+        }
+        assert_eq!(recorder.sinks[0].dropped.load(Ordering::Relaxed), 3);
+
+        recorder.flush().await;
+        assert_eq!(written.recv().await, Some(2));
+        assert_eq!(recorder.sinks[0].dropped.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,109 @@
+use super::{PacketRecord, Sink, SinkError};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::{io, sync::Arc};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufStream},
+    net::TcpStream,
+};
+use tracing::debug;
+
+/// NATS subject each record is published to, as a JSON message. Uses the
+/// [client protocol](https://docs.nats.io/reference/reference-protocols/nats-protocol)
+/// without TLS or authentication, so the server is expected to run locally
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct NatsSinkConfig {
+    /// Host and port of the server. Defaults to `127.0.0.1:4222`
+    #[serde(default = "NatsSinkConfig::default_address")]
+    pub address: String,
+    pub subject: String,
+}
+
+impl NatsSinkConfig {
+    fn default_address() -> String {
+        "127.0.0.1:4222".to_owned()
+    }
+}
+
+type Connection = BufStream<TcpStream>;
+
+/// Reads a line sent by the server, without its CRLF
+async fn read_line(connection: &mut Connection) -> Result<String, SinkError> {
+    let mut line = String::new();
+    if connection.read_line(&mut line).await? == 0 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    line.truncate(line.trim_end().len());
+    Ok(line)
+}
+
+async fn connect(address: &str) -> Result<Connection, SinkError> {
+    debug!("Connecting to NATS server at {}", address);
+    let mut connection = BufStream::new(TcpStream::connect(address).await?);
+    let info = read_line(&mut connection).await?;
+    if !info.starts_with("INFO") {
+        return Err(SinkError::Refused(format!("unexpected greeting: {}", info)));
+    }
+    connection
+        .write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false,\"name\":\"ilp-node\"}\r\n")
+        .await?;
+    Ok(connection)
+}
+
+/// Publishes the records, then waits for the server to answer a PING,
+/// which it does once it processed the messages sent before it
+async fn publish(
+    connection: &mut Connection,
+    subject: &str,
+    records: &[Arc<PacketRecord>],
+) -> Result<(), SinkError> {
+    for record in records {
+        let payload = serde_json::to_vec(record.as_ref())?;
+        let header = format!("PUB {} {}\r\n", subject, payload.len());
+        connection.write_all(header.as_bytes()).await?;
+        connection.write_all(&payload).await?;
+        connection.write_all(b"\r\n").await?;
+    }
+    connection.write_all(b"PING\r\n").await?;
+    connection.flush().await?;
+
+    loop {
+        let line = read_line(connection).await?;
+        if line == "PONG" {
+            return Ok(());
+        } else if line == "PING" {
+            connection.write_all(b"PONG\r\n").await?;
+            connection.flush().await?;
+        } else if line.starts_with("-ERR") {
+            return Err(SinkError::Refused(line));
+        }
+    }
+}
+
+pub(super) struct NatsSink {
+    config: NatsSinkConfig,
+    /// Opened on the first batch, and opened again after it failed
+    connection: Option<Connection>,
+}
+
+impl NatsSink {
+    pub(super) fn new(config: NatsSinkConfig) -> Self {
+        NatsSink {
+            config,
+            connection: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for NatsSink {
+    async fn write(&mut self, records: &[Arc<PacketRecord>]) -> Result<(), SinkError> {
+        let mut connection = match self.connection.take() {
+            Some(connection) => connection,
+            None => connect(&self.config.address).await?,
+        };
+        publish(&mut connection, &self.config.subject, records).await?;
+        self.connection = Some(connection);
+        Ok(())
+    }
+}
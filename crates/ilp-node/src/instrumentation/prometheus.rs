@@ -1,7 +1,16 @@
-use crate::InterledgerNode;
-use metrics_core::{Builder, Drain, Observe};
+use futures::{channel::oneshot, FutureExt};
+use metrics_core::{Builder, Drain, Key, Observe};
+use metrics_runtime::{Controller, Receiver, Sink};
 use serde::Deserialize;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    cell::RefCell,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
 use tracing::{error, info};
 use warp::{
     http::{Response, StatusCode},
@@ -32,58 +41,203 @@ impl PrometheusConfig {
     fn default_histogram_granularity() -> u64 {
         10_000
     }
+
+    fn same_histograms(&self, other: &PrometheusConfig) -> bool {
+        self.histogram_window == other.histogram_window
+            && self.histogram_granularity == other.histogram_granularity
+    }
 }
 
-/// Starts a Prometheus metrics server that will listen on the configured address.
-///
-/// # Errors
-/// This will fail if another Prometheus server is already running in this
-/// process or on the configured port.
-#[allow(clippy::cognitive_complexity)]
-pub async fn serve_prometheus(node: InterledgerNode) -> Result<(), ()> {
-    let prometheus = if let Some(ref prometheus) = node.prometheus {
-        prometheus
-    } else {
-        error!(target: "interledger-node", "No prometheus configuration provided");
-        return Err(());
-    };
-
-    // Set up the metrics collector
-    let receiver = metrics_runtime::Builder::default()
-        .histogram(
-            Duration::from_millis(prometheus.histogram_window),
-            Duration::from_millis(prometheus.histogram_granularity),
-        )
-        .build()
-        .expect("Failed to create metrics Receiver");
-
-    let controller = receiver.controller();
-    // Try installing the global recorder
-    match metrics::set_boxed_recorder(Box::new(receiver)) {
-        Ok(_) => {
-            let observer = Arc::new(metrics_runtime::observers::PrometheusBuilder::default());
-
-            let filter = warp::get().and(warp::path::end()).map(move || {
-                let mut observer = observer.build();
-                controller.observe(&mut observer);
-                let prometheus_response = observer.drain();
-                Response::builder()
-                    .status(StatusCode::OK)
-                    .header("Content-Type", "text/plain; version=0.0.4")
-                    .body(prometheus_response)
+/// Ids of the receivers, so that the sinks each thread keeps are replaced along with the receiver
+static NEXT_RECEIVER_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Sink of the receiver with the id, kept like `metrics_runtime::Receiver` keeps its own
+    static SINK: RefCell<Option<(u64, Sink)>> = RefCell::default();
+}
+
+/// The receiver the metrics are recorded to, if they are collected. It is replaced
+/// when the histogram settings change
+#[derive(Default)]
+struct Metrics {
+    receiver: RwLock<Option<(u64, Receiver)>>,
+}
+
+impl Metrics {
+    fn with_sink<F: FnOnce(&mut Sink)>(&self, f: F) {
+        let receiver = self.receiver.read().unwrap();
+        if let Some((id, ref receiver)) = *receiver {
+            SINK.with(|sink| {
+                let mut sink = sink.borrow_mut();
+                match *sink {
+                    Some((sink_id, ref mut sink)) if sink_id == id => f(sink),
+                    _ => {
+                        let mut new_sink = receiver.sink();
+                        f(&mut new_sink);
+                        *sink = Some((id, new_sink));
+                    }
+                }
             });
+        }
+    }
+
+    fn controller(&self) -> Option<Controller> {
+        self.receiver
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|(_, receiver)| receiver.controller())
+    }
+}
+
+/// The global metrics recorder, which records to the node's current receiver
+struct MetricsRecorder(Arc<Metrics>);
+
+impl metrics::Recorder for MetricsRecorder {
+    fn increment_counter(&self, key: Key, value: u64) {
+        self.0.with_sink(|sink| sink.increment_counter(key, value))
+    }
+
+    fn update_gauge(&self, key: Key, value: i64) {
+        self.0.with_sink(|sink| sink.update_gauge(key, value))
+    }
+
+    fn record_histogram(&self, key: Key, value: u64) {
+        self.0.with_sink(|sink| sink.record_value(key, value))
+    }
+}
+
+struct ServerState {
+    config: Option<PrometheusConfig>,
+    /// Whether the recorder was installed. There is only one per process
+    installed: bool,
+    /// Stops the server which is listening, if any
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+/// Serves the Prometheus metrics, with the configuration which can be changed
+/// while the node runs
+#[derive(Clone)]
+pub struct PrometheusServer {
+    metrics: Arc<Metrics>,
+    state: Arc<Mutex<ServerState>>,
+}
+
+impl Default for PrometheusServer {
+    fn default() -> Self {
+        PrometheusServer {
+            metrics: Arc::new(Metrics::default()),
+            state: Arc::new(Mutex::new(ServerState {
+                config: None,
+                installed: false,
+                shutdown: None,
+            })),
+        }
+    }
+}
+
+impl PrometheusServer {
+    /// Starts collecting the metrics and serving them on the configured address,
+    /// moves them to another address, or stops collecting and serving them.
+    ///
+    /// The histograms start over when their window or granularity change, and so do
+    /// the counters. If the new address cannot be bound, nothing changes.
+    ///
+    /// # Errors
+    /// This will fail if another Prometheus server is already running in this
+    /// process or on the configured port.
+    pub fn apply(&self, config: Option<&PrometheusConfig>) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if state.config.as_ref() == config {
+            return Ok(());
+        }
+        let config = match config {
+            Some(config) => config,
+            None => {
+                if let Some(shutdown) = state.shutdown.take() {
+                    shutdown.send(()).ok();
+                }
+                *self.metrics.receiver.write().unwrap() = None;
+                state.config = None;
+                info!(target: "interledger-node", "Prometheus metrics server stopped");
+                return Ok(());
+            }
+        };
 
+        if !state.installed {
+            metrics::set_boxed_recorder(Box::new(MetricsRecorder(self.metrics.clone())))
+                .map_err(|err| {
+                    format!("could not install the global metrics recorder (this is likely caused by trying to run two nodes with Prometheus metrics in the same process): {:?}", err)
+                })?;
+            state.installed = true;
+        }
+
+        let rebind = match state.config {
+            Some(ref current) => current.bind_address != config.bind_address,
+            None => true,
+        };
+        let new_receiver = match state.config {
+            Some(ref current) if current.same_histograms(config) => None,
+            _ => Some(
+                metrics_runtime::Builder::default()
+                    .histogram(
+                        Duration::from_millis(config.histogram_window),
+                        Duration::from_millis(config.histogram_granularity),
+                    )
+                    .build()
+                    .map_err(|err| format!("could not create the metrics receiver: {:?}", err))?,
+            ),
+        };
+
+        if rebind {
+            let (shutdown, stopped) = oneshot::channel();
+            let (_, server) = warp::serve(self.filter())
+                .try_bind_with_graceful_shutdown(config.bind_address, stopped.map(|_| ()))
+                .map_err(|err| format!("could not listen on {}: {}", config.bind_address, err))?;
+            tokio::spawn(server);
+            if let Some(previous) = state.shutdown.replace(shutdown) {
+                previous.send(()).ok();
+            }
             info!(target: "interledger-node",
                 "Prometheus metrics server listening on: {}",
-                prometheus.bind_address
+                config.bind_address
             );
-
-            tokio::spawn(warp::serve(filter).bind(prometheus.bind_address));
-            Ok(())
         }
-        Err(e) => {
-            error!(target: "interledger-node", "Error installing global metrics recorder (this is likely caused by trying to run two nodes with Prometheus metrics in the same process): {:?}", e);
-            Err(())
+        if let Some(receiver) = new_receiver {
+            let id = NEXT_RECEIVER_ID.fetch_add(1, Ordering::Relaxed);
+            *self.metrics.receiver.write().unwrap() = Some((id, receiver));
         }
+        state.config = Some(config.clone());
+        Ok(())
+    }
+
+    fn filter(
+        &self,
+    ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+        let metrics = self.metrics.clone();
+        let observer = Arc::new(metrics_runtime::observers::PrometheusBuilder::default());
+        warp::get().and(warp::path::end()).map(move || {
+            let mut observer = observer.build();
+            if let Some(controller) = metrics.controller() {
+                controller.observe(&mut observer);
+            }
+            let prometheus_response = observer.drain();
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "text/plain; version=0.0.4")
+                .body(prometheus_response)
+        })
+    }
+}
+
+/// Starts a Prometheus metrics server if the configuration was provided, and returns
+/// the handle used to apply the changes of the configuration.
+///
+/// The node runs even if the server could not be started.
+pub fn serve_prometheus(config: Option<&PrometheusConfig>) -> PrometheusServer {
+    let server = PrometheusServer::default();
+    if let Err(err) = server.apply(config) {
+        error!(target: "interledger-node", "Prometheus metrics server not started: {}", err);
     }
+    server
 }
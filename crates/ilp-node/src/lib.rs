@@ -1,8 +1,20 @@
 #![type_length_limit = "10000000"]
 mod instrumentation;
 mod node;
+mod reload;
+mod shutdown;
+mod tls;
 
+#[cfg(feature = "memory")]
+mod memory_store;
+#[cfg(feature = "postgres")]
+mod postgres_store;
 #[cfg(feature = "redis")]
 mod redis_store;
+#[cfg(feature = "sled")]
+mod sled_store;
 
 pub use node::*;
+pub use reload::{ConfigReloader, ReloadError};
+pub use shutdown::NodeHandle;
+pub use tls::TlsConfig;
@@ -1,6 +1,9 @@
 #![type_length_limit = "10000000"]
 mod instrumentation;
 pub mod node;
+mod reload;
+mod shutdown;
+mod tls;
 
 use cfg_if::cfg_if;
 
@@ -14,16 +17,24 @@ cfg_if! {
     }
 }
 
+#[cfg(feature = "memory")]
+mod memory_store;
+#[cfg(feature = "postgres")]
+mod postgres_store;
 #[cfg(feature = "redis")]
 mod redis_store;
+#[cfg(feature = "sled")]
+mod sled_store;
 
 use clap::{App, Arg, ArgMatches};
 use config::{Config, Source};
 use config::{ConfigError, FileFormat, Value};
 use libc::{c_int, isatty};
 use node::InterledgerNode;
+use reload::ConfigReloader;
 use std::{
     ffi::{OsStr, OsString},
+    fmt,
     io::Read,
     vec::Vec,
 };
@@ -39,17 +50,16 @@ async fn main() {
     let app = cmdline_configuration(&version);
     let args = std::env::args_os().collect::<Vec<_>>();
 
-    let stdin = std::io::stdin();
-
-    let additional_config = if !is_fd_tty(0) {
-        // this might be read by load_configuration, depending on the presence of a config file
-        let lock = stdin.lock();
-        Some(lock)
+    // Kept to load the same configuration again when it is reloaded
+    let stdin_config = if !is_fd_tty(0) {
+        let mut input = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut input).ok();
+        Some(input)
     } else {
         None
     };
 
-    let node = match load_configuration(app, args, additional_config) {
+    let node = match load_configuration(app, args.clone(), stdin_config.as_deref()) {
         Ok(node) => node,
         Err(BadConfig::HelpOrVersion(e)) | Err(BadConfig::BadArguments(e)) => {
             e.exit();
@@ -68,8 +78,6 @@ async fn main() {
         }
     };
 
-    drop(stdin);
-
     cfg_if! {
         if #[cfg(feature = "monitoring")] {
             let mut log_writer = LogWriter::default();
@@ -92,11 +100,59 @@ async fn main() {
         }
     }
 
-    node.serve(log_writer.clone()).await.unwrap();
+    let node = node.serve(log_writer.clone()).await.unwrap();
+
+    // Reloading the configuration reads the same arguments, environment variables,
+    // file and stdin input again, so that the changes made to the file are applied
+    let reloader = node.reloader();
+    reloader.set_source(move || {
+        load_configuration(
+            cmdline_configuration(&version),
+            args.clone(),
+            stdin_config.as_deref(),
+        )
+        .map_err(|err| err.to_string())
+    });
+    reload_on_hangup(reloader);
+
+    // Run until we are asked to stop, then let the packets in flight complete before exiting
+    shutdown_signal().await;
+    node.shutdown().await;
+}
+
+/// Resolves once the node receives a SIGTERM (on Unix) or a Ctrl-C
+async fn shutdown_signal() {
+    cfg_if! {
+        if #[cfg(unix)] {
+            use futures::future::{select, FutureExt};
+            use tokio::signal::unix::{signal, SignalKind};
+
+            let mut terminate =
+                signal(SignalKind::terminate()).expect("Could not listen for SIGTERM");
+            select(terminate.recv().boxed(), tokio::signal::ctrl_c().boxed()).await;
+        } else {
+            tokio::signal::ctrl_c().await.ok();
+        }
+    }
+}
+
+/// Reloads the configuration each time the node receives a SIGHUP (on Unix).
+/// The reloader logs the changes it could not apply
+fn reload_on_hangup(reloader: ConfigReloader) {
+    cfg_if! {
+        if #[cfg(unix)] {
+            use tokio::signal::unix::{signal, SignalKind};
 
-    // Add a future which is always pending. This will ensure main does not exist
-    // TODO: Is there a better way of doing this?
-    futures::future::pending().await
+            let mut hangups = signal(SignalKind::hangup()).expect("Could not listen for SIGHUP");
+            tokio::spawn(async move {
+                while hangups.recv().await.is_some() {
+                    reloader.reload_from_source().ok();
+                }
+            });
+        } else {
+            drop(reloader);
+        }
+    }
 }
 
 fn cmdline_configuration<'b>(version: &'b str) -> clap::App<'static, 'b> {
@@ -145,7 +201,7 @@ fn cmdline_configuration<'b>(version: &'b str) -> clap::App<'static, 'b> {
             .alias("redis_url")
             .takes_value(true)
             .default_value("redis://127.0.0.1:6379")
-            .help("Redis URI (for example, \"redis://127.0.0.1:6379\" or \"unix:/tmp/redis.sock\")"),
+            .help("Data store URI (for example, \"redis://127.0.0.1:6379\", \"unix:/tmp/redis.sock\", \"postgres://localhost/ilp\", \"sled:/var/lib/ilp-node\" or \"memory://\")"),
         Arg::with_name("database_prefix")
             .long("database_prefix")
             .takes_value(true)
@@ -159,6 +215,30 @@ fn cmdline_configuration<'b>(version: &'b str) -> clap::App<'static, 'b> {
             .long("settlement_api_bind_address")
             .takes_value(true)
             .help("IP address and port to listen for the Settlement Engine API"),
+        Arg::with_name("http_tls.cert_path")
+            .long("http_tls.cert_path")
+            .takes_value(true)
+            .help("PEM file with the certificate chain presented to clients of the HTTP API, ILP over HTTP and BTP. If it is set, the listener only accepts TLS connections. The certificate and key are reloaded when the node receives a SIGHUP."),
+        Arg::with_name("http_tls.key_path")
+            .long("http_tls.key_path")
+            .takes_value(true)
+            .help("PEM file with the private key of the http_tls.cert_path certificate, in PKCS #8 or RSA format."),
+        Arg::with_name("http_tls.client_ca_path")
+            .long("http_tls.client_ca_path")
+            .takes_value(true)
+            .help("PEM file with the CA certificates which issue the client certificates of the HTTP API, ILP over HTTP and BTP. If it is set, clients must present a certificate issued by one of them (mutual TLS)."),
+        Arg::with_name("settlement_api_tls.cert_path")
+            .long("settlement_api_tls.cert_path")
+            .takes_value(true)
+            .help("PEM file with the certificate chain presented to clients of the Settlement Engine API. If it is set, the listener only accepts TLS connections. The certificate and key are reloaded when the node receives a SIGHUP."),
+        Arg::with_name("settlement_api_tls.key_path")
+            .long("settlement_api_tls.key_path")
+            .takes_value(true)
+            .help("PEM file with the private key of the settlement_api_tls.cert_path certificate, in PKCS #8 or RSA format."),
+        Arg::with_name("settlement_api_tls.client_ca_path")
+            .long("settlement_api_tls.client_ca_path")
+            .takes_value(true)
+            .help("PEM file with the CA certificates which issue the client certificates of the Settlement Engine API. If it is set, clients must present a certificate issued by one of them (mutual TLS)."),
         Arg::with_name("default_spsp_account")
             .long("default_spsp_account")
             .takes_value(true)
@@ -167,15 +247,27 @@ fn cmdline_configuration<'b>(version: &'b str) -> clap::App<'static, 'b> {
             .long("route_broadcast_interval")
             .takes_value(true)
             .help("Interval, defined in milliseconds, on which the node will broadcast routing information to other nodes using CCP. Defaults to 30000ms (30 seconds)."),
+        Arg::with_name("stateful_stream_receiver")
+            .long("stateful_stream_receiver")
+            .takes_value(true)
+            .help("When true, the node keeps the state of incoming STREAM connections in the database. Connections are then limited to the amount registered for them (if any) and a single payment notification is published per connection when it closes. Defaults to false."),
+        Arg::with_name("max_transactions_per_account")
+            .long("max_transactions_per_account")
+            .takes_value(true)
+            .help("Number of transactions kept in the history of each account. Once an account has more, its oldest transactions are removed and only their totals are kept, so that the account can still be reconciled. Defaults to 100000."),
         Arg::with_name("exchange_rate.provider")
             .long("exchange_rate.provider")
             .takes_value(true)
             .help("Exchange rate API to poll for exchange rates. If this is not set, the node will not poll for rates and will instead use the rates set via the HTTP API. \
-                Note that CryptoCompare can also be used when the node is configured via a config file or stdin, because an API key must be provided to use that service."),
+                Note that CryptoCompare, Http and File can also be used when the node is configured via a config file or stdin, because they need more settings."),
         Arg::with_name("exchange_rate.poll_interval")
             .long("exchange_rate.poll_interval")
             .default_value("60000") // also change ExchangeRateConfig::default_poll_interval
             .help("Interval, defined in milliseconds, on which the node will poll the exchange_rate.provider (if specified) for exchange rates."),
+        Arg::with_name("exchange_rate.history_retention")
+            .long("exchange_rate.history_retention")
+            .takes_value(true)
+            .help("How long, in milliseconds, the exchange rates are kept in their history. The last rate recorded before that is kept as well, since it was still in effect. Defaults to 7776000000ms (90 days)."),
         Arg::with_name("exchange_rate.spread")
             .long("exchange_rate.spread")
             .default_value("0") // also change ExchangeRateConfig::default_spread
@@ -201,6 +293,12 @@ fn cmdline_configuration<'b>(version: &'b str) -> clap::App<'static, 'b> {
                 old data. For example, a value of 1000ms (1 second) would mean that the \
                 node forgets the oldest 1 second of histogram data points every second. \
                 Defaults to 10000ms (10 seconds)."),
+        Arg::with_name("shutdown_timeout")
+            .long("shutdown_timeout")
+            .takes_value(true)
+            .help("Maximum time, in milliseconds, that the node waits for the packets in flight when it \
+                receives a SIGTERM or Ctrl-C, and then for the delayed settlements to complete. \
+                Packets coming in meanwhile are rejected. Defaults to 60000ms (60 seconds)."),
         Arg::with_name("settle_every")
             .long("settle_every")
             .takes_value(true)
@@ -223,6 +321,19 @@ enum BadConfig {
     ConversionFailed(config::ConfigError),
 }
 
+impl fmt::Display for BadConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BadConfig::HelpOrVersion(e) | BadConfig::BadArguments(e) => write!(f, "{}", e),
+            BadConfig::MergingStdinFailed(e) => write!(f, "invalid stdin input: {}", e),
+            BadConfig::MergingConfigFileFailed(path, e) => {
+                write!(f, "invalid file {}: {}", path, e)
+            }
+            BadConfig::ConversionFailed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
 fn load_configuration<R: Read>(
     mut app: App<'_, '_>,
     args: Vec<OsString>,
@@ -631,6 +742,44 @@ mod tests {
         assert_eq!(expected, node);
     }
 
+    #[cfg(feature = "google-pubsub")]
+    #[test]
+    fn adds_google_pubsub_to_the_packet_record_sinks() {
+        use crate::instrumentation::packet_records::SinkConfig;
+
+        let node = serde_json::from_value::<InterledgerNode>(serde_json::json!({
+            "admin_auth_token": "foobar",
+            "secret_seed": "8852500887504328225458511465394229327394647958135038836332350604",
+            "google_pubsub": {
+                "service_account_credentials": "/etc/ilp-node/google.json",
+                "project_id": "ilp",
+                "topic": "packets",
+            },
+        }))
+        .unwrap();
+
+        let config = node.packet_records_config().unwrap();
+        assert_eq!(config.sinks.len(), 1);
+        assert!(matches!(config.sinks[0], SinkConfig::GooglePubsub(_)));
+    }
+
+    #[cfg(not(feature = "google-pubsub"))]
+    #[test]
+    fn rejects_google_pubsub_without_the_feature() {
+        let err = serde_json::from_value::<InterledgerNode>(serde_json::json!({
+            "admin_auth_token": "foobar",
+            "secret_seed": "8852500887504328225458511465394229327394647958135038836332350604",
+            "google_pubsub": {
+                "service_account_credentials": "/etc/ilp-node/google.json",
+                "project_id": "ilp",
+                "topic": "packets",
+            },
+        }))
+        .unwrap_err();
+
+        assert!(err.to_string().contains("google-pubsub feature"), "{}", err);
+    }
+
     #[test]
     fn invalid_preceding_values_error_out() {
         // command line is the only place where a valid secret_seed is provided, but the presence
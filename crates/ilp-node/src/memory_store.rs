@@ -0,0 +1,25 @@
+#![cfg(feature = "memory")]
+
+use crate::node::{InterledgerNode, LogWriter};
+use crate::shutdown::NodeHandle;
+use interledger::{packet::Address, store::memory::InMemoryStoreBuilder};
+use tracing::warn;
+
+pub fn default_memory_url() -> String {
+    String::from("memory://")
+}
+
+// See the note on `serve_redis_node` for why this is not an inherent method on InterledgerNode.
+pub async fn serve_memory_node(
+    node: InterledgerNode,
+    ilp_address: Address,
+    log_writer: Option<LogWriter>,
+) -> Result<NodeHandle, ()> {
+    warn!(target: "interledger-node", "Using the in-memory store. Accounts, balances and routes will be lost when the node stops");
+    let store = InMemoryStoreBuilder::new()
+        .node_ilp_address(ilp_address.clone())
+        .max_transactions_per_account(node.max_transactions_per_account)
+        .rate_history_retention(node.exchange_rate.history_retention)
+        .build();
+    node.chain_services(store, ilp_address, log_writer).await
+}
@@ -1,12 +1,14 @@
 use cfg_if::cfg_if;
 
+#[cfg(feature = "packet-records")]
+use crate::instrumentation::packet_records::{record_packets, PacketRecorder, PacketRecordsConfig};
 #[cfg(feature = "google-pubsub")]
-use crate::instrumentation::google_pubsub::{create_google_pubsub_wrapper, PubsubConfig};
+use crate::instrumentation::packet_records::{PubsubConfig, SinkConfig};
+#[cfg(feature = "google-pubsub")]
+use tracing::warn;
 
 cfg_if! {
     if #[cfg(feature = "monitoring")] {
-        use interledger::errors::ApiError;
-        use secrecy::{ExposeSecret, SecretString};
         use tracing::debug_span;
         use tracing_appender::non_blocking::NonBlocking;
         use tracing_futures::Instrument;
@@ -16,17 +18,16 @@ cfg_if! {
             reload::Handle,
         };
         use crate::instrumentation::{
-            metrics::{incoming_metrics, outgoing_metrics},
+            metrics::{btp_connection_metrics, incoming_metrics, outgoing_metrics},
             prometheus::{serve_prometheus, PrometheusConfig},
             trace::{trace_forwarding, trace_incoming, trace_outgoing},
         };
         use interledger::service::IncomingService;
-        use futures::FutureExt;
         use std::{io::{self, Stdout}, sync::Arc};
     }
 }
 
-#[cfg(any(feature = "monitoring", feature = "google-pubsub"))]
+#[cfg(any(feature = "monitoring", feature = "packet-records"))]
 use interledger::service::OutgoingService;
 
 use bytes::Bytes;
@@ -34,22 +35,26 @@ use futures::TryFutureExt;
 use hex::FromHex;
 use interledger::{
     api::{NodeApi, NodeStore},
-    btp::{btp_service_as_filter, connect_client, BtpOutgoingService, BtpStore},
+    btp::{btp_service_as_filter, connect_client, BtpAccount, BtpOutgoingService, BtpStore},
     ccp::{CcpRouteManagerBuilder, CcpRoutingAccount, CcpRoutingStore, RoutingRelation},
     errors::*,
     http::{HttpClientService, HttpServer as IlpOverHttpServer, HttpStore},
     ildcp::IldcpService,
     packet::Address,
     packet::{ErrorCode, RejectBuilder},
-    rates::{ExchangeRateFetcher, ExchangeRateStore},
+    rates::{
+        ExchangeRateFetcher, ExchangeRateHistoryStore, ExchangeRateStore, RateAggregation,
+        DEFAULT_RATE_HISTORY_RETENTION,
+    },
     router::{Router, RouterStore},
     service::{
         outgoing_service_fn, Account as AccountTrait, AccountStore, AddressStore, OutgoingRequest,
         Username,
     },
     service_util::{
-        BalanceStore, EchoService, ExchangeRateService, ExpiryShortenerService,
-        MaxPacketAmountService, RateLimitService, RateLimitStore, ValidatorService,
+        BalanceStore, EchoService, ExchangeRateService, ExpiryShortenerService, FeePolicy,
+        FeePolicyStore, MaxPacketAmountService, PacketDrain, RateLimitService, RateLimitStore,
+        ShutdownService, TransactionStore, ValidatorService, DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT,
     },
     settlement::{
         api::{create_settlements_filter, SettlementMessageService},
@@ -59,11 +64,12 @@ use interledger::{
         },
     },
     store::account::Account,
-    stream::{StreamNotificationsStore, StreamReceiverService},
+    stream::{StreamConnectionStore, StreamNotificationsStore, StreamReceiverService},
 };
 use num_bigint::BigUint;
 use once_cell::sync::Lazy;
-use serde::{de::Error as DeserializeError, Deserialize, Deserializer};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{de::Error as DeserializeError, Deserialize, Deserializer, Serialize};
 #[cfg(feature = "balance-tracking")]
 use std::num::NonZeroU32;
 use std::{
@@ -76,21 +82,37 @@ use tokio::spawn;
 use tracing::{debug, error, info};
 use url::Url;
 use uuid::Uuid;
-use warp::{self, Filter};
+use warp::{self, filters::BoxedFilter, Filter, Reply};
 
+#[cfg(feature = "memory")]
+use crate::memory_store::*;
+#[cfg(feature = "postgres")]
+use crate::postgres_store::*;
 #[cfg(feature = "redis")]
 use crate::redis_store::*;
+#[cfg(feature = "sled")]
+use crate::sled_store::*;
 #[cfg(feature = "balance-tracking")]
 use interledger::service_util::{start_delayed_settlement, BalanceService};
 
 #[doc(hidden)]
 pub use interledger::rates::ExchangeRateProvider;
 
+use crate::reload::{ConfigReloader, LiveServices, ReloadError};
+use crate::shutdown::NodeHandle;
+use crate::tls::{bind_tls, reload_on_hangup, ReloadableAcceptor, TlsConfig};
+
 static DEFAULT_ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("local.host").unwrap());
 
 fn default_settlement_api_bind_address() -> SocketAddr {
     SocketAddr::from(([127, 0, 0, 1], 7771))
 }
+fn default_shutdown_timeout() -> u64 {
+    60_000
+}
+fn default_max_transactions_per_account() -> usize {
+    DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT
+}
 fn default_http_bind_address() -> SocketAddr {
     SocketAddr::from(([127, 0, 0, 1], 7770))
 }
@@ -102,6 +124,8 @@ fn default_http_bind_address() -> SocketAddr {
 fn default_database_url() -> String {
     #[cfg(feature = "redis")]
     return default_redis_url();
+    #[cfg(feature = "memory")]
+    return default_memory_url();
     panic!("no backing store configured")
 }
 
@@ -143,6 +167,18 @@ where
     }
 }
 
+/// Fails on the deprecated `google_pubsub` key, rather than ignoring it, when the node
+/// cannot publish the packet records to Google Cloud PubSub
+#[cfg(not(feature = "google-pubsub"))]
+fn reject_google_pubsub<'de, D>(_deserializer: D) -> Result<(), D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Err(DeserializeError::custom(
+        "google_pubsub needs the google-pubsub feature, which this node was built without",
+    ))
+}
+
 /// Configuration for calculating exchange rates between various pairs.
 #[derive(Deserialize, Clone, PartialEq, Debug)]
 pub struct ExchangeRateConfig {
@@ -157,10 +193,37 @@ pub struct ExchangeRateConfig {
     /// API to poll for exchange rates. Currently the supported options are:
     /// - [CoinCap](https://docs.coincap.io)
     /// - [CryptoCompare](https://cryptocompare.com) (note this requires an API key)
+    /// - `Http`, any JSON API, with the paths of the rates in its responses
+    /// - `File`, a local JSON or YAML file, which is read again on every poll
     /// If this value is not set, the node will not poll for exchange rates and will
     /// instead use the rates configured via the HTTP API.
     #[serde(default)]
     pub provider: Option<ExchangeRateProvider>,
+    /// More APIs to poll for exchange rates, along with `provider`. The providers are
+    /// polled concurrently and their rates are combined per asset: quotes further than
+    /// `max_deviation` from the median are dropped, and the median of the remaining ones
+    /// is used if at least `min_sources` providers agree on it.
+    #[serde(default)]
+    pub providers: Vec<ExchangeRateProvider>,
+    /// Largest difference, as a fraction of the median of the quotes of all providers,
+    /// that a provider's quote may have before it is dropped as an outlier.
+    /// Defaults to 0.05 (5%).
+    #[serde(default = "ExchangeRateConfig::default_max_deviation")]
+    pub max_deviation: f64,
+    /// Number of providers which must agree on the rate of an asset for it to be updated.
+    /// Defaults to 1.
+    #[serde(default = "ExchangeRateConfig::default_min_sources")]
+    pub min_sources: usize,
+    /// Age, in milliseconds, above which a rate fetched from the providers is not used
+    /// anymore and the packets it would price are rejected. If this value is not set,
+    /// rates are used until they are removed after `poll_failure_tolerance` failed polls.
+    #[serde(default)]
+    pub max_rate_age: Option<u64>,
+    /// How long, in milliseconds, the rates are kept in their history. The last rate
+    /// recorded before that is kept as well, since it was still in effect.
+    /// Defaults to 7776000000ms (90 days).
+    #[serde(default = "ExchangeRateConfig::default_history_retention")]
+    pub history_retention: u64,
     /// Spread, as a fraction, to add on top of the exchange rate.
     /// This amount is kept as the node operator's profit, or may cover
     /// fluctuations in exchange rates.
@@ -169,6 +232,10 @@ pub struct ExchangeRateConfig {
     /// outgoing packet would be 198 (instead of 200 without the spread).
     #[serde(default)]
     pub spread: f64,
+    /// Spreads of specific asset pairs, and spreads and fees of specific accounts,
+    /// overriding `spread`. They can also be set through the HTTP API.
+    #[serde(default)]
+    pub fees: FeePolicy,
 }
 
 impl Default for ExchangeRateConfig {
@@ -179,7 +246,13 @@ impl Default for ExchangeRateConfig {
             poll_interval: Self::default_poll_interval(),
             poll_failure_tolerance: Self::default_poll_failure_tolerance(),
             provider: Default::default(),
+            providers: Default::default(),
+            max_deviation: Self::default_max_deviation(),
+            min_sources: Self::default_min_sources(),
+            max_rate_age: Default::default(),
+            history_retention: Self::default_history_retention(),
             spread: Self::default_spread(),
+            fees: Default::default(),
         }
     }
 }
@@ -194,6 +267,24 @@ impl ExchangeRateConfig {
     pub(crate) fn default_spread() -> f64 {
         0.0
     }
+    pub(crate) fn default_max_deviation() -> f64 {
+        0.05
+    }
+    pub(crate) fn default_min_sources() -> usize {
+        1
+    }
+    pub(crate) fn default_history_retention() -> u64 {
+        DEFAULT_RATE_HISTORY_RETENTION
+    }
+
+    /// All of the providers to poll, starting with `provider`
+    fn all_providers(&self) -> Vec<ExchangeRateProvider> {
+        self.provider
+            .iter()
+            .chain(self.providers.iter())
+            .cloned()
+            .collect()
+    }
 }
 
 /// An all-in-one Interledger node that includes sender and receiver functionality,
@@ -211,7 +302,7 @@ pub struct InterledgerNode {
     pub secret_seed: [u8; 32],
     /// HTTP Authorization token for the node admin (sent as a Bearer token)
     pub admin_auth_token: String,
-    /// Data store URI (for example, "redis://127.0.0.1:6379" or "redis+unix:/tmp/redis.sock")
+    /// Data store URI (for example, "redis://127.0.0.1:6379", "redis+unix:/tmp/redis.sock", "postgres://localhost/ilp", "sled:/var/lib/ilp-node" or "memory://")
     #[serde(
         default = "default_database_url",
         // temporary alias for backwards compatibility
@@ -228,6 +319,14 @@ pub struct InterledgerNode {
     /// IP address and port to listen for the Settlement Engine API
     #[serde(default = "default_settlement_api_bind_address")]
     pub settlement_api_bind_address: SocketAddr,
+    /// TLS configuration of the HTTP listener, which serves the API, ILP over HTTP
+    /// and BTP. Connections are plaintext if this is not set
+    #[serde(default)]
+    pub http_tls: Option<TlsConfig>,
+    /// TLS configuration of the Settlement Engine API listener.
+    /// Connections are plaintext if this is not set
+    #[serde(default)]
+    pub settlement_api_tls: Option<TlsConfig>,
     /// When SPSP payments are sent to the root domain, the payment pointer is resolved
     /// to <domain>/.well-known/pay. This value determines which account those payments
     /// will be sent to.
@@ -236,6 +335,16 @@ pub struct InterledgerNode {
     /// Interval, defined in milliseconds, on which the node will broadcast routing
     /// information to other nodes using CCP. Defaults to 30000ms (30 seconds).
     pub route_broadcast_interval: Option<u64>,
+    /// Keep the state of incoming STREAM connections in the store, so that each
+    /// connection is limited to the amount registered for it (if any), and publish
+    /// a single payment notification per connection when it closes.
+    #[serde(default)]
+    pub stateful_stream_receiver: bool,
+    /// Number of transactions kept in the history of each account. Once an account has
+    /// more, its oldest transactions are removed and only their totals are kept.
+    /// Defaults to 100000.
+    #[serde(default = "default_max_transactions_per_account")]
+    pub max_transactions_per_account: usize,
     #[serde(default)]
     /// Configuration for calculating exchange rates between various pairs.
     pub exchange_rate: ExchangeRateConfig,
@@ -245,44 +354,66 @@ pub struct InterledgerNode {
     #[cfg(feature = "monitoring")]
     #[serde(default)]
     pub prometheus: Option<PrometheusConfig>,
+    /// Records of the fulfilled and rejected packets, and the sinks they are written to.
+    /// If this configuration is not provided, the packets are not recorded.
+    /// Needs the feature flag "packet-records" to be enabled
+    #[cfg(feature = "packet-records")]
+    #[serde(default)]
+    pub packet_records: Option<PacketRecordsConfig>,
+    /// Deprecated: Google Cloud PubSub topic the packet records are published to.
+    /// It is added to the sinks of `packet_records`, where it should be configured instead
     #[cfg(feature = "google-pubsub")]
+    #[serde(default)]
     pub google_pubsub: Option<PubsubConfig>,
+    #[cfg(not(feature = "google-pubsub"))]
+    #[serde(default, deserialize_with = "reject_google_pubsub")]
+    google_pubsub: (),
     /// The delay in seconds to settle peering account to `settle_to` level in addition to settling
     /// the account when it exceeds the settlement threshold.
     ///
     /// See further notes at `--help` output.
     #[cfg(feature = "balance-tracking")]
     pub settle_every: Option<NonZeroU32>,
+    /// Maximum time, in milliseconds, that the node waits for the packets in flight when
+    /// it shuts down, and then for the delayed settlements. Defaults to 60000ms (60 seconds).
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
 }
 
 impl InterledgerNode {
-    /// Returns a future that runs the Interledger.rs Node.
+    /// Returns a future that starts the Interledger.rs Node and resolves
+    /// to the handle used to shut it down once it is running.
     ///
     /// If the Prometheus configuration was provided, it will
     /// also run the Prometheus metrics server on the given address.
     // TODO when a BTP connection is made, insert a outgoing HTTP entry into the Store to tell other
     // connector instances to forward packets for that account to us
-    pub async fn serve(self, log_writer: Option<LogWriter>) -> Result<(), ()> {
-        cfg_if! {
-            if #[cfg(feature = "monitoring")] {
-                let f = futures::future::join(serve_prometheus(self.clone()), self.serve_node(log_writer)).then(
-                    |r| async move {
-                        if r.0.is_ok() || r.1.is_ok() {
-                            Ok(())
-                        } else {
-                            Err(())
-                        }
-                    },
+    pub async fn serve(self, log_writer: Option<LogWriter>) -> Result<NodeHandle, ()> {
+        self.serve_node(log_writer).await
+    }
+
+    /// The packet records configuration, with the deprecated `google_pubsub` topic
+    /// added to its sinks
+    #[cfg(feature = "packet-records")]
+    pub(crate) fn packet_records_config(&self) -> Option<PacketRecordsConfig> {
+        #[allow(unused_mut)]
+        let mut config = self.packet_records.clone();
+        #[cfg(feature = "google-pubsub")]
+        {
+            if let Some(ref pubsub) = self.google_pubsub {
+                warn!(target: "interledger-node",
+                    "google_pubsub is deprecated, configure it as a sink of packet_records instead"
                 );
-            } else {
-                let f = self.serve_node(log_writer);
+                config
+                    .get_or_insert_with(PacketRecordsConfig::default)
+                    .sinks
+                    .push(SinkConfig::GooglePubsub(pubsub.clone()));
             }
         }
-
-        f.await
+        config
     }
 
-    async fn serve_node(self, log_writer: Option<LogWriter>) -> Result<(), ()> {
+    async fn serve_node(self, log_writer: Option<LogWriter>) -> Result<NodeHandle, ()> {
         let ilp_address = if let Some(address) = &self.ilp_address {
             address.clone()
         } else {
@@ -304,6 +435,12 @@ impl InterledgerNode {
         match database_url.scheme() {
             #[cfg(feature = "redis")]
             "redis" | "redis+unix" => serve_redis_node(self, ilp_address, log_writer).await,
+            #[cfg(feature = "memory")]
+            "memory" => serve_memory_node(self, ilp_address, log_writer).await,
+            #[cfg(feature = "postgres")]
+            "postgres" | "postgresql" => serve_postgres_node(self, ilp_address, log_writer).await,
+            #[cfg(feature = "sled")]
+            "sled" => serve_sled_node(self, database_url.path(), ilp_address, log_writer).await,
             other => {
                 error!("unsupported data source scheme: {}", other);
                 Err(())
@@ -317,16 +454,20 @@ impl InterledgerNode {
         store: S,
         ilp_address: Address,
         _log_writer: Option<LogWriter>,
-    ) -> Result<(), ()>
+    ) -> Result<NodeHandle, ()>
     where
         S: NodeStore<Account = Account>
             + AddressStore
             + BtpStore<Account = Account>
             + HttpStore<Account = Account>
             + StreamNotificationsStore<Account = Account>
+            + StreamConnectionStore
             + BalanceStore
+            + TransactionStore
             + SettlementStore<Account = Account>
             + ExchangeRateStore
+            + ExchangeRateHistoryStore
+            + FeePolicyStore
             + BalanceStore
             + SettlementStore<Account = Account>
             + RouterStore<Account = Account>
@@ -348,17 +489,36 @@ impl InterledgerNode {
         let secret_seed = Bytes::copy_from_slice(&self.secret_seed[..]);
         let http_bind_address = self.http_bind_address;
         let settlement_api_bind_address = self.settlement_api_bind_address;
+        let http_tls = self.http_tls.clone();
+        let settlement_api_tls = self.settlement_api_tls.clone();
         let ilp_address_clone = ilp_address.clone();
         let ilp_address_clone2 = ilp_address.clone();
         let admin_auth_token = self.admin_auth_token.clone();
         let default_spsp_account = self.default_spsp_account.clone();
         let route_broadcast_interval = self.route_broadcast_interval;
-        let exchange_rate_provider = self.exchange_rate.provider.clone();
+        let exchange_rate_providers = self.exchange_rate.all_providers();
+        let exchange_rate_aggregation = RateAggregation {
+            max_deviation: self.exchange_rate.max_deviation,
+            min_sources: self.exchange_rate.min_sources,
+        };
+        let exchange_rate_max_age = self.exchange_rate.max_rate_age;
         let exchange_rate_poll_interval = self.exchange_rate.poll_interval;
         let exchange_rate_poll_failure_tolerance = self.exchange_rate.poll_failure_tolerance;
         let exchange_rate_spread = self.exchange_rate.spread;
-        #[cfg(feature = "google-pubsub")]
-        let google_pubsub = self.google_pubsub.clone();
+        if let Err(err) = self.exchange_rate.fees.validate() {
+            error!(target: "interledger-node", "Invalid exchange_rate.fees: {}", err);
+            return Err(());
+        }
+        store.set_fee_policy(self.exchange_rate.fees.clone());
+        let config = self.clone();
+        #[cfg(feature = "monitoring")]
+        let prometheus = serve_prometheus(self.prometheus.as_ref());
+        let packet_drain = PacketDrain::new();
+        #[cfg(feature = "packet-records")]
+        let packet_recorder = match self.packet_records_config() {
+            Some(ref config) => Some(PacketRecorder::start(config).map_err(|_| ()).await?),
+            None => None,
+        };
 
         let btp_accounts = store
             .get_btp_outgoing_accounts()
@@ -373,8 +533,15 @@ impl InterledgerNode {
             {
                 error!(target: "interledger-node", "No route found for outgoing request");
             }
+            // Accounts we connect to over BTP are only unreachable until the connection
+            // comes back, so let the Router fail over to another route if there is one
+            let code = if request.to.get_ilp_over_btp_url().is_some() {
+                ErrorCode::T01_PEER_UNREACHABLE
+            } else {
+                ErrorCode::F02_UNREACHABLE
+            };
             Err(RejectBuilder {
-                code: ErrorCode::F02_UNREACHABLE,
+                code,
                 message: &format!(
                     // TODO we might not want to expose the internal account ID in the error
                     "No outgoing route for account: {} (ILP address of the Prepare packet: {})",
@@ -389,8 +556,8 @@ impl InterledgerNode {
         });
 
         // Connect to all of the accounts that have outgoing ilp_over_btp_urls configured
-        // but don't fail if we are unable to connect
-        // TODO try reconnecting to those accounts later
+        // but don't fail if we are unable to connect: dropped or unavailable connections
+        // are dialed again in the background
         let btp_client_service = connect_client(
             ilp_address_clone2.clone(),
             btp_accounts,
@@ -399,10 +566,25 @@ impl InterledgerNode {
         )
         .map_err(|err| error!("{}", err))
         .await?;
+        #[cfg(feature = "monitoring")]
+        tokio::spawn(btp_connection_metrics(
+            btp_client_service.subscribe_connection_events(),
+        ));
+        // Track the incoming connections together with the ones we dial,
+        // so that the API reports all the BTP peers
         let btp_server_service =
-            BtpOutgoingService::new(ilp_address_clone2, btp_client_service.clone());
+            BtpOutgoingService::new(ilp_address_clone2, btp_client_service.clone())
+                .with_connection_monitor(btp_client_service.connection_monitor().clone());
         let btp_server_service_clone = btp_server_service.clone();
         let btp = btp_client_service.clone();
+        let close_btp = {
+            let btp_client_service = btp_client_service.clone();
+            let btp_server_service = btp_server_service.clone();
+            move || {
+                btp_client_service.close();
+                btp_server_service.close();
+            }
+        };
 
         // The BTP service is both an Incoming and Outgoing one so we pass it first as the Outgoing
         // service to others like the router and then call handle_incoming on it to set up the incoming handler
@@ -418,27 +600,60 @@ impl InterledgerNode {
         let outgoing_service = ExpiryShortenerService::new(outgoing_service);
         let outgoing_service =
             StreamReceiverService::new(secret_seed.clone(), store.clone(), outgoing_service);
+        let outgoing_service = if self.stateful_stream_receiver {
+            outgoing_service.with_connection_state()
+        } else {
+            outgoing_service
+        };
 
         #[cfg(feature = "balance-tracking")]
-        let outgoing_service = match self.settle_every {
+        let (outgoing_service, delayed_settlement) = match self.settle_every {
             Some(seconds) => {
                 use futures::stream::StreamExt;
                 let delay = Duration::from_secs(seconds.get().into());
                 let (tx, rx) = tokio::sync::mpsc::channel(128);
 
-                start_delayed_settlement(delay, rx.fuse(), store.clone());
+                let task = start_delayed_settlement(delay, rx.fuse(), store.clone());
 
-                BalanceService::new(store.clone(), Some(tx), outgoing_service)
+                (
+                    BalanceService::new(store.clone(), Some(tx.clone()), outgoing_service),
+                    Some((tx, task)),
+                )
             }
-            None => BalanceService::new(store.clone(), None, outgoing_service),
+            None => (
+                BalanceService::new(store.clone(), None, outgoing_service),
+                None,
+            ),
         };
-
-        let outgoing_service =
+        #[cfg(feature = "balance-tracking")]
+        let balance_tasks = Some(outgoing_service.tasks());
+        #[cfg(not(feature = "balance-tracking"))]
+        let (delayed_settlement, balance_tasks) = (None, None);
+        let settlement_delay = delayed_settlement
+            .as_ref()
+            .map(|(sender, _)| sender.clone());
+
+        let mut outgoing_service =
             ExchangeRateService::new(exchange_rate_spread, store.clone(), outgoing_service);
-
-        #[cfg(feature = "google-pubsub")]
-        let outgoing_service =
-            outgoing_service.wrap(create_google_pubsub_wrapper(google_pubsub).await);
+        if let Some(max_rate_age) = exchange_rate_max_age {
+            outgoing_service =
+                outgoing_service.with_max_rate_age(Duration::from_millis(max_rate_age));
+        }
+        #[cfg(feature = "packet-records")]
+        {
+            if let Some(ref recorder) = packet_recorder {
+                outgoing_service =
+                    outgoing_service.with_conversion_listener(record_packets(recorder.clone()));
+            }
+        }
+        let set_spread = {
+            let exchange_rate_service = outgoing_service.clone();
+            move |spread| exchange_rate_service.set_spread(spread)
+        };
+        let set_fee_policy = {
+            let store = store.clone();
+            move |policy| store.set_fee_policy(policy)
+        };
 
         // Add tracing to add the outgoing request details to the incoming span
         cfg_if! {
@@ -470,12 +685,19 @@ impl InterledgerNode {
         }
 
         let incoming_service = ccp_builder.to_service();
+        let set_route_broadcast_interval = {
+            let ccp_service = incoming_service.clone();
+            move |ms| ccp_service.set_broadcast_interval(ms)
+        };
         let incoming_service = EchoService::new(store.clone(), incoming_service);
         let incoming_service = SettlementMessageService::new(incoming_service);
         let incoming_service = IldcpService::new(incoming_service);
         let incoming_service = MaxPacketAmountService::new(store.clone(), incoming_service);
         let incoming_service = ValidatorService::incoming(store.clone(), incoming_service);
         let incoming_service = RateLimitService::new(store.clone(), incoming_service);
+        // Reject the packets coming in once the node shuts down
+        let incoming_service =
+            ShutdownService::new(packet_drain.clone(), store.clone(), incoming_service);
 
         // Add tracing to track the incoming request details
         #[cfg(feature = "monitoring")]
@@ -535,6 +757,19 @@ impl InterledgerNode {
         }
         api.node_version(env!("CARGO_PKG_VERSION").to_string());
 
+        let reloader = ConfigReloader::new(
+            config,
+            LiveServices {
+                set_spread: Box::new(set_spread),
+                set_fee_policy: Box::new(set_fee_policy),
+                set_route_broadcast_interval: Box::new(set_route_broadcast_interval),
+                default_spsp_account: api.shared_default_spsp_account(),
+                delayed_settlement: settlement_delay,
+                #[cfg(feature = "monitoring")]
+                prometheus,
+            },
+        );
+
         cfg_if! {
             if #[cfg(feature = "monitoring")] {
                 let incoming_service_http = incoming_service
@@ -557,25 +792,53 @@ impl InterledgerNode {
                 store.clone(),
             ));
 
+        let admin_only = warp::header::<SecretString>("authorization")
+            .and_then(move |authorization: SecretString| {
+                let admin_auth_header = format!("Bearer {}", self.admin_auth_token.clone());
+                async move {
+                    if authorization.expose_secret() == &admin_auth_header {
+                        Ok::<(), warp::Rejection>(())
+                    } else {
+                        Err(warp::Rejection::from(ApiError::unauthorized()))
+                    }
+                }
+            })
+            .untuple_one()
+            .boxed();
+
+        // Expose an endpoint at /config/reload which allows administrators to apply
+        // the configuration of the node again, once its files were changed
+        let reload_config = {
+            let reloader = reloader.clone();
+            warp::post()
+                .and(warp::path("config"))
+                .and(warp::path("reload"))
+                .and(warp::path::end())
+                .and(admin_only.clone())
+                .and_then(move || {
+                    let reloader = reloader.clone();
+                    async move {
+                        let changed = reloader.reload_from_source().map_err(|err| {
+                            let api_error = match err {
+                                ReloadError::RestartRequired(_) => ApiError::conflict(),
+                                ReloadError::Load(_) | ReloadError::Invalid(_) => {
+                                    ApiError::bad_request()
+                                }
+                                ReloadError::NoSource => ApiError::internal_server_error(),
+                            };
+                            api_error.detail(err.to_string())
+                        })?;
+                        Ok::<_, warp::Rejection>(warp::reply::json(&ReloadResponse { changed }))
+                    }
+                })
+        };
+        let api = api.or(reload_config);
+
         // If monitoring is enabled, run a tracing subscriber
         // and expose a new endpoint at /tracing-level which allows
         // changing the tracing level by administrators
         cfg_if! {
             if #[cfg(feature = "monitoring")] {
-                let admin_only = warp::header::<SecretString>("authorization")
-                    .and_then(move |authorization: SecretString| {
-                        let admin_auth_header = format!("Bearer {}", self.admin_auth_token.clone());
-                        async move {
-                            if authorization.expose_secret() == &admin_auth_header {
-                                Ok::<(), warp::Rejection>(())
-                            } else {
-                                Err(warp::Rejection::from(ApiError::unauthorized()))
-                            }
-                        }
-                    })
-                    .untuple_one()
-                    .boxed();
-
                 let api = {
                     let tracing_handle = _log_writer.and_then(|al| al.handle);
 
@@ -624,18 +887,40 @@ impl InterledgerNode {
             .with(warp::log("interledger-api"))
             .boxed();
 
-        info!(target: "interledger-node", "Interledger.rs node HTTP API listening on: {}", http_bind_address);
-        spawn(warp::serve(api).bind(http_bind_address));
+        let mut tls_acceptors = Vec::new();
+        tls_acceptors.extend(
+            serve_listener(
+                "Interledger.rs node HTTP API",
+                api,
+                http_bind_address,
+                http_tls,
+            )
+            .await?,
+        );
 
         // Settlement API
         let settlement_api = create_settlements_filter(store.clone(), outgoing_service.clone());
-        info!(target: "interledger-node", "Settlement API listening on: {}", settlement_api_bind_address);
-        spawn(warp::serve(settlement_api).bind(settlement_api_bind_address));
+        tls_acceptors.extend(
+            serve_listener(
+                "Settlement API",
+                settlement_api,
+                settlement_api_bind_address,
+                settlement_api_tls,
+            )
+            .await?,
+        );
+
+        if !tls_acceptors.is_empty() {
+            reload_on_hangup(tls_acceptors).map_err(|err| {
+                error!(target: "interledger-node", "Could not listen for SIGHUP to reload the TLS certificates: {}", err)
+            })?;
+        }
 
         // Exchange Rate Polling
-        if let Some(provider) = exchange_rate_provider {
-            let exchange_rate_fetcher = ExchangeRateFetcher::new(
-                provider,
+        if !exchange_rate_providers.is_empty() {
+            let exchange_rate_fetcher = ExchangeRateFetcher::with_providers(
+                exchange_rate_providers,
+                exchange_rate_aggregation,
                 exchange_rate_poll_failure_tolerance,
                 store.clone(),
             );
@@ -645,7 +930,15 @@ impl InterledgerNode {
             debug!(target: "interledger-node", "Not using exchange rate provider. Rates must be set via the HTTP API");
         }
 
-        Ok(())
+        Ok(NodeHandle {
+            packets: packet_drain,
+            balance_tasks,
+            delayed_settlement,
+            close_btp: Box::new(close_btp),
+            reloader,
+            #[cfg(feature = "packet-records")]
+            packet_records: packet_recorder,
+        })
     }
 }
 
@@ -683,3 +976,43 @@ cfg_if! {
         pub struct LogWriter;
     }
 }
+
+/// Response of the `POST /config/reload` endpoint
+#[derive(Serialize)]
+struct ReloadResponse {
+    /// Settings which were changed by the new configuration
+    changed: Vec<&'static str>,
+}
+
+/// Spawns a server for the filter on the address, over TLS if it is configured.
+/// Returns the acceptor of the TLS connections, so that its certificates can be reloaded
+async fn serve_listener<T>(
+    name: &str,
+    filter: BoxedFilter<(T,)>,
+    bind_address: SocketAddr,
+    tls: Option<TlsConfig>,
+) -> Result<Option<ReloadableAcceptor>, ()>
+where
+    T: Reply + Send + 'static,
+{
+    let tls = match tls {
+        Some(tls) => tls,
+        None => {
+            info!(target: "interledger-node", "{} listening on: {}", name, bind_address);
+            spawn(warp::serve(filter).bind(bind_address));
+            return Ok(None);
+        }
+    };
+
+    let acceptor = ReloadableAcceptor::new(tls).map_err(|err| {
+        error!(target: "interledger-node", "Invalid TLS configuration for the {}: {}", name, err)
+    })?;
+    let incoming = bind_tls(bind_address, acceptor.clone())
+        .await
+        .map_err(|err| {
+            error!(target: "interledger-node", "Could not bind the {} to {}: {}", name, bind_address, err)
+        })?;
+    info!(target: "interledger-node", "{} listening with TLS on: {}", name, bind_address);
+    spawn(warp::serve(filter).run_incoming(incoming));
+    Ok(Some(acceptor))
+}
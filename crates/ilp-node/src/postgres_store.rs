@@ -0,0 +1,40 @@
+#![cfg(feature = "postgres")]
+
+use crate::node::{InterledgerNode, LogWriter};
+use crate::shutdown::NodeHandle;
+use interledger::{packet::Address, store::postgres::PostgresStoreBuilder};
+use postgres_crate::Config;
+use ring::hmac;
+use std::str::FromStr;
+use tracing::error;
+
+static POSTGRES_SECRET_GENERATION_STRING: &str = "ilp_postgres_secret";
+
+// See the note on `serve_redis_node` for why this is not an inherent method on InterledgerNode.
+pub async fn serve_postgres_node(
+    node: InterledgerNode,
+    ilp_address: Address,
+    log_writer: Option<LogWriter>,
+) -> Result<NodeHandle, ()> {
+    let config = Config::from_str(&node.database_url).map_err(
+        |err| error!(target: "interledger-node", "Invalid Postgres database URL: {}", err),
+    )?;
+    let postgres_secret = generate_postgres_secret(&node.secret_seed);
+    let store = PostgresStoreBuilder::new(config, postgres_secret)
+        .node_ilp_address(ilp_address.clone())
+        .max_transactions_per_account(node.max_transactions_per_account)
+        .rate_history_retention(node.exchange_rate.history_retention)
+        .connect()
+        .await?;
+    node.chain_services(store, ilp_address, log_writer).await
+}
+
+pub fn generate_postgres_secret(secret_seed: &[u8; 32]) -> [u8; 32] {
+    let mut postgres_secret: [u8; 32] = [0; 32];
+    let sig = hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, secret_seed),
+        POSTGRES_SECRET_GENERATION_STRING.as_bytes(),
+    );
+    postgres_secret.copy_from_slice(sig.as_ref());
+    postgres_secret
+}
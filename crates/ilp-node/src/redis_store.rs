@@ -1,6 +1,7 @@
 #![cfg(feature = "redis")]
 
 use crate::node::{InterledgerNode, LogWriter};
+use crate::shutdown::NodeHandle;
 use futures::TryFutureExt;
 pub use interledger::{
     api::{AccountDetails, NodeStore},
@@ -25,13 +26,15 @@ pub async fn serve_redis_node(
     node: InterledgerNode,
     ilp_address: Address,
     log_writer: Option<LogWriter>,
-) -> Result<(), ()> {
+) -> Result<NodeHandle, ()> {
     let redis_connection_info = node.database_url.clone().into_connection_info().unwrap();
     let redis_addr = redis_connection_info.addr.clone();
     let redis_secret = generate_redis_secret(&node.secret_seed);
     let store = RedisStoreBuilder::new(redis_connection_info, redis_secret)
         .with_db_prefix(node.database_prefix.as_str())
         .node_ilp_address(ilp_address.clone())
+        .max_transactions_per_account(node.max_transactions_per_account)
+        .rate_history_retention(node.exchange_rate.history_retention)
         .connect()
         .map_err(move |err| error!(target: "interledger-node", "Error connecting to Redis: {:?} {:?}", redis_addr, err))
         .await?;
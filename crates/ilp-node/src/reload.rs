@@ -0,0 +1,277 @@
+#[cfg(feature = "monitoring")]
+use crate::instrumentation::prometheus::PrometheusServer;
+use crate::node::InterledgerNode;
+use interledger::{
+    api::DefaultSpspAccount,
+    ccp::DEFAULT_BROADCAST_INTERVAL,
+    service_util::{FeePolicy, ManageTimeout},
+};
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Loads the configuration again, from wherever the node got it when it started
+type ConfigSource = dyn Fn() -> Result<InterledgerNode, String> + Send + Sync;
+
+/// Errors reloading the configuration of a running node
+#[derive(Error, Debug)]
+pub enum ReloadError {
+    #[error("the node does not know where to load its configuration from")]
+    NoSource,
+    #[error("could not load the configuration: {0}")]
+    Load(String),
+    #[error("invalid configuration: {0}")]
+    Invalid(String),
+    #[error("the node must be restarted to change {}", .0.join(", "))]
+    RestartRequired(Vec<&'static str>),
+}
+
+/// Handles to the running services, used to apply the settings which can be changed live
+pub(crate) struct LiveServices {
+    pub(crate) set_spread: Box<dyn Fn(f64) + Send + Sync>,
+    pub(crate) set_fee_policy: Box<dyn Fn(FeePolicy) + Send + Sync>,
+    pub(crate) set_route_broadcast_interval: Box<dyn Fn(u64) + Send + Sync>,
+    pub(crate) default_spsp_account: DefaultSpspAccount,
+    /// Commands of the task running the delayed settlements, if the node settles on delay
+    pub(crate) delayed_settlement: Option<mpsc::Sender<ManageTimeout>>,
+    #[cfg(feature = "monitoring")]
+    pub(crate) prometheus: PrometheusServer,
+}
+
+struct ReloaderState {
+    current: Mutex<InterledgerNode>,
+    services: LiveServices,
+    source: RwLock<Option<Box<ConfigSource>>>,
+}
+
+/// Applies a new configuration to a running node.
+///
+/// These settings are changed live:
+/// - `exchange_rate.spread`
+/// - `exchange_rate.fees`
+/// - `route_broadcast_interval`
+/// - `default_spsp_account`
+/// - `settle_every`, if the node was already started with it
+/// - `shutdown_timeout`
+/// - `prometheus`, which starts, moves or stops the metrics server
+///
+/// Changing any other setting requires a restart, so a configuration changing one of them
+/// is rejected as a whole and the node keeps running with the configuration it has.
+#[derive(Clone)]
+pub struct ConfigReloader {
+    state: Arc<ReloaderState>,
+}
+
+impl ConfigReloader {
+    pub(crate) fn new(config: InterledgerNode, services: LiveServices) -> Self {
+        ConfigReloader {
+            state: Arc::new(ReloaderState {
+                current: Mutex::new(config),
+                services,
+                source: RwLock::new(None),
+            }),
+        }
+    }
+
+    /// Sets where `reload_from_source` loads the configuration from, which is usually
+    /// the same files, environment variables and arguments that the node was started with
+    pub fn set_source<F>(&self, source: F)
+    where
+        F: Fn() -> Result<InterledgerNode, String> + Send + Sync + 'static,
+    {
+        *self.state.source.write().unwrap() = Some(Box::new(source));
+    }
+
+    /// Loads the configuration from its source and applies it.
+    /// Returns the settings which were changed
+    pub fn reload_from_source(&self) -> Result<Vec<&'static str>, ReloadError> {
+        let config = {
+            let source = self.state.source.read().unwrap();
+            let source = source.as_ref().ok_or(ReloadError::NoSource)?;
+            source().map_err(|err| {
+                let err = ReloadError::Load(err);
+                warn!(target: "interledger-node", "Configuration was not reloaded: {}", err);
+                err
+            })?
+        };
+        self.reload(config)
+    }
+
+    /// Applies the configuration to the running node. Returns the settings which were changed
+    pub fn reload(&self, config: InterledgerNode) -> Result<Vec<&'static str>, ReloadError> {
+        let mut current = self.state.current.lock().unwrap();
+        let restart_required = restart_required(&current, &config);
+        if !restart_required.is_empty() {
+            let err = ReloadError::RestartRequired(restart_required);
+            warn!(target: "interledger-node", "Configuration was not reloaded: {}", err);
+            return Err(err);
+        }
+        if let Err(err) = config.exchange_rate.fees.validate() {
+            let err = ReloadError::Invalid(format!("exchange_rate.fees: {}", err));
+            warn!(target: "interledger-node", "Configuration was not reloaded: {}", err);
+            return Err(err);
+        }
+
+        let services = &self.state.services;
+        let mut changed = Vec::new();
+        // Applied first, since the new address may not be available
+        #[cfg(feature = "monitoring")]
+        {
+            if current.prometheus != config.prometheus {
+                if let Err(err) = services.prometheus.apply(config.prometheus.as_ref()) {
+                    let err = ReloadError::Invalid(format!("prometheus: {}", err));
+                    warn!(target: "interledger-node", "Configuration was not reloaded: {}", err);
+                    return Err(err);
+                }
+                changed.push("prometheus");
+            }
+        }
+        // Compared by bits, so that any change of the value is applied
+        if current.exchange_rate.spread.to_bits() != config.exchange_rate.spread.to_bits() {
+            (services.set_spread)(config.exchange_rate.spread);
+            changed.push("exchange_rate.spread");
+        }
+        if current.exchange_rate.fees != config.exchange_rate.fees {
+            (services.set_fee_policy)(config.exchange_rate.fees.clone());
+            changed.push("exchange_rate.fees");
+        }
+        if current.route_broadcast_interval != config.route_broadcast_interval {
+            (services.set_route_broadcast_interval)(
+                config
+                    .route_broadcast_interval
+                    .unwrap_or(DEFAULT_BROADCAST_INTERVAL),
+            );
+            changed.push("route_broadcast_interval");
+        }
+        if current.default_spsp_account != config.default_spsp_account {
+            services
+                .default_spsp_account
+                .set(config.default_spsp_account.clone());
+            changed.push("default_spsp_account");
+        }
+        #[cfg(feature = "balance-tracking")]
+        {
+            if let (Some(sender), Some(seconds)) =
+                (&services.delayed_settlement, config.settle_every)
+            {
+                if current.settle_every != config.settle_every {
+                    let delay = Duration::from_secs(seconds.get().into());
+                    if let Err(err) = sender.clone().try_send(ManageTimeout::SetDelay(delay)) {
+                        warn!(target: "interledger-node", "Could not change the delay of the settlements: {}", err);
+                    }
+                    changed.push("settle_every");
+                }
+            }
+        }
+        if current.shutdown_timeout != config.shutdown_timeout {
+            changed.push("shutdown_timeout");
+        }
+
+        if changed.is_empty() {
+            info!(target: "interledger-node", "Configuration reloaded, nothing changed");
+        } else {
+            info!(target: "interledger-node", "Configuration reloaded, changed {}", changed.join(", "));
+        }
+        *current = config;
+        Ok(changed)
+    }
+
+    pub(crate) fn shutdown_timeout(&self) -> Duration {
+        Duration::from_millis(self.state.current.lock().unwrap().shutdown_timeout)
+    }
+}
+
+/// Names the settings which are changed by the new configuration but
+/// cannot be applied to the running node
+fn restart_required(current: &InterledgerNode, new: &InterledgerNode) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    let mut check = |name, changed: bool| {
+        if changed {
+            fields.push(name)
+        }
+    };
+
+    check("ilp_address", current.ilp_address != new.ilp_address);
+    check("secret_seed", current.secret_seed != new.secret_seed);
+    check(
+        "admin_auth_token",
+        current.admin_auth_token != new.admin_auth_token,
+    );
+    check("database_url", current.database_url != new.database_url);
+    check(
+        "database_prefix",
+        current.database_prefix != new.database_prefix,
+    );
+    check(
+        "http_bind_address",
+        current.http_bind_address != new.http_bind_address,
+    );
+    check(
+        "settlement_api_bind_address",
+        current.settlement_api_bind_address != new.settlement_api_bind_address,
+    );
+    check("http_tls", current.http_tls != new.http_tls);
+    check(
+        "settlement_api_tls",
+        current.settlement_api_tls != new.settlement_api_tls,
+    );
+    check(
+        "stateful_stream_receiver",
+        current.stateful_stream_receiver != new.stateful_stream_receiver,
+    );
+    check(
+        "max_transactions_per_account",
+        current.max_transactions_per_account != new.max_transactions_per_account,
+    );
+    check(
+        "exchange_rate.history_retention",
+        current.exchange_rate.history_retention != new.exchange_rate.history_retention,
+    );
+    check(
+        "exchange_rate.poll_interval",
+        current.exchange_rate.poll_interval != new.exchange_rate.poll_interval,
+    );
+    check(
+        "exchange_rate.poll_failure_tolerance",
+        current.exchange_rate.poll_failure_tolerance != new.exchange_rate.poll_failure_tolerance,
+    );
+    check(
+        "exchange_rate.provider",
+        current.exchange_rate.provider != new.exchange_rate.provider,
+    );
+    check(
+        "exchange_rate.providers",
+        current.exchange_rate.providers != new.exchange_rate.providers,
+    );
+    check(
+        "exchange_rate.max_deviation",
+        current.exchange_rate.max_deviation.to_bits() != new.exchange_rate.max_deviation.to_bits(),
+    );
+    check(
+        "exchange_rate.min_sources",
+        current.exchange_rate.min_sources != new.exchange_rate.min_sources,
+    );
+    check(
+        "exchange_rate.max_rate_age",
+        current.exchange_rate.max_rate_age != new.exchange_rate.max_rate_age,
+    );
+    #[cfg(feature = "packet-records")]
+    check(
+        "packet_records",
+        current.packet_records != new.packet_records,
+    );
+    #[cfg(feature = "google-pubsub")]
+    check("google_pubsub", current.google_pubsub != new.google_pubsub);
+    // The delay can be changed, but settling on delay cannot be turned on or off
+    #[cfg(feature = "balance-tracking")]
+    check(
+        "settle_every",
+        current.settle_every.is_some() != new.settle_every.is_some(),
+    );
+
+    fields
+}
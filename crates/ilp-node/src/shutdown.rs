@@ -0,0 +1,92 @@
+#[cfg(feature = "packet-records")]
+use crate::instrumentation::packet_records::PacketRecorder;
+use crate::reload::ConfigReloader;
+use interledger::service_util::{BalanceTasks, ManageTimeout, PacketDrain};
+use std::time::Duration;
+use tokio::{
+    sync::mpsc,
+    task::JoinHandle,
+    time::{delay_for, timeout},
+};
+use tracing::{info, warn};
+
+/// Time given to the BTP connections to send their Close frames
+const BTP_CLOSE_GRACE_PERIOD: Duration = Duration::from_millis(200);
+
+/// Reloads the configuration of a running node, and shuts it down without losing
+/// the packets it is forwarding. Returned by `InterledgerNode::serve`.
+pub struct NodeHandle {
+    pub(crate) packets: PacketDrain,
+    /// Balance updates and settlements of the packets which already got their response
+    pub(crate) balance_tasks: Option<BalanceTasks>,
+    /// Asks the task running the delayed settlements to flush them, if the node settles on delay
+    pub(crate) delayed_settlement: Option<(mpsc::Sender<ManageTimeout>, JoinHandle<()>)>,
+    pub(crate) close_btp: Box<dyn FnOnce() + Send>,
+    pub(crate) reloader: ConfigReloader,
+    #[cfg(feature = "packet-records")]
+    pub(crate) packet_records: Option<PacketRecorder>,
+}
+
+impl NodeHandle {
+    /// Applies new configurations to the node while it runs
+    pub fn reloader(&self) -> ConfigReloader {
+        self.reloader.clone()
+    }
+
+    /// Shuts the node down:
+    /// 1. the incoming Prepare packets are rejected with a `T03: Connector Busy` error
+    /// 1. the packets in flight are fulfilled, rejected or expire
+    /// 1. the balances of these packets are updated, and the settlements they trigger are sent
+    /// 1. the accounts waiting for their delayed settlement are settled
+    /// 1. the packet records which are buffered are written
+    /// 1. the BTP connections are closed
+    ///
+    /// The HTTP listeners keep running until the process exits, so that the packets
+    /// coming in meanwhile are rejected rather than dropped.
+    pub async fn shutdown(self) {
+        // Maximum time to wait for the packets in flight and then for the settlements
+        let max_wait = self.reloader.shutdown_timeout();
+        info!(target: "interledger-node", "Shutting down, waiting for {} packets in flight", self.packets.in_flight());
+        self.packets.close();
+        if timeout(max_wait, self.packets.drained()).await.is_err() {
+            warn!(target: "interledger-node",
+                "{} packets were still in flight after {:?}",
+                self.packets.in_flight(),
+                max_wait
+            );
+        }
+
+        // These may still set delayed settlements, so they are waited for before the flush
+        if let Some(tasks) = self.balance_tasks {
+            if timeout(max_wait, tasks.drained()).await.is_err() {
+                warn!(target: "interledger-node",
+                    "{} balance updates and settlements were still running after {:?}",
+                    tasks.running(),
+                    max_wait
+                );
+            }
+        }
+
+        if let Some((mut flush, task)) = self.delayed_settlement {
+            // The task is gone if it already stopped on its own
+            if flush.send(ManageTimeout::Flush).await.is_ok()
+                && timeout(max_wait, task).await.is_err()
+            {
+                warn!(target: "interledger-node", "Delayed settlements were still running after {:?}", max_wait);
+            }
+        }
+
+        #[cfg(feature = "packet-records")]
+        {
+            if let Some(recorder) = self.packet_records {
+                if timeout(max_wait, recorder.flush()).await.is_err() {
+                    warn!(target: "interledger-node", "Packet records were still being written after {:?}", max_wait);
+                }
+            }
+        }
+
+        (self.close_btp)();
+        delay_for(BTP_CLOSE_GRACE_PERIOD).await;
+        info!(target: "interledger-node", "Shutdown complete");
+    }
+}
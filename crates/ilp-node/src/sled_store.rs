@@ -0,0 +1,39 @@
+#![cfg(feature = "sled")]
+
+use crate::node::{InterledgerNode, LogWriter};
+use crate::shutdown::NodeHandle;
+use interledger::{packet::Address, store::sled::SledStoreBuilder};
+use ring::hmac;
+use tracing::error;
+
+static SLED_SECRET_GENERATION_STRING: &str = "ilp_sled_secret";
+
+// See the note on `serve_redis_node` for why this is not an inherent method on InterledgerNode.
+pub async fn serve_sled_node(
+    node: InterledgerNode,
+    path: &str,
+    ilp_address: Address,
+    log_writer: Option<LogWriter>,
+) -> Result<NodeHandle, ()> {
+    if path.is_empty() {
+        error!(target: "interledger-node", "The sled database URL must contain a path, for example sled:/var/lib/ilp-node");
+        return Err(());
+    }
+    let sled_secret = generate_sled_secret(&node.secret_seed);
+    let store = SledStoreBuilder::new(path, sled_secret)
+        .node_ilp_address(ilp_address.clone())
+        .max_transactions_per_account(node.max_transactions_per_account)
+        .rate_history_retention(node.exchange_rate.history_retention)
+        .open()?;
+    node.chain_services(store, ilp_address, log_writer).await
+}
+
+pub fn generate_sled_secret(secret_seed: &[u8; 32]) -> [u8; 32] {
+    let mut sled_secret: [u8; 32] = [0; 32];
+    let sig = hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, secret_seed),
+        SLED_SECRET_GENERATION_STRING.as_bytes(),
+    );
+    sled_secret.copy_from_slice(sig.as_ref());
+    sled_secret
+}
@@ -0,0 +1,227 @@
+use futures::{stream, Stream};
+use serde::Deserialize;
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time::{delay_for, timeout},
+};
+use tokio_rustls::{
+    rustls::{
+        internal::pemfile, AllowAnyAuthenticatedClient, NoClientAuth, RootCertStore, ServerConfig,
+        TLSError,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+use tracing::{debug, error, info, warn};
+
+/// Time given to clients to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Number of connections which completed the handshake and wait to be served
+const ACCEPTED_BACKLOG: usize = 128;
+/// Wait before accepting connections again after an error such as running out of
+/// file descriptors, which would otherwise fail again right away. It doubles with
+/// each error in a row
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+/// Longest wait before accepting connections again after an error
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// TLS configuration of one of the node's listeners.
+/// The certificates and key are loaded again each time the node receives a SIGHUP,
+/// so they can be renewed without restarting the node.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain presented to clients, starting with the
+    /// node's own certificate
+    pub cert_path: PathBuf,
+    /// PEM file with the private key of the certificate (PKCS #8 or RSA)
+    pub key_path: PathBuf,
+    /// PEM file with the certificates of the CAs which issue the client certificates.
+    /// If it is set, clients must present a certificate issued by one of them (mutual TLS)
+    #[serde(default)]
+    pub client_ca_path: Option<PathBuf>,
+}
+
+/// Errors loading the TLS configuration of a listener
+#[derive(Error, Debug)]
+pub enum TlsError {
+    #[error("cannot read {0}: {1}")]
+    Read(PathBuf, io::Error),
+    #[error("no PEM certificate found in {0}")]
+    NoCertificates(PathBuf),
+    #[error("no PEM private key found in {0}")]
+    NoPrivateKey(PathBuf),
+    #[error("invalid certificate or private key: {0}")]
+    InvalidCertificate(#[from] TLSError),
+}
+
+impl TlsConfig {
+    /// Reads the certificates and key into a rustls configuration
+    fn load(&self) -> Result<ServerConfig, TlsError> {
+        let verifier = match self.client_ca_path {
+            Some(ref path) => {
+                let mut roots = RootCertStore::empty();
+                let (valid, _) = roots
+                    .add_pem_file(&mut open(path)?)
+                    .map_err(|_| TlsError::NoCertificates(path.clone()))?;
+                if valid == 0 {
+                    return Err(TlsError::NoCertificates(path.clone()));
+                }
+                AllowAnyAuthenticatedClient::new(roots)
+            }
+            None => NoClientAuth::new(),
+        };
+
+        let certs = pemfile::certs(&mut open(&self.cert_path)?)
+            .ok()
+            .filter(|certs| !certs.is_empty())
+            .ok_or_else(|| TlsError::NoCertificates(self.cert_path.clone()))?;
+        // Keys are usually PKCS #8 but older tools still write RSA keys
+        let key = pemfile::pkcs8_private_keys(&mut open(&self.key_path)?)
+            .ok()
+            .and_then(|mut keys| keys.pop())
+            .or_else(|| {
+                pemfile::rsa_private_keys(&mut open(&self.key_path).ok()?)
+                    .ok()?
+                    .pop()
+            })
+            .ok_or_else(|| TlsError::NoPrivateKey(self.key_path.clone()))?;
+
+        let mut config = ServerConfig::new(verifier);
+        config.set_single_cert(certs, key)?;
+        config.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+        Ok(config)
+    }
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, TlsError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|err| TlsError::Read(path.to_path_buf(), err))
+}
+
+/// Accepts TLS connections with the last configuration which was successfully loaded
+#[derive(Clone)]
+pub(crate) struct ReloadableAcceptor {
+    config: TlsConfig,
+    current: Arc<RwLock<Arc<ServerConfig>>>,
+}
+
+impl ReloadableAcceptor {
+    pub(crate) fn new(config: TlsConfig) -> Result<Self, TlsError> {
+        let current = Arc::new(RwLock::new(Arc::new(config.load()?)));
+        Ok(ReloadableAcceptor { config, current })
+    }
+
+    /// Loads the certificates and key again. The connections which are already open
+    /// are not affected. If loading fails, the previous configuration is kept
+    pub(crate) fn reload(&self) -> Result<(), TlsError> {
+        let config = Arc::new(self.config.load()?);
+        *self.current.write().unwrap() = config;
+        Ok(())
+    }
+
+    fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.current.read().unwrap().clone())
+    }
+}
+
+/// Binds to the address and returns the connections which completed the TLS handshake,
+/// to be served with `warp::Server::run_incoming`. Handshakes are made concurrently,
+/// and connections which fail it are dropped.
+pub(crate) async fn bind_tls(
+    bind_address: SocketAddr,
+    acceptor: ReloadableAcceptor,
+) -> io::Result<impl Stream<Item = io::Result<TlsStream<TcpStream>>>> {
+    let mut listener = TcpListener::bind(bind_address).await?;
+    let (sender, receiver) = mpsc::channel(ACCEPTED_BACKLOG);
+
+    tokio::spawn(async move {
+        let mut backoff = MIN_ACCEPT_BACKOFF;
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => {
+                    backoff = MIN_ACCEPT_BACKOFF;
+                    accepted
+                }
+                // Only that connection failed, so the next one can be accepted right away
+                Err(ref err) if is_connection_error(err) => continue,
+                Err(err) => {
+                    warn!(target: "interledger-node", "Error accepting connection on {}, retrying in {:?}: {}", bind_address, backoff, err);
+                    delay_for(backoff).await;
+                    backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                    continue;
+                }
+            };
+            let acceptor = acceptor.acceptor();
+            let mut sender = sender.clone();
+            tokio::spawn(async move {
+                match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        // The server is gone if nobody is receiving
+                        sender.send(stream).await.ok();
+                    }
+                    Ok(Err(err)) => {
+                        debug!(target: "interledger-node", "TLS handshake with {} failed: {}", peer, err)
+                    }
+                    Err(_) => {
+                        debug!(target: "interledger-node", "TLS handshake with {} timed out", peer)
+                    }
+                }
+            });
+        }
+    });
+
+    Ok(stream::unfold(receiver, |mut receiver| async move {
+        let stream = receiver.recv().await?;
+        Some((Ok(stream), receiver))
+    }))
+}
+
+/// Errors which are specific to the connection being accepted, rather than to the listener
+fn is_connection_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// Reloads the certificates of the listeners each time the node receives a SIGHUP
+#[cfg(unix)]
+pub(crate) fn reload_on_hangup(acceptors: Vec<ReloadableAcceptor>) -> io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            for acceptor in &acceptors {
+                match acceptor.reload() {
+                    Ok(()) => {
+                        info!(target: "interledger-node", "Reloaded TLS certificate {}", acceptor.config.cert_path.display())
+                    }
+                    Err(err) => {
+                        error!(target: "interledger-node", "Could not reload TLS certificate, keeping the previous one: {}", err)
+                    }
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Signals are only supported on Unix, so certificates are never reloaded elsewhere
+#[cfg(not(unix))]
+pub(crate) fn reload_on_hangup(_acceptors: Vec<ReloadableAcceptor>) -> io::Result<()> {
+    Ok(())
+}
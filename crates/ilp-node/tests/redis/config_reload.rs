@@ -0,0 +1,90 @@
+use crate::redis_helpers::*;
+use crate::test_helpers::*;
+use ilp_node::{InterledgerNode, ReloadError};
+use reqwest::{Client, StatusCode};
+use serde_json::{self, json, Value};
+
+fn node_config(context: &TestContext, http_port: u16) -> Value {
+    json!({
+        "ilp_address": "example.reload",
+        "admin_auth_token": "admin",
+        "database_url": connection_info_to_string(context.get_client_connection_info()),
+        "http_bind_address": format!("127.0.0.1:{}", http_port),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", get_open_port(None)),
+        "secret_seed": random_secret(),
+    })
+}
+
+#[tokio::test]
+async fn applies_default_spsp_account() {
+    let context = TestContext::new();
+    let http_port = get_open_port(None);
+    let mut config = node_config(&context, http_port);
+
+    let node: InterledgerNode = serde_json::from_value(config.clone()).unwrap();
+    let node = node.serve(None).await.unwrap();
+    create_account_on_node(
+        http_port,
+        json!({
+            "username": "alice",
+            "asset_code": "XYZ",
+            "asset_scale": 9,
+        }),
+        "admin",
+    )
+    .await
+    .unwrap();
+
+    let well_known = format!("http://localhost:{}/.well-known/pay", http_port);
+    let res = Client::new().get(&well_known).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    config["default_spsp_account"] = json!("alice");
+    let changed = node
+        .reloader()
+        .reload(serde_json::from_value(config).unwrap())
+        .unwrap();
+    assert_eq!(changed, vec!["default_spsp_account"]);
+
+    let res = Client::new().get(&well_known).send().await.unwrap();
+    assert!(res.status().is_success());
+}
+
+#[tokio::test]
+async fn rejects_changes_requiring_restart() {
+    let context = TestContext::new();
+    let http_port = get_open_port(None);
+    let config = node_config(&context, http_port);
+
+    let node: InterledgerNode = serde_json::from_value(config.clone()).unwrap();
+    let node = node.serve(None).await.unwrap();
+    let reloader = node.reloader();
+
+    let mut changed_address = config.clone();
+    changed_address["ilp_address"] = json!("example.other");
+    changed_address["exchange_rate"] = json!({ "spread": 0.01 });
+    match reloader.reload(serde_json::from_value(changed_address).unwrap()) {
+        Err(ReloadError::RestartRequired(fields)) => assert_eq!(fields, vec!["ilp_address"]),
+        other => panic!("Expected the reload to be rejected, got {:?}", other),
+    }
+
+    // The API reloads the configuration from its source
+    let reload = || {
+        Client::new()
+            .post(&format!("http://localhost:{}/config/reload", http_port))
+            .header("Authorization", "Bearer admin")
+            .send()
+    };
+    let res = reload().await.unwrap();
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let mut changed_spread = config;
+    changed_spread["exchange_rate"] = json!({ "spread": 0.01 });
+    reloader.set_source(move || {
+        serde_json::from_value(changed_spread.clone()).map_err(|err| err.to_string())
+    });
+    let res = reload().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body, json!({ "changed": ["exchange_rate.spread"] }));
+}
@@ -64,7 +64,7 @@ async fn prometheus() {
         "ilp_over_http_incoming_token" : "token",
     });
 
-    let node_a: InterledgerNode = serde_json::from_value(json!({
+    let node_a_config = json!({
         "admin_auth_token": "admin",
         "database_url": connection_info_to_string(connection_info1),
         "http_bind_address": format!("127.0.0.1:{}", node_a_http),
@@ -75,8 +75,8 @@ async fn prometheus() {
             "histogram_window": 10000,
             "histogram_granularity": 1000,
         }
-    }))
-    .unwrap();
+    });
+    let node_a: InterledgerNode = serde_json::from_value(node_a_config.clone()).unwrap();
 
     let node_b: InterledgerNode = serde_json::from_value(json!({
         "ilp_address": "example.parent",
@@ -89,7 +89,7 @@ async fn prometheus() {
     }))
     .unwrap();
 
-    node_a.serve(None).await.unwrap();
+    let node_a = node_a.serve(None).await.unwrap();
     node_b.serve(None).await.unwrap();
 
     create_account_on_node(node_b_http, a_on_b, "admin")
@@ -105,9 +105,9 @@ async fn prometheus() {
         .await
         .unwrap();
 
-    let check_metrics = move || {
+    let check_metrics_on = |port: u16| {
         Client::new()
-            .get(&format!("http://127.0.0.1:{}", prometheus_port))
+            .get(&format!("http://127.0.0.1:{}", port))
             .send()
             .map_err(|err| eprintln!("Error getting metrics {:?}", err))
             .and_then(|res| {
@@ -115,6 +115,7 @@ async fn prometheus() {
                     .map_err(|err| eprintln!("Response was not a string: {:?}", err))
             })
     };
+    let check_metrics = move || check_metrics_on(prometheus_port);
 
     send_money_to_username(
         node_a_http,
@@ -157,4 +158,19 @@ async fn prometheus() {
     assert!(ret.contains("requests_outgoing_prepare"));
     assert!(ret.contains("requests_outgoing_reject"));
     assert!(ret.contains("requests_outgoing_duration"));
+
+    // The metrics server moves to the new address when the configuration is reloaded,
+    // and keeps the metrics since the histogram settings did not change
+    let new_prometheus_port = get_open_port(None);
+    let mut reloaded = node_a_config;
+    reloaded["prometheus"]["bind_address"] = json!(format!("127.0.0.1:{}", new_prometheus_port));
+    let changed = node_a
+        .reloader()
+        .reload(serde_json::from_value(reloaded).unwrap())
+        .unwrap();
+    assert_eq!(changed, vec!["prometheus"]);
+    let ret = check_metrics_on(new_prometheus_port).await.unwrap();
+    assert!(ret.contains("requests_incoming_fulfill"));
+    tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+    assert!(check_metrics().await.is_err());
 }
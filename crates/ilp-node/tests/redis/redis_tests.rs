@@ -1,9 +1,11 @@
 #![type_length_limit = "10000000"]
 mod btp;
+mod config_reload;
 mod exchange_rates;
 mod payments_incoming;
 mod three_nodes;
 mod time_based_settlement;
+mod tls;
 
 // Only run prometheus tests if the monitoring feature is turned on
 #[cfg(feature = "monitoring")]
@@ -212,6 +212,171 @@ async fn time_based_settlement() {
     );
 }
 
+#[tokio::test]
+#[cfg(feature = "balance-tracking")]
+async fn settles_on_shutdown() {
+    // Same nodes as in `time_based_settlement`, but node b shuts down before the delay passes
+    let settle_threshold = 500;
+    let settle_to = 0;
+    // long enough for the settlement to only happen because of the shutdown
+    let settle_delay = std::time::Duration::from_secs(3600);
+
+    let context = TestContext::new();
+
+    let mut node_a_connections = context.get_client_connection_info();
+    node_a_connections.db = 1;
+    let mut node_b_connections = context.get_client_connection_info();
+    node_b_connections.db = 2;
+
+    let node_a_http = get_open_port(None);
+    let node_a_settlement = get_open_port(None);
+    let node_b_http = get_open_port(None);
+    let node_b_settlement = get_open_port(None);
+
+    // Launch a in-test settlement engine which will communicate the API back to us through the
+    // channel. Shutdown channel might not be necessary.
+    let (_shutdown, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+    let (node_b_settlement_engine, settlement_engine_server) = warp::serve(settlement_engine(tx))
+        .bind_with_graceful_shutdown(([127, 0, 0, 1], 0), async move {
+            // not sure if this is required
+            let _ = shutdown_rx.await;
+        });
+
+    tokio::task::spawn(settlement_engine_server);
+
+    let asset_code = "XYZ";
+    let asset_scale: usize = 9;
+    let http_secret = "default account holder";
+    let btp_secret = "token";
+
+    // accounts to be created on node a
+    let alice_on_a = json!({
+        "username": "alice_on_a",
+        "asset_code": asset_code,
+        "asset_scale": asset_scale,
+        "ilp_over_http_incoming_token": http_secret,
+    });
+    let b_on_a = json!({
+        "username": "b_on_a",
+        "asset_code": asset_code,
+        "asset_scale": asset_scale,
+        "ilp_over_btp_url": format!("ws://localhost:{}/accounts/{}/ilp/btp", node_b_http, "a_on_b"),
+        "ilp_over_btp_outgoing_token" : btp_secret,
+        "routing_relation": "Parent",
+        "settlement_engine_url": format!("http://{}:{}", node_b_settlement_engine.ip(), node_b_settlement_engine.port()),
+    });
+
+    // accounts to be created on node b
+    let a_on_b = json!({
+        "username": "a_on_b",
+        "asset_code": asset_code,
+        "asset_scale": asset_scale,
+        "ilp_over_btp_incoming_token" : btp_secret,
+        "routing_relation": "Child",
+        "prepaid_balance": "0",
+        "settle_threshold": settle_threshold,
+        "settle_to": settle_to,
+    });
+    let bob_on_b = json!({
+        "username": "bob_on_b",
+        "asset_code": asset_code,
+        "asset_scale": asset_scale,
+        "ilp_over_http_incoming_token" : http_secret,
+        "prepaid_balance": "0",
+        "settle_threshold": settle_threshold,
+        "settle_to": settle_to,
+        "settlement_engine_url": format!("http://{}:{}", node_b_settlement_engine.ip(), node_b_settlement_engine.port()),
+    });
+
+    // node a config
+    let node_a: InterledgerNode = serde_json::from_value(json!({
+        "admin_auth_token": "admin",
+        "database_url": connection_info_to_string(node_a_connections),
+        "http_bind_address": format!("127.0.0.1:{}", node_a_http),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", node_a_settlement),
+        "secret_seed": random_secret(),
+        "route_broadcast_interval": 200,
+        "exchange_rate": {
+            "poll_interval": 60000
+        },
+    }))
+    .expect("Error creating node_a.");
+
+    // node b config
+    let node_b: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.parent",
+        "default_spsp_account": "bob_on_b",
+        "admin_auth_token": "admin",
+        "database_url": connection_info_to_string(node_b_connections),
+        "http_bind_address": format!("127.0.0.1:{}", node_b_http),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", node_b_settlement),
+        "secret_seed": random_secret(),
+        "route_broadcast_interval": 200,
+        "exchange_rate": {
+            "poll_interval": 60000
+        },
+        // different from the other cases
+        "settle_every": settle_delay.as_secs(),
+        "settlement_engine_url": format!("http://{}:{}", node_b_settlement_engine.ip(), node_b_settlement_engine.port()),
+    }))
+    .expect("Error creating node_b.");
+
+    // start node b and open its accounts
+    let node_b = node_b.serve(None).await.unwrap();
+    create_account_on_node(node_b_http, a_on_b, "admin")
+        .await
+        .unwrap();
+    create_account_on_node(node_b_http, bob_on_b, "admin")
+        .await
+        .unwrap();
+
+    let bob_on_b_id = match rx.recv().await.unwrap() {
+        SettlementEngineEvent::AccountCreation(id) => id,
+        x => unreachable!("{:?}", x),
+    };
+
+    // start node a and open its accounts
+    node_a.serve(None).await.unwrap();
+    create_account_on_node(node_a_http, alice_on_a, "admin")
+        .await
+        .unwrap();
+    create_account_on_node(node_a_http, b_on_a, "admin")
+        .await
+        .unwrap();
+
+    let _b_on_a_id = match rx.recv().await.unwrap() {
+        SettlementEngineEvent::AccountCreation(id) => id,
+        x => unreachable!("{:?}", x),
+    };
+
+    crate::test_helpers::send_money_to_username(
+        node_a_http,
+        node_b_http,
+        settle_threshold - 1,
+        "bob_on_b",
+        "alice_on_a",
+        http_secret,
+    )
+    .await
+    .unwrap();
+
+    node_b.shutdown().await;
+
+    match tokio::time::timeout(std::time::Duration::from_millis(100), rx.recv()).await {
+        Ok(Some(SettlementEngineEvent::Settlement {
+            account,
+            amount,
+            scale,
+        })) => {
+            assert_eq!(account, bob_on_b_id);
+            assert_eq!(amount, settle_threshold - 1);
+            assert_eq!(scale, 9);
+        }
+        x => unreachable!("{:?}", x),
+    };
+}
+
 #[derive(Debug)]
 enum SettlementEngineEvent {
     AccountCreation(uuid::Uuid),
@@ -0,0 +1,136 @@
+use crate::redis_helpers::*;
+use crate::test_helpers::*;
+use ilp_node::InterledgerNode;
+use reqwest::{Certificate, Client, Identity};
+use serde_json::{self, json};
+use std::{fs, path::Path};
+
+static CA: &[u8] = include_bytes!("tls/ca.pem");
+static CLIENT_IDENTITY: &[u8] = include_bytes!("tls/client.p12");
+
+fn tls_dir() -> &'static Path {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/redis/tls"))
+}
+
+fn client(identity: bool) -> Client {
+    let mut builder = Client::builder().add_root_certificate(Certificate::from_pem(CA).unwrap());
+    if identity {
+        builder = builder.identity(Identity::from_pkcs12_der(CLIENT_IDENTITY, "password").unwrap());
+    }
+    builder.build().unwrap()
+}
+
+#[tokio::test]
+async fn serves_api_over_tls() {
+    let context = TestContext::new();
+    let http_port = get_open_port(None);
+
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.tls",
+        "admin_auth_token": "admin",
+        "database_url": connection_info_to_string(context.get_client_connection_info()),
+        "http_bind_address": format!("127.0.0.1:{}", http_port),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", get_open_port(None)),
+        "secret_seed": random_secret(),
+        "http_tls": {
+            "cert_path": tls_dir().join("server.pem"),
+            "key_path": tls_dir().join("server.key"),
+        },
+    }))
+    .unwrap();
+    node.serve(None).await.unwrap();
+
+    let res = client(false)
+        .get(&format!("https://localhost:{}/", http_port))
+        .send()
+        .await
+        .unwrap();
+    assert!(res.status().is_success());
+
+    // plaintext connections are not accepted
+    let res = Client::new()
+        .get(&format!("http://localhost:{}/", http_port))
+        .send()
+        .await;
+    assert!(res.is_err());
+}
+
+#[tokio::test]
+async fn requires_client_certificate() {
+    let context = TestContext::new();
+    let http_port = get_open_port(None);
+
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.mtls",
+        "admin_auth_token": "admin",
+        "database_url": connection_info_to_string(context.get_client_connection_info()),
+        "http_bind_address": format!("127.0.0.1:{}", http_port),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", get_open_port(None)),
+        "secret_seed": random_secret(),
+        "http_tls": {
+            "cert_path": tls_dir().join("server.pem"),
+            "key_path": tls_dir().join("server.key"),
+            "client_ca_path": tls_dir().join("ca.pem"),
+        },
+    }))
+    .unwrap();
+    node.serve(None).await.unwrap();
+
+    let res = client(false)
+        .get(&format!("https://localhost:{}/", http_port))
+        .send()
+        .await;
+    assert!(res.is_err());
+
+    let res = client(true)
+        .get(&format!("https://localhost:{}/", http_port))
+        .send()
+        .await
+        .unwrap();
+    assert!(res.status().is_success());
+}
+
+#[tokio::test]
+async fn keeps_certificate_if_reload_fails() {
+    let context = TestContext::new();
+    let http_port = get_open_port(None);
+    let settlement_port = get_open_port(None);
+
+    let dir = tempfile::tempdir().unwrap();
+    let cert_path = dir.path().join("cert.pem");
+    let key_path = dir.path().join("key.pem");
+    fs::copy(tls_dir().join("server.pem"), &cert_path).unwrap();
+    fs::copy(tls_dir().join("server.key"), &key_path).unwrap();
+
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.reload",
+        "admin_auth_token": "admin",
+        "database_url": connection_info_to_string(context.get_client_connection_info()),
+        "http_bind_address": format!("127.0.0.1:{}", http_port),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", settlement_port),
+        "secret_seed": random_secret(),
+        "settlement_api_tls": {
+            "cert_path": cert_path,
+            "key_path": key_path,
+        },
+    }))
+    .unwrap();
+    node.serve(None).await.unwrap();
+
+    // the renewed certificate is broken, so the node keeps the one it has
+    fs::write(&key_path, "not a key").unwrap();
+    unsafe {
+        libc::raise(libc::SIGHUP);
+    }
+    delay(100).await;
+
+    let res = client(false)
+        .get(&format!(
+            "https://localhost:{}/accounts/nobody/settlements",
+            settlement_port
+        ))
+        .send()
+        .await;
+    // any response means that the handshake succeeded with the previous certificate
+    assert!(res.is_ok());
+}
@@ -4,19 +4,27 @@ use bytes::Bytes;
 use interledger_btp::{BtpAccount, BtpOutgoingService};
 use interledger_ccp::CcpRoutingAccount;
 use interledger_errors::NodeStoreError;
-use interledger_http::{HttpAccount, HttpStore};
+use interledger_http::{HttpAccount, HttpClientSettings, HttpStore};
 use interledger_packet::Address;
-use interledger_rates::ExchangeRateStore;
-use interledger_router::RouterStore;
+use interledger_rates::{ExchangeRateHistoryStore, ExchangeRateStore};
+use interledger_router::{Route, RouterStore};
 use interledger_service::{
     Account, AccountStore, AddressStore, IncomingService, OutgoingService, Username,
 };
-use interledger_service_util::BalanceStore;
-use interledger_settlement::core::types::{SettlementAccount, SettlementStore};
+use interledger_service_util::{BalanceStore, FeePolicyStore, TransactionStore};
+use interledger_settlement::core::types::{LeftoversStore, SettlementAccount, SettlementStore};
 use interledger_stream::StreamNotificationsStore;
+use num_bigint::BigUint;
 use secrecy::SecretString;
 use serde::{de, Deserialize, Serialize};
-use std::{boxed::*, collections::HashMap, fmt::Display, net::SocketAddr, str::FromStr};
+use std::{
+    boxed::*,
+    collections::HashMap,
+    fmt::Display,
+    net::SocketAddr,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
 use url::Url;
 use uuid::Uuid;
 use warp::{self, Filter};
@@ -107,12 +115,14 @@ pub trait NodeStore: Clone + Send + Sync + 'static {
     /// Gets all stored accounts
     async fn get_all_accounts(&self) -> Result<Vec<Self::Account>, NodeStoreError>;
 
-    /// Sets the static routes for routing
-    async fn set_static_routes<R>(&self, routes: R) -> Result<(), NodeStoreError>
+    /// Sets the static routes for routing. Each prefix can be routed to a single
+    /// account ID or to a `Route` with several next hops.
+    async fn set_static_routes<R, T>(&self, routes: R) -> Result<(), NodeStoreError>
     where
         // The 'async_trait lifetime is used after recommendation here:
         // https://github.com/dtolnay/async-trait/issues/8#issuecomment-514812245
-        R: IntoIterator<Item = (String, Uuid)> + Send + 'async_trait;
+        R: IntoIterator<Item = (String, T)> + Send + 'async_trait,
+        T: Into<Route> + Send + 'async_trait;
 
     /// Sets a single static route
     async fn set_static_route(
@@ -218,6 +228,11 @@ pub struct AccountDetails {
     pub min_balance: Option<i64>,
     /// The account's ILP over HTTP URL (this is where packets are sent over HTTP from your node)
     pub ilp_over_http_url: Option<String>,
+    /// Settings of the HTTP client used to send packets to the account's ILP over HTTP URL
+    /// (timeout, concurrency, HTTP/2, TLS certificates and extra headers).
+    /// The default settings are used if none are provided
+    #[serde(default)]
+    pub ilp_over_http_client: Option<HttpClientSettings>,
     /// The account's API and incoming ILP over HTTP token.
     /// This must match the ILP over HTTP outgoing token on the peer's node if receiving
     /// packets from that peer
@@ -262,12 +277,31 @@ pub struct AccountDetails {
     pub settlement_engine_url: Option<String>,
 }
 
+/// The account which receives the SPSP payments sent to the root domain.
+/// It is shared by its clones, so that it can be changed while the API is served.
+#[derive(Clone, Default, Debug)]
+pub struct DefaultSpspAccount(Arc<RwLock<Option<Username>>>);
+
+impl DefaultSpspAccount {
+    pub fn new(username: Option<Username>) -> Self {
+        DefaultSpspAccount(Arc::new(RwLock::new(username)))
+    }
+
+    pub fn get(&self) -> Option<Username> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, username: Option<Username>) {
+        *self.0.write().unwrap() = username;
+    }
+}
+
 pub struct NodeApi<S, I, O, B, A: Account> {
     store: S,
     /// The admin's API token, used to make admin-only changes
     // TODO: Make this a SecretString
     admin_api_token: String,
-    default_spsp_account: Option<Username>,
+    default_spsp_account: DefaultSpspAccount,
     incoming_handler: I,
     // The outgoing service is included so that the API can send outgoing
     // requests to specific accounts (namely ILDCP requests)
@@ -287,10 +321,14 @@ where
         + AddressStore
         + HttpStore<Account = A>
         + BalanceStore
+        + TransactionStore
         + SettlementStore<Account = A>
+        + LeftoversStore<AccountId = Uuid, AssetType = BigUint>
         + StreamNotificationsStore<Account = A>
         + RouterStore
-        + ExchangeRateStore,
+        + ExchangeRateStore
+        + ExchangeRateHistoryStore
+        + FeePolicyStore,
     I: IncomingService<A> + Clone + Send + Sync + 'static,
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
     B: OutgoingService<A> + Clone + Send + Sync + 'static,
@@ -315,7 +353,7 @@ where
         NodeApi {
             store,
             admin_api_token,
-            default_spsp_account: None,
+            default_spsp_account: DefaultSpspAccount::default(),
             incoming_handler,
             outgoing_handler,
             btp,
@@ -328,10 +366,16 @@ where
     /// the payment pointer is resolved to <domain>/.well-known/pay. This value determines
     /// which account those payments will be sent to.
     pub fn default_spsp_account(&mut self, username: Username) -> &mut Self {
-        self.default_spsp_account = Some(username);
+        self.default_spsp_account.set(Some(username));
         self
     }
 
+    /// Returns the default SPSP account used by the API, which can be changed
+    /// after the API was turned into a filter
+    pub fn shared_default_spsp_account(&self) -> DefaultSpspAccount {
+        self.default_spsp_account.clone()
+    }
+
     /// Sets the node version
     pub fn node_version(&mut self, version: String) -> &mut Self {
         self.node_version = Some(version);
@@ -350,10 +394,15 @@ where
             self.store.clone(),
         )
         .or(routes::node_settings_api(
-            self.admin_api_token,
+            self.admin_api_token.clone(),
             self.node_version,
-            self.store,
+            self.store.clone(),
+        ))
+        .or(routes::reconciliation_api(
+            self.admin_api_token.clone(),
+            self.store.clone(),
         ))
+        .or(routes::fees_api(self.admin_api_token, self.store))
         .boxed()
     }
 
@@ -1,7 +1,13 @@
-use crate::{number_or_string, AccountDetails, AccountSettings, NodeStore};
+use crate::{
+    number_or_string, optional_number_or_string, AccountDetails, AccountSettings,
+    DefaultSpspAccount, NodeStore,
+};
 use bytes::Bytes;
-use futures::{Future, FutureExt, StreamExt, TryFutureExt};
-use interledger_btp::{connect_to_service_account, BtpAccount, BtpOutgoingService};
+use futures::{Future, FutureExt, SinkExt, StreamExt, TryFutureExt};
+use interledger_btp::{
+    connect_and_supervise, BtpAccount, BtpConnectionInfo, BtpConnectionMonitor, BtpConnectionState,
+    BtpOutgoingService, ReconnectConfig,
+};
 use interledger_ccp::{CcpRoutingAccount, Mode, RouteControlRequest, RoutingRelation};
 use interledger_errors::*;
 use interledger_http::{deserialize_json, HttpAccount, HttpStore};
@@ -13,18 +19,35 @@ use interledger_service::{
     Account, AccountStore, AddressStore, IncomingService, OutgoingRequest, OutgoingService,
     Username,
 };
-use interledger_service_util::BalanceStore;
+use interledger_service_util::{
+    BalanceStore, TransactionQuery, TransactionRecord, TransactionStore,
+};
 use interledger_settlement::core::{types::SettlementAccount, SettlementClient};
-use interledger_spsp::{pay, SpspResponder};
-use interledger_stream::{PaymentNotification, StreamNotificationsStore};
+use interledger_spsp::{
+    pay, pay_with_min_rate, quote, start_pay, start_pay_with_min_rate, Error as SpspError,
+    SpspResponder,
+};
+use interledger_stream::{
+    PaymentHandle, PaymentNotification, PaymentState, PaymentStatus, StreamNotificationsStore,
+    StreamSourceQuote,
+};
 use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::RecvError;
 use tracing::{debug, error, trace};
 use uuid::Uuid;
-use warp::{self, reply::Json, Filter, Rejection};
+use warp::{
+    self,
+    http::{HeaderMap, StatusCode},
+    reply::Json,
+    Filter, Rejection,
+};
 
 pub const BEARER_TOKEN_START: usize = 7;
 
@@ -32,8 +55,38 @@ const fn get_default_max_slippage() -> f64 {
     0.015
 }
 
+const fn get_default_quote_validity() -> u64 {
+    30
+}
+
+/// Longest time, in seconds, that a quote may be valid for
+const MAX_QUOTE_VALIDITY: u64 = 3600;
+
 #[derive(Deserialize, Debug)]
 struct SpspPayRequest {
+    /// Required unless the payment pays a quote
+    #[serde(default)]
+    receiver: Option<String>,
+    /// Required unless the payment pays a quote
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    source_amount: Option<u64>,
+    #[serde(
+        deserialize_with = "number_or_string",
+        default = "get_default_max_slippage"
+    )]
+    slippage: f64,
+    /// Return right away and keep sending the payment in the background,
+    /// as a resource that can be followed, paused, resumed and cancelled
+    #[serde(default)]
+    background: bool,
+    /// Quote whose receiver and source amount are paid, enforcing its minimum exchange rate
+    /// instead of the slippage
+    #[serde(default)]
+    quote_id: Option<Uuid>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SpspQuoteRequest {
     receiver: String,
     #[serde(deserialize_with = "number_or_string")]
     source_amount: u64,
@@ -42,12 +95,325 @@ struct SpspPayRequest {
         default = "get_default_max_slippage"
     )]
     slippage: f64,
+    /// How long, in seconds, the quote can be paid for
+    #[serde(
+        deserialize_with = "number_or_string",
+        default = "get_default_quote_validity"
+    )]
+    valid_for: u64,
+}
+
+/// A quote for a payment, as returned by the API
+#[derive(Serialize, Debug, Clone)]
+struct PaymentQuote {
+    id: Uuid,
+    receiver: String,
+    #[serde(flatten)]
+    quote: StreamSourceQuote,
+    /// Milliseconds since the Unix epoch
+    expires_at: u64,
+}
+
+/// Quotes which can still be paid, by quote id, with the id of the account they were made for
+#[derive(Clone, Default)]
+struct PaymentQuotes(Arc<Mutex<HashMap<Uuid, (Uuid, PaymentQuote)>>>);
+
+impl PaymentQuotes {
+    fn insert(
+        &self,
+        account_id: Uuid,
+        receiver: String,
+        quote: StreamSourceQuote,
+        valid_for: u64,
+    ) -> PaymentQuote {
+        let now = now_millis();
+        let quote = PaymentQuote {
+            id: Uuid::new_v4(),
+            receiver,
+            quote,
+            expires_at: now.saturating_add(valid_for.saturating_mul(1000)),
+        };
+        let mut quotes = self.0.lock().unwrap();
+        quotes.retain(|_, (_, quote)| quote.expires_at > now);
+        quotes.insert(quote.id, (account_id, quote.clone()));
+        quote
+    }
+
+    /// Remove the account's quote so that it is paid only once, if it did not expire yet
+    fn take(&self, account_id: Uuid, id: Uuid) -> Result<PaymentQuote, ApiError> {
+        let mut quotes = self.0.lock().unwrap();
+        match quotes.get(&id) {
+            Some((owner, _)) if *owner == account_id => {}
+            _ => return Err(ApiError::not_found().detail("quote not found")),
+        }
+        let (_, quote) = quotes.remove(&id).unwrap();
+        if quote.expires_at <= now_millis() {
+            return Err(ApiError::bad_request().detail("quote expired"));
+        }
+        Ok(quote)
+    }
+
+    /// Put back a quote whose payment did not send anything, so that it can be paid again
+    fn restore(&self, account_id: Uuid, quote: PaymentQuote) {
+        self.0.lock().unwrap().insert(quote.id, (account_id, quote));
+    }
+}
+
+/// Whether the payment failed before it sent any packet, because the receiver
+/// could not be resolved
+fn failed_before_sending(err: &SpspError) -> bool {
+    matches!(
+        err,
+        SpspError::HttpError(_)
+            | SpspError::InvalidSpspServerResponseError(_)
+            | SpspError::InvalidPaymentPointerError(_)
+    )
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Receiver and source amount of a payment: the ones of its quote, if it pays one,
+/// or the ones it was given. The quote is taken so that it is not paid twice, and
+/// should be [restored](struct.PaymentQuotes.html#method.restore) if the payment
+/// does not start
+fn payment_terms(
+    pay_request: &SpspPayRequest,
+    quotes: &PaymentQuotes,
+    account_id: Uuid,
+) -> Result<(String, u64, Option<PaymentQuote>), ApiError> {
+    let quote_id = match pay_request.quote_id {
+        Some(quote_id) => quote_id,
+        None => {
+            return match (&pay_request.receiver, pay_request.source_amount) {
+                (Some(receiver), Some(source_amount)) => {
+                    Ok((receiver.clone(), source_amount, None))
+                }
+                _ => Err(ApiError::bad_request()
+                    .detail("receiver and source_amount are required without a quote_id")),
+            }
+        }
+    };
+
+    let quote = quotes.take(account_id, quote_id)?;
+    let receiver_differs = pay_request
+        .receiver
+        .as_ref()
+        .map_or(false, |receiver| *receiver != quote.receiver);
+    let amount_differs = pay_request
+        .source_amount
+        .map_or(false, |amount| amount != quote.quote.source_amount);
+    if receiver_differs || amount_differs {
+        quotes.restore(account_id, quote);
+        return Err(ApiError::bad_request()
+            .detail("receiver and source_amount must be the ones of the quote"));
+    }
+    Ok((
+        quote.receiver.clone(),
+        quote.quote.source_amount,
+        Some(quote),
+    ))
+}
+
+/// A payment sent in the background, as returned by the API
+#[derive(Serialize, Debug)]
+struct PaymentResource {
+    id: Uuid,
+    #[serde(flatten)]
+    state: PaymentState,
+}
+
+/// How long finished background payments can still be followed
+const FINISHED_PAYMENT_RETENTION: Duration = Duration::from_secs(3600);
+
+/// Most background payments kept for each account, finished or not
+const MAX_PAYMENTS_PER_ACCOUNT: usize = 100;
+
+/// A payment sent in the background, with the id of the account sending it
+struct BackgroundPayment {
+    account_id: Uuid,
+    handle: Arc<PaymentHandle>,
+    /// When the payment was first seen completed, cancelled or failed
+    finished_at: Option<Instant>,
+}
+
+/// Payments sent in the background, by payment id.
+///
+/// They are only kept in memory, so they are lost when the node restarts and cannot be
+/// resumed: resuming needs the payment's STREAM shared secret, which the node does not
+/// persist, and its receipt as of the last packet, which would have to be written after
+/// each one. A caller who needs the rest paid after a restart can start a new payment
+/// for the amount the receiver did not get.
+#[derive(Clone)]
+struct BackgroundPayments {
+    payments: Arc<Mutex<HashMap<Uuid, BackgroundPayment>>>,
+    retention: Duration,
+    max_per_account: usize,
+}
+
+impl Default for BackgroundPayments {
+    fn default() -> Self {
+        BackgroundPayments::new(FINISHED_PAYMENT_RETENTION, MAX_PAYMENTS_PER_ACCOUNT)
+    }
+}
+
+impl BackgroundPayments {
+    fn new(retention: Duration, max_per_account: usize) -> Self {
+        BackgroundPayments {
+            payments: Arc::new(Mutex::new(HashMap::new())),
+            retention,
+            max_per_account,
+        }
+    }
+
+    /// Forget the payments which finished longer than the retention period ago, and
+    /// fail if the account still has as many payments as it may have
+    async fn make_room(&self, account_id: Uuid) -> Result<(), ApiError> {
+        let unfinished: Vec<_> = self
+            .payments
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, payment)| payment.finished_at.is_none())
+            .map(|(id, payment)| (*id, payment.handle.clone()))
+            .collect();
+        let mut finished = Vec::new();
+        for (id, handle) in unfinished {
+            match handle.state().await.status {
+                PaymentStatus::Sending | PaymentStatus::Paused => {}
+                _ => finished.push(id),
+            }
+        }
+
+        let now = Instant::now();
+        let mut payments = self.payments.lock().unwrap();
+        for id in finished {
+            if let Some(payment) = payments.get_mut(&id) {
+                payment.finished_at.get_or_insert(now);
+            }
+        }
+        let retention = self.retention;
+        payments.retain(|_, payment| {
+            payment.finished_at.map_or(true, |finished_at| {
+                now.duration_since(finished_at) < retention
+            })
+        });
+        self.check_room(&payments, account_id)
+    }
+
+    fn check_room(
+        &self,
+        payments: &HashMap<Uuid, BackgroundPayment>,
+        account_id: Uuid,
+    ) -> Result<(), ApiError> {
+        let count = payments
+            .values()
+            .filter(|payment| payment.account_id == account_id)
+            .count();
+        if count >= self.max_per_account {
+            return Err(ApiError::conflict().detail(format!(
+                "accounts can have at most {} background payments",
+                self.max_per_account
+            )));
+        }
+        Ok(())
+    }
+
+    /// Keep the payment, unless the account got too many payments since
+    /// [`make_room`](#method.make_room), in which case the payment is cancelled
+    fn insert(
+        &self,
+        account_id: Uuid,
+        handle: PaymentHandle,
+    ) -> Result<(Uuid, Arc<PaymentHandle>), ApiError> {
+        let mut payments = self.payments.lock().unwrap();
+        if let Err(err) = self.check_room(&payments, account_id) {
+            handle.cancel();
+            return Err(err);
+        }
+        let id = Uuid::new_v4();
+        let handle = Arc::new(handle);
+        payments.insert(
+            id,
+            BackgroundPayment {
+                account_id,
+                handle: handle.clone(),
+                finished_at: None,
+            },
+        );
+        Ok((id, handle))
+    }
+
+    /// Get the account's payment, without revealing whether other accounts' payments exist
+    fn get(&self, account_id: Uuid, id: Uuid) -> Result<Arc<PaymentHandle>, ApiError> {
+        match self.payments.lock().unwrap().get(&id) {
+            Some(payment) if payment.account_id == account_id => Ok(payment.handle.clone()),
+            _ => Err(ApiError::not_found().detail("payment not found")),
+        }
+    }
+}
+
+/// Query string of the transaction history of an account
+#[derive(Deserialize, Debug)]
+struct TransactionsQuery {
+    /// Milliseconds since the Unix epoch
+    from: Option<u64>,
+    /// Milliseconds since the Unix epoch
+    to: Option<u64>,
+    before: Option<u64>,
+    after: Option<u64>,
+    limit: Option<usize>,
+}
+
+/// Maximum number of transactions returned at once
+const MAX_TRANSACTIONS_LIMIT: usize = 1000;
+
+/// A page of the transaction history of an account, as returned by the API
+#[derive(Serialize, Debug)]
+struct TransactionsPage {
+    transactions: Vec<TransactionRecord>,
+    /// The `before` parameter of the next page, if there may be one
+    next: Option<u64>,
+}
+
+/// Status of the BTP connection with a peer, as returned by the API
+#[derive(Serialize, Debug)]
+struct PeerConnection {
+    account_id: Uuid,
+    username: Username,
+    connected: bool,
+    #[serde(flatten)]
+    connection: Option<BtpConnectionInfo>,
+    /// How reconnecting is going, for the peers we dial
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reconnect: Option<BtpConnectionState>,
+}
+
+impl PeerConnection {
+    fn new<B, A>(account: &A, btp: &BtpOutgoingService<B, A>) -> Self
+    where
+        B: OutgoingService<A> + Clone,
+        A: BtpAccount + Send + Sync + 'static,
+    {
+        let connection = btp.connection_monitor().connection(&account.id());
+        PeerConnection {
+            account_id: account.id(),
+            username: account.username().clone(),
+            connected: connection.is_some(),
+            connection,
+            reconnect: btp.connection_state(&account.id()),
+        }
+    }
 }
 
 pub fn accounts_api<I, O, S, A, B>(
     server_secret: Bytes,
     admin_api_token: String,
-    default_spsp_account: Option<Username>,
+    default_spsp_account: DefaultSpspAccount,
     incoming_handler: I,
     outgoing_handler: O,
     btp: BtpOutgoingService<B, A>,
@@ -62,6 +428,7 @@ where
         + AddressStore
         + HttpStore<Account = A>
         + BalanceStore
+        + TransactionStore
         + StreamNotificationsStore<Account = A>
         + ExchangeRateStore
         + RouterStore,
@@ -78,6 +445,10 @@ where
     // TODO can we make any of the Filters const or put them in once_cell?
     let with_store = warp::any().map(move || store.clone());
     let with_incoming_handler = warp::any().map(move || incoming_handler.clone());
+    let background_payments = BackgroundPayments::default();
+    let with_background_payments = warp::any().map(move || background_payments.clone());
+    let payment_quotes = PaymentQuotes::default();
+    let with_payment_quotes = warp::any().map(move || payment_quotes.clone());
 
     // Helper filters
     let admin_auth_header = format!("Bearer {}", admin_api_token);
@@ -262,6 +633,35 @@ where
             }
         });
 
+    // GET /accounts/:username/transactions
+    let get_account_transactions = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only.clone())
+        .and(warp::path("transactions"))
+        .and(warp::path::end())
+        .and(warp::query::<TransactionsQuery>())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, query: TransactionsQuery, store: S| async move {
+            let query = TransactionQuery {
+                from: query.from,
+                to: query.to,
+                before: query.before,
+                after: query.after,
+                limit: query
+                    .limit
+                    .unwrap_or(TransactionQuery::default().limit)
+                    .min(MAX_TRANSACTIONS_LIMIT),
+            };
+            let limit = query.limit;
+            let transactions = store.get_transactions(id, query).await?;
+            let next = if transactions.len() == limit {
+                transactions.last().map(|record| record.id)
+            } else {
+                None
+            };
+            Ok::<Json, Rejection>(warp::reply::json(&TransactionsPage { transactions, next }))
+        });
+
     // DELETE /accounts/:username
     let btp_clone = btp.clone();
     let delete_account = warp::delete()
@@ -282,6 +682,7 @@ where
 
     // PUT /accounts/:username/settings
     let outgoing_handler_clone = outgoing_handler;
+    let btp_clone = btp.clone();
     let put_account_settings = warp::put()
         .and(warp::path("accounts"))
         .and(admin_or_authorized_user_only.clone())
@@ -290,7 +691,7 @@ where
         .and(deserialize_json())
         .and(with_store.clone())
         .and_then(move |id: Uuid, settings: AccountSettings, store: S| {
-            let btp = btp.clone();
+            let btp = btp_clone.clone();
             let outgoing_handler = outgoing_handler_clone.clone();
             async move {
                 if settings.ilp_over_btp_incoming_token.is_some() {
@@ -330,6 +731,64 @@ where
             })
         });
 
+    // GET /accounts/:username/connection
+    let btp_clone = btp.clone();
+    let get_account_connection = warp::get()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("connection"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(move |id: Uuid, store: S| {
+            let btp = btp_clone.clone();
+            async move {
+                let accounts = store.get_accounts(vec![id]).await?;
+                let connection = PeerConnection::new(&accounts[0], &btp);
+                Ok::<Json, Rejection>(warp::reply::json(&connection))
+            }
+        });
+
+    // GET /connections
+    let btp_clone = btp.clone();
+    let get_connections = warp::get()
+        .and(warp::path("connections"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(move |store: S| {
+            let btp = btp_clone.clone();
+            async move {
+                let accounts = store.get_all_accounts().await?;
+                let connections: Vec<PeerConnection> = accounts
+                    .iter()
+                    // Only list the BTP peers
+                    .filter(|account| {
+                        account.get_ilp_over_btp_url().is_some()
+                            || btp.connection_monitor().connection(&account.id()).is_some()
+                    })
+                    .map(|account| PeerConnection::new(account, &btp))
+                    .collect();
+                Ok::<Json, Rejection>(warp::reply::json(&connections))
+            }
+        });
+
+    // (Websocket) /connections/events
+    let monitor = btp.connection_monitor().clone();
+    let connection_events = warp::path("connections")
+        .and(admin_only.clone())
+        .and(warp::path("events"))
+        .and(warp::path::end())
+        .and(warp::ws())
+        .map(move |ws: warp::ws::Ws| {
+            let monitor = monitor.clone();
+            ws.on_upgrade(move |ws: warp::ws::WebSocket| {
+                let (ws_tx, ws_rx) = ws.split();
+                tokio::task::spawn(notify_peer_events(ws_tx, monitor));
+                consume_msg_drain(ws_rx)
+            })
+        });
+
     // (Websocket) /payments/incoming
     let all_payment_notifications = warp::path("payments")
         .and(admin_only)
@@ -345,36 +804,233 @@ where
             })
         });
 
-    // POST /accounts/:username/payments
-    let post_payments = warp::post()
+    // POST /accounts/:username/quotes
+    let post_quotes = warp::post()
         .and(warp::path("accounts"))
-        .and(authorized_user_only)
-        .and(warp::path("payments"))
+        .and(authorized_user_only.clone())
+        .and(warp::path("quotes"))
         .and(warp::path::end())
         .and(deserialize_json())
-        .and(with_incoming_handler)
+        .and(with_incoming_handler.clone())
         .and(with_store.clone())
+        .and(with_payment_quotes.clone())
         .and_then(
-            move |account: A, pay_request: SpspPayRequest, incoming_handler: I, store: S| {
+            move |account: A,
+                  quote_request: SpspQuoteRequest,
+                  incoming_handler: I,
+                  store: S,
+                  payment_quotes: PaymentQuotes| {
                 async move {
-                    let receipt = pay(
+                    if quote_request.valid_for > MAX_QUOTE_VALIDITY {
+                        return Err(Rejection::from(ApiError::bad_request().detail(format!(
+                            "quotes are valid for at most {} seconds",
+                            MAX_QUOTE_VALIDITY
+                        ))));
+                    }
+
+                    let quote = quote(
                         incoming_handler,
                         account.clone(),
                         store,
-                        &pay_request.receiver,
-                        pay_request.source_amount,
-                        pay_request.slippage,
+                        &quote_request.receiver,
+                        quote_request.source_amount,
+                        quote_request.slippage,
                     )
                     .map_err(|err| {
-                        let msg = format!("Error sending SPSP payment: {}", err);
+                        let msg = format!("Error quoting SPSP payment: {}", err);
                         error!("{}", msg);
-                        // TODO give a different error message depending on what type of error it is
                         Rejection::from(ApiError::internal_server_error().detail(msg))
                     })
                     .await?;
 
+                    let quote = payment_quotes.insert(
+                        account.id(),
+                        quote_request.receiver,
+                        quote,
+                        quote_request.valid_for,
+                    );
+                    debug!("Quoted SPSP payment {}: {:?}", quote.id, quote);
+                    Ok::<_, Rejection>(warp::reply::with_status(
+                        warp::reply::json(&quote),
+                        StatusCode::CREATED,
+                    ))
+                }
+            },
+        );
+
+    // POST /accounts/:username/payments
+    let post_payments = warp::post()
+        .and(warp::path("accounts"))
+        .and(authorized_user_only.clone())
+        .and(warp::path("payments"))
+        .and(warp::path::end())
+        .and(deserialize_json())
+        .and(with_incoming_handler)
+        .and(with_store.clone())
+        .and(with_background_payments.clone())
+        .and(with_payment_quotes)
+        .and_then(
+            move |account: A,
+                  pay_request: SpspPayRequest,
+                  incoming_handler: I,
+                  store: S,
+                  background_payments: BackgroundPayments,
+                  payment_quotes: PaymentQuotes| {
+                async move {
+                    let (receiver, source_amount, payment_quote) =
+                        payment_terms(&pay_request, &payment_quotes, account.id())?;
+                    let min_exchange_rate = payment_quote
+                        .as_ref()
+                        .map(|payment_quote| payment_quote.quote.min_exchange_rate);
+
+                    if pay_request.background {
+                        if let Err(err) = background_payments.make_room(account.id()).await {
+                            if let Some(payment_quote) = payment_quote {
+                                payment_quotes.restore(account.id(), payment_quote);
+                            }
+                            return Err(Rejection::from(err));
+                        }
+                        let handle = match min_exchange_rate {
+                            Some(min_exchange_rate) => {
+                                start_pay_with_min_rate(
+                                    incoming_handler,
+                                    account.clone(),
+                                    store,
+                                    &receiver,
+                                    source_amount,
+                                    min_exchange_rate,
+                                )
+                                .await
+                            }
+                            None => {
+                                start_pay(
+                                    incoming_handler,
+                                    account.clone(),
+                                    store,
+                                    &receiver,
+                                    source_amount,
+                                    pay_request.slippage,
+                                )
+                                .await
+                            }
+                        }
+                        .map_err(|err| {
+                            // The payment was not started, so it sent nothing
+                            if let Some(payment_quote) = payment_quote {
+                                payment_quotes.restore(account.id(), payment_quote);
+                            }
+                            let msg = format!("Error starting SPSP payment: {}", err);
+                            error!("{}", msg);
+                            Rejection::from(ApiError::internal_server_error().detail(msg))
+                        })?;
+
+                        let (id, handle) = background_payments.insert(account.id(), handle)?;
+                        debug!("Started SPSP payment {} in the background", id);
+                        let payment = PaymentResource {
+                            id,
+                            state: handle.state().await,
+                        };
+                        return Ok::<_, Rejection>(warp::reply::with_status(
+                            warp::reply::json(&payment),
+                            StatusCode::CREATED,
+                        ));
+                    }
+
+                    let receipt = match min_exchange_rate {
+                        Some(min_exchange_rate) => {
+                            pay_with_min_rate(
+                                incoming_handler,
+                                account.clone(),
+                                store,
+                                &receiver,
+                                source_amount,
+                                min_exchange_rate,
+                            )
+                            .await
+                        }
+                        None => {
+                            pay(
+                                incoming_handler,
+                                account.clone(),
+                                store,
+                                &receiver,
+                                source_amount,
+                                pay_request.slippage,
+                            )
+                            .await
+                        }
+                    }
+                    .map_err(|err| {
+                        if let Some(payment_quote) = payment_quote {
+                            if failed_before_sending(&err) {
+                                payment_quotes.restore(account.id(), payment_quote);
+                            }
+                        }
+                        let msg = format!("Error sending SPSP payment: {}", err);
+                        error!("{}", msg);
+                        // TODO give a different error message depending on what type of error it is
+                        Rejection::from(ApiError::internal_server_error().detail(msg))
+                    })?;
+
                     debug!("Sent SPSP payment, receipt: {:?}", receipt);
-                    Ok::<Json, Rejection>(warp::reply::json(&json!(receipt)))
+                    Ok::<_, Rejection>(warp::reply::with_status(
+                        warp::reply::json(&json!(receipt)),
+                        StatusCode::OK,
+                    ))
+                }
+            },
+        );
+
+    // GET /accounts/:username/payments/:id
+    let get_payment = warp::get()
+        .and(warp::path("accounts"))
+        .and(authorized_user_only.clone())
+        .and(warp::path("payments"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(with_background_payments.clone())
+        .and_then(
+            |account: A, id: Uuid, background_payments: BackgroundPayments| async move {
+                let handle = background_payments.get(account.id(), id)?;
+                let payment = PaymentResource {
+                    id,
+                    state: handle.state().await,
+                };
+                Ok::<Json, Rejection>(warp::reply::json(&payment))
+            },
+        );
+
+    // POST /accounts/:username/payments/:id/(pause|resume|cancel)
+    let post_payment_action = warp::post()
+        .and(warp::path("accounts"))
+        .and(authorized_user_only)
+        .and(warp::path("payments"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(with_background_payments)
+        .and_then(
+            |account: A, id: Uuid, action: String, background_payments: BackgroundPayments| {
+                async move {
+                    let handle = background_payments.get(account.id(), id)?;
+                    match action.as_str() {
+                        "pause" => handle.pause(),
+                        "resume" => handle.resume(),
+                        "cancel" => handle.cancel(),
+                        _ => {
+                            return Err(Rejection::from(
+                                ApiError::not_found()
+                                    .detail(format!("unknown payment action: {}", action)),
+                            ))
+                        }
+                    }
+                    debug!("Requested payment {} to {}", id, action);
+
+                    let payment = PaymentResource {
+                        id,
+                        state: handle.state().await,
+                    };
+                    Ok::<Json, Rejection>(warp::reply::json(&payment))
                 }
             },
         );
@@ -386,8 +1042,9 @@ where
         .and(account_username_to_id)
         .and(warp::path("spsp"))
         .and(warp::path::end())
+        .and(warp::header::headers_cloned())
         .and(with_store.clone())
-        .and_then(move |id: Uuid, store: S| {
+        .and_then(move |id: Uuid, headers: HeaderMap, store: S| {
             let server_secret_clone = server_secret_clone.clone();
             async move {
                 let accounts = store.get_accounts(vec![id]).await?;
@@ -397,7 +1054,7 @@ where
                         accounts[0].ilp_address().clone(),
                         server_secret_clone.clone(),
                     )
-                    .generate_http_response(),
+                    .generate_http_response_for_query(&headers),
                 )
             }
         });
@@ -409,9 +1066,10 @@ where
         .and(warp::path(".well-known"))
         .and(warp::path("pay"))
         .and(warp::path::end())
+        .and(warp::header::headers_cloned())
         .and(with_store)
-        .and_then(move |store: S| {
-            let default_spsp_account = default_spsp_account.clone();
+        .and_then(move |headers: HeaderMap, store: S| {
+            let default_spsp_account = default_spsp_account.get();
             let server_secret_clone = server_secret.clone();
             async move {
                 if let Some(ref username) = default_spsp_account {
@@ -427,7 +1085,7 @@ where
                             account.ilp_address().clone(),
                             server_secret_clone.clone(),
                         )
-                        .generate_http_response(),
+                        .generate_http_response_for_query(&headers),
                     )
                 } else {
                     Err(Rejection::from(
@@ -445,10 +1103,17 @@ where
         .or(delete_account)
         .or(get_account)
         .or(get_account_balance)
+        .or(get_account_transactions)
         .or(put_account_settings)
+        .or(get_account_connection)
+        .or(get_connections)
+        .or(connection_events)
         .or(incoming_payment_notifications)
         .or(all_payment_notifications)
+        .or(post_quotes)
         .or(post_payments)
+        .or(get_payment)
+        .or(post_payment_action)
 }
 
 async fn consume_msg_drain(mut ws_rx: futures::stream::SplitStream<warp::ws::WebSocket>) {
@@ -509,6 +1174,29 @@ fn notify_all_payments(
         .then(futures::future::ok)
 }
 
+/// Forwards the connections and disconnections of the BTP peers to the WebSocket
+async fn notify_peer_events(
+    mut ws_tx: futures::stream::SplitSink<warp::ws::WebSocket, warp::ws::Message>,
+    monitor: BtpConnectionMonitor,
+) {
+    let mut events = monitor.subscribe();
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(skipped)) => {
+                debug!("Peer events websocket skipped {} events", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let msg = warp::ws::Message::text(serde_json::to_string(&event).unwrap());
+        if let Err(e) = ws_tx.send(msg).await {
+            debug!("websocket send error: {}", e);
+            return;
+        }
+    }
+}
+
 async fn get_address_from_parent_and_update_routes<O, A, S>(
     mut service: O,
     parent: A,
@@ -605,13 +1293,13 @@ where
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
     A: CcpRoutingAccount + BtpAccount + SettlementAccount + Clone + Send + Sync + 'static,
     S: NodeStore<Account = A> + AddressStore + BalanceStore + Clone + Send + Sync + 'static,
-    B: OutgoingService<A> + Clone + 'static,
+    B: OutgoingService<A> + Clone + Send + Sync + 'static,
 {
     // Try to connect to the account's BTP socket if they have
     // one configured
     if account.get_ilp_over_btp_url().is_some() {
         trace!("Newly inserted account has a BTP URL configured, will try to connect");
-        connect_to_service_account(account.clone(), true, btp).await?
+        connect_and_supervise(account.clone(), true, btp, ReconnectConfig::default()).await?
     }
 
     // If we added a parent, get the address assigned to us by
@@ -677,7 +1365,16 @@ where
 
 #[cfg(test)]
 mod tests {
+    use super::{
+        payment_terms, BackgroundPayments, PaymentQuotes, PaymentStatus, SpspPayRequest,
+        StreamSourceQuote,
+    };
     use crate::routes::test_helpers::*;
+    use interledger_packet::Address;
+    use serde_json::{json, Value};
+    use std::str::FromStr;
+    use std::time::Duration;
+    use uuid::Uuid;
     // TODO: Add test for GET /accounts/:username/spsp and /.well_known
 
     #[tokio::test]
@@ -710,6 +1407,42 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn pages_through_account_transactions() {
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/transactions?limit=2",
+            "password",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let page: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(page["transactions"].as_array().unwrap().len(), 2);
+        assert_eq!(page["transactions"][0]["id"], 3);
+        assert_eq!(page["transactions"][0]["kind"], "incoming_settlement");
+        assert_eq!(page["transactions"][0]["amount"], 100);
+        assert_eq!(page["next"], 2);
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/transactions?limit=2&before=2",
+            "admin",
+            None,
+        )
+        .await;
+        let page: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(page["transactions"].as_array().unwrap().len(), 1);
+        assert_eq!(page["transactions"][0]["id"], 1);
+        assert_eq!(page["next"], Value::Null);
+
+        let resp = api_call(&api, "GET", "/accounts/alice/transactions", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_can_get_all_accounts() {
         let api = test_accounts_api();
@@ -734,6 +1467,26 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn only_admin_can_get_connections() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "GET", "/accounts/alice/connection", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let connection: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(connection["connected"], false);
+        assert!(connection.get("bytes_sent").is_none());
+
+        let resp = api_call(&api, "GET", "/accounts/alice/connection", "password", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "GET", "/connections", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.body().as_ref(), b"[]");
+
+        let resp = api_call(&api, "GET", "/connections", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_or_user_can_get_accounts_balance() {
         let api = test_accounts_api();
@@ -824,4 +1577,159 @@ mod tests {
         .await;
         assert_eq!(resp.status().as_u16(), 401);
     }
+
+    #[tokio::test]
+    async fn only_user_can_quote_payment() {
+        let quote: Option<serde_json::Value> = Some(serde_json::json!({
+            "receiver": "some_receiver",
+            "source_amount" : 10,
+        }));
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/quotes",
+            "password",
+            quote.clone(),
+        )
+        .await;
+        // The receiver cannot be queried, as for the payments
+        assert_eq!(resp.status().as_u16(), 500);
+
+        let resp = api_call(&api, "POST", "/accounts/alice/quotes", "admin", quote).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let quote: Option<serde_json::Value> = Some(serde_json::json!({
+            "receiver": "some_receiver",
+            "source_amount" : 10,
+            "valid_for": 86400,
+        }));
+        let resp = api_call(&api, "POST", "/accounts/alice/quotes", "password", quote).await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
+    #[tokio::test]
+    async fn pays_only_known_quotes() {
+        let api = test_accounts_api();
+        let payment = Some(serde_json::json!({ "quote_id": Uuid::new_v4() }));
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments",
+            "password",
+            payment,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 404);
+
+        // Without a quote, the receiver and source amount are required
+        let payment = Some(serde_json::json!({ "source_amount": 10 }));
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments",
+            "password",
+            payment,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
+    fn test_stream_quote() -> StreamSourceQuote {
+        StreamSourceQuote {
+            to: Address::from_str("example.receiver").unwrap(),
+            source_amount: 10,
+            estimated_destination_amount: 20,
+            min_destination_amount: 19,
+            min_exchange_rate: 1.9,
+            probe_source_amount: 1000,
+            probe_destination_amount: 2000,
+            destination_asset_scale: Some(9),
+            destination_asset_code: Some("ABC".to_string()),
+        }
+    }
+
+    #[test]
+    fn takes_quotes_once() {
+        let quotes = PaymentQuotes::default();
+        let account_id = Uuid::new_v4();
+        let stream_quote = test_stream_quote();
+        let quote = quotes.insert(
+            account_id,
+            "$receiver".to_string(),
+            stream_quote.clone(),
+            30,
+        );
+
+        // Other accounts cannot pay it
+        assert!(quotes.take(Uuid::new_v4(), quote.id).is_err());
+        assert_eq!(
+            quotes.take(account_id, quote.id).unwrap().receiver,
+            "$receiver"
+        );
+        assert!(quotes.take(account_id, quote.id).is_err());
+
+        let quote = quotes.insert(account_id, "$receiver".to_string(), stream_quote, 0);
+        assert!(quotes.take(account_id, quote.id).is_err());
+    }
+
+    #[test]
+    fn keeps_quotes_of_payments_which_did_not_start() {
+        let quotes = PaymentQuotes::default();
+        let account_id = Uuid::new_v4();
+        let quote = quotes.insert(account_id, "$receiver".to_string(), test_stream_quote(), 30);
+
+        // Paying another receiver does not use the quote up
+        let request: SpspPayRequest =
+            serde_json::from_value(json!({ "quote_id": quote.id, "receiver": "$other" })).unwrap();
+        assert!(payment_terms(&request, &quotes, account_id).is_err());
+
+        let request: SpspPayRequest =
+            serde_json::from_value(json!({ "quote_id": quote.id })).unwrap();
+        let (receiver, source_amount, taken) =
+            payment_terms(&request, &quotes, account_id).unwrap();
+        assert_eq!((receiver.as_str(), source_amount), ("$receiver", 10));
+        assert!(payment_terms(&request, &quotes, account_id).is_err());
+
+        // The receiver could not be resolved, so the quote can be paid again
+        quotes.restore(account_id, taken.unwrap());
+        assert!(payment_terms(&request, &quotes, account_id).is_ok());
+    }
+
+    #[tokio::test]
+    async fn limits_and_forgets_background_payments() {
+        let account_id = Uuid::new_v4();
+        let payments = BackgroundPayments::new(Duration::from_secs(3600), 1);
+        let (id, _) = payments.insert(account_id, test_payment_handle()).unwrap();
+        assert!(payments.insert(account_id, test_payment_handle()).is_err());
+        // Finished payments are kept until the retention period is over
+        assert!(payments.make_room(account_id).await.is_err());
+        assert!(payments.get(account_id, id).is_ok());
+        assert!(payments.make_room(Uuid::new_v4()).await.is_ok());
+
+        let payments = BackgroundPayments::new(Duration::from_secs(0), 1);
+        let (id, handle) = payments.insert(account_id, test_payment_handle()).unwrap();
+        while handle.state().await.status == PaymentStatus::Sending {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        payments.make_room(account_id).await.unwrap();
+        assert!(payments.get(account_id, id).is_err());
+    }
+
+    #[tokio::test]
+    async fn only_user_can_follow_background_payment() {
+        let api = test_accounts_api();
+        let path = format!("/accounts/alice/payments/{}", Uuid::new_v4());
+
+        // No payment was started in the background
+        let resp = api_call(&api, "GET", &path, "password", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+        let resp = api_call(&api, "POST", &format!("{}/pause", path), "password", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+
+        let resp = api_call(&api, "GET", &path, "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "POST", &format!("{}/cancel", path), "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
 }
@@ -0,0 +1,99 @@
+use interledger_errors::*;
+use interledger_http::deserialize_json;
+use interledger_service_util::{FeePolicy, FeePolicyStore};
+use secrecy::{ExposeSecret, SecretString};
+use warp::{self, reply::Json, Filter, Rejection};
+
+pub fn fees_api<S>(
+    admin_api_token: String,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    S: FeePolicyStore + Send + Sync + 'static,
+{
+    // Helper filters
+    let admin_auth_header = format!("Bearer {}", admin_api_token);
+    let admin_only = warp::header::<SecretString>("authorization")
+        .and_then(move |authorization: SecretString| {
+            let admin_auth_header = admin_auth_header.clone();
+            async move {
+                if authorization.expose_secret() == &admin_auth_header {
+                    Ok::<(), Rejection>(())
+                } else {
+                    Err(Rejection::from(
+                        ApiError::unauthorized().detail("invalid admin auth token provided"),
+                    ))
+                }
+            }
+        })
+        .untuple_one()
+        .boxed();
+    let with_store = warp::any().map(move || store.clone()).boxed();
+
+    // GET /fees
+    let get_fees = warp::get()
+        .and(warp::path("fees"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .map(|store: S| warp::reply::json(&*store.get_fee_policy()));
+
+    // PUT /fees
+    let put_fees = warp::put()
+        .and(warp::path("fees"))
+        .and(warp::path::end())
+        .and(admin_only)
+        .and(deserialize_json())
+        .and(with_store)
+        .and_then(|policy: FeePolicy, store: S| async move {
+            policy
+                .validate()
+                .map_err(|err| Rejection::from(ApiError::bad_request().detail(err)))?;
+            let reply = warp::reply::json(&policy);
+            store.set_fee_policy(policy);
+            Ok::<Json, Rejection>(reply)
+        });
+
+    get_fees.or(put_fees)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::test_helpers::*;
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn only_admin_can_set_fees() {
+        let api = test_fees_api();
+        let policy = json!({
+            "pairs": [{ "from": "ABC", "to": "XYZ", "spread": 0.01 }],
+            "accounts": { "alice": { "fixed_fee": 10 } },
+            "routing_relations": { "Peer": { "proportional_fee": 0.001 } },
+        });
+        let resp = api_call(&api, "PUT", "/fees", "admin", Some(policy.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let saved: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(saved["accounts"]["alice"]["fixed_fee"], 10);
+        assert_eq!(
+            saved["routing_relations"]["Peer"]["proportional_fee"],
+            0.001
+        );
+        assert_eq!(saved["rounding"], "up");
+
+        let resp = api_call(&api, "PUT", "/fees", "wrong", Some(policy)).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "GET", "/fees", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "GET", "/fees", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_fees() {
+        let api = test_fees_api();
+        let policy = json!({ "accounts": { "alice": { "proportional_fee": 2.0 } } });
+        let resp = api_call(&api, "PUT", "/fees", "admin", Some(policy)).await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+}
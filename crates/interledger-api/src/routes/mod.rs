@@ -1,8 +1,12 @@
 mod accounts;
+mod fees;
 mod node_settings;
+mod reconciliation;
 
 pub use accounts::accounts_api;
+pub use fees::fees_api;
 pub use node_settings::node_settings_api;
+pub use reconciliation::reconciliation_api;
 
 #[cfg(test)]
 pub mod test_helpers;
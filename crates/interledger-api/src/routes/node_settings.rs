@@ -4,12 +4,13 @@ use futures::TryFutureExt;
 use interledger_errors::*;
 use interledger_http::{deserialize_json, HttpAccount};
 use interledger_packet::Address;
-use interledger_rates::ExchangeRateStore;
-use interledger_router::RouterStore;
+use interledger_rates::{changed_rates, ExchangeRateHistoryStore, ExchangeRateStore, RateSnapshot};
+use interledger_router::{NextHop, Route, RouterStore};
 use interledger_service::{Account, AccountStore, AddressStore, Username};
+use interledger_service_util::now_millis;
 use interledger_settlement::core::{types::SettlementAccount, SettlementClient};
 use secrecy::{ExposeSecret, SecretString};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     str::{self, FromStr},
@@ -28,6 +29,92 @@ struct StatusResponse {
     version: Option<String>,
 }
 
+/// A route in the API, which is either the username of a single account
+/// or a list of next hops (see `Route` for how they are used)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum RouteConfig {
+    Username(String),
+    NextHops(Vec<NextHopConfig>),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum NextHopConfig {
+    Username(String),
+    Weighted {
+        username: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        weight: Option<u32>,
+    },
+}
+
+impl RouteConfig {
+    fn next_hops(&self) -> Vec<(&str, Option<u32>)> {
+        match self {
+            RouteConfig::Username(username) => vec![(username.as_str(), None)],
+            RouteConfig::NextHops(next_hops) => next_hops
+                .iter()
+                .map(|next_hop| match next_hop {
+                    NextHopConfig::Username(username) => (username.as_str(), None),
+                    NextHopConfig::Weighted { username, weight } => (username.as_str(), *weight),
+                })
+                .collect(),
+        }
+    }
+
+    fn from_route(route: &Route, usernames: &HashMap<Uuid, String>) -> Self {
+        let username = |account_id: Uuid| {
+            usernames
+                .get(&account_id)
+                .cloned()
+                .unwrap_or_else(|| account_id.to_string())
+        };
+        match route.next_hops() {
+            [NextHop {
+                account_id,
+                weight: None,
+            }] => RouteConfig::Username(username(*account_id)),
+            next_hops => RouteConfig::NextHops(
+                next_hops
+                    .iter()
+                    .map(|next_hop| match next_hop.weight {
+                        None => NextHopConfig::Username(username(next_hop.account_id)),
+                        Some(weight) => NextHopConfig::Weighted {
+                            username: username(next_hop.account_id),
+                            weight: Some(weight),
+                        },
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Query string of the history of an asset's exchange rate
+#[derive(Deserialize, Debug)]
+struct RateHistoryQuery {
+    /// Required, but optional here so that a request without it gets a helpful error
+    asset: Option<String>,
+    /// Milliseconds since the Unix epoch, defaults to the oldest rate
+    from: Option<u64>,
+    /// Milliseconds since the Unix epoch, defaults to now
+    to: Option<u64>,
+    limit: Option<usize>,
+}
+
+/// Number of rates returned at once, unless the query asks for fewer
+const MAX_RATE_HISTORY_LIMIT: usize = 1000;
+
+/// A page of the history of an asset's exchange rate, oldest first, as returned by the API
+#[derive(Serialize, Debug)]
+struct RateHistoryPage {
+    asset_code: String,
+    rates: Vec<RateSnapshot>,
+    /// The `from` parameter of the next page, if there may be one
+    next: Option<u64>,
+}
+
 pub fn node_settings_api<S, A>(
     admin_api_token: String,
     node_version: Option<String>,
@@ -38,6 +125,7 @@ where
         + AccountStore<Account = A>
         + AddressStore
         + ExchangeRateStore
+        + ExchangeRateHistoryStore
         + RouterStore,
     A: Account + HttpAccount + Send + Sync + SettlementAccount + Serialize + 'static,
 {
@@ -81,6 +169,12 @@ where
         .and(deserialize_json())
         .and(with_store.clone())
         .and_then(|rates: ExchangeRates, store: S| async move {
+            // Recorded first, so that the rates are never used without being in the
+            // history, as done by the exchange rate fetcher
+            let current = store.get_all_exchange_rates()?;
+            store
+                .record_exchange_rates(now_millis(), changed_rates(&current, &rates.0))
+                .await?;
             store.set_exchange_rates(rates.0.clone())?;
             Ok::<_, Rejection>(warp::reply::json(&rates))
         });
@@ -95,8 +189,45 @@ where
             Ok::<_, Rejection>(warp::reply::json(&rates))
         });
 
+    // GET /rates/history
+    let get_rate_history = warp::get()
+        .and(warp::path("rates"))
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and(warp::query::<RateHistoryQuery>())
+        .and(with_store.clone())
+        .and_then(|query: RateHistoryQuery, store: S| async move {
+            let asset_code = query.asset.ok_or_else(|| {
+                Rejection::from(
+                    ApiError::bad_request().detail("the asset to get the rates of is missing"),
+                )
+            })?;
+            let limit = query
+                .limit
+                .unwrap_or(MAX_RATE_HISTORY_LIMIT)
+                .min(MAX_RATE_HISTORY_LIMIT);
+            let rates = store
+                .get_exchange_rate_history(
+                    &asset_code,
+                    query.from.unwrap_or(0),
+                    query.to.unwrap_or_else(|| now_millis() + 1),
+                    limit,
+                )
+                .await?;
+            let next = if rates.len() == limit {
+                rates.last().map(|snapshot| snapshot.timestamp + 1)
+            } else {
+                None
+            };
+            Ok::<_, Rejection>(warp::reply::json(&RateHistoryPage {
+                asset_code,
+                rates,
+                next,
+            }))
+        });
+
     // GET /routes
-    // Response: Map of ILP Address prefix -> Username
+    // Response: Map of ILP Address prefix -> Username (or list of next hops)
     let get_routes = warp::get()
         .and(warp::path("routes"))
         .and(warp::path::end())
@@ -106,13 +237,26 @@ where
                 // Convert the account IDs listed in the routing table
                 // to the usernames for the API response
                 let routes = store.routing_table().clone();
-                let accounts = store
-                    .get_accounts(routes.values().cloned().collect())
-                    .await?;
-                let routes: HashMap<String, String> = routes
+                let mut account_ids: Vec<Uuid> = routes
+                    .values()
+                    .flat_map(|route| route.account_ids())
+                    .collect();
+                account_ids.sort();
+                account_ids.dedup();
+                let usernames: HashMap<Uuid, String> = store
+                    .get_accounts(account_ids)
+                    .await?
+                    .into_iter()
+                    .map(|account| (account.id(), account.username().to_string()))
+                    .collect();
+                let routes: HashMap<String, RouteConfig> = routes
                     .iter()
-                    .map(|(prefix, _)| prefix.to_string())
-                    .zip(accounts.into_iter().map(|a| a.username().to_string()))
+                    .map(|(prefix, route)| {
+                        (
+                            prefix.to_string(),
+                            RouteConfig::from_route(route, &usernames),
+                        )
+                    })
                     .collect();
 
                 Ok::<Json, Rejection>(warp::reply::json(&routes))
@@ -120,7 +264,7 @@ where
         });
 
     // PUT /routes/static
-    // Body: Map of ILP Address prefix -> Username
+    // Body: Map of ILP Address prefix -> Username (or list of next hops)
     let put_static_routes = warp::put()
         .and(warp::path("routes"))
         .and(warp::path("static"))
@@ -128,27 +272,28 @@ where
         .and(admin_only.clone())
         .and(deserialize_json())
         .and(with_store.clone())
-        .and_then(move |routes: HashMap<String, String>, store: S| {
+        .and_then(move |routes: HashMap<String, RouteConfig>, store: S| {
             async move {
                 // Convert the usernames to account IDs to set the routes in the store
-                let mut usernames: Vec<Username> = Vec::new();
-                for username in routes.values() {
-                    let user = match Username::from_str(&username) {
-                        Ok(u) => u,
-                        Err(_) => return Err(Rejection::from(ApiError::bad_request())),
-                    };
-                    usernames.push(user);
-                }
-
-                let mut account_ids: Vec<Uuid> = Vec::new();
-                for username in usernames {
-                    account_ids.push(store.get_account_id_from_username(&username).await?);
+                let mut static_routes = Vec::with_capacity(routes.len());
+                for (prefix, route) in routes.iter() {
+                    let mut next_hops = Vec::new();
+                    for (username, weight) in route.next_hops() {
+                        let username = Username::from_str(username)
+                            .map_err(|_| Rejection::from(ApiError::bad_request()))?;
+                        let account_id = store.get_account_id_from_username(&username).await?;
+                        next_hops.push(NextHop { account_id, weight });
+                    }
+                    if next_hops.is_empty() {
+                        return Err(Rejection::from(
+                            ApiError::bad_request()
+                                .detail(format!("route for prefix {} has no next hops", prefix)),
+                        ));
+                    }
+                    static_routes.push((prefix.clone(), Route::new(next_hops)));
                 }
 
-                let prefixes = routes.keys().map(|s| s.to_string());
-                store
-                    .set_static_routes(prefixes.zip(account_ids.into_iter()))
-                    .await?;
+                store.set_static_routes(static_routes).await?;
                 Ok::<Json, Rejection>(warp::reply::json(&routes))
             }
         });
@@ -222,6 +367,7 @@ where
     get_root
         .or(put_rates)
         .or(get_rates)
+        .or(get_rate_history)
         .or(get_routes)
         .or(put_static_routes)
         .or(put_static_route)
@@ -255,6 +401,40 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn gets_rate_history() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/rates/history?asset=ABC", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({
+                "asset_code": "ABC",
+                "rates": [
+                    {"timestamp": 1000, "rate": 1.0},
+                    {"timestamp": 2000, "rate": 2.0},
+                    {"timestamp": 3000, "rate": 3.0},
+                ],
+                "next": null,
+            })
+        );
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/rates/history?asset=ABC&from=1500&to=4000&limit=1",
+            "",
+            None,
+        )
+        .await;
+        let page: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(page["rates"], json!([{"timestamp": 2000, "rate": 2.0}]));
+        assert_eq!(page["next"], 2001);
+
+        let resp = api_call(&api, "GET", "/rates/history", "", None).await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
     #[tokio::test]
     async fn gets_routes() {
         let api = test_node_settings_api();
@@ -284,6 +464,26 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn puts_static_routes_with_several_next_hops() {
+        let api = test_node_settings_api();
+        let routes = json!({
+            "g.": ["alice", "bob"],
+            "example.eu": [{"username": "alice", "weight": 3}, {"username": "bob", "weight": 1}],
+            "example.us": "charlie",
+        });
+        let resp = api_call(&api, "PUT", "/routes/static", "admin", Some(routes.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            routes
+        );
+
+        let routes = json!({"g.": []});
+        let resp = api_call(&api, "PUT", "/routes/static", "admin", Some(routes)).await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
     #[tokio::test]
     async fn only_admin_can_put_single_static_route() {
         let api = test_node_settings_api();
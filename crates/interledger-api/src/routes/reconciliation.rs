@@ -0,0 +1,107 @@
+use crate::NodeStore;
+use interledger_errors::*;
+use interledger_service::Account;
+use interledger_service_util::{
+    AccountReconciliation, BalanceStore, TransactionStore, TransactionTotals, UncreditedAmount,
+};
+use interledger_settlement::core::types::LeftoversStore;
+use num_bigint::BigUint;
+use secrecy::{ExposeSecret, SecretString};
+use uuid::Uuid;
+use warp::{self, reply::Json, Filter, Rejection};
+
+/// Compares the account's balance with its history, and reports its uncredited settlement amounts
+async fn reconcile_account<S, A>(store: &S, account: &A) -> Result<AccountReconciliation, Rejection>
+where
+    S: BalanceStore
+        + TransactionStore
+        + LeftoversStore<AccountId = Uuid, AssetType = BigUint>
+        + Sync,
+    A: Account,
+{
+    let totals = TransactionTotals::load(store, account.id()).await?;
+    let balance = store.get_balance(account.id()).await?;
+    let (amount, scale) = store
+        .peek_uncredited_settlement_amount(account.id())
+        .await?;
+    Ok(AccountReconciliation::new(
+        account,
+        balance,
+        totals,
+        UncreditedAmount {
+            amount: amount.to_string(),
+            scale,
+        },
+    ))
+}
+
+pub fn reconciliation_api<S, A>(
+    admin_api_token: String,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    S: NodeStore<Account = A>
+        + BalanceStore
+        + TransactionStore
+        + LeftoversStore<AccountId = Uuid, AssetType = BigUint>,
+    A: Account + Send + Sync + 'static,
+{
+    // Helper filters
+    let admin_auth_header = format!("Bearer {}", admin_api_token);
+    let admin_only = warp::header::<SecretString>("authorization")
+        .and_then(move |authorization: SecretString| {
+            let admin_auth_header = admin_auth_header.clone();
+            async move {
+                if authorization.expose_secret() == &admin_auth_header {
+                    Ok::<(), Rejection>(())
+                } else {
+                    Err(Rejection::from(
+                        ApiError::unauthorized().detail("invalid admin auth token provided"),
+                    ))
+                }
+            }
+        })
+        .untuple_one();
+    let with_store = warp::any().map(move || store.clone());
+
+    // GET /reconciliation
+    warp::get()
+        .and(warp::path("reconciliation"))
+        .and(warp::path::end())
+        .and(admin_only)
+        .and(with_store)
+        .and_then(|store: S| async move {
+            let accounts = store.get_all_accounts().await?;
+            let mut report = Vec::with_capacity(accounts.len());
+            for account in &accounts {
+                report.push(reconcile_account(&store, account).await?);
+            }
+            Ok::<Json, Rejection>(warp::reply::json(&report))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::test_helpers::*;
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn reports_balance_mismatches() {
+        let api = test_reconciliation_api();
+        let resp = api_call(&api, "GET", "/reconciliation", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let report: Value = serde_json::from_slice(resp.body()).unwrap();
+        let accounts = report.as_array().unwrap();
+        assert_eq!(accounts.len(), 2);
+        // The test store has three incoming settlements of 100 and a balance of 1
+        assert_eq!(accounts[0]["settled_in"], 300);
+        assert_eq!(accounts[0]["expected_balance"], 300);
+        assert_eq!(accounts[0]["balance"], 1);
+        assert_eq!(accounts[0]["uncredited"]["amount"], "0");
+        assert_eq!(accounts[0]["discrepancies"][0]["type"], "balance_mismatch");
+        assert_eq!(accounts[0]["discrepancies"][0]["difference"], -299);
+
+        let resp = api_call(&api, "GET", "/reconciliation", "password", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
-    routes::{accounts_api, node_settings_api},
-    AccountDetails, AccountSettings, NodeStore,
+    routes::{accounts_api, fees_api, node_settings_api, reconciliation_api},
+    AccountDetails, AccountSettings, DefaultSpspAccount, NodeStore,
 };
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -11,14 +11,22 @@ use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
 use interledger_errors::*;
 use interledger_http::{HttpAccount, HttpStore};
 use interledger_packet::{Address, ErrorCode, FulfillBuilder, RejectBuilder};
-use interledger_rates::ExchangeRateStore;
-use interledger_router::RouterStore;
+use interledger_rates::{ExchangeRateHistoryStore, ExchangeRateStore, RateSnapshot};
+use interledger_router::{Route, RouterStore, RoutingTable};
 use interledger_service::{
     incoming_service_fn, outgoing_service_fn, Account, AccountStore, AddressStore, Username,
 };
-use interledger_service_util::BalanceStore;
-use interledger_settlement::core::types::{SettlementAccount, SettlementEngineDetails};
-use interledger_stream::{PaymentNotification, StreamNotificationsStore};
+use interledger_service_util::{
+    BalanceStore, FeePolicy, FeePolicyStore, Transaction, TransactionKind, TransactionQuery,
+    TransactionRecord, TransactionStore,
+};
+use interledger_settlement::core::types::{
+    LeftoversStore, SettlementAccount, SettlementEngineDetails,
+};
+use interledger_stream::{
+    resume_payment, PaymentHandle, PaymentNotification, StreamDelivery, StreamNotificationsStore,
+};
+use num_bigint::BigUint;
 use once_cell::sync::Lazy;
 use secrecy::SecretString;
 use serde::{Deserialize, Serialize};
@@ -59,6 +67,16 @@ pub fn test_node_settings_api(
     node_settings_api("admin".to_owned(), None, TestStore).recover(default_rejection_handler)
 }
 
+pub fn test_reconciliation_api(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    reconciliation_api("admin".to_owned(), TestStore).recover(default_rejection_handler)
+}
+
+pub fn test_fees_api(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    fees_api("admin".to_owned(), TestStore).recover(default_rejection_handler)
+}
+
 pub fn test_accounts_api(
 ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     let incoming = incoming_service_fn(|_request| {
@@ -85,7 +103,7 @@ pub fn test_accounts_api(
     accounts_api(
         Bytes::from("admin"),
         "admin".to_owned(),
-        None,
+        DefaultSpspAccount::default(),
         incoming,
         outgoing,
         btp,
@@ -94,6 +112,32 @@ pub fn test_accounts_api(
     .recover(default_rejection_handler)
 }
 
+/// A background payment to a receiver which rejects every packet, so it fails right away
+pub fn test_payment_handle() -> PaymentHandle {
+    let incoming = incoming_service_fn(|_request| {
+        Err(RejectBuilder {
+            code: ErrorCode::F02_UNREACHABLE,
+            message: b"No other incoming handler!",
+            data: &[],
+            triggered_by: None,
+        }
+        .build())
+    });
+    let delivery = StreamDelivery::new(
+        &TestAccount,
+        Address::from_str("example.receiver").unwrap(),
+        100,
+    );
+    resume_payment(
+        incoming,
+        &TestAccount,
+        TestStore,
+        vec![0; 32],
+        delivery,
+        0.0,
+    )
+}
+
 /*
  * Lots of boilerplate implementations of all necessary traits to launch
  * the crate's APIs in unit tests
@@ -217,9 +261,41 @@ impl ExchangeRateStore for TestStore {
     }
 }
 
+#[async_trait]
+impl ExchangeRateHistoryStore for TestStore {
+    async fn record_exchange_rates(
+        &self,
+        _timestamp: u64,
+        _rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        Ok(())
+    }
+
+    /// The rates 1, 2 and 3 of ABC, recorded at 1000, 2000 and 3000
+    async fn get_exchange_rate_history(
+        &self,
+        asset_code: &str,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<RateSnapshot>, ExchangeRateStoreError> {
+        if asset_code != "ABC" {
+            return Ok(Vec::new());
+        }
+        Ok((1..=3)
+            .map(|i| RateSnapshot {
+                timestamp: i * 1000,
+                rate: i as f64,
+            })
+            .filter(|snapshot| snapshot.timestamp >= from && snapshot.timestamp < to)
+            .take(limit)
+            .collect())
+    }
+}
+
 impl RouterStore for TestStore {
-    fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
-        Arc::new(HashMap::new())
+    fn routing_table(&self) -> Arc<RoutingTable<Route>> {
+        Arc::new(RoutingTable::new())
     }
 }
 
@@ -258,9 +334,10 @@ impl NodeStore for TestStore {
         Ok(vec![TestAccount, TestAccount])
     }
 
-    async fn set_static_routes<R>(&self, _routes: R) -> Result<(), NodeStoreError>
+    async fn set_static_routes<R, T>(&self, _routes: R) -> Result<(), NodeStoreError>
     where
-        R: IntoIterator<Item = (String, Uuid)> + Send + 'async_trait,
+        R: IntoIterator<Item = (String, T)> + Send + 'async_trait,
+        T: Into<Route> + Send + 'async_trait,
     {
         Ok(())
     }
@@ -367,6 +444,82 @@ impl BalanceStore for TestStore {
     }
 }
 
+#[async_trait]
+impl TransactionStore for TestStore {
+    async fn record_transactions(&self, _: Vec<Transaction>) -> Result<(), TransactionStoreError> {
+        unimplemented!()
+    }
+
+    /// Three settlements, with the IDs 3, 2 and 1
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        query: TransactionQuery,
+    ) -> Result<Vec<TransactionRecord>, TransactionStoreError> {
+        Ok((1..=3)
+            .rev()
+            .filter(|id| query.before.map_or(true, |before| *id < before))
+            .filter(|id| query.after.map_or(true, |after| *id > after))
+            .take(query.limit)
+            .map(|id| TransactionRecord {
+                id,
+                transaction: Transaction {
+                    timestamp: id * 1000,
+                    ..Transaction::settlement(account_id, TransactionKind::IncomingSettlement, 100)
+                },
+            })
+            .collect())
+    }
+}
+
+impl FeePolicyStore for TestStore {
+    fn get_fee_policy(&self) -> Arc<FeePolicy> {
+        Arc::new(FeePolicy::default())
+    }
+
+    fn set_fee_policy(&self, _policy: FeePolicy) {}
+}
+
+#[async_trait]
+impl LeftoversStore for TestStore {
+    type AccountId = Uuid;
+    type AssetType = BigUint;
+
+    async fn save_uncredited_settlement_amount(
+        &self,
+        _: Uuid,
+        _: (BigUint, u8),
+    ) -> Result<(), LeftoversStoreError> {
+        unimplemented!()
+    }
+
+    async fn load_uncredited_settlement_amount(
+        &self,
+        _: Uuid,
+        _local_scale: u8,
+    ) -> Result<BigUint, LeftoversStoreError> {
+        unimplemented!()
+    }
+
+    async fn clear_uncredited_settlement_amount(&self, _: Uuid) -> Result<(), LeftoversStoreError> {
+        unimplemented!()
+    }
+
+    async fn get_uncredited_settlement_amount(
+        &self,
+        _: Uuid,
+    ) -> Result<(BigUint, u8), LeftoversStoreError> {
+        unimplemented!()
+    }
+
+    async fn peek_uncredited_settlement_amount(
+        &self,
+        _: Uuid,
+    ) -> Result<(BigUint, u8), LeftoversStoreError> {
+        Ok((BigUint::from(0u32), 0))
+    }
+}
+
 #[async_trait]
 impl HttpStore for TestStore {
     type Account = TestAccount;
@@ -1,17 +1,57 @@
 use super::packet::*;
-use super::service::BtpOutgoingService;
+use super::service::{BtpConnectionState, BtpConnectionStatus, BtpOutgoingService};
 use super::BtpAccount;
-use futures::{future::join_all, SinkExt, StreamExt, TryFutureExt};
+use futures::{
+    channel::oneshot,
+    future::{self, join_all, Either},
+    SinkExt, StreamExt, TryFutureExt,
+};
 use interledger_errors::ApiError;
 use interledger_packet::Address;
 use interledger_service::*;
 use rand::random;
+use std::time::Duration;
 use thiserror::Error;
+use tokio::time;
 use tokio_tungstenite::connect_async;
-use tracing::{debug, error, trace};
+use tracing::{debug, error, trace, warn};
 use tungstenite::Message;
 use url::Url;
 
+/// How a dropped BTP connection is dialed again
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReconnectConfig {
+    /// Delay before the first reconnection attempt
+    pub initial_delay: Duration,
+    /// Upper bound of the delay between two attempts, which doubles after each failure
+    pub max_delay: Duration,
+    /// Give up after this many failed attempts in a row. Retries forever if `None`
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        ReconnectConfig {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectConfig {
+    /// Delay before the given attempt (starting at 0), jittered between
+    /// half and the full exponential backoff so that peers do not redial in lockstep
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_delay
+            .checked_mul(2u32.saturating_pow(attempt))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        backoff.mul_f64(0.5 + random::<f64>() / 2.0)
+    }
+}
+
 /// Create a BtpOutgoingService wrapping BTP connections to the accounts specified.
 /// Calling `handle_incoming` with an `IncomingService` will turn the returned
 /// BtpOutgoingService into a bidirectional handler.
@@ -22,17 +62,18 @@ pub async fn connect_client<A, S>(
     next_outgoing: S,
 ) -> Result<BtpOutgoingService<S, A>, BtpClientError>
 where
-    S: OutgoingService<A> + Clone + 'static,
+    S: OutgoingService<A> + Clone + Send + Sync + 'static,
     A: BtpAccount + Send + Sync + 'static,
 {
     let service = BtpOutgoingService::new(ilp_address, next_outgoing);
     let mut connect_btp = Vec::new();
     for account in accounts {
         // Can we make this take a reference to a service?
-        connect_btp.push(connect_to_service_account(
+        connect_btp.push(connect_and_supervise(
             account,
             error_on_unavailable,
             service.clone(),
+            ReconnectConfig::default(),
         ));
     }
     let res = join_all(connect_btp).await;
@@ -64,11 +105,171 @@ impl From<BtpClientError> for warp::Rejection {
 /// 1. Initialize a WebSocket connection at the BTP account's URL
 /// 2. Send a BTP authorization packet to the peer
 /// 3. If successful, consider the BTP connection established and add it to the service
+///
+/// The connection is not dialed again if it drops, see `connect_and_supervise` for that.
 pub async fn connect_to_service_account<O, A>(
     account: A,
     error_on_unavailable: bool,
     service: BtpOutgoingService<O, A>,
 ) -> Result<(), BtpClientError>
+where
+    O: OutgoingService<A> + Clone + 'static,
+    A: BtpAccount + Send + Sync + 'static,
+{
+    match connect(account, service).await {
+        Ok(_) => Ok(()),
+        Err(BtpClientError::Unavailable(_)) if !error_on_unavailable => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Connects to the specified account like `connect_to_service_account` and keeps the connection
+/// up: whenever it drops, it is dialed again and re-authenticated after a jittered exponential
+/// backoff delay. The state of the connection can be followed with
+/// `BtpOutgoingService::connection_state` and `BtpOutgoingService::subscribe_connection_events`.
+///
+/// If the first attempt fails, an error is returned when `error_on_unavailable` is set,
+/// and whichever task supervised the account before keeps doing so.
+/// Otherwise the account is dialed again in the background.
+/// Reconnecting stops once `BtpOutgoingService::close_connection` or `BtpOutgoingService::close`
+/// is called.
+pub async fn connect_and_supervise<O, A>(
+    account: A,
+    error_on_unavailable: bool,
+    service: BtpOutgoingService<O, A>,
+    config: ReconnectConfig,
+) -> Result<(), BtpClientError>
+where
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    A: BtpAccount + Send + Sync + 'static,
+{
+    let first_attempt = connect(account.clone(), service.clone()).await;
+    let (closed, attempts) = match first_attempt {
+        Ok(closed) => {
+            service.set_connection_state(&account, connected());
+            (Some(closed), 0)
+        }
+        // Any task already supervising the account keeps going
+        Err(err) if error_on_unavailable => return Err(err),
+        Err(err) => {
+            warn!(
+                "Cannot connect to account {}, will try again: {}",
+                account.username(),
+                err
+            );
+            service.set_connection_state(&account, reconnecting(1, err.to_string()));
+            (None, 1)
+        }
+    };
+    // Replaces (and so stops) the task which supervised the account before
+    let tripwire = service.add_supervisor(account.id());
+
+    tokio::spawn(async move {
+        let mut closed = closed;
+        let mut attempts = attempts;
+        loop {
+            if let Some(closed) = closed.take() {
+                // Wait for the connection to drop or for the supervision to stop
+                let stopped = future::select(closed, Box::pin(tripwire.clone()));
+                if let Either::Right(_) = stopped.await {
+                    break;
+                }
+                if service.is_closed() {
+                    break;
+                }
+                debug!(
+                    "Connection to account {} dropped, reconnecting",
+                    account.username()
+                );
+                service.set_connection_state(
+                    &account,
+                    reconnecting(0, "Connection closed".to_owned()),
+                );
+            }
+
+            if let Some(max_attempts) = config.max_attempts {
+                if attempts >= max_attempts {
+                    error!(
+                        "Giving up reconnecting to account {} after {} attempts",
+                        account.username(),
+                        attempts
+                    );
+                    let last_error = service
+                        .connection_state(&account.id())
+                        .and_then(|state| state.last_error);
+                    service.set_connection_state(
+                        &account,
+                        BtpConnectionState {
+                            status: BtpConnectionStatus::Failed,
+                            attempts,
+                            last_error,
+                        },
+                    );
+                    break;
+                }
+            }
+
+            let delay = time::delay_for(config.delay(attempts));
+            if let Either::Right(_) =
+                future::select(Box::pin(delay), Box::pin(tripwire.clone())).await
+            {
+                break;
+            }
+            if service.is_closed() {
+                break;
+            }
+
+            match connect(account.clone(), service.clone()).await {
+                Ok(new_connection) => {
+                    debug!("Reconnected to account {}", account.username());
+                    attempts = 0;
+                    closed = Some(new_connection);
+                    service.set_connection_state(&account, connected());
+                }
+                Err(err) => {
+                    attempts += 1;
+                    warn!(
+                        "Reconnecting to account {} failed (attempt {}): {}",
+                        account.username(),
+                        attempts,
+                        err
+                    );
+                    let state = reconnecting(attempts, err.to_string());
+                    service.set_connection_state(&account, state);
+                }
+            }
+        }
+        debug!(
+            "Stopped supervising connection to account {}",
+            account.username()
+        );
+    });
+
+    Ok(())
+}
+
+fn connected() -> BtpConnectionState {
+    BtpConnectionState {
+        status: BtpConnectionStatus::Connected,
+        attempts: 0,
+        last_error: None,
+    }
+}
+
+fn reconnecting(attempts: u32, error: String) -> BtpConnectionState {
+    BtpConnectionState {
+        status: BtpConnectionStatus::Reconnecting,
+        attempts,
+        last_error: Some(error),
+    }
+}
+
+/// Dials and authenticates to the account, returning a receiver which completes
+/// once the established connection closes
+async fn connect<O, A>(
+    account: A,
+    service: BtpOutgoingService<O, A>,
+) -> Result<oneshot::Receiver<()>, BtpClientError>
 where
     O: OutgoingService<A> + Clone + 'static,
     A: BtpAccount + Send + Sync + 'static,
@@ -139,17 +340,12 @@ where
         Ok(_) => {
             debug!("Connected to account {}'s server", account.id());
             let connection = connection.filter_map(|v| async move { v.ok() });
-            service.add_connection(account, connection);
-            Ok(())
+            Ok(service.add_connection(account, connection))
         }
         Err(err) => {
             let msg = format!("Error sending auth packet on connection {}: {}", url, err);
             error!("{}", msg);
-            if error_on_unavailable {
-                Err(BtpClientError::Unavailable(msg))
-            } else {
-                Ok(())
-            }
+            Err(BtpClientError::Unavailable(msg))
         }
     }
 }
@@ -12,14 +12,20 @@ use url::Url;
 
 mod client;
 mod errors;
+mod monitor;
 mod packet;
 mod server;
 mod service;
 mod wrapped_ws;
 
-pub use self::client::{connect_client, connect_to_service_account};
+pub use self::client::{
+    connect_and_supervise, connect_client, connect_to_service_account, ReconnectConfig,
+};
+pub use self::monitor::{BtpConnectionInfo, BtpConnectionMonitor, BtpPeerEvent, BtpPeerEventType};
 pub use self::server::btp_service_as_filter; // This is consumed only by the node.
-pub use self::service::{BtpOutgoingService, BtpService};
+pub use self::service::{
+    BtpConnectionEvent, BtpConnectionState, BtpConnectionStatus, BtpOutgoingService, BtpService,
+};
 
 use interledger_errors::BtpStoreError;
 
@@ -329,4 +335,289 @@ mod client_server {
 
         btp_service.close();
     }
+
+    /// Serves BTP on the given address, fulfilling all the incoming packets
+    async fn spawn_server(
+        bind_addr: SocketAddr,
+    ) -> BtpOutgoingService<impl OutgoingService<TestAccount> + Clone, TestAccount> {
+        let server_store = TestStore {
+            accounts: Arc::new([TestAccount {
+                id: Uuid::new_v4(),
+                ilp_over_btp_incoming_token: Some("test_auth_token".to_string()),
+                ilp_over_btp_outgoing_token: None,
+                ilp_over_btp_url: None,
+            }]),
+        };
+        let server_address = Address::from_str("example.server").unwrap();
+        let btp_service = BtpOutgoingService::new(
+            server_address.clone(),
+            outgoing_service_fn(move |_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other outgoing handler",
+                    triggered_by: Some(&server_address),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        btp_service
+            .clone()
+            .handle_incoming(incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: b"test data",
+                }
+                .build())
+            }))
+            .await;
+        let filter = btp_service_as_filter(btp_service.clone(), server_store);
+        tokio::spawn(warp::serve(filter).bind(bind_addr));
+        btp_service
+    }
+
+    fn test_request(account: &TestAccount) -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from: account.clone(),
+            to: account.clone(),
+            original_amount: 100,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                execution_condition: &[0; 32],
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                data: b"test data",
+            }
+            .build(),
+        }
+    }
+
+    #[tokio::test]
+    async fn client_reconnects_once_server_is_up() {
+        let bind_addr = get_open_port();
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_over_btp_url: Some(
+                Url::parse(&format!("btp+ws://{}/accounts/alice/ilp/btp", bind_addr)).unwrap(),
+            ),
+            ilp_over_btp_outgoing_token: Some("test_auth_token".to_string()),
+            ilp_over_btp_incoming_token: None,
+        };
+        let addr = Address::from_str("example.address").unwrap();
+        let btp_client = BtpOutgoingService::new(
+            addr.clone(),
+            outgoing_service_fn(move |_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: &[],
+                    data: &[],
+                    triggered_by: Some(&addr),
+                }
+                .build())
+            }),
+        );
+        let mut events = btp_client.subscribe_connection_events();
+        let config = ReconnectConfig {
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+            max_attempts: None,
+        };
+
+        // The server is not up yet
+        connect_and_supervise(account.clone(), false, btp_client.clone(), config)
+            .await
+            .unwrap();
+        let state = btp_client.connection_state(&account.id).unwrap();
+        assert_eq!(state.status, BtpConnectionStatus::Reconnecting);
+        assert!(state.last_error.is_some());
+
+        let btp_service = spawn_server(bind_addr).await;
+
+        let connected = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let event = events.recv().await.unwrap();
+                if event.state.status == BtpConnectionStatus::Connected {
+                    return event;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(connected.account_id, account.id);
+        assert_eq!(btp_client.connection_states()[&account.id], connected.state);
+
+        let mut btp_client = btp_client
+            .handle_incoming(incoming_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: &[],
+                    data: &[],
+                    triggered_by: None,
+                }
+                .build())
+            }))
+            .await;
+        let res = btp_client.send_request(test_request(&account)).await;
+        assert!(res.is_ok());
+
+        btp_service.close();
+    }
+
+    #[tokio::test]
+    async fn failed_redial_keeps_the_previous_supervisor() {
+        let bind_addr = get_open_port();
+        let btp_service = spawn_server(bind_addr).await;
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_over_btp_url: Some(
+                Url::parse(&format!("btp+ws://{}/accounts/alice/ilp/btp", bind_addr)).unwrap(),
+            ),
+            ilp_over_btp_outgoing_token: Some("test_auth_token".to_string()),
+            ilp_over_btp_incoming_token: None,
+        };
+        let addr = Address::from_str("example.address").unwrap();
+        let btp_client = BtpOutgoingService::new(
+            addr.clone(),
+            outgoing_service_fn(move |_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: &[],
+                    data: &[],
+                    triggered_by: Some(&addr),
+                }
+                .build())
+            }),
+        );
+        let config = ReconnectConfig {
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+            max_attempts: None,
+        };
+        connect_and_supervise(account.clone(), true, btp_client.clone(), config.clone())
+            .await
+            .unwrap();
+        let mut events = btp_client.subscribe_connection_events();
+
+        // Updating the account with a URL which cannot be dialed fails...
+        let unreachable = TestAccount {
+            ilp_over_btp_url: Some(
+                Url::parse(&format!(
+                    "btp+ws://{}/accounts/alice/ilp/btp",
+                    get_open_port()
+                ))
+                .unwrap(),
+            ),
+            ..account.clone()
+        };
+        assert!(
+            connect_and_supervise(unreachable, true, btp_client.clone(), config)
+                .await
+                .is_err()
+        );
+
+        // ...but the connection is still dialed again when the server drops it
+        btp_service.close();
+        let reconnected = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let event = events.recv().await.unwrap();
+                if event.state.status == BtpConnectionStatus::Connected {
+                    return event;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(reconnected.account_id, account.id);
+
+        btp_client.close();
+    }
+
+    #[tokio::test]
+    async fn connection_monitor_tracks_peers() {
+        let bind_addr = get_open_port();
+        let btp_service = spawn_server(bind_addr).await;
+        let mut server_events = btp_service.connection_monitor().subscribe();
+
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_over_btp_url: Some(
+                Url::parse(&format!("btp+ws://{}/accounts/alice/ilp/btp", bind_addr)).unwrap(),
+            ),
+            ilp_over_btp_outgoing_token: Some("test_auth_token".to_string()),
+            ilp_over_btp_incoming_token: None,
+        };
+        let addr = Address::from_str("example.address").unwrap();
+        let btp_client = connect_client(
+            addr.clone(),
+            vec![account.clone()],
+            true,
+            outgoing_service_fn(move |_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: &[],
+                    data: &[],
+                    triggered_by: Some(&addr),
+                }
+                .build())
+            }),
+        )
+        .await
+        .unwrap();
+        let monitor = btp_client.connection_monitor().clone();
+        let mut btp_client = btp_client
+            .handle_incoming(incoming_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: &[],
+                    data: &[],
+                    triggered_by: None,
+                }
+                .build())
+            }))
+            .await;
+
+        let connected = server_events.recv().await.unwrap();
+        assert_eq!(connected.event_type, BtpPeerEventType::Connected);
+        assert_eq!(connected.username, *ALICE);
+
+        assert!(btp_client
+            .send_request(test_request(&account))
+            .await
+            .is_ok());
+        let info = monitor.connection(&account.id).unwrap();
+        // The auth packet is sent before the connection is tracked, but not its response
+        assert_eq!(info.packets_sent, 1);
+        assert_eq!(info.packets_received, 2);
+        assert!(info.bytes_sent > 0 && info.bytes_received > 0);
+        assert_eq!(monitor.connections().len(), 1);
+
+        btp_service.close_connection(&connected.account_id);
+        let disconnected = server_events.recv().await.unwrap();
+        assert_eq!(disconnected.event_type, BtpPeerEventType::Disconnected);
+        assert_eq!(disconnected.account_id, connected.account_id);
+        assert!(btp_service
+            .connection_monitor()
+            .connection(&connected.account_id)
+            .is_none());
+
+        btp_client.close();
+        btp_service.close();
+    }
+
+    #[test]
+    fn reconnect_delay_is_jittered_and_bounded() {
+        let config = ReconnectConfig {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            max_attempts: None,
+        };
+        for _ in 0..100 {
+            let first = config.delay(0);
+            assert!(first >= Duration::from_millis(500) && first <= Duration::from_secs(1));
+            let third = config.delay(2);
+            assert!(third >= Duration::from_secs(2) && third <= Duration::from_secs(4));
+            let capped = config.delay(40);
+            assert!(capped >= Duration::from_secs(30) && capped <= Duration::from_secs(60));
+        }
+    }
 }
@@ -0,0 +1,216 @@
+use chrono::{DateTime, Utc};
+use interledger_service::Username;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tungstenite::Message;
+use uuid::Uuid;
+
+/// Number of peer events kept for subscribers that fall behind
+const PEER_EVENTS_CAPACITY: usize = 256;
+
+/// Liveness and traffic of an open BTP connection
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BtpConnectionInfo {
+    /// When the connection was established
+    pub connected_at: DateTime<Utc>,
+    /// How long the connection has been open, in seconds
+    pub connection_age_secs: u64,
+    /// Round trip time of the last Ping answered by the peer, in milliseconds.
+    /// Only measured on the connections we dial, since the server side does not send Pings
+    pub last_ping_rtt_ms: Option<f64>,
+    /// Bytes of the BTP packets sent to the peer
+    pub bytes_sent: u64,
+    /// Bytes of the BTP packets received from the peer
+    pub bytes_received: u64,
+    /// Number of BTP packets sent to the peer
+    pub packets_sent: u64,
+    /// Number of BTP packets received from the peer
+    pub packets_received: u64,
+}
+
+/// Whether a BTP peer connected or disconnected
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BtpPeerEventType {
+    Connected,
+    Disconnected,
+}
+
+/// Published each time a BTP connection opens or closes, whichever side initiated it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BtpPeerEvent {
+    #[serde(rename = "type")]
+    pub event_type: BtpPeerEventType,
+    pub account_id: Uuid,
+    pub username: Username,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Traffic of an open connection, updated as messages go through it
+#[derive(Debug)]
+pub(crate) struct ConnectionStats {
+    username: Username,
+    connected_at: DateTime<Utc>,
+    opened: Instant,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    packets_sent: AtomicU64,
+    packets_received: AtomicU64,
+    ping_sent_at: Mutex<Option<Instant>>,
+    last_ping_rtt: Mutex<Option<Duration>>,
+}
+
+impl ConnectionStats {
+    fn new(username: Username) -> Self {
+        ConnectionStats {
+            username,
+            connected_at: Utc::now(),
+            opened: Instant::now(),
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            packets_sent: AtomicU64::new(0),
+            packets_received: AtomicU64::new(0),
+            ping_sent_at: Mutex::new(None),
+            last_ping_rtt: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn record_sent(&self, message: &Message) {
+        match message {
+            Message::Binary(data) => {
+                self.packets_sent.fetch_add(1, Ordering::Relaxed);
+                self.bytes_sent
+                    .fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+            Message::Ping(_) => {
+                self.ping_sent_at.lock().replace(Instant::now());
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn record_received(&self, message: &Message) {
+        match message {
+            Message::Binary(data) => {
+                self.packets_received.fetch_add(1, Ordering::Relaxed);
+                self.bytes_received
+                    .fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+            Message::Pong(_) => {
+                if let Some(sent_at) = self.ping_sent_at.lock().take() {
+                    self.last_ping_rtt.lock().replace(sent_at.elapsed());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn info(&self) -> BtpConnectionInfo {
+        BtpConnectionInfo {
+            connected_at: self.connected_at,
+            connection_age_secs: self.opened.elapsed().as_secs(),
+            last_ping_rtt_ms: self
+                .last_ping_rtt
+                .lock()
+                .map(|rtt| rtt.as_secs_f64() * 1000.0),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            packets_sent: self.packets_sent.load(Ordering::Relaxed),
+            packets_received: self.packets_received.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Keeps track of the open BTP connections of one or more `BtpOutgoingService`s
+/// and publishes an event each time one of them opens or closes
+#[derive(Clone)]
+pub struct BtpConnectionMonitor {
+    connections: Arc<RwLock<HashMap<Uuid, Arc<ConnectionStats>>>>,
+    events: broadcast::Sender<BtpPeerEvent>,
+}
+
+impl Default for BtpConnectionMonitor {
+    fn default() -> Self {
+        let (events, _) = broadcast::channel(PEER_EVENTS_CAPACITY);
+        BtpConnectionMonitor {
+            connections: Arc::new(RwLock::new(HashMap::new())),
+            events,
+        }
+    }
+}
+
+impl BtpConnectionMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The open connection with the account, if any
+    pub fn connection(&self, account_id: &Uuid) -> Option<BtpConnectionInfo> {
+        self.connections
+            .read()
+            .get(account_id)
+            .map(|stats| stats.info())
+    }
+
+    /// All the open connections, indexed by account uid
+    pub fn connections(&self) -> HashMap<Uuid, BtpConnectionInfo> {
+        self.connections
+            .read()
+            .iter()
+            .map(|(id, stats)| (*id, stats.info()))
+            .collect()
+    }
+
+    /// Subscribe to the connections and disconnections of the peers
+    pub fn subscribe(&self) -> broadcast::Receiver<BtpPeerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Start tracking a new connection with the account, replacing any previous one
+    pub(crate) fn open(&self, account_id: Uuid, username: Username) -> Arc<ConnectionStats> {
+        let stats = Arc::new(ConnectionStats::new(username.clone()));
+        self.connections.write().insert(account_id, stats.clone());
+        self.publish(BtpPeerEventType::Connected, account_id, username);
+        stats
+    }
+
+    /// Stop tracking the connection with the account. If `stats` is given, the connection
+    /// is only forgotten if it was not replaced by a newer one in the meantime
+    pub(crate) fn close(&self, account_id: &Uuid, stats: Option<&Arc<ConnectionStats>>) {
+        let mut connections = self.connections.write();
+        let is_current = match (connections.get(account_id), stats) {
+            (Some(current), Some(stats)) => Arc::ptr_eq(current, stats),
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if is_current {
+            if let Some(closed) = connections.remove(account_id) {
+                drop(connections);
+                self.publish(
+                    BtpPeerEventType::Disconnected,
+                    *account_id,
+                    closed.username.clone(),
+                );
+            }
+        }
+    }
+
+    fn publish(&self, event_type: BtpPeerEventType, account_id: Uuid, username: Username) {
+        // Nobody may be listening
+        self.events
+            .send(BtpPeerEvent {
+                event_type,
+                account_id,
+                username,
+                timestamp: Utc::now(),
+            })
+            .ok();
+    }
+}
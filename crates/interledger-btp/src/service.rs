@@ -1,4 +1,4 @@
-use super::{packet::*, BtpAccount};
+use super::{monitor::BtpConnectionMonitor, packet::*, BtpAccount};
 use async_trait::async_trait;
 use bytes::BytesMut;
 use futures::{
@@ -13,9 +13,11 @@ use interledger_service::*;
 use once_cell::sync::Lazy;
 use parking_lot::{Mutex, RwLock};
 use rand::random;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::{convert::TryFrom, iter::IntoIterator, marker::PhantomData, sync::Arc, time::Duration};
-use stream_cancel::{Trigger, Valve};
+use stream_cancel::{Trigger, Tripwire, Valve};
+use tokio::sync::broadcast;
 use tokio::time;
 use tracing::{debug, error, trace, warn};
 use tungstenite::Message;
@@ -31,9 +33,42 @@ static PONG: Lazy<Message> = Lazy::new(|| Message::Pong(Vec::with_capacity(0)));
 // with us
 const SEND_MSG_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// Number of connection events kept for subscribers that fall behind
+const CONNECTION_EVENTS_CAPACITY: usize = 256;
+
 type IlpResultChannel = oneshot::Sender<Result<Fulfill, Reject>>;
 type IncomingRequestBuffer<A> = UnboundedReceiver<(A, u32, Prepare)>;
 
+/// Status of a BTP connection we dial and keep up
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BtpConnectionStatus {
+    /// The connection is established and authenticated
+    Connected,
+    /// The connection is down and will be dialed again after a backoff delay
+    Reconnecting,
+    /// The connection is down and we gave up reconnecting
+    Failed,
+}
+
+/// State of a BTP connection we dial and keep up
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BtpConnectionState {
+    pub status: BtpConnectionStatus,
+    /// Failed connection attempts since the connection was last established
+    pub attempts: u32,
+    /// Why the connection was last lost or could not be established
+    pub last_error: Option<String>,
+}
+
+/// Published each time the state of a BTP connection we dial changes
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BtpConnectionEvent {
+    pub account_id: Uuid,
+    pub username: Username,
+    pub state: BtpConnectionState,
+}
+
 /// The BtpOutgoingService wraps all BTP/WebSocket connections that come
 /// in on the given address. It implements OutgoingService for sending
 /// outgoing ILP Prepare packets over one of the connected BTP connections.
@@ -53,6 +88,13 @@ pub struct BtpOutgoingService<O, A: Account> {
     next: O,
     close_all_connections: Arc<Mutex<Option<Trigger>>>,
     stream_valve: Arc<Valve>,
+    /// State of the connections we dial and keep up, indexed by account uid
+    connection_states: Arc<RwLock<HashMap<Uuid, BtpConnectionState>>>,
+    /// Publishes the changes of the connection states
+    connection_events: broadcast::Sender<BtpConnectionEvent>,
+    /// Dropping an account's trigger stops reconnecting to it
+    supervisors: Arc<Mutex<HashMap<Uuid, Trigger>>>,
+    monitor: BtpConnectionMonitor,
 }
 
 /// Handle the packets based on whether they are an incoming request or a response to something we sent.
@@ -129,6 +171,7 @@ where
     pub fn new(ilp_address: Address, next: O) -> Self {
         let (incoming_sender, incoming_receiver) = unbounded();
         let (close_all_connections, stream_valve) = Valve::new();
+        let (connection_events, _) = broadcast::channel(CONNECTION_EVENTS_CAPACITY);
         BtpOutgoingService {
             ilp_address,
             connections: Arc::new(RwLock::new(HashMap::new())),
@@ -138,19 +181,97 @@ where
             next,
             close_all_connections: Arc::new(Mutex::new(Some(close_all_connections))),
             stream_valve: Arc::new(stream_valve),
+            connection_states: Arc::new(RwLock::new(HashMap::new())),
+            connection_events,
+            supervisors: Arc::new(Mutex::new(HashMap::new())),
+            monitor: BtpConnectionMonitor::new(),
         }
     }
 
+    /// Track the connections of this service with the given monitor instead of its own one,
+    /// so that the connections of several services can be followed together.
+    /// This must be called before any connection is added to the service.
+    pub fn with_connection_monitor(mut self, monitor: BtpConnectionMonitor) -> Self {
+        self.monitor = monitor;
+        self
+    }
+
+    /// The monitor tracking the open connections of this service
+    pub fn connection_monitor(&self) -> &BtpConnectionMonitor {
+        &self.monitor
+    }
+
     /// Deletes the websocket associated with the provided `account_id`
+    /// and stops reconnecting to the account
     pub fn close_connection(&self, account_id: &Uuid) {
+        self.supervisors.lock().remove(account_id);
+        self.connection_states.write().remove(account_id);
         self.connections.write().remove(account_id);
+        self.monitor.close(account_id, None);
     }
 
-    /// Close all of the open WebSocket connections
+    /// Closes the websocket associated with the provided `account_id`.
+    /// If we dialed it, the connection will be established again.
+    fn drop_connection(&self, account_id: &Uuid) {
+        if let Some(connection) = self.connections.write().remove(account_id) {
+            connection.unbounded_send(Message::Close(None)).ok();
+        }
+        self.monitor.close(account_id, None);
+    }
+
+    /// State of the connection we dial to the account, if we do
+    pub fn connection_state(&self, account_id: &Uuid) -> Option<BtpConnectionState> {
+        self.connection_states.read().get(account_id).cloned()
+    }
+
+    /// States of all the connections we dial, indexed by account uid
+    pub fn connection_states(&self) -> HashMap<Uuid, BtpConnectionState> {
+        self.connection_states.read().clone()
+    }
+
+    /// Subscribe to the changes of the states of the connections we dial
+    pub fn subscribe_connection_events(&self) -> broadcast::Receiver<BtpConnectionEvent> {
+        self.connection_events.subscribe()
+    }
+
+    pub(crate) fn set_connection_state(&self, account: &A, state: BtpConnectionState) {
+        self.connection_states
+            .write()
+            .insert(account.id(), state.clone());
+        // Nobody may be listening
+        self.connection_events
+            .send(BtpConnectionEvent {
+                account_id: account.id(),
+                username: account.username().clone(),
+                state,
+            })
+            .ok();
+    }
+
+    /// Register the task keeping up the connection to the account, replacing any previous one.
+    /// The returned tripwire resolves once the task should stop
+    pub(crate) fn add_supervisor(&self, account_id: Uuid) -> Tripwire {
+        let (trigger, tripwire) = Tripwire::new();
+        self.supervisors.lock().insert(account_id, trigger);
+        tripwire
+    }
+
+    /// Were all the connections closed with `close`?
+    pub(crate) fn is_closed(&self) -> bool {
+        self.close_all_connections.lock().is_none()
+    }
+
+    /// Close all of the open WebSocket connections, telling the peers that we are going away,
+    /// and stop reconnecting to the accounts we dial
     // TODO is there some more automatic way of knowing when we should close the connections?
     // The problem is that the WS client can be a server too, so it's not clear when we are done with it
     pub fn close(&self) {
         debug!("Closing all WebSocket connections");
+        self.supervisors.lock().clear();
+        for (account_id, connection) in self.connections.write().drain() {
+            connection.unbounded_send(Message::Close(None)).ok();
+            self.monitor.close(&account_id, None);
+        }
         self.close_all_connections.lock().take();
     }
 
@@ -158,12 +279,14 @@ where
     // incoming Prepare packets are buffered in a channel (until an IncomingService is added
     // via the handle_incoming method), and ILP Fulfill and Reject packets will be
     // sent back to the Future that sent the outgoing request originally.
+    // The returned receiver completes once the connection is closed.
     pub(crate) fn add_connection(
         &self,
         account: A,
         ws_stream: impl Stream<Item = Message> + Sink<Message> + Send + 'static,
-    ) {
+    ) -> oneshot::Receiver<()> {
         let account_id = account.id();
+        let stats = self.monitor.open(account_id, account.username().clone());
         // Set up a channel to forward outgoing packets to the WebSocket connection
         let (client_tx, client_rx) = unbounded();
         let (write, read) = ws_stream.split();
@@ -171,6 +294,8 @@ where
 
         // tx -> rx -> write -> our peer
         // Responsible mainly for responding to Pings
+        let sent_stats = stats.clone();
+        let client_rx = client_rx.inspect(move |message| sent_stats.record_sent(message));
         let write_to_ws = client_rx.map(Ok).forward(write).then(move |_| {
             async move {
                 debug!(
@@ -188,7 +313,9 @@ where
         let pending_outgoing = self.pending_outgoing.clone();
         let incoming_sender = self.incoming_sender.clone();
         let client_tx_clone = client_tx.clone();
+        let received_stats = stats.clone();
         let handle_message_fn = move |msg: Message| {
+            received_stats.record_received(&msg);
             handle_message(
                 msg,
                 client_tx_clone.clone(),
@@ -201,11 +328,27 @@ where
         // Close connections trigger
         let read = valve.wrap(read); // close when `write_to_ws` calls `drop(connection)`
         let read = self.stream_valve.wrap(read);
+        let connections = self.connections.clone();
+        let monitor = self.monitor.clone();
+        let connection_tx = client_tx.clone();
+        let (closed_tx, closed_rx) = oneshot::channel();
         let read_from_ws = read.for_each(handle_message_fn).then(move |_| async move {
             debug!(
                 "Finished reading from WebSocket stream for account: {}",
                 account_id
             );
+            // Forget the connection, unless it was already replaced by a new one
+            let mut connections = connections.write();
+            let is_current = connections
+                .get(&account_id)
+                .map(|tx| tx.same_receiver(&connection_tx))
+                .unwrap_or(false);
+            if is_current {
+                connections.remove(&account_id);
+            }
+            drop(connections);
+            monitor.close(&account_id, Some(&stats));
+            closed_tx.send(()).ok();
             Ok::<(), ()>(())
         });
         tokio::spawn(read_from_ws);
@@ -229,6 +372,7 @@ where
 
         // Save the sender side of the channel so we have a way to forward outgoing requests to the WebSocket
         self.connections.write().insert(account_id, client_tx);
+        closed_rx
     }
 
     /// Convert this BtpOutgoingService into a bidirectional BtpService by adding a handler for incoming requests.
@@ -348,7 +492,7 @@ where
                             // Assume that such a long timeout means that the peer closed their
                             // connection with us, so we'll remove the pending request and the websocket
                             (*self.pending_outgoing.lock()).remove(&request_id);
-                            self.close_connection(&request.to.id());
+                            self.drop_connection(&request.to.id());
 
                             return Err(RejectBuilder {
                                 code: ErrorCode::R00_TRANSFER_TIMED_OUT,
@@ -387,8 +531,9 @@ where
                         "Error sending websocket message for request {} to account {}: {:?}",
                         request_id, account_id, send_error
                     );
+                    // The connection was closed, so the Router can try another route
                     Err(RejectBuilder {
-                        code: ErrorCode::T00_INTERNAL_ERROR,
+                        code: ErrorCode::T01_PEER_UNREACHABLE,
                         message: &[],
                         triggered_by: Some(&ilp_address),
                         data: &[],
@@ -25,7 +25,7 @@ mod server;
 mod test_helpers;
 
 pub use packet::{Mode, RouteControlRequest};
-pub use server::{CcpRouteManager, CcpRouteManagerBuilder};
+pub use server::{CcpRouteManager, CcpRouteManagerBuilder, DEFAULT_BROADCAST_INTERVAL};
 
 use serde::{Deserialize, Serialize};
 
@@ -23,7 +23,7 @@ use std::{
     convert::TryFrom,
     str,
     sync::{
-        atomic::{AtomicU32, Ordering},
+        atomic::{AtomicU32, AtomicU64, Ordering},
         Arc,
     },
     time::Duration,
@@ -43,7 +43,8 @@ use once_cell::sync::Lazy;
 // otherwise. we could make it longer and make sure the BTP server
 // comes after the expiry shortener
 const DEFAULT_ROUTE_EXPIRY_TIME: u32 = 30000;
-const DEFAULT_BROADCAST_INTERVAL: u64 = 30000;
+/// Interval (in milliseconds) between the route broadcasts if none is set
+pub const DEFAULT_BROADCAST_INTERVAL: u64 = 30000;
 const DUMMY_ROUTING_TABLE_ID: [u8; 16] = [0; 16];
 
 fn hash(preimage: &[u8; 32]) -> [u8; 32] {
@@ -111,6 +112,7 @@ where
             local_table: Arc::new(RwLock::new(RoutingTable::default())),
             incoming_tables: Arc::new(RwLock::new(HashMap::new())),
             unavailable_accounts: Arc::new(Mutex::new(HashMap::new())),
+            broadcast_interval: Arc::new(AtomicU64::new(self.broadcast_interval)),
         };
 
         #[cfg(not(test))]
@@ -175,6 +177,8 @@ pub struct CcpRouteManager<I, O, S, A: Account> {
     /// This maps the account ID to the number of route brodcast intervals
     /// we should wait before trying again
     unavailable_accounts: Arc<Mutex<HashMap<Uuid, BackoffParams>>>,
+    /// Interval (in milliseconds) between the route broadcasts, which can be changed while they run
+    broadcast_interval: Arc<AtomicU64>,
 }
 
 impl<I, O, S, A> CcpRouteManager<I, O, S, A>
@@ -187,10 +191,22 @@ where
     /// Returns a future that will trigger this service to update its routes and broadcast
     /// updates to peers on the given interval. `interval` is in milliseconds
     pub async fn start_broadcast_interval(&self, interval: u64) {
+        self.set_broadcast_interval(interval);
         self.request_all_routes().await;
-        let mut interval = tokio::time::interval(Duration::from_millis(interval));
+        let mut current = interval;
+        let mut interval = tokio::time::interval(Duration::from_millis(current));
         loop {
             interval.tick().await;
+            let ms = self.broadcast_interval.load(Ordering::Relaxed);
+            if ms != current {
+                debug!(
+                    "Changing the route broadcast interval from {}ms to {}ms",
+                    current, ms
+                );
+                current = ms;
+                let period = Duration::from_millis(ms);
+                interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            }
             // ensure we have the latest ILP Address from the store
             self.update_ilp_address();
             // Do not consume the result if an error since we want to keep the loop going
@@ -198,6 +214,12 @@ where
         }
     }
 
+    /// Changes the interval (in milliseconds) of the running broadcasts. It applies from
+    /// the next broadcast on
+    pub fn set_broadcast_interval(&self, ms: u64) {
+        self.broadcast_interval.store(ms, Ordering::Relaxed);
+    }
+
     fn update_ilp_address(&self) {
         let current_ilp_address = self.ilp_address.read();
         let ilp_address = self.store.get_ilp_address();
@@ -20,6 +20,8 @@ pub enum CreateAccountError {
     InvalidRoutingRelation(String),
     #[error("the provided value for parameter `{0}` was too large")]
     ParamTooLarge(String),
+    #[error("the provided http client settings are not valid: {0}")]
+    InvalidHttpClientSettings(String),
 }
 
 impl From<CreateAccountError> for ApiError {
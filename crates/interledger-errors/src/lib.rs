@@ -31,3 +31,9 @@ pub use settlement_errors::{IdempotentStoreError, LeftoversStoreError, Settlemen
 
 mod create_account_error;
 pub use create_account_error::CreateAccountError;
+
+mod stream_connection_store_error;
+pub use stream_connection_store_error::StreamConnectionStoreError;
+
+mod transaction_store_error;
+pub use transaction_store_error::TransactionStoreError;
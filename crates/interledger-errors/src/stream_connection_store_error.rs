@@ -0,0 +1,48 @@
+use crate::error::ApiError;
+use std::error::Error as StdError;
+use thiserror::Error;
+
+/// Errors for the StreamConnectionStore
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum StreamConnectionStoreError {
+    #[error("{0}")]
+    Other(#[from] Box<dyn StdError + Send + 'static>),
+    #[error("STREAM connection `{0}` is closed")]
+    ConnectionClosed(String),
+    #[error("STREAM connection `{token}` can receive at most {receive_max} (received {total_received} so far)")]
+    ReceiveMaxExceeded {
+        token: String,
+        total_received: u64,
+        receive_max: u64,
+    },
+}
+
+impl From<StreamConnectionStoreError> for ApiError {
+    fn from(src: StreamConnectionStoreError) -> Self {
+        match src {
+            StreamConnectionStoreError::ConnectionClosed(_)
+            | StreamConnectionStoreError::ReceiveMaxExceeded { .. } => {
+                ApiError::conflict().detail(src.to_string())
+            }
+            _ => ApiError::internal_server_error().detail(src.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "warp_errors")]
+impl From<StreamConnectionStoreError> for warp::Rejection {
+    fn from(src: StreamConnectionStoreError) -> Self {
+        ApiError::from(src).into()
+    }
+}
+
+#[cfg(feature = "redis_errors")]
+use redis::RedisError;
+
+#[cfg(feature = "redis_errors")]
+impl From<RedisError> for StreamConnectionStoreError {
+    fn from(src: RedisError) -> StreamConnectionStoreError {
+        StreamConnectionStoreError::Other(Box::new(src))
+    }
+}
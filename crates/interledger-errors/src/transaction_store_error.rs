@@ -0,0 +1,34 @@
+use crate::error::ApiError;
+use std::error::Error as StdError;
+use thiserror::Error;
+
+/// Errors for the TransactionStore
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum TransactionStoreError {
+    #[error("{0}")]
+    Other(#[from] Box<dyn StdError + Send + 'static>),
+}
+
+impl From<TransactionStoreError> for ApiError {
+    fn from(src: TransactionStoreError) -> Self {
+        ApiError::internal_server_error().detail(src.to_string())
+    }
+}
+
+#[cfg(feature = "warp_errors")]
+impl From<TransactionStoreError> for warp::Rejection {
+    fn from(src: TransactionStoreError) -> Self {
+        ApiError::from(src).into()
+    }
+}
+
+#[cfg(feature = "redis_errors")]
+use redis::RedisError;
+
+#[cfg(feature = "redis_errors")]
+impl From<RedisError> for TransactionStoreError {
+    fn from(src: RedisError) -> TransactionStoreError {
+        TransactionStoreError::Other(Box::new(src))
+    }
+}
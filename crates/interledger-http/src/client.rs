@@ -1,4 +1,5 @@
 use super::{HttpAccount, HttpStore};
+use crate::multiplex::{MultiplexError, MultiplexedPeer};
 use async_trait::async_trait;
 use bytes::BytesMut;
 use futures::future::TryFutureExt;
@@ -6,11 +7,203 @@ use interledger_packet::{Address, ErrorCode, Packet, RejectBuilder};
 use interledger_service::*;
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue},
-    Client, ClientBuilder, Response as HttpResponse,
+    Certificate, Client, ClientBuilder, Identity, Response as HttpResponse,
 };
 use secrecy::{ExposeSecret, SecretString};
-use std::{convert::TryFrom, iter::FromIterator, marker::PhantomData, sync::Arc, time::Duration};
-use tracing::{error, trace};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::{
+    convert::TryFrom,
+    fs,
+    iter::FromIterator,
+    marker::PhantomData,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::sync::Semaphore;
+use tracing::{debug, error, trace};
+use uuid::Uuid;
+
+/// Timeout of the requests to the peers which do not configure their own
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How packets are sent to a peer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HttpTransport {
+    /// Each packet is sent in its own POST request
+    Post,
+    /// Packets are multiplexed over a WebSocket opened to the peer's ILP over HTTP URL.
+    /// If the peer does not accept it, packets are sent in POST requests instead
+    WebSocket,
+}
+
+impl Default for HttpTransport {
+    fn default() -> Self {
+        HttpTransport::Post
+    }
+}
+
+/// Settings of the HTTP client used to send ILP over HTTP packets to a peer.
+/// Peers without settings are sent packets with a shared client and the default settings.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpClientSettings {
+    /// Timeout of each request, in milliseconds (30 seconds if not set)
+    pub timeout_ms: Option<u64>,
+    /// Maximum number of requests in flight to the peer (unlimited if not set).
+    /// Further packets wait for one of the requests to complete
+    pub max_concurrent_requests: Option<u32>,
+    /// Use HTTP/2 without negotiating it first, for peers known to support it
+    pub http2_prior_knowledge: bool,
+    /// Path to a PEM-encoded CA certificate trusted to verify the peer's certificate,
+    /// in addition to the system's ones
+    pub ca_certificate_path: Option<String>,
+    /// Path to a PKCS #12 archive with the client certificate and private key
+    /// presented to the peer (mutual TLS)
+    pub client_identity_path: Option<String>,
+    /// Password of the PKCS #12 archive.
+    /// It is serialized as is, so stores must encrypt it before persisting the settings
+    #[serde(serialize_with = "optional_secret_string_to_str")]
+    pub client_identity_password: Option<SecretString>,
+    /// Additional headers sent with each request
+    pub headers: BTreeMap<String, String>,
+    /// How packets are sent to the peer
+    pub transport: HttpTransport,
+}
+
+impl PartialEq for HttpClientSettings {
+    fn eq(&self, other: &Self) -> bool {
+        self.timeout_ms == other.timeout_ms
+            && self.max_concurrent_requests == other.max_concurrent_requests
+            && self.http2_prior_knowledge == other.http2_prior_knowledge
+            && self.ca_certificate_path == other.ca_certificate_path
+            && self.client_identity_path == other.client_identity_path
+            && self
+                .client_identity_password
+                .as_ref()
+                .map(ExposeSecret::expose_secret)
+                == other
+                    .client_identity_password
+                    .as_ref()
+                    .map(ExposeSecret::expose_secret)
+            && self.headers == other.headers
+            && self.transport == other.transport
+    }
+}
+
+fn optional_secret_string_to_str<S>(
+    secret: &Option<SecretString>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    secret
+        .as_ref()
+        .map(ExposeSecret::expose_secret)
+        .serialize(serializer)
+}
+
+/// Errors building an HTTP client from an account's settings
+#[derive(Error, Debug)]
+pub enum HttpClientSettingsError {
+    #[error("cannot read {0}: {1}")]
+    Read(String, std::io::Error),
+    #[error("invalid header {0}")]
+    InvalidHeader(String),
+    #[error("max_concurrent_requests must be positive")]
+    NoConcurrentRequests,
+    #[error("the WebSocket transport does not support custom certificates")]
+    WebSocketCertificates,
+    #[error("{0}")]
+    Client(#[from] reqwest::Error),
+}
+
+impl HttpClientSettings {
+    /// Builds a client with these settings
+    ///
+    /// # Errors
+    /// 1. If a certificate cannot be read or parsed
+    /// 1. If one of the headers has an invalid name or value
+    /// 1. If certificates are set for the WebSocket transport
+    pub fn build_client(&self) -> Result<Client, HttpClientSettingsError> {
+        if self.max_concurrent_requests == Some(0) {
+            return Err(HttpClientSettingsError::NoConcurrentRequests);
+        }
+        if self.transport == HttpTransport::WebSocket
+            && (self.ca_certificate_path.is_some() || self.client_identity_path.is_some())
+        {
+            return Err(HttpClientSettingsError::WebSocketCertificates);
+        }
+        let mut headers = default_headers();
+        headers.extend(self.custom_headers()?);
+
+        let mut builder = ClientBuilder::new()
+            .default_headers(headers)
+            .timeout(self.timeout());
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(ref path) = self.ca_certificate_path {
+            let pem = read(path)?;
+            builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
+        }
+        if let Some(ref path) = self.client_identity_path {
+            let archive = read(path)?;
+            let password = self
+                .client_identity_password
+                .as_ref()
+                .map(|password| password.expose_secret().as_str())
+                .unwrap_or("");
+            builder = builder.identity(Identity::from_pkcs12_der(&archive, password)?);
+        }
+        Ok(builder.build()?)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_TIMEOUT)
+    }
+
+    fn custom_headers(&self) -> Result<HeaderMap, HttpClientSettingsError> {
+        let mut headers = HeaderMap::with_capacity(self.headers.len());
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| HttpClientSettingsError::InvalidHeader(name.clone()))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| HttpClientSettingsError::InvalidHeader(name.to_string()))?;
+            headers.insert(name, value);
+        }
+        Ok(headers)
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, HttpClientSettingsError> {
+    fs::read(path).map_err(|err| HttpClientSettingsError::Read(path.to_owned(), err))
+}
+
+fn default_headers() -> HeaderMap {
+    let mut headers = HeaderMap::with_capacity(2);
+    headers.insert(
+        HeaderName::from_static("content-type"),
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    headers
+}
+
+/// Client built with a peer's own settings
+#[derive(Clone)]
+struct PeerClient {
+    settings: HttpClientSettings,
+    client: Client,
+    /// Limits the number of requests in flight, if the settings do
+    limiter: Option<Arc<Semaphore>>,
+    /// Connection the packets are multiplexed over, if the peer uses the WebSocket transport
+    multiplexed: Option<Arc<MultiplexedPeer>>,
+}
 
 /// The HttpClientService implements [OutgoingService](../../interledger_service/trait.OutgoingService)
 /// for sending ILP Prepare packets over to the HTTP URL associated with the provided account
@@ -19,8 +212,11 @@ use tracing::{error, trace};
 #[derive(Clone)]
 pub struct HttpClientService<S, O, A> {
     /// An HTTP client configured with a 30 second timeout by default. It is used to send the
-    /// ILP over HTTP messages to the peer
+    /// ILP over HTTP messages to the peers without their own client settings
     client: Client,
+    /// Clients of the peers with their own settings, indexed by account id.
+    /// They are built on the first packet sent to the peer, and built again if its settings change
+    peer_clients: Arc<RwLock<HashMap<Uuid, PeerClient>>>,
     /// The store used by the client to get the node's ILP Address,
     /// used to populate the `triggered_by` field in Reject packets
     store: Arc<S>,
@@ -38,19 +234,15 @@ where
 {
     /// Constructs the HttpClientService
     pub fn new(store: S, next: O) -> Self {
-        let mut headers = HeaderMap::with_capacity(2);
-        headers.insert(
-            HeaderName::from_static("content-type"),
-            HeaderValue::from_static("application/octet-stream"),
-        );
         let client = ClientBuilder::new()
-            .default_headers(headers)
-            .timeout(Duration::from_secs(30))
+            .default_headers(default_headers())
+            .timeout(DEFAULT_TIMEOUT)
             .build()
             .unwrap();
 
         HttpClientService {
             client,
+            peer_clients: Arc::new(RwLock::new(HashMap::new())),
             store: Arc::new(store),
             next,
             account_type: PhantomData,
@@ -58,6 +250,43 @@ where
     }
 }
 
+impl<S, O, A> HttpClientService<S, O, A> {
+    /// Returns the client of the peer with the given settings, building it if the peer
+    /// has none yet or if its settings changed
+    fn peer_client(
+        &self,
+        account_id: Uuid,
+        settings: &HttpClientSettings,
+    ) -> Result<PeerClient, HttpClientSettingsError> {
+        if let Some(peer) = self.peer_clients.read().unwrap().get(&account_id) {
+            if &peer.settings == settings {
+                return Ok(peer.clone());
+            }
+        }
+
+        debug!("Building HTTP client for account {}", account_id);
+        let peer = PeerClient {
+            settings: settings.clone(),
+            client: settings.build_client()?,
+            limiter: settings
+                .max_concurrent_requests
+                .map(|limit| Arc::new(Semaphore::new(limit as usize))),
+            multiplexed: match settings.transport {
+                HttpTransport::WebSocket => Some(Arc::new(MultiplexedPeer::new(
+                    settings.custom_headers()?,
+                    settings.timeout(),
+                ))),
+                HttpTransport::Post => None,
+            },
+        };
+        self.peer_clients
+            .write()
+            .unwrap()
+            .insert(account_id, peer.clone());
+        Ok(peer)
+    }
+}
+
 #[async_trait]
 impl<S, O, A> OutgoingService<A> for HttpClientService<S, O, A>
 where
@@ -82,19 +311,82 @@ where
                 .unwrap_or_else(|| SecretString::new("".to_owned()));
             let header = format!("Bearer {}", token.expose_secret());
             let body = request.prepare.as_ref().to_owned();
-            let resp = self_clone
-                .client
+
+            let (client, limiter, multiplexed) = match request.to.get_http_client_settings() {
+                Some(settings) => match self.peer_client(request.to.id(), settings) {
+                    Ok(peer) => (peer.client, peer.limiter, peer.multiplexed),
+                    Err(err) => {
+                        error!(
+                            "Invalid HTTP client settings for account {}: {}",
+                            request.to.id(),
+                            err
+                        );
+                        return Err(RejectBuilder {
+                            code: ErrorCode::T00_INTERNAL_ERROR,
+                            message: b"Invalid HTTP client settings for the peer",
+                            triggered_by: Some(&ilp_address),
+                            data: &[],
+                        }
+                        .build());
+                    }
+                },
+                None => (self_clone.client.clone(), None, None),
+            };
+            // Wait for a slot if the peer limits its concurrent requests
+            let _permit = match limiter {
+                Some(ref limiter) => Some(limiter.acquire().await),
+                None => None,
+            };
+
+            // Packets are POSTed to peers which refuse the WebSocket
+            if let Some(multiplexed) = multiplexed {
+                if let Some(connection) = multiplexed.connection(url, &header).await {
+                    let response = connection.send(&body).await.map_err(|err| {
+                        error!(
+                            "Error sending packet to account {} over WebSocket: {}",
+                            request.to.id(),
+                            err
+                        );
+                        // Only a packet which was never sent may be sent to another peer,
+                        // because the peer may have it otherwise
+                        let code = match err {
+                            MultiplexError::NotSent => ErrorCode::T01_PEER_UNREACHABLE,
+                            MultiplexError::TimedOut => ErrorCode::R00_TRANSFER_TIMED_OUT,
+                            MultiplexError::Closed => ErrorCode::T00_INTERNAL_ERROR,
+                        };
+                        RejectBuilder {
+                            code,
+                            message: format!("Error sending ILP over HTTP request: {}", err)
+                                .as_bytes(),
+                            triggered_by: Some(&ilp_address),
+                            data: &[],
+                        }
+                        .build()
+                    })?;
+                    return parse_packet(response, &ilp_address);
+                }
+            }
+
+            let resp = client
                 .post(url.as_ref())
                 .header("authorization", &header)
                 .body(body)
                 .send()
                 .map_err(move |err| {
                     error!("Error sending HTTP request: {:?}", err);
-                    let mut code = ErrorCode::T01_PEER_UNREACHABLE;
-                    if let Some(status) = err.status() {
-                        if status.is_client_error() {
-                            code = ErrorCode::F00_BAD_REQUEST
-                        }
+                    // Only a request which never got to the peer may be sent to another peer,
+                    // because the peer may have the packet otherwise
+                    let code = if err.is_connect() {
+                        ErrorCode::T01_PEER_UNREACHABLE
+                    } else if err.is_timeout() {
+                        ErrorCode::R00_TRANSFER_TIMED_OUT
+                    } else if err
+                        .status()
+                        .map_or(false, |status| status.is_client_error())
+                    {
+                        ErrorCode::F00_BAD_REQUEST
+                    } else {
+                        ErrorCode::T00_INTERNAL_ERROR
                     };
 
                     let message = format!("Error sending ILP over HTTP request: {}", err);
@@ -129,7 +421,8 @@ async fn parse_packet_from_response(response: HttpResponse, ilp_address: Address
                 ErrorCode::F02_UNREACHABLE
             } else {
                 // TODO more specific errors for rate limiting, etc?
-                ErrorCode::T01_PEER_UNREACHABLE
+                // Not T01, since the peer got the request and may have forwarded the packet
+                ErrorCode::T00_INTERNAL_ERROR
             }
         } else {
             ErrorCode::T00_INTERNAL_ERROR
@@ -143,31 +436,111 @@ async fn parse_packet_from_response(response: HttpResponse, ilp_address: Address
         .build()
     })?;
 
-    let ilp_address_clone = ilp_address.clone();
     let body = response
         .bytes()
         .map_err(|err| {
             error!("Error getting HTTP response body: {:?}", err);
+            // The peer got the packet, but its response is lost
+            let code = if err.is_timeout() {
+                ErrorCode::R00_TRANSFER_TIMED_OUT
+            } else {
+                ErrorCode::T00_INTERNAL_ERROR
+            };
             RejectBuilder {
-                code: ErrorCode::T01_PEER_UNREACHABLE,
+                code,
                 message: &[],
-                triggered_by: Some(&ilp_address_clone),
+                triggered_by: Some(&ilp_address),
                 data: &[],
             }
             .build()
         })
         .await?;
 
-    let body = BytesMut::from_iter(body.into_iter());
+    parse_packet(BytesMut::from_iter(body.into_iter()), &ilp_address)
+}
+
+/// Parses the Fulfill or Reject packet a peer responded with
+fn parse_packet(body: BytesMut, ilp_address: &Address) -> IlpResult {
     match Packet::try_from(body) {
         Ok(Packet::Fulfill(fulfill)) => Ok(fulfill),
         Ok(Packet::Reject(reject)) => Err(reject),
+        // The peer got the packet, but its response cannot be understood
         _ => Err(RejectBuilder {
-            code: ErrorCode::T01_PEER_UNREACHABLE,
+            code: ErrorCode::T00_INTERNAL_ERROR,
             message: &[],
-            triggered_by: Some(&ilp_address_clone),
+            triggered_by: Some(ilp_address),
             data: &[],
         }
         .build()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_client_from_settings() {
+        let mut settings = HttpClientSettings {
+            timeout_ms: Some(120_000),
+            max_concurrent_requests: Some(8),
+            http2_prior_knowledge: true,
+            ..Default::default()
+        };
+        settings
+            .headers
+            .insert("x-peer-id".to_owned(), "node-a".to_owned());
+        assert!(settings.build_client().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let mut settings = HttpClientSettings::default();
+        settings
+            .headers
+            .insert("invalid header".to_owned(), "value".to_owned());
+        assert!(matches!(
+            settings.build_client(),
+            Err(HttpClientSettingsError::InvalidHeader(_))
+        ));
+
+        let settings = HttpClientSettings {
+            ca_certificate_path: Some("/nonexistent/ca.pem".to_owned()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            settings.build_client(),
+            Err(HttpClientSettingsError::Read(_, _))
+        ));
+
+        let settings = HttpClientSettings {
+            max_concurrent_requests: Some(0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            settings.build_client(),
+            Err(HttpClientSettingsError::NoConcurrentRequests)
+        ));
+
+        let settings = HttpClientSettings {
+            transport: HttpTransport::WebSocket,
+            ca_certificate_path: Some("/nonexistent/ca.pem".to_owned()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            settings.build_client(),
+            Err(HttpClientSettingsError::WebSocketCertificates)
+        ));
+    }
+
+    #[test]
+    fn settings_default_when_missing() {
+        let settings: HttpClientSettings =
+            serde_json::from_str(r#"{"timeout_ms":5000,"headers":{"x-a":"b"}}"#).unwrap();
+        assert_eq!(settings.timeout_ms, Some(5000));
+        assert_eq!(settings.max_concurrent_requests, None);
+        assert!(!settings.http2_prior_knowledge);
+        assert_eq!(settings.transport, HttpTransport::Post);
+        assert_eq!(settings.headers["x-a"], "b");
+    }
+}
@@ -14,10 +14,14 @@ use warp::{self, Filter, Rejection};
 
 /// [ILP over HTTP](https://interledger.org/rfcs/0035-ilp-over-http/) Outgoing Service
 mod client;
+/// Packets multiplexed over a WebSocket, as an alternative to a request per packet
+mod multiplex;
 /// [ILP over HTTP](https://interledger.org/rfcs/0035-ilp-over-http/) API (implemented with [Warp](https://docs.rs/warp/0.2.0/warp/))
 mod server;
 
-pub use self::client::HttpClientService;
+pub use self::client::{
+    HttpClientService, HttpClientSettings, HttpClientSettingsError, HttpTransport,
+};
 pub use self::server::HttpServer;
 
 /// Extension trait for [Account](../interledger_service/trait.Account.html) with [ILP over HTTP](https://interledger.org/rfcs/0035-ilp-over-http/) related information
@@ -26,6 +30,11 @@ pub trait HttpAccount: Account {
     fn get_http_url(&self) -> Option<&Url>;
     /// Returns the HTTP token which is sent as an HTTP header on each ILP over HTTP request
     fn get_http_auth_token(&self) -> Option<SecretString>;
+    /// Returns the settings of the HTTP client used to send packets to this account,
+    /// if it does not use the default ones
+    fn get_http_client_settings(&self) -> Option<&HttpClientSettings> {
+        None
+    }
 }
 
 /// The interface for Stores that can be used with the HttpServerService.
@@ -0,0 +1,343 @@
+//! Multiplexed ILP over HTTP.
+//!
+//! A peer which sends many packets can open a WebSocket to the account's ILP over HTTP
+//! endpoint (`GET /accounts/:username/ilp`, authorized like the POST requests) instead of
+//! making a request per packet. Each binary message carries one packet, prefixed with a
+//! 4-byte big-endian request ID. The Prepare packets are handled concurrently and each is
+//! answered with its Fulfill or Reject under the same request ID, so responses may arrive
+//! in any order. At most `MAX_CONCURRENT_PACKETS` packets of a connection are handled at a
+//! time: the following frames are not read until one of them is answered.
+use bytes::BytesMut;
+use futures::{SinkExt, StreamExt};
+use http::{header::AUTHORIZATION, HeaderMap, HeaderValue, Request};
+use interledger_packet::{ErrorCode, Prepare, RejectBuilder};
+use interledger_service::{Account, IncomingRequest, IncomingService};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::{
+    sync::{mpsc, oneshot, Semaphore},
+    time::timeout,
+};
+use tokio_tungstenite::connect_async;
+use tracing::{debug, trace, warn};
+use tungstenite::Message;
+use url::Url;
+use warp::ws::{self, WebSocket};
+
+/// Length of the request ID which prefixes each packet
+pub(crate) const REQUEST_ID_LENGTH: usize = 4;
+/// Time during which packets are POSTed to a peer which did not accept the WebSocket,
+/// before trying to open it again
+const UPGRADE_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+/// Number of the packets a peer sent over its WebSocket which are handled at the same time
+const MAX_CONCURRENT_PACKETS: usize = 256;
+
+pub(crate) fn encode_frame(request_id: u32, packet: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(REQUEST_ID_LENGTH + packet.len());
+    frame.extend_from_slice(&request_id.to_be_bytes());
+    frame.extend_from_slice(packet);
+    frame
+}
+
+pub(crate) fn decode_frame(frame: &[u8]) -> Option<(u32, &[u8])> {
+    if frame.len() < REQUEST_ID_LENGTH {
+        return None;
+    }
+    let (request_id, packet) = frame.split_at(REQUEST_ID_LENGTH);
+    let mut id = [0; REQUEST_ID_LENGTH];
+    id.copy_from_slice(request_id);
+    Some((u32::from_be_bytes(id), packet))
+}
+
+/// Errors sending a packet over a multiplexed connection
+#[derive(Error, Debug)]
+pub(crate) enum MultiplexError {
+    /// The packet never left the node
+    #[error("the connection was closed before the packet was sent")]
+    NotSent,
+    /// The packet may have been sent, so the peer may have it
+    #[error("the connection was closed before the peer responded")]
+    Closed,
+    /// The packet may have been sent, so the peer may have it
+    #[error("the peer did not respond in time")]
+    TimedOut,
+}
+
+type PendingRequests = Arc<Mutex<HashMap<u32, oneshot::Sender<BytesMut>>>>;
+
+/// An open WebSocket to a peer
+#[derive(Clone)]
+pub(crate) struct Connection {
+    frames: mpsc::UnboundedSender<Message>,
+    /// Requests waiting for their response, by request ID
+    pending: PendingRequests,
+    next_request_id: Arc<AtomicU32>,
+    closed: Arc<AtomicBool>,
+    timeout: Duration,
+}
+
+impl Connection {
+    async fn open(
+        url: &Url,
+        authorization: &str,
+        headers: &HeaderMap,
+        response_timeout: Duration,
+    ) -> Result<Self, tungstenite::Error> {
+        let mut url = url.clone();
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        // Both schemes are special ones so this cannot fail
+        url.set_scheme(scheme).ok();
+        let mut request = Request::get(url.as_str()).body(())?;
+        request.headers_mut().extend(headers.clone());
+        request.headers_mut().insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(authorization).map_err(http::Error::from)?,
+        );
+
+        let (socket, _) = connect_async(request).await?;
+        let (mut sink, mut stream) = socket.split();
+        let (frames, mut outgoing) = mpsc::unbounded_channel();
+        let connection = Connection {
+            frames,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            next_request_id: Arc::new(AtomicU32::new(0)),
+            closed: Arc::new(AtomicBool::new(false)),
+            timeout: response_timeout,
+        };
+
+        tokio::spawn(async move {
+            while let Some(frame) = outgoing.recv().await {
+                if sink.send(frame).await.is_err() {
+                    return;
+                }
+            }
+            // Nobody sends packets over the connection anymore
+            sink.send(Message::Close(None)).await.ok();
+        });
+
+        let pending = connection.pending.clone();
+        let closed = connection.closed.clone();
+        let peer = url.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(message)) = stream.next().await {
+                match message {
+                    Message::Binary(frame) => match decode_frame(&frame) {
+                        Some((request_id, packet)) => {
+                            trace!("Got response to request id {} from {}", request_id, peer);
+                            if let Some(sender) = pending.lock().unwrap().remove(&request_id) {
+                                sender.send(BytesMut::from(packet)).ok();
+                            }
+                        }
+                        None => {
+                            warn!("Got an invalid frame from {}, closing the connection", peer);
+                            break;
+                        }
+                    },
+                    Message::Close(_) => break,
+                    _ => {}
+                }
+            }
+            debug!("WebSocket to {} closed", peer);
+            closed.store(true, Ordering::SeqCst);
+            // Dropping the senders fails the requests still waiting for a response
+            pending.lock().unwrap().clear();
+        });
+
+        Ok(connection)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Sends the Prepare packet and returns the peer's response to it. Once the frame is
+    /// queued, it may be written at any time, so the later errors do not mean the peer
+    /// did not get the packet
+    pub(crate) async fn send(&self, prepare: &[u8]) -> Result<BytesMut, MultiplexError> {
+        if self.is_closed() {
+            return Err(MultiplexError::NotSent);
+        }
+        let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(request_id, sender);
+        let frame = Message::Binary(encode_frame(request_id, prepare));
+        if self.frames.send(frame).is_err() {
+            self.pending.lock().unwrap().remove(&request_id);
+            return Err(MultiplexError::NotSent);
+        }
+
+        match timeout(self.timeout, receiver).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => Err(MultiplexError::Closed),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&request_id);
+                Err(MultiplexError::TimedOut)
+            }
+        }
+    }
+}
+
+enum PeerState {
+    Disconnected,
+    Connected(Connection),
+    /// The peer did not accept the WebSocket at that time
+    Refused(Instant),
+}
+
+/// Multiplexed connection to a peer, which is opened on the first packet sent to it
+/// and opened again once it drops
+pub(crate) struct MultiplexedPeer {
+    /// Custom headers sent with the request opening the connection
+    headers: HeaderMap,
+    timeout: Duration,
+    state: tokio::sync::Mutex<PeerState>,
+}
+
+impl MultiplexedPeer {
+    pub(crate) fn new(headers: HeaderMap, timeout: Duration) -> Self {
+        MultiplexedPeer {
+            headers,
+            timeout,
+            state: tokio::sync::Mutex::new(PeerState::Disconnected),
+        }
+    }
+
+    /// Returns the connection to the peer, opening it if needed. Returns `None` if the peer
+    /// does not accept WebSockets (or cannot be reached), in which case the packets should
+    /// be sent in POST requests
+    pub(crate) async fn connection(&self, url: &Url, authorization: &str) -> Option<Connection> {
+        let mut state = self.state.lock().await;
+        match *state {
+            PeerState::Connected(ref connection) if !connection.is_closed() => {
+                return Some(connection.clone())
+            }
+            PeerState::Refused(since) if since.elapsed() < UPGRADE_RETRY_INTERVAL => return None,
+            _ => {}
+        }
+
+        debug!("Opening WebSocket to {}", url);
+        let opening = Connection::open(url, authorization, &self.headers, self.timeout);
+        let error = match timeout(self.timeout, opening).await {
+            Ok(Ok(connection)) => {
+                *state = PeerState::Connected(connection.clone());
+                return Some(connection);
+            }
+            Ok(Err(err)) => err.to_string(),
+            Err(_) => "timed out".to_owned(),
+        };
+        warn!(
+            "Could not open WebSocket to {}, sending packets in POST requests for {:?}: {}",
+            url, UPGRADE_RETRY_INTERVAL, error
+        );
+        *state = PeerState::Refused(Instant::now());
+        None
+    }
+}
+
+/// Gives a permit back to the semaphore once the packet holding it is answered
+struct PacketPermit(Arc<Semaphore>);
+
+impl Drop for PacketPermit {
+    fn drop(&mut self) {
+        self.0.add_permits(1);
+    }
+}
+
+/// Handles the packets a peer sends over its WebSocket until the peer closes it
+pub(crate) async fn serve_connection<A, I>(socket: WebSocket, account: A, incoming: I)
+where
+    A: Account + 'static,
+    I: IncomingService<A> + Clone + Send + 'static,
+{
+    let (mut sink, mut stream) = socket.split();
+    let (responses, mut outgoing) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(frame) = outgoing.recv().await {
+            if sink.send(frame).await.is_err() {
+                break;
+            }
+        }
+    });
+    let in_flight = Arc::new(Semaphore::new(MAX_CONCURRENT_PACKETS));
+
+    while let Some(Ok(message)) = stream.next().await {
+        if message.is_close() {
+            break;
+        }
+        if !message.is_binary() {
+            continue;
+        }
+        let (request_id, packet) = match decode_frame(message.as_bytes()) {
+            Some(frame) => frame,
+            None => {
+                warn!(
+                    "Got an invalid frame from account {}, closing the connection",
+                    account.id()
+                );
+                break;
+            }
+        };
+
+        let prepare = match Prepare::try_from(BytesMut::from(packet)) {
+            Ok(prepare) => prepare,
+            Err(_) => {
+                debug!("Frame {} was not a valid Prepare packet", request_id);
+                let reject = RejectBuilder {
+                    code: ErrorCode::F00_BAD_REQUEST,
+                    message: b"Invalid Prepare packet",
+                    triggered_by: None,
+                    data: &[],
+                }
+                .build();
+                let frame = encode_frame(request_id, BytesMut::from(reject).as_ref());
+                responses.send(ws::Message::binary(frame)).ok();
+                continue;
+            }
+        };
+
+        // Stop reading frames while the peer has too many packets in flight
+        in_flight.acquire().await.forget();
+        let permit = PacketPermit(in_flight.clone());
+        let mut incoming = incoming.clone();
+        let account = account.clone();
+        let responses = responses.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            let response: BytesMut = match incoming
+                .handle_request(IncomingRequest {
+                    from: account,
+                    prepare,
+                })
+                .await
+            {
+                Ok(fulfill) => fulfill.into(),
+                Err(reject) => reject.into(),
+            };
+            // The peer is gone if the connection was closed
+            responses
+                .send(ws::Message::binary(encode_frame(request_id, &response)))
+                .ok();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_roundtrip() {
+        let frame = encode_frame(0x0102_0304, b"packet");
+        assert_eq!(&frame[..4], &[1, 2, 3, 4]);
+        assert_eq!(decode_frame(&frame), Some((0x0102_0304, &b"packet"[..])));
+        assert_eq!(decode_frame(&[1, 2, 3]), None);
+    }
+}
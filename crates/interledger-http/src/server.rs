@@ -1,17 +1,20 @@
 use super::HttpStore;
+use crate::multiplex::{serve_connection, REQUEST_ID_LENGTH};
 use bytes::{Bytes, BytesMut};
 use interledger_errors::ApiError;
 use interledger_packet::Prepare;
 use interledger_service::Username;
-use interledger_service::{IncomingRequest, IncomingService};
+use interledger_service::{Account, IncomingRequest, IncomingService};
 use secrecy::{ExposeSecret, SecretString};
 use std::convert::TryFrom;
 use std::net::SocketAddr;
-use tracing::error;
-use warp::{Filter, Rejection};
+use tracing::{debug, error};
+use warp::{ws::Ws, Filter, Rejection};
 
 /// Max message size that is allowed to transfer from a request or a message.
 pub const MAX_PACKET_SIZE: u64 = 40000;
+/// Max size of a multiplexed packet, with its request ID
+const MAX_FRAME_SIZE: usize = MAX_PACKET_SIZE as usize + REQUEST_ID_LENGTH;
 /// The offset after which the bearer token should be in an ILP over HTTP request
 /// e.g. in `token = "Bearer: MyAuthToken"`, `MyAuthToken` can be taken via token[BEARER_TOKEN_START..]
 pub const BEARER_TOKEN_START: usize = 7;
@@ -98,17 +101,43 @@ where
     }
 }
 
+/// Upgrades the connection to a WebSocket over which the account multiplexes its packets,
+/// if the account's credentials are valid
+async fn ilp_over_websocket<S, I>(
+    path_username: Username,
+    password: SecretString,
+    ws: Ws,
+    store: S,
+    incoming: I,
+) -> Result<impl warp::Reply, warp::Rejection>
+where
+    S: HttpStore,
+    S::Account: 'static,
+    I: IncomingService<S::Account> + Clone + Send + 'static,
+{
+    let account = get_account(store, &path_username, &password).await?;
+    debug!(
+        "Account {} opened a multiplexed ILP over HTTP connection",
+        account.id()
+    );
+    Ok(ws
+        .max_message_size(MAX_FRAME_SIZE)
+        .on_upgrade(move |socket| serve_connection(socket, account, incoming)))
+}
+
 impl<I, S> HttpServer<I, S>
 where
-    I: IncomingService<S::Account> + Clone + Send + Sync,
+    I: IncomingService<S::Account> + Clone + Send + Sync + 'static,
     S: HttpStore + Clone,
+    S::Account: 'static,
 {
     pub fn new(incoming: I, store: S) -> Self {
         HttpServer { incoming, store }
     }
 
     /// Returns a Warp filter which exposes per-account endpoints for [ILP over HTTP](https://interledger.org/rfcs/0035-ilp-over-http/).
-    /// The endpoint is /accounts/:username/ilp.
+    /// The endpoint is /accounts/:username/ilp. Packets are POSTed to it, or multiplexed
+    /// over a WebSocket opened to it with a GET request.
     pub fn as_filter(
         &self,
     ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
@@ -116,17 +145,26 @@ where
         let incoming = self.incoming.clone();
         let with_store = warp::any().map(move || store.clone());
         let with_incoming = warp::any().map(move || incoming.clone());
-        warp::post()
-            .and(warp::path("accounts"))
+        let account_ilp = warp::path("accounts")
             .and(warp::path::param::<Username>())
             .and(warp::path("ilp"))
             .and(warp::path::end())
-            .and(warp::header::<SecretString>("authorization"))
+            .and(warp::header::<SecretString>("authorization"));
+
+        let post = warp::post()
+            .and(account_ilp.clone())
             .and(warp::body::content_length_limit(MAX_PACKET_SIZE))
             .and(warp::body::bytes())
+            .and(with_store.clone())
+            .and(with_incoming.clone())
+            .and_then(ilp_over_http);
+        let multiplexed = warp::get()
+            .and(account_ilp)
+            .and(warp::ws())
             .and(with_store)
             .and(with_incoming)
-            .and_then(ilp_over_http)
+            .and_then(ilp_over_websocket);
+        post.or(multiplexed)
     }
 
     // Do we really need to bind self to static?
@@ -139,16 +177,17 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::multiplex::{decode_frame, encode_frame};
     use crate::HttpAccount;
     use async_trait::async_trait;
     use bytes::BytesMut;
     use http::Response;
     use interledger_errors::{default_rejection_handler, HttpStoreError};
-    use interledger_packet::{Address, ErrorCode, PrepareBuilder, RejectBuilder};
+    use interledger_packet::{Address, ErrorCode, PrepareBuilder, Reject, RejectBuilder};
     use interledger_service::{incoming_service_fn, Account};
     use once_cell::sync::Lazy;
     use secrecy::SecretString;
-    use std::convert::TryInto;
+    use std::convert::{TryFrom, TryInto};
     use std::str::FromStr;
     use std::time::SystemTime;
     use url::Url;
@@ -220,6 +259,44 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 200);
     }
 
+    #[tokio::test]
+    async fn multiplexes_over_websocket() {
+        let incoming = incoming_service_fn(|_request| {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: b"No other incoming handler!",
+                data: &[],
+                triggered_by: None,
+            }
+            .build())
+        });
+        let api = HttpServer::new(incoming, TestStore)
+            .as_filter()
+            .recover(default_rejection_handler);
+
+        let refused = warp::test::ws()
+            .path("/accounts/alice/ilp")
+            .header("Authorization", "Bearer wrong")
+            .handshake(api.clone())
+            .await;
+        assert!(refused.is_err());
+
+        let mut client = warp::test::ws()
+            .path("/accounts/alice/ilp")
+            .header("Authorization", format!("Bearer {}", AUTH_PASSWORD))
+            .handshake(api)
+            .await
+            .unwrap();
+        client
+            .send(warp::ws::Message::binary(encode_frame(7, &PREPARE_BYTES)))
+            .await;
+        let response = client.recv().await.unwrap();
+        let (request_id, packet) = decode_frame(response.as_bytes()).unwrap();
+        assert_eq!(request_id, 7);
+        let reject = Reject::try_from(BytesMut::from(packet)).unwrap();
+        assert_eq!(reject.code(), ErrorCode::F02_UNREACHABLE);
+    }
+
     #[derive(Debug, Clone)]
     struct TestAccount;
     impl Account for TestAccount {
@@ -0,0 +1,139 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// How the rates of several providers are combined into one rate per asset
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct RateAggregation {
+    /// Largest difference, as a fraction of the median of all the quotes, that a
+    /// provider's quote may have before it is dropped as an outlier.
+    /// Defaults to 0.05 (5%).
+    #[serde(default = "RateAggregation::default_max_deviation")]
+    pub max_deviation: f64,
+    /// Number of providers which must agree on the rate of an asset for it to be used.
+    /// Assets quoted by fewer providers keep their previous rate, which eventually goes stale.
+    /// Defaults to 1.
+    #[serde(default = "RateAggregation::default_min_sources")]
+    pub min_sources: usize,
+}
+
+impl Default for RateAggregation {
+    fn default() -> Self {
+        RateAggregation {
+            max_deviation: Self::default_max_deviation(),
+            min_sources: Self::default_min_sources(),
+        }
+    }
+}
+
+impl RateAggregation {
+    pub(crate) fn default_max_deviation() -> f64 {
+        0.05
+    }
+
+    pub(crate) fn default_min_sources() -> usize {
+        1
+    }
+
+    /// Combines the quotes of the providers into one rate per asset: the median of the quotes
+    /// left after dropping the outliers, for the assets which enough of the quotes agree on
+    pub fn aggregate(&self, quotes: &[HashMap<String, f64>]) -> HashMap<String, f64> {
+        let mut quotes_by_asset: HashMap<&str, Vec<f64>> = HashMap::new();
+        for provider_quotes in quotes {
+            for (asset_code, rate) in provider_quotes {
+                // A rate of 0, or one which is not a number, can only be an error of the provider
+                if rate.is_finite() && *rate > 0.0 {
+                    quotes_by_asset
+                        .entry(asset_code.as_str())
+                        .or_default()
+                        .push(*rate);
+                }
+            }
+        }
+
+        quotes_by_asset
+            .into_iter()
+            .filter_map(|(asset_code, asset_quotes)| {
+                self.aggregate_asset(asset_quotes)
+                    .map(|rate| (asset_code.to_string(), rate))
+            })
+            .collect()
+    }
+
+    fn aggregate_asset(&self, mut quotes: Vec<f64>) -> Option<f64> {
+        let min_sources = self.min_sources.max(1);
+        if quotes.len() < min_sources {
+            return None;
+        }
+        let center = median(&mut quotes);
+        let mut agreeing: Vec<f64> = quotes
+            .into_iter()
+            .filter(|rate| ((rate - center) / center).abs() <= self.max_deviation)
+            .collect();
+        if agreeing.len() < min_sources {
+            return None;
+        }
+        Some(median(&mut agreeing))
+    }
+}
+
+/// Median of a non-empty list of finite numbers
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let middle = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quotes(rates: &[f64]) -> Vec<HashMap<String, f64>> {
+        rates
+            .iter()
+            .map(|rate| {
+                let mut quotes = HashMap::new();
+                quotes.insert("XRP".to_string(), *rate);
+                quotes
+            })
+            .collect()
+    }
+
+    #[test]
+    fn takes_median_of_agreeing_quotes() {
+        let aggregation = RateAggregation {
+            max_deviation: 0.05,
+            min_sources: 2,
+        };
+        // The last quote is an outlier
+        let rates = aggregation.aggregate(&quotes(&[1.0, 1.02, 1.04, 10.0]));
+        assert_eq!(rates["XRP"], 1.02);
+
+        let rates = aggregation.aggregate(&quotes(&[1.0, 1.0625]));
+        assert_eq!(rates["XRP"], 1.03125);
+    }
+
+    #[test]
+    fn requires_min_sources() {
+        let aggregation = RateAggregation {
+            max_deviation: 0.05,
+            min_sources: 2,
+        };
+        assert!(aggregation.aggregate(&quotes(&[0.25])).is_empty());
+        // The two quotes are both too far from their median
+        assert!(aggregation.aggregate(&quotes(&[0.25, 0.375])).is_empty());
+        // Invalid quotes do not count
+        assert!(aggregation
+            .aggregate(&quotes(&[0.25, 0.0, std::f64::NAN]))
+            .is_empty());
+    }
+
+    #[test]
+    fn single_source_is_used_as_is() {
+        let rates = RateAggregation::default().aggregate(&quotes(&[0.25]));
+        assert_eq!(rates["XRP"], 0.25);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::mapping::RateMapping;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::HashMap, path::PathBuf};
+use tracing::error;
+
+/// A local JSON or YAML file of exchange rates. It is read again on every poll,
+/// so the changes made to it are used from the next poll on
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FileProvider {
+    /// Files with a `.yaml` or `.yml` extension are read as YAML, the others as JSON
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub mapping: RateMapping,
+}
+
+impl FileProvider {
+    fn is_yaml(&self) -> bool {
+        self.path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map_or(false, |extension| {
+                extension.eq_ignore_ascii_case("yaml") || extension.eq_ignore_ascii_case("yml")
+            })
+    }
+}
+
+pub async fn query_file(provider: &FileProvider) -> Result<HashMap<String, f64>, ()> {
+    let source = provider.path.display().to_string();
+    let contents = tokio::fs::read(&provider.path).await.map_err(|err| {
+        error!("Error reading exchange rates file {}: {:?}", source, err);
+    })?;
+
+    let document: Value = if provider.is_yaml() {
+        serde_yaml::from_slice(&contents).map_err(|err| {
+            error!(
+                "Exchange rates file {} is not valid YAML: {:?}",
+                source, err
+            );
+        })?
+    } else {
+        serde_json::from_slice(&contents).map_err(|err| {
+            error!(
+                "Exchange rates file {} is not valid JSON: {:?}",
+                source, err
+            );
+        })?
+    };
+
+    provider.mapping.extract(&source, &document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_json_and_yaml_files() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = dir.path().join("rates.json");
+        std::fs::write(&path, r#"{"EUR": 1.25, "XRP": "0.5"}"#).unwrap();
+        let provider = FileProvider {
+            path,
+            mapping: RateMapping::default(),
+        };
+        let rates = query_file(&provider).await.unwrap();
+        assert_eq!(rates["EUR"], 1.25);
+        assert_eq!(rates["XRP"], 0.5);
+
+        // The file is read again with its new contents
+        std::fs::write(&provider.path, r#"{"EUR": 1.5}"#).unwrap();
+        let rates = query_file(&provider).await.unwrap();
+        assert_eq!(rates["EUR"], 1.5);
+        assert!(!rates.contains_key("XRP"));
+
+        let path = dir.path().join("rates.yaml");
+        std::fs::write(&path, "rates:\n  EUR: 1.25\n").unwrap();
+        let provider = FileProvider {
+            path,
+            mapping: RateMapping {
+                rates_path: Some("rates".to_string()),
+                ..Default::default()
+            },
+        };
+        let rates = query_file(&provider).await.unwrap();
+        assert_eq!(rates["EUR"], 1.25);
+    }
+}
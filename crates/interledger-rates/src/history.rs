@@ -0,0 +1,82 @@
+use async_trait::async_trait;
+use interledger_errors::ExchangeRateStoreError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How long the rates are kept in the history by default, in milliseconds (90 days)
+pub const DEFAULT_RATE_HISTORY_RETENTION: u64 = 90 * 24 * 60 * 60 * 1000;
+
+/// The rate of an asset at a point in time
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RateSnapshot {
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Price of the asset in USD
+    pub rate: f64,
+}
+
+/// Keeps the rates the node used over time, so that past packets and
+/// settlements can be repriced or audited with the rates in effect back then.
+///
+/// A rate is only recorded when it changes, so each rate stays in effect until
+/// the next one recorded for its asset. The stores remove the rates older than
+/// their retention, except for the last one before it, which was still in effect
+#[async_trait]
+pub trait ExchangeRateHistoryStore {
+    /// Adds the rates to the history of their assets, as of `timestamp`
+    /// (milliseconds since the Unix epoch), and removes the ones which are
+    /// past the retention. A rate recorded again at the same time replaces
+    /// the previous one
+    async fn record_exchange_rates(
+        &self,
+        timestamp: u64,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError>;
+
+    /// Returns at most `limit` of the asset's rates recorded at or after `from`
+    /// and before `to`, oldest first
+    async fn get_exchange_rate_history(
+        &self,
+        asset_code: &str,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<RateSnapshot>, ExchangeRateStoreError>;
+}
+
+/// The rates which differ from the ones currently in use, which are the ones
+/// to add to the history
+pub fn changed_rates(
+    current: &HashMap<String, f64>,
+    rates: &HashMap<String, f64>,
+) -> HashMap<String, f64> {
+    rates
+        .iter()
+        .filter(|(asset_code, rate)| current.get(*asset_code) != Some(*rate))
+        .map(|(asset_code, rate)| (asset_code.clone(), *rate))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_keeps_the_rates_which_changed() {
+        let current: HashMap<String, f64> =
+            vec![("ABC".to_string(), 1.0), ("XYZ".to_string(), 2.0)]
+                .into_iter()
+                .collect();
+        let rates: HashMap<String, f64> = vec![
+            ("ABC".to_string(), 1.0),
+            ("XYZ".to_string(), 3.0),
+            ("NEW".to_string(), 4.0),
+        ]
+        .into_iter()
+        .collect();
+        let changed = changed_rates(&current, &rates);
+        assert_eq!(changed.len(), 2);
+        assert_eq!(changed["XYZ"], 3.0);
+        assert_eq!(changed["NEW"], 4.0);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::mapping::RateMapping;
+use futures::TryFutureExt;
+use reqwest::Client;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use tracing::error;
+
+/// A JSON API serving exchange rates, such as an internal pricing service
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpProvider {
+    pub url: String,
+    /// Value of the `Authorization` header sent with the requests, such as `Bearer <token>`
+    #[serde(default)]
+    pub auth_header: Option<SecretString>,
+    #[serde(flatten)]
+    pub mapping: RateMapping,
+}
+
+impl PartialEq for HttpProvider {
+    fn eq(&self, other: &Self) -> bool {
+        self.url == other.url
+            && self.mapping == other.mapping
+            && self
+                .auth_header
+                .as_ref()
+                .map(|header| header.expose_secret())
+                == other
+                    .auth_header
+                    .as_ref()
+                    .map(|header| header.expose_secret())
+    }
+}
+
+pub async fn query_http(
+    client: &Client,
+    provider: &HttpProvider,
+) -> Result<HashMap<String, f64>, ()> {
+    let mut request = client.get(provider.url.as_str());
+    if let Some(ref auth_header) = provider.auth_header {
+        request = request.header("Authorization", auth_header.expose_secret().as_str());
+    }
+    let res = request
+        .send()
+        .map_err(|err| {
+            error!(
+                "Error fetching exchange rates from {}: {:?}",
+                provider.url, err
+            );
+        })
+        .await?;
+
+    let res = res.error_for_status().map_err(|err| {
+        error!(
+            "HTTP error getting exchange rates from {}: {:?}",
+            provider.url, err
+        );
+    })?;
+
+    let document: Value = res
+        .json()
+        .map_err(|err| {
+            error!(
+                "Error getting exchange rate response body from {}, invalid JSON: {:?}",
+                provider.url, err
+            );
+        })
+        .await?;
+
+    provider.mapping.extract(&provider.url, &document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::mock;
+
+    #[tokio::test]
+    async fn queries_rates() {
+        let m = mock("GET", "/prices")
+            .match_header("Authorization", "Bearer token")
+            .with_body(r#"{"data":{"EUR":{"price":"1.25"},"GBP":{"price":1.5}}}"#)
+            .create();
+        let mut fields = HashMap::new();
+        fields.insert("EUR".to_string(), "data.EUR.price".to_string());
+        fields.insert("GBP".to_string(), "data.GBP.price".to_string());
+        let provider = HttpProvider {
+            url: format!("{}/prices", mockito::server_url()),
+            auth_header: Some(SecretString::new("Bearer token".to_string())),
+            mapping: RateMapping {
+                fields,
+                ..Default::default()
+            },
+        };
+
+        let rates = query_http(&Client::new(), &provider).await.unwrap();
+        m.assert();
+        assert_eq!(rates.len(), 3);
+        assert_eq!(rates["EUR"], 1.25);
+        assert_eq!(rates["GBP"], 1.5);
+        assert_eq!(rates["USD"], 1.0);
+    }
+
+    #[tokio::test]
+    async fn fails_on_http_errors() {
+        let m = mock("GET", "/unavailable").with_status(503).create();
+        let provider = HttpProvider {
+            url: format!("{}/unavailable", mockito::server_url()),
+            auth_header: None,
+            mapping: RateMapping::default(),
+        };
+
+        assert!(query_http(&Client::new(), &provider).await.is_err());
+        m.assert();
+    }
+}
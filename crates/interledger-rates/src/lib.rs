@@ -1,4 +1,3 @@
-use futures::TryFutureExt;
 use interledger_errors::ExchangeRateStoreError;
 use reqwest::Client;
 use secrecy::SecretString;
@@ -6,13 +5,30 @@ use serde::Deserialize;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tracing::{debug, error, trace, warn};
 
 mod cryptocompare;
 
 mod coincap;
 
+mod http;
+
+mod file;
+
+mod mapping;
+
+mod aggregation;
+
+mod history;
+pub use aggregation::RateAggregation;
+pub use file::FileProvider;
+pub use history::{
+    changed_rates, ExchangeRateHistoryStore, RateSnapshot, DEFAULT_RATE_HISTORY_RETENTION,
+};
+pub use http::HttpProvider;
+pub use mapping::RateMapping;
+
 pub trait ExchangeRateStore: Clone {
     // TODO we may want to make this async if/when we use pubsub to broadcast
     // rate changes to different instances of a horizontally-scalable node
@@ -28,6 +44,25 @@ pub trait ExchangeRateStore: Clone {
     // but in the normal case of getting the rate between two assets, we don't want to
     // copy all the rate data
     fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError>;
+
+    /// Replaces the rates like `set_exchange_rates`, also saving when each of them was
+    /// fetched, in milliseconds since the Unix epoch. Rates without a fetch time never go stale
+    fn set_exchange_rates_fetched_at(
+        &self,
+        rates: HashMap<String, f64>,
+        _fetched_at: HashMap<String, u64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        self.set_exchange_rates(rates)
+    }
+
+    /// Returns when the rates of the assets were fetched, or `None` for the rates
+    /// which were set without a fetch time (such as through the HTTP API)
+    fn get_exchange_rates_fetched_at(
+        &self,
+        asset_codes: &[&str],
+    ) -> Result<Vec<Option<u64>>, ExchangeRateStoreError> {
+        Ok(vec![None; asset_codes.len()])
+    }
 }
 
 /// This determines which external API service to poll for exchange rates.
@@ -51,6 +86,17 @@ pub enum ExchangeRateProvider {
     /// [CryptoCompare]: https://cryptocompare.com
     #[serde(alias = "cryptocompare")]
     CryptoCompare(SecretString),
+    /// Use any JSON API, such as an internal pricing service, reading the rates
+    /// at the configured paths of its responses.
+    ///
+    /// Note that when configured with YAML, this MUST be specified as "Http".
+    #[serde(alias = "http")]
+    Http(HttpProvider),
+    /// Use a local JSON or YAML file of rates, which is read again on every poll.
+    ///
+    /// Note that when configured with YAML, this MUST be specified as "File".
+    #[serde(alias = "file")]
+    File(FileProvider),
 }
 
 impl PartialEq<ExchangeRateProvider> for ExchangeRateProvider {
@@ -65,6 +111,8 @@ impl PartialEq<ExchangeRateProvider> for ExchangeRateProvider {
             {
                 true
             }
+            (ExchangeRateProvider::Http(l), ExchangeRateProvider::Http(r)) => l == r,
+            (ExchangeRateProvider::File(l), ExchangeRateProvider::File(r)) => l == r,
             _ => false,
         }
     }
@@ -73,7 +121,8 @@ impl PartialEq<ExchangeRateProvider> for ExchangeRateProvider {
 /// Poll exchange rate providers for the current exchange rates
 #[derive(Clone)]
 pub struct ExchangeRateFetcher<S> {
-    provider: ExchangeRateProvider,
+    providers: Vec<ExchangeRateProvider>,
+    aggregation: RateAggregation,
     consecutive_failed_polls: Arc<AtomicU32>,
     failed_polls_before_invalidation: u32,
     store: S,
@@ -82,16 +131,32 @@ pub struct ExchangeRateFetcher<S> {
 
 impl<S> ExchangeRateFetcher<S>
 where
-    S: ExchangeRateStore + Send + Sync + 'static,
+    S: ExchangeRateStore + ExchangeRateHistoryStore + Send + Sync + 'static,
 {
     /// Simple constructor
     pub fn new(
         provider: ExchangeRateProvider,
         failed_polls_before_invalidation: u32,
         store: S,
+    ) -> Self {
+        Self::with_providers(
+            vec![provider],
+            RateAggregation::default(),
+            failed_polls_before_invalidation,
+            store,
+        )
+    }
+
+    /// Polls all of the providers and combines their rates as configured by `aggregation`
+    pub fn with_providers(
+        providers: Vec<ExchangeRateProvider>,
+        aggregation: RateAggregation,
+        failed_polls_before_invalidation: u32,
+        store: S,
     ) -> Self {
         ExchangeRateFetcher {
-            provider,
+            providers,
+            aggregation,
             consecutive_failed_polls: Arc::new(AtomicU32::new(0)),
             failed_polls_before_invalidation,
             store,
@@ -102,8 +167,8 @@ where
     /// Spawns a future which calls [`self.update_rates()`](./struct.ExchangeRateFetcher.html#method.update_rates) every `interval`
     pub fn spawn_interval(self, interval: Duration) {
         debug!(
-            "Starting interval to poll exchange rate provider: {:?} for rates",
-            self.provider
+            "Starting interval to poll exchange rate providers: {:?} for rates",
+            self.providers
         );
         let interval = async move {
             let mut interval = tokio::time::interval(interval);
@@ -117,52 +182,151 @@ where
     }
 
     /// Calls the proper exchange rate provider
-    async fn fetch_rates(&self) -> Result<HashMap<String, f64>, ()> {
-        match self.provider {
+    async fn fetch_rates(
+        &self,
+        provider: &ExchangeRateProvider,
+    ) -> Result<HashMap<String, f64>, ()> {
+        match provider {
             ExchangeRateProvider::CryptoCompare(ref api_key) => {
                 cryptocompare::query_cryptocompare(&self.client, api_key).await
             }
             ExchangeRateProvider::CoinCap => coincap::query_coincap(&self.client).await,
+            ExchangeRateProvider::Http(ref provider) => {
+                http::query_http(&self.client, provider).await
+            }
+            ExchangeRateProvider::File(ref provider) => file::query_file(provider).await,
         }
     }
 
-    /// Gets the exchange rates and proceeds to update the store with the newly polled values
-    async fn update_rates(&self) -> Result<(), ()> {
-        let consecutive_failed_polls = self.consecutive_failed_polls.clone();
-        let consecutive_failed_polls_zeroer = consecutive_failed_polls.clone();
-        let failed_polls_before_invalidation = self.failed_polls_before_invalidation;
-        let store = self.store.clone();
-        let store_clone = self.store.clone();
-        let provider = self.provider.clone();
-        #[allow(clippy::cognitive_complexity)]
-        let mut rates = self.fetch_rates()
-            .map_err(move |_| {
-                // Note that a race between the read on this line and the check on the line after
-                // is quite unlikely as long as the interval between polls is reasonable.
-                let failed_polls = consecutive_failed_polls.fetch_add(1, Ordering::Relaxed);
-                if failed_polls < failed_polls_before_invalidation {
-                    warn!("Failed to update exchange rates (previous consecutive failed attempts: {})", failed_polls);
-                } else {
-                    error!("Failed to update exchange rates (previous consecutive failed attempts: {}), removing old rates for safety", failed_polls);
-                    // Clear out all of the old rates
-                    if store.set_exchange_rates(HashMap::new()).is_err() {
-                        error!("Failed to clear exchange rates cache after exchange rates server became unresponsive; panicking");
-                        panic!("Failed to clear exchange rates cache after exchange rates server became unresponsive");
-                    }
+    /// Polls all of the providers at once and combines the rates of the ones which answered
+    async fn fetch_aggregated_rates(&self) -> Result<HashMap<String, f64>, ()> {
+        let results = futures::future::join_all(
+            self.providers
+                .iter()
+                .map(|provider| self.fetch_rates(provider)),
+        )
+        .await;
+        let quotes: Vec<HashMap<String, f64>> = results
+            .into_iter()
+            .zip(self.providers.iter())
+            .filter_map(|(result, provider)| match result {
+                Ok(rates) => Some(rates),
+                Err(_) => {
+                    warn!("Exchange rate provider {:?} did not return rates", provider);
+                    None
                 }
-            }).await?;
-
-        trace!("Fetched exchange rates: {:?}", rates);
-        let num_rates = rates.len();
-        rates.insert("USD".to_string(), 1.0);
-        if store_clone.set_exchange_rates(rates).is_ok() {
-            // Reset our invalidation counter
-            consecutive_failed_polls_zeroer.store(0, Ordering::Relaxed);
-            debug!("Updated {} exchange rates from {:?}", num_rates, provider);
-            Ok(())
+            })
+            .collect();
+        if quotes.is_empty() {
+            return Err(());
+        }
+
+        let rates = self.aggregation.aggregate(&quotes);
+        if rates.is_empty() {
+            warn!(
+                "Exchange rate providers do not agree on the rate of any asset ({} of {} answered)",
+                quotes.len(),
+                self.providers.len()
+            );
+            return Err(());
+        }
+        Ok(rates)
+    }
+
+    /// Counts a failed poll, and removes all of the rates once too many polls failed in a row
+    fn poll_failed(&self) {
+        // Note that a race between the read on this line and the check on the line after
+        // is quite unlikely as long as the interval between polls is reasonable.
+        let failed_polls = self
+            .consecutive_failed_polls
+            .fetch_add(1, Ordering::Relaxed);
+        if failed_polls < self.failed_polls_before_invalidation {
+            warn!(
+                "Failed to update exchange rates (previous consecutive failed attempts: {})",
+                failed_polls
+            );
         } else {
+            error!("Failed to update exchange rates (previous consecutive failed attempts: {}), removing old rates for safety", failed_polls);
+            // Clear out all of the old rates
+            if self.store.set_exchange_rates(HashMap::new()).is_err() {
+                error!("Failed to clear exchange rates cache after exchange rates server became unresponsive; panicking");
+                panic!("Failed to clear exchange rates cache after exchange rates server became unresponsive");
+            }
+        }
+    }
+
+    /// Gets the exchange rates and proceeds to update the store with the newly polled values.
+    /// The assets the providers did not agree on keep their previous rate and fetch time.
+    /// Only the newly polled rates which changed are added to the history of the rates
+    async fn update_rates(&self) -> Result<(), ()> {
+        let fetched_rates = match self.fetch_aggregated_rates().await {
+            Ok(rates) => rates,
+            Err(()) => {
+                self.poll_failed();
+                return Err(());
+            }
+        };
+
+        trace!("Fetched exchange rates: {:?}", fetched_rates);
+        let num_rates = fetched_rates.len();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_millis() as u64)
+            .unwrap_or_default();
+
+        // Keep the rates which were not fetched this time, along with their previous fetch time
+        let mut rates = self.store.get_all_exchange_rates().map_err(|_| {
+            error!("Error getting exchange rates from store");
+        })?;
+        let previous_assets: Vec<&str> = rates.keys().map(String::as_str).collect();
+        let previous_fetched_at = self
+            .store
+            .get_exchange_rates_fetched_at(&previous_assets)
+            .map_err(|_| {
+                error!("Error getting exchange rates fetch times from store");
+            })?;
+        let mut fetched_at: HashMap<String, u64> = previous_assets
+            .iter()
+            .zip(previous_fetched_at)
+            .filter_map(|(asset_code, fetched_at)| {
+                fetched_at.map(|fetched_at| (asset_code.to_string(), fetched_at))
+            })
+            .collect();
+        let fetched_rates: HashMap<String, f64> = fetched_rates
+            .into_iter()
+            .chain(std::iter::once(("USD".to_string(), 1.0)))
+            .collect();
+
+        // Recorded first, so that the rates are never used without being in the history.
+        // Rates which could not be recorded are not used, like rates which could not be fetched
+        if let Err(err) = self
+            .store
+            .record_exchange_rates(now, changed_rates(&rates, &fetched_rates))
+            .await
+        {
+            error!("Error adding exchange rates to their history: {}", err);
+            self.poll_failed();
+            return Err(());
+        }
+
+        for (asset_code, rate) in fetched_rates.iter() {
+            fetched_at.insert(asset_code.clone(), now);
+            rates.insert(asset_code.clone(), *rate);
+        }
+        if self
+            .store
+            .set_exchange_rates_fetched_at(rates, fetched_at)
+            .is_err()
+        {
             error!("Error setting exchange rates in store");
-            Err(())
+            return Err(());
         }
+        // Reset our invalidation counter
+        self.consecutive_failed_polls.store(0, Ordering::Relaxed);
+        debug!(
+            "Updated {} exchange rates from {:?}",
+            num_rates, self.providers
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,192 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::HashMap, str::FromStr};
+use tracing::{error, warn};
+
+/// Where the rates are in a JSON (or YAML) document, and how they are expressed
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RateMapping {
+    /// Path of an object mapping asset codes to rates, such as `data.rates`.
+    /// Defaults to the whole document if no `fields` are set.
+    #[serde(default)]
+    pub rates_path: Option<String>,
+    /// Paths of the rates of individual assets, such as `EUR: "data[0].price"`
+    #[serde(default)]
+    pub fields: HashMap<String, String>,
+    /// Asset the rates are expressed in. Defaults to USD
+    #[serde(default = "RateMapping::default_base_asset")]
+    pub base_asset: String,
+    /// If true, the rates are the amounts of each asset one unit of the base asset is worth
+    /// (as with most foreign exchange APIs), instead of the prices of the assets in the base asset
+    #[serde(default)]
+    pub inverted: bool,
+}
+
+impl Default for RateMapping {
+    fn default() -> Self {
+        RateMapping {
+            rates_path: None,
+            fields: HashMap::new(),
+            base_asset: Self::default_base_asset(),
+            inverted: false,
+        }
+    }
+}
+
+impl RateMapping {
+    fn default_base_asset() -> String {
+        "USD".to_string()
+    }
+
+    /// Extracts the rates from the document, as prices in USD
+    pub(crate) fn extract(
+        &self,
+        source: &str,
+        document: &Value,
+    ) -> Result<HashMap<String, f64>, ()> {
+        let mut rates = HashMap::new();
+        let rates_path = match self.rates_path {
+            Some(ref path) => Some(path.as_str()),
+            None if self.fields.is_empty() => Some(""),
+            None => None,
+        };
+        if let Some(path) = rates_path {
+            let object = select(document, path)
+                .and_then(Value::as_object)
+                .ok_or_else(|| {
+                    error!(
+                        "No object of rates at \"{}\" in the rates from {}",
+                        path, source
+                    );
+                })?;
+            for (asset_code, value) in object {
+                if let Some(rate) = as_rate(source, asset_code, value) {
+                    rates.insert(asset_code.to_uppercase(), rate);
+                }
+            }
+        }
+        for (asset_code, path) in &self.fields {
+            match select(document, path) {
+                Some(value) => {
+                    if let Some(rate) = as_rate(source, asset_code, value) {
+                        rates.insert(asset_code.to_uppercase(), rate);
+                    }
+                }
+                None => warn!(
+                    "No {} rate at \"{}\" in the rates from {}",
+                    asset_code, path, source
+                ),
+            }
+        }
+
+        self.to_usd_prices(source, rates)
+    }
+
+    /// Converts the rates, as configured, to the prices of the assets in USD
+    fn to_usd_prices(
+        &self,
+        source: &str,
+        rates: HashMap<String, f64>,
+    ) -> Result<HashMap<String, f64>, ()> {
+        let base_asset = self.base_asset.to_uppercase();
+        let mut prices: HashMap<String, f64> = rates
+            .into_iter()
+            .map(|(asset_code, rate)| {
+                let price = if self.inverted { 1.0 / rate } else { rate };
+                (asset_code, price)
+            })
+            .collect();
+        prices.insert(base_asset.clone(), 1.0);
+        if base_asset == "USD" {
+            return Ok(prices);
+        }
+
+        let usd_price = *prices.get("USD").ok_or_else(|| {
+            error!(
+                "The rates from {} are in {} and do not include USD, so they cannot be converted to USD",
+                source, base_asset
+            );
+        })?;
+        Ok(prices
+            .into_iter()
+            .map(|(asset_code, price)| (asset_code, price / usd_price))
+            .collect())
+    }
+}
+
+/// Finds the value at a path such as `$.data.rates[0].price`: keys separated by dots,
+/// each one optionally followed by array indexes. An empty path (or `$`) is the whole document
+pub(crate) fn select<'a>(document: &'a Value, path: &str) -> Option<&'a Value> {
+    let path = path.trim_start_matches('$');
+    let mut value = document;
+    for segment in path.split('.').filter(|segment| !segment.is_empty()) {
+        let (key, indexes) = match segment.find('[') {
+            Some(start) => segment.split_at(start),
+            None => (segment, ""),
+        };
+        if !key.is_empty() {
+            value = value.get(key)?;
+        }
+        for index in indexes.split(']').filter(|index| !index.is_empty()) {
+            let index = usize::from_str(index.trim_start_matches('[')).ok()?;
+            value = value.get(index)?;
+        }
+    }
+    Some(value)
+}
+
+/// Rates are either numbers or strings of numbers. Only positive rates are valid
+fn as_rate(source: &str, asset_code: &str, value: &Value) -> Option<f64> {
+    let rate = match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(string) => f64::from_str(string).ok(),
+        _ => None,
+    };
+    match rate {
+        Some(rate) if rate.is_finite() && rate > 0.0 => Some(rate),
+        _ => {
+            warn!("Invalid {} rate from {}: {}", asset_code, source, value);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn selects_paths() {
+        let document = json!({ "data": { "items": [{ "price": 1.5 }, { "price": "2" }] } });
+        assert_eq!(select(&document, "data.items[1].price"), Some(&json!("2")));
+        assert_eq!(
+            select(&document, "$.data.items[0]"),
+            Some(&json!({ "price": 1.5 }))
+        );
+        assert_eq!(select(&document, "$"), Some(&document));
+        assert_eq!(select(&document, "data.items[2].price"), None);
+        assert_eq!(select(&document, "data.missing"), None);
+    }
+
+    #[test]
+    fn converts_rates_to_usd() {
+        let document = json!({ "rates": { "usd": 1.25, "GBP": "0.5", "JPY": 0 } });
+        let mapping = RateMapping {
+            rates_path: Some("rates".to_string()),
+            base_asset: "EUR".to_string(),
+            inverted: true,
+            ..Default::default()
+        };
+        let prices = mapping.extract("test", &document).unwrap();
+        // 1 EUR is 1.25 USD, and 1 GBP is 2 EUR
+        assert_eq!(prices["USD"], 1.0);
+        assert_eq!(prices["EUR"], 1.25);
+        assert_eq!(prices["GBP"], 2.5);
+        assert!(!prices.contains_key("JPY"));
+
+        // The base asset must be converted to USD
+        let document = json!({ "rates": { "GBP": 0.5 } });
+        assert!(mapping.extract("test", &document).is_err());
+    }
+}
@@ -6,6 +6,8 @@
 //!
 //! A routing table could be as simple as a single entry for the empty prefix
 //! ("") that will route all requests to a specific outgoing account.
+//! Each prefix can also have several next hops, which are used for failover
+//! and load-balancing (see `Route`).
 //!
 //! Note that the Router is not responsible for building the routing table,
 //! only using the information provided by the store. The routing table in the
@@ -13,12 +15,15 @@
 //! (see the `interledger-ccp` crate for more details).
 
 use interledger_service::AccountStore;
-use std::{collections::HashMap, sync::Arc};
-use uuid::Uuid;
+use std::sync::Arc;
 
+mod route;
 mod router;
+mod routing_table;
 
+pub use self::route::{NextHop, ParseRouteError, Route};
 pub use self::router::Router;
+pub use self::routing_table::{Iter, RoutingTable};
 
 /// A trait for Store implmentations that have ILP routing tables.
 pub trait RouterStore: AccountStore + Clone + Send + Sync + 'static {
@@ -27,5 +32,5 @@ pub trait RouterStore: AccountStore + Clone + Send + Sync + 'static {
     /// keep the routing table in memory and use PubSub or polling to keep it updated.
     /// This ensures that individual packets can be routed without hitting the underlying store.
     /// An Arc is returned to avoid copying the underlying data while processing each packet.
-    fn routing_table(&self) -> Arc<HashMap<String, Uuid>>;
+    fn routing_table(&self) -> Arc<RoutingTable<Route>>;
 }
@@ -0,0 +1,207 @@
+use rand::Rng;
+use std::{error::Error, fmt, str::FromStr};
+use uuid::Uuid;
+
+/// One of the accounts which a route forwards packets to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NextHop {
+    pub account_id: Uuid,
+    /// Relative share of the packets which are sent to this account first.
+    /// Only used if at least one of the route's next hops has a weight.
+    pub weight: Option<u32>,
+}
+
+impl From<Uuid> for NextHop {
+    fn from(account_id: Uuid) -> Self {
+        NextHop {
+            account_id,
+            weight: None,
+        }
+    }
+}
+
+/// The next hops for a prefix in the routing table.
+///
+/// If none of the next hops has a weight, packets are sent to the first one and
+/// the others are used, in order, when it is unreachable. If some of them have
+/// weights, the first attempt is picked at random in proportion to the weights
+/// (hops without a weight count as 0) and the remaining hops are tried in order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Route {
+    next_hops: Vec<NextHop>,
+}
+
+impl Route {
+    pub fn new(next_hops: Vec<NextHop>) -> Self {
+        Route { next_hops }
+    }
+
+    /// The next hops in the order in which they were configured
+    pub fn next_hops(&self) -> &[NextHop] {
+        &self.next_hops
+    }
+
+    pub fn account_ids(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.next_hops.iter().map(|hop| hop.account_id)
+    }
+
+    /// Returns the accounts in the order in which a packet should be sent to them
+    pub fn attempt_order<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<Uuid> {
+        let mut order: Vec<Uuid> = self.account_ids().collect();
+        let total: u64 = self
+            .next_hops
+            .iter()
+            .map(|hop| u64::from(hop.weight.unwrap_or(0)))
+            .sum();
+        if total > 0 {
+            let mut pick = rng.gen_range(0, total);
+            for (i, hop) in self.next_hops.iter().enumerate() {
+                let weight = u64::from(hop.weight.unwrap_or(0));
+                if pick < weight {
+                    let first = order.remove(i);
+                    order.insert(0, first);
+                    break;
+                }
+                pick -= weight;
+            }
+        }
+        order
+    }
+}
+
+impl From<Uuid> for Route {
+    fn from(account_id: Uuid) -> Self {
+        Route::new(vec![NextHop::from(account_id)])
+    }
+}
+
+/// Formats the route as a comma-separated list of account IDs, each optionally
+/// followed by `:<weight>`. A route to a single account is just its ID.
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, hop) in self.next_hops.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", hop.account_id.to_hyphenated())?;
+            if let Some(weight) = hop.weight {
+                write!(f, ":{}", weight)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Route {
+    type Err = ParseRouteError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let next_hops = src
+            .split(',')
+            .map(|hop| {
+                let mut parts = hop.splitn(2, ':');
+                let account_id = parts
+                    .next()
+                    .and_then(|id| Uuid::from_str(id).ok())
+                    .ok_or_else(|| ParseRouteError(src.to_string()))?;
+                let weight = match parts.next() {
+                    Some(weight) => Some(
+                        weight
+                            .parse()
+                            .map_err(|_| ParseRouteError(src.to_string()))?,
+                    ),
+                    None => None,
+                };
+                Ok(NextHop { account_id, weight })
+            })
+            .collect::<Result<Vec<NextHop>, ParseRouteError>>()?;
+        Ok(Route::new(next_hops))
+    }
+}
+
+/// Error returned when a string is not a valid route
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseRouteError(String);
+
+impl fmt::Display for ParseRouteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid route: \"{}\"", self.0)
+    }
+}
+
+impl Error for ParseRouteError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn ids() -> (Uuid, Uuid, Uuid) {
+        (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4())
+    }
+
+    #[test]
+    fn unweighted_routes_keep_their_order() {
+        let (a, b, c) = ids();
+        let route = Route::new(vec![a.into(), b.into(), c.into()]);
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10 {
+            assert_eq!(route.attempt_order(&mut rng), vec![a, b, c]);
+        }
+    }
+
+    #[test]
+    fn weighted_routes_pick_the_first_attempt_by_weight() {
+        let (a, b, c) = ids();
+        let route = Route::new(vec![
+            NextHop {
+                account_id: a,
+                weight: Some(1),
+            },
+            NextHop {
+                account_id: b,
+                weight: Some(3),
+            },
+            // Only used for failover
+            c.into(),
+        ]);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut firsts = (0, 0);
+        for _ in 0..1000 {
+            match route.attempt_order(&mut rng).as_slice() {
+                [first, second, third] if *first == a => {
+                    assert_eq!((*second, *third), (b, c));
+                    firsts.0 += 1;
+                }
+                [first, second, third] if *first == b => {
+                    assert_eq!((*second, *third), (a, c));
+                    firsts.1 += 1;
+                }
+                order => panic!("unexpected order: {:?}", order),
+            }
+        }
+        assert!(firsts.0 > 150 && firsts.0 < 350, "{:?}", firsts);
+    }
+
+    #[test]
+    fn parses_and_formats_routes() {
+        let (a, b, _) = ids();
+        let single = Route::from(a);
+        assert_eq!(single.to_string(), a.to_string());
+        assert_eq!(Route::from_str(&a.to_string()).unwrap(), single);
+
+        let multi = Route::new(vec![
+            NextHop {
+                account_id: a,
+                weight: Some(2),
+            },
+            b.into(),
+        ]);
+        assert_eq!(multi.to_string(), format!("{}:2,{}", a, b));
+        assert_eq!(Route::from_str(&multi.to_string()).unwrap(), multi);
+
+        assert!(Route::from_str("").is_err());
+        assert!(Route::from_str(&format!("{}:x", a)).is_err());
+        assert!(Route::from_str(&format!("{},", a)).is_err());
+    }
+}
@@ -1,9 +1,9 @@
 use super::RouterStore;
 use async_trait::async_trait;
-use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_packet::{Address, ErrorCode, Reject, RejectBuilder};
 use interledger_service::*;
-use std::str;
-use tracing::{error, trace};
+use rand::thread_rng;
+use tracing::{debug, error, trace};
 
 /// # Interledger Router
 ///
@@ -46,68 +46,39 @@ where
 {
     /// Figures out the next node to pass the received Prepare packet to.
     ///
-    /// Firstly, it checks if there is a direct path for that account and uses that.
-    /// If not it scans through the routing table and checks if the route prefix matches
-    /// the prepare packet's destination or if it's a catch-all address (i.e. empty prefix)
+    /// The routing table finds the longest prefix which matches the prepare packet's
+    /// destination, or the catch-all route (i.e. empty prefix) if there is one.
+    /// If the route has several next hops, the packet is sent to the next one
+    /// whenever it could not be sent to the previous one at all (see `never_left_node`).
+    /// Any other rejection is returned to the sender, since the previous next hop may
+    /// have the packet.
     async fn handle_request(&mut self, request: IncomingRequest<S::Account>) -> IlpResult {
         let destination = request.prepare.destination();
-        let mut next_hop = None;
         let routing_table = self.store.routing_table();
         let ilp_address = self.store.get_ilp_address();
 
-        // Check if we have a direct path for that account or if we need to scan
-        // through the routing table
-        let dest: &str = &destination;
-        if let Some(account_id) = routing_table.get(dest) {
+        let mut next_hops = Vec::new();
+        if let Some((prefix, route)) = routing_table.longest_match(&destination) {
             trace!(
-                "Found direct route for address: \"{}\". Account: {}",
+                "Found matching route for address: \"{}\". Prefix: \"{}\", next hops: {}",
                 destination,
-                account_id
+                prefix,
+                route,
             );
-            next_hop = Some(*account_id);
-        } else if !routing_table.is_empty() {
-            let mut matching_prefix = "";
-            let routing_table = self.store.routing_table();
-            for (ref prefix, account) in (*routing_table).iter() {
-                // Check if the route prefix matches or is empty (meaning it's a catch-all address)
-                if (prefix.is_empty() || dest.starts_with(prefix.as_str()))
-                    && prefix.len() >= matching_prefix.len()
-                {
-                    next_hop.replace(*account);
-                    matching_prefix = prefix.as_str();
-                }
-            }
-            if let Some(account_id) = next_hop {
-                trace!(
-                    "Found matching route for address: \"{}\". Prefix: \"{}\", account: {}",
-                    destination,
-                    matching_prefix,
-                    account_id,
-                );
-            }
-        } else {
+            next_hops = route.attempt_order(&mut thread_rng());
+        } else if routing_table.is_empty() {
             error!("Unable to route request because routing table is empty");
         }
 
-        if let Some(account_id) = next_hop {
-            let mut next = self.next.clone();
-            match self.store.get_accounts(vec![account_id]).await {
-                Ok(mut accounts) => {
-                    let request = request.into_outgoing(accounts.remove(0));
-                    next.send_request(request).await
-                }
-                Err(_) => {
-                    error!("No record found for account: {}", account_id);
-                    Err(RejectBuilder {
-                        code: ErrorCode::F02_UNREACHABLE,
-                        message: &[],
-                        triggered_by: Some(&ilp_address),
-                        data: &[],
-                    }
-                    .build())
-                }
-            }
-        } else {
+        let unreachable = RejectBuilder {
+            code: ErrorCode::F02_UNREACHABLE,
+            message: &[],
+            triggered_by: Some(&ilp_address),
+            data: &[],
+        }
+        .build();
+
+        if next_hops.is_empty() {
             error!(
                 "No route found for request {}: {:?}",
                 {
@@ -126,22 +97,65 @@ where
                 },
                 request
             );
-            Err(RejectBuilder {
-                code: ErrorCode::F02_UNREACHABLE,
-                message: &[],
-                triggered_by: Some(&ilp_address),
-                data: &[],
+            return Err(unreachable);
+        }
+
+        let mut result = Err(unreachable);
+        let mut request = Some(request);
+        let mut next_hops = next_hops.into_iter().peekable();
+        while let Some(account_id) = next_hops.next() {
+            let account = match self.store.get_accounts(vec![account_id]).await {
+                Ok(mut accounts) => accounts.remove(0),
+                Err(_) => {
+                    error!("No record found for account: {}", account_id);
+                    continue;
+                }
+            };
+            // Only copy the request if we might need to fail over to another account
+            let request = if next_hops.peek().is_some() {
+                request.clone()
+            } else {
+                request.take()
+            };
+            let request = match request {
+                Some(request) => request.into_outgoing(account),
+                None => break,
+            };
+
+            let mut next = self.next.clone();
+            result = next.send_request(request).await;
+            match result {
+                Err(ref reject) if never_left_node(reject, &ilp_address) => {
+                    if next_hops.peek().is_some() {
+                        debug!(
+                            "Account {} is unreachable, trying the next hop for {}",
+                            account_id, destination
+                        );
+                    }
+                }
+                _ => break,
             }
-            .build())
         }
+        result
     }
 }
 
+/// Whether the packet was rejected before it left the node, in which case it can be sent to
+/// another next hop without the risk of two peers getting it. The outgoing services reject
+/// with `T01_PEER_UNREACHABLE` triggered by the node itself only when they could not send
+/// the packet at all, such as when there is no connection to the peer. A T01 from a peer
+/// is its final answer to the packet, so it is returned to the sender like the others
+fn never_left_node(reject: &Reject, ilp_address: &Address) -> bool {
+    reject.code() == ErrorCode::T01_PEER_UNREACHABLE
+        && reject.triggered_by().as_ref() == Some(ilp_address)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{NextHop, Route, RoutingTable};
     use interledger_errors::*;
-    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use interledger_packet::{Address, FulfillBuilder, Prepare, PrepareBuilder};
     use interledger_service::outgoing_service_fn;
     use once_cell::sync::Lazy;
     use parking_lot::Mutex;
@@ -182,7 +196,7 @@ mod tests {
 
     #[derive(Clone)]
     struct TestStore {
-        routes: HashMap<String, Uuid>,
+        routes: HashMap<String, Route>,
     }
 
     #[async_trait]
@@ -223,8 +237,8 @@ mod tests {
     }
 
     impl RouterStore for TestStore {
-        fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
-            Arc::new(self.routes.clone())
+        fn routing_table(&self) -> Arc<RoutingTable<Route>> {
+            Arc::new(self.routes.clone().into_iter().collect())
         }
     }
 
@@ -263,7 +277,7 @@ mod tests {
     async fn no_route() {
         let mut router = Router::new(
             TestStore {
-                routes: vec![("example.other".to_string(), Uuid::new_v4())]
+                routes: vec![("example.other".to_string(), Uuid::new_v4().into())]
                     .into_iter()
                     .collect(),
             },
@@ -296,7 +310,7 @@ mod tests {
     async fn finds_exact_route() {
         let mut router = Router::new(
             TestStore {
-                routes: vec![("example.destination".to_string(), Uuid::new_v4())]
+                routes: vec![("example.destination".to_string(), Uuid::new_v4().into())]
                     .into_iter()
                     .collect(),
             },
@@ -329,7 +343,9 @@ mod tests {
     async fn catch_all_route() {
         let mut router = Router::new(
             TestStore {
-                routes: vec![(String::new(), Uuid::new_v4())].into_iter().collect(),
+                routes: vec![(String::new(), Uuid::new_v4().into())]
+                    .into_iter()
+                    .collect(),
             },
             outgoing_service_fn(|_| {
                 Ok(FulfillBuilder {
@@ -360,7 +376,7 @@ mod tests {
     async fn finds_matching_prefix() {
         let mut router = Router::new(
             TestStore {
-                routes: vec![("example.".to_string(), Uuid::new_v4())]
+                routes: vec![("example.".to_string(), Uuid::new_v4().into())]
                     .into_iter()
                     .collect(),
             },
@@ -399,9 +415,9 @@ mod tests {
         let mut router = Router::new(
             TestStore {
                 routes: vec![
-                    (String::new(), id0),
-                    ("example.destination".to_string(), id2),
-                    ("example.".to_string(), id1),
+                    (String::new(), id0.into()),
+                    ("example.destination".to_string(), id2.into()),
+                    ("example.".to_string(), id1.into()),
                 ]
                 .into_iter()
                 .collect(),
@@ -433,4 +449,244 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(to.lock().take().unwrap().0, id2);
     }
+
+    fn prepare(destination: &str) -> Prepare {
+        PrepareBuilder {
+            destination: Address::from_str(destination).unwrap(),
+            amount: 100,
+            execution_condition: &[1; 32],
+            expires_at: UNIX_EPOCH,
+            data: &[],
+        }
+        .build()
+    }
+
+    /// Outgoing service which rejects packets for the given accounts with the given code,
+    /// as if the node itself (`example.connector`) rejected them, and records the accounts
+    /// it was called with
+    fn rejecting_service(
+        rejecting: Vec<Uuid>,
+        code: ErrorCode,
+        attempts: Arc<Mutex<Vec<Uuid>>>,
+    ) -> impl OutgoingService<TestAccount> + Clone {
+        rejecting_service_from(rejecting, code, "example.connector", attempts)
+    }
+
+    /// Like `rejecting_service`, with the rejections triggered by the given address
+    fn rejecting_service_from(
+        rejecting: Vec<Uuid>,
+        code: ErrorCode,
+        triggered_by: &'static str,
+        attempts: Arc<Mutex<Vec<Uuid>>>,
+    ) -> impl OutgoingService<TestAccount> + Clone {
+        let triggered_by = Address::from_str(triggered_by).unwrap();
+        outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+            attempts.lock().push(request.to.0);
+            if rejecting.contains(&request.to.0) {
+                Err(RejectBuilder {
+                    code,
+                    message: &[],
+                    triggered_by: Some(&triggered_by),
+                    data: &[],
+                }
+                .build())
+            } else {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn fails_over_to_next_hop_when_peer_is_unreachable() {
+        let id0 = Uuid::from_slice(&[0; 16]).unwrap();
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new(
+            TestStore {
+                routes: vec![(
+                    "example.".to_string(),
+                    Route::new(vec![id0.into(), id1.into()]),
+                )]
+                .into_iter()
+                .collect(),
+            },
+            rejecting_service(vec![id0], ErrorCode::T01_PEER_UNREACHABLE, attempts.clone()),
+        );
+
+        let result = router
+            .handle_request(IncomingRequest {
+                from: TestAccount(Uuid::new_v4()),
+                prepare: prepare("example.destination"),
+            })
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(*attempts.lock(), vec![id0, id1]);
+    }
+
+    #[tokio::test]
+    async fn returns_last_rejection_when_all_hops_are_unreachable() {
+        let id0 = Uuid::from_slice(&[0; 16]).unwrap();
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new(
+            TestStore {
+                routes: vec![(String::new(), Route::new(vec![id0.into(), id1.into()]))]
+                    .into_iter()
+                    .collect(),
+            },
+            rejecting_service(
+                vec![id0, id1],
+                ErrorCode::T01_PEER_UNREACHABLE,
+                attempts.clone(),
+            ),
+        );
+
+        let result = router
+            .handle_request(IncomingRequest {
+                from: TestAccount(Uuid::new_v4()),
+                prepare: prepare("example.destination"),
+            })
+            .await;
+        assert_eq!(result.unwrap_err().code(), ErrorCode::T01_PEER_UNREACHABLE);
+        assert_eq!(*attempts.lock(), vec![id0, id1]);
+    }
+
+    #[tokio::test]
+    async fn does_not_fail_over_on_other_rejections() {
+        let id0 = Uuid::from_slice(&[0; 16]).unwrap();
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new(
+            TestStore {
+                routes: vec![(String::new(), Route::new(vec![id0.into(), id1.into()]))]
+                    .into_iter()
+                    .collect(),
+            },
+            rejecting_service(
+                vec![id0],
+                ErrorCode::F99_APPLICATION_ERROR,
+                attempts.clone(),
+            ),
+        );
+
+        let result = router
+            .handle_request(IncomingRequest {
+                from: TestAccount(Uuid::new_v4()),
+                prepare: prepare("example.destination"),
+            })
+            .await;
+        assert_eq!(result.unwrap_err().code(), ErrorCode::F99_APPLICATION_ERROR);
+        assert_eq!(*attempts.lock(), vec![id0]);
+    }
+
+    #[tokio::test]
+    async fn does_not_fail_over_when_next_hop_times_out() {
+        let id0 = Uuid::from_slice(&[0; 16]).unwrap();
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new(
+            TestStore {
+                routes: vec![(String::new(), Route::new(vec![id0.into(), id1.into()]))]
+                    .into_iter()
+                    .collect(),
+            },
+            // The first hop may have the packet, so it must not be sent to the second one
+            rejecting_service(
+                vec![id0],
+                ErrorCode::R00_TRANSFER_TIMED_OUT,
+                attempts.clone(),
+            ),
+        );
+
+        let result = router
+            .handle_request(IncomingRequest {
+                from: TestAccount(Uuid::new_v4()),
+                prepare: prepare("example.destination"),
+            })
+            .await;
+        assert_eq!(
+            result.unwrap_err().code(),
+            ErrorCode::R00_TRANSFER_TIMED_OUT
+        );
+        assert_eq!(*attempts.lock(), vec![id0]);
+    }
+
+    #[tokio::test]
+    async fn does_not_fail_over_when_peer_rejects_as_unreachable() {
+        let id0 = Uuid::from_slice(&[0; 16]).unwrap();
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new(
+            TestStore {
+                routes: vec![(String::new(), Route::new(vec![id0.into(), id1.into()]))]
+                    .into_iter()
+                    .collect(),
+            },
+            rejecting_service_from(
+                vec![id0],
+                ErrorCode::T01_PEER_UNREACHABLE,
+                "example.peer",
+                attempts.clone(),
+            ),
+        );
+
+        let result = router
+            .handle_request(IncomingRequest {
+                from: TestAccount(Uuid::new_v4()),
+                prepare: prepare("example.destination"),
+            })
+            .await;
+        let reject = result.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T01_PEER_UNREACHABLE);
+        assert_eq!(
+            reject.triggered_by(),
+            Some(Address::from_str("example.peer").unwrap())
+        );
+        assert_eq!(*attempts.lock(), vec![id0]);
+    }
+
+    #[tokio::test]
+    async fn balances_load_across_weighted_hops() {
+        let id0 = Uuid::from_slice(&[0; 16]).unwrap();
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new(
+            TestStore {
+                routes: vec![(
+                    String::new(),
+                    Route::new(vec![
+                        NextHop {
+                            account_id: id0,
+                            weight: Some(1),
+                        },
+                        NextHop {
+                            account_id: id1,
+                            weight: Some(1),
+                        },
+                    ]),
+                )]
+                .into_iter()
+                .collect(),
+            },
+            rejecting_service(vec![], ErrorCode::T01_PEER_UNREACHABLE, attempts.clone()),
+        );
+
+        for _ in 0..100 {
+            let result = router
+                .handle_request(IncomingRequest {
+                    from: TestAccount(Uuid::new_v4()),
+                    prepare: prepare("example.destination"),
+                })
+                .await;
+            assert!(result.is_ok());
+        }
+        let attempts = attempts.lock();
+        assert_eq!(attempts.len(), 100);
+        assert!(attempts.contains(&id0));
+        assert!(attempts.contains(&id1));
+    }
 }
@@ -0,0 +1,319 @@
+use std::{collections::HashMap, fmt, iter::FromIterator, ops::Index};
+
+/// A routing table which maps ILP address prefixes to next hops and finds
+/// the longest matching prefix for a destination address.
+///
+/// Prefixes are stored in a trie keyed by address segments and matched segment
+/// by segment, so `g.a` matches `g.a` and `g.a.b` but not `g.ab`.
+/// A prefix which ends with a `.`, such as `g.a.`, only matches the addresses
+/// below it (`g.a.b` but not `g.a`). The empty prefix matches every address.
+#[derive(Clone, PartialEq)]
+pub struct RoutingTable<T> {
+    root: Node<T>,
+    len: usize,
+}
+
+#[derive(Clone, PartialEq)]
+struct Node<T> {
+    /// Route for the prefix made up of the segments leading to this node
+    exact: Option<(String, T)>,
+    /// Route for the same prefix followed by a `.`, which only matches longer addresses
+    below: Option<(String, T)>,
+    children: HashMap<String, Node<T>>,
+}
+
+impl<T> Default for Node<T> {
+    fn default() -> Self {
+        Node {
+            exact: None,
+            below: None,
+            children: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Node<T> {
+    fn slot(&self, below: bool) -> &Option<(String, T)> {
+        if below {
+            &self.below
+        } else {
+            &self.exact
+        }
+    }
+
+    fn slot_mut(&mut self, below: bool) -> &mut Option<(String, T)> {
+        if below {
+            &mut self.below
+        } else {
+            &mut self.exact
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.exact.is_none() && self.below.is_none() && self.children.is_empty()
+    }
+}
+
+/// Splits a prefix into its segments and whether it ends with a `.`
+fn split_prefix(prefix: &str) -> (Vec<&str>, bool) {
+    let (prefix, below) = match prefix.strip_suffix('.') {
+        Some(prefix) => (prefix, true),
+        None => (prefix, false),
+    };
+    if prefix.is_empty() {
+        (Vec::new(), below)
+    } else {
+        (prefix.split('.').collect(), below)
+    }
+}
+
+impl<T> Default for RoutingTable<T> {
+    fn default() -> Self {
+        RoutingTable {
+            root: Node::default(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> RoutingTable<T> {
+    /// Creates an empty routing table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of prefixes in the table
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Sets the route for the prefix, returning the previous route if there was one
+    pub fn insert(&mut self, prefix: String, value: T) -> Option<T> {
+        let (segments, below) = split_prefix(&prefix);
+        let mut node = &mut self.root;
+        for segment in segments {
+            node = node.children.entry(segment.to_string()).or_default();
+        }
+        let previous = node
+            .slot_mut(below)
+            .replace((prefix, value))
+            .map(|(_, value)| value);
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous
+    }
+
+    /// Removes the route for exactly this prefix
+    pub fn remove(&mut self, prefix: &str) -> Option<T> {
+        fn remove_from<T>(node: &mut Node<T>, segments: &[&str], below: bool) -> Option<T> {
+            match segments.split_first() {
+                None => node.slot_mut(below).take().map(|(_, value)| value),
+                Some((first, rest)) => {
+                    let child = node.children.get_mut(*first)?;
+                    let removed = remove_from(child, rest, below);
+                    if child.is_empty() {
+                        node.children.remove(*first);
+                    }
+                    removed
+                }
+            }
+        }
+
+        let (segments, below) = split_prefix(prefix);
+        let removed = remove_from(&mut self.root, &segments, below);
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    /// Returns the route for exactly this prefix
+    pub fn get(&self, prefix: &str) -> Option<&T> {
+        let (segments, below) = split_prefix(prefix);
+        let mut node = &self.root;
+        for segment in segments {
+            node = node.children.get(segment)?;
+        }
+        node.slot(below).as_ref().map(|(_, value)| value)
+    }
+
+    pub fn contains_key(&self, prefix: &str) -> bool {
+        self.get(prefix).is_some()
+    }
+
+    /// Finds the route with the longest prefix which matches the address.
+    /// Returns the matching prefix together with its route.
+    pub fn longest_match(&self, address: &str) -> Option<(&str, &T)> {
+        let mut node = &self.root;
+        let mut best = node.exact.as_ref();
+        if !address.is_empty() && node.below.is_some() {
+            best = node.below.as_ref();
+        }
+
+        let mut segments = address.split('.').peekable();
+        while let Some(segment) = segments.next() {
+            node = match node.children.get(segment) {
+                Some(child) => child,
+                None => break,
+            };
+            if node.exact.is_some() {
+                best = node.exact.as_ref();
+            }
+            if node.below.is_some() && segments.peek().is_some() {
+                best = node.below.as_ref();
+            }
+        }
+        best.map(|(prefix, value)| (prefix.as_str(), value))
+    }
+
+    /// Iterates over the prefixes and their routes, in no particular order
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            nodes: vec![&self.root],
+            entries: Vec::new(),
+        }
+    }
+
+    /// Iterates over the routes, in no particular order
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.iter().map(|(_, value)| value)
+    }
+}
+
+/// An iterator over the entries of a `RoutingTable`
+pub struct Iter<'a, T> {
+    nodes: Vec<&'a Node<T>>,
+    entries: Vec<&'a (String, T)>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (&'a str, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((prefix, value)) = self.entries.pop() {
+                return Some((prefix.as_str(), value));
+            }
+            let node = self.nodes.pop()?;
+            self.nodes.extend(node.children.values());
+            self.entries
+                .extend(node.exact.iter().chain(node.below.iter()));
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a RoutingTable<T> {
+    type Item = (&'a str, &'a T);
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> FromIterator<(String, T)> for RoutingTable<T> {
+    fn from_iter<I: IntoIterator<Item = (String, T)>>(iter: I) -> Self {
+        let mut table = RoutingTable::new();
+        table.extend(iter);
+        table
+    }
+}
+
+impl<T> Extend<(String, T)> for RoutingTable<T> {
+    fn extend<I: IntoIterator<Item = (String, T)>>(&mut self, iter: I) {
+        for (prefix, value) in iter {
+            self.insert(prefix, value);
+        }
+    }
+}
+
+impl<T> Index<&str> for RoutingTable<T> {
+    type Output = T;
+
+    fn index(&self, prefix: &str) -> &T {
+        self.get(prefix)
+            .unwrap_or_else(|| panic!("no route for prefix \"{}\"", prefix))
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RoutingTable<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(prefixes: &[&str]) -> RoutingTable<usize> {
+        prefixes
+            .iter()
+            .enumerate()
+            .map(|(i, prefix)| (prefix.to_string(), i))
+            .collect()
+    }
+
+    #[test]
+    fn matches_whole_segments() {
+        let table = table(&["g.a", "g.ab.c"]);
+        assert_eq!(table.longest_match("g.a"), Some(("g.a", &0)));
+        assert_eq!(table.longest_match("g.a.b"), Some(("g.a", &0)));
+        assert_eq!(table.longest_match("g.ab"), None);
+        assert_eq!(table.longest_match("g.ab.c.d"), Some(("g.ab.c", &1)));
+    }
+
+    #[test]
+    fn finds_longest_prefix() {
+        let table = table(&["", "g", "g.a.b", "g.a"]);
+        assert_eq!(table.longest_match("g.a.b.c"), Some(("g.a.b", &2)));
+        assert_eq!(table.longest_match("g.a.c"), Some(("g.a", &3)));
+        assert_eq!(table.longest_match("g.b"), Some(("g", &1)));
+        assert_eq!(table.longest_match("test.a"), Some(("", &0)));
+    }
+
+    #[test]
+    fn trailing_separator_only_matches_longer_addresses() {
+        let first = table(&["g.a.", "g"]);
+        assert_eq!(first.longest_match("g.a.b"), Some(("g.a.", &0)));
+        assert_eq!(first.longest_match("g.a"), Some(("g", &1)));
+
+        let second = table(&["g.a", "g.a."]);
+        assert_eq!(second.longest_match("g.a"), Some(("g.a", &0)));
+        assert_eq!(second.longest_match("g.a.b"), Some(("g.a.", &1)));
+    }
+
+    #[test]
+    fn insert_get_and_remove() {
+        let mut table = table(&["g.a", "g.a.b", "g.a."]);
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.insert("g.a".to_string(), 5), Some(0));
+        assert_eq!(table.len(), 3);
+        assert_eq!(table["g.a"], 5);
+        assert_eq!(table.get("g.a."), Some(&2));
+        assert_eq!(table.get("g"), None);
+
+        assert_eq!(table.remove("g.a.b"), Some(1));
+        assert_eq!(table.remove("g.a.b"), None);
+        assert_eq!(table.remove("g.a."), Some(2));
+        assert_eq!(table.remove("g.a"), Some(5));
+        assert!(table.is_empty());
+        assert_eq!(table, RoutingTable::new());
+    }
+
+    #[test]
+    fn iterates_over_all_entries() {
+        let table = table(&["", "g.a", "g.a.", "g.b.c"]);
+        let mut entries: Vec<(&str, &usize)> = table.iter().collect();
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![("", &0), ("g.a", &1), ("g.a.", &2), ("g.b.c", &3)]
+        );
+    }
+}
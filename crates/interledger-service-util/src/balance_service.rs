@@ -1,5 +1,8 @@
+use crate::transactions::{
+    now_millis, PacketOutcome, Transaction, TransactionKind, TransactionStore,
+};
 use async_trait::async_trait;
-use futures::TryFutureExt;
+use futures::{Future, TryFutureExt};
 use interledger_errors::BalanceStoreError;
 use interledger_packet::{ErrorCode, RejectBuilder};
 use interledger_service::*;
@@ -8,9 +11,12 @@ use interledger_settlement::core::{
     SettlementClient,
 };
 use std::marker::PhantomData;
-use std::sync::{Arc, Mutex};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
 use std::{fmt, time::Duration, time::Instant};
-use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc::error::TrySendError, Notify};
 use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
@@ -55,6 +61,75 @@ pub trait BalanceStore {
     ) -> Result<(i64, u64), BalanceStoreError>;
 }
 
+struct TasksState {
+    running: AtomicUsize,
+    idle: Notify,
+}
+
+impl Default for TasksState {
+    fn default() -> Self {
+        TasksState {
+            running: AtomicUsize::new(0),
+            idle: Notify::new(),
+        }
+    }
+}
+
+/// The tasks a `BalanceService` runs in the background once the packets it forwarded got
+/// their response: the balance updates, the settlements and the records of the transactions.
+/// A node shutting down waits for them, since dropping them would leave the balances
+/// without their rollbacks or the settlements taken out of them.
+#[derive(Clone, Default)]
+pub struct BalanceTasks {
+    state: Arc<TasksState>,
+}
+
+impl BalanceTasks {
+    /// Number of tasks which did not finish yet
+    pub fn running(&self) -> usize {
+        self.state.running.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once none of the tasks is running anymore. The packets forwarded from now on
+    /// start new tasks, so this should be called once no more packets come in
+    pub async fn drained(&self) {
+        while self.running() > 0 {
+            self.state.idle.notified().await;
+        }
+    }
+
+    fn spawn<F>(&self, task: F)
+    where
+        F: Future + Send + 'static,
+        F::Output: Send,
+    {
+        let running = Running::new(self.state.clone());
+        tokio::spawn(async move {
+            let _running = running;
+            task.await
+        });
+    }
+}
+
+/// Counts a task as running for as long as it lives, which includes the
+/// case of the runtime dropping it before it finished
+struct Running(Arc<TasksState>);
+
+impl Running {
+    fn new(state: Arc<TasksState>) -> Self {
+        state.running.fetch_add(1, Ordering::SeqCst);
+        Running(state)
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if self.0.running.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify();
+        }
+    }
+}
+
 /// # Balance Service
 ///
 /// Responsible for managing the balances of the account and the interaction with the Settlement Engine
@@ -68,6 +143,7 @@ pub struct BalanceService<S, O, A> {
     policy: Policy,
     account_type: PhantomData<A>,
     channel_last_fail: Arc<Mutex<Instant>>,
+    tasks: BalanceTasks,
 }
 
 impl<S, O, A> BalanceService<S, O, A>
@@ -91,14 +167,27 @@ where
             },
             account_type: PhantomData,
             channel_last_fail: Arc::new(Mutex::new(Instant::now())),
+            tasks: BalanceTasks::default(),
         }
     }
+
+    /// The tasks the service runs in the background, which are shared by its clones
+    pub fn tasks(&self) -> BalanceTasks {
+        self.tasks.clone()
+    }
 }
 
 #[async_trait]
 impl<S, O, A> OutgoingService<A> for BalanceService<S, O, A>
 where
-    S: AddressStore + BalanceStore + SettlementStore<Account = A> + Clone + Send + Sync + 'static,
+    S: AddressStore
+        + BalanceStore
+        + SettlementStore<Account = A>
+        + TransactionStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     O: OutgoingService<A> + Send + Clone + 'static,
     A: SettlementAccount + Send + Sync + 'static,
 {
@@ -110,6 +199,7 @@ where
     ///       INDEPENDENTLY of if the call suceeds or fails. This makes a `sendMoney` call if the fulfill puts the account's balance over the `settle_threshold`
     ///     - if it returns an reject calls `store.update_balances_for_reject` and replies with the fulfill
     ///       INDEPENDENTLY of if the call suceeds or fails
+    /// 1. Records the packet in the transactions of both accounts, in the background as well
     async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
         // Don't bother touching the store for zero-amount packets.
         // Note that it is possible for the original_amount to be >0 while the
@@ -131,6 +221,7 @@ where
         let outgoing_amount = request.prepare.amount();
         let ilp_address = self.store.get_ilp_address();
         let settlement_client = self.settlement_client.clone();
+        let transactions = packet_transactions(&request);
 
         // Update the balance _before_ sending the settlement so that we don't accidentally send
         // multiple settlements for the same balance. While there will be a small moment of time (the delta
@@ -156,7 +247,9 @@ where
             })
             .await?;
 
-        match next.send_request(request).await {
+        let result = next.send_request(request).await;
+        record_packet_later(&self.tasks, self.store.clone(), transactions, &result);
+        match result {
             Ok(fulfill) => {
                 if outgoing_amount > 0 {
                     // We will spawn a task to update the balances in the database
@@ -168,6 +261,7 @@ where
                     // for the packet we forwarded. Note this means that we will
                     // relay the fulfillment _even if saving to the DB fails._
                     settle_or_rollback_later(
+                        &self.tasks,
                         incoming_amount,
                         outgoing_amount,
                         store,
@@ -191,7 +285,7 @@ where
                 // to get the error message from the original Reject packet rather
                 // than a less specific one saying that this node had an "internal
                 // error" caused by a database issue.
-                tokio::spawn({
+                self.tasks.spawn({
                     let store_clone = self.store.clone();
                     async move {
                         store_clone.update_balances_for_reject(
@@ -207,9 +301,83 @@ where
     }
 }
 
+/// The transactions of the accounts the packet came in from and is forwarded to,
+/// which are recorded once it is fulfilled or rejected
+fn packet_transactions<A: Account>(request: &OutgoingRequest<A>) -> Vec<Transaction> {
+    let destination = Some(request.prepare.destination().to_string());
+    vec![
+        Transaction {
+            account_id: request.from.id(),
+            kind: TransactionKind::IncomingPacket,
+            amount: request.original_amount,
+            counterparty: Some(request.to.username().clone()),
+            destination: destination.clone(),
+            outcome: None,
+            error_code: None,
+            timestamp: 0,
+        },
+        Transaction {
+            account_id: request.to.id(),
+            kind: TransactionKind::OutgoingPacket,
+            amount: request.prepare.amount(),
+            counterparty: Some(request.from.username().clone()),
+            destination,
+            outcome: None,
+            error_code: None,
+            timestamp: 0,
+        },
+    ]
+}
+
+/// Records the outcome of the packet in another task, for the same reason as the balance updates
+fn record_packet_later<Store>(
+    tasks: &BalanceTasks,
+    store: Store,
+    mut transactions: Vec<Transaction>,
+    result: &IlpResult,
+) where
+    Store: TransactionStore + Send + Sync + 'static,
+{
+    let (outcome, error_code) = match result {
+        Ok(_) => (PacketOutcome::Fulfilled, None),
+        Err(reject) => (PacketOutcome::Rejected, Some(reject.code().to_string())),
+    };
+    let timestamp = now_millis();
+    for transaction in transactions.iter_mut() {
+        transaction.outcome = Some(outcome);
+        transaction.error_code = error_code.clone();
+        transaction.timestamp = timestamp;
+    }
+    tasks.spawn(async move {
+        store
+            .record_transactions(transactions)
+            .map_err(|err| error!("Error recording the transactions of a packet: {}", err))
+            .await
+    });
+}
+
+/// Records a settlement or a refund, logging the error if it cannot be recorded
+async fn record_settlement<Store>(
+    store: &Store,
+    account_id: Uuid,
+    kind: TransactionKind,
+    amount: u64,
+) where
+    Store: TransactionStore,
+{
+    let transaction = Transaction::settlement(account_id, kind, amount);
+    if let Err(err) = store.record_transactions(vec![transaction]).await {
+        error!(
+            "Error recording {:?} of {} for account {}: {}",
+            kind, amount, account_id, err
+        );
+    }
+}
+
 // See comments above in the BalanceStore::send_request why this is done in another task.
 #[allow(clippy::too_many_arguments)]
 fn settle_or_rollback_later<Acct, Store>(
+    tasks: &BalanceTasks,
     incoming_amount: u64,
     outgoing_amount: u64,
     store: Store,
@@ -220,9 +388,10 @@ fn settle_or_rollback_later<Acct, Store>(
     channel_last_fail: Arc<Mutex<Instant>>,
 ) where
     Acct: SettlementAccount + Send + Sync + 'static,
-    Store: BalanceStore + SettlementStore<Account = Acct> + Send + Sync + 'static,
+    Store:
+        BalanceStore + SettlementStore<Account = Acct> + TransactionStore + Send + Sync + 'static,
 {
-    tokio::spawn(settle_or_rollback_now(
+    tasks.spawn(settle_or_rollback_now(
         incoming_amount,
         outgoing_amount,
         store,
@@ -247,7 +416,8 @@ async fn settle_or_rollback_now<Acct, Store>(
 ) -> Result<(), ()>
 where
     Acct: SettlementAccount + Send + Sync + 'static,
-    Store: BalanceStore + SettlementStore<Account = Acct> + Send + Sync + 'static,
+    Store:
+        BalanceStore + SettlementStore<Account = Acct> + TransactionStore + Send + Sync + 'static,
 {
     let (balance, amount_to_settle) = store
         .update_balances_for_fulfill(to.id(), outgoing_amount)
@@ -294,7 +464,7 @@ async fn settle_or_rollback<Store, Acct>(
     client: SettlementClient,
 ) -> Result<(), ()>
 where
-    Store: SettlementStore<Account = Acct> + 'static,
+    Store: SettlementStore<Account = Acct> + TransactionStore + 'static,
     Acct: SettlementAccount + 'static,
 {
     if amount == 0 {
@@ -307,11 +477,11 @@ where
         // Note that if this program crashes after changing the balance (in the PROCESS_FULFILL
         // script) and the send_settlement fails but the program isn't alive to hear that, the
         // balance will be incorrect. No other instance will know that it was trying to send an
-        // outgoing settlement. We could make this more robust by saving something to the DB about
-        // the outgoing settlement when we change the balance but then we would also need to
-        // prevent a situation where every connector instance is polling the settlement engine for
-        // the status of each outgoing settlement and putting unnecessary load on the settlement
-        // engine.
+        // outgoing settlement. The settlement is recorded as pending before it is sent, and as
+        // accepted or refunded once the engine answered, so the reconciliation report lists the
+        // settlements the engine never answered for. Their amount is only added back to the
+        // balance by hand, since the engine may have settled them before the crash.
+        record_settlement(&store, to.id(), TransactionKind::OutgoingSettlement, amount).await;
 
         let result = client
             .send_settlement(to.id(), engine_url, amount, to.asset_scale())
@@ -336,12 +506,14 @@ where
                     )
                 })
                 .await?;
+            record_settlement(&store, to.id(), TransactionKind::SettlementRefund, amount).await;
         } else {
             info!(
                 "Settlement for account {} for {} succeeded",
                 to.id(),
                 amount
             );
+            record_settlement(&store, to.id(), TransactionKind::SettlementAccepted, amount).await;
         }
     } else {
         debug!("Settlement for account {} for {} failed as the account has no settlement engine details",
@@ -366,6 +538,7 @@ impl Policy {
             Policy::ThresholdOnly => (),
             Policy::TimeBased(ref mut sender) => Policy::drop_error(
                 sender.try_send(ManageTimeout::Clear(account_id)),
+                account_id,
                 channel_last_fail,
             ),
         }
@@ -377,6 +550,7 @@ impl Policy {
             Policy::ThresholdOnly => (),
             Policy::TimeBased(ref mut sender) => Policy::drop_error(
                 sender.try_send(ManageTimeout::Set(account_id)),
+                account_id,
                 channel_last_fail,
             ),
         }
@@ -413,12 +587,13 @@ impl Policy {
     // the very same problem.
     fn drop_error(
         result: Result<(), TrySendError<ManageTimeout>>,
+        uuid: Uuid,
         channel_last_fail: Arc<Mutex<Instant>>,
     ) {
-        let (reason, uuid) = match result {
+        let reason = match result {
             Ok(_) => return,
-            Err(TrySendError::Full(mto)) => ("full", mto.uuid()),
-            Err(TrySendError::Closed(mto)) => ("closed", mto.uuid()),
+            Err(TrySendError::Full(_)) => "full",
+            Err(TrySendError::Closed(_)) => "closed",
         };
 
         // By using try_lock() we avoid worsening any overload situation by limiting unnecessary
@@ -444,20 +619,19 @@ impl Policy {
 pub enum ManageTimeout {
     Clear(Uuid),
     Set(Uuid),
-}
-
-impl ManageTimeout {
-    fn uuid(&self) -> Uuid {
-        match *self {
-            ManageTimeout::Clear(uuid) => uuid,
-            ManageTimeout::Set(uuid) => uuid,
-        }
-    }
+    /// Settle all of the accounts waiting for their delayed settlement right away, and stop
+    /// once these settlements and the ones which already expired are done. This is sent when
+    /// the node shuts down.
+    Flush,
+    /// Change the delay of the settlements set from now on. The ones which are already set
+    /// keep their deadline.
+    SetDelay(Duration),
 }
 
 #[derive(Debug)]
 enum ExitReason {
     InputClosed,
+    Flushed,
     Shutdown,
     Capacity,
     Other(tokio::time::Error),
@@ -468,6 +642,7 @@ impl fmt::Display for ExitReason {
         use ExitReason::*;
         match *self {
             InputClosed => write!(fmt, "Input was closed and ran out of timed settlements"),
+            Flushed => write!(fmt, "Pending settlements were flushed"),
             Shutdown => write!(fmt, "Tokio is shutting down"),
             Capacity => write!(fmt, "Timer service capacity exceeded"),
             Other(ref e) => write!(fmt, "Other: {}", e),
@@ -488,6 +663,7 @@ where
     Store: BalanceStore
         + SettlementStore<Account = Acct>
         + AccountStore<Account = Acct>
+        + TransactionStore
         + Clone
         + Send
         + Sync
@@ -511,7 +687,7 @@ where
 }
 
 async fn run_timeouts_and_settle_on_delay<St, Store, Acct>(
-    delay: Duration,
+    mut delay: Duration,
     mut cmds: St,
     store: Store,
     client: SettlementClient,
@@ -521,6 +697,7 @@ where
     Store: BalanceStore
         + SettlementStore<Account = Acct>
         + AccountStore<Account = Acct>
+        + TransactionStore
         + Clone
         + Send
         + Sync
@@ -533,6 +710,8 @@ where
 
     let mut timeouts = DelayQueue::new();
     let mut in_queue = HashMap::new();
+    // The settlements whose delay expired, which a flush waits for as well
+    let expired_settlements = BalanceTasks::default();
 
     loop {
         tokio::select! {
@@ -558,6 +737,19 @@ where
                             key
                         });
                     }
+                    ManageTimeout::Flush => {
+                        debug!("Flushing {} pending delayed settlements", in_queue.len());
+                        let settlements = in_queue
+                            .drain()
+                            .map(|(id, _)| settle_delayed(id, store.clone(), client.clone()));
+                        futures::future::join_all(settlements).await;
+                        expired_settlements.drained().await;
+                        return ExitReason::Flushed;
+                    }
+                    ManageTimeout::SetDelay(new_delay) => {
+                        debug!("Delaying the settlements by {:?} from now on", new_delay);
+                        delay = new_delay;
+                    }
                 }
             },
             next = timeouts.next(), if !timeouts.is_empty() || cmds.is_terminated() => {
@@ -571,41 +763,7 @@ where
                         let client = client.clone();
                         let store = store.clone();
 
-                        tokio::spawn(async move {
-                            // bailing out instead of not re-scheduling on failing to load the
-                            // account: it is assumed that if this account is valid and should be
-                            // settled there is near-continouos traffic which would trigger either
-                            // the threshold or time based settlement again.
-                            let to = match store.get_accounts(vec![id]).await {
-                                Ok(mut accounts) if accounts.len() == 1 => {
-                                    Ok(accounts.pop().unwrap())
-                                },
-                                Ok(accounts) => {
-                                    error!(
-                                        "Asked for account {} for delayed settlement got back {} accounts: {:?}",
-                                        id, accounts.len(), accounts
-                                    );
-                                    Err(())
-                                }
-                                Err(e) => {
-                                    warn!(
-                                        "Failed to load account {} for time-based settlement: {}",
-                                        id, e
-                                    );
-                                    Err(())
-                                }
-                            }?;
-
-                            let (balance, amount_to_settle) = store.update_balances_for_delayed_settlement(id).await
-                                .map_err(|e| warn!("Time-based settlement failed for {}: {}", id, e))?;
-
-                            debug!(
-                                "Account {} balance at time-based settlement: {}, amount that needs to be settled: {}",
-                                to.id(), balance, amount_to_settle
-                            );
-
-                            settle_or_rollback(store, to, amount_to_settle, client).await
-                        });
+                        expired_settlements.spawn(settle_delayed(id, store, client));
                     },
                     Some(Err(e)) if e.is_shutdown() => {
                         // we probably cant do much better than to exit here (and to drop the
@@ -630,10 +788,70 @@ where
     }
 }
 
+/// Settles the account whose delayed settlement expired
+async fn settle_delayed<Store, Acct>(
+    id: Uuid,
+    store: Store,
+    client: SettlementClient,
+) -> Result<(), ()>
+where
+    Store: BalanceStore
+        + SettlementStore<Account = Acct>
+        + AccountStore<Account = Acct>
+        + TransactionStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    Acct: SettlementAccount + Send + Sync + 'static,
+{
+    // bailing out instead of not re-scheduling on failing to load the
+    // account: it is assumed that if this account is valid and should be
+    // settled there is near-continouos traffic which would trigger either
+    // the threshold or time based settlement again.
+    let to = match store.get_accounts(vec![id]).await {
+        Ok(mut accounts) if accounts.len() == 1 => Ok(accounts.pop().unwrap()),
+        Ok(accounts) => {
+            error!(
+                "Asked for account {} for delayed settlement got back {} accounts: {:?}",
+                id,
+                accounts.len(),
+                accounts
+            );
+            Err(())
+        }
+        Err(e) => {
+            warn!(
+                "Failed to load account {} for time-based settlement: {}",
+                id, e
+            );
+            Err(())
+        }
+    }?;
+
+    let (balance, amount_to_settle) = store
+        .update_balances_for_delayed_settlement(id)
+        .await
+        .map_err(|e| warn!("Time-based settlement failed for {}: {}", id, e))?;
+
+    debug!(
+        "Account {} balance at time-based settlement: {}, amount that needs to be settled: {}",
+        to.id(),
+        balance,
+        amount_to_settle
+    );
+
+    settle_or_rollback(store, to, amount_to_settle, client).await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use interledger_errors::{AddressStoreError, SettlementStoreError};
+    use crate::reconciliation::{
+        AccountReconciliation, Discrepancy, TransactionTotals, UncreditedAmount,
+    };
+    use crate::transactions::{TransactionQuery, TransactionRecord};
+    use interledger_errors::{AddressStoreError, SettlementStoreError, TransactionStoreError};
     use interledger_packet::{Address, FulfillBuilder, PrepareBuilder, RejectBuilder};
     use interledger_settlement::core::types::SettlementEngineDetails;
     use once_cell::sync::Lazy;
@@ -662,6 +880,77 @@ mod tests {
         mock.assert();
         assert_eq!(*store.refunded_settlement.read(), false);
         assert_eq!(*store.rejected_message.read(), false);
+        let kinds: Vec<_> = store.transactions.read().iter().map(|t| t.kind).collect();
+        assert_eq!(
+            &kinds[kinds.len() - 2..],
+            &[
+                TransactionKind::OutgoingSettlement,
+                TransactionKind::SettlementAccepted
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_settlements_interrupted_by_a_crash() {
+        // The engine keeps failing, so the settlement waits to be retried
+        let mock = mockito::mock("POST", mockito::Matcher::Any)
+            .with_status(500)
+            .create();
+        let store = TestStore::new(1);
+        let to = TEST_REQUEST.to.clone();
+        let settlement = settle_or_rollback(store.clone(), to.clone(), 1, Default::default());
+        // Dropping the settlement before the engine accepted it is what a crash does
+        assert!(tokio::time::timeout(Duration::from_millis(500), settlement)
+            .await
+            .is_err());
+        mock.assert();
+
+        let transactions = store.transactions.read().clone();
+        let kinds: Vec<_> = transactions.iter().map(|t| t.kind).collect();
+        assert_eq!(kinds, vec![TransactionKind::OutgoingSettlement]);
+        let mut totals = TransactionTotals::default();
+        for transaction in &transactions {
+            totals.add(transaction);
+        }
+        let uncredited = UncreditedAmount {
+            amount: "0".to_string(),
+            scale: 0,
+        };
+        // The settlement was taken out of the balance, so only the engine can tell
+        // whether it was sent
+        let reconciliation = AccountReconciliation::new(&to, -1, totals, uncredited);
+        assert_eq!(
+            reconciliation.discrepancies,
+            vec![Discrepancy::UnconfirmedSettlements { amount: 1 }]
+        );
+    }
+
+    #[tokio::test]
+    async fn waits_for_settlements_in_progress() {
+        let mock = mockito::mock("POST", mockito::Matcher::Any).create();
+        let next = outgoing_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let store = TestStore::new(1);
+        let mut service = BalanceService::new(store.clone(), None, next);
+        let tasks = service.tasks();
+        service.send_request(TEST_REQUEST.clone()).await.unwrap();
+        // The fulfill is relayed before its settlement is sent
+        assert!(tasks.running() > 0);
+        assert!(store.transactions.read().is_empty());
+
+        tokio::time::timeout(Duration::from_secs(5), tasks.drained())
+            .await
+            .unwrap();
+        assert_eq!(tasks.running(), 0);
+        mock.assert();
+        let kinds: Vec<_> = store.transactions.read().iter().map(|t| t.kind).collect();
+        assert!(kinds.contains(&TransactionKind::SettlementAccepted));
+        assert_eq!(kinds.len(), 4);
     }
 
     #[tokio::test]
@@ -737,6 +1026,45 @@ mod tests {
         assert_eq!(*store.rejected_message.read(), true);
     }
 
+    #[tokio::test]
+    async fn records_transactions() {
+        let mock = mockito::mock("POST", mockito::Matcher::Any)
+            .with_status(404)
+            .create();
+        let next = outgoing_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let store = TestStore::new(1);
+        let mut service = BalanceService::new(store.clone(), None, next);
+        service.send_request(TEST_REQUEST.clone()).await.unwrap();
+
+        tokio::time::delay_for(Duration::from_millis(100u64)).await;
+        mock.assert();
+        let mut transactions = store.transactions.read().clone();
+        transactions.sort_by_key(|transaction| transaction.kind as u8);
+        let kinds: Vec<_> = transactions.iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TransactionKind::IncomingPacket,
+                TransactionKind::OutgoingPacket,
+                TransactionKind::OutgoingSettlement,
+                TransactionKind::SettlementRefund,
+            ]
+        );
+        assert_eq!(transactions[0].counterparty.as_ref(), Some(&*ALICE));
+        assert_eq!(
+            transactions[1].destination.as_deref(),
+            Some("example.destination")
+        );
+        assert_eq!(transactions[1].outcome, Some(PacketOutcome::Fulfilled));
+        assert_eq!(transactions[2].amount, 1);
+    }
+
     #[derive(Debug, Clone)]
     struct TestAccount {
         pub engine_url: Url,
@@ -782,6 +1110,7 @@ mod tests {
         amount_to_settle: u64,
         rejected_message: Arc<RwLock<bool>>,
         refunded_settlement: Arc<RwLock<bool>>,
+        transactions: Arc<RwLock<Vec<Transaction>>>,
     }
 
     impl TestStore {
@@ -790,6 +1119,7 @@ mod tests {
                 amount_to_settle,
                 rejected_message: Arc::new(RwLock::new(false)),
                 refunded_settlement: Arc::new(RwLock::new(false)),
+                transactions: Arc::new(RwLock::new(Vec::new())),
             }
         }
     }
@@ -867,6 +1197,25 @@ mod tests {
         }
     }
 
+    #[async_trait]
+    impl TransactionStore for TestStore {
+        async fn record_transactions(
+            &self,
+            transactions: Vec<Transaction>,
+        ) -> Result<(), TransactionStoreError> {
+            self.transactions.write().extend(transactions);
+            Ok(())
+        }
+
+        async fn get_transactions(
+            &self,
+            _: Uuid,
+            _: TransactionQuery,
+        ) -> Result<Vec<TransactionRecord>, TransactionStoreError> {
+            unimplemented!()
+        }
+    }
+
     static TEST_REQUEST: Lazy<OutgoingRequest<TestAccount>> = Lazy::new(|| {
         let url = mockito::server_url();
         OutgoingRequest {
@@ -1,18 +1,47 @@
+use crate::fee_policy::FeePolicyStore;
 use async_trait::async_trait;
-use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_ccp::CcpRoutingAccount;
+use interledger_packet::{ErrorCode, Reject, RejectBuilder};
 use interledger_rates::ExchangeRateStore;
 use interledger_service::*;
 use interledger_settlement::core::types::{Convert, ConvertDetails};
-use std::marker::PhantomData;
+use std::{
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tracing::{error, trace, warn};
 
+/// How the amount of a packet was converted to the outgoing account's asset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Conversion {
+    /// Exchange rate between the assets, before the spread
+    pub rate: f64,
+    /// Spread applied on top of the exchange rate
+    pub spread: f64,
+    /// Fee taken out of the amount before it was converted, in the incoming asset and scale
+    pub fee: u64,
+}
+
+/// Gets each forwarded request, with its converted amount, along with how it was converted
+/// (if it got to be) and its result
+pub type ConversionListener<A> =
+    Arc<dyn Fn(&OutgoingRequest<A>, Option<&Conversion>, &IlpResult) + Send + Sync>;
+
 /// # Exchange Rates Service
 ///
 /// Responsible for getting the exchange rates for the two assets in the outgoing request (`request.from.asset_code`, `request.to.asset_code`).
-/// Requires a `ExchangeRateStore`
+/// Requires a `ExchangeRateStore` and a `FeePolicyStore`
 #[derive(Clone)]
-pub struct ExchangeRateService<S, O, A> {
-    spread: f64,
+pub struct ExchangeRateService<S, O, A: Account> {
+    /// Bits of the spread, shared by the clones so that it can be changed while the node runs
+    spread: Arc<AtomicU64>,
+    /// Age above which the rates fetched from the exchange rate providers are not used
+    max_rate_age: Option<Duration>,
+    listener: Option<ConversionListener<A>>,
     store: S,
     next: O,
     account_type: PhantomData<A>,
@@ -26,29 +55,77 @@ where
 {
     pub fn new(spread: f64, store: S, next: O) -> Self {
         ExchangeRateService {
-            spread,
+            spread: Arc::new(AtomicU64::new(spread.to_bits())),
+            max_rate_age: None,
+            listener: None,
             store,
             next,
             account_type: PhantomData,
         }
     }
+
+    /// Rejects the packets which would be priced on a rate fetched longer than `max_rate_age`
+    /// ago. Rates without a fetch time, such as the ones set through the HTTP API, are always used
+    pub fn with_max_rate_age(mut self, max_rate_age: Duration) -> Self {
+        self.max_rate_age = Some(max_rate_age);
+        self
+    }
+
+    /// Gives the listener the conversion and the result of every request
+    pub fn with_conversion_listener(mut self, listener: ConversionListener<A>) -> Self {
+        self.listener = Some(listener);
+        self
+    }
 }
 
-#[async_trait]
-impl<S, O, A> OutgoingService<A> for ExchangeRateService<S, O, A>
+impl<S, O, A: Account> ExchangeRateService<S, O, A> {
+    /// Changes the spread applied by this service and all of its clones
+    pub fn set_spread(&self, spread: f64) {
+        self.spread.store(spread.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn spread(&self) -> f64 {
+        f64::from_bits(self.spread.load(Ordering::Relaxed))
+    }
+}
+
+impl<S, O, A> ExchangeRateService<S, O, A>
 where
-    // TODO can we make these non-'static?
-    S: AddressStore + ExchangeRateStore + Clone + Send + Sync + 'static,
-    O: OutgoingService<A> + Send + Sync + Clone + 'static,
-    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore,
+    A: Account,
 {
-    /// On send request:
-    /// 1. If the prepare packet's amount is 0, it just forwards
-    /// 1. Retrieves the exchange rate from the store (the store independently is responsible for polling the rates)
-    ///     - return reject if the call to the store fails
-    /// 1. Calculates the exchange rate AND scales it up/down depending on how many decimals each asset requires
-    /// 1. Updates the amount in the prepare packet and forwards it
-    async fn send_request(&mut self, mut request: OutgoingRequest<A>) -> IlpResult {
+    /// Returns the asset of the request whose rate was fetched longer than `max_rate_age` ago, if any
+    fn stale_asset<'a>(&self, request: &'a OutgoingRequest<A>) -> Option<&'a str> {
+        let max_rate_age = self.max_rate_age?.as_millis() as u64;
+        let asset_codes = [request.from.asset_code(), request.to.asset_code()];
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_millis() as u64)
+            .unwrap_or_default();
+        match self.store.get_exchange_rates_fetched_at(&asset_codes) {
+            Ok(fetched_at) => asset_codes
+                .iter()
+                .zip(fetched_at)
+                .find(|(_, fetched_at)| {
+                    fetched_at.map_or(false, |fetched_at| {
+                        now.saturating_sub(fetched_at) > max_rate_age
+                    })
+                })
+                .map(|(asset_code, _)| *asset_code),
+            // Without the fetch times, the rates cannot be trusted to be recent
+            Err(_) => Some(asset_codes[0]),
+        }
+    }
+}
+
+impl<S, O, A> ExchangeRateService<S, O, A>
+where
+    S: AddressStore + ExchangeRateStore + FeePolicyStore,
+    A: CcpRoutingAccount,
+{
+    /// Converts the amount of the request to the outgoing account's asset, after taking the fee
+    /// out of it. Returns how it was converted, or nothing for the requests without an amount
+    fn convert(&self, request: &mut OutgoingRequest<A>) -> Result<Option<Conversion>, Reject> {
         let ilp_address = self.store.get_ilp_address();
         if request.prepare.amount() > 0 {
             let rate: f64 = if request.from.asset_code() == request.to.asset_code() {
@@ -57,6 +134,22 @@ where
                 .store
                 .get_exchange_rates(&[&request.from.asset_code(), &request.to.asset_code()])
             {
+                if let Some(stale_asset) = self.stale_asset(&request) {
+                    error!("Exchange rate for asset: {} is stale", stale_asset);
+                    return Err(RejectBuilder {
+                        code: ErrorCode::T00_INTERNAL_ERROR,
+                        message: format!(
+                            "Exchange rate from asset: {} to: {} is stale",
+                            request.from.asset_code(),
+                            request.to.asset_code()
+                        )
+                        .as_bytes(),
+                        triggered_by: Some(&ilp_address),
+                        data: &[],
+                    }
+                    .build());
+                }
+
                 // Exchange rates are expressed as `base asset / asset`. To calculate the outgoing amount,
                 // we multiply by the incoming asset's rate and divide by the outgoing asset's rate. For example,
                 // if an incoming packet is denominated in an asset worth 1 USD and the outgoing asset is worth
@@ -82,9 +175,27 @@ where
                 .build());
             };
 
-            // Apply spread
+            // Take the fee out of the amount, then apply the spread
             // TODO should this be applied differently for "local" or same-currency packets?
-            let rate = rate * (1.0 - self.spread);
+            let policy = self.store.get_fee_policy();
+            let fee = policy.fee(&request.from, request.prepare.amount());
+            if fee > 0 && fee >= request.prepare.amount() {
+                return Err(RejectBuilder {
+                    code: ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT,
+                    message: format!(
+                        "Amount: {} does not cover the fee: {}",
+                        request.prepare.amount(),
+                        fee
+                    )
+                    .as_bytes(),
+                    triggered_by: Some(&ilp_address),
+                    data: &[],
+                }
+                .build());
+            }
+            let spread = policy.spread(&request.from, &request.to, self.spread());
+            let exchange_rate = rate;
+            let rate = rate * (1.0 - spread);
             let rate = if rate.is_finite() && rate.is_sign_positive() {
                 rate
             } else {
@@ -96,7 +207,7 @@ where
             };
 
             // Can we overflow here?
-            let outgoing_amount = (request.prepare.amount() as f64) * rate;
+            let outgoing_amount = ((request.prepare.amount() - fee) as f64) * rate;
             let outgoing_amount = outgoing_amount.normalize_scale(ConvertDetails {
                 from: request.from.asset_scale(),
                 to: request.to.asset_scale(),
@@ -141,16 +252,21 @@ where
                         .build());
                     }
                     request.prepare.set_amount(outgoing_amount as u64);
-                    trace!("Converted incoming amount of: {} {} (scale {}) from account {} to outgoing amount of: {} {} (scale {}) for account {}",
+                    trace!("Converted incoming amount of: {} {} (scale {}) from account {} to outgoing amount of: {} {} (scale {}) for account {} (fee: {})",
                         request.original_amount, request.from.asset_code(), request.from.asset_scale(), request.from.id(),
-                        outgoing_amount, request.to.asset_code(), request.to.asset_scale(), request.to.id());
+                        outgoing_amount, request.to.asset_code(), request.to.asset_scale(), request.to.id(), fee);
+                    Ok(Some(Conversion {
+                        rate: exchange_rate,
+                        spread,
+                        fee,
+                    }))
                 }
                 Err(_) => {
                     // This branch gets executed when the `Convert` trait
                     // returns an error. Happens due to float
                     // multiplication overflow .
                     // (float overflow in Rust produces +inf)
-                    return Err(RejectBuilder {
+                    Err(RejectBuilder {
                         code: ErrorCode::F08_AMOUNT_TOO_LARGE,
                         message: format!(
                             "Could not convert exchange rate from {}:{} to: {}:{}. Got incoming amount: {}",
@@ -164,18 +280,60 @@ where
                         triggered_by: Some(&ilp_address),
                         data: &[],
                     }
-                    .build());
+                    .build())
                 }
             }
+        } else {
+            Ok(None)
         }
+    }
+}
 
-        self.next.send_request(request).await
+#[async_trait]
+impl<S, O, A> OutgoingService<A> for ExchangeRateService<S, O, A>
+where
+    // TODO can we make these non-'static?
+    S: AddressStore + ExchangeRateStore + FeePolicyStore + Clone + Send + Sync + 'static,
+    O: OutgoingService<A> + Send + Sync + Clone + 'static,
+    A: CcpRoutingAccount + Send + Sync + 'static,
+{
+    /// On send request:
+    /// 1. If the prepare packet's amount is 0, it just forwards
+    /// 1. Retrieves the exchange rate from the store (the store independently is responsible for polling the rates)
+    ///     - return reject if the call to the store fails
+    /// 1. Takes the fee out of the amount and applies the spread, as set by the store's fee policy
+    /// 1. Calculates the exchange rate AND scales it up/down depending on how many decimals each asset requires
+    /// 1. Updates the amount in the prepare packet and forwards it
+    /// 1. Gives the conversion and the result to the listener, if there is one
+    async fn send_request(&mut self, mut request: OutgoingRequest<A>) -> IlpResult {
+        let conversion = match self.convert(&mut request) {
+            Ok(conversion) => conversion,
+            Err(reject) => {
+                let result = Err(reject);
+                if let Some(ref listener) = self.listener {
+                    listener(&request, None, &result);
+                }
+                return result;
+            }
+        };
+
+        match self.listener.clone() {
+            Some(listener) => {
+                let forwarded = request.clone();
+                let result = self.next.send_request(request).await;
+                listener(&forwarded, conversion.as_ref(), &result);
+                result
+            }
+            None => self.next.send_request(request).await,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fee_policy::{FeePolicy, FeeSchedule, PairSpread};
+    use interledger_ccp::RoutingRelation;
     use interledger_errors::{AddressStoreError, ExchangeRateStoreError};
     use interledger_packet::{Address, Fulfill, FulfillBuilder, PrepareBuilder, Reject};
     use interledger_service::{outgoing_service_fn, Account};
@@ -249,6 +407,179 @@ mod tests {
         assert_eq!(ret.1[0].prepare.amount(), 0);
     }
 
+    #[tokio::test]
+    async fn clones_share_spread() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_clone = requests.clone();
+        let outgoing = outgoing_service_fn(move |request| {
+            requests_clone.lock().unwrap().push(request);
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"hello!",
+            }
+            .build())
+        });
+        let mut service = test_service(1.0, 2.0, 0.0, outgoing);
+        service.clone().set_spread(0.5);
+
+        service
+            .send_request(OutgoingRequest {
+                from: TestAccount::new("ABC".to_owned(), 1),
+                to: TestAccount::new("XYZ".to_owned(), 1),
+                original_amount: 200,
+                prepare: PrepareBuilder {
+                    destination: Address::from_str("example.destination").unwrap(),
+                    amount: 200,
+                    expires_at: SystemTime::now(),
+                    execution_condition: &[1; 32],
+                    data: b"hello",
+                }
+                .build(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(requests.lock().unwrap()[0].prepare.amount(), 50);
+    }
+
+    #[tokio::test]
+    async fn rejects_stale_rates() {
+        let outgoing = outgoing_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"hello!",
+            }
+            .build())
+        });
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let mut store = test_store(1.0, 2.0);
+        store.fetched_at.insert("ABC".to_owned(), now);
+        store.fetched_at.insert("XYZ".to_owned(), now - 120_000);
+        let mut service = ExchangeRateService::new(0.0, store.clone(), outgoing.clone())
+            .with_max_rate_age(Duration::from_secs(60));
+        let request = OutgoingRequest {
+            from: TestAccount::new("ABC".to_owned(), 1),
+            to: TestAccount::new("XYZ".to_owned(), 1),
+            original_amount: 200,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 200,
+                expires_at: SystemTime::now(),
+                execution_condition: &[1; 32],
+                data: b"hello",
+            }
+            .build(),
+        };
+
+        let reject = service.send_request(request.clone()).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T00_INTERNAL_ERROR);
+        assert_eq!(
+            reject.message(),
+            b"Exchange rate from asset: ABC to: XYZ is stale"
+        );
+
+        // Rates without a fetch time do not go stale
+        store.fetched_at.remove("XYZ");
+        let mut service = ExchangeRateService::new(0.0, store, outgoing)
+            .with_max_rate_age(Duration::from_secs(60));
+        assert!(service.send_request(request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn applies_fee_policy() {
+        let mut store = test_store(1.0, 2.0);
+        store.fee_policy.pairs.push(PairSpread {
+            from: "ABC".to_owned(),
+            to: "XYZ".to_owned(),
+            spread: 0.5,
+        });
+        let conversions = Arc::new(Mutex::new(Vec::new()));
+        let conversions_clone = conversions.clone();
+        let listener: ConversionListener<TestAccount> = Arc::new(
+            move |request: &OutgoingRequest<TestAccount>,
+                  conversion: Option<&Conversion>,
+                  result: &IlpResult| {
+                conversions_clone.lock().unwrap().push((
+                    request.prepare.amount(),
+                    conversion.cloned(),
+                    result.is_ok(),
+                ));
+            },
+        );
+        let mut service = ExchangeRateService::new(0.0, store.clone(), fulfilling_service())
+            .with_conversion_listener(listener.clone());
+        service.send_request(test_request(200)).await.unwrap();
+
+        // The routing relation's schedule takes precedence over the pair's spread
+        store.fee_policy.routing_relations.insert(
+            RoutingRelation::Peer,
+            FeeSchedule {
+                spread: Some(0.0),
+                fixed_fee: 10,
+                proportional_fee: 0.1,
+            },
+        );
+        let mut service = ExchangeRateService::new(0.0, store, fulfilling_service())
+            .with_conversion_listener(listener);
+        service.send_request(test_request(200)).await.unwrap();
+        let reject = service.send_request(test_request(10)).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT);
+
+        assert_eq!(
+            *conversions.lock().unwrap(),
+            vec![
+                (
+                    50,
+                    Some(Conversion {
+                        rate: 0.5,
+                        spread: 0.5,
+                        fee: 0
+                    }),
+                    true
+                ),
+                // The fee of 10 + 200 * 0.1 leaves 170 to convert
+                (
+                    85,
+                    Some(Conversion {
+                        rate: 0.5,
+                        spread: 0.0,
+                        fee: 30
+                    }),
+                    true
+                ),
+                (10, None, false),
+            ]
+        );
+    }
+
+    fn fulfilling_service() -> impl OutgoingService<TestAccount> + Clone + Send + Sync {
+        outgoing_service_fn(|_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"hello!",
+            }
+            .build())
+        })
+    }
+
+    fn test_request(amount: u64) -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from: TestAccount::new("ABC".to_owned(), 1),
+            to: TestAccount::new("XYZ".to_owned(), 1),
+            original_amount: amount,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount,
+                expires_at: SystemTime::now(),
+                execution_condition: &[1; 32],
+                data: b"hello",
+            }
+            .build(),
+        }
+    }
+
     // Instantiates an exchange rate service and returns the fulfill/reject
     // packet and the outgoing request after performing an asset conversion
     async fn exchange_rate(
@@ -345,9 +676,27 @@ mod tests {
         }
     }
 
+    impl CcpRoutingAccount for TestAccount {
+        fn routing_relation(&self) -> RoutingRelation {
+            RoutingRelation::Peer
+        }
+    }
+
     #[derive(Debug, Clone)]
     struct TestStore {
         rates: HashMap<Vec<String>, (f64, f64)>,
+        fetched_at: HashMap<String, u64>,
+        fee_policy: FeePolicy,
+    }
+
+    impl FeePolicyStore for TestStore {
+        fn get_fee_policy(&self) -> Arc<FeePolicy> {
+            Arc::new(self.fee_policy.clone())
+        }
+
+        fn set_fee_policy(&self, _policy: FeePolicy) {
+            unimplemented!()
+        }
     }
 
     impl ExchangeRateStore for TestStore {
@@ -380,12 +729,26 @@ mod tests {
         fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
             unimplemented!()
         }
+
+        fn get_exchange_rates_fetched_at(
+            &self,
+            asset_codes: &[&str],
+        ) -> Result<Vec<Option<u64>>, ExchangeRateStoreError> {
+            Ok(asset_codes
+                .iter()
+                .map(|code| self.fetched_at.get(*code).cloned())
+                .collect())
+        }
     }
 
     fn test_store(rate1: f64, rate2: f64) -> TestStore {
         let mut rates = HashMap::new();
         rates.insert(vec!["ABC".to_owned(), "XYZ".to_owned()], (rate1, rate2));
-        TestStore { rates }
+        TestStore {
+            rates,
+            fetched_at: HashMap::new(),
+            fee_policy: FeePolicy::default(),
+        }
     }
 
     fn test_service(
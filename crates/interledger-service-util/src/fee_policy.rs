@@ -0,0 +1,309 @@
+use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
+use interledger_service::Username;
+use serde::{de::Error as DeserializeError, Deserialize, Deserializer, Serialize};
+use std::{collections::BTreeMap, fmt::Debug, str::FromStr, sync::Arc};
+
+/// How the proportional fees are rounded to whole units of the incoming asset
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeRounding {
+    Up,
+    Down,
+    Nearest,
+}
+
+impl Default for FeeRounding {
+    fn default() -> Self {
+        FeeRounding::Up
+    }
+}
+
+impl FeeRounding {
+    fn round(self, amount: f64) -> u64 {
+        let rounded = match self {
+            FeeRounding::Up => amount.ceil(),
+            FeeRounding::Down => amount.floor(),
+            FeeRounding::Nearest => amount.round(),
+        };
+        // Saturates at 0 and u64::MAX
+        rounded as u64
+    }
+}
+
+/// The spread and fees charged on the packets coming from an account
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    /// Spread, overriding the spread of the asset pair
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spread: Option<f64>,
+    /// Fee charged on every packet, in the incoming account's asset and scale
+    #[serde(default)]
+    pub fixed_fee: u64,
+    /// Fee charged as a fraction of the amount of every packet
+    #[serde(default)]
+    pub proportional_fee: f64,
+}
+
+/// The spread of the conversions from one asset to another
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PairSpread {
+    pub from: String,
+    pub to: String,
+    pub spread: f64,
+}
+
+/// The spreads and fees the node charges on the packets it forwards, on top of its global spread.
+///
+/// The spread of a packet is the first one set by:
+/// 1. the fee schedule of the account the packet comes from
+/// 1. the fee schedule of the routing relation of that account
+/// 1. the spread of the pair of assets
+/// 1. the global spread
+///
+/// The fees are the ones of the account's fee schedule or, if the account has none,
+/// of its routing relation's. They are taken out of the incoming amount before it is converted.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeePolicy {
+    #[serde(default)]
+    pub pairs: Vec<PairSpread>,
+    /// Fee schedules of the accounts the packets come from, by username
+    #[serde(default, deserialize_with = "deserialize_keys")]
+    pub accounts: BTreeMap<Username, FeeSchedule>,
+    /// Fee schedules of the accounts the packets come from, by routing relation
+    #[serde(default, deserialize_with = "deserialize_keys")]
+    pub routing_relations: BTreeMap<RoutingRelation, FeeSchedule>,
+    #[serde(default)]
+    pub rounding: FeeRounding,
+}
+
+/// Deserializes the keys from owned strings, which is what the configuration sources give
+/// (`Username` is deserialized from borrowed ones) and case-insensitively for the routing relations
+fn deserialize_keys<'de, D, K>(deserializer: D) -> Result<BTreeMap<K, FeeSchedule>, D::Error>
+where
+    D: Deserializer<'de>,
+    K: FromStr + Ord,
+    K::Err: Debug,
+{
+    BTreeMap::<String, FeeSchedule>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, schedule)| {
+            K::from_str(&key)
+                .map(|key| (key, schedule))
+                .map_err(|err| DeserializeError::custom(format!("Invalid key {}: {:?}", key, err)))
+        })
+        .collect()
+}
+
+impl FeePolicy {
+    /// Checks that the spreads and fees are finite and that the proportional fees
+    /// are fractions of the amount
+    pub fn validate(&self) -> Result<(), String> {
+        for pair in &self.pairs {
+            if !pair.spread.is_finite() {
+                return Err(format!(
+                    "invalid spread from {} to {}: {}",
+                    pair.from, pair.to, pair.spread
+                ));
+            }
+        }
+        let schedules = self
+            .accounts
+            .iter()
+            .map(|(username, schedule)| (username.to_string(), schedule))
+            .chain(
+                self.routing_relations
+                    .iter()
+                    .map(|(relation, schedule)| (relation.to_string(), schedule)),
+            );
+        for (name, schedule) in schedules {
+            if let Some(spread) = schedule.spread {
+                if !spread.is_finite() {
+                    return Err(format!("invalid spread for {}: {}", name, spread));
+                }
+            }
+            if !(0.0..1.0).contains(&schedule.proportional_fee) {
+                return Err(format!(
+                    "invalid proportional fee for {}: {} (must be at least 0 and less than 1)",
+                    name, schedule.proportional_fee
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The fee schedule of the account: its own or, if it has none, its routing relation's
+    pub fn schedule<A: CcpRoutingAccount>(&self, from: &A) -> Option<&FeeSchedule> {
+        self.accounts
+            .get(from.username())
+            .or_else(|| self.routing_relations.get(&from.routing_relation()))
+    }
+
+    /// The spread of the packets forwarded from one account to the other
+    pub fn spread<A: CcpRoutingAccount>(&self, from: &A, to: &A, global_spread: f64) -> f64 {
+        self.schedule(from)
+            .and_then(|schedule| schedule.spread)
+            .or_else(|| {
+                self.pairs
+                    .iter()
+                    .find(|pair| pair.from == from.asset_code() && pair.to == to.asset_code())
+                    .map(|pair| pair.spread)
+            })
+            .unwrap_or(global_spread)
+    }
+
+    /// The fee charged on a packet of that amount from the account,
+    /// in the account's asset and scale
+    pub fn fee<A: CcpRoutingAccount>(&self, from: &A, amount: u64) -> u64 {
+        self.schedule(from).map_or(0, |schedule| {
+            let proportional_fee = self
+                .rounding
+                .round(schedule.proportional_fee * amount as f64);
+            schedule.fixed_fee.saturating_add(proportional_fee)
+        })
+    }
+}
+
+/// Keeps the fee policy the node applies. Like the exchange rates, it is only kept in memory
+pub trait FeePolicyStore: Clone {
+    fn get_fee_policy(&self) -> Arc<FeePolicy>;
+
+    fn set_fee_policy(&self, policy: FeePolicy);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::Address;
+    use interledger_service::Account;
+    use uuid::Uuid;
+
+    #[derive(Clone, Debug)]
+    struct TestAccount {
+        username: Username,
+        asset_code: String,
+        relation: RoutingRelation,
+    }
+
+    impl TestAccount {
+        fn new(username: &str, asset_code: &str, relation: RoutingRelation) -> Self {
+            TestAccount {
+                username: Username::from_str(username).unwrap(),
+                asset_code: asset_code.to_owned(),
+                relation,
+            }
+        }
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &self.username
+        }
+
+        fn asset_code(&self) -> &str {
+            &self.asset_code
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            unimplemented!()
+        }
+    }
+
+    impl CcpRoutingAccount for TestAccount {
+        fn routing_relation(&self) -> RoutingRelation {
+            self.relation
+        }
+    }
+
+    fn policy() -> FeePolicy {
+        let mut policy = FeePolicy::default();
+        policy.pairs.push(PairSpread {
+            from: "USD".to_owned(),
+            to: "EUR".to_owned(),
+            spread: 0.01,
+        });
+        policy.accounts.insert(
+            Username::from_str("alice").unwrap(),
+            FeeSchedule {
+                spread: Some(0.02),
+                fixed_fee: 10,
+                proportional_fee: 0.001,
+            },
+        );
+        policy.routing_relations.insert(
+            RoutingRelation::Peer,
+            FeeSchedule {
+                spread: None,
+                fixed_fee: 5,
+                proportional_fee: 0.0,
+            },
+        );
+        policy
+    }
+
+    #[test]
+    fn overrides_spreads() {
+        let policy = policy();
+        let alice = TestAccount::new("alice", "USD", RoutingRelation::Child);
+        let bob = TestAccount::new("bob", "USD", RoutingRelation::Peer);
+        let carl = TestAccount::new("carl", "USD", RoutingRelation::Child);
+        let eur = TestAccount::new("eve", "EUR", RoutingRelation::Child);
+        let xrp = TestAccount::new("xavier", "XRP", RoutingRelation::Child);
+
+        assert_eq!(policy.spread(&alice, &eur, 0.5), 0.02);
+        // Bob's routing relation does not override the spread
+        assert_eq!(policy.spread(&bob, &eur, 0.5), 0.01);
+        assert_eq!(policy.spread(&carl, &eur, 0.5), 0.01);
+        assert_eq!(policy.spread(&carl, &xrp, 0.5), 0.5);
+    }
+
+    #[test]
+    fn charges_fees() {
+        let mut policy = policy();
+        let alice = TestAccount::new("alice", "USD", RoutingRelation::Child);
+        let bob = TestAccount::new("bob", "USD", RoutingRelation::Peer);
+        let carl = TestAccount::new("carl", "USD", RoutingRelation::Child);
+
+        assert_eq!(policy.fee(&alice, 1500), 12);
+        assert_eq!(policy.fee(&bob, 1500), 5);
+        assert_eq!(policy.fee(&carl, 1500), 0);
+
+        policy.rounding = FeeRounding::Down;
+        assert_eq!(policy.fee(&alice, 1500), 11);
+        policy.rounding = FeeRounding::Nearest;
+        assert_eq!(policy.fee(&alice, 1500), 12);
+        assert_eq!(policy.fee(&alice, 1400), 11);
+    }
+
+    #[test]
+    fn validates_fees() {
+        let mut policy = policy();
+        assert!(policy.validate().is_ok());
+        policy
+            .routing_relations
+            .get_mut(&RoutingRelation::Peer)
+            .unwrap()
+            .proportional_fee = 1.0;
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn deserializes_policy() {
+        let policy: FeePolicy = serde_json::from_str(
+            r#"{
+                "pairs": [{ "from": "USD", "to": "EUR", "spread": 0.01 }],
+                "accounts": { "alice": { "spread": 0.02, "fixed_fee": 10, "proportional_fee": 0.001 } },
+                "routing_relations": { "peer": { "fixed_fee": 5 } }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(policy, self::policy());
+    }
+}
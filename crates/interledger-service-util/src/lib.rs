@@ -11,22 +11,42 @@ mod exchange_rates_service;
 /// Service responsible for shortening the expiry time of packets,
 /// to take into account for network latency
 mod expiry_shortener_service;
+/// Spreads and fees charged on the forwarded packets
+mod fee_policy;
 /// Service responsible for capping the amount an account can send in a packet
 mod max_packet_amount_service;
 /// Service responsible for capping the amount of packets and amount in packets an account can send
 mod rate_limit_service;
+/// Reconciliation of the accounts' balances with their settlements and the rest of their history
+mod reconciliation;
+/// Service responsible for rejecting packets once the node shuts down,
+/// and for waiting for the packets in flight
+mod shutdown_service;
+/// History of the changes of the accounts' balances
+mod transactions;
 /// Service responsible for checking that packets are not expired and that prepare packets' fulfillment conditions
 /// match the fulfillment inside the incoming fulfills
 mod validator_service;
 
-pub use self::balance_service::{start_delayed_settlement, BalanceService, BalanceStore};
+pub use self::balance_service::{
+    start_delayed_settlement, BalanceService, BalanceStore, BalanceTasks, ManageTimeout,
+};
 pub use self::echo_service::EchoService;
-pub use self::exchange_rates_service::ExchangeRateService;
+pub use self::exchange_rates_service::{Conversion, ConversionListener, ExchangeRateService};
 pub use self::expiry_shortener_service::{
     ExpiryShortenerService, RoundTripTimeAccount, DEFAULT_ROUND_TRIP_TIME,
 };
+pub use self::fee_policy::{FeePolicy, FeePolicyStore, FeeRounding, FeeSchedule, PairSpread};
 pub use self::max_packet_amount_service::{MaxPacketAmountAccount, MaxPacketAmountService};
 pub use self::rate_limit_service::{
     RateLimitAccount, RateLimitError, RateLimitService, RateLimitStore,
 };
+pub use self::reconciliation::{
+    AccountReconciliation, Discrepancy, TransactionTotals, UncreditedAmount,
+};
+pub use self::shutdown_service::{PacketDrain, ShutdownService};
+pub use self::transactions::{
+    now_millis, PacketOutcome, Transaction, TransactionKind, TransactionQuery, TransactionRecord,
+    TransactionStore, DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT,
+};
 pub use self::validator_service::ValidatorService;
@@ -0,0 +1,323 @@
+use crate::transactions::{
+    PacketOutcome, Transaction, TransactionKind, TransactionQuery, TransactionStore,
+};
+use interledger_errors::TransactionStoreError;
+use interledger_service::{Account, Username};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+/// Number of transactions loaded at a time while summing an account's history
+const TRANSACTIONS_PAGE_SIZE: usize = 1000;
+
+/// Sums of an account's transactions, in the account's asset and scale
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransactionTotals {
+    /// Fulfilled packets which came in from the account
+    pub packets_in: u64,
+    /// Fulfilled packets which were forwarded to the account
+    pub packets_out: u64,
+    /// Settlements the node received from the account holder
+    pub settled_in: u64,
+    /// Settlements the node took out of the balance to send to the account holder,
+    /// including the ones which failed and were refunded
+    pub settled_out: u64,
+    /// Outgoing settlements which the settlement engine accepted
+    #[serde(default)]
+    pub accepted_out: u64,
+    /// Outgoing settlements which failed, so their amount was added back to the balance
+    pub refunded: u64,
+    /// Number of refunds
+    pub refunds: u64,
+}
+
+impl TransactionTotals {
+    pub fn add(&mut self, transaction: &Transaction) {
+        let fulfilled = transaction.outcome == Some(PacketOutcome::Fulfilled);
+        let total = match transaction.kind {
+            TransactionKind::IncomingPacket if fulfilled => &mut self.packets_in,
+            TransactionKind::OutgoingPacket if fulfilled => &mut self.packets_out,
+            TransactionKind::IncomingPacket | TransactionKind::OutgoingPacket => return,
+            TransactionKind::IncomingSettlement => &mut self.settled_in,
+            TransactionKind::OutgoingSettlement => &mut self.settled_out,
+            TransactionKind::SettlementAccepted => &mut self.accepted_out,
+            TransactionKind::SettlementRefund => {
+                self.refunds += 1;
+                &mut self.refunded
+            }
+        };
+        *total = total.saturating_add(transaction.amount);
+    }
+
+    /// The balance the transactions add up to, from the account holder's perspective
+    pub fn expected_balance(&self) -> i64 {
+        let balance = i128::from(self.packets_out) - i128::from(self.packets_in)
+            + i128::from(self.settled_in)
+            - i128::from(self.settled_out)
+            + i128::from(self.refunded);
+        i64::try_from(balance).unwrap_or(if balance < 0 { i64::MIN } else { i64::MAX })
+    }
+
+    /// Outgoing settlements which the settlement engine neither accepted nor rejected
+    pub fn unconfirmed_out(&self) -> u64 {
+        self.settled_out
+            .saturating_sub(self.accepted_out)
+            .saturating_sub(self.refunded)
+    }
+
+    /// Sums all of the transactions of the account, including the ones which were
+    /// trimmed from its history
+    pub async fn load<S>(store: &S, account_id: Uuid) -> Result<Self, TransactionStoreError>
+    where
+        S: TransactionStore + Sync,
+    {
+        let mut totals = store.get_trimmed_transaction_totals(account_id).await?;
+        let mut before = None;
+        loop {
+            let page = store
+                .get_transactions(
+                    account_id,
+                    TransactionQuery {
+                        before,
+                        limit: TRANSACTIONS_PAGE_SIZE,
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            for record in &page {
+                totals.add(&record.transaction);
+            }
+            if page.len() < TRANSACTIONS_PAGE_SIZE {
+                return Ok(totals);
+            }
+            before = page.last().map(|record| record.id);
+        }
+    }
+}
+
+/// Something about an account which does not add up
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Discrepancy {
+    /// The balance is not the sum of the recorded transactions. A balance under the
+    /// expected one is what is left when the node stopped after taking a settlement out
+    /// of the balance, but before it recorded it. Packets in flight also make the balance
+    /// lower than expected, until they are fulfilled or rejected.
+    BalanceMismatch {
+        expected: i64,
+        actual: i64,
+        /// The actual balance minus the expected one
+        difference: i64,
+    },
+    /// More was refunded than the outgoing settlements added up to
+    RefundsExceedSettlements { settled_out: u64, refunded: u64 },
+    /// Outgoing settlements were taken out of the balance, but the settlement engine
+    /// neither accepted nor rejected them, which is what is left when the node stopped
+    /// while it was sending them. The engine may or may not have settled them, so the
+    /// engine's records tell whether their amount should be added back to the balance.
+    /// Settlements which are still being sent are also unconfirmed until the engine answers
+    UnconfirmedSettlements { amount: u64 },
+}
+
+/// Settlement amounts which were received but could not be credited yet,
+/// because they are smaller than the account's asset scale allows
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct UncreditedAmount {
+    pub amount: String,
+    pub scale: u8,
+}
+
+/// How an account's balance compares with its settlements and the rest of its history
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AccountReconciliation {
+    pub account_id: Uuid,
+    pub username: Username,
+    pub asset_code: String,
+    pub asset_scale: u8,
+    /// The balance in the store, in the account's asset and scale
+    pub balance: i64,
+    /// The balance the transactions add up to
+    pub expected_balance: i64,
+    #[serde(flatten)]
+    pub totals: TransactionTotals,
+    pub uncredited: UncreditedAmount,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl AccountReconciliation {
+    pub fn new<A: Account>(
+        account: &A,
+        balance: i64,
+        totals: TransactionTotals,
+        uncredited: UncreditedAmount,
+    ) -> Self {
+        let expected_balance = totals.expected_balance();
+        let mut discrepancies = Vec::new();
+        if balance != expected_balance {
+            discrepancies.push(Discrepancy::BalanceMismatch {
+                expected: expected_balance,
+                actual: balance,
+                difference: balance.saturating_sub(expected_balance),
+            });
+        }
+        if totals.refunded > totals.settled_out {
+            discrepancies.push(Discrepancy::RefundsExceedSettlements {
+                settled_out: totals.settled_out,
+                refunded: totals.refunded,
+            });
+        }
+        let unconfirmed = totals.unconfirmed_out();
+        if unconfirmed > 0 {
+            discrepancies.push(Discrepancy::UnconfirmedSettlements {
+                amount: unconfirmed,
+            });
+        }
+        AccountReconciliation {
+            account_id: account.id(),
+            username: account.username().clone(),
+            asset_code: account.asset_code().to_string(),
+            asset_scale: account.asset_scale(),
+            balance,
+            expected_balance,
+            totals,
+            uncredited,
+            discrepancies,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::Address;
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+
+    fn transaction(
+        kind: TransactionKind,
+        amount: u64,
+        outcome: Option<PacketOutcome>,
+    ) -> Transaction {
+        Transaction {
+            outcome,
+            ..Transaction::settlement(Uuid::nil(), kind, amount)
+        }
+    }
+
+    #[test]
+    fn sums_transactions() {
+        let mut totals = TransactionTotals::default();
+        for transaction in &[
+            transaction(
+                TransactionKind::OutgoingPacket,
+                100,
+                Some(PacketOutcome::Fulfilled),
+            ),
+            transaction(
+                TransactionKind::OutgoingPacket,
+                1000,
+                Some(PacketOutcome::Rejected),
+            ),
+            transaction(
+                TransactionKind::IncomingPacket,
+                30,
+                Some(PacketOutcome::Fulfilled),
+            ),
+            transaction(TransactionKind::OutgoingSettlement, 50, None),
+            transaction(TransactionKind::SettlementRefund, 50, None),
+            transaction(TransactionKind::OutgoingSettlement, 60, None),
+            transaction(TransactionKind::SettlementAccepted, 60, None),
+            transaction(TransactionKind::IncomingSettlement, 5, None),
+        ] {
+            totals.add(transaction);
+        }
+        assert_eq!(
+            totals,
+            TransactionTotals {
+                packets_in: 30,
+                packets_out: 100,
+                settled_in: 5,
+                settled_out: 110,
+                accepted_out: 60,
+                refunded: 50,
+                refunds: 1,
+            }
+        );
+        assert_eq!(totals.expected_balance(), 100 - 30 + 5 - 110 + 50);
+        assert_eq!(totals.unconfirmed_out(), 0);
+    }
+
+    #[test]
+    fn flags_unrecorded_settlements() {
+        // The node took a settlement of 60 out of the balance and stopped
+        // before it recorded it
+        let totals = TransactionTotals {
+            packets_out: 100,
+            ..Default::default()
+        };
+        let uncredited = UncreditedAmount {
+            amount: "0".to_string(),
+            scale: 0,
+        };
+
+        let reconciliation = AccountReconciliation::new(&TestAccount, 40, totals, uncredited);
+        assert_eq!(reconciliation.expected_balance, 100);
+        assert_eq!(
+            reconciliation.discrepancies,
+            vec![Discrepancy::BalanceMismatch {
+                expected: 100,
+                actual: 40,
+                difference: -60,
+            }]
+        );
+    }
+
+    #[test]
+    fn flags_unconfirmed_settlements() {
+        // The node recorded a settlement of 60 and stopped while sending it,
+        // so the settlement engine never answered
+        let totals = TransactionTotals {
+            packets_out: 100,
+            settled_out: 60,
+            ..Default::default()
+        };
+        let uncredited = UncreditedAmount {
+            amount: "0".to_string(),
+            scale: 0,
+        };
+
+        let reconciliation = AccountReconciliation::new(&TestAccount, 40, totals, uncredited);
+        assert_eq!(reconciliation.expected_balance, 40);
+        assert_eq!(
+            reconciliation.discrepancies,
+            vec![Discrepancy::UnconfirmedSettlements { amount: 60 }]
+        );
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestAccount;
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            unimplemented!()
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use async_trait::async_trait;
+use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_service::*;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+use tokio::sync::Notify;
+use tracing::debug;
+
+struct DrainState {
+    closed: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+impl Default for DrainState {
+    fn default() -> Self {
+        DrainState {
+            closed: AtomicBool::new(false),
+            in_flight: AtomicUsize::new(0),
+            idle: Notify::new(),
+        }
+    }
+}
+
+/// Stops the `ShutdownService`s created with it from accepting new packets
+/// and waits for the packets they already forwarded.
+#[derive(Clone, Default)]
+pub struct PacketDrain {
+    state: Arc<DrainState>,
+}
+
+impl PacketDrain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject all the Prepare packets which come in from now on
+    pub fn close(&self) {
+        self.state.closed.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.closed.load(Ordering::SeqCst)
+    }
+
+    /// Number of packets which were forwarded and did not get a response yet
+    pub fn in_flight(&self) -> usize {
+        self.state.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once none of the packets forwarded is waiting for a response anymore.
+    /// Packets keep coming in until `close` is called, so this should be called after it
+    pub async fn drained(&self) {
+        while self.in_flight() > 0 {
+            self.state.idle.notified().await;
+        }
+    }
+}
+
+/// Counts a packet as in flight for as long as it lives, which includes the
+/// case of its request being dropped before it got a response
+struct InFlight(Arc<DrainState>);
+
+impl InFlight {
+    fn new(state: Arc<DrainState>) -> Self {
+        state.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(state)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify();
+        }
+    }
+}
+
+/// # Shutdown Service
+///
+/// Lets a node shut down without losing the packets it is forwarding.
+/// Once its `PacketDrain` is closed, the service rejects the incoming Prepare packets with
+/// a `T03: Connector Busy` error, so that senders retry them later or through another connector.
+/// The packets forwarded before are still handled normally and the `PacketDrain` can be used
+/// to wait until all of them were fulfilled, rejected or expired.
+/// Requires an `AddressStore`.
+#[derive(Clone)]
+pub struct ShutdownService<I, S> {
+    next: I,
+    store: S,
+    drain: PacketDrain,
+}
+
+impl<I, S> ShutdownService<I, S> {
+    pub fn new(drain: PacketDrain, store: S, next: I) -> Self {
+        ShutdownService { next, store, drain }
+    }
+}
+
+#[async_trait]
+impl<I, S, A> IncomingService<A> for ShutdownService<I, S>
+where
+    I: IncomingService<A> + Send + Sync + 'static,
+    S: AddressStore + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    /// On receive request:
+    /// 1. if the drain is closed, reject the request
+    /// 2. otherwise forward it and count it as in flight until it gets a response
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        // Counted before checking whether the drain is closed, so that
+        // `drained` cannot miss a packet which is accepted concurrently
+        let in_flight = InFlight::new(self.drain.state.clone());
+        if self.drain.is_closed() {
+            debug!(
+                "Rejecting packet from account {} because the node is shutting down",
+                request.from.id()
+            );
+            return Err(RejectBuilder {
+                code: ErrorCode::T03_CONNECTOR_BUSY,
+                message: b"Node is shutting down",
+                triggered_by: Some(&self.store.get_ilp_address()),
+                data: &[],
+            }
+            .build());
+        }
+
+        let result = self.next.handle_request(request).await;
+        drop(in_flight);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{pin_mut, FutureExt};
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
+    use std::str::FromStr;
+    use std::time::{Duration, SystemTime};
+    use tokio::sync::oneshot;
+    use uuid::Uuid;
+
+    #[derive(Debug, Clone)]
+    struct TestAccount;
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::new_v4()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _ilp_address: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    fn request() -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: TestAccount,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: b"test data",
+            }
+            .build(),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_once_closed() {
+        let drain = PacketDrain::new();
+        let next = incoming_service_fn(|_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let mut service = ShutdownService::new(drain.clone(), TestStore, next);
+
+        assert!(service.handle_request(request()).await.is_ok());
+
+        drain.close();
+        let reject = service.handle_request(request()).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T03_CONNECTOR_BUSY);
+        assert_eq!(drain.in_flight(), 0);
+    }
+
+    /// Responds to the packet once it is released
+    #[derive(Clone)]
+    struct PendingService(Arc<Mutex<Option<oneshot::Receiver<()>>>>);
+
+    #[async_trait]
+    impl IncomingService<TestAccount> for PendingService {
+        async fn handle_request(&mut self, _request: IncomingRequest<TestAccount>) -> IlpResult {
+            let released = self.0.lock().take().unwrap();
+            released.await.unwrap();
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        }
+    }
+
+    #[tokio::test]
+    async fn waits_for_packets_in_flight() {
+        let drain = PacketDrain::new();
+        let (release, released) = oneshot::channel();
+        let next = PendingService(Arc::new(Mutex::new(Some(released))));
+        let mut service = ShutdownService::new(drain.clone(), TestStore, next);
+
+        let handling = service.handle_request(request());
+        pin_mut!(handling);
+        assert!(handling.as_mut().now_or_never().is_none());
+        assert_eq!(drain.in_flight(), 1);
+
+        drain.close();
+        let drained = drain.drained();
+        pin_mut!(drained);
+        assert!(drained.as_mut().now_or_never().is_none());
+
+        release.send(()).unwrap();
+        assert!(handling.await.is_ok());
+        drained.await;
+        assert_eq!(drain.in_flight(), 0);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::reconciliation::TransactionTotals;
+use async_trait::async_trait;
+use interledger_errors::TransactionStoreError;
+use interledger_service::Username;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Number of transactions kept for each account, unless the store is configured otherwise.
+/// The older ones are removed, and only their totals are kept
+pub const DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT: usize = 100_000;
+
+/// What changed the balance of an account, from the node's perspective
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionKind {
+    /// A Prepare packet which came in from the account
+    IncomingPacket,
+    /// A Prepare packet which was forwarded to the account
+    OutgoingPacket,
+    /// A settlement the node received from the account holder
+    IncomingSettlement,
+    /// A settlement the node took out of the balance to send to the account holder.
+    /// It is recorded before it is sent to the settlement engine, so it stays pending
+    /// until the engine accepts it or it is refunded
+    OutgoingSettlement,
+    /// The settlement engine accepted an outgoing settlement. The balance does not change
+    SettlementAccepted,
+    /// An outgoing settlement which failed, so its amount was added back to the balance
+    SettlementRefund,
+}
+
+/// Whether a Prepare packet was fulfilled or rejected
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PacketOutcome {
+    Fulfilled,
+    Rejected,
+}
+
+/// A change of an account's balance
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transaction {
+    pub account_id: Uuid,
+    pub kind: TransactionKind,
+    /// Amount in the account's asset and scale
+    pub amount: u64,
+    /// The other account of a packet: the one it was forwarded to (for an incoming packet)
+    /// or the one it came in from (for an outgoing packet)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counterparty: Option<Username>,
+    /// ILP address the packet was sent to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<PacketOutcome>,
+    /// Error code of the Reject packet, if the packet was rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+impl Transaction {
+    /// A settlement or a refund, which happened now
+    pub fn settlement(account_id: Uuid, kind: TransactionKind, amount: u64) -> Self {
+        Transaction {
+            account_id,
+            kind,
+            amount,
+            counterparty: None,
+            destination: None,
+            outcome: None,
+            error_code: None,
+            timestamp: now_millis(),
+        }
+    }
+}
+
+/// A transaction with the ID the store gave it. The IDs grow in the order the
+/// transactions are recorded
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransactionRecord {
+    pub id: u64,
+    #[serde(flatten)]
+    pub transaction: Transaction,
+}
+
+/// Selects a page of an account's transactions, newest first
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransactionQuery {
+    /// Only the transactions at or after this time, in milliseconds since the Unix epoch
+    pub from: Option<u64>,
+    /// Only the transactions before this time, in milliseconds since the Unix epoch
+    pub to: Option<u64>,
+    /// Only the transactions recorded before the one with this ID, which is
+    /// the last ID of the previous page
+    pub before: Option<u64>,
+    /// Only the transactions recorded after the one with this ID, for example
+    /// the newest one seen so far
+    pub after: Option<u64>,
+    /// Maximum number of transactions returned
+    pub limit: usize,
+}
+
+impl Default for TransactionQuery {
+    fn default() -> Self {
+        TransactionQuery {
+            from: None,
+            to: None,
+            before: None,
+            after: None,
+            limit: 100,
+        }
+    }
+}
+
+impl TransactionQuery {
+    /// Whether the transaction is within the time range
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        self.from.map_or(true, |from| transaction.timestamp >= from)
+            && self.to.map_or(true, |to| transaction.timestamp < to)
+    }
+
+    /// Whether the stores can stop looking for transactions once they got to this one,
+    /// going from the newest to the oldest. Only the IDs tell: the timestamps are not in
+    /// the order the transactions are recorded, since the packets are recorded once they
+    /// are fulfilled or rejected
+    pub fn is_exhausted_by(&self, record: &TransactionRecord) -> bool {
+        self.after.map_or(false, |after| record.id <= after)
+    }
+}
+
+/// Milliseconds since the Unix epoch
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Keeps the history of the changes of the accounts' balances.
+///
+/// The stores keep a limited number of transactions for each account. Once an account
+/// has more, its oldest transactions are removed and added to its trimmed totals, so
+/// that the totals of its whole history are still known
+#[async_trait]
+pub trait TransactionStore {
+    /// Adds the transactions to the history of their accounts, and trims the histories
+    /// which get too long
+    async fn record_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+    ) -> Result<(), TransactionStoreError>;
+
+    /// Returns the account's transactions which match the query, newest first
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        query: TransactionQuery,
+    ) -> Result<Vec<TransactionRecord>, TransactionStoreError>;
+
+    /// Returns the totals of the account's transactions which were removed from its history.
+    /// Stores which keep every transaction have none
+    async fn get_trimmed_transaction_totals(
+        &self,
+        _account_id: Uuid,
+    ) -> Result<TransactionTotals, TransactionStoreError> {
+        Ok(TransactionTotals::default())
+    }
+}
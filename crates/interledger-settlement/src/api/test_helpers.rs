@@ -273,6 +273,13 @@ impl LeftoversStore for TestStore {
         })
     }
 
+    async fn peek_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        self.get_uncredited_settlement_amount(account_id).await
+    }
+
     async fn clear_uncredited_settlement_amount(
         &self,
         _account_id: Uuid,
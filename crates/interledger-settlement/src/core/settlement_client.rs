@@ -76,7 +76,8 @@ impl SettlementClient {
 
     /// Sends an idempotent settlement request to the engine (will retry if it fails)
     /// This is done by sending a POST to /accounts/:id/settlements with the provided `amount` and `asset_scale`
-    /// as the request's body
+    /// as the request's body. The retries use the same idempotency key, so that the engine
+    /// settles the amount once
     pub async fn send_settlement(
         &self,
         id: Uuid,
@@ -84,8 +85,17 @@ impl SettlementClient {
         amount: u64,
         asset_scale: u8,
     ) -> Response {
+        let idempotency_key = Uuid::new_v4().to_hyphenated().to_string();
         FutureRetry::new(
-            move || self.send_settlement_once(id, engine_url.clone(), amount, asset_scale),
+            move || {
+                self.send_settlement_once(
+                    id,
+                    engine_url.clone(),
+                    amount,
+                    asset_scale,
+                    idempotency_key.clone(),
+                )
+            },
             RequestErrorHandler::new(self.max_retries),
         )
         .await
@@ -118,6 +128,7 @@ impl SettlementClient {
         engine_url: Url,
         amount: u64,
         asset_scale: u8,
+        idempotency_key: String,
     ) -> Response {
         let mut settlement_engine_url = engine_url;
 
@@ -133,14 +144,11 @@ impl SettlementClient {
             amount, settlement_engine_url
         );
 
-        // Mark the request as idempotent
-        let idempotency_uuid = Uuid::new_v4().to_hyphenated().to_string();
-
         // Make the POST request future
         let response = self
             .client
             .post(settlement_engine_url.as_ref())
-            .header("Idempotency-Key", idempotency_uuid)
+            .header("Idempotency-Key", idempotency_key)
             .json(&json!(Quantity::new(amount, asset_scale)))
             .send()
             .await?;
@@ -184,6 +184,12 @@ pub trait LeftoversStore {
         &self,
         account_id: Self::AccountId,
     ) -> Result<(Self::AssetType, u8), LeftoversStoreError>;
+
+    /// Gets the current amount of leftovers in the store, without removing them
+    async fn peek_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError>;
 }
 
 /// Helper struct for converting a quantity's amount from one asset scale to another
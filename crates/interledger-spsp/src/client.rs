@@ -2,7 +2,10 @@ use super::{Error, SpspResponse};
 use futures::TryFutureExt;
 use interledger_rates::ExchangeRateStore;
 use interledger_service::{Account, IncomingService};
-use interledger_stream::{send_money, StreamDelivery};
+use interledger_stream::{
+    quote_fixed_source, send_money, send_money_with_min_rate, start_payment,
+    start_payment_with_min_rate, PaymentHandle, StreamDelivery, StreamSourceQuote,
+};
 use reqwest::Client;
 use tracing::{debug, error, trace};
 
@@ -68,6 +71,153 @@ where
     Ok(receipt)
 }
 
+/// Query the details of the given Payment Pointer and start sending a payment in the background.
+///
+/// The returned handle follows the payment's progress and can pause, resume or cancel it.
+pub async fn start_pay<I, A, S>(
+    service: I,
+    from_account: A,
+    store: S,
+    receiver: &str,
+    source_amount: u64,
+    slippage: f64,
+) -> Result<PaymentHandle, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    let spsp = query(receiver).await?;
+    let addr = spsp.destination_account;
+    debug!("Starting SPSP payment to address: {}", addr);
+
+    Ok(start_payment(
+        service,
+        &from_account,
+        store,
+        addr,
+        spsp.shared_secret,
+        source_amount,
+        slippage,
+    ))
+}
+
+/// Query the details of the given Payment Pointer and discover the exchange rate to it with
+/// unfulfillable test packets, to quote what sending the source amount should deliver. No money is sent.
+///
+/// The quote's minimum exchange rate can be enforced by paying it with [`pay_with_min_rate`](./fn.pay_with_min_rate.html).
+pub async fn quote<I, A, S>(
+    service: I,
+    from_account: A,
+    store: S,
+    receiver: &str,
+    source_amount: u64,
+    slippage: f64,
+) -> Result<StreamSourceQuote, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    let spsp = query(receiver).await?;
+    let addr = spsp.destination_account;
+    debug!("Quoting SPSP payment to address: {}", addr);
+
+    let quote = quote_fixed_source(
+        service,
+        &from_account,
+        store,
+        addr,
+        spsp.shared_secret,
+        source_amount,
+        slippage,
+    )
+    .map_err(|err| {
+        error!("Error quoting payment: {:?}", err);
+        Error::from(err)
+    })
+    .await?;
+
+    debug!("Quoted SPSP payment: {:?}", quote);
+    Ok(quote)
+}
+
+/// Query the details of the given Payment Pointer and send a payment using the STREAM protocol,
+/// which fails instead of getting an exchange rate below the given one (in destination units per source unit).
+///
+/// This returns the amount delivered, as reported by the receiver and in the receiver's asset's units.
+pub async fn pay_with_min_rate<I, A, S>(
+    service: I,
+    from_account: A,
+    store: S,
+    receiver: &str,
+    source_amount: u64,
+    min_exchange_rate: f64,
+) -> Result<StreamDelivery, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    let spsp = query(receiver).await?;
+    let addr = spsp.destination_account;
+    debug!(
+        "Sending SPSP payment to address: {} at a minimum rate of {}",
+        addr, min_exchange_rate
+    );
+
+    let receipt = send_money_with_min_rate(
+        service,
+        &from_account,
+        store,
+        addr,
+        spsp.shared_secret,
+        source_amount,
+        min_exchange_rate,
+    )
+    .map_err(|err| {
+        error!("Error sending payment: {:?}", err);
+        Error::from(err)
+    })
+    .await?;
+
+    debug!("Sent SPSP payment. StreamDelivery: {:?}", receipt);
+    Ok(receipt)
+}
+
+/// Like [`start_pay`](./fn.start_pay.html), but the payment fails instead of getting an exchange rate
+/// below the given one (in destination units per source unit).
+pub async fn start_pay_with_min_rate<I, A, S>(
+    service: I,
+    from_account: A,
+    store: S,
+    receiver: &str,
+    source_amount: u64,
+    min_exchange_rate: f64,
+) -> Result<PaymentHandle, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    let spsp = query(receiver).await?;
+    let addr = spsp.destination_account;
+    debug!(
+        "Starting SPSP payment to address: {} at a minimum rate of {}",
+        addr, min_exchange_rate
+    );
+
+    Ok(start_payment_with_min_rate(
+        service,
+        &from_account,
+        store,
+        addr,
+        spsp.shared_secret,
+        source_amount,
+        min_exchange_rate,
+    )?)
+}
+
 fn payment_pointer_to_url(payment_pointer: &str) -> String {
     let mut url: String = if let Some(suffix) = payment_pointer.strip_prefix("$") {
         let prefix = "https://";
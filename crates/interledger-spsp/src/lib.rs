@@ -14,7 +14,7 @@ mod client;
 /// An SPSP Server implementing an HTTP Service which generates ILP Addresses and Shared Secrets
 mod server;
 
-pub use client::{pay, query};
+pub use client::{pay, pay_with_min_rate, query, quote, start_pay, start_pay_with_min_rate};
 pub use server::SpspResponder;
 
 #[derive(Debug, thiserror::Error)]
@@ -42,6 +42,13 @@ pub struct SpspResponse {
     /// to be consumed for the STREAM connection
     #[serde(with = "serde_base64")]
     shared_secret: Vec<u8>,
+    /// Base-64 encoded nonce included in the STREAM receipts for this connection
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_base64_option"
+    )]
+    receipt_nonce: Option<Vec<u8>>,
 }
 
 // From https://github.com/serde-rs/json/issues/360#issuecomment-330095360
@@ -65,3 +72,27 @@ mod serde_base64 {
         base64::decode(s).map_err(de::Error::custom)
     }
 }
+
+#[doc(hidden)]
+mod serde_base64_option {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match bytes {
+            Some(bytes) => super::serde_base64::serialize(bytes, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<&str>::deserialize(deserializer)?
+            .map(|s| base64::decode(s).map_err(de::Error::custom))
+            .transpose()
+    }
+}
@@ -1,8 +1,8 @@
 use super::SpspResponse;
 use bytes::Bytes;
-use hyper::{service::Service as HttpService, Body, Error, Request, Response};
+use hyper::{service::Service as HttpService, Body, Error, HeaderMap, Request, Response};
 use interledger_packet::Address;
-use interledger_stream::ConnectionGenerator;
+use interledger_stream::{ConnectionGenerator, ReceiptDetails};
 use std::error::Error as StdError;
 use std::{
     fmt, str,
@@ -10,6 +10,11 @@ use std::{
 };
 use tracing::debug;
 
+/// Header in which the party verifying the receipts provides the base64-encoded receipt nonce
+const RECEIPT_NONCE_HEADER: &str = "Receipt-Nonce";
+/// Header in which the party verifying the receipts provides the base64-encoded receipt secret
+const RECEIPT_SECRET_HEADER: &str = "Receipt-Secret";
+
 /// A Hyper::Service that responds to incoming SPSP Query requests with newly generated
 /// details for a STREAM connection.
 #[derive(Clone)]
@@ -35,21 +40,95 @@ impl SpspResponder {
         let (destination_account, shared_secret) = self
             .connection_generator
             .generate_address_and_secret(&self.ilp_address);
-        debug!(
-            "Generated address and secret for: {:?}",
-            destination_account
-        );
-        let response = SpspResponse {
+        respond(destination_account, shared_secret, None)
+    }
+
+    /// Returns an HTTP Response for an SPSP query with the given headers.
+    ///
+    /// If the query includes `Receipt-Nonce` and `Receipt-Secret` headers, as described in
+    /// the [STREAM receipts RFC](https://interledger.org/rfcs/0039-stream-receipts/),
+    /// the receiver sends STREAM receipts signed with them and the response includes the nonce.
+    /// Otherwise no receipts are sent, like for [`generate_http_response`](#method.generate_http_response).
+    pub fn generate_http_response_for_query(&self, headers: &HeaderMap) -> Response<Body> {
+        match receipt_details_from_headers(headers) {
+            Ok(Some(receipt_details)) => self.generate_http_response_with_receipts(receipt_details),
+            Ok(None) => self.generate_http_response(),
+            Err(message) => Response::builder()
+                .status(400)
+                .body(Body::from(message))
+                .unwrap(),
+        }
+    }
+
+    fn generate_http_response_with_receipts(
+        &self,
+        receipt_details: ReceiptDetails,
+    ) -> Response<Body> {
+        let (destination_account, shared_secret) = self
+            .connection_generator
+            .generate_address_and_secret_with_receipts(&self.ilp_address, &receipt_details);
+        // The receipt secret is never returned, so the sender cannot forge receipts
+        respond(
             destination_account,
-            shared_secret: shared_secret.to_vec(),
-        };
-
-        Response::builder()
-            .header("Content-Type", "application/spsp4+json")
-            .header("Cache-Control", "max-age=60")
-            .status(200)
-            .body(Body::from(serde_json::to_string(&response).unwrap()))
-            .unwrap()
+            shared_secret,
+            Some(receipt_details.nonce.to_vec()),
+        )
+    }
+}
+
+fn respond(
+    destination_account: Address,
+    shared_secret: [u8; 32],
+    receipt_nonce: Option<Vec<u8>>,
+) -> Response<Body> {
+    debug!(
+        "Generated address and secret for: {:?}",
+        destination_account
+    );
+    let response = SpspResponse {
+        destination_account,
+        shared_secret: shared_secret.to_vec(),
+        receipt_nonce,
+    };
+
+    Response::builder()
+        .header("Content-Type", "application/spsp4+json")
+        .header("Cache-Control", "max-age=60")
+        .status(200)
+        .body(Body::from(serde_json::to_string(&response).unwrap()))
+        .unwrap()
+}
+
+/// Reads the receipt nonce and secret from the SPSP query headers, if both are present
+fn receipt_details_from_headers(headers: &HeaderMap) -> Result<Option<ReceiptDetails>, String> {
+    let decode = |name: &str, length: usize| -> Result<Option<Vec<u8>>, String> {
+        headers
+            .get(name)
+            .map(|value| {
+                value
+                    .to_str()
+                    .ok()
+                    .and_then(|value| base64::decode(value).ok())
+                    .filter(|bytes| bytes.len() == length)
+                    .ok_or_else(|| format!("{} must be {} base64-encoded bytes", name, length))
+            })
+            .transpose()
+    };
+    let nonce = decode(RECEIPT_NONCE_HEADER, 16)?;
+    let secret = decode(RECEIPT_SECRET_HEADER, 32)?;
+    match (nonce, secret) {
+        (Some(nonce_bytes), Some(secret_bytes)) => {
+            let mut nonce = [0; 16];
+            let mut secret = [0; 32];
+            nonce.copy_from_slice(&nonce_bytes);
+            secret.copy_from_slice(&secret_bytes);
+            Ok(Some(ReceiptDetails { nonce, secret }))
+        }
+        (None, None) => Ok(None),
+        _ => Err(format!(
+            "{} and {} must be provided together",
+            RECEIPT_NONCE_HEADER, RECEIPT_SECRET_HEADER
+        )),
     }
 }
 
@@ -62,8 +141,8 @@ impl HttpService<Request<Body>> for SpspResponder {
         Ok(()).into()
     }
 
-    fn call(&mut self, _request: Request<Body>) -> Self::Future {
-        futures::future::ok(self.generate_http_response())
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        futures::future::ok(self.generate_http_response_for_query(request.headers()))
     }
 }
 
@@ -112,4 +191,73 @@ mod spsp_server_test {
             "max-age=60"
         );
     }
+
+    #[tokio::test]
+    async fn uses_receipt_details_from_query() {
+        let addr = Address::from_str("example.receiver").unwrap();
+        let mut responder = SpspResponder::new(addr, Bytes::from(&[0; 32][..]));
+        let nonce = base64::encode(&[1u8; 16]);
+        let secret = base64::encode(&[2u8; 32]);
+        let response = responder
+            .call(
+                Request::builder()
+                    .method("GET")
+                    .uri("http://example.com")
+                    .header("Accept", "application/spsp4+json")
+                    .header("Receipt-Nonce", nonce.as_str())
+                    .header("Receipt-Secret", secret.as_str())
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let spsp: SpspResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(spsp.receipt_nonce.unwrap(), vec![1; 16]);
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(json.get("receipt_secret").is_none());
+    }
+
+    #[tokio::test]
+    async fn sends_no_receipts_without_receipt_headers() {
+        let addr = Address::from_str("example.receiver").unwrap();
+        let mut responder = SpspResponder::new(addr, Bytes::from(&[0; 32][..]));
+        let response = responder
+            .call(
+                Request::builder()
+                    .method("GET")
+                    .uri("http://example.com")
+                    .header("Accept", "application/spsp4+json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let spsp: SpspResponse = serde_json::from_slice(&body).unwrap();
+        assert!(spsp.receipt_nonce.is_none());
+        let connection_generator = ConnectionGenerator::new(Bytes::from(&[0; 32][..]));
+        assert!(connection_generator
+            .receipt_details(&spsp.destination_account)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_receipt_headers() {
+        let addr = Address::from_str("example.receiver").unwrap();
+        let mut responder = SpspResponder::new(addr, Bytes::from(&[0; 32][..]));
+        let response = responder
+            .call(
+                Request::builder()
+                    .method("GET")
+                    .uri("http://example.com")
+                    .header("Receipt-Nonce", base64::encode(&[1u8; 15]).as_str())
+                    .header("Receipt-Secret", base64::encode(&[2u8; 32]).as_str())
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+    }
 }
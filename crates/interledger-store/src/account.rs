@@ -3,7 +3,7 @@ use interledger_api::AccountDetails;
 use interledger_btp::BtpAccount;
 use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
 use interledger_errors::CreateAccountError;
-use interledger_http::HttpAccount;
+use interledger_http::{HttpAccount, HttpClientSettings};
 use interledger_packet::Address;
 use interledger_service::{Account as AccountTrait, Username};
 use interledger_service_util::{
@@ -42,6 +42,9 @@ pub struct Account {
     pub(crate) min_balance: Option<i64>,
     /// The account's ILP over HTTP URL (this is where packets are sent over HTTP from your node)
     pub(crate) ilp_over_http_url: Option<Url>,
+    #[serde(serialize_with = "optional_http_client_settings_without_password")]
+    /// Settings of the HTTP client used to send packets to the account's ILP over HTTP URL
+    pub(crate) ilp_over_http_client: Option<HttpClientSettings>,
     #[serde(serialize_with = "optional_secret_bytes_to_utf8")]
     /// The account's API and incoming ILP over HTTP token.
     /// This must match the ILP over HTTP outgoing token on the peer's node if receiving
@@ -102,6 +105,23 @@ where
     serializer.serialize_str("SECRET")
 }
 
+fn optional_http_client_settings_without_password<S>(
+    settings: &Option<HttpClientSettings>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let settings = settings.as_ref().map(|settings| HttpClientSettings {
+        client_identity_password: settings
+            .client_identity_password
+            .as_ref()
+            .map(|_| SecretString::new("SECRET".to_owned())),
+        ..settings.clone()
+    });
+    settings.serialize(serializer)
+}
+
 impl Account {
     /// Creates an account from the provided id and details. If there is no ILP Address
     /// in the provided details, then the account's ILP Address is generated by appending
@@ -125,6 +145,12 @@ impl Account {
             None
         };
 
+        if let Some(ref settings) = details.ilp_over_http_client {
+            settings
+                .build_client()
+                .map_err(|err| CreateAccountError::InvalidHttpClientSettings(err.to_string()))?;
+        }
+
         let ilp_over_btp_url = if let Some(ref url) = details.ilp_over_btp_url {
             Some(Url::parse(url).map_err(CreateAccountError::InvalidBtpUrl)?)
         } else {
@@ -153,6 +179,7 @@ impl Account {
             max_packet_amount: details.max_packet_amount,
             min_balance: details.min_balance,
             ilp_over_http_url,
+            ilp_over_http_client: details.ilp_over_http_client,
             ilp_over_http_incoming_token: details
                 .ilp_over_http_incoming_token
                 .map(|token| SecretBytesMut::new(token.expose_secret().as_str())),
@@ -176,7 +203,8 @@ impl Account {
         })
     }
 
-    /// Encrypts the account's incoming/outgoing BTP and HTTP keys with the provided encryption key
+    /// Encrypts the account's incoming/outgoing BTP and HTTP keys, and the password of its
+    /// HTTP client identity, with the provided encryption key
     pub fn encrypt_tokens(
         mut self,
         encryption_key: &aead::LessSafeKey,
@@ -205,6 +233,13 @@ impl Account {
                 &token.expose_secret(),
             )));
         }
+        if let Some(ref mut settings) = self.ilp_over_http_client {
+            if let Some(ref password) = settings.client_identity_password {
+                let encrypted = encrypt_token(encryption_key, password.expose_secret().as_bytes());
+                settings.client_identity_password =
+                    Some(SecretString::new(base64::encode(&encrypted)));
+            }
+        }
         AccountWithEncryptedTokens { account: self }
     }
 }
@@ -216,7 +251,8 @@ pub struct AccountWithEncryptedTokens {
 }
 
 impl AccountWithEncryptedTokens {
-    /// Decrypts the account's incoming/outgoing BTP and HTTP keys with the provided decryption key
+    /// Decrypts the account's incoming/outgoing BTP and HTTP keys, and the password of its
+    /// HTTP client identity, with the provided decryption key
     pub fn decrypt_tokens(mut self, decryption_key: &aead::LessSafeKey) -> Account {
         if let Some(ref encrypted) = self.account.ilp_over_btp_outgoing_token {
             self.account.ilp_over_btp_outgoing_token =
@@ -262,6 +298,23 @@ impl AccountWithEncryptedTokens {
                     })
                     .ok();
         }
+        let id = self.account.id;
+        if let Some(ref mut settings) = self.account.ilp_over_http_client {
+            if let Some(ref encrypted) = settings.client_identity_password {
+                settings.client_identity_password = base64::decode(encrypted.expose_secret())
+                    .ok()
+                    .and_then(|encrypted| decrypt_token(decryption_key, &encrypted).ok())
+                    .and_then(|password| String::from_utf8(password.expose_secret().to_vec()).ok())
+                    .map(SecretString::new)
+                    .or_else(|| {
+                        error!(
+                            "Unable to decrypt client_identity_password for account {}",
+                            id
+                        );
+                        None
+                    });
+            }
+        }
 
         self.account
     }
@@ -296,6 +349,10 @@ impl HttpAccount for Account {
         self.ilp_over_http_url.as_ref()
     }
 
+    fn get_http_client_settings(&self) -> Option<&HttpClientSettings> {
+        self.ilp_over_http_client.as_ref()
+    }
+
     fn get_http_auth_token(&self) -> Option<SecretString> {
         self.ilp_over_http_outgoing_token.as_ref().map(|s| {
             SecretString::new(
@@ -373,6 +430,7 @@ mod test {
         min_balance: Some(-1000),
         // we are Bob and we're using this account to peer with Alice
         ilp_over_http_url: Some("http://example.com/accounts/bob/ilp".to_string()),
+        ilp_over_http_client: None,
         ilp_over_http_incoming_token: Some(SecretString::new("incoming_auth_token".to_string())),
         ilp_over_http_outgoing_token: Some(SecretString::new("outgoing_auth_token".to_string())),
         ilp_over_btp_url: Some("btp+ws://example.com/accounts/bob/ilp/btp".to_string()),
@@ -416,4 +474,41 @@ mod test {
         );
         assert_eq!(account.routing_relation(), RoutingRelation::Peer);
     }
+    #[test]
+    fn http_client_settings() {
+        let settings = HttpClientSettings {
+            timeout_ms: Some(60_000),
+            client_identity_password: Some(SecretString::new("identity password".to_owned())),
+            ..Default::default()
+        };
+        let mut details = ACCOUNT_DETAILS.clone();
+        details.ilp_over_http_client = Some(settings.clone());
+        let account = Account::try_from(
+            Uuid::new_v4(),
+            details,
+            Address::from_str("example.account").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(account.get_http_client_settings(), Some(&settings));
+        let json = serde_json::to_value(&account).unwrap();
+        assert_eq!(json["ilp_over_http_client"]["timeout_ms"], 60_000);
+        assert_eq!(
+            json["ilp_over_http_client"]["client_identity_password"],
+            "SECRET"
+        );
+
+        let mut details = ACCOUNT_DETAILS.clone();
+        details.ilp_over_http_client = Some(HttpClientSettings {
+            ca_certificate_path: Some("/nonexistent/ca.pem".to_owned()),
+            ..Default::default()
+        });
+        assert!(matches!(
+            Account::try_from(
+                Uuid::new_v4(),
+                details,
+                Address::from_str("example.account").unwrap(),
+            ),
+            Err(CreateAccountError::InvalidHttpClientSettings(_))
+        ));
+    }
 }
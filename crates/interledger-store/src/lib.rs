@@ -6,6 +6,18 @@
 pub mod account;
 /// Cryptographic utilities for encrypting/decrypting data as well as clearing data from memory
 pub mod crypto;
+/// An in-memory backend which does not persist any data
+#[cfg(feature = "memory")]
+pub mod memory;
+/// A PostgreSQL backend using [tokio-postgres](https://github.com/sfackler/rust-postgres)
+#[cfg(feature = "postgres")]
+pub mod postgres;
+/// An in-process implementation of the rate limiting used by the Redis store
+#[cfg(any(feature = "memory", feature = "sled", feature = "postgres"))]
+mod rate_limit;
 /// A redis backend using [redis-rs](https://github.com/mitsuhiko/redis-rs/)
 #[cfg(feature = "redis")]
 pub mod redis;
+/// An embedded backend using [sled](https://github.com/spacejam/sled)
+#[cfg(feature = "sled")]
+pub mod sled;
@@ -0,0 +1,1596 @@
+// The in-memory store keeps the same logical layout as the Redis store, but all of
+// the data lives inside the process:
+//   accounts               map         account id -> account details
+//   balances               map         account id -> balance and prepaid amount
+//   usernames              map         username -> account id
+//   routes                 map         dynamic routing table (set by CCP)
+//   static_routes          map         static routing table
+//   default_route          option      catch-all route
+//   settlement_engines     map         asset code -> settlement engine url
+//   uncredited_amounts     map         account id -> leftovers from settlements
+//   stream_connections     map         STREAM connection token -> total received, receive max
+//   transactions           map         account id -> transactions, oldest first
+//   trimmed_transactions   map         account id -> totals of the transactions removed from its history
+//
+// All of the balance-related operations take a single write lock over the store's
+// data, which gives them the same atomicity guarantees as the Lua scripts used by
+// the Redis store. Nothing is persisted, so this store is intended for tests and
+// throwaway nodes.
+use super::account::Account;
+use super::rate_limit::RateLimiter;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::channel::mpsc::UnboundedSender;
+use http::StatusCode;
+use interledger_api::{AccountDetails, AccountSettings, NodeStore};
+use interledger_btp::BtpStore;
+use interledger_ccp::{CcpRoutingAccount, CcpRoutingStore, RoutingRelation};
+use interledger_errors::*;
+use interledger_http::HttpStore;
+use interledger_packet::Address;
+use interledger_rates::{
+    ExchangeRateHistoryStore, ExchangeRateStore, RateSnapshot, DEFAULT_RATE_HISTORY_RETENTION,
+};
+use interledger_router::{Route, RouterStore, RoutingTable};
+use interledger_service::{AccountStore, AddressStore, Username};
+use interledger_service_util::{
+    BalanceStore, FeePolicy, FeePolicyStore, RateLimitError, RateLimitStore, Transaction,
+    TransactionKind, TransactionQuery, TransactionRecord, TransactionStore, TransactionTotals,
+    DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT,
+};
+use interledger_settlement::core::{
+    idempotency::{IdempotentData, IdempotentStore},
+    scale_with_precision_loss,
+    types::{Convert, ConvertDetails, LeftoversStore, SettlementStore},
+};
+use interledger_stream::{
+    PaymentNotification, StreamConnectionRecord, StreamConnectionStore, StreamNotificationsStore,
+};
+use num_bigint::BigUint;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use secrecy::{ExposeSecret, SecretBytesMut};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    convert::TryFrom,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::sync::broadcast;
+use tracing::{debug, error, trace, warn};
+use url::Url;
+use uuid::Uuid;
+
+/// How long idempotency keys are remembered (24 hours, same as the Redis store)
+const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(86400);
+
+/// The node's default ILP Address
+static DEFAULT_ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("local.host").unwrap());
+
+/// Errors which are specific to the in-memory store. These are wrapped in the
+/// `Other` variant of the errors of each store trait.
+#[derive(Error, Debug)]
+enum InMemoryStoreError {
+    #[error("account `{0}` was not found")]
+    AccountNotFound(Uuid),
+    #[error("Incoming prepare of {amount} would bring account {account_id} under its minimum balance. Current balance: {balance}, min balance: {min_balance}")]
+    MinBalanceExceeded {
+        account_id: Uuid,
+        amount: u64,
+        balance: i64,
+        min_balance: i64,
+    },
+    #[error("Amount {amount} does not fit in the balance of account {account_id}")]
+    AmountOutOfRange { account_id: Uuid, amount: u64 },
+}
+
+/// Builder for the In-Memory Store
+pub struct InMemoryStoreBuilder {
+    /// Connector's ILP Address. Used to insert `Child` accounts as
+    node_ilp_address: Address,
+    /// Number of transactions kept for each account
+    max_transactions_per_account: usize,
+    /// How long the rates are kept in their history, in milliseconds
+    rate_history_retention: u64,
+}
+
+impl Default for InMemoryStoreBuilder {
+    fn default() -> Self {
+        InMemoryStoreBuilder {
+            node_ilp_address: DEFAULT_ILP_ADDRESS.clone(),
+            max_transactions_per_account: DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT,
+            rate_history_retention: DEFAULT_RATE_HISTORY_RETENTION,
+        }
+    }
+}
+
+impl InMemoryStoreBuilder {
+    /// Simple Constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the ILP Address corresponding to the node
+    pub fn node_ilp_address(&mut self, node_ilp_address: Address) -> &mut Self {
+        self.node_ilp_address = node_ilp_address;
+        self
+    }
+
+    /// Sets the number of transactions kept for each account. Once an account has more,
+    /// its oldest transactions are removed and only their totals are kept
+    pub fn max_transactions_per_account(&mut self, max: usize) -> &mut Self {
+        self.max_transactions_per_account = max;
+        self
+    }
+
+    /// Sets how long (in milliseconds) the rates are kept in their history. The last rate
+    /// recorded before that is kept as well, since it was still in effect
+    pub fn rate_history_retention(&mut self, retention: u64) -> &mut Self {
+        self.rate_history_retention = retention;
+        self
+    }
+
+    /// Creates an empty store
+    pub fn build(&self) -> InMemoryStore {
+        let (payment_publisher, _) = broadcast::channel::<PaymentNotification>(256);
+        let data = StoreData {
+            max_transactions_per_account: self.max_transactions_per_account,
+            rate_history_retention: self.rate_history_retention,
+            ..Default::default()
+        };
+        InMemoryStore {
+            ilp_address: Arc::new(RwLock::new(self.node_ilp_address.clone())),
+            data: Arc::new(RwLock::new(data)),
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            payment_publisher,
+            exchange_rates: Arc::new(RwLock::new(HashMap::new())),
+            exchange_rates_fetched_at: Arc::new(RwLock::new(HashMap::new())),
+            fee_policy: Arc::new(RwLock::new(Arc::new(FeePolicy::default()))),
+            routes: Arc::new(RwLock::new(Arc::new(RoutingTable::new()))),
+            rate_limiter: Arc::new(RateLimiter::new()),
+        }
+    }
+}
+
+/// The net position with an account holder, see the store's README for details
+#[derive(Debug, Default, Clone, Copy)]
+struct Balance {
+    balance: i64,
+    prepaid_amount: i64,
+}
+
+impl Balance {
+    fn total(&self) -> i64 {
+        self.balance + self.prepaid_amount
+    }
+}
+
+/// Converts an amount to the type of the balances, failing if it does not fit
+fn signed_amount(account_id: Uuid, amount: u64) -> Result<i64, InMemoryStoreError> {
+    i64::try_from(amount).map_err(|_| InMemoryStoreError::AmountOutOfRange { account_id, amount })
+}
+
+/// Adds the amount to the balance, failing rather than overflowing
+fn credit(balance: i64, account_id: Uuid, amount: u64) -> Result<i64, InMemoryStoreError> {
+    signed_amount(account_id, amount)?
+        .checked_add(balance)
+        .ok_or(InMemoryStoreError::AmountOutOfRange { account_id, amount })
+}
+
+#[derive(Default)]
+struct StoreData {
+    accounts: HashMap<Uuid, Account>,
+    balances: HashMap<Uuid, Balance>,
+    usernames: HashMap<String, Uuid>,
+    routes: HashMap<String, Uuid>,
+    static_routes: HashMap<String, Route>,
+    default_route: Option<Uuid>,
+    settlement_engines: HashMap<String, Url>,
+    /// The address we were configured with by our parent (if any)
+    parent_ilp_address: Option<Address>,
+    uncredited_amounts: HashMap<Uuid, Vec<(BigUint, u8)>>,
+    idempotent_data: HashMap<String, (IdempotentData, Instant)>,
+    settlement_idempotency_keys: HashMap<String, Instant>,
+    stream_connections: HashMap<String, StreamConnectionRecord>,
+    transactions: HashMap<Uuid, VecDeque<TransactionRecord>>,
+    /// Totals of the transactions which were removed from the history of each account
+    trimmed_transactions: HashMap<Uuid, TransactionTotals>,
+    max_transactions_per_account: usize,
+    rate_history_retention: u64,
+    /// ID of the last transaction which was recorded
+    last_transaction_id: u64,
+    /// The rates of each asset by the time they were recorded
+    rate_history: HashMap<String, BTreeMap<u64, f64>>,
+}
+
+impl StoreData {
+    fn record_transaction(&mut self, transaction: Transaction) {
+        self.last_transaction_id += 1;
+        let record = TransactionRecord {
+            id: self.last_transaction_id,
+            transaction,
+        };
+        let account_id = record.transaction.account_id;
+        let transactions = self.transactions.entry(account_id).or_default();
+        transactions.push_back(record);
+        while transactions.len() > self.max_transactions_per_account {
+            if let Some(trimmed) = transactions.pop_front() {
+                self.trimmed_transactions
+                    .entry(account_id)
+                    .or_default()
+                    .add(&trimmed.transaction);
+            }
+        }
+    }
+
+    /// Returns the account with the globally configured settlement engine
+    /// for its asset code filled in, if it does not have one of its own
+    fn load_account(&self, id: Uuid) -> Option<Account> {
+        self.accounts.get(&id).map(|account| {
+            let mut account = account.clone();
+            if account.settlement_engine_url.is_none() {
+                account.settlement_engine_url =
+                    self.settlement_engines.get(&account.asset_code).cloned();
+            }
+            account
+        })
+    }
+
+    fn all_accounts(&self) -> Vec<Account> {
+        self.accounts
+            .keys()
+            .filter_map(|id| self.load_account(*id))
+            .collect()
+    }
+
+    fn filter_accounts<F>(&self, predicate: F) -> Vec<Account>
+    where
+        F: Fn(&Account) -> bool,
+    {
+        self.all_accounts()
+            .into_iter()
+            .filter(|account| predicate(account))
+            .collect()
+    }
+
+    fn balance_mut(&mut self, id: Uuid) -> Result<&mut Balance, InMemoryStoreError> {
+        self.balances
+            .get_mut(&id)
+            .ok_or(InMemoryStoreError::AccountNotFound(id))
+    }
+
+    /// Sums all the uncredited amounts of the account (scaled to the largest
+    /// scale among them) and removes them from the store
+    fn take_uncredited_settlement_amount(&mut self, account_id: Uuid) -> (BigUint, u8) {
+        let amounts = self
+            .uncredited_amounts
+            .remove(&account_id)
+            .unwrap_or_default();
+        sum_uncredited_amounts(&amounts)
+    }
+
+    fn peek_uncredited_settlement_amount(&self, account_id: Uuid) -> (BigUint, u8) {
+        self.uncredited_amounts
+            .get(&account_id)
+            .map(|amounts| sum_uncredited_amounts(amounts))
+            .unwrap_or((BigUint::from(0u32), 0))
+    }
+}
+
+/// Sums the amounts in the largest of their scales
+fn sum_uncredited_amounts(amounts: &[(BigUint, u8)]) -> (BigUint, u8) {
+    let max_scale = amounts.iter().map(|(_, scale)| *scale).max().unwrap_or(0);
+    let mut sum = BigUint::from(0u32);
+    for (num, scale) in amounts {
+        sum += num
+            .normalize_scale(ConvertDetails {
+                from: *scale,
+                to: max_scale,
+            })
+            .unwrap();
+    }
+    (sum, max_scale)
+}
+
+/// A Store that keeps all of its data in memory.
+///
+/// This store does not need any external database, but it also does not persist any of the
+/// accounts, balances or routes. It is meant for tests and throwaway development nodes.
+#[derive(Clone)]
+pub struct InMemoryStore {
+    /// The Store's ILP Address
+    ilp_address: Arc<RwLock<Address>>,
+    /// Accounts, balances, routes and all the other data of the store
+    data: Arc<RwLock<StoreData>>,
+    /// WebSocket senders which publish incoming payment updates
+    subscriptions: Arc<Mutex<HashMap<Uuid, Vec<UnboundedSender<PaymentNotification>>>>>,
+    /// A subscriber to all payment notifications, exposed via a WebSocket
+    payment_publisher: broadcast::Sender<PaymentNotification>,
+    exchange_rates: Arc<RwLock<HashMap<String, f64>>>,
+    /// When each of the rates was fetched, in milliseconds since the Unix epoch
+    exchange_rates_fetched_at: Arc<RwLock<HashMap<String, u64>>>,
+    fee_policy: Arc<RwLock<Arc<FeePolicy>>>,
+    /// The routing table is kept separately from the rest of the data and is
+    /// rebuilt whenever the routes change, so that the `Router` does not need
+    /// to contend with the balance updates for the lock.
+    routes: Arc<RwLock<Arc<RoutingTable<Route>>>>,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl InMemoryStore {
+    /// Rebuilds the routing table which is returned to the `Router`.
+    /// Static routes take precedence over the routes set by CCP and the default route.
+    fn update_routes(&self, data: &StoreData) {
+        let routes: RoutingTable<Route> = data
+            .routes
+            .iter()
+            .map(|(prefix, id)| (prefix.clone(), Route::from(*id)))
+            // Include the default route if there is one
+            .chain(
+                data.default_route
+                    .map(|id| (String::new(), Route::from(id))),
+            )
+            // Having the static_routes inserted after ensures that they will overwrite
+            // any routes with the same prefix from the first set
+            .chain(
+                data.static_routes
+                    .iter()
+                    .map(|(prefix, route)| (prefix.clone(), route.clone())),
+            )
+            .collect();
+        trace!("Routing table is: {:?}", routes);
+        *self.routes.write() = Arc::new(routes);
+    }
+
+    fn insert_account_data(&self, account: Account) -> Result<Account, NodeStoreError> {
+        let mut data = self.data.write();
+        let username = account.username.to_string();
+        if data.accounts.contains_key(&account.id)
+            || data.usernames.contains_key(&username)
+            || (account.routing_relation == RoutingRelation::Parent
+                && data.parent_ilp_address.is_some())
+        {
+            warn!(
+                "An account already exists with the same {}. Cannot insert account: {:?}",
+                account.id, account
+            );
+            return Err(NodeStoreError::AccountExists(username));
+        }
+
+        data.usernames.insert(username, account.id);
+        data.balances.insert(account.id, Balance::default());
+        data.routes
+            .insert(account.ilp_address.to_string(), account.id);
+        data.accounts.insert(account.id, account.clone());
+        self.update_routes(&data);
+        debug!(
+            "Inserted account {} (ILP address: {})",
+            account.id, account.ilp_address
+        );
+        Ok(account)
+    }
+
+    fn update_account_data(&self, account: Account) -> Result<Account, NodeStoreError> {
+        let mut data = self.data.write();
+        let old = match data.accounts.get(&account.id) {
+            Some(old) => old.clone(),
+            None => {
+                warn!(
+                    "No account exists with ID {}, cannot update account {:?}",
+                    account.id, account
+                );
+                return Err(NodeStoreError::AccountNotFound(account.id.to_string()));
+            }
+        };
+
+        let username = account.username.to_string();
+        if let Some(id) = data.usernames.get(&username) {
+            if *id != account.id {
+                return Err(NodeStoreError::AccountExists(username));
+            }
+        }
+        data.usernames.remove(old.username.as_ref());
+        data.usernames.insert(username, account.id);
+
+        // Replace the route to the account's old address
+        let old_address = old.ilp_address.to_string();
+        if data.routes.get(&old_address) == Some(&account.id) {
+            data.routes.remove(&old_address);
+        }
+        data.routes
+            .insert(account.ilp_address.to_string(), account.id);
+        data.accounts.insert(account.id, account.clone());
+        self.update_routes(&data);
+        debug!(
+            "Updated account {} (id: {}, ILP address: {})",
+            account.username, account.id, account.ilp_address
+        );
+        Ok(account)
+    }
+
+    fn modify_account_data(
+        &self,
+        id: Uuid,
+        settings: AccountSettings,
+    ) -> Result<Account, NodeStoreError> {
+        if let Some(settle_to) = settings.settle_to {
+            if settle_to > std::i64::MAX as u64 {
+                return Err(NodeStoreError::InvalidAccount(
+                    CreateAccountError::ParamTooLarge("settle_to".to_owned()),
+                ));
+            }
+        }
+        let ilp_over_btp_url = match settings.ilp_over_btp_url {
+            Some(ref url) => Some(Url::parse(url).map_err(|err| {
+                NodeStoreError::InvalidAccount(CreateAccountError::InvalidBtpUrl(err))
+            })?),
+            None => None,
+        };
+        let ilp_over_http_url = match settings.ilp_over_http_url {
+            Some(ref url) => Some(Url::parse(url).map_err(|err| {
+                NodeStoreError::InvalidAccount(CreateAccountError::InvalidHttpUrl(err))
+            })?),
+            None => None,
+        };
+
+        let mut data = self.data.write();
+        let account = data
+            .accounts
+            .get_mut(&id)
+            .ok_or_else(|| NodeStoreError::AccountNotFound(id.to_string()))?;
+
+        if let Some(url) = ilp_over_btp_url {
+            account.ilp_over_btp_url = Some(url);
+        }
+        if let Some(url) = ilp_over_http_url {
+            account.ilp_over_http_url = Some(url);
+        }
+        if let Some(ref token) = settings.ilp_over_btp_outgoing_token {
+            account.ilp_over_btp_outgoing_token =
+                Some(SecretBytesMut::new(token.expose_secret().as_str()));
+        }
+        if let Some(ref token) = settings.ilp_over_http_outgoing_token {
+            account.ilp_over_http_outgoing_token =
+                Some(SecretBytesMut::new(token.expose_secret().as_str()));
+        }
+        if let Some(ref token) = settings.ilp_over_btp_incoming_token {
+            account.ilp_over_btp_incoming_token =
+                Some(SecretBytesMut::new(token.expose_secret().as_str()));
+        }
+        if let Some(ref token) = settings.ilp_over_http_incoming_token {
+            account.ilp_over_http_incoming_token =
+                Some(SecretBytesMut::new(token.expose_secret().as_str()));
+        }
+        if let Some(settle_threshold) = settings.settle_threshold {
+            account.settle_threshold = Some(settle_threshold);
+        }
+        if let Some(settle_to) = settings.settle_to {
+            account.settle_to = Some(settle_to as i64);
+        }
+
+        Ok(data.load_account(id).unwrap())
+    }
+
+    fn delete_account_data(&self, id: Uuid) -> Result<Account, NodeStoreError> {
+        let mut data = self.data.write();
+        let account = data
+            .load_account(id)
+            .ok_or_else(|| NodeStoreError::AccountNotFound(id.to_string()))?;
+        data.accounts.remove(&id);
+        data.balances.remove(&id);
+        data.usernames.remove(account.username.as_ref());
+        data.uncredited_amounts.remove(&id);
+        data.transactions.remove(&id);
+        data.trimmed_transactions.remove(&id);
+        let address = account.ilp_address.to_string();
+        if data.routes.get(&address) == Some(&id) {
+            data.routes.remove(&address);
+        }
+        self.update_routes(&data);
+        debug!("Deleted account {}", account.id);
+        Ok(account)
+    }
+
+    fn set_ilp_address_data(&self, ilp_address: Address) {
+        let mut data = self.data.write();
+        (*self.ilp_address.write()) = ilp_address.clone();
+        data.parent_ilp_address = Some(ilp_address.clone());
+
+        let first_segment = ilp_address
+            .segments()
+            .rev()
+            .next()
+            .expect("address did not have a first segment, this should be impossible");
+        let ids: Vec<Uuid> = data.accounts.keys().cloned().collect();
+        for id in ids {
+            let (old_address, new_address) = {
+                let account = data.accounts.get_mut(&id).unwrap();
+                // Update the address and routes of all children and non-routing accounts.
+                if account.routing_relation == RoutingRelation::Parent
+                    || account.routing_relation == RoutingRelation::Peer
+                {
+                    continue;
+                }
+                // if the username of the account ends with the
+                // node's address, we're already configured so no
+                // need to append anything.
+                let new_address = if first_segment == account.username.to_string() {
+                    ilp_address.clone()
+                } else {
+                    ilp_address
+                        .with_suffix(account.username.as_bytes())
+                        .unwrap()
+                };
+                let old_address = std::mem::replace(&mut account.ilp_address, new_address.clone());
+                (old_address, new_address)
+            };
+            data.routes.remove(&old_address.to_string());
+            data.routes.insert(new_address.to_string(), id);
+        }
+        self.update_routes(&data);
+    }
+
+    fn notify(&self, payment: PaymentNotification) {
+        let account_id = match self.data.read().usernames.get(payment.to_username.as_ref()) {
+            Some(id) => *id,
+            None => {
+                error!(
+                    "Failed to find account ID corresponding to username: {}",
+                    payment.to_username
+                );
+                return;
+            }
+        };
+        trace!(
+            "Publishing payment notification for account {}: {:?}",
+            account_id,
+            payment
+        );
+
+        if self.payment_publisher.receiver_count() > 0 {
+            if let Err(err) = self.payment_publisher.send(payment.clone()) {
+                error!("Failed to send a node-wide payment notification: {:?}", err);
+            }
+        }
+        match self.subscriptions.lock().get_mut(&account_id) {
+            Some(senders) => {
+                senders.retain(|sender| {
+                    if let Err(err) = sender.unbounded_send(payment.clone()) {
+                        debug!("Failed to send message: {}", err);
+                        false
+                    } else {
+                        true
+                    }
+                });
+            }
+            None => trace!(
+                "Ignoring message for account {} because there were no open subscriptions",
+                account_id
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl AccountStore for InMemoryStore {
+    type Account = Account;
+
+    async fn get_accounts(
+        &self,
+        account_ids: Vec<Uuid>,
+    ) -> Result<Vec<Account>, AccountStoreError> {
+        let data = self.data.read();
+        let accounts: Vec<Account> = account_ids
+            .iter()
+            .filter_map(|id| data.load_account(*id))
+            .collect();
+        if accounts.len() == account_ids.len() {
+            Ok(accounts)
+        } else {
+            Err(AccountStoreError::WrongLength {
+                expected: account_ids.len(),
+                actual: accounts.len(),
+            })
+        }
+    }
+
+    async fn get_account_id_from_username(
+        &self,
+        username: &Username,
+    ) -> Result<Uuid, AccountStoreError> {
+        match self.data.read().usernames.get(username.as_ref()) {
+            Some(id) => Ok(*id),
+            None => {
+                debug!("Username not found: {}", username);
+                Err(AccountStoreError::AccountNotFound(username.to_string()))
+            }
+        }
+    }
+}
+
+impl StreamNotificationsStore for InMemoryStore {
+    type Account = Account;
+
+    fn add_payment_notification_subscription(
+        &self,
+        id: Uuid,
+        sender: UnboundedSender<PaymentNotification>,
+    ) {
+        trace!("Added payment notification listener for {}", id);
+        self.subscriptions
+            .lock()
+            .entry(id)
+            .or_insert_with(Vec::new)
+            .push(sender);
+    }
+
+    fn publish_payment_notification(&self, payment: PaymentNotification) {
+        self.notify(payment)
+    }
+
+    fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification> {
+        self.payment_publisher.subscribe()
+    }
+}
+
+#[async_trait]
+impl StreamConnectionStore for InMemoryStore {
+    async fn set_stream_receive_max(
+        &self,
+        token: &str,
+        receive_max: u64,
+    ) -> Result<(), StreamConnectionStoreError> {
+        self.data
+            .write()
+            .stream_connections
+            .entry(token.to_string())
+            .or_default()
+            .receive_max = Some(receive_max);
+        Ok(())
+    }
+
+    async fn get_stream_connection(
+        &self,
+        token: &str,
+    ) -> Result<Option<StreamConnectionRecord>, StreamConnectionStoreError> {
+        Ok(self.data.read().stream_connections.get(token).cloned())
+    }
+
+    async fn add_stream_received(
+        &self,
+        token: &str,
+        amount: u64,
+    ) -> Result<StreamConnectionRecord, StreamConnectionStoreError> {
+        let mut data = self.data.write();
+        let record = data
+            .stream_connections
+            .entry(token.to_string())
+            .or_default();
+        record.add_received(token, amount)?;
+        trace!(
+            "STREAM connection {} received {} (total: {})",
+            token,
+            amount,
+            record.total_received
+        );
+        Ok(*record)
+    }
+
+    async fn close_stream_connection(
+        &self,
+        token: &str,
+    ) -> Result<Option<StreamConnectionRecord>, StreamConnectionStoreError> {
+        match self.data.write().stream_connections.get_mut(token) {
+            Some(record) if !record.closed => {
+                record.closed = true;
+                Ok(Some(*record))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl TransactionStore for InMemoryStore {
+    async fn record_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+    ) -> Result<(), TransactionStoreError> {
+        let mut data = self.data.write();
+        for transaction in transactions {
+            data.record_transaction(transaction);
+        }
+        Ok(())
+    }
+
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        query: TransactionQuery,
+    ) -> Result<Vec<TransactionRecord>, TransactionStoreError> {
+        let data = self.data.read();
+        let transactions = match data.transactions.get(&account_id) {
+            Some(transactions) => transactions,
+            None => return Ok(Vec::new()),
+        };
+        Ok(transactions
+            .iter()
+            .rev()
+            .skip_while(|record| query.before.map_or(false, |before| record.id >= before))
+            .take_while(|record| !query.is_exhausted_by(record))
+            .filter(|record| query.matches(&record.transaction))
+            .take(query.limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_trimmed_transaction_totals(
+        &self,
+        account_id: Uuid,
+    ) -> Result<TransactionTotals, TransactionStoreError> {
+        Ok(self
+            .data
+            .read()
+            .trimmed_transactions
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl ExchangeRateHistoryStore for InMemoryStore {
+    async fn record_exchange_rates(
+        &self,
+        timestamp: u64,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        let mut data = self.data.write();
+        let cutoff = timestamp.saturating_sub(data.rate_history_retention);
+        for (asset_code, rate) in rates {
+            let history = data.rate_history.entry(asset_code).or_default();
+            history.insert(timestamp, rate);
+            // Keep the last rate before the cutoff, which was still in effect
+            if let Some(kept) = history.range(..cutoff).next_back().map(|(kept, _)| *kept) {
+                *history = history.split_off(&kept);
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_exchange_rate_history(
+        &self,
+        asset_code: &str,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<RateSnapshot>, ExchangeRateStoreError> {
+        if from >= to {
+            return Ok(Vec::new());
+        }
+        let data = self.data.read();
+        Ok(data
+            .rate_history
+            .get(asset_code)
+            .map(|history| {
+                history
+                    .range(from..to)
+                    .take(limit)
+                    .map(|(timestamp, rate)| RateSnapshot {
+                        timestamp: *timestamp,
+                        rate: *rate,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl BalanceStore for InMemoryStore {
+    /// Returns the balance **from the account holder's perspective**, meaning the sum of
+    /// the Payable Balance and Pending Outgoing minus the Receivable Balance and the Pending Incoming.
+    async fn get_balance(&self, account_id: Uuid) -> Result<i64, BalanceStoreError> {
+        self.data
+            .read()
+            .balances
+            .get(&account_id)
+            .map(Balance::total)
+            .ok_or_else(|| {
+                BalanceStoreError::Other(Box::new(InMemoryStoreError::AccountNotFound(account_id)))
+            })
+    }
+
+    async fn update_balances_for_prepare(
+        &self,
+        from_account_id: Uuid,
+        incoming_amount: u64,
+    ) -> Result<(), BalanceStoreError> {
+        // Don't do anything if the amount was 0
+        if incoming_amount == 0 {
+            return Ok(());
+        }
+
+        let mut data = self.data.write();
+        let min_balance = data
+            .accounts
+            .get(&from_account_id)
+            .and_then(|account| account.min_balance);
+        let balance = data
+            .balance_mut(from_account_id)
+            .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+        let out_of_range = || {
+            BalanceStoreError::Other(Box::new(InMemoryStoreError::AmountOutOfRange {
+                account_id: from_account_id,
+                amount: incoming_amount,
+            }))
+        };
+        let amount = i64::try_from(incoming_amount).map_err(|_| out_of_range())?;
+        let total = balance
+            .total()
+            .checked_sub(amount)
+            .ok_or_else(out_of_range)?;
+
+        // Check that the prepare wouldn't go under the account's minimum balance
+        if let Some(min_balance) = min_balance {
+            if total < min_balance {
+                return Err(BalanceStoreError::Other(Box::new(
+                    InMemoryStoreError::MinBalanceExceeded {
+                        account_id: from_account_id,
+                        amount: incoming_amount,
+                        balance: balance.balance,
+                        min_balance,
+                    },
+                )));
+            }
+        }
+
+        // Deduct the amount from the prepaid_amount and/or the balance
+        if balance.prepaid_amount >= amount {
+            balance.prepaid_amount -= amount;
+        } else if balance.prepaid_amount > 0 {
+            balance.balance = balance
+                .balance
+                .checked_sub(amount - balance.prepaid_amount)
+                .ok_or_else(out_of_range)?;
+            balance.prepaid_amount = 0;
+        } else {
+            balance.balance = balance
+                .balance
+                .checked_sub(amount)
+                .ok_or_else(out_of_range)?;
+        }
+
+        trace!(
+            "Processed prepare with incoming amount: {}. Account {} has balance (including prepaid amount): {} ",
+            incoming_amount, from_account_id, balance.total()
+        );
+        Ok(())
+    }
+
+    async fn update_balances_for_fulfill(
+        &self,
+        to_account_id: Uuid,
+        outgoing_amount: u64,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        let mut data = self.data.write();
+        let settlement = data
+            .accounts
+            .get(&to_account_id)
+            .map(|account| (account.settle_threshold, account.settle_to));
+        let balance = data
+            .balance_mut(to_account_id)
+            .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+        balance.balance = credit(balance.balance, to_account_id, outgoing_amount)
+            .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+
+        // Settlement is triggered if both the threshold and settle_to are set, the balance
+        // reached the threshold and the threshold is greater than settle_to. The balance
+        // is updated _before_ sending the settlement so that we don't accidentally send
+        // multiple settlements for the same balance. If the settlement fails the balance
+        // change is rolled back with `refund_settlement`.
+        let mut amount_to_settle = 0;
+        if let Some((Some(settle_threshold), Some(settle_to))) = settlement {
+            if balance.balance >= settle_threshold && settle_threshold > settle_to {
+                amount_to_settle = (balance.balance - settle_to) as u64;
+                balance.balance = settle_to;
+            }
+        }
+
+        trace!(
+            "Processed fulfill for account {} for outgoing amount {}. Fulfill call result: {} {}",
+            to_account_id,
+            outgoing_amount,
+            balance.total(),
+            amount_to_settle,
+        );
+        Ok((balance.total(), amount_to_settle))
+    }
+
+    async fn update_balances_for_reject(
+        &self,
+        from_account_id: Uuid,
+        incoming_amount: u64,
+    ) -> Result<(), BalanceStoreError> {
+        if incoming_amount == 0 {
+            return Ok(());
+        }
+
+        let mut data = self.data.write();
+        let balance = data
+            .balance_mut(from_account_id)
+            .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+        balance.balance = credit(balance.balance, from_account_id, incoming_amount)
+            .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+
+        trace!(
+            "Processed reject for incoming amount: {}. Account {} has balance (including prepaid amount): {}",
+            incoming_amount, from_account_id, balance.total()
+        );
+        Ok(())
+    }
+
+    async fn update_balances_for_delayed_settlement(
+        &self,
+        to_account_id: Uuid,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        let mut data = self.data.write();
+        let settlement = data
+            .accounts
+            .get(&to_account_id)
+            .map(|account| (account.settle_threshold, account.settle_to));
+        let balance = data
+            .balance_mut(to_account_id)
+            .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+
+        // Upon completion the balance is at the level of settle_to
+        let mut amount_to_settle = 0;
+        if let Some((Some(settle_threshold), Some(settle_to))) = settlement {
+            if settle_threshold > settle_to && balance.balance >= settle_to {
+                amount_to_settle = (balance.balance - settle_to) as u64;
+                balance.balance = settle_to;
+            }
+        }
+
+        trace!(
+            "Processed account {} for delayed settlement, balance: {}, to_settle: {}",
+            to_account_id,
+            balance.total(),
+            amount_to_settle
+        );
+        Ok((balance.total(), amount_to_settle))
+    }
+}
+
+impl ExchangeRateStore for InMemoryStore {
+    fn get_exchange_rates(&self, asset_codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError> {
+        let rates: Vec<f64> = asset_codes
+            .iter()
+            .filter_map(|code| (*self.exchange_rates.read()).get(*code).cloned())
+            .collect();
+        if rates.len() == asset_codes.len() {
+            Ok(rates)
+        } else {
+            Err(ExchangeRateStoreError::PairNotFound {
+                from: asset_codes[0].to_string(),
+                to: asset_codes[1].to_string(),
+            })
+        }
+    }
+
+    fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        Ok((*self.exchange_rates.read()).clone())
+    }
+
+    fn set_exchange_rates(
+        &self,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        (*self.exchange_rates.write()) = rates;
+        self.exchange_rates_fetched_at.write().clear();
+        Ok(())
+    }
+
+    fn set_exchange_rates_fetched_at(
+        &self,
+        rates: HashMap<String, f64>,
+        fetched_at: HashMap<String, u64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        let mut exchange_rates = self.exchange_rates.write();
+        *exchange_rates = rates;
+        (*self.exchange_rates_fetched_at.write()) = fetched_at;
+        Ok(())
+    }
+
+    fn get_exchange_rates_fetched_at(
+        &self,
+        asset_codes: &[&str],
+    ) -> Result<Vec<Option<u64>>, ExchangeRateStoreError> {
+        let fetched_at = self.exchange_rates_fetched_at.read();
+        Ok(asset_codes
+            .iter()
+            .map(|code| fetched_at.get(*code).cloned())
+            .collect())
+    }
+}
+
+impl FeePolicyStore for InMemoryStore {
+    fn get_fee_policy(&self) -> Arc<FeePolicy> {
+        self.fee_policy.read().clone()
+    }
+
+    fn set_fee_policy(&self, policy: FeePolicy) {
+        *self.fee_policy.write() = Arc::new(policy);
+    }
+}
+
+#[async_trait]
+impl BtpStore for InMemoryStore {
+    type Account = Account;
+
+    async fn get_account_from_btp_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, BtpStoreError> {
+        let data = self.data.read();
+        let account = data
+            .usernames
+            .get(username.as_ref())
+            .and_then(|id| data.load_account(*id));
+
+        if let Some(account) = account {
+            match account.ilp_over_btp_incoming_token {
+                Some(ref t) if t.expose_secret().as_ref() == token.as_bytes() => Ok(account),
+                Some(_) => {
+                    debug!(
+                        "Found account {} but BTP auth token was wrong",
+                        account.username
+                    );
+                    Err(BtpStoreError::Unauthorized(username.to_string()))
+                }
+                None => {
+                    debug!(
+                        "Account {} does not have an incoming btp token configured",
+                        account.username
+                    );
+                    Err(BtpStoreError::Unauthorized(username.to_string()))
+                }
+            }
+        } else {
+            warn!("No account found with BTP token");
+            Err(BtpStoreError::AccountNotFound(username.to_string()))
+        }
+    }
+
+    async fn get_btp_outgoing_accounts(&self) -> Result<Vec<Self::Account>, BtpStoreError> {
+        Ok(self
+            .data
+            .read()
+            .filter_accounts(|account| account.ilp_over_btp_url.is_some()))
+    }
+}
+
+#[async_trait]
+impl HttpStore for InMemoryStore {
+    type Account = Account;
+
+    /// Checks if the stored token for the provided account id matches the
+    /// provided token, and if so, returns the account associated with that token
+    async fn get_account_from_http_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, HttpStoreError> {
+        let data = self.data.read();
+        let account = data
+            .usernames
+            .get(username.as_ref())
+            .and_then(|id| data.load_account(*id));
+
+        if let Some(account) = account {
+            match account.ilp_over_http_incoming_token {
+                Some(ref t) if t.expose_secret().as_ref() == token.as_bytes() => Ok(account),
+                _ => Err(HttpStoreError::Unauthorized(username.to_string())),
+            }
+        } else {
+            warn!("No account found with given HTTP auth");
+            Err(HttpStoreError::AccountNotFound(username.to_string()))
+        }
+    }
+}
+
+impl RouterStore for InMemoryStore {
+    fn routing_table(&self) -> Arc<RoutingTable<Route>> {
+        self.routes.read().clone()
+    }
+}
+
+#[async_trait]
+impl NodeStore for InMemoryStore {
+    type Account = Account;
+
+    async fn insert_account(
+        &self,
+        account: AccountDetails,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let id = Uuid::new_v4();
+        let account = Account::try_from(id, account, self.get_ilp_address())
+            .map_err(NodeStoreError::InvalidAccount)?;
+        debug!(
+            "Generated account id for {}: {}",
+            account.username, account.id
+        );
+        self.insert_account_data(account)
+    }
+
+    async fn delete_account(&self, id: Uuid) -> Result<Account, NodeStoreError> {
+        self.delete_account_data(id)
+    }
+
+    async fn update_account(
+        &self,
+        id: Uuid,
+        account: AccountDetails,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let account = Account::try_from(id, account, self.get_ilp_address())
+            .map_err(NodeStoreError::InvalidAccount)?;
+        self.update_account_data(account)
+    }
+
+    async fn modify_account_settings(
+        &self,
+        id: Uuid,
+        settings: AccountSettings,
+    ) -> Result<Self::Account, NodeStoreError> {
+        self.modify_account_data(id, settings)
+    }
+
+    async fn get_all_accounts(&self) -> Result<Vec<Self::Account>, NodeStoreError> {
+        Ok(self.data.read().all_accounts())
+    }
+
+    async fn set_static_routes<R, T>(&self, routes: R) -> Result<(), NodeStoreError>
+    where
+        R: IntoIterator<Item = (String, T)> + Send + 'async_trait,
+        T: Into<Route> + Send + 'async_trait,
+    {
+        let routes: HashMap<String, Route> = routes
+            .into_iter()
+            .map(|(prefix, route)| (prefix, route.into()))
+            .collect();
+        let mut data = self.data.write();
+        if !routes
+            .values()
+            .flat_map(|route| route.account_ids())
+            .all(|id| data.accounts.contains_key(&id))
+        {
+            error!("Error setting static routes because not all of the given accounts exist");
+            return Err(NodeStoreError::MissingAccounts);
+        }
+        data.static_routes = routes;
+        self.update_routes(&data);
+        Ok(())
+    }
+
+    async fn set_static_route(
+        &self,
+        prefix: String,
+        account_id: Uuid,
+    ) -> Result<(), NodeStoreError> {
+        let mut data = self.data.write();
+        if !data.accounts.contains_key(&account_id) {
+            error!(
+                "Cannot set static route for prefix: {} because account {} does not exist",
+                prefix, account_id
+            );
+            return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
+        }
+        data.static_routes.insert(prefix, Route::from(account_id));
+        self.update_routes(&data);
+        Ok(())
+    }
+
+    async fn set_default_route(&self, account_id: Uuid) -> Result<(), NodeStoreError> {
+        let mut data = self.data.write();
+        if !data.accounts.contains_key(&account_id) {
+            error!(
+                "Cannot set default route because account {} does not exist",
+                account_id
+            );
+            return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
+        }
+        data.default_route = Some(account_id);
+        debug!("Set default route to account id: {}", account_id);
+        self.update_routes(&data);
+        Ok(())
+    }
+
+    async fn set_settlement_engines(
+        &self,
+        asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
+    ) -> Result<(), NodeStoreError> {
+        let asset_to_url_map: Vec<(String, Url)> = asset_to_url_map.into_iter().collect();
+        debug!("Setting settlement engines to {:?}", asset_to_url_map);
+        self.data
+            .write()
+            .settlement_engines
+            .extend(asset_to_url_map);
+        Ok(())
+    }
+
+    async fn get_asset_settlement_engine(
+        &self,
+        asset_code: &str,
+    ) -> Result<Option<Url>, NodeStoreError> {
+        Ok(self.data.read().settlement_engines.get(asset_code).cloned())
+    }
+}
+
+#[async_trait]
+impl AddressStore for InMemoryStore {
+    // Updates the ILP address of the store & iterates over all children and
+    // updates their ILP Address to match the new address.
+    async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
+        debug!("Setting ILP address to: {}", ilp_address);
+        self.set_ilp_address_data(ilp_address);
+        Ok(())
+    }
+
+    async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+        self.data.write().parent_ilp_address = None;
+        // overwrite the ilp address with the default value
+        *(self.ilp_address.write()) = DEFAULT_ILP_ADDRESS.clone();
+        Ok(())
+    }
+
+    fn get_ilp_address(&self) -> Address {
+        self.ilp_address.read().clone()
+    }
+}
+
+type Routes<A> = HashMap<String, A>;
+
+#[async_trait]
+impl CcpRoutingStore for InMemoryStore {
+    type Account = Account;
+
+    async fn get_accounts_to_send_routes_to(
+        &self,
+        ignore_accounts: Vec<Uuid>,
+    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        Ok(self.data.read().filter_accounts(|account| {
+            account.should_send_routes() && !ignore_accounts.contains(&account.id)
+        }))
+    }
+
+    async fn get_accounts_to_receive_routes_from(
+        &self,
+    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        Ok(self
+            .data
+            .read()
+            .filter_accounts(|account| account.should_receive_routes()))
+    }
+
+    async fn get_local_and_configured_routes(
+        &self,
+    ) -> Result<(Routes<Account>, Routes<Account>), CcpRoutingStoreError> {
+        let data = self.data.read();
+        let local_table: HashMap<String, Account> = data
+            .all_accounts()
+            .into_iter()
+            .map(|account| (account.ilp_address.to_string(), account))
+            .collect();
+
+        let configured_table: HashMap<String, Account> = data
+            .static_routes
+            .iter()
+            .filter_map(|(prefix, route)| {
+                // Advertise the route through the first of its next hops which still exists
+                if let Some(account) = route.account_ids().find_map(|id| data.load_account(id)) {
+                    Some((prefix.clone(), account))
+                } else {
+                    warn!(
+                        "No account for route: {}, ignoring configured route for prefix: {}",
+                        route, prefix
+                    );
+                    None
+                }
+            })
+            .collect();
+
+        Ok((local_table, configured_table))
+    }
+
+    async fn set_routes(
+        &mut self,
+        routes: impl IntoIterator<Item = (String, Account)> + Send + 'async_trait,
+    ) -> Result<(), CcpRoutingStoreError> {
+        let routes: HashMap<String, Uuid> = routes
+            .into_iter()
+            .map(|(prefix, account)| (prefix, account.id))
+            .collect();
+        let mut data = self.data.write();
+        trace!("Saved {} routes", routes.len());
+        data.routes = routes;
+        self.update_routes(&data);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for InMemoryStore {
+    type Account = Account;
+
+    /// Apply rate limits for number of packets per minute and amount of money per minute
+    ///
+    /// This uses the same generic cell rate algorithm as [redis-cell](https://github.com/brandur/redis-cell)
+    async fn apply_rate_limits(
+        &self,
+        account: Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        if account.amount_per_minute_limit.is_none() && account.packets_per_minute_limit.is_none() {
+            return Ok(());
+        }
+
+        // Both limits are always applied, even if the first one is exceeded
+        let packets_limited = account.packets_per_minute_limit.map(|limit| {
+            let limit = u64::from(limit.saturating_sub(1));
+            self.rate_limiter
+                .throttle(format!("limit:packets:{}", account.id), limit, limit, 1)
+        });
+        let amount_limited = account.amount_per_minute_limit.map(|limit| {
+            let limit = limit.saturating_sub(1);
+            self.rate_limiter.throttle(
+                format!("limit:throughput:{}", account.id),
+                limit,
+                limit,
+                prepare_amount,
+            )
+        });
+
+        if packets_limited == Some(true) {
+            Err(RateLimitError::PacketLimitExceeded)
+        } else if amount_limited == Some(true) {
+            Err(RateLimitError::ThroughputLimitExceeded)
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn refund_throughput_limit(
+        &self,
+        account: Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        if let Some(limit) = account.amount_per_minute_limit {
+            self.rate_limiter.refund(
+                &format!("limit:throughput:{}", account.id),
+                limit.saturating_sub(1),
+                prepare_amount,
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IdempotentStore for InMemoryStore {
+    async fn load_idempotent_data(
+        &self,
+        idempotency_key: String,
+    ) -> Result<Option<IdempotentData>, IdempotentStoreError> {
+        let data = self.data.read();
+        match data.idempotent_data.get(&idempotency_key) {
+            Some((idempotent_data, saved_at)) if saved_at.elapsed() < IDEMPOTENCY_KEY_TTL => {
+                trace!(
+                    "Loaded idempotency key {:?} - {:?}",
+                    idempotency_key,
+                    idempotent_data
+                );
+                Ok(Some(idempotent_data.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn save_idempotent_data(
+        &self,
+        idempotency_key: String,
+        input_hash: [u8; 32],
+        status_code: StatusCode,
+        data: Bytes,
+    ) -> Result<(), IdempotentStoreError> {
+        trace!(
+            "Cached {:?}: {:?}, {:?}",
+            idempotency_key,
+            status_code,
+            data,
+        );
+        self.data.write().idempotent_data.insert(
+            idempotency_key,
+            (
+                IdempotentData::new(status_code, data, input_hash),
+                Instant::now(),
+            ),
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SettlementStore for InMemoryStore {
+    type Account = Account;
+
+    async fn update_balance_for_incoming_settlement(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: Option<String>,
+    ) -> Result<(), SettlementStoreError> {
+        let mut data = self.data.write();
+        if !data.balances.contains_key(&account_id) {
+            return Err(SettlementStoreError::Other(Box::new(
+                InMemoryStoreError::AccountNotFound(account_id),
+            )));
+        }
+
+        // If the idempotency key has been used, then do not perform any operations
+        if let Some(idempotency_key) = idempotency_key {
+            if let Some(used_at) = data.settlement_idempotency_keys.get(&idempotency_key) {
+                if used_at.elapsed() < IDEMPOTENCY_KEY_TTL {
+                    return Ok(());
+                }
+            }
+            data.settlement_idempotency_keys
+                .insert(idempotency_key, Instant::now());
+        }
+
+        // Credit the incoming settlement to the balance and/or prepaid amount,
+        // depending on whether that account currently owes money or not
+        let balance = data.balances.get_mut(&account_id).unwrap();
+        let out_of_range = || {
+            SettlementStoreError::Other(Box::new(InMemoryStoreError::AmountOutOfRange {
+                account_id,
+                amount,
+            }))
+        };
+        if balance.balance >= 0 {
+            balance.prepaid_amount =
+                credit(balance.prepaid_amount, account_id, amount).map_err(|_| out_of_range())?;
+        } else {
+            // Whatever is left once the balance is back to 0 is prepaid
+            let credited =
+                credit(balance.balance, account_id, amount).map_err(|_| out_of_range())?;
+            if credited <= 0 {
+                balance.balance = credited;
+            } else {
+                balance.prepaid_amount = balance
+                    .prepaid_amount
+                    .checked_add(credited)
+                    .ok_or_else(out_of_range)?;
+                balance.balance = 0;
+            }
+        }
+
+        trace!(
+            "Processed incoming settlement from account: {} for amount: {}. Balance is now: {}",
+            account_id,
+            amount,
+            balance.total()
+        );
+        data.record_transaction(Transaction::settlement(
+            account_id,
+            TransactionKind::IncomingSettlement,
+            amount,
+        ));
+        Ok(())
+    }
+
+    async fn refund_settlement(
+        &self,
+        account_id: Uuid,
+        settle_amount: u64,
+    ) -> Result<(), SettlementStoreError> {
+        trace!(
+            "Refunding settlement for account: {} of amount: {}",
+            account_id,
+            settle_amount
+        );
+        let mut data = self.data.write();
+        let balance = data
+            .balance_mut(account_id)
+            .map_err(|err| SettlementStoreError::Other(Box::new(err)))?;
+        balance.balance = credit(balance.balance, account_id, settle_amount)
+            .map_err(|err| SettlementStoreError::Other(Box::new(err)))?;
+
+        trace!(
+            "Refunded settlement for account: {} of amount: {}. Balance is now: {}",
+            account_id,
+            settle_amount,
+            balance.balance
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LeftoversStore for InMemoryStore {
+    type AccountId = Uuid;
+    type AssetType = BigUint;
+
+    async fn get_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        Ok(self
+            .data
+            .write()
+            .take_uncredited_settlement_amount(account_id))
+    }
+
+    async fn peek_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        Ok(self
+            .data
+            .read()
+            .peek_uncredited_settlement_amount(account_id))
+    }
+
+    async fn save_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+        uncredited_settlement_amount: (Self::AssetType, u8),
+    ) -> Result<(), LeftoversStoreError> {
+        trace!(
+            "Saving uncredited_settlement_amount {:?} {:?}",
+            account_id,
+            uncredited_settlement_amount
+        );
+        self.data
+            .write()
+            .uncredited_amounts
+            .entry(account_id)
+            .or_insert_with(Vec::new)
+            .push(uncredited_settlement_amount);
+        Ok(())
+    }
+
+    async fn load_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+        local_scale: u8,
+    ) -> Result<Self::AssetType, LeftoversStoreError> {
+        trace!("Loading uncredited_settlement_amount {:?}", account_id);
+        let mut data = self.data.write();
+        let amount = data.take_uncredited_settlement_amount(account_id);
+        // scale the amount from the max scale to the local scale, and then
+        // save any potential leftovers to the store
+        let (scaled_amount, precision_loss) =
+            scale_with_precision_loss(amount.0, local_scale, amount.1);
+
+        if precision_loss > BigUint::from(0u32) {
+            data.uncredited_amounts
+                .entry(account_id)
+                .or_insert_with(Vec::new)
+                .push((precision_loss, std::cmp::max(local_scale, amount.1)));
+        }
+
+        Ok(scaled_amount)
+    }
+
+    async fn clear_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(), LeftoversStoreError> {
+        trace!("Clearing uncredited_settlement_amount {:?}", account_id);
+        self.data.write().uncredited_amounts.remove(&account_id);
+        Ok(())
+    }
+}
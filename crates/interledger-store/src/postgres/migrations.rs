@@ -0,0 +1,111 @@
+use super::PostgresStoreError;
+use deadpool_postgres::Client;
+use tracing::{debug, info};
+
+/// The versioned schema migrations, in the order in which they are applied.
+///
+/// A migration must never be changed once it has been released. Schema
+/// changes are made by appending a new migration with the next version.
+static MIGRATIONS: &[(i32, &str, &str)] = &[
+    (1, "initial", include_str!("migrations/0001_initial.sql")),
+    (
+        2,
+        "static_route_hops",
+        include_str!("migrations/0002_static_route_hops.sql"),
+    ),
+    (
+        3,
+        "stream_connections",
+        include_str!("migrations/0003_stream_connections.sql"),
+    ),
+    (
+        4,
+        "http_client_settings",
+        include_str!("migrations/0004_http_client_settings.sql"),
+    ),
+    (
+        5,
+        "transactions",
+        include_str!("migrations/0005_transactions.sql"),
+    ),
+    (
+        6,
+        "rate_history",
+        include_str!("migrations/0006_rate_history.sql"),
+    ),
+    (
+        7,
+        "transaction_history",
+        include_str!("migrations/0007_transaction_history.sql"),
+    ),
+    (
+        8,
+        "settlement_acceptance",
+        include_str!("migrations/0008_settlement_acceptance.sql"),
+    ),
+];
+
+/// Arbitrary key of the advisory lock which is held while migrating, so that
+/// nodes which share a database and start at the same time do not race
+const MIGRATIONS_LOCK_ID: i64 = 0x696c_705f_6d69_6772;
+
+/// The latest schema version known to this version of the store
+fn latest_version() -> i32 {
+    MIGRATIONS
+        .last()
+        .map(|(version, _, _)| *version)
+        .unwrap_or(0)
+}
+
+/// Brings the database schema up to date by applying every migration which is
+/// not yet recorded in the `schema_migrations` table. Each migration is applied
+/// in its own transaction together with its record, so a failed migration
+/// leaves the schema at the previous version.
+pub(super) async fn run_migrations(client: &mut Client) -> Result<(), PostgresStoreError> {
+    client
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+        )
+        .await?;
+
+    for (version, name, sql) in MIGRATIONS {
+        let tx = client.transaction().await?;
+        tx.execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATIONS_LOCK_ID])
+            .await?;
+
+        let current: i32 = tx
+            .query_one(
+                "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+                &[],
+            )
+            .await?
+            .try_get(0)?;
+        if current > latest_version() {
+            return Err(PostgresStoreError::SchemaTooNew {
+                found: current,
+                supported: latest_version(),
+            });
+        }
+
+        if current < *version {
+            info!("Applying database migration {} ({})", version, name);
+            tx.batch_execute(sql).await?;
+            tx.execute(
+                "INSERT INTO schema_migrations (version, name) VALUES ($1, $2)",
+                &[version, name],
+            )
+            .await?;
+        } else {
+            debug!(
+                "Database migration {} ({}) is already applied",
+                version, name
+            );
+        }
+        tx.commit().await?;
+    }
+    Ok(())
+}
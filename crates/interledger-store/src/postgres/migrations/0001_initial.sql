@@ -0,0 +1,100 @@
+-- Initial schema of the Postgres store.
+--
+-- Amounts which are unsigned 64-bit integers in the node (max_packet_amount,
+-- amount_per_minute_limit) are stored as NUMERIC(20, 0) since Postgres has no
+-- unsigned types. Balances are signed 64-bit integers, like in the Redis store.
+
+CREATE TABLE accounts (
+    id UUID PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    ilp_address TEXT NOT NULL,
+    asset_code TEXT NOT NULL,
+    asset_scale SMALLINT NOT NULL,
+    max_packet_amount NUMERIC(20, 0) NOT NULL,
+    min_balance BIGINT,
+    ilp_over_http_url TEXT,
+    ilp_over_http_incoming_token BYTEA,
+    ilp_over_http_outgoing_token BYTEA,
+    ilp_over_btp_url TEXT,
+    ilp_over_btp_incoming_token BYTEA,
+    ilp_over_btp_outgoing_token BYTEA,
+    settle_threshold BIGINT,
+    settle_to BIGINT,
+    routing_relation TEXT NOT NULL,
+    round_trip_time BIGINT NOT NULL,
+    packets_per_minute_limit BIGINT,
+    amount_per_minute_limit NUMERIC(20, 0),
+    settlement_engine_url TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- The net position with each account holder, see the store's README for details
+CREATE TABLE balances (
+    account_id UUID PRIMARY KEY REFERENCES accounts (id) ON DELETE CASCADE,
+    balance BIGINT NOT NULL DEFAULT 0,
+    prepaid_amount BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Append-only log of every settlement which changed a balance. There is no foreign
+-- key to the accounts so that the history is kept when an account is deleted.
+--   incoming: a settlement received from the account holder
+--   outgoing: a settlement triggered by a fulfill (or a delayed settlement)
+--   refund:   an outgoing settlement which failed and was credited back
+CREATE TABLE settlements (
+    id BIGSERIAL PRIMARY KEY,
+    account_id UUID NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('incoming', 'outgoing', 'refund')),
+    amount BIGINT NOT NULL,
+    idempotency_key TEXT,
+    balance_after BIGINT NOT NULL,
+    prepaid_amount_after BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX settlements_account_id ON settlements (account_id, created_at);
+CREATE INDEX settlements_idempotency_key ON settlements (idempotency_key, created_at)
+    WHERE idempotency_key IS NOT NULL;
+
+-- Dynamic routing table (set by CCP)
+CREATE TABLE routes (
+    prefix TEXT PRIMARY KEY,
+    account_id UUID NOT NULL
+);
+
+-- Static routing table, which takes precedence over the dynamic one
+CREATE TABLE static_routes (
+    prefix TEXT PRIMARY KEY,
+    account_id UUID NOT NULL
+);
+
+-- Node-wide settings, such as the parent's ILP address and the default route
+CREATE TABLE node_settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE TABLE settlement_engines (
+    asset_code TEXT PRIMARY KEY,
+    url TEXT NOT NULL
+);
+
+-- Leftovers from incoming settlements which could not be credited due to precision loss
+CREATE TABLE uncredited_settlement_amounts (
+    id BIGSERIAL PRIMARY KEY,
+    account_id UUID NOT NULL,
+    amount NUMERIC NOT NULL,
+    scale SMALLINT NOT NULL
+);
+
+CREATE INDEX uncredited_settlement_amounts_account_id
+    ON uncredited_settlement_amounts (account_id);
+
+-- Cached responses of the settlement API
+CREATE TABLE idempotent_responses (
+    idempotency_key TEXT PRIMARY KEY,
+    status INTEGER NOT NULL,
+    body BYTEA NOT NULL,
+    input_hash BYTEA NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,16 @@
+-- Static routes can have several next hops, which the Router tries in order or
+-- picks by weight. Each next hop is a row and its position is its place in the route.
+-- Weights are unsigned 32-bit integers, so they are stored as BIGINT.
+
+CREATE TABLE static_route_hops (
+    prefix TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    account_id UUID NOT NULL,
+    weight BIGINT CHECK (weight BETWEEN 0 AND 4294967295),
+    PRIMARY KEY (prefix, position)
+);
+
+INSERT INTO static_route_hops (prefix, position, account_id)
+    SELECT prefix, 0, account_id FROM static_routes;
+
+DROP TABLE static_routes;
@@ -0,0 +1,11 @@
+-- State of the STREAM connections of a stateful receiver, keyed by the connection
+-- token (the last segment of the connection's ILP address). The amounts are
+-- unsigned 64-bit integers, so they are stored as NUMERIC(20, 0).
+
+CREATE TABLE stream_connections (
+    token TEXT PRIMARY KEY,
+    total_received NUMERIC(20, 0) NOT NULL DEFAULT 0,
+    receive_max NUMERIC(20, 0),
+    closed BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,4 @@
+-- Settings of the HTTP client used to forward packets to an account over HTTP,
+-- stored as JSON next to the account's ilp_over_http_url.
+
+ALTER TABLE accounts ADD COLUMN ilp_over_http_client TEXT;
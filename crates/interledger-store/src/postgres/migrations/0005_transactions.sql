@@ -0,0 +1,22 @@
+-- History of the changes of each account's balance: the packets it sent and
+-- received, and its settlements. Unlike the settlements table, the history is
+-- deleted along with the account. The id orders the transactions of an account
+-- in the order they were recorded, and is the cursor for paging through them.
+-- The timestamp is in milliseconds since the Unix epoch.
+
+CREATE TABLE transactions (
+    id BIGSERIAL PRIMARY KEY,
+    account_id UUID NOT NULL REFERENCES accounts (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN (
+        'incoming_packet', 'outgoing_packet', 'incoming_settlement',
+        'outgoing_settlement', 'settlement_refund'
+    )),
+    amount NUMERIC(20, 0) NOT NULL,
+    counterparty TEXT,
+    destination TEXT,
+    outcome TEXT CHECK (outcome IN ('fulfilled', 'rejected')),
+    error_code TEXT,
+    timestamp BIGINT NOT NULL
+);
+
+CREATE INDEX transactions_account_id ON transactions (account_id, id);
@@ -0,0 +1,11 @@
+-- History of the exchange rates the node used: each asset's price in USD, by the
+-- time it was recorded, in milliseconds since the Unix epoch. The rates are kept
+-- when the current ones are replaced, so that past packets and settlements can be
+-- repriced or audited with the rates in effect back then.
+
+CREATE TABLE rate_history (
+    asset_code TEXT NOT NULL,
+    timestamp BIGINT NOT NULL,
+    rate DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (asset_code, timestamp)
+);
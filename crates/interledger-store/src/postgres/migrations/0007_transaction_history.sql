@@ -0,0 +1,18 @@
+-- The number of transactions kept in the history of each account, and the totals
+-- of the ones which were trimmed from it once it grew past the maximum, so that
+-- the account can still be reconciled with its whole history. The amounts are
+-- u64 sums, so they are stored as NUMERIC like the other amounts.
+
+CREATE TABLE transaction_history (
+    account_id UUID PRIMARY KEY REFERENCES accounts (id) ON DELETE CASCADE,
+    retained BIGINT NOT NULL DEFAULT 0,
+    packets_in NUMERIC(20, 0) NOT NULL DEFAULT 0,
+    packets_out NUMERIC(20, 0) NOT NULL DEFAULT 0,
+    settled_in NUMERIC(20, 0) NOT NULL DEFAULT 0,
+    settled_out NUMERIC(20, 0) NOT NULL DEFAULT 0,
+    refunded NUMERIC(20, 0) NOT NULL DEFAULT 0,
+    refunds BIGINT NOT NULL DEFAULT 0
+);
+
+INSERT INTO transaction_history (account_id, retained)
+SELECT account_id, COUNT(*) FROM transactions GROUP BY account_id;
@@ -0,0 +1,14 @@
+-- Outgoing settlements are recorded before they are sent to the settlement engine,
+-- and again once the engine accepted them, so that the reconciliation report finds
+-- the ones the engine never answered for. The history keeps the total of the
+-- accepted settlements which were trimmed from it.
+
+ALTER TABLE transactions
+    DROP CONSTRAINT transactions_kind_check,
+    ADD CONSTRAINT transactions_kind_check CHECK (kind IN (
+        'incoming_packet', 'outgoing_packet', 'incoming_settlement',
+        'outgoing_settlement', 'settlement_accepted', 'settlement_refund'
+    ));
+
+ALTER TABLE transaction_history
+    ADD COLUMN accepted_out NUMERIC(20, 0) NOT NULL DEFAULT 0;
@@ -0,0 +1,2704 @@
+// The Postgres store keeps the same data as the Redis store in relational tables, so that
+// balances and settlements can be audited and joined against other data. The schema is
+// created and upgraded by the versioned migrations in the `migrations` directory.
+//
+// The balance updates run as SQL transactions which lock the account's row in the
+// `balances` table, and enforce the same invariants as the Lua scripts of the Redis store.
+// Every settlement is also appended to the `settlements` table, and every change of a
+// balance to the `transactions` table, which is the history of the account. Once the
+// history of an account grows past the maximum, its oldest transactions are folded into
+// the totals of the `transaction_history` table.
+// Like the Redis store, the current exchange rates and rate limits only live in memory,
+// while their history is kept in the `rate_history` table.
+use super::account::{Account, AccountWithEncryptedTokens};
+use super::crypto::{encrypt_token, generate_keys, DecryptionKey, EncryptionKey};
+use super::rate_limit::RateLimiter;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use deadpool_postgres::{Client, Manager, Pool, PoolError};
+use futures::channel::mpsc::UnboundedSender;
+use http::StatusCode;
+use interledger_api::{AccountDetails, AccountSettings, NodeStore};
+use interledger_btp::BtpStore;
+use interledger_ccp::{CcpRoutingAccount, CcpRoutingStore, RoutingRelation};
+use interledger_errors::*;
+use interledger_http::HttpStore;
+use interledger_packet::Address;
+use interledger_rates::{
+    ExchangeRateHistoryStore, ExchangeRateStore, RateSnapshot, DEFAULT_RATE_HISTORY_RETENTION,
+};
+use interledger_router::{NextHop, Route, RouterStore, RoutingTable};
+use interledger_service::{AccountStore, AddressStore, Username};
+use interledger_service_util::{
+    BalanceStore, FeePolicy, FeePolicyStore, RateLimitError, RateLimitStore,
+    Transaction as AccountTransaction, TransactionKind, TransactionQuery, TransactionRecord,
+    TransactionStore, TransactionTotals, DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT,
+};
+use interledger_settlement::core::{
+    idempotency::{IdempotentData, IdempotentStore},
+    scale_with_precision_loss,
+    types::{Convert, ConvertDetails, LeftoversStore, SettlementStore},
+};
+use interledger_stream::{
+    PaymentNotification, StreamConnectionRecord, StreamConnectionStore, StreamNotificationsStore,
+};
+use num_bigint::BigUint;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use postgres_crate::{error::SqlState, types::ToSql, Config, NoTls, Row, Transaction};
+use secrecy::{ExposeSecret, Secret, SecretBytesMut, SecretString};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::{TryFrom, TryInto},
+    str::FromStr,
+    sync::{Arc, Weak},
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::sync::broadcast;
+use tracing::{debug, error, trace, warn};
+use url::Url;
+use uuid::Uuid;
+use zeroize::Zeroize;
+
+mod migrations;
+
+static PARENT_ILP_KEY: &str = "parent_node_account_address";
+static DEFAULT_ROUTE_KEY: &str = "routes:default";
+
+/// Default maximum number of connections kept open to the database
+const DEFAULT_POOL_SIZE: usize = 16;
+/// Default interval at which the routing table is reloaded from the database
+const DEFAULT_POLL_INTERVAL: u64 = 30000; // 30 seconds
+
+/// The node's default ILP Address
+static DEFAULT_ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("local.host").unwrap());
+
+/// The columns which are selected to load an account. The u64 amounts are stored as
+/// NUMERIC and converted to text, since there is no unsigned integer type in Postgres.
+/// The settlement engine configured for the account's asset is used if the
+/// account does not have one of its own.
+static SELECT_ACCOUNTS: &str =
+    "SELECT a.id, a.username, a.ilp_address, a.asset_code, a.asset_scale,
+    a.max_packet_amount::TEXT, a.min_balance, a.ilp_over_http_url,
+    a.ilp_over_http_incoming_token, a.ilp_over_http_outgoing_token, a.ilp_over_btp_url,
+    a.ilp_over_btp_incoming_token, a.ilp_over_btp_outgoing_token, a.settle_threshold,
+    a.settle_to, a.routing_relation, a.round_trip_time, a.packets_per_minute_limit,
+    a.amount_per_minute_limit::TEXT, COALESCE(a.settlement_engine_url, e.url),
+    a.ilp_over_http_client
+    FROM accounts a LEFT JOIN settlement_engines e ON e.asset_code = a.asset_code";
+
+/// Errors which are specific to the Postgres store. These are wrapped in the
+/// `Other` variant of the errors of each store trait.
+#[derive(Error, Debug)]
+enum PostgresStoreError {
+    #[error("{0}")]
+    Postgres(#[from] postgres_crate::Error),
+    #[error("could not get a database connection: {0}")]
+    Pool(#[from] PoolError),
+    #[error("stored data is corrupted: {0}")]
+    Corrupted(String),
+    #[error("database schema version {found} is newer than the latest version supported by this node ({supported})")]
+    SchemaTooNew { found: i32, supported: i32 },
+    #[error("account `{0}` was not found")]
+    AccountNotFound(Uuid),
+    #[error("Incoming prepare of {amount} would bring account {account_id} under its minimum balance. Current balance: {balance}, min balance: {min_balance}")]
+    MinBalanceExceeded {
+        account_id: Uuid,
+        amount: u64,
+        balance: i64,
+        min_balance: i64,
+    },
+    #[error("Amount {amount} does not fit in the balance of account {account_id}")]
+    AmountOutOfRange { account_id: Uuid, amount: u64 },
+    #[error("{0}")]
+    Node(NodeStoreError),
+    #[error("{0}")]
+    Stream(StreamConnectionStoreError),
+}
+
+impl From<PostgresStoreError> for NodeStoreError {
+    fn from(err: PostgresStoreError) -> Self {
+        match err {
+            PostgresStoreError::AccountNotFound(id) => {
+                NodeStoreError::AccountNotFound(id.to_string())
+            }
+            PostgresStoreError::Node(err) => err,
+            err => NodeStoreError::Other(Box::new(err)),
+        }
+    }
+}
+
+impl From<PostgresStoreError> for StreamConnectionStoreError {
+    fn from(err: PostgresStoreError) -> Self {
+        match err {
+            PostgresStoreError::Stream(err) => err,
+            err => StreamConnectionStoreError::Other(Box::new(err)),
+        }
+    }
+}
+
+macro_rules! impl_from_postgres_store_error {
+    ($($error:ident),*) => {
+        $(
+            impl From<PostgresStoreError> for $error {
+                fn from(err: PostgresStoreError) -> Self {
+                    $error::Other(Box::new(err))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_postgres_store_error!(
+    AccountStoreError,
+    AddressStoreError,
+    BalanceStoreError,
+    BtpStoreError,
+    CcpRoutingStoreError,
+    ExchangeRateStoreError,
+    HttpStoreError,
+    IdempotentStoreError,
+    LeftoversStoreError,
+    SettlementStoreError,
+    TransactionStoreError
+);
+
+/// Builder for the Postgres Store
+pub struct PostgresStoreBuilder {
+    // Connection configuration, parsed from a `postgres://` URL
+    config: Config,
+    // The key used to encrypt the account tokens
+    secret: [u8; 32],
+    // Connector's ILP Address. Used to insert `Child` accounts as
+    node_ilp_address: Address,
+    // Maximum number of connections in the pool
+    pool_size: usize,
+    // Interval at which the routing table is reloaded, in milliseconds
+    poll_interval: u64,
+    // Number of transactions kept for each account
+    max_transactions_per_account: usize,
+    // How long the rates are kept in their history, in milliseconds
+    rate_history_retention: u64,
+}
+
+impl PostgresStoreBuilder {
+    /// Simple Constructor
+    pub fn new(config: Config, secret: [u8; 32]) -> Self {
+        PostgresStoreBuilder {
+            config,
+            secret,
+            node_ilp_address: DEFAULT_ILP_ADDRESS.clone(),
+            pool_size: DEFAULT_POOL_SIZE,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            max_transactions_per_account: DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT,
+            rate_history_retention: DEFAULT_RATE_HISTORY_RETENTION,
+        }
+    }
+
+    /// Sets the ILP Address corresponding to the node
+    pub fn node_ilp_address(&mut self, node_ilp_address: Address) -> &mut Self {
+        self.node_ilp_address = node_ilp_address;
+        self
+    }
+
+    /// Sets the maximum number of connections kept open to the database
+    pub fn pool_size(&mut self, pool_size: usize) -> &mut Self {
+        self.pool_size = pool_size;
+        self
+    }
+
+    /// Sets the interval (in milliseconds) at which the routing table is reloaded,
+    /// which picks up the changes made by other nodes sharing the database
+    pub fn poll_interval(&mut self, poll_interval: u64) -> &mut Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets the number of transactions kept for each account. Once an account has more,
+    /// its oldest transactions are removed and only their totals are kept
+    pub fn max_transactions_per_account(&mut self, max: usize) -> &mut Self {
+        self.max_transactions_per_account = max;
+        self
+    }
+
+    /// Sets how long (in milliseconds) the rates are kept in their history. The last rate
+    /// recorded before that is kept as well, since it was still in effect
+    pub fn rate_history_retention(&mut self, retention: u64) -> &mut Self {
+        self.rate_history_retention = retention;
+        self
+    }
+
+    /// Connects to the Postgres Store
+    ///
+    /// Specifically
+    /// 1. Generates encryption and decryption keys
+    /// 1. Connects to the database and applies any pending schema migrations
+    /// 1. Gets the Node address assigned to us by our parent (if it exists)
+    /// 1. Loads the routing table and starts polling it for changes
+    pub async fn connect(&mut self) -> Result<PostgresStore, ()> {
+        let (encryption_key, decryption_key) = generate_keys(&self.secret[..]);
+        self.secret.zeroize(); // clear the secret after it has been used for key generation
+
+        let pool = Pool::new(Manager::new(self.config.clone(), NoTls), self.pool_size);
+        let mut client = pool
+            .get()
+            .await
+            .map_err(|err| error!("Error connecting to Postgres: {:?}", err))?;
+        migrations::run_migrations(&mut client)
+            .await
+            .map_err(|err| error!("Error migrating the database schema: {}", err))?;
+
+        // Before using the store, check if we have an address
+        // that was configured due to adding a parent. If no parent was
+        // found, use the builder's provided address (local.host) or the
+        // one we decided to override it with
+        let parent_address = client
+            .query_opt(
+                "SELECT value FROM node_settings WHERE key = $1",
+                &[&PARENT_ILP_KEY],
+            )
+            .await
+            .map_err(|err| {
+                error!(
+                    "Error checking whether we have a parent configured: {:?}",
+                    err
+                )
+            })?;
+        drop(client);
+
+        let mut ilp_address = self.node_ilp_address.clone();
+        if let Some(row) = parent_address {
+            let address: String = row.get(0);
+            match Address::from_str(&address) {
+                Ok(address) => ilp_address = address,
+                Err(err) => error!("Ignoring invalid parent ILP address: {:?}", err),
+            }
+        }
+
+        let (payment_publisher, _) = broadcast::channel::<PaymentNotification>(256);
+        let store = PostgresStore {
+            ilp_address: Arc::new(RwLock::new(ilp_address)),
+            pool,
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            payment_publisher,
+            exchange_rates: Arc::new(RwLock::new(HashMap::new())),
+            exchange_rates_fetched_at: Arc::new(RwLock::new(HashMap::new())),
+            fee_policy: Arc::new(RwLock::new(Arc::new(FeePolicy::default()))),
+            routes: Arc::new(RwLock::new(Arc::new(RoutingTable::new()))),
+            rate_limiter: Arc::new(RateLimiter::new()),
+            encryption_key: Arc::new(encryption_key),
+            decryption_key: Arc::new(decryption_key),
+            max_transactions_per_account: self.max_transactions_per_account,
+            rate_history_retention: self.rate_history_retention,
+        };
+        store
+            .update_routes()
+            .await
+            .map_err(|err| error!("Error loading routing table: {}", err))?;
+
+        // Poll for routing table updates made by other nodes using the same database.
+        // The task stops once all clones of the store have been dropped.
+        let pool = store.pool.clone();
+        let routing_table = Arc::downgrade(&store.routes);
+        let poll_interval = self.poll_interval;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_millis(poll_interval));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if !poll_routes(&pool, &routing_table).await {
+                    debug!("Not polling routes anymore because the store was dropped");
+                    break;
+                }
+            }
+        });
+
+        Ok(store)
+    }
+}
+
+/// Reloads the routing table if the store is still alive. Returns false otherwise.
+async fn poll_routes(pool: &Pool, routing_table: &Weak<RwLock<Arc<RoutingTable<Route>>>>) -> bool {
+    if routing_table.strong_count() == 0 {
+        return false;
+    }
+    match load_routes(pool).await {
+        Ok(routes) => {
+            if let Some(routing_table) = routing_table.upgrade() {
+                *routing_table.write() = Arc::new(routes);
+                true
+            } else {
+                false
+            }
+        }
+        Err(err) => {
+            error!("Error polling routing table: {}", err);
+            true
+        }
+    }
+}
+
+/// Loads the routing table which is returned to the `Router`.
+/// Static routes take precedence over the routes set by CCP and the default route.
+async fn load_routes(pool: &Pool) -> Result<RoutingTable<Route>, PostgresStoreError> {
+    let client = pool.get().await?;
+    let mut routes: RoutingTable<Route> = client
+        .query("SELECT prefix, account_id FROM routes", &[])
+        .await?
+        .iter()
+        .map(|row| (row.get(0), Route::from(row.get::<_, Uuid>(1))))
+        .collect();
+    if let Some(row) = client
+        .query_opt(
+            "SELECT value FROM node_settings WHERE key = $1",
+            &[&DEFAULT_ROUTE_KEY],
+        )
+        .await?
+    {
+        routes.insert(String::new(), Route::from(parse_id(row.get(0))?));
+    }
+    // Having the static_routes inserted after ensures that they will overwrite
+    // any routes with the same prefix from the first set
+    routes.extend(load_static_routes(&client).await?);
+    trace!("Routing table is: {:?}", routes);
+    Ok(routes)
+}
+
+/// Loads the static routes, with their next hops in order
+async fn load_static_routes(client: &Client) -> Result<Vec<(String, Route)>, PostgresStoreError> {
+    let rows = client
+        .query(
+            "SELECT prefix, account_id, weight FROM static_route_hops ORDER BY prefix, position",
+            &[],
+        )
+        .await?;
+    let mut routes: Vec<(String, Vec<NextHop>)> = Vec::new();
+    for row in rows {
+        let prefix: String = row.get(0);
+        let next_hop = NextHop {
+            account_id: row.get(1),
+            weight: row.get::<_, Option<i64>>(2).map(|weight| weight as u32),
+        };
+        match routes.last_mut() {
+            Some((last, next_hops)) if *last == prefix => next_hops.push(next_hop),
+            _ => routes.push((prefix, vec![next_hop])),
+        }
+    }
+    Ok(routes
+        .into_iter()
+        .map(|(prefix, next_hops)| (prefix, Route::new(next_hops)))
+        .collect())
+}
+
+/// The net position with an account holder, see the store's README for details
+#[derive(Debug, Default, Clone, Copy)]
+struct Balance {
+    balance: i64,
+    prepaid_amount: i64,
+}
+
+impl Balance {
+    fn total(&self) -> i64 {
+        self.balance + self.prepaid_amount
+    }
+
+    /// Deducts the amount from the prepaid amount and/or the balance,
+    /// or returns `None` if the balance would overflow
+    fn debit(mut self, amount: i64) -> Option<Self> {
+        if self.prepaid_amount >= amount {
+            self.prepaid_amount -= amount;
+        } else if self.prepaid_amount > 0 {
+            self.balance = self.balance.checked_sub(amount - self.prepaid_amount)?;
+            self.prepaid_amount = 0;
+        } else {
+            self.balance = self.balance.checked_sub(amount)?;
+        }
+        Some(self)
+    }
+
+    /// Adds the amount to the balance, or returns `None` if it would overflow
+    fn credit(mut self, amount: i64) -> Option<Self> {
+        self.balance = self.balance.checked_add(amount)?;
+        Some(self)
+    }
+
+    /// Credits a settlement to the balance and/or the prepaid amount, depending on whether
+    /// the account currently owes money or not, or returns `None` if either would overflow
+    fn credit_settlement(mut self, amount: i64) -> Option<Self> {
+        if self.balance >= 0 {
+            self.prepaid_amount = self.prepaid_amount.checked_add(amount)?;
+        } else if self.balance + amount <= 0 {
+            self.balance += amount;
+        } else {
+            // Whatever is left once the balance is back to 0 is prepaid
+            self.prepaid_amount = self.prepaid_amount.checked_add(self.balance + amount)?;
+            self.balance = 0;
+        }
+        Some(self)
+    }
+}
+
+/// Converts an amount to the type of the balances, failing if it does not fit
+fn signed_amount(account_id: Uuid, amount: u64) -> Result<i64, PostgresStoreError> {
+    i64::try_from(amount).map_err(|_| PostgresStoreError::AmountOutOfRange { account_id, amount })
+}
+
+/// A balance which is locked for the rest of the transaction, together
+/// with the account settings which are needed to update it
+struct LockedBalance {
+    balance: Balance,
+    min_balance: Option<i64>,
+    settle_threshold: Option<i64>,
+    settle_to: Option<i64>,
+}
+
+/// The kinds of rows in the `settlements` table
+#[derive(Debug, Clone, Copy)]
+enum SettlementKind {
+    Incoming,
+    Outgoing,
+    Refund,
+}
+
+impl SettlementKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            SettlementKind::Incoming => "incoming",
+            SettlementKind::Outgoing => "outgoing",
+            SettlementKind::Refund => "refund",
+        }
+    }
+}
+
+/// Locks the account's balance until the end of the transaction
+async fn lock_balance(
+    tx: &Transaction<'_>,
+    account_id: Uuid,
+) -> Result<LockedBalance, PostgresStoreError> {
+    let row = tx
+        .query_opt(
+            "SELECT b.balance, b.prepaid_amount, a.min_balance, a.settle_threshold, a.settle_to
+            FROM balances b JOIN accounts a ON a.id = b.account_id
+            WHERE b.account_id = $1 FOR UPDATE OF b",
+            &[&account_id],
+        )
+        .await?
+        .ok_or(PostgresStoreError::AccountNotFound(account_id))?;
+    Ok(LockedBalance {
+        balance: Balance {
+            balance: row.try_get(0)?,
+            prepaid_amount: row.try_get(1)?,
+        },
+        min_balance: row.try_get(2)?,
+        settle_threshold: row.try_get(3)?,
+        settle_to: row.try_get(4)?,
+    })
+}
+
+async fn write_balance(
+    tx: &Transaction<'_>,
+    account_id: Uuid,
+    balance: Balance,
+) -> Result<(), PostgresStoreError> {
+    tx.execute(
+        "UPDATE balances SET balance = $2, prepaid_amount = $3, updated_at = now()
+        WHERE account_id = $1",
+        &[&account_id, &balance.balance, &balance.prepaid_amount],
+    )
+    .await?;
+    Ok(())
+}
+
+async fn record_settlement(
+    tx: &Transaction<'_>,
+    account_id: Uuid,
+    kind: SettlementKind,
+    amount: i64,
+    idempotency_key: Option<&str>,
+    balance: Balance,
+) -> Result<(), PostgresStoreError> {
+    tx.execute(
+        "INSERT INTO settlements
+        (account_id, kind, amount, idempotency_key, balance_after, prepaid_amount_after)
+        VALUES ($1, $2, $3, $4, $5, $6)",
+        &[
+            &account_id,
+            &kind.as_str(),
+            &amount,
+            &idempotency_key,
+            &balance.balance,
+            &balance.prepaid_amount,
+        ],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Appends the change of an account's balance to its history, and trims the oldest
+/// transactions of the account past the maximum
+async fn record_transaction(
+    tx: &Transaction<'_>,
+    transaction: &AccountTransaction,
+    max_transactions: usize,
+) -> Result<(), PostgresStoreError> {
+    tx.execute(
+        "INSERT INTO transactions
+        (account_id, kind, amount, counterparty, destination, outcome, error_code, timestamp)
+        VALUES ($1, $2, $3::TEXT::NUMERIC, $4, $5, $6, $7, $8)",
+        &[
+            &transaction.account_id,
+            &enum_to_string(&transaction.kind),
+            &transaction.amount.to_string(),
+            &transaction
+                .counterparty
+                .as_ref()
+                .map(|username| username.to_string()),
+            &transaction.destination,
+            &transaction.outcome.as_ref().map(enum_to_string),
+            &transaction.error_code,
+            &(transaction.timestamp as i64),
+        ],
+    )
+    .await?;
+    // This also locks the account's history until the end of the transaction,
+    // so that concurrent trims do not overlap
+    let retained: i64 = tx
+        .query_one(
+            "INSERT INTO transaction_history (account_id, retained) VALUES ($1, 1)
+            ON CONFLICT (account_id)
+            DO UPDATE SET retained = transaction_history.retained + 1
+            RETURNING retained",
+            &[&transaction.account_id],
+        )
+        .await?
+        .try_get(0)?;
+    let excess = retained - max_transactions.min(i64::MAX as usize) as i64;
+    if excess > 0 {
+        tx.execute(
+            "WITH trimmed AS (
+                DELETE FROM transactions WHERE id IN (
+                    SELECT id FROM transactions WHERE account_id = $1 ORDER BY id LIMIT $2
+                ) RETURNING kind, amount, outcome
+            )
+            UPDATE transaction_history SET
+            retained = retained - (SELECT COUNT(*) FROM trimmed),
+            packets_in = LEAST(packets_in + (SELECT COALESCE(SUM(amount), 0) FROM trimmed
+                WHERE kind = 'incoming_packet' AND outcome = 'fulfilled'), $3::TEXT::NUMERIC),
+            packets_out = LEAST(packets_out + (SELECT COALESCE(SUM(amount), 0) FROM trimmed
+                WHERE kind = 'outgoing_packet' AND outcome = 'fulfilled'), $3::TEXT::NUMERIC),
+            settled_in = LEAST(settled_in + (SELECT COALESCE(SUM(amount), 0) FROM trimmed
+                WHERE kind = 'incoming_settlement'), $3::TEXT::NUMERIC),
+            settled_out = LEAST(settled_out + (SELECT COALESCE(SUM(amount), 0) FROM trimmed
+                WHERE kind = 'outgoing_settlement'), $3::TEXT::NUMERIC),
+            accepted_out = LEAST(accepted_out + (SELECT COALESCE(SUM(amount), 0) FROM trimmed
+                WHERE kind = 'settlement_accepted'), $3::TEXT::NUMERIC),
+            refunded = LEAST(refunded + (SELECT COALESCE(SUM(amount), 0) FROM trimmed
+                WHERE kind = 'settlement_refund'), $3::TEXT::NUMERIC),
+            refunds = refunds + (SELECT COUNT(*) FROM trimmed WHERE kind = 'settlement_refund')
+            WHERE account_id = $1",
+            // The totals saturate like the ones kept by the other stores
+            &[&transaction.account_id, &excess, &u64::MAX.to_string()],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// The name of a unit variant, as it is serialized
+fn enum_to_string<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+fn enum_from_string<T: DeserializeOwned>(value: String) -> Result<T, PostgresStoreError> {
+    serde_json::from_value(serde_json::Value::String(value.clone()))
+        .map_err(|_| PostgresStoreError::Corrupted(format!("invalid variant {}", value)))
+}
+
+fn transaction_from_row(
+    account_id: Uuid,
+    row: &Row,
+) -> Result<TransactionRecord, PostgresStoreError> {
+    let id: i64 = row.try_get(0)?;
+    let counterparty: Option<String> = row.try_get(3)?;
+    let outcome: Option<String> = row.try_get(5)?;
+    let timestamp: i64 = row.try_get(7)?;
+    Ok(TransactionRecord {
+        id: id as u64,
+        transaction: AccountTransaction {
+            account_id,
+            kind: enum_from_string(row.try_get(1)?)?,
+            amount: parse_u64(row.try_get(2)?)?,
+            counterparty: counterparty
+                .map(|username| {
+                    Username::from_str(&username).map_err(|_| {
+                        PostgresStoreError::Corrupted(format!("invalid username {}", username))
+                    })
+                })
+                .transpose()?,
+            destination: row.try_get(4)?,
+            outcome: outcome.map(enum_from_string).transpose()?,
+            error_code: row.try_get(6)?,
+            timestamp: timestamp as u64,
+        },
+    })
+}
+
+fn parse_id(id: String) -> Result<Uuid, PostgresStoreError> {
+    Uuid::from_str(&id)
+        .map_err(|err| PostgresStoreError::Corrupted(format!("invalid account id: {}", err)))
+}
+
+fn parse_url(url: Option<String>) -> Result<Option<Url>, PostgresStoreError> {
+    url.map(|url| {
+        Url::parse(&url).map_err(|_| PostgresStoreError::Corrupted(format!("invalid URL {}", url)))
+    })
+    .transpose()
+}
+
+fn parse_u64(num: String) -> Result<u64, PostgresStoreError> {
+    u64::from_str(&num)
+        .map_err(|_| PostgresStoreError::Corrupted(format!("invalid amount {}", num)))
+}
+
+/// The columns which are selected to load the state of a STREAM connection
+static SELECT_STREAM_CONNECTION: &str =
+    "SELECT total_received::TEXT, receive_max::TEXT, closed FROM stream_connections";
+
+fn stream_connection_from_row(row: &Row) -> Result<StreamConnectionRecord, PostgresStoreError> {
+    let receive_max: Option<String> = row.try_get(1)?;
+    Ok(StreamConnectionRecord {
+        total_received: parse_u64(row.try_get(0)?)?,
+        receive_max: receive_max.map(parse_u64).transpose()?,
+        closed: row.try_get(2)?,
+    })
+}
+
+fn token_to_vec(token: &Option<SecretBytesMut>) -> Option<Vec<u8>> {
+    token.as_ref().map(|token| token.expose_secret().to_vec())
+}
+
+fn vec_to_token(token: Option<Vec<u8>>) -> Option<SecretBytesMut> {
+    token.map(|token| SecretBytesMut::from(BytesMut::from(token.as_slice())))
+}
+
+fn account_from_row(row: &Row) -> Result<AccountWithEncryptedTokens, PostgresStoreError> {
+    let username: String = row.try_get(1)?;
+    let ilp_address: String = row.try_get(2)?;
+    let asset_scale: i16 = row.try_get(4)?;
+    let routing_relation: String = row.try_get(15)?;
+    let round_trip_time: i64 = row.try_get(16)?;
+    let packets_per_minute_limit: Option<i64> = row.try_get(17)?;
+    let amount_per_minute_limit: Option<String> = row.try_get(18)?;
+    let ilp_over_http_client: Option<String> = row.try_get(20)?;
+    Ok(AccountWithEncryptedTokens {
+        account: Account {
+            id: row.try_get(0)?,
+            username: Username::from_str(&username).map_err(|_| {
+                PostgresStoreError::Corrupted(format!("invalid username {}", username))
+            })?,
+            ilp_address: Address::from_str(&ilp_address).map_err(|_| {
+                PostgresStoreError::Corrupted(format!("invalid ILP address {}", ilp_address))
+            })?,
+            asset_code: row.try_get(3)?,
+            asset_scale: u8::try_from(asset_scale).map_err(|_| {
+                PostgresStoreError::Corrupted(format!("invalid asset scale {}", asset_scale))
+            })?,
+            max_packet_amount: parse_u64(row.try_get(5)?)?,
+            min_balance: row.try_get(6)?,
+            ilp_over_http_url: parse_url(row.try_get(7)?)?,
+            ilp_over_http_incoming_token: vec_to_token(row.try_get(8)?),
+            ilp_over_http_outgoing_token: vec_to_token(row.try_get(9)?),
+            ilp_over_btp_url: parse_url(row.try_get(10)?)?,
+            ilp_over_btp_incoming_token: vec_to_token(row.try_get(11)?),
+            ilp_over_btp_outgoing_token: vec_to_token(row.try_get(12)?),
+            settle_threshold: row.try_get(13)?,
+            settle_to: row.try_get(14)?,
+            routing_relation: RoutingRelation::from_str(&routing_relation).map_err(|_| {
+                PostgresStoreError::Corrupted(format!(
+                    "invalid routing relation {}",
+                    routing_relation
+                ))
+            })?,
+            round_trip_time: u32::try_from(round_trip_time).map_err(|_| {
+                PostgresStoreError::Corrupted(format!(
+                    "invalid round trip time {}",
+                    round_trip_time
+                ))
+            })?,
+            packets_per_minute_limit: packets_per_minute_limit
+                .map(|limit| {
+                    u32::try_from(limit).map_err(|_| {
+                        PostgresStoreError::Corrupted(format!("invalid packet limit {}", limit))
+                    })
+                })
+                .transpose()?,
+            amount_per_minute_limit: amount_per_minute_limit.map(parse_u64).transpose()?,
+            settlement_engine_url: parse_url(row.try_get(19)?)?,
+            ilp_over_http_client: ilp_over_http_client
+                .map(|settings| {
+                    serde_json::from_str(&settings).map_err(|_| {
+                        PostgresStoreError::Corrupted(format!(
+                            "invalid http client settings {}",
+                            settings
+                        ))
+                    })
+                })
+                .transpose()?,
+        },
+    })
+}
+
+/// The values of an account's row, in the order of the columns in `INSERT_ACCOUNT`
+struct AccountRow {
+    id: Uuid,
+    username: String,
+    ilp_address: String,
+    asset_code: String,
+    asset_scale: i16,
+    max_packet_amount: String,
+    min_balance: Option<i64>,
+    ilp_over_http_url: Option<String>,
+    ilp_over_http_incoming_token: Option<Vec<u8>>,
+    ilp_over_http_outgoing_token: Option<Vec<u8>>,
+    ilp_over_btp_url: Option<String>,
+    ilp_over_btp_incoming_token: Option<Vec<u8>>,
+    ilp_over_btp_outgoing_token: Option<Vec<u8>>,
+    settle_threshold: Option<i64>,
+    settle_to: Option<i64>,
+    routing_relation: String,
+    round_trip_time: i64,
+    packets_per_minute_limit: Option<i64>,
+    amount_per_minute_limit: Option<String>,
+    settlement_engine_url: Option<String>,
+    ilp_over_http_client: Option<String>,
+}
+
+static INSERT_ACCOUNT: &str = "INSERT INTO accounts (id, username, ilp_address, asset_code,
+    asset_scale, max_packet_amount, min_balance, ilp_over_http_url, ilp_over_http_incoming_token,
+    ilp_over_http_outgoing_token, ilp_over_btp_url, ilp_over_btp_incoming_token,
+    ilp_over_btp_outgoing_token, settle_threshold, settle_to, routing_relation, round_trip_time,
+    packets_per_minute_limit, amount_per_minute_limit, settlement_engine_url,
+    ilp_over_http_client)
+    VALUES ($1, $2, $3, $4, $5, $6::TEXT::NUMERIC, $7, $8, $9, $10, $11, $12, $13, $14, $15,
+    $16, $17, $18, $19::TEXT::NUMERIC, $20, $21)";
+
+static UPDATE_ACCOUNT: &str = "UPDATE accounts SET username = $2, ilp_address = $3,
+    asset_code = $4, asset_scale = $5, max_packet_amount = $6::TEXT::NUMERIC, min_balance = $7,
+    ilp_over_http_url = $8, ilp_over_http_incoming_token = $9, ilp_over_http_outgoing_token = $10,
+    ilp_over_btp_url = $11, ilp_over_btp_incoming_token = $12, ilp_over_btp_outgoing_token = $13,
+    settle_threshold = $14, settle_to = $15, routing_relation = $16, round_trip_time = $17,
+    packets_per_minute_limit = $18, amount_per_minute_limit = $19::TEXT::NUMERIC,
+    settlement_engine_url = $20, ilp_over_http_client = $21
+    WHERE id = $1";
+
+impl From<&AccountWithEncryptedTokens> for AccountRow {
+    fn from(encrypted: &AccountWithEncryptedTokens) -> Self {
+        let account = &encrypted.account;
+        AccountRow {
+            id: account.id,
+            username: account.username.to_string(),
+            ilp_address: account.ilp_address.to_string(),
+            asset_code: account.asset_code.clone(),
+            asset_scale: i16::from(account.asset_scale),
+            max_packet_amount: account.max_packet_amount.to_string(),
+            min_balance: account.min_balance,
+            ilp_over_http_url: account.ilp_over_http_url.as_ref().map(Url::to_string),
+            ilp_over_http_incoming_token: token_to_vec(&account.ilp_over_http_incoming_token),
+            ilp_over_http_outgoing_token: token_to_vec(&account.ilp_over_http_outgoing_token),
+            ilp_over_btp_url: account.ilp_over_btp_url.as_ref().map(Url::to_string),
+            ilp_over_btp_incoming_token: token_to_vec(&account.ilp_over_btp_incoming_token),
+            ilp_over_btp_outgoing_token: token_to_vec(&account.ilp_over_btp_outgoing_token),
+            settle_threshold: account.settle_threshold,
+            settle_to: account.settle_to,
+            routing_relation: account.routing_relation.to_string(),
+            round_trip_time: i64::from(account.round_trip_time),
+            packets_per_minute_limit: account.packets_per_minute_limit.map(i64::from),
+            amount_per_minute_limit: account.amount_per_minute_limit.map(|l| l.to_string()),
+            settlement_engine_url: account.settlement_engine_url.as_ref().map(Url::to_string),
+            // Serializing plain strings and numbers cannot fail
+            ilp_over_http_client: account
+                .ilp_over_http_client
+                .as_ref()
+                .map(|settings| serde_json::to_string(settings).unwrap()),
+        }
+    }
+}
+
+impl AccountRow {
+    fn params(&self) -> [&(dyn ToSql + Sync); 21] {
+        [
+            &self.id,
+            &self.username,
+            &self.ilp_address,
+            &self.asset_code,
+            &self.asset_scale,
+            &self.max_packet_amount,
+            &self.min_balance,
+            &self.ilp_over_http_url,
+            &self.ilp_over_http_incoming_token,
+            &self.ilp_over_http_outgoing_token,
+            &self.ilp_over_btp_url,
+            &self.ilp_over_btp_incoming_token,
+            &self.ilp_over_btp_outgoing_token,
+            &self.settle_threshold,
+            &self.settle_to,
+            &self.routing_relation,
+            &self.round_trip_time,
+            &self.packets_per_minute_limit,
+            &self.amount_per_minute_limit,
+            &self.settlement_engine_url,
+            &self.ilp_over_http_client,
+        ]
+    }
+}
+
+fn is_unique_violation(err: &postgres_crate::Error) -> bool {
+    err.code() == Some(&SqlState::UNIQUE_VIOLATION)
+}
+
+type UncreditedAmounts = Vec<(String, u8)>;
+
+/// Sums the uncredited amounts (scaled to the largest scale among them)
+fn sum_uncredited_amounts(amounts: UncreditedAmounts) -> Result<(BigUint, u8), PostgresStoreError> {
+    let max_scale = amounts.iter().map(|(_, scale)| *scale).max().unwrap_or(0);
+    let mut sum = BigUint::from(0u32);
+    for (num, scale) in amounts {
+        let num = BigUint::from_str(&num)
+            .map_err(|_| PostgresStoreError::Corrupted(format!("invalid amount {}", num)))?;
+        sum += num
+            .normalize_scale(ConvertDetails {
+                from: scale,
+                to: max_scale,
+            })
+            .unwrap();
+    }
+    Ok((sum, max_scale))
+}
+
+fn uncredited_amount_from_row(row: &Row) -> Result<(String, u8), PostgresStoreError> {
+    let scale: i16 = row.try_get(1)?;
+    let scale = u8::try_from(scale)
+        .map_err(|_| PostgresStoreError::Corrupted(format!("invalid scale {}", scale)))?;
+    Ok((row.try_get(0)?, scale))
+}
+
+/// Removes and returns the uncredited amounts of the account
+async fn take_uncredited_amounts(
+    tx: &Transaction<'_>,
+    account_id: Uuid,
+) -> Result<UncreditedAmounts, PostgresStoreError> {
+    tx.query(
+        "DELETE FROM uncredited_settlement_amounts WHERE account_id = $1
+        RETURNING amount::TEXT, scale",
+        &[&account_id],
+    )
+    .await?
+    .iter()
+    .map(uncredited_amount_from_row)
+    .collect()
+}
+
+async fn push_uncredited_amount(
+    tx: &Transaction<'_>,
+    account_id: Uuid,
+    amount: (BigUint, u8),
+) -> Result<(), PostgresStoreError> {
+    tx.execute(
+        "INSERT INTO uncredited_settlement_amounts (account_id, amount, scale)
+        VALUES ($1, $2::TEXT::NUMERIC, $3)",
+        &[&account_id, &amount.0.to_string(), &i16::from(amount.1)],
+    )
+    .await?;
+    Ok(())
+}
+
+/// A Store that uses [PostgreSQL](https://www.postgresql.org/) as its backend.
+///
+/// Several nodes can share one database, in which case each node picks up the
+/// routing table changes made by the others when it polls the database.
+#[derive(Clone)]
+pub struct PostgresStore {
+    /// The Store's ILP Address
+    ilp_address: Arc<RwLock<Address>>,
+    pool: Pool,
+    /// WebSocket senders which publish incoming payment updates
+    subscriptions: Arc<Mutex<HashMap<Uuid, Vec<UnboundedSender<PaymentNotification>>>>>,
+    /// A subscriber to all payment notifications, exposed via a WebSocket
+    payment_publisher: broadcast::Sender<PaymentNotification>,
+    exchange_rates: Arc<RwLock<HashMap<String, f64>>>,
+    /// When each of the rates was fetched, in milliseconds since the Unix epoch
+    exchange_rates_fetched_at: Arc<RwLock<HashMap<String, u64>>>,
+    fee_policy: Arc<RwLock<Arc<FeePolicy>>>,
+    /// The store keeps the routing table in memory so that it can be returned
+    /// synchronously to the Router (which needs to process packets synchronously).
+    /// It is reloaded whenever the routes in the database change.
+    routes: Arc<RwLock<Arc<RoutingTable<Route>>>>,
+    rate_limiter: Arc<RateLimiter>,
+    /// Encryption Key so that the no cleartext data are stored
+    encryption_key: Arc<Secret<EncryptionKey>>,
+    /// Decryption Key to provide cleartext data to users
+    decryption_key: Arc<Secret<DecryptionKey>>,
+    /// Number of transactions kept for each account
+    max_transactions_per_account: usize,
+    /// How long the rates are kept in their history, in milliseconds
+    rate_history_retention: u64,
+}
+
+impl PostgresStore {
+    async fn update_routes(&self) -> Result<(), PostgresStoreError> {
+        let routes = load_routes(&self.pool).await?;
+        *self.routes.write() = Arc::new(routes);
+        Ok(())
+    }
+
+    fn encrypt(&self, token: &[u8]) -> Vec<u8> {
+        encrypt_token(&self.encryption_key.expose_secret().0, token).to_vec()
+    }
+
+    fn decrypt_rows(&self, rows: Vec<Row>) -> Result<Vec<Account>, PostgresStoreError> {
+        rows.iter()
+            .map(|row| {
+                Ok(account_from_row(row)?.decrypt_tokens(&self.decryption_key.expose_secret().0))
+            })
+            .collect()
+    }
+
+    async fn query_accounts(
+        &self,
+        filter: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Account>, PostgresStoreError> {
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(format!("{} {}", SELECT_ACCOUNTS, filter).as_str(), params)
+            .await?;
+        self.decrypt_rows(rows)
+    }
+
+    async fn get_account(&self, id: Uuid) -> Result<Option<Account>, PostgresStoreError> {
+        Ok(self.query_accounts("WHERE a.id = $1", &[&id]).await?.pop())
+    }
+
+    async fn get_account_by_username(
+        &self,
+        username: &Username,
+    ) -> Result<Option<Account>, PostgresStoreError> {
+        Ok(self
+            .query_accounts("WHERE a.username = $1", &[&username.to_string()])
+            .await?
+            .pop())
+    }
+
+    async fn all_accounts(&self) -> Result<Vec<Account>, PostgresStoreError> {
+        self.query_accounts("ORDER BY a.created_at", &[]).await
+    }
+
+    async fn filter_accounts<F>(&self, predicate: F) -> Result<Vec<Account>, PostgresStoreError>
+    where
+        F: Fn(&Account) -> bool,
+    {
+        Ok(self
+            .all_accounts()
+            .await?
+            .into_iter()
+            .filter(|account| predicate(account))
+            .collect())
+    }
+
+    async fn insert_account_data(&self, account: Account) -> Result<Account, PostgresStoreError> {
+        let encrypted = account
+            .clone()
+            .encrypt_tokens(&self.encryption_key.expose_secret().0);
+        let row = AccountRow::from(&encrypted);
+        let account_exists = || {
+            PostgresStoreError::Node(NodeStoreError::AccountExists(account.username.to_string()))
+        };
+
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        // Only one parent can be configured
+        if account.routing_relation == RoutingRelation::Parent
+            && tx
+                .query_opt(
+                    "SELECT 1 FROM node_settings WHERE key = $1",
+                    &[&PARENT_ILP_KEY],
+                )
+                .await?
+                .is_some()
+        {
+            return Err(account_exists());
+        }
+        // The id and username are unique, so inserting a duplicate fails
+        match tx.execute(INSERT_ACCOUNT, &row.params()).await {
+            Err(ref err) if is_unique_violation(err) => return Err(account_exists()),
+            result => result?,
+        };
+        tx.execute(
+            "INSERT INTO balances (account_id) VALUES ($1)",
+            &[&account.id],
+        )
+        .await?;
+        tx.execute(
+            "INSERT INTO routes (prefix, account_id) VALUES ($1, $2)
+            ON CONFLICT (prefix) DO UPDATE SET account_id = EXCLUDED.account_id",
+            &[&row.ilp_address, &account.id],
+        )
+        .await?;
+        tx.commit().await?;
+        drop(client);
+
+        self.update_routes().await?;
+        debug!(
+            "Inserted account {} (ILP address: {})",
+            account.id, account.ilp_address
+        );
+        Ok(account)
+    }
+
+    async fn update_account_data(&self, account: Account) -> Result<Account, PostgresStoreError> {
+        let encrypted = account
+            .clone()
+            .encrypt_tokens(&self.encryption_key.expose_secret().0);
+        let row = AccountRow::from(&encrypted);
+
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        let old_address: String = tx
+            .query_opt(
+                "SELECT ilp_address FROM accounts WHERE id = $1 FOR UPDATE",
+                &[&account.id],
+            )
+            .await?
+            .ok_or(PostgresStoreError::AccountNotFound(account.id))?
+            .try_get(0)?;
+        match tx.execute(UPDATE_ACCOUNT, &row.params()).await {
+            Err(ref err) if is_unique_violation(err) => {
+                return Err(PostgresStoreError::Node(NodeStoreError::AccountExists(
+                    account.username.to_string(),
+                )))
+            }
+            result => result?,
+        };
+        // Replace the route to the account's old address
+        tx.execute(
+            "DELETE FROM routes WHERE prefix = $1 AND account_id = $2",
+            &[&old_address, &account.id],
+        )
+        .await?;
+        tx.execute(
+            "INSERT INTO routes (prefix, account_id) VALUES ($1, $2)
+            ON CONFLICT (prefix) DO UPDATE SET account_id = EXCLUDED.account_id",
+            &[&row.ilp_address, &account.id],
+        )
+        .await?;
+        tx.commit().await?;
+        drop(client);
+
+        self.update_routes().await?;
+        debug!(
+            "Updated account {} (id: {}, ILP address: {})",
+            account.username, account.id, account.ilp_address
+        );
+        Ok(account)
+    }
+
+    async fn modify_account_data(
+        &self,
+        id: Uuid,
+        settings: AccountSettings,
+    ) -> Result<Account, PostgresStoreError> {
+        if let Some(settle_to) = settings.settle_to {
+            if settle_to > std::i64::MAX as u64 {
+                return Err(PostgresStoreError::Node(NodeStoreError::InvalidAccount(
+                    CreateAccountError::ParamTooLarge("settle_to".to_owned()),
+                )));
+            }
+        }
+        if let Some(ref url) = settings.ilp_over_btp_url {
+            Url::parse(url).map_err(|err| {
+                PostgresStoreError::Node(NodeStoreError::InvalidAccount(
+                    CreateAccountError::InvalidBtpUrl(err),
+                ))
+            })?;
+        }
+        if let Some(ref url) = settings.ilp_over_http_url {
+            Url::parse(url).map_err(|err| {
+                PostgresStoreError::Node(NodeStoreError::InvalidAccount(
+                    CreateAccountError::InvalidHttpUrl(err),
+                ))
+            })?;
+        }
+        let encrypt = |token: &Option<SecretString>| {
+            token
+                .as_ref()
+                .map(|token| self.encrypt(token.expose_secret().as_bytes()))
+        };
+        let ilp_over_btp_outgoing_token = encrypt(&settings.ilp_over_btp_outgoing_token);
+        let ilp_over_http_outgoing_token = encrypt(&settings.ilp_over_http_outgoing_token);
+        let ilp_over_btp_incoming_token = encrypt(&settings.ilp_over_btp_incoming_token);
+        let ilp_over_http_incoming_token = encrypt(&settings.ilp_over_http_incoming_token);
+        let settle_to = settings.settle_to.map(|settle_to| settle_to as i64);
+
+        // Only the settings which were provided are changed
+        let client = self.pool.get().await?;
+        let updated = client
+            .execute(
+                "UPDATE accounts SET
+                ilp_over_btp_url = COALESCE($2, ilp_over_btp_url),
+                ilp_over_http_url = COALESCE($3, ilp_over_http_url),
+                ilp_over_btp_outgoing_token = COALESCE($4, ilp_over_btp_outgoing_token),
+                ilp_over_http_outgoing_token = COALESCE($5, ilp_over_http_outgoing_token),
+                ilp_over_btp_incoming_token = COALESCE($6, ilp_over_btp_incoming_token),
+                ilp_over_http_incoming_token = COALESCE($7, ilp_over_http_incoming_token),
+                settle_threshold = COALESCE($8, settle_threshold),
+                settle_to = COALESCE($9, settle_to)
+                WHERE id = $1",
+                &[
+                    &id,
+                    &settings.ilp_over_btp_url,
+                    &settings.ilp_over_http_url,
+                    &ilp_over_btp_outgoing_token,
+                    &ilp_over_http_outgoing_token,
+                    &ilp_over_btp_incoming_token,
+                    &ilp_over_http_incoming_token,
+                    &settings.settle_threshold,
+                    &settle_to,
+                ],
+            )
+            .await?;
+        drop(client);
+        if updated == 0 {
+            return Err(PostgresStoreError::AccountNotFound(id));
+        }
+        self.get_account(id)
+            .await?
+            .ok_or(PostgresStoreError::AccountNotFound(id))
+    }
+
+    async fn delete_account_data(&self, id: Uuid) -> Result<Account, PostgresStoreError> {
+        let account = self
+            .get_account(id)
+            .await?
+            .ok_or(PostgresStoreError::AccountNotFound(id))?;
+
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        // The balance is deleted along with the account. The settlements are kept for auditing.
+        if tx
+            .execute("DELETE FROM accounts WHERE id = $1", &[&id])
+            .await?
+            == 0
+        {
+            return Err(PostgresStoreError::AccountNotFound(id));
+        }
+        tx.execute(
+            "DELETE FROM uncredited_settlement_amounts WHERE account_id = $1",
+            &[&id],
+        )
+        .await?;
+        tx.execute(
+            "DELETE FROM routes WHERE prefix = $1 AND account_id = $2",
+            &[&account.ilp_address.to_string(), &id],
+        )
+        .await?;
+        tx.commit().await?;
+        drop(client);
+
+        self.update_routes().await?;
+        debug!("Deleted account {}", id);
+        Ok(account)
+    }
+
+    async fn set_ilp_address_data(&self, ilp_address: Address) -> Result<(), PostgresStoreError> {
+        // Set the ILP address we have in memory
+        (*self.ilp_address.write()) = ilp_address.clone();
+
+        let first_segment = ilp_address
+            .segments()
+            .rev()
+            .next()
+            .expect("address did not have a first segment, this should be impossible");
+
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        tx.execute(
+            "INSERT INTO node_settings (key, value) VALUES ($1, $2)
+            ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value",
+            &[&PARENT_ILP_KEY, &ilp_address.to_string()],
+        )
+        .await?;
+
+        // Update the address and routes of all children and non-routing accounts.
+        let accounts = tx
+            .query(
+                "SELECT id, username, ilp_address FROM accounts
+                WHERE routing_relation NOT IN ('Parent', 'Peer') FOR UPDATE",
+                &[],
+            )
+            .await?;
+        for row in accounts {
+            let id: Uuid = row.try_get(0)?;
+            let username: String = row.try_get(1)?;
+            let old_address: String = row.try_get(2)?;
+            // if the username of the account ends with the
+            // node's address, we're already configured so no
+            // need to append anything.
+            let new_address = if first_segment == username {
+                ilp_address.clone()
+            } else {
+                ilp_address.with_suffix(username.as_bytes()).unwrap()
+            }
+            .to_string();
+            tx.execute(
+                "UPDATE accounts SET ilp_address = $2 WHERE id = $1",
+                &[&id, &new_address],
+            )
+            .await?;
+            tx.execute("DELETE FROM routes WHERE prefix = $1", &[&old_address])
+                .await?;
+            tx.execute(
+                "INSERT INTO routes (prefix, account_id) VALUES ($1, $2)
+                ON CONFLICT (prefix) DO UPDATE SET account_id = EXCLUDED.account_id",
+                &[&new_address, &id],
+            )
+            .await?;
+        }
+        tx.commit().await?;
+        drop(client);
+
+        self.update_routes().await
+    }
+
+    async fn notify(&self, payment: PaymentNotification) {
+        let account_id = match self.get_account_by_username(&payment.to_username).await {
+            Ok(Some(account)) => account.id,
+            Ok(None) => {
+                error!(
+                    "Failed to find account ID corresponding to username: {}",
+                    payment.to_username
+                );
+                return;
+            }
+            Err(err) => {
+                error!("Failed to load account ID: {}", err);
+                return;
+            }
+        };
+        trace!(
+            "Publishing payment notification for account {}: {:?}",
+            account_id,
+            payment
+        );
+
+        if self.payment_publisher.receiver_count() > 0 {
+            if let Err(err) = self.payment_publisher.send(payment.clone()) {
+                error!("Failed to send a node-wide payment notification: {:?}", err);
+            }
+        }
+        match self.subscriptions.lock().get_mut(&account_id) {
+            Some(senders) => {
+                senders.retain(|sender| {
+                    if let Err(err) = sender.unbounded_send(payment.clone()) {
+                        debug!("Failed to send message: {}", err);
+                        false
+                    } else {
+                        true
+                    }
+                });
+            }
+            None => trace!(
+                "Ignoring message for account {} because there were no open subscriptions",
+                account_id
+            ),
+        }
+    }
+
+    async fn get_balance_data(&self, account_id: Uuid) -> Result<i64, PostgresStoreError> {
+        let client = self.pool.get().await?;
+        let row = client
+            .query_opt(
+                "SELECT balance + prepaid_amount FROM balances WHERE account_id = $1",
+                &[&account_id],
+            )
+            .await?
+            .ok_or(PostgresStoreError::AccountNotFound(account_id))?;
+        Ok(row.try_get(0)?)
+    }
+
+    async fn prepare_data(
+        &self,
+        from_account_id: Uuid,
+        incoming_amount: u64,
+    ) -> Result<Balance, PostgresStoreError> {
+        let amount = signed_amount(from_account_id, incoming_amount)?;
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        let locked = lock_balance(&tx, from_account_id).await?;
+
+        // Deduct the amount from the prepaid_amount and/or the balance
+        let balance = match locked.balance.debit(amount) {
+            Some(balance) => balance,
+            None => {
+                tx.rollback().await?;
+                return Err(PostgresStoreError::AmountOutOfRange {
+                    account_id: from_account_id,
+                    amount: incoming_amount,
+                });
+            }
+        };
+
+        // Check that the prepare wouldn't go under the account's minimum balance
+        if let Some(min_balance) = locked.min_balance {
+            if balance.total() < min_balance {
+                return Err(PostgresStoreError::MinBalanceExceeded {
+                    account_id: from_account_id,
+                    amount: incoming_amount,
+                    balance: locked.balance.balance,
+                    min_balance,
+                });
+            }
+        }
+        write_balance(&tx, from_account_id, balance).await?;
+        tx.commit().await?;
+        Ok(balance)
+    }
+
+    /// Adds the amount to the balance and settles down to `settle_to` if
+    /// `settle` returns true for the account's settle threshold, settle_to and new balance.
+    async fn add_and_settle<F>(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        settle: F,
+    ) -> Result<(Balance, u64), PostgresStoreError>
+    where
+        F: Fn(i64, i64, i64) -> bool,
+    {
+        let signed = signed_amount(account_id, amount)?;
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        let locked = lock_balance(&tx, account_id).await?;
+        let mut balance = match locked.balance.credit(signed) {
+            Some(balance) => balance,
+            None => {
+                tx.rollback().await?;
+                return Err(PostgresStoreError::AmountOutOfRange { account_id, amount });
+            }
+        };
+
+        let mut amount_to_settle = 0;
+        if let (Some(settle_threshold), Some(settle_to)) =
+            (locked.settle_threshold, locked.settle_to)
+        {
+            if settle(settle_threshold, settle_to, balance.balance) {
+                amount_to_settle = (balance.balance - settle_to) as u64;
+                balance.balance = settle_to;
+            }
+        }
+        write_balance(&tx, account_id, balance).await?;
+        if amount_to_settle > 0 {
+            record_settlement(
+                &tx,
+                account_id,
+                SettlementKind::Outgoing,
+                amount_to_settle as i64,
+                None,
+                balance,
+            )
+            .await?;
+        }
+        tx.commit().await?;
+        Ok((balance, amount_to_settle))
+    }
+
+    async fn reject_data(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+    ) -> Result<Balance, PostgresStoreError> {
+        let signed = signed_amount(account_id, amount)?;
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        let locked = lock_balance(&tx, account_id).await?;
+        let balance = match locked.balance.credit(signed) {
+            Some(balance) => balance,
+            None => {
+                tx.rollback().await?;
+                return Err(PostgresStoreError::AmountOutOfRange { account_id, amount });
+            }
+        };
+        write_balance(&tx, account_id, balance).await?;
+        tx.commit().await?;
+        Ok(balance)
+    }
+
+    async fn refund_data(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+    ) -> Result<Balance, PostgresStoreError> {
+        let signed = signed_amount(account_id, amount)?;
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        let locked = lock_balance(&tx, account_id).await?;
+        let balance = match locked.balance.credit(signed) {
+            Some(balance) => balance,
+            None => {
+                tx.rollback().await?;
+                return Err(PostgresStoreError::AmountOutOfRange { account_id, amount });
+            }
+        };
+        write_balance(&tx, account_id, balance).await?;
+        record_settlement(
+            &tx,
+            account_id,
+            SettlementKind::Refund,
+            signed,
+            None,
+            balance,
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(balance)
+    }
+
+    async fn incoming_settlement_data(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: Option<String>,
+    ) -> Result<Option<Balance>, PostgresStoreError> {
+        let signed = signed_amount(account_id, amount)?;
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        // Locking the balance first serializes concurrent settlements for the same account
+        let balance = lock_balance(&tx, account_id).await?.balance;
+
+        // If the idempotency key has been used in the last 24 hours (the same as
+        // the expiry of the keys in the Redis store), then do not perform any operations
+        if let Some(ref idempotency_key) = idempotency_key {
+            if tx
+                .query_opt(
+                    "SELECT 1 FROM settlements
+                    WHERE idempotency_key = $1 AND kind = 'incoming'
+                    AND created_at > now() - interval '24 hours'",
+                    &[idempotency_key],
+                )
+                .await?
+                .is_some()
+            {
+                return Ok(None);
+            }
+        }
+
+        let balance = match balance.credit_settlement(signed) {
+            Some(balance) => balance,
+            None => {
+                tx.rollback().await?;
+                return Err(PostgresStoreError::AmountOutOfRange { account_id, amount });
+            }
+        };
+        write_balance(&tx, account_id, balance).await?;
+        record_settlement(
+            &tx,
+            account_id,
+            SettlementKind::Incoming,
+            signed,
+            idempotency_key.as_deref(),
+            balance,
+        )
+        .await?;
+        record_transaction(
+            &tx,
+            &AccountTransaction::settlement(
+                account_id,
+                TransactionKind::IncomingSettlement,
+                amount,
+            ),
+            self.max_transactions_per_account,
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(Some(balance))
+    }
+
+    async fn load_uncredited_data(
+        &self,
+        account_id: Uuid,
+        local_scale: u8,
+    ) -> Result<BigUint, PostgresStoreError> {
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        let amount = sum_uncredited_amounts(take_uncredited_amounts(&tx, account_id).await?)?;
+        // scale the amount from the max scale to the local scale, and then
+        // save any potential leftovers to the store
+        let (scaled_amount, precision_loss) =
+            scale_with_precision_loss(amount.0, local_scale, amount.1);
+        if precision_loss > BigUint::from(0u32) {
+            push_uncredited_amount(
+                &tx,
+                account_id,
+                (precision_loss, std::cmp::max(local_scale, amount.1)),
+            )
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(scaled_amount)
+    }
+
+    async fn add_stream_received_data(
+        &self,
+        token: &str,
+        amount: u64,
+    ) -> Result<StreamConnectionRecord, PostgresStoreError> {
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        tx.execute(
+            "INSERT INTO stream_connections (token) VALUES ($1) ON CONFLICT (token) DO NOTHING",
+            &[&token],
+        )
+        .await?;
+        // Lock the connection's row so that concurrent packets are counted one after the other
+        let row = tx
+            .query_one(
+                format!("{} WHERE token = $1 FOR UPDATE", SELECT_STREAM_CONNECTION).as_str(),
+                &[&token],
+            )
+            .await?;
+        let mut record = stream_connection_from_row(&row)?;
+        record
+            .add_received(token, amount)
+            .map_err(PostgresStoreError::Stream)?;
+        tx.execute(
+            "UPDATE stream_connections SET total_received = $2::TEXT::NUMERIC, updated_at = now()
+            WHERE token = $1",
+            &[&token, &record.total_received.to_string()],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(record)
+    }
+}
+
+#[async_trait]
+impl AccountStore for PostgresStore {
+    type Account = Account;
+
+    async fn get_accounts(
+        &self,
+        account_ids: Vec<Uuid>,
+    ) -> Result<Vec<Account>, AccountStoreError> {
+        let found = self
+            .query_accounts("WHERE a.id = ANY($1)", &[&account_ids])
+            .await?;
+        // Return the accounts in the order in which they were requested
+        let mut found: HashMap<Uuid, Account> = found
+            .into_iter()
+            .map(|account| (account.id, account))
+            .collect();
+        let accounts: Vec<Account> = account_ids
+            .iter()
+            .filter_map(|id| found.remove(id))
+            .collect();
+        if accounts.len() == account_ids.len() {
+            Ok(accounts)
+        } else {
+            Err(AccountStoreError::WrongLength {
+                expected: account_ids.len(),
+                actual: accounts.len(),
+            })
+        }
+    }
+
+    async fn get_account_id_from_username(
+        &self,
+        username: &Username,
+    ) -> Result<Uuid, AccountStoreError> {
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        match client
+            .query_opt(
+                "SELECT id FROM accounts WHERE username = $1",
+                &[&username.to_string()],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?
+        {
+            Some(row) => Ok(row.try_get(0).map_err(PostgresStoreError::from)?),
+            None => {
+                debug!("Username not found: {}", username);
+                Err(AccountStoreError::AccountNotFound(username.to_string()))
+            }
+        }
+    }
+}
+
+impl StreamNotificationsStore for PostgresStore {
+    type Account = Account;
+
+    fn add_payment_notification_subscription(
+        &self,
+        id: Uuid,
+        sender: UnboundedSender<PaymentNotification>,
+    ) {
+        trace!("Added payment notification listener for {}", id);
+        self.subscriptions
+            .lock()
+            .entry(id)
+            .or_insert_with(Vec::new)
+            .push(sender);
+    }
+
+    fn publish_payment_notification(&self, payment: PaymentNotification) {
+        // Looking up the account requires a database query, so this is done in the background
+        let store = self.clone();
+        tokio::spawn(async move { store.notify(payment).await });
+    }
+
+    fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification> {
+        self.payment_publisher.subscribe()
+    }
+}
+
+#[async_trait]
+impl TransactionStore for PostgresStore {
+    async fn record_transactions(
+        &self,
+        transactions: Vec<AccountTransaction>,
+    ) -> Result<(), TransactionStoreError> {
+        let mut client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let tx = client
+            .transaction()
+            .await
+            .map_err(PostgresStoreError::from)?;
+        for transaction in &transactions {
+            record_transaction(&tx, transaction, self.max_transactions_per_account).await?;
+        }
+        tx.commit().await.map_err(PostgresStoreError::from)?;
+        Ok(())
+    }
+
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        query: TransactionQuery,
+    ) -> Result<Vec<TransactionRecord>, TransactionStoreError> {
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let rows = client
+            .query(
+                "SELECT id, kind, amount::TEXT, counterparty, destination, outcome, error_code,
+                timestamp FROM transactions
+                WHERE account_id = $1 AND ($2::BIGINT IS NULL OR id < $2)
+                AND ($3::BIGINT IS NULL OR timestamp >= $3)
+                AND ($4::BIGINT IS NULL OR timestamp < $4)
+                AND ($6::BIGINT IS NULL OR id > $6)
+                ORDER BY id DESC LIMIT $5",
+                &[
+                    &account_id,
+                    &query.before.map(|before| before as i64),
+                    &query.from.map(|from| from as i64),
+                    &query.to.map(|to| to as i64),
+                    &(query.limit as i64),
+                    &query.after.map(|after| after as i64),
+                ],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?;
+        Ok(rows
+            .iter()
+            .map(|row| transaction_from_row(account_id, row))
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    async fn get_trimmed_transaction_totals(
+        &self,
+        account_id: Uuid,
+    ) -> Result<TransactionTotals, TransactionStoreError> {
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let row = client
+            .query_opt(
+                "SELECT packets_in::TEXT, packets_out::TEXT, settled_in::TEXT, settled_out::TEXT,
+                accepted_out::TEXT, refunded::TEXT, refunds FROM transaction_history
+                WHERE account_id = $1",
+                &[&account_id],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?;
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(TransactionTotals::default()),
+        };
+        let refunds: i64 = row.try_get(6).map_err(PostgresStoreError::from)?;
+        let amount =
+            |idx: usize| -> Result<u64, PostgresStoreError> { parse_u64(row.try_get(idx)?) };
+        Ok(TransactionTotals {
+            packets_in: amount(0)?,
+            packets_out: amount(1)?,
+            settled_in: amount(2)?,
+            settled_out: amount(3)?,
+            accepted_out: amount(4)?,
+            refunded: amount(5)?,
+            refunds: refunds as u64,
+        })
+    }
+}
+
+#[async_trait]
+impl ExchangeRateHistoryStore for PostgresStore {
+    async fn record_exchange_rates(
+        &self,
+        timestamp: u64,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        let cutoff = timestamp.saturating_sub(self.rate_history_retention);
+        let mut client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let tx = client
+            .transaction()
+            .await
+            .map_err(PostgresStoreError::from)?;
+        for (asset_code, rate) in &rates {
+            tx.execute(
+                "INSERT INTO rate_history (asset_code, timestamp, rate) VALUES ($1, $2, $3)
+                ON CONFLICT (asset_code, timestamp) DO UPDATE SET rate = EXCLUDED.rate",
+                &[asset_code, &(timestamp as i64), rate],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?;
+            // Keep the last rate before the cutoff, which was still in effect
+            tx.execute(
+                "DELETE FROM rate_history WHERE asset_code = $1 AND timestamp < (
+                    SELECT MAX(timestamp) FROM rate_history
+                    WHERE asset_code = $1 AND timestamp < $2
+                )",
+                &[asset_code, &(cutoff.min(i64::MAX as u64) as i64)],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?;
+        }
+        tx.commit().await.map_err(PostgresStoreError::from)?;
+        Ok(())
+    }
+
+    async fn get_exchange_rate_history(
+        &self,
+        asset_code: &str,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<RateSnapshot>, ExchangeRateStoreError> {
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let rows = client
+            .query(
+                "SELECT timestamp, rate FROM rate_history
+                WHERE asset_code = $1 AND timestamp >= $2 AND timestamp < $3
+                ORDER BY timestamp LIMIT $4",
+                &[
+                    &asset_code,
+                    // Timestamps past the range of BIGINT are later than any recorded rate
+                    &(from.min(i64::MAX as u64) as i64),
+                    &(to.min(i64::MAX as u64) as i64),
+                    &(limit as i64),
+                ],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?;
+        Ok(rows
+            .iter()
+            .map(|row| RateSnapshot {
+                timestamp: row.get::<_, i64>(0) as u64,
+                rate: row.get(1),
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl StreamConnectionStore for PostgresStore {
+    async fn set_stream_receive_max(
+        &self,
+        token: &str,
+        receive_max: u64,
+    ) -> Result<(), StreamConnectionStoreError> {
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        client
+            .execute(
+                "INSERT INTO stream_connections (token, receive_max) VALUES ($1, $2::TEXT::NUMERIC)
+                ON CONFLICT (token) DO UPDATE SET receive_max = EXCLUDED.receive_max,
+                updated_at = now()",
+                &[&token, &receive_max.to_string()],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?;
+        Ok(())
+    }
+
+    async fn get_stream_connection(
+        &self,
+        token: &str,
+    ) -> Result<Option<StreamConnectionRecord>, StreamConnectionStoreError> {
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let row = client
+            .query_opt(
+                format!("{} WHERE token = $1", SELECT_STREAM_CONNECTION).as_str(),
+                &[&token],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?;
+        Ok(row.as_ref().map(stream_connection_from_row).transpose()?)
+    }
+
+    async fn add_stream_received(
+        &self,
+        token: &str,
+        amount: u64,
+    ) -> Result<StreamConnectionRecord, StreamConnectionStoreError> {
+        let record = self.add_stream_received_data(token, amount).await?;
+        trace!(
+            "STREAM connection {} received {} (total: {})",
+            token,
+            amount,
+            record.total_received
+        );
+        Ok(record)
+    }
+
+    async fn close_stream_connection(
+        &self,
+        token: &str,
+    ) -> Result<Option<StreamConnectionRecord>, StreamConnectionStoreError> {
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        // Only the call which closes the connection gets its final state
+        let row = client
+            .query_opt(
+                "UPDATE stream_connections SET closed = TRUE, updated_at = now()
+                WHERE token = $1 AND NOT closed
+                RETURNING total_received::TEXT, receive_max::TEXT, closed",
+                &[&token],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?;
+        Ok(row.as_ref().map(stream_connection_from_row).transpose()?)
+    }
+}
+
+#[async_trait]
+impl BalanceStore for PostgresStore {
+    /// Returns the balance **from the account holder's perspective**, meaning the sum of
+    /// the Payable Balance and Pending Outgoing minus the Receivable Balance and the Pending Incoming.
+    async fn get_balance(&self, account_id: Uuid) -> Result<i64, BalanceStoreError> {
+        Ok(self.get_balance_data(account_id).await?)
+    }
+
+    async fn update_balances_for_prepare(
+        &self,
+        from_account_id: Uuid,
+        incoming_amount: u64,
+    ) -> Result<(), BalanceStoreError> {
+        // Don't do anything if the amount was 0
+        if incoming_amount == 0 {
+            return Ok(());
+        }
+
+        let balance = self.prepare_data(from_account_id, incoming_amount).await?;
+        trace!(
+            "Processed prepare with incoming amount: {}. Account {} has balance (including prepaid amount): {} ",
+            incoming_amount, from_account_id, balance.total()
+        );
+        Ok(())
+    }
+
+    async fn update_balances_for_fulfill(
+        &self,
+        to_account_id: Uuid,
+        outgoing_amount: u64,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        // Settlement is triggered if both the threshold and settle_to are set, the balance
+        // reached the threshold and the threshold is greater than settle_to
+        let (balance, amount_to_settle) = self
+            .add_and_settle(
+                to_account_id,
+                outgoing_amount,
+                |settle_threshold, settle_to, balance| {
+                    balance >= settle_threshold && settle_threshold > settle_to
+                },
+            )
+            .await?;
+
+        trace!(
+            "Processed fulfill for account {} for outgoing amount {}. Fulfill call result: {} {}",
+            to_account_id,
+            outgoing_amount,
+            balance.total(),
+            amount_to_settle,
+        );
+        Ok((balance.total(), amount_to_settle))
+    }
+
+    async fn update_balances_for_reject(
+        &self,
+        from_account_id: Uuid,
+        incoming_amount: u64,
+    ) -> Result<(), BalanceStoreError> {
+        if incoming_amount == 0 {
+            return Ok(());
+        }
+
+        let balance = self.reject_data(from_account_id, incoming_amount).await?;
+        trace!(
+            "Processed reject for incoming amount: {}. Account {} has balance (including prepaid amount): {}",
+            incoming_amount, from_account_id, balance.total()
+        );
+        Ok(())
+    }
+
+    async fn update_balances_for_delayed_settlement(
+        &self,
+        to_account_id: Uuid,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        // Upon completion the balance is at the level of settle_to
+        let (balance, amount_to_settle) = self
+            .add_and_settle(to_account_id, 0, |settle_threshold, settle_to, balance| {
+                settle_threshold > settle_to && balance >= settle_to
+            })
+            .await?;
+
+        trace!(
+            "Processed account {} for delayed settlement, balance: {}, to_settle: {}",
+            to_account_id,
+            balance.total(),
+            amount_to_settle
+        );
+        Ok((balance.total(), amount_to_settle))
+    }
+}
+
+impl ExchangeRateStore for PostgresStore {
+    fn get_exchange_rates(&self, asset_codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError> {
+        let rates: Vec<f64> = asset_codes
+            .iter()
+            .filter_map(|code| (*self.exchange_rates.read()).get(*code).cloned())
+            .collect();
+        if rates.len() == asset_codes.len() {
+            Ok(rates)
+        } else {
+            Err(ExchangeRateStoreError::PairNotFound {
+                from: asset_codes[0].to_string(),
+                to: asset_codes[1].to_string(),
+            })
+        }
+    }
+
+    fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        Ok((*self.exchange_rates.read()).clone())
+    }
+
+    fn set_exchange_rates(
+        &self,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        (*self.exchange_rates.write()) = rates;
+        self.exchange_rates_fetched_at.write().clear();
+        Ok(())
+    }
+
+    fn set_exchange_rates_fetched_at(
+        &self,
+        rates: HashMap<String, f64>,
+        fetched_at: HashMap<String, u64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        let mut exchange_rates = self.exchange_rates.write();
+        *exchange_rates = rates;
+        (*self.exchange_rates_fetched_at.write()) = fetched_at;
+        Ok(())
+    }
+
+    fn get_exchange_rates_fetched_at(
+        &self,
+        asset_codes: &[&str],
+    ) -> Result<Vec<Option<u64>>, ExchangeRateStoreError> {
+        let fetched_at = self.exchange_rates_fetched_at.read();
+        Ok(asset_codes
+            .iter()
+            .map(|code| fetched_at.get(*code).cloned())
+            .collect())
+    }
+}
+
+impl FeePolicyStore for PostgresStore {
+    fn get_fee_policy(&self) -> Arc<FeePolicy> {
+        self.fee_policy.read().clone()
+    }
+
+    fn set_fee_policy(&self, policy: FeePolicy) {
+        *self.fee_policy.write() = Arc::new(policy);
+    }
+}
+
+#[async_trait]
+impl BtpStore for PostgresStore {
+    type Account = Account;
+
+    async fn get_account_from_btp_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, BtpStoreError> {
+        if let Some(account) = self.get_account_by_username(username).await? {
+            match account.ilp_over_btp_incoming_token {
+                Some(ref t) if t.expose_secret().as_ref() == token.as_bytes() => Ok(account),
+                Some(_) => {
+                    debug!(
+                        "Found account {} but BTP auth token was wrong",
+                        account.username
+                    );
+                    Err(BtpStoreError::Unauthorized(username.to_string()))
+                }
+                None => {
+                    debug!(
+                        "Account {} does not have an incoming btp token configured",
+                        account.username
+                    );
+                    Err(BtpStoreError::Unauthorized(username.to_string()))
+                }
+            }
+        } else {
+            warn!("No account found with BTP token");
+            Err(BtpStoreError::AccountNotFound(username.to_string()))
+        }
+    }
+
+    async fn get_btp_outgoing_accounts(&self) -> Result<Vec<Self::Account>, BtpStoreError> {
+        Ok(self
+            .query_accounts(
+                "WHERE a.ilp_over_btp_url IS NOT NULL ORDER BY a.created_at",
+                &[],
+            )
+            .await?)
+    }
+}
+
+#[async_trait]
+impl HttpStore for PostgresStore {
+    type Account = Account;
+
+    /// Checks if the stored token for the provided account id matches the
+    /// provided token, and if so, returns the account associated with that token
+    async fn get_account_from_http_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, HttpStoreError> {
+        if let Some(account) = self.get_account_by_username(username).await? {
+            match account.ilp_over_http_incoming_token {
+                Some(ref t) if t.expose_secret().as_ref() == token.as_bytes() => Ok(account),
+                _ => Err(HttpStoreError::Unauthorized(username.to_string())),
+            }
+        } else {
+            warn!("No account found with given HTTP auth");
+            Err(HttpStoreError::AccountNotFound(username.to_string()))
+        }
+    }
+}
+
+impl RouterStore for PostgresStore {
+    fn routing_table(&self) -> Arc<RoutingTable<Route>> {
+        self.routes.read().clone()
+    }
+}
+
+#[async_trait]
+impl NodeStore for PostgresStore {
+    type Account = Account;
+
+    async fn insert_account(
+        &self,
+        account: AccountDetails,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let id = Uuid::new_v4();
+        let account = Account::try_from(id, account, self.get_ilp_address())
+            .map_err(NodeStoreError::InvalidAccount)?;
+        debug!(
+            "Generated account id for {}: {}",
+            account.username, account.id
+        );
+        Ok(self.insert_account_data(account).await?)
+    }
+
+    async fn delete_account(&self, id: Uuid) -> Result<Account, NodeStoreError> {
+        Ok(self.delete_account_data(id).await?)
+    }
+
+    async fn update_account(
+        &self,
+        id: Uuid,
+        account: AccountDetails,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let account = Account::try_from(id, account, self.get_ilp_address())
+            .map_err(NodeStoreError::InvalidAccount)?;
+        Ok(self.update_account_data(account).await?)
+    }
+
+    async fn modify_account_settings(
+        &self,
+        id: Uuid,
+        settings: AccountSettings,
+    ) -> Result<Self::Account, NodeStoreError> {
+        Ok(self.modify_account_data(id, settings).await?)
+    }
+
+    async fn get_all_accounts(&self) -> Result<Vec<Self::Account>, NodeStoreError> {
+        Ok(self.all_accounts().await?)
+    }
+
+    async fn set_static_routes<R, T>(&self, routes: R) -> Result<(), NodeStoreError>
+    where
+        R: IntoIterator<Item = (String, T)> + Send + 'async_trait,
+        T: Into<Route> + Send + 'async_trait,
+    {
+        let routes: HashMap<String, Route> = routes
+            .into_iter()
+            .map(|(prefix, route)| (prefix, route.into()))
+            .collect();
+        // One row per next hop
+        let mut prefixes: Vec<&str> = Vec::new();
+        let mut positions: Vec<i32> = Vec::new();
+        let mut account_ids: Vec<Uuid> = Vec::new();
+        let mut weights: Vec<Option<i64>> = Vec::new();
+        for (prefix, route) in &routes {
+            for (position, next_hop) in route.next_hops().iter().enumerate() {
+                prefixes.push(prefix);
+                positions.push(position as i32);
+                account_ids.push(next_hop.account_id);
+                weights.push(next_hop.weight.map(i64::from));
+            }
+        }
+
+        let mut client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let tx = client
+            .transaction()
+            .await
+            .map_err(PostgresStoreError::from)?;
+        let existing: i64 = tx
+            .query_one(
+                "SELECT COUNT(*) FROM accounts WHERE id = ANY($1)",
+                &[&account_ids],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?
+            .try_get(0)
+            .map_err(PostgresStoreError::from)?;
+        let unique_ids = account_ids.iter().collect::<HashSet<_>>().len();
+        if existing as usize != unique_ids {
+            error!("Error setting static routes because not all of the given accounts exist");
+            return Err(NodeStoreError::MissingAccounts);
+        }
+        tx.batch_execute("DELETE FROM static_route_hops")
+            .await
+            .map_err(PostgresStoreError::from)?;
+        tx.execute(
+            "INSERT INTO static_route_hops (prefix, position, account_id, weight)
+            SELECT * FROM UNNEST($1::TEXT[], $2::INTEGER[], $3::UUID[], $4::BIGINT[])",
+            &[&prefixes, &positions, &account_ids, &weights],
+        )
+        .await
+        .map_err(PostgresStoreError::from)?;
+        tx.commit().await.map_err(PostgresStoreError::from)?;
+        drop(client);
+
+        self.update_routes().await?;
+        Ok(())
+    }
+
+    async fn set_static_route(
+        &self,
+        prefix: String,
+        account_id: Uuid,
+    ) -> Result<(), NodeStoreError> {
+        let mut client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let tx = client
+            .transaction()
+            .await
+            .map_err(PostgresStoreError::from)?;
+        tx.execute(
+            "DELETE FROM static_route_hops WHERE prefix = $1",
+            &[&prefix],
+        )
+        .await
+        .map_err(PostgresStoreError::from)?;
+        let inserted = tx
+            .execute(
+                "INSERT INTO static_route_hops (prefix, position, account_id)
+                SELECT $1, 0, id FROM accounts WHERE id = $2",
+                &[&prefix, &account_id],
+            )
+            .await
+            .map_err(|err| {
+                error!("Cannot set static route for prefix: {}: {:?}", prefix, err);
+                PostgresStoreError::from(err)
+            })?;
+        // Leave the previous route in place if the account does not exist
+        if inserted == 1 {
+            tx.commit().await.map_err(PostgresStoreError::from)?;
+        }
+        drop(client);
+        if inserted == 0 {
+            error!(
+                "Cannot set static route for prefix: {} because account {} does not exist",
+                prefix, account_id
+            );
+            return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
+        }
+        self.update_routes().await?;
+        Ok(())
+    }
+
+    async fn set_default_route(&self, account_id: Uuid) -> Result<(), NodeStoreError> {
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let inserted = client
+            .execute(
+                "INSERT INTO node_settings (key, value)
+                SELECT $1, id::TEXT FROM accounts WHERE id = $2
+                ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value",
+                &[&DEFAULT_ROUTE_KEY, &account_id],
+            )
+            .await
+            .map_err(|err| {
+                error!("Cannot set default route: {:?}", err);
+                PostgresStoreError::from(err)
+            })?;
+        drop(client);
+        if inserted == 0 {
+            error!(
+                "Cannot set default route because account {} does not exist",
+                account_id
+            );
+            return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
+        }
+        debug!("Set default route to account id: {}", account_id);
+        self.update_routes().await?;
+        Ok(())
+    }
+
+    async fn set_settlement_engines(
+        &self,
+        asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
+    ) -> Result<(), NodeStoreError> {
+        let (asset_codes, urls): (Vec<String>, Vec<String>) = asset_to_url_map
+            .into_iter()
+            .map(|(asset_code, url)| (asset_code, url.to_string()))
+            .unzip();
+        debug!(
+            "Setting settlement engines to {:?}",
+            asset_codes.iter().zip(urls.iter()).collect::<Vec<_>>()
+        );
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        client
+            .execute(
+                "INSERT INTO settlement_engines (asset_code, url)
+                SELECT * FROM UNNEST($1::TEXT[], $2::TEXT[])
+                ON CONFLICT (asset_code) DO UPDATE SET url = EXCLUDED.url",
+                &[&asset_codes, &urls],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?;
+        Ok(())
+    }
+
+    async fn get_asset_settlement_engine(
+        &self,
+        asset_code: &str,
+    ) -> Result<Option<Url>, NodeStoreError> {
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let url = client
+            .query_opt(
+                "SELECT url FROM settlement_engines WHERE asset_code = $1",
+                &[&asset_code],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?
+            .map(|row| row.get::<_, String>(0));
+        Ok(url.and_then(|url| Url::parse(&url).ok()))
+    }
+}
+
+#[async_trait]
+impl AddressStore for PostgresStore {
+    // Updates the ILP address of the store & iterates over all children and
+    // updates their ILP Address to match the new address.
+    async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
+        debug!("Setting ILP address to: {}", ilp_address);
+        Ok(self.set_ilp_address_data(ilp_address).await?)
+    }
+
+    async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+        let client = self
+            .pool
+            .get()
+            .await
+            .map_err(|err| AddressStoreError::from(PostgresStoreError::from(err)))?;
+        client
+            .execute(
+                "DELETE FROM node_settings WHERE key = $1",
+                &[&PARENT_ILP_KEY],
+            )
+            .await
+            .map_err(|err| AddressStoreError::from(PostgresStoreError::from(err)))?;
+        // overwrite the ilp address with the default value
+        *(self.ilp_address.write()) = DEFAULT_ILP_ADDRESS.clone();
+        Ok(())
+    }
+
+    fn get_ilp_address(&self) -> Address {
+        self.ilp_address.read().clone()
+    }
+}
+
+type Routes<A> = HashMap<String, A>;
+
+#[async_trait]
+impl CcpRoutingStore for PostgresStore {
+    type Account = Account;
+
+    async fn get_accounts_to_send_routes_to(
+        &self,
+        ignore_accounts: Vec<Uuid>,
+    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        Ok(self
+            .filter_accounts(|account| {
+                account.should_send_routes() && !ignore_accounts.contains(&account.id)
+            })
+            .await?)
+    }
+
+    async fn get_accounts_to_receive_routes_from(
+        &self,
+    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        Ok(self
+            .filter_accounts(|account| account.should_receive_routes())
+            .await?)
+    }
+
+    async fn get_local_and_configured_routes(
+        &self,
+    ) -> Result<(Routes<Account>, Routes<Account>), CcpRoutingStoreError> {
+        let accounts = self.all_accounts().await?;
+        let local_table: HashMap<String, Account> = accounts
+            .iter()
+            .map(|account| (account.ilp_address.to_string(), account.clone()))
+            .collect();
+        let accounts: HashMap<Uuid, Account> = accounts
+            .into_iter()
+            .map(|account| (account.id, account))
+            .collect();
+
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let static_routes = load_static_routes(&client).await?;
+        let mut configured_table = HashMap::new();
+        for (prefix, route) in static_routes {
+            // Advertise the route through the first of its next hops which still exists
+            if let Some(account) = route.account_ids().find_map(|id| accounts.get(&id)) {
+                configured_table.insert(prefix, account.clone());
+            } else {
+                warn!(
+                    "No account for route: {}, ignoring configured route for prefix: {}",
+                    route, prefix
+                );
+            }
+        }
+
+        Ok((local_table, configured_table))
+    }
+
+    async fn set_routes(
+        &mut self,
+        routes: impl IntoIterator<Item = (String, Account)> + Send + 'async_trait,
+    ) -> Result<(), CcpRoutingStoreError> {
+        let (prefixes, account_ids): (Vec<String>, Vec<Uuid>) = routes
+            .into_iter()
+            .map(|(prefix, account)| (prefix, account.id))
+            .collect::<HashMap<String, Uuid>>()
+            .into_iter()
+            .unzip();
+        let num_routes = prefixes.len();
+
+        // The routes set by CCP replace the whole dynamic routing table
+        let mut client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let tx = client
+            .transaction()
+            .await
+            .map_err(PostgresStoreError::from)?;
+        tx.batch_execute("DELETE FROM routes")
+            .await
+            .map_err(PostgresStoreError::from)?;
+        tx.execute(
+            "INSERT INTO routes (prefix, account_id)
+            SELECT * FROM UNNEST($1::TEXT[], $2::UUID[])",
+            &[&prefixes, &account_ids],
+        )
+        .await
+        .map_err(PostgresStoreError::from)?;
+        tx.commit().await.map_err(PostgresStoreError::from)?;
+        drop(client);
+
+        trace!("Saved {} routes to Postgres", num_routes);
+        self.update_routes().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for PostgresStore {
+    type Account = Account;
+
+    /// Apply rate limits for number of packets per minute and amount of money per minute
+    ///
+    /// This uses the same generic cell rate algorithm as [redis-cell](https://github.com/brandur/redis-cell)
+    async fn apply_rate_limits(
+        &self,
+        account: Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        // Both limits are always applied, even if the first one is exceeded
+        let packets_limited = account.packets_per_minute_limit.map(|limit| {
+            let limit = u64::from(limit.saturating_sub(1));
+            self.rate_limiter
+                .throttle(format!("limit:packets:{}", account.id), limit, limit, 1)
+        });
+        let amount_limited = account.amount_per_minute_limit.map(|limit| {
+            let limit = limit.saturating_sub(1);
+            self.rate_limiter.throttle(
+                format!("limit:throughput:{}", account.id),
+                limit,
+                limit,
+                prepare_amount,
+            )
+        });
+
+        if packets_limited == Some(true) {
+            Err(RateLimitError::PacketLimitExceeded)
+        } else if amount_limited == Some(true) {
+            Err(RateLimitError::ThroughputLimitExceeded)
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn refund_throughput_limit(
+        &self,
+        account: Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        if let Some(limit) = account.amount_per_minute_limit {
+            self.rate_limiter.refund(
+                &format!("limit:throughput:{}", account.id),
+                limit.saturating_sub(1),
+                prepare_amount,
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IdempotentStore for PostgresStore {
+    async fn load_idempotent_data(
+        &self,
+        idempotency_key: String,
+    ) -> Result<Option<IdempotentData>, IdempotentStoreError> {
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let row = match client
+            .query_opt(
+                "SELECT status, body, input_hash FROM idempotent_responses
+                WHERE idempotency_key = $1 AND expires_at > now()",
+                &[&idempotency_key],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?
+        {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let status: i32 = row.try_get(0).map_err(PostgresStoreError::from)?;
+        let body: Vec<u8> = row.try_get(1).map_err(PostgresStoreError::from)?;
+        let input_hash: Vec<u8> = row.try_get(2).map_err(PostgresStoreError::from)?;
+
+        let status = u16::try_from(status)
+            .ok()
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .ok_or_else(|| PostgresStoreError::Corrupted(format!("invalid status {}", status)))?;
+        let input_hash: [u8; 32] = input_hash.as_slice().try_into().map_err(|_| {
+            PostgresStoreError::Corrupted("input hash is not 32 bytes long".to_owned())
+        })?;
+        let data = IdempotentData::new(status, Bytes::from(body), input_hash);
+        trace!("Loaded idempotency key {:?} - {:?}", idempotency_key, data);
+        Ok(Some(data))
+    }
+
+    async fn save_idempotent_data(
+        &self,
+        idempotency_key: String,
+        input_hash: [u8; 32],
+        status_code: StatusCode,
+        data: Bytes,
+    ) -> Result<(), IdempotentStoreError> {
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        // Keys expire after 24 hours, the same as in the Redis store
+        client
+            .execute(
+                "INSERT INTO idempotent_responses
+                (idempotency_key, status, body, input_hash, expires_at)
+                VALUES ($1, $2, $3, $4, now() + interval '24 hours')
+                ON CONFLICT (idempotency_key) DO UPDATE SET status = EXCLUDED.status,
+                body = EXCLUDED.body, input_hash = EXCLUDED.input_hash,
+                expires_at = EXCLUDED.expires_at",
+                &[
+                    &idempotency_key,
+                    &i32::from(status_code.as_u16()),
+                    &&data[..],
+                    &&input_hash[..],
+                ],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?;
+        trace!(
+            "Cached {:?}: {:?}, {:?}",
+            idempotency_key,
+            status_code,
+            data,
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SettlementStore for PostgresStore {
+    type Account = Account;
+
+    async fn update_balance_for_incoming_settlement(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: Option<String>,
+    ) -> Result<(), SettlementStoreError> {
+        let balance = self
+            .incoming_settlement_data(account_id, amount, idempotency_key)
+            .await?;
+        if let Some(balance) = balance {
+            trace!(
+                "Processed incoming settlement from account: {} for amount: {}. Balance is now: {}",
+                account_id,
+                amount,
+                balance.total()
+            );
+        }
+        Ok(())
+    }
+
+    async fn refund_settlement(
+        &self,
+        account_id: Uuid,
+        settle_amount: u64,
+    ) -> Result<(), SettlementStoreError> {
+        trace!(
+            "Refunding settlement for account: {} of amount: {}",
+            account_id,
+            settle_amount
+        );
+        let balance = self.refund_data(account_id, settle_amount).await?;
+        trace!(
+            "Refunded settlement for account: {} of amount: {}. Balance is now: {}",
+            account_id,
+            settle_amount,
+            balance.balance
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LeftoversStore for PostgresStore {
+    type AccountId = Uuid;
+    type AssetType = BigUint;
+
+    async fn get_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        let mut client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let tx = client
+            .transaction()
+            .await
+            .map_err(PostgresStoreError::from)?;
+        let amounts = take_uncredited_amounts(&tx, account_id).await?;
+        tx.commit().await.map_err(PostgresStoreError::from)?;
+        Ok(sum_uncredited_amounts(amounts)?)
+    }
+
+    async fn peek_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let amounts = client
+            .query(
+                "SELECT amount::TEXT, scale FROM uncredited_settlement_amounts
+                WHERE account_id = $1",
+                &[&account_id],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?
+            .iter()
+            .map(uncredited_amount_from_row)
+            .collect::<Result<UncreditedAmounts, _>>()?;
+        Ok(sum_uncredited_amounts(amounts)?)
+    }
+
+    async fn save_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+        uncredited_settlement_amount: (Self::AssetType, u8),
+    ) -> Result<(), LeftoversStoreError> {
+        trace!(
+            "Saving uncredited_settlement_amount {:?} {:?}",
+            account_id,
+            uncredited_settlement_amount
+        );
+        let mut client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        let tx = client
+            .transaction()
+            .await
+            .map_err(PostgresStoreError::from)?;
+        push_uncredited_amount(&tx, account_id, uncredited_settlement_amount).await?;
+        tx.commit().await.map_err(PostgresStoreError::from)?;
+        Ok(())
+    }
+
+    async fn load_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+        local_scale: u8,
+    ) -> Result<Self::AssetType, LeftoversStoreError> {
+        trace!("Loading uncredited_settlement_amount {:?}", account_id);
+        Ok(self.load_uncredited_data(account_id, local_scale).await?)
+    }
+
+    async fn clear_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(), LeftoversStoreError> {
+        trace!("Clearing uncredited_settlement_amount {:?}", account_id);
+        let client = self.pool.get().await.map_err(PostgresStoreError::from)?;
+        client
+            .execute(
+                "DELETE FROM uncredited_settlement_amounts WHERE account_id = $1",
+                &[&account_id],
+            )
+            .await
+            .map_err(PostgresStoreError::from)?;
+        Ok(())
+    }
+}
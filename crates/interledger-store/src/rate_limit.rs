@@ -0,0 +1,65 @@
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// The period over which the per-minute rate limits are applied
+const RATE_LIMIT_PERIOD: Duration = Duration::from_secs(60);
+
+/// An in-process implementation of the generic cell rate algorithm, mirroring
+/// `CL.THROTTLE` from [redis-cell](https://github.com/brandur/redis-cell) which
+/// is used by the Redis store.
+///
+/// Rate limits are not persisted, so they reset when the node restarts.
+pub(crate) struct RateLimiter {
+    /// Reference point for the theoretical arrival times
+    epoch: Instant,
+    /// Theoretical arrival times (in nanoseconds since `epoch`) for each key
+    tats: Mutex<HashMap<String, u128>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new() -> Self {
+        RateLimiter {
+            epoch: Instant::now(),
+            tats: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes `quantity` from the bucket identified by `key`, which allows `count_per_period`
+    /// per minute with bursts of up to `max_burst + 1`. Returns true if the request should be limited.
+    pub(crate) fn throttle(
+        &self,
+        key: String,
+        max_burst: u64,
+        count_per_period: u64,
+        quantity: u64,
+    ) -> bool {
+        let now = self.epoch.elapsed().as_nanos();
+        let emission_interval = emission_interval(count_per_period);
+        let delay_variation_tolerance = emission_interval * (u128::from(max_burst) + 1);
+        let increment = emission_interval * u128::from(quantity);
+
+        let mut tats = self.tats.lock();
+        let tat = tats.get(&key).cloned().unwrap_or(now).max(now);
+        let new_tat = tat + increment;
+        if new_tat.saturating_sub(delay_variation_tolerance) > now {
+            true
+        } else {
+            tats.insert(key, new_tat);
+            false
+        }
+    }
+
+    /// Gives back capacity which was taken by a previous call to `throttle`
+    pub(crate) fn refund(&self, key: &str, count_per_period: u64, quantity: u64) {
+        if let Some(tat) = self.tats.lock().get_mut(key) {
+            *tat = tat.saturating_sub(emission_interval(count_per_period) * u128::from(quantity));
+        }
+    }
+}
+
+fn emission_interval(count_per_period: u64) -> u128 {
+    RATE_LIMIT_PERIOD.as_nanos() / u128::from(count_per_period.max(1))
+}
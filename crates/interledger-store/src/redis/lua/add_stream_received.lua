@@ -0,0 +1,18 @@
+-- Adds the amount to the total received by a STREAM connection, unless the connection
+-- is closed or the amount would take it over its receive max.
+-- Returns the status (0: added, 1: closed, 2: receive max exceeded), the total
+-- received and the receive max (nil if there is none)
+local connection_key = KEYS[1]
+local amount = ARGV[1]
+local total_received, receive_max, closed = unpack(redis.call('HMGET', connection_key, 'total_received', 'receive_max', 'closed'))
+total_received = total_received or 0
+
+if closed == '1' then
+    return {1, total_received, receive_max}
+end
+if receive_max and tonumber(total_received) + tonumber(amount) > tonumber(receive_max) then
+    return {2, total_received, receive_max}
+end
+
+total_received = redis.call('HINCRBY', connection_key, 'total_received', amount)
+return {0, total_received, receive_max}
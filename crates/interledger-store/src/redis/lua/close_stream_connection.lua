@@ -0,0 +1,9 @@
+-- Marks a STREAM connection as closed and returns its total received and receive max,
+-- or nil if the connection is unknown or was already closed
+local connection_key = KEYS[1]
+if redis.call('EXISTS', connection_key) == 0 or redis.call('HGET', connection_key, 'closed') == '1' then
+    return nil
+end
+
+redis.call('HSET', connection_key, 'closed', 1)
+return redis.call('HMGET', connection_key, 'total_received', 'receive_max')
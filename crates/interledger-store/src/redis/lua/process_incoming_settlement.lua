@@ -5,9 +5,10 @@ local idempotency_key = ARGV[4]
 
 local balance, prepaid_amount = unpack(redis.call('HMGET', account, 'balance', 'prepaid_amount'))
 
--- If idempotency key has been used, then do not perform any operations
+-- If idempotency key has been used, then do not perform any operations.
+-- The second value tells whether the settlement was credited
 if redis.call('EXISTS', idempotency_key) == 1 then
-    return balance + prepaid_amount
+    return {balance + prepaid_amount, 0}
 end
 
 -- Otherwise, set it to true and make it expire after 24h (86400 sec)
@@ -25,4 +26,4 @@ else
     redis.call('HSET', account, 'balance', 0)
 end
 
-return balance + prepaid_amount
\ No newline at end of file
+return {balance + prepaid_amount, 1}
\ No newline at end of file
@@ -0,0 +1,9 @@
+local history_key = KEYS[1]
+local cutoff = ARGV[1]
+
+-- The last rate before the cutoff is kept, because it was still in effect
+local kept = redis.call('ZREVRANGEBYSCORE', history_key, '(' .. cutoff, '-inf', 'WITHSCORES', 'LIMIT', 0, 1)
+if #kept == 0 then
+    return 0
+end
+return redis.call('ZREMRANGEBYSCORE', history_key, '-inf', '(' .. kept[2])
@@ -0,0 +1,39 @@
+local transactions_key = KEYS[1]
+local trimmed_key = KEYS[2]
+local max_transactions = tonumber(ARGV[1])
+
+local excess = redis.call('ZCARD', transactions_key) - max_transactions
+if excess <= 0 then
+    return 0
+end
+
+-- The transactions are scored by their ID, so the oldest come first.
+-- The fields are matched rather than decoded, because the amounts may
+-- not fit in a Lua number
+local trimmed = redis.call('ZRANGE', transactions_key, 0, excess - 1)
+for _, member in ipairs(trimmed) do
+    local kind = string.match(member, '"kind":"([%a_]+)"')
+    local amount = string.match(member, '"amount":(%d+)')
+    local fulfilled = string.find(member, '"outcome":"fulfilled"', 1, true) ~= nil
+    local total = nil
+    if kind == 'incoming_packet' and fulfilled then
+        total = 'packets_in'
+    elseif kind == 'outgoing_packet' and fulfilled then
+        total = 'packets_out'
+    elseif kind == 'incoming_settlement' then
+        total = 'settled_in'
+    elseif kind == 'outgoing_settlement' then
+        total = 'settled_out'
+    elseif kind == 'settlement_accepted' then
+        total = 'accepted_out'
+    elseif kind == 'settlement_refund' then
+        total = 'refunded'
+        redis.call('HINCRBY', trimmed_key, 'refunds', 1)
+    end
+    if total then
+        redis.call('HINCRBY', trimmed_key, total, amount)
+    end
+end
+
+redis.call('ZREMRANGEBYRANK', transactions_key, 0, excess - 1)
+return excess
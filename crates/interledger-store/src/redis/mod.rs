@@ -9,6 +9,11 @@
 //   accounts               set
 //   usernames              hash
 //   btp_outgoing
+//   stream_connections:<token>  hash  state of a connection of a stateful STREAM receiver
+//   next_transaction_id    string      unique ID for each recorded transaction
+//   transactions:<id>      sorted set  JSON transactions of each account, scored by their ID
+//   trimmed_transactions:<id>  hash    totals of the transactions removed from the history of each account
+//   rate_history:<asset>   sorted set  JSON rates of each asset, scored by when they were recorded
 // For interactive exploration of the store,
 // use the redis-cli tool included with your redis install.
 // Within redis-cli:
@@ -31,18 +36,24 @@ use interledger_ccp::{CcpRoutingAccount, CcpRoutingStore, RoutingRelation};
 use interledger_errors::*;
 use interledger_http::HttpStore;
 use interledger_packet::Address;
-use interledger_rates::ExchangeRateStore;
-use interledger_router::RouterStore;
+use interledger_rates::{
+    ExchangeRateHistoryStore, ExchangeRateStore, RateSnapshot, DEFAULT_RATE_HISTORY_RETENTION,
+};
+use interledger_router::{Route, RouterStore, RoutingTable};
 use interledger_service::{Account as AccountTrait, AccountStore, AddressStore, Username};
 use interledger_service_util::{
-    BalanceStore, RateLimitError, RateLimitStore, DEFAULT_ROUND_TRIP_TIME,
+    BalanceStore, FeePolicy, FeePolicyStore, RateLimitError, RateLimitStore, Transaction,
+    TransactionKind, TransactionQuery, TransactionRecord, TransactionStore, TransactionTotals,
+    DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT, DEFAULT_ROUND_TRIP_TIME,
 };
 use interledger_settlement::core::{
     idempotency::{IdempotentData, IdempotentStore},
     scale_with_precision_loss,
     types::{Convert, ConvertDetails, LeftoversStore, SettlementStore},
 };
-use interledger_stream::{PaymentNotification, StreamNotificationsStore};
+use interledger_stream::{
+    PaymentNotification, StreamConnectionRecord, StreamConnectionStore, StreamNotificationsStore,
+};
 use num_bigint::BigUint;
 use once_cell::sync::Lazy;
 use parking_lot::{Mutex, RwLock};
@@ -52,7 +63,7 @@ use redis_crate::{
     PubSubCommands, RedisError, RedisWrite, Script, ToRedisArgs, Value,
 };
 use secrecy::{ExposeSecret, Secret, SecretBytesMut};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{borrow::Cow, iter, str, str::FromStr, sync::Arc, time::Duration};
 use std::{collections::HashMap, fmt::Display};
 use tokio::sync::broadcast;
@@ -62,7 +73,7 @@ use uuid::Uuid;
 use zeroize::Zeroize;
 
 const DEFAULT_POLL_INTERVAL: u64 = 30000; // 30 seconds
-const ACCOUNT_DETAILS_FIELDS: usize = 21;
+const ACCOUNT_DETAILS_FIELDS: usize = 22;
 const DEFAULT_DB_PREFIX: &str = "";
 
 static PARENT_ILP_KEY: &str = "parent_node_account_address";
@@ -76,6 +87,7 @@ static ACCOUNTS_KEY: &str = "accounts";
 static SEND_ROUTES_KEY: &str = "send_routes_to";
 static RECEIVE_ROUTES_FROM_KEY: &str = "receive_routes_from";
 static BPT_OUTGOING: &str = "btp_outgoing";
+static NEXT_TRANSACTION_ID_KEY: &str = "next_transaction_id";
 
 /// Domain separator for leftover amounts
 fn uncredited_amount_key(prefix: &str, account_id: impl ToString) -> String {
@@ -103,6 +115,26 @@ fn prefixed_key<'a>(prefix: &str, key: &'a str) -> Cow<'a, str> {
     }
 }
 
+/// Domain separator for the state of STREAM connections
+fn stream_connection_key(prefix: &str, token: &str) -> String {
+    prefixed_key(prefix, &format!("stream_connections:{}", token)).into_owned()
+}
+
+/// Domain separator for the transaction history of accounts
+fn transactions_key(prefix: &str, account_id: Uuid) -> String {
+    prefixed_key(prefix, &format!("transactions:{}", account_id)).into_owned()
+}
+
+/// Domain separator for the totals of the transactions trimmed from the history of accounts
+fn trimmed_transactions_key(prefix: &str, account_id: Uuid) -> String {
+    prefixed_key(prefix, &format!("trimmed_transactions:{}", account_id)).into_owned()
+}
+
+/// Domain separator for the history of the exchange rates of assets
+fn rate_history_key(prefix: &str, asset_code: &str) -> String {
+    prefixed_key(prefix, &format!("rate_history:{}", asset_code)).into_owned()
+}
+
 /// Domain separator for accounts
 fn accounts_key(prefix: &str, account_id: Uuid) -> String {
     prefixed_key(prefix, &format!("accounts:{}", account_id)).into_owned()
@@ -154,6 +186,24 @@ static REFUND_SETTLEMENT: Lazy<Script> =
 static PROCESS_INCOMING_SETTLEMENT: Lazy<Script> =
     Lazy::new(|| Script::new(include_str!("lua/process_incoming_settlement.lua")));
 
+/// Lua script which adds to the total received by a STREAM connection, within its receive max
+static ADD_STREAM_RECEIVED: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/add_stream_received.lua")));
+
+/// Lua script which closes a STREAM connection, if it is open
+static CLOSE_STREAM_CONNECTION: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/close_stream_connection.lua")));
+
+/// Lua script which removes the oldest transactions of an account past the maximum number
+/// kept, and adds them to the account's trimmed totals
+static TRIM_TRANSACTIONS: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/trim_transactions.lua")));
+
+/// Lua script which removes the rates of an asset which are past the retention, except
+/// for the last one before it
+static TRIM_RATE_HISTORY: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/trim_rate_history.lua")));
+
 /// Builder for the Redis Store
 pub struct RedisStoreBuilder {
     redis_url: ConnectionInfo,
@@ -162,6 +212,8 @@ pub struct RedisStoreBuilder {
     /// Connector's ILP Address. Used to insert `Child` accounts as
     node_ilp_address: Address,
     db_prefix: String,
+    max_transactions_per_account: usize,
+    rate_history_retention: u64,
 }
 
 impl RedisStoreBuilder {
@@ -173,6 +225,8 @@ impl RedisStoreBuilder {
             poll_interval: DEFAULT_POLL_INTERVAL,
             node_ilp_address: DEFAULT_ILP_ADDRESS.clone(),
             db_prefix: DEFAULT_DB_PREFIX.to_string(),
+            max_transactions_per_account: DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT,
+            rate_history_retention: DEFAULT_RATE_HISTORY_RETENTION,
         }
     }
 
@@ -195,6 +249,20 @@ impl RedisStoreBuilder {
         self
     }
 
+    /// Sets the number of transactions kept for each account. Once an account has more,
+    /// its oldest transactions are removed and only their totals are kept
+    pub fn max_transactions_per_account(&mut self, max: usize) -> &mut Self {
+        self.max_transactions_per_account = max;
+        self
+    }
+
+    /// Sets how long (in milliseconds) the rates are kept in their history. The last rate
+    /// recorded before that is kept as well, since it was still in effect
+    pub fn rate_history_retention(&mut self, retention: u64) -> &mut Self {
+        self.rate_history_retention = retention;
+        self
+    }
+
     /// Connects to the Redis Store
     ///
     /// Specifically
@@ -246,10 +314,14 @@ impl RedisStoreBuilder {
             subscriptions: Arc::new(Mutex::new(HashMap::new())),
             payment_publisher: all_payment_publisher,
             exchange_rates: Arc::new(RwLock::new(HashMap::new())),
-            routes: Arc::new(RwLock::new(Arc::new(HashMap::new()))),
+            exchange_rates_fetched_at: Arc::new(RwLock::new(HashMap::new())),
+            fee_policy: Arc::new(RwLock::new(Arc::new(FeePolicy::default()))),
+            routes: Arc::new(RwLock::new(Arc::new(RoutingTable::new()))),
             encryption_key: Arc::new(encryption_key),
             decryption_key: Arc::new(decryption_key),
             db_prefix: self.db_prefix.clone(),
+            max_transactions_per_account: self.max_transactions_per_account,
+            rate_history_retention: self.rate_history_retention,
         };
 
         // Poll for routing table updates
@@ -359,19 +431,26 @@ pub struct RedisStore {
     /// A subscriber to all payment notifications, exposed via a WebSocket
     payment_publisher: broadcast::Sender<PaymentNotification>,
     exchange_rates: Arc<RwLock<HashMap<String, f64>>>,
+    /// When each of the rates was fetched, in milliseconds since the Unix epoch
+    exchange_rates_fetched_at: Arc<RwLock<HashMap<String, u64>>>,
+    fee_policy: Arc<RwLock<Arc<FeePolicy>>>,
     /// The store keeps the routing table in memory so that it can be returned
     /// synchronously while the Router is processing packets.
     /// The outer `Arc<RwLock>` is used so that we can update the stored routing
     /// table after polling the store for updates.
     /// The inner `Arc<HashMap>` is used so that the `routing_table` method can
     /// return a reference to the routing table without cloning the underlying data.
-    routes: Arc<RwLock<Arc<HashMap<String, Uuid>>>>,
+    routes: Arc<RwLock<Arc<RoutingTable<Route>>>>,
     /// Encryption Key so that the no cleartext data are stored
     encryption_key: Arc<Secret<EncryptionKey>>,
     /// Decryption Key to provide cleartext data to users
     decryption_key: Arc<Secret<DecryptionKey>>,
     /// Prefix for all top level keys. This enables multiple nodes to use the same db instance.
     db_prefix: String,
+    /// Number of transactions kept for each account
+    max_transactions_per_account: usize,
+    /// How long the rates are kept in their history, in milliseconds
+    rate_history_retention: u64,
 }
 
 impl RedisStore {
@@ -664,6 +743,10 @@ impl RedisStore {
         .ignore();
         pipe.del(&*accounts_key(&self.db_prefix, account.id))
             .ignore();
+        pipe.del(&*transactions_key(&self.db_prefix, account.id))
+            .ignore();
+        pipe.del(&*trimmed_transactions_key(&self.db_prefix, account.id))
+            .ignore();
         pipe.hdel(
             &*prefixed_key(&self.db_prefix, USERNAMES_KEY),
             account.username().as_ref(),
@@ -829,6 +912,223 @@ impl StreamNotificationsStore for RedisStore {
     }
 }
 
+/// Number of transactions fetched at a time while looking for the ones which match a query
+const TRANSACTIONS_BATCH_SIZE: usize = 100;
+
+#[async_trait]
+impl TransactionStore for RedisStore {
+    async fn record_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+    ) -> Result<(), TransactionStoreError> {
+        if transactions.is_empty() {
+            return Ok(());
+        }
+        let mut connection = self.connection.clone();
+        // Reserve one ID per transaction, the last of which is returned
+        let last_id: u64 = connection
+            .incr(
+                &*prefixed_key(&self.db_prefix, NEXT_TRANSACTION_ID_KEY),
+                transactions.len() as u64,
+            )
+            .await?;
+        let first_id = last_id + 1 - transactions.len() as u64;
+
+        let mut pipe = redis_crate::pipe();
+        let mut account_ids = Vec::new();
+        for (id, transaction) in (first_id..).zip(transactions) {
+            let key = transactions_key(&self.db_prefix, transaction.account_id);
+            if !account_ids.contains(&transaction.account_id) {
+                account_ids.push(transaction.account_id);
+            }
+            let record = TransactionRecord { id, transaction };
+            let member = serde_json::to_string(&record)
+                .map_err(|err| TransactionStoreError::Other(Box::new(err)))?;
+            pipe.zadd(key, member, id).ignore();
+        }
+        pipe.query_async(&mut connection).await?;
+
+        for account_id in account_ids {
+            let trimmed: u64 = TRIM_TRANSACTIONS
+                .key(transactions_key(&self.db_prefix, account_id))
+                .key(trimmed_transactions_key(&self.db_prefix, account_id))
+                .arg(self.max_transactions_per_account)
+                .invoke_async(&mut connection)
+                .await?;
+            if trimmed > 0 {
+                trace!(
+                    "Trimmed {} transactions from the history of account {}",
+                    trimmed,
+                    account_id
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        query: TransactionQuery,
+    ) -> Result<Vec<TransactionRecord>, TransactionStoreError> {
+        let mut records = Vec::new();
+        if query.limit == 0 {
+            return Ok(records);
+        }
+        let key = transactions_key(&self.db_prefix, account_id);
+        let max = match query.before {
+            Some(before) => format!("({}", before),
+            None => "+inf".to_string(),
+        };
+        let min = match query.after {
+            Some(after) => format!("({}", after),
+            None => "-inf".to_string(),
+        };
+        let mut offset = 0;
+        loop {
+            let members: Vec<String> = cmd("ZREVRANGEBYSCORE")
+                .arg(&key)
+                .arg(&max)
+                .arg(&min)
+                .arg("LIMIT")
+                .arg(offset)
+                .arg(TRANSACTIONS_BATCH_SIZE)
+                .query_async(&mut self.connection.clone())
+                .await?;
+            let fetched = members.len();
+            for member in members {
+                let record: TransactionRecord = serde_json::from_str(&member)
+                    .map_err(|err| TransactionStoreError::Other(Box::new(err)))?;
+                if query.matches(&record.transaction) {
+                    records.push(record);
+                    if records.len() >= query.limit {
+                        return Ok(records);
+                    }
+                }
+            }
+            if fetched < TRANSACTIONS_BATCH_SIZE {
+                return Ok(records);
+            }
+            offset += fetched;
+        }
+    }
+
+    async fn get_trimmed_transaction_totals(
+        &self,
+        account_id: Uuid,
+    ) -> Result<TransactionTotals, TransactionStoreError> {
+        let totals: HashMap<String, u64> = self
+            .connection
+            .clone()
+            .hgetall(trimmed_transactions_key(&self.db_prefix, account_id))
+            .await?;
+        let total = |name: &str| totals.get(name).copied().unwrap_or_default();
+        Ok(TransactionTotals {
+            packets_in: total("packets_in"),
+            packets_out: total("packets_out"),
+            settled_in: total("settled_in"),
+            settled_out: total("settled_out"),
+            accepted_out: total("accepted_out"),
+            refunded: total("refunded"),
+            refunds: total("refunds"),
+        })
+    }
+}
+
+#[async_trait]
+impl StreamConnectionStore for RedisStore {
+    async fn set_stream_receive_max(
+        &self,
+        token: &str,
+        receive_max: u64,
+    ) -> Result<(), StreamConnectionStoreError> {
+        let _: () = self
+            .connection
+            .clone()
+            .hset(
+                stream_connection_key(&self.db_prefix, token),
+                "receive_max",
+                receive_max,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_stream_connection(
+        &self,
+        token: &str,
+    ) -> Result<Option<StreamConnectionRecord>, StreamConnectionStoreError> {
+        let (total_received, receive_max, closed): (Option<u64>, Option<u64>, Option<u8>) =
+            cmd("HMGET")
+                .arg(stream_connection_key(&self.db_prefix, token))
+                .arg("total_received")
+                .arg("receive_max")
+                .arg("closed")
+                .query_async(&mut self.connection.clone())
+                .await?;
+        if total_received.is_none() && receive_max.is_none() && closed.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(StreamConnectionRecord {
+            total_received: total_received.unwrap_or(0),
+            receive_max,
+            closed: closed == Some(1),
+        }))
+    }
+
+    async fn add_stream_received(
+        &self,
+        token: &str,
+        amount: u64,
+    ) -> Result<StreamConnectionRecord, StreamConnectionStoreError> {
+        let (status, total_received, receive_max): (u8, u64, Option<u64>) = ADD_STREAM_RECEIVED
+            .key(stream_connection_key(&self.db_prefix, token))
+            .arg(amount)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        match status {
+            0 => {
+                trace!(
+                    "STREAM connection {} received {} (total: {})",
+                    token,
+                    amount,
+                    total_received
+                );
+                Ok(StreamConnectionRecord {
+                    total_received,
+                    receive_max,
+                    closed: false,
+                })
+            }
+            1 => Err(StreamConnectionStoreError::ConnectionClosed(
+                token.to_string(),
+            )),
+            _ => Err(StreamConnectionStoreError::ReceiveMaxExceeded {
+                token: token.to_string(),
+                total_received,
+                receive_max: receive_max.unwrap_or_default(),
+            }),
+        }
+    }
+
+    async fn close_stream_connection(
+        &self,
+        token: &str,
+    ) -> Result<Option<StreamConnectionRecord>, StreamConnectionStoreError> {
+        let closed: Option<(Option<u64>, Option<u64>)> = CLOSE_STREAM_CONNECTION
+            .key(stream_connection_key(&self.db_prefix, token))
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        Ok(
+            closed.map(|(total_received, receive_max)| StreamConnectionRecord {
+                total_received: total_received.unwrap_or(0),
+                receive_max,
+                closed: true,
+            }),
+        )
+    }
+}
+
 #[async_trait]
 impl BalanceStore for RedisStore {
     /// Returns the balance **from the account holder's perspective**, meaning the sum of
@@ -966,8 +1266,118 @@ impl ExchangeRateStore for RedisStore {
     ) -> Result<(), ExchangeRateStoreError> {
         // TODO publish rate updates through a pubsub mechanism to support horizontally scaling nodes
         (*self.exchange_rates.write()) = rates;
+        self.exchange_rates_fetched_at.write().clear();
+        Ok(())
+    }
+
+    fn set_exchange_rates_fetched_at(
+        &self,
+        rates: HashMap<String, f64>,
+        fetched_at: HashMap<String, u64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        let mut exchange_rates = self.exchange_rates.write();
+        *exchange_rates = rates;
+        (*self.exchange_rates_fetched_at.write()) = fetched_at;
         Ok(())
     }
+
+    fn get_exchange_rates_fetched_at(
+        &self,
+        asset_codes: &[&str],
+    ) -> Result<Vec<Option<u64>>, ExchangeRateStoreError> {
+        let fetched_at = self.exchange_rates_fetched_at.read();
+        Ok(asset_codes
+            .iter()
+            .map(|code| fetched_at.get(*code).cloned())
+            .collect())
+    }
+}
+
+#[async_trait]
+impl ExchangeRateHistoryStore for RedisStore {
+    async fn record_exchange_rates(
+        &self,
+        timestamp: u64,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        if rates.is_empty() {
+            return Ok(());
+        }
+        let mut connection = self.connection.clone();
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic();
+        for (asset_code, rate) in rates.iter() {
+            let key = rate_history_key(&self.db_prefix, asset_code);
+            let member = serde_json::to_string(&RateSnapshot {
+                timestamp,
+                rate: *rate,
+            })
+            .map_err(|err| ExchangeRateStoreError::Other(Box::new(err)))?;
+            // Replace the rate recorded at the same time, if there is one
+            pipe.cmd("ZREMRANGEBYSCORE")
+                .arg(&key)
+                .arg(timestamp)
+                .arg(timestamp)
+                .ignore();
+            pipe.zadd(key, member, timestamp).ignore();
+        }
+        pipe.query_async(&mut connection).await?;
+
+        let cutoff = timestamp.saturating_sub(self.rate_history_retention);
+        for asset_code in rates.keys() {
+            let trimmed: u64 = TRIM_RATE_HISTORY
+                .key(rate_history_key(&self.db_prefix, asset_code))
+                .arg(cutoff)
+                .invoke_async(&mut connection)
+                .await?;
+            if trimmed > 0 {
+                trace!(
+                    "Trimmed {} rates from the history of {}",
+                    trimmed,
+                    asset_code
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_exchange_rate_history(
+        &self,
+        asset_code: &str,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<RateSnapshot>, ExchangeRateStoreError> {
+        if limit == 0 || from >= to {
+            return Ok(Vec::new());
+        }
+        let members: Vec<String> = cmd("ZRANGEBYSCORE")
+            .arg(rate_history_key(&self.db_prefix, asset_code))
+            .arg(from)
+            .arg(format!("({}", to))
+            .arg("LIMIT")
+            .arg(0)
+            .arg(limit)
+            .query_async(&mut self.connection.clone())
+            .await?;
+        members
+            .iter()
+            .map(|member| {
+                serde_json::from_str(member)
+                    .map_err(|err| ExchangeRateStoreError::Other(Box::new(err)))
+            })
+            .collect()
+    }
+}
+
+impl FeePolicyStore for RedisStore {
+    fn get_fee_policy(&self) -> Arc<FeePolicy> {
+        self.fee_policy.read().clone()
+    }
+
+    fn set_fee_policy(&self, policy: FeePolicy) {
+        *self.fee_policy.write() = Arc::new(policy);
+    }
 }
 
 #[async_trait]
@@ -1071,7 +1481,7 @@ impl HttpStore for RedisStore {
 }
 
 impl RouterStore for RedisStore {
-    fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
+    fn routing_table(&self) -> Arc<RoutingTable<Route>> {
         self.routes.read().clone()
     }
 }
@@ -1193,19 +1603,22 @@ impl NodeStore for RedisStore {
         Ok(accounts)
     }
 
-    async fn set_static_routes<R>(&self, routes: R) -> Result<(), NodeStoreError>
+    async fn set_static_routes<R, T>(&self, routes: R) -> Result<(), NodeStoreError>
     where
-        R: IntoIterator<Item = (String, Uuid)> + Send + 'async_trait,
+        R: IntoIterator<Item = (String, T)> + Send + 'async_trait,
+        T: Into<Route> + Send + 'async_trait,
     {
         let mut connection = self.connection.clone();
-        let routes: Vec<(String, RedisAccountId)> = routes
+        let routes: Vec<(String, RedisRoute)> = routes
             .into_iter()
-            .map(|(s, id)| (s, RedisAccountId(id)))
+            .map(|(s, route)| (s, RedisRoute(route.into())))
             .collect();
-        let accounts = routes.iter().map(|(_prefix, account_id)| account_id);
+        let accounts = routes
+            .iter()
+            .flat_map(|(_prefix, route)| route.0.account_ids());
         let mut pipe = redis_crate::pipe();
         for account_id in accounts {
-            pipe.exists(accounts_key(&self.db_prefix, (*account_id).0));
+            pipe.exists(accounts_key(&self.db_prefix, account_id));
         }
 
         let routing_table = self.routes.clone();
@@ -1254,7 +1667,7 @@ impl NodeStore for RedisStore {
             .hset(
                 &*prefixed_key(&self.db_prefix, STATIC_ROUTES_KEY),
                 prefix,
-                RedisAccountId(account_id),
+                RedisRoute(Route::from(account_id)),
             )
             .await?;
 
@@ -1433,7 +1846,7 @@ impl AddressStore for RedisStore {
     }
 }
 
-type RoutingTable<A> = HashMap<String, A>;
+type Routes<A> = HashMap<String, A>;
 
 #[async_trait]
 impl CcpRoutingStore for RedisStore {
@@ -1481,8 +1894,8 @@ impl CcpRoutingStore for RedisStore {
 
     async fn get_local_and_configured_routes(
         &self,
-    ) -> Result<(RoutingTable<Account>, RoutingTable<Account>), CcpRoutingStoreError> {
-        let static_routes: Vec<(String, RedisAccountId)> = self
+    ) -> Result<(Routes<Account>, Routes<Account>), CcpRoutingStoreError> {
+        let static_routes: Vec<(String, RedisRoute)> = self
             .connection
             .clone()
             .hgetall(&*prefixed_key(&self.db_prefix, STATIC_ROUTES_KEY))
@@ -1501,13 +1914,14 @@ impl CcpRoutingStore for RedisStore {
             .collect();
         let configured_table: HashMap<String, Account> = static_routes
             .into_iter()
-            .filter_map(|(prefix, account_id)| {
-                if let Some(account) = account_map.get(&account_id.0) {
+            .filter_map(|(prefix, route)| {
+                // Advertise the route through the first of its next hops which still exists
+                if let Some(account) = route.0.account_ids().find_map(|id| account_map.get(&id)) {
                     Some((prefix, (*account).clone()))
                 } else {
                     warn!(
-                        "No account for ID: {}, ignoring configured route for prefix: {}",
-                        account_id, prefix
+                        "No account for route: {}, ignoring configured route for prefix: {}",
+                        route.0, prefix
                     );
                     None
                 }
@@ -1718,7 +2132,7 @@ impl SettlementStore for RedisStore {
         idempotency_key: Option<String>,
     ) -> Result<(), SettlementStoreError> {
         let idempotency_key = idempotency_key.unwrap();
-        let balance: i64 = PROCESS_INCOMING_SETTLEMENT
+        let (balance, credited): (i64, bool) = PROCESS_INCOMING_SETTLEMENT
             .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
             .arg(RedisAccountId(account_id))
             .arg(amount)
@@ -1731,6 +2145,16 @@ impl SettlementStore for RedisStore {
             amount,
             balance
         );
+        if credited {
+            let transaction =
+                Transaction::settlement(account_id, TransactionKind::IncomingSettlement, amount);
+            if let Err(err) = self.record_transactions(vec![transaction]).await {
+                warn!(
+                    "Could not record incoming settlement from account {}: {}",
+                    account_id, err
+                );
+            }
+        }
         Ok(())
     }
 
@@ -1880,6 +2304,18 @@ impl LeftoversStore for RedisStore {
         Ok((amount.num, amount.scale))
     }
 
+    async fn peek_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        let amount: AmountWithScale = self
+            .connection
+            .clone()
+            .lrange(uncredited_amount_key(&self.db_prefix, account_id), 0, -1)
+            .await?;
+        Ok((amount.num, amount.scale))
+    }
+
     async fn save_uncredited_settlement_amount(
         &self,
         account_id: Uuid,
@@ -1956,15 +2392,18 @@ use futures::future::TryFutureExt;
 // TODO replace this with pubsub when async pubsub is added upstream: https://github.com/mitsuhiko/redis-rs/issues/183
 async fn update_routes(
     mut connection: RedisReconnect,
-    routing_table: Arc<RwLock<Arc<HashMap<String, Uuid>>>>,
+    routing_table: Arc<RwLock<Arc<RoutingTable<Route>>>>,
     db_prefix: &str,
 ) -> Result<(), RedisError> {
     let mut pipe = redis_crate::pipe();
     pipe.hgetall(&*prefixed_key(db_prefix, ROUTES_KEY))
         .hgetall(&*prefixed_key(db_prefix, STATIC_ROUTES_KEY))
         .get(&*prefixed_key(db_prefix, DEFAULT_ROUTE_KEY));
-    let (routes, static_routes, default_route): (RouteVec, RouteVec, Option<RedisAccountId>) =
-        pipe.query_async(&mut connection).await?;
+    let (routes, static_routes, default_route): (
+        RouteVec,
+        Vec<(String, RedisRoute)>,
+        Option<RedisAccountId>,
+    ) = pipe.query_async(&mut connection).await?;
     trace!(
         "Loaded routes from redis. Static routes: {:?}, default route: {:?}, other routes: {:?}",
         static_routes,
@@ -1975,15 +2414,15 @@ async fn update_routes(
     // set the entry for "" in the routing table to route to that account
     let default_route_iter = iter::once(default_route)
         .filter_map(|r| r)
-        .map(|rid| (String::new(), rid.0));
-    let routes = routes
+        .map(|rid| (String::new(), Route::from(rid.0)));
+    let routes: RoutingTable<Route> = routes
         .into_iter()
-        .map(|(s, rid)| (s, rid.0))
+        .map(|(s, rid)| (s, Route::from(rid.0)))
         // Include the default route if there is one
         .chain(default_route_iter)
         // Having the static_routes inserted after ensures that they will overwrite
         // any routes with the same prefix from the first set
-        .chain(static_routes.into_iter().map(|(s, rid)| (s, rid.0)))
+        .chain(static_routes.into_iter().map(|(s, route)| (s, route.0)))
         .collect();
     // TODO we may not want to print this because the routing table will be very big
     // if the node has a lot of local accounts
@@ -2029,6 +2468,26 @@ impl FromRedisValue for RedisAccountId {
     }
 }
 
+// Static routes are stored as strings (see `Route`'s `Display` implementation).
+// A route to a single account is stored as just its ID, like the other routes.
+#[derive(Debug, Clone)]
+struct RedisRoute(Route);
+
+impl ToRedisArgs for RedisRoute {
+    fn write_redis_args<W: RedisWrite + ?Sized>(&self, out: &mut W) {
+        out.write_arg(self.0.to_string().as_bytes());
+    }
+}
+
+impl FromRedisValue for RedisRoute {
+    fn from_redis_value(v: &Value) -> Result<Self, RedisError> {
+        let route = String::from_redis_value(v)?;
+        let route = Route::from_str(&route)
+            .map_err(|_| RedisError::from((ErrorKind::TypeError, "Invalid route string")))?;
+        Ok(RedisRoute(route))
+    }
+}
+
 impl ToRedisArgs for &AccountWithEncryptedTokens {
     fn write_redis_args<W: RedisWrite + ?Sized>(&self, out: &mut W) {
         let mut rv = Vec::with_capacity(ACCOUNT_DETAILS_FIELDS * 2);
@@ -2067,6 +2526,13 @@ impl ToRedisArgs for &AccountWithEncryptedTokens {
             "ilp_over_http_url".write_redis_args(&mut rv);
             ilp_over_http_url.as_str().write_redis_args(&mut rv);
         }
+        if let Some(settings) = account.ilp_over_http_client.as_ref() {
+            "ilp_over_http_client".write_redis_args(&mut rv);
+            // Serializing plain strings and numbers cannot fail
+            serde_json::to_string(settings)
+                .unwrap()
+                .write_redis_args(&mut rv);
+        }
         if let Some(ilp_over_http_incoming_token) = account.ilp_over_http_incoming_token.as_ref() {
             "ilp_over_http_incoming_token".write_redis_args(&mut rv);
             ilp_over_http_incoming_token
@@ -2160,6 +2626,7 @@ impl FromRedisValue for AccountWithEncryptedTokens {
                 asset_code: get_value("asset_code", &hash)?,
                 asset_scale: get_value("asset_scale", &hash)?,
                 ilp_over_http_url: get_url_option("ilp_over_http_url", &hash)?,
+                ilp_over_http_client: get_json_option("ilp_over_http_client", &hash)?,
                 ilp_over_http_incoming_token: get_bytes_option(
                     "ilp_over_http_incoming_token",
                     &hash,
@@ -2233,6 +2700,20 @@ fn get_bytes_option(
     }
 }
 
+fn get_json_option<T: DeserializeOwned>(
+    key: &str,
+    map: &HashMap<String, Value>,
+) -> Result<Option<T>, RedisError> {
+    if let Some(ref value) = map.get(key) {
+        let value: String = from_redis_value(value)?;
+        serde_json::from_str(&value)
+            .map(Some)
+            .map_err(|_| RedisError::from((ErrorKind::TypeError, "Invalid JSON", key.to_string())))
+    } else {
+        Ok(None)
+    }
+}
+
 fn get_url_option(key: &str, map: &HashMap<String, Value>) -> Result<Option<Url>, RedisError> {
     if let Some(ref value) = map.get(key) {
         let value: String = from_redis_value(value)?;
@@ -0,0 +1,2300 @@
+// The sled store persists the same data as the Redis store in an embedded database,
+// using one tree per kind of data:
+//   accounts                        account id -> account details (tokens encrypted)
+//   balances                        account id -> balance and prepaid amount
+//   usernames                       username -> account id
+//   routes                          dynamic routing table (set by CCP)
+//   routes_static                   static routing table (prefix -> route, see `route_from_bytes`)
+//   settlement_engines              asset code -> settlement engine url
+//   uncredited_settlement_amounts   account id -> leftovers from settlements
+//   idempotency_keys                idempotency key -> cached settlement API response
+//   settlement_idempotency_keys     idempotency key -> expiry of incoming settlement keys
+//   stream_connections              STREAM connection token -> total received and receive max
+//   transactions                    account id + transaction id -> transaction
+//   transaction_history             account id -> number of transactions kept and totals of the trimmed ones
+//   rate_history                    asset code + timestamp -> exchange rate (see `rate_history_key`)
+// The default tree holds the parent's ILP address and the default route.
+//
+// Every update which touches more than one key is done in a sled transaction, so the
+// balance updates are atomic in the same way as the Lua scripts used by the Redis store.
+// Like the Redis store, the current exchange rates and rate limits only live in memory.
+use super::account::{Account, AccountWithEncryptedTokens};
+use super::crypto::{encrypt_token, generate_keys, DecryptionKey, EncryptionKey};
+use super::rate_limit::RateLimiter;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::channel::mpsc::UnboundedSender;
+use http::StatusCode;
+use interledger_api::{AccountDetails, AccountSettings, NodeStore};
+use interledger_btp::BtpStore;
+use interledger_ccp::{CcpRoutingAccount, CcpRoutingStore, RoutingRelation};
+use interledger_errors::*;
+use interledger_http::{HttpClientSettings, HttpStore};
+use interledger_packet::Address;
+use interledger_rates::{
+    ExchangeRateHistoryStore, ExchangeRateStore, RateSnapshot, DEFAULT_RATE_HISTORY_RETENTION,
+};
+use interledger_router::{Route, RouterStore, RoutingTable};
+use interledger_service::{AccountStore, AddressStore, Username};
+use interledger_service_util::{
+    BalanceStore, FeePolicy, FeePolicyStore, RateLimitError, RateLimitStore, Transaction,
+    TransactionKind, TransactionQuery, TransactionRecord, TransactionStore, TransactionTotals,
+    DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT,
+};
+use interledger_settlement::core::{
+    idempotency::{IdempotentData, IdempotentStore},
+    scale_with_precision_loss,
+    types::{Convert, ConvertDetails, LeftoversStore, SettlementStore},
+};
+use interledger_stream::{
+    PaymentNotification, StreamConnectionRecord, StreamConnectionStore, StreamNotificationsStore,
+};
+use num_bigint::BigUint;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use secrecy::{ExposeSecret, Secret, SecretBytesMut, SecretString};
+use serde::{Deserialize, Serialize};
+use sled_crate::{
+    transaction::{
+        ConflictableTransactionError, ConflictableTransactionResult, TransactionError,
+        TransactionalTree,
+    },
+    Batch, Db, Transactional, Tree,
+};
+use std::{
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use tokio::sync::broadcast;
+use tracing::{debug, error, trace, warn};
+use url::Url;
+use uuid::Uuid;
+use zeroize::Zeroize;
+
+static PARENT_ILP_KEY: &str = "parent_node_account_address";
+static DEFAULT_ROUTE_KEY: &str = "routes:default";
+
+/// How long idempotency keys are remembered (24 hours, same as the Redis store)
+const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(86400);
+
+/// The node's default ILP Address
+static DEFAULT_ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("local.host").unwrap());
+
+/// Errors which are specific to the sled store. These are wrapped in the
+/// `Other` variant of the errors of each store trait.
+#[derive(Error, Debug)]
+enum SledStoreError {
+    #[error("{0}")]
+    Sled(#[from] sled_crate::Error),
+    #[error("could not (de)serialize stored data: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("stored data is corrupted: {0}")]
+    Corrupted(String),
+    #[error("account `{0}` was not found")]
+    AccountNotFound(Uuid),
+    #[error("Incoming prepare of {amount} would bring account {account_id} under its minimum balance. Current balance: {balance}, min balance: {min_balance}")]
+    MinBalanceExceeded {
+        account_id: Uuid,
+        amount: u64,
+        balance: i64,
+        min_balance: i64,
+    },
+    #[error("Amount {amount} does not fit in the balance of account {account_id}")]
+    AmountOutOfRange { account_id: Uuid, amount: u64 },
+    #[error("{0}")]
+    Node(NodeStoreError),
+    #[error("{0}")]
+    Stream(StreamConnectionStoreError),
+}
+
+impl From<TransactionError<SledStoreError>> for SledStoreError {
+    fn from(err: TransactionError<SledStoreError>) -> Self {
+        match err {
+            TransactionError::Abort(err) => err,
+            TransactionError::Storage(err) => SledStoreError::Sled(err),
+        }
+    }
+}
+
+impl From<SledStoreError> for NodeStoreError {
+    fn from(err: SledStoreError) -> Self {
+        match err {
+            SledStoreError::AccountNotFound(id) => NodeStoreError::AccountNotFound(id.to_string()),
+            SledStoreError::Node(err) => err,
+            err => NodeStoreError::Other(Box::new(err)),
+        }
+    }
+}
+
+macro_rules! impl_from_sled_store_error {
+    ($($error:ident),*) => {
+        $(
+            impl From<SledStoreError> for $error {
+                fn from(err: SledStoreError) -> Self {
+                    $error::Other(Box::new(err))
+                }
+            }
+        )*
+    };
+}
+
+impl From<SledStoreError> for StreamConnectionStoreError {
+    fn from(err: SledStoreError) -> Self {
+        match err {
+            SledStoreError::Stream(err) => err,
+            err => StreamConnectionStoreError::Other(Box::new(err)),
+        }
+    }
+}
+
+impl_from_sled_store_error!(
+    AccountStoreError,
+    AddressStoreError,
+    BalanceStoreError,
+    BtpStoreError,
+    CcpRoutingStoreError,
+    ExchangeRateStoreError,
+    HttpStoreError,
+    IdempotentStoreError,
+    LeftoversStoreError,
+    SettlementStoreError,
+    TransactionStoreError
+);
+
+/// Aborts a sled transaction with the provided error
+fn abort<T, E: Into<SledStoreError>>(err: E) -> ConflictableTransactionResult<T, SledStoreError> {
+    Err(ConflictableTransactionError::Abort(err.into()))
+}
+
+/// Converts an amount to the type of the balances, failing if it does not fit
+fn signed_amount(account_id: Uuid, amount: u64) -> Result<i64, SledStoreError> {
+    i64::try_from(amount).map_err(|_| SledStoreError::AmountOutOfRange { account_id, amount })
+}
+
+/// Builder for the Sled Store
+pub struct SledStoreBuilder {
+    // Path to the directory where the database is stored
+    path: PathBuf,
+    // The key used to encrypt the account tokens
+    secret: [u8; 32],
+    // Connector's ILP Address. Used to insert `Child` accounts as
+    node_ilp_address: Address,
+    // Number of transactions kept for each account
+    max_transactions_per_account: usize,
+    // How long the rates are kept in their history, in milliseconds
+    rate_history_retention: u64,
+}
+
+impl SledStoreBuilder {
+    /// Simple Constructor
+    pub fn new<P: AsRef<Path>>(path: P, secret: [u8; 32]) -> Self {
+        SledStoreBuilder {
+            path: path.as_ref().to_path_buf(),
+            secret,
+            node_ilp_address: DEFAULT_ILP_ADDRESS.clone(),
+            max_transactions_per_account: DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT,
+            rate_history_retention: DEFAULT_RATE_HISTORY_RETENTION,
+        }
+    }
+
+    /// Sets the ILP Address corresponding to the node
+    pub fn node_ilp_address(&mut self, node_ilp_address: Address) -> &mut Self {
+        self.node_ilp_address = node_ilp_address;
+        self
+    }
+
+    /// Sets the number of transactions kept for each account. Once an account has more,
+    /// its oldest transactions are removed and only their totals are kept
+    pub fn max_transactions_per_account(&mut self, max: usize) -> &mut Self {
+        self.max_transactions_per_account = max;
+        self
+    }
+
+    /// Sets how long (in milliseconds) the rates are kept in their history. The last rate
+    /// recorded before that is kept as well, since it was still in effect
+    pub fn rate_history_retention(&mut self, retention: u64) -> &mut Self {
+        self.rate_history_retention = retention;
+        self
+    }
+
+    /// Opens the Sled Store
+    ///
+    /// Specifically
+    /// 1. Generates encryption and decryption keys
+    /// 1. Opens (or creates) the database at the configured path
+    /// 1. Gets the Node address assigned to us by our parent (if it exists)
+    /// 1. Loads the routing table
+    pub fn open(&mut self) -> Result<SledStore, ()> {
+        let (encryption_key, decryption_key) = generate_keys(&self.secret[..]);
+        self.secret.zeroize(); // clear the secret after it has been used for key generation
+
+        let db = sled_crate::open(&self.path).map_err(|err| {
+            error!(
+                "Error opening sled database at {}: {:?}",
+                self.path.display(),
+                err
+            )
+        })?;
+        let open_tree = |name: &str| {
+            db.open_tree(name)
+                .map_err(|err| error!("Error opening sled tree {}: {:?}", name, err))
+        };
+
+        let (payment_publisher, _) = broadcast::channel::<PaymentNotification>(256);
+        let store = SledStore {
+            ilp_address: Arc::new(RwLock::new(self.node_ilp_address.clone())),
+            accounts: open_tree("accounts")?,
+            balances: open_tree("balances")?,
+            usernames: open_tree("usernames")?,
+            routes_tree: open_tree("routes")?,
+            static_routes: open_tree("routes_static")?,
+            settlement_engines: open_tree("settlement_engines")?,
+            uncredited_amounts: open_tree("uncredited_settlement_amounts")?,
+            idempotency_keys: open_tree("idempotency_keys")?,
+            settlement_idempotency_keys: open_tree("settlement_idempotency_keys")?,
+            stream_connections: open_tree("stream_connections")?,
+            transactions: open_tree("transactions")?,
+            transaction_history: open_tree("transaction_history")?,
+            max_transactions_per_account: self.max_transactions_per_account,
+            rate_history_retention: self.rate_history_retention,
+            rate_history: open_tree("rate_history")?,
+            db,
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            payment_publisher,
+            exchange_rates: Arc::new(RwLock::new(HashMap::new())),
+            exchange_rates_fetched_at: Arc::new(RwLock::new(HashMap::new())),
+            fee_policy: Arc::new(RwLock::new(Arc::new(FeePolicy::default()))),
+            routes: Arc::new(RwLock::new(Arc::new(RoutingTable::new()))),
+            rate_limiter: Arc::new(RateLimiter::new()),
+            encryption_key: Arc::new(encryption_key),
+            decryption_key: Arc::new(decryption_key),
+        };
+
+        // Before using the store, check if we have an address
+        // that was configured due to adding a parent. If no parent was
+        // found, use the builder's provided address (local.host) or the
+        // one we decided to override it with
+        let parent_address = store.db.get(PARENT_ILP_KEY).map_err(|err| {
+            error!(
+                "Error checking whether we have a parent configured: {:?}",
+                err
+            )
+        })?;
+        if let Some(address) = parent_address {
+            match Address::from_str(&String::from_utf8_lossy(&address)) {
+                Ok(address) => *store.ilp_address.write() = address,
+                Err(err) => error!("Ignoring invalid parent ILP address: {:?}", err),
+            }
+        }
+
+        store
+            .update_routes()
+            .map_err(|err| error!("Error loading routing table: {}", err))?;
+        Ok(store)
+    }
+}
+
+/// The net position with an account holder, see the store's README for details
+#[derive(Debug, Default, Clone, Copy)]
+struct Balance {
+    balance: i64,
+    prepaid_amount: i64,
+}
+
+impl Balance {
+    fn total(&self) -> i64 {
+        self.balance + self.prepaid_amount
+    }
+
+    fn to_bytes(self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&self.balance.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.prepaid_amount.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, SledStoreError> {
+        if bytes.len() != 16 {
+            return Err(SledStoreError::Corrupted(format!(
+                "balance has {} bytes, expected 16",
+                bytes.len()
+            )));
+        }
+        Ok(Balance {
+            balance: i64::from_be_bytes(bytes[..8].try_into().unwrap()),
+            prepaid_amount: i64::from_be_bytes(bytes[8..].try_into().unwrap()),
+        })
+    }
+}
+
+/// The on-disk representation of an account. The tokens are encrypted.
+#[derive(Serialize, Deserialize)]
+struct StoredAccount {
+    id: Uuid,
+    username: String,
+    ilp_address: String,
+    asset_code: String,
+    asset_scale: u8,
+    max_packet_amount: u64,
+    min_balance: Option<i64>,
+    ilp_over_http_url: Option<Url>,
+    #[serde(default)]
+    ilp_over_http_client: Option<HttpClientSettings>,
+    ilp_over_http_incoming_token: Option<Vec<u8>>,
+    ilp_over_http_outgoing_token: Option<Vec<u8>>,
+    ilp_over_btp_url: Option<Url>,
+    ilp_over_btp_incoming_token: Option<Vec<u8>>,
+    ilp_over_btp_outgoing_token: Option<Vec<u8>>,
+    settle_threshold: Option<i64>,
+    settle_to: Option<i64>,
+    routing_relation: RoutingRelation,
+    round_trip_time: u32,
+    packets_per_minute_limit: Option<u32>,
+    amount_per_minute_limit: Option<u64>,
+    settlement_engine_url: Option<Url>,
+}
+
+fn token_to_vec(token: &Option<SecretBytesMut>) -> Option<Vec<u8>> {
+    token.as_ref().map(|token| token.expose_secret().to_vec())
+}
+
+fn vec_to_token(token: Option<Vec<u8>>) -> Option<SecretBytesMut> {
+    token.map(|token| SecretBytesMut::from(BytesMut::from(token.as_slice())))
+}
+
+impl From<&AccountWithEncryptedTokens> for StoredAccount {
+    fn from(encrypted: &AccountWithEncryptedTokens) -> Self {
+        let account = &encrypted.account;
+        StoredAccount {
+            id: account.id,
+            username: account.username.to_string(),
+            ilp_address: account.ilp_address.to_string(),
+            asset_code: account.asset_code.clone(),
+            asset_scale: account.asset_scale,
+            max_packet_amount: account.max_packet_amount,
+            min_balance: account.min_balance,
+            ilp_over_http_url: account.ilp_over_http_url.clone(),
+            ilp_over_http_client: account.ilp_over_http_client.clone(),
+            ilp_over_http_incoming_token: token_to_vec(&account.ilp_over_http_incoming_token),
+            ilp_over_http_outgoing_token: token_to_vec(&account.ilp_over_http_outgoing_token),
+            ilp_over_btp_url: account.ilp_over_btp_url.clone(),
+            ilp_over_btp_incoming_token: token_to_vec(&account.ilp_over_btp_incoming_token),
+            ilp_over_btp_outgoing_token: token_to_vec(&account.ilp_over_btp_outgoing_token),
+            settle_threshold: account.settle_threshold,
+            settle_to: account.settle_to,
+            routing_relation: account.routing_relation,
+            round_trip_time: account.round_trip_time,
+            packets_per_minute_limit: account.packets_per_minute_limit,
+            amount_per_minute_limit: account.amount_per_minute_limit,
+            settlement_engine_url: account.settlement_engine_url.clone(),
+        }
+    }
+}
+
+impl StoredAccount {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, SledStoreError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    fn to_vec(&self) -> Result<Vec<u8>, SledStoreError> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    fn into_encrypted(self) -> Result<AccountWithEncryptedTokens, SledStoreError> {
+        let username = Username::from_str(&self.username).map_err(|_| {
+            SledStoreError::Corrupted(format!("invalid username {}", self.username))
+        })?;
+        let ilp_address = Address::from_str(&self.ilp_address).map_err(|_| {
+            SledStoreError::Corrupted(format!("invalid ILP address {}", self.ilp_address))
+        })?;
+        Ok(AccountWithEncryptedTokens {
+            account: Account {
+                id: self.id,
+                username,
+                ilp_address,
+                asset_code: self.asset_code,
+                asset_scale: self.asset_scale,
+                max_packet_amount: self.max_packet_amount,
+                min_balance: self.min_balance,
+                ilp_over_http_url: self.ilp_over_http_url,
+                ilp_over_http_client: self.ilp_over_http_client,
+                ilp_over_http_incoming_token: vec_to_token(self.ilp_over_http_incoming_token),
+                ilp_over_http_outgoing_token: vec_to_token(self.ilp_over_http_outgoing_token),
+                ilp_over_btp_url: self.ilp_over_btp_url,
+                ilp_over_btp_incoming_token: vec_to_token(self.ilp_over_btp_incoming_token),
+                ilp_over_btp_outgoing_token: vec_to_token(self.ilp_over_btp_outgoing_token),
+                settle_threshold: self.settle_threshold,
+                settle_to: self.settle_to,
+                routing_relation: self.routing_relation,
+                round_trip_time: self.round_trip_time,
+                packets_per_minute_limit: self.packets_per_minute_limit,
+                amount_per_minute_limit: self.amount_per_minute_limit,
+                settlement_engine_url: self.settlement_engine_url,
+            },
+        })
+    }
+}
+
+/// A cached response of the settlement API
+#[derive(Serialize, Deserialize)]
+struct StoredIdempotentData {
+    status: u16,
+    body: Vec<u8>,
+    input_hash: [u8; 32],
+    expires_at: u64,
+}
+
+/// How many transactions are kept for an account, and the totals of the ones which
+/// were trimmed from its history
+#[derive(Default, Serialize, Deserialize)]
+struct TransactionHistory {
+    retained: usize,
+    trimmed: TransactionTotals,
+}
+
+/// Key of a transaction, which sorts the transactions of each account in the order
+/// they were recorded
+fn transaction_key(account_id: Uuid, id: u64) -> [u8; 24] {
+    let mut key = [0; 24];
+    key[..16].copy_from_slice(account_id.as_bytes());
+    key[16..].copy_from_slice(&id.to_be_bytes());
+    key
+}
+
+/// Key of a rate of an asset, which sorts the rates of each asset in the order they were
+/// recorded. The asset code is terminated by a 0 byte, so that the rates of an asset
+/// are not mixed up with those of the assets whose code starts with the same letters
+fn rate_history_key(asset_code: &str, timestamp: u64) -> Vec<u8> {
+    let mut key = Vec::with_capacity(asset_code.len() + 9);
+    key.extend_from_slice(asset_code.as_bytes());
+    key.push(0);
+    key.extend_from_slice(&timestamp.to_be_bytes());
+    key
+}
+
+fn id_from_bytes(bytes: &[u8]) -> Result<Uuid, SledStoreError> {
+    Uuid::from_slice(bytes)
+        .map_err(|err| SledStoreError::Corrupted(format!("invalid account id: {}", err)))
+}
+
+fn string_from_bytes(bytes: &[u8]) -> Result<String, SledStoreError> {
+    String::from_utf8(bytes.to_vec())
+        .map_err(|err| SledStoreError::Corrupted(format!("invalid string: {}", err)))
+}
+
+/// Seconds since the UNIX epoch, used for expiring idempotency keys
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Reads an account inside of a transaction
+fn tx_get_account(
+    accounts: &TransactionalTree,
+    id: Uuid,
+) -> ConflictableTransactionResult<StoredAccount, SledStoreError> {
+    match accounts.get(&id.as_bytes()[..])? {
+        Some(bytes) => StoredAccount::from_bytes(&bytes).or_else(abort),
+        None => abort(SledStoreError::AccountNotFound(id)),
+    }
+}
+
+/// Reads the transaction history of an account inside of a transaction
+fn tx_get_transaction_history(
+    history: &TransactionalTree,
+    account_id: Uuid,
+) -> ConflictableTransactionResult<TransactionHistory, SledStoreError> {
+    match history.get(account_id.as_bytes())? {
+        Some(value) => serde_json::from_slice(&value).or_else(abort),
+        None => Ok(TransactionHistory::default()),
+    }
+}
+
+/// Reads a balance inside of a transaction
+fn tx_get_balance(
+    balances: &TransactionalTree,
+    id: Uuid,
+) -> ConflictableTransactionResult<Balance, SledStoreError> {
+    match balances.get(&id.as_bytes()[..])? {
+        Some(bytes) => Balance::from_bytes(&bytes).or_else(abort),
+        None => abort(SledStoreError::AccountNotFound(id)),
+    }
+}
+
+/// Reads a whole tree of `string -> account id` entries
+fn read_id_map(tree: &Tree) -> Result<HashMap<String, Uuid>, SledStoreError> {
+    tree.iter()
+        .map(|entry| -> Result<(String, Uuid), SledStoreError> {
+            let (key, value) = entry?;
+            Ok((string_from_bytes(&key)?, id_from_bytes(&value)?))
+        })
+        .collect()
+}
+
+/// Static routes are stored as strings (see `Route`'s `Display` implementation).
+/// Routes which were set before routes could have several next hops are stored as
+/// the raw bytes of a single account id.
+fn route_from_bytes(bytes: &[u8]) -> Result<Route, SledStoreError> {
+    if bytes.len() == 16 {
+        return Ok(Route::from(id_from_bytes(bytes)?));
+    }
+    Route::from_str(&string_from_bytes(bytes)?)
+        .map_err(|err| SledStoreError::Corrupted(err.to_string()))
+}
+
+/// Reads a whole tree of `string -> route` entries
+fn read_route_map(tree: &Tree) -> Result<HashMap<String, Route>, SledStoreError> {
+    tree.iter()
+        .map(|entry| -> Result<(String, Route), SledStoreError> {
+            let (key, value) = entry?;
+            Ok((string_from_bytes(&key)?, route_from_bytes(&value)?))
+        })
+        .collect()
+}
+
+type UncreditedAmounts = Vec<(String, u8)>;
+
+/// Sums the uncredited amounts (scaled to the largest scale among them)
+fn sum_uncredited_amounts(amounts: UncreditedAmounts) -> Result<(BigUint, u8), SledStoreError> {
+    let max_scale = amounts.iter().map(|(_, scale)| *scale).max().unwrap_or(0);
+    let mut sum = BigUint::from(0u32);
+    for (num, scale) in amounts {
+        let num = BigUint::from_str(&num)
+            .map_err(|_| SledStoreError::Corrupted(format!("invalid amount {}", num)))?;
+        sum += num
+            .normalize_scale(ConvertDetails {
+                from: scale,
+                to: max_scale,
+            })
+            .unwrap();
+    }
+    Ok((sum, max_scale))
+}
+
+/// A Store that uses the embedded [sled](https://github.com/spacejam/sled) database as its backend.
+///
+/// The database is stored in a directory on the local filesystem, so no other process
+/// needs to run next to the node. Only one node can use the database at a time.
+#[derive(Clone)]
+pub struct SledStore {
+    /// The Store's ILP Address
+    ilp_address: Arc<RwLock<Address>>,
+    db: Db,
+    accounts: Tree,
+    balances: Tree,
+    usernames: Tree,
+    routes_tree: Tree,
+    static_routes: Tree,
+    settlement_engines: Tree,
+    uncredited_amounts: Tree,
+    idempotency_keys: Tree,
+    settlement_idempotency_keys: Tree,
+    stream_connections: Tree,
+    transactions: Tree,
+    transaction_history: Tree,
+    max_transactions_per_account: usize,
+    rate_history: Tree,
+    rate_history_retention: u64,
+    /// WebSocket senders which publish incoming payment updates
+    subscriptions: Arc<Mutex<HashMap<Uuid, Vec<UnboundedSender<PaymentNotification>>>>>,
+    /// A subscriber to all payment notifications, exposed via a WebSocket
+    payment_publisher: broadcast::Sender<PaymentNotification>,
+    exchange_rates: Arc<RwLock<HashMap<String, f64>>>,
+    /// When each of the rates was fetched, in milliseconds since the Unix epoch
+    exchange_rates_fetched_at: Arc<RwLock<HashMap<String, u64>>>,
+    fee_policy: Arc<RwLock<Arc<FeePolicy>>>,
+    /// The store keeps the routing table in memory so that it can be returned
+    /// synchronously to the Router (which needs to process packets synchronously).
+    /// It is rebuilt whenever the routes in the database change.
+    routes: Arc<RwLock<Arc<RoutingTable<Route>>>>,
+    rate_limiter: Arc<RateLimiter>,
+    /// Encryption Key so that the no cleartext data are stored
+    encryption_key: Arc<Secret<EncryptionKey>>,
+    /// Decryption Key to provide cleartext data to users
+    decryption_key: Arc<Secret<DecryptionKey>>,
+}
+
+impl SledStore {
+    /// Rebuilds the routing table which is returned to the `Router`.
+    /// Static routes take precedence over the routes set by CCP and the default route.
+    fn update_routes(&self) -> Result<(), SledStoreError> {
+        let mut routes: RoutingTable<Route> = read_id_map(&self.routes_tree)?
+            .into_iter()
+            .map(|(prefix, id)| (prefix, Route::from(id)))
+            .collect();
+        if let Some(id) = self.db.get(DEFAULT_ROUTE_KEY)? {
+            routes.insert(String::new(), Route::from(id_from_bytes(&id)?));
+        }
+        // Having the static_routes inserted after ensures that they will overwrite
+        // any routes with the same prefix from the first set
+        routes.extend(read_route_map(&self.static_routes)?);
+        trace!("Routing table is: {:?}", routes);
+        *self.routes.write() = Arc::new(routes);
+        Ok(())
+    }
+
+    fn encrypt(&self, token: &[u8]) -> Vec<u8> {
+        encrypt_token(&self.encryption_key.expose_secret().0, token).to_vec()
+    }
+
+    /// Decrypts the account's tokens and fills in the globally configured settlement
+    /// engine for its asset code, if it does not have one of its own
+    fn decrypt(&self, stored: StoredAccount) -> Result<Account, SledStoreError> {
+        let mut account = stored
+            .into_encrypted()?
+            .decrypt_tokens(&self.decryption_key.expose_secret().0);
+        if account.settlement_engine_url.is_none() {
+            if let Some(url) = self.settlement_engines.get(account.asset_code.as_bytes())? {
+                account.settlement_engine_url = Url::parse(&string_from_bytes(&url)?).ok();
+            }
+        }
+        Ok(account)
+    }
+
+    fn get_account(&self, id: Uuid) -> Result<Option<Account>, SledStoreError> {
+        match self.accounts.get(&id.as_bytes()[..])? {
+            Some(bytes) => Ok(Some(self.decrypt(StoredAccount::from_bytes(&bytes)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_account_by_username(
+        &self,
+        username: &Username,
+    ) -> Result<Option<Account>, SledStoreError> {
+        match self.usernames.get(username.as_bytes())? {
+            Some(id) => self.get_account(id_from_bytes(&id)?),
+            None => Ok(None),
+        }
+    }
+
+    fn all_accounts(&self) -> Result<Vec<Account>, SledStoreError> {
+        self.accounts
+            .iter()
+            .values()
+            .map(|bytes| self.decrypt(StoredAccount::from_bytes(&bytes?)?))
+            .collect()
+    }
+
+    fn filter_accounts<F>(&self, predicate: F) -> Result<Vec<Account>, SledStoreError>
+    where
+        F: Fn(&Account) -> bool,
+    {
+        Ok(self
+            .all_accounts()?
+            .into_iter()
+            .filter(|account| predicate(account))
+            .collect())
+    }
+
+    fn insert_account_data(&self, account: Account) -> Result<Account, SledStoreError> {
+        let encrypted = account
+            .clone()
+            .encrypt_tokens(&self.encryption_key.expose_secret().0);
+        let bytes = StoredAccount::from(&encrypted).to_vec()?;
+        (
+            &self.accounts,
+            &self.usernames,
+            &self.balances,
+            &self.routes_tree,
+            &*self.db,
+        )
+            .transaction(|(accounts, usernames, balances, routes, meta)| {
+                // Check that there isn't already an account with values that MUST be unique
+                if accounts.get(&account.id.as_bytes()[..])?.is_some()
+                    || usernames.get(account.username.as_bytes())?.is_some()
+                    || (account.routing_relation == RoutingRelation::Parent
+                        && meta.get(PARENT_ILP_KEY)?.is_some())
+                {
+                    return abort(SledStoreError::Node(NodeStoreError::AccountExists(
+                        account.username.to_string(),
+                    )));
+                }
+                accounts.insert(&account.id.as_bytes()[..], bytes.as_slice())?;
+                usernames.insert(account.username.as_bytes(), &account.id.as_bytes()[..])?;
+                balances.insert(
+                    &account.id.as_bytes()[..],
+                    &Balance::default().to_bytes()[..],
+                )?;
+                routes.insert(account.ilp_address.as_bytes(), &account.id.as_bytes()[..])?;
+                Ok(())
+            })?;
+        self.update_routes()?;
+        debug!(
+            "Inserted account {} (ILP address: {})",
+            account.id, account.ilp_address
+        );
+        Ok(account)
+    }
+
+    fn update_account_data(&self, account: Account) -> Result<Account, SledStoreError> {
+        let encrypted = account
+            .clone()
+            .encrypt_tokens(&self.encryption_key.expose_secret().0);
+        let bytes = StoredAccount::from(&encrypted).to_vec()?;
+        (&self.accounts, &self.usernames, &self.routes_tree).transaction(
+            |(accounts, usernames, routes)| {
+                let old = tx_get_account(accounts, account.id)?;
+                if let Some(id) = usernames.get(account.username.as_bytes())? {
+                    if id.as_ref() != &account.id.as_bytes()[..] {
+                        return abort(SledStoreError::Node(NodeStoreError::AccountExists(
+                            account.username.to_string(),
+                        )));
+                    }
+                }
+                usernames.remove(old.username.as_bytes())?;
+                usernames.insert(account.username.as_bytes(), &account.id.as_bytes()[..])?;
+
+                // Replace the route to the account's old address
+                if let Some(id) = routes.get(old.ilp_address.as_bytes())? {
+                    if id.as_ref() == &account.id.as_bytes()[..] {
+                        routes.remove(old.ilp_address.as_bytes())?;
+                    }
+                }
+                routes.insert(account.ilp_address.as_bytes(), &account.id.as_bytes()[..])?;
+                accounts.insert(&account.id.as_bytes()[..], bytes.as_slice())?;
+                Ok(())
+            },
+        )?;
+        self.update_routes()?;
+        debug!(
+            "Updated account {} (id: {}, ILP address: {})",
+            account.username, account.id, account.ilp_address
+        );
+        Ok(account)
+    }
+
+    fn modify_account_data(
+        &self,
+        id: Uuid,
+        settings: AccountSettings,
+    ) -> Result<Account, SledStoreError> {
+        if let Some(settle_to) = settings.settle_to {
+            if settle_to > std::i64::MAX as u64 {
+                return Err(SledStoreError::Node(NodeStoreError::InvalidAccount(
+                    CreateAccountError::ParamTooLarge("settle_to".to_owned()),
+                )));
+            }
+        }
+        let ilp_over_btp_url = match settings.ilp_over_btp_url {
+            Some(ref url) => Some(Url::parse(url).map_err(|err| {
+                SledStoreError::Node(NodeStoreError::InvalidAccount(
+                    CreateAccountError::InvalidBtpUrl(err),
+                ))
+            })?),
+            None => None,
+        };
+        let ilp_over_http_url = match settings.ilp_over_http_url {
+            Some(ref url) => Some(Url::parse(url).map_err(|err| {
+                SledStoreError::Node(NodeStoreError::InvalidAccount(
+                    CreateAccountError::InvalidHttpUrl(err),
+                ))
+            })?),
+            None => None,
+        };
+        let encrypt = |token: &Option<SecretString>| {
+            token
+                .as_ref()
+                .map(|token| self.encrypt(token.expose_secret().as_bytes()))
+        };
+        let ilp_over_btp_outgoing_token = encrypt(&settings.ilp_over_btp_outgoing_token);
+        let ilp_over_http_outgoing_token = encrypt(&settings.ilp_over_http_outgoing_token);
+        let ilp_over_btp_incoming_token = encrypt(&settings.ilp_over_btp_incoming_token);
+        let ilp_over_http_incoming_token = encrypt(&settings.ilp_over_http_incoming_token);
+
+        let stored = self.accounts.transaction(|accounts| {
+            let mut stored = tx_get_account(accounts, id)?;
+            if let Some(ref url) = ilp_over_btp_url {
+                stored.ilp_over_btp_url = Some(url.clone());
+            }
+            if let Some(ref url) = ilp_over_http_url {
+                stored.ilp_over_http_url = Some(url.clone());
+            }
+            if let Some(ref token) = ilp_over_btp_outgoing_token {
+                stored.ilp_over_btp_outgoing_token = Some(token.clone());
+            }
+            if let Some(ref token) = ilp_over_http_outgoing_token {
+                stored.ilp_over_http_outgoing_token = Some(token.clone());
+            }
+            if let Some(ref token) = ilp_over_btp_incoming_token {
+                stored.ilp_over_btp_incoming_token = Some(token.clone());
+            }
+            if let Some(ref token) = ilp_over_http_incoming_token {
+                stored.ilp_over_http_incoming_token = Some(token.clone());
+            }
+            if let Some(settle_threshold) = settings.settle_threshold {
+                stored.settle_threshold = Some(settle_threshold);
+            }
+            if let Some(settle_to) = settings.settle_to {
+                stored.settle_to = Some(settle_to as i64);
+            }
+            accounts.insert(&id.as_bytes()[..], stored.to_vec().or_else(abort)?)?;
+            Ok(stored)
+        })?;
+        self.decrypt(stored)
+    }
+
+    fn delete_account_data(&self, id: Uuid) -> Result<Account, SledStoreError> {
+        let stored = (
+            &self.accounts,
+            &self.balances,
+            &self.usernames,
+            &self.routes_tree,
+            &self.uncredited_amounts,
+        )
+            .transaction(|(accounts, balances, usernames, routes, uncredited)| {
+                let stored = tx_get_account(accounts, id)?;
+                accounts.remove(&id.as_bytes()[..])?;
+                balances.remove(&id.as_bytes()[..])?;
+                usernames.remove(stored.username.as_bytes())?;
+                uncredited.remove(&id.as_bytes()[..])?;
+                if let Some(route) = routes.get(stored.ilp_address.as_bytes())? {
+                    if route.as_ref() == &id.as_bytes()[..] {
+                        routes.remove(stored.ilp_address.as_bytes())?;
+                    }
+                }
+                Ok(stored)
+            })?;
+        let mut batch = Batch::default();
+        for entry in self.transactions.scan_prefix(id.as_bytes()).keys() {
+            batch.remove(entry?);
+        }
+        self.transactions.apply_batch(batch)?;
+        self.transaction_history.remove(id.as_bytes())?;
+        self.update_routes()?;
+        debug!("Deleted account {}", id);
+        self.decrypt(stored)
+    }
+
+    fn set_ilp_address_data(&self, ilp_address: Address) -> Result<(), SledStoreError> {
+        // Set the ILP address we have in memory
+        (*self.ilp_address.write()) = ilp_address.clone();
+
+        let first_segment = ilp_address
+            .segments()
+            .rev()
+            .next()
+            .expect("address did not have a first segment, this should be impossible");
+        // Update the address and routes of all children and non-routing accounts.
+        let ids: Vec<Uuid> = self
+            .all_accounts()?
+            .into_iter()
+            .filter(|account| {
+                account.routing_relation != RoutingRelation::Parent
+                    && account.routing_relation != RoutingRelation::Peer
+            })
+            .map(|account| account.id)
+            .collect();
+
+        (&self.accounts, &self.routes_tree, &*self.db).transaction(
+            |(accounts, routes, meta)| {
+                meta.insert(PARENT_ILP_KEY, ilp_address.as_bytes())?;
+                for id in &ids {
+                    let mut stored = tx_get_account(accounts, *id)?;
+                    // if the username of the account ends with the
+                    // node's address, we're already configured so no
+                    // need to append anything.
+                    let new_address = if first_segment == stored.username {
+                        ilp_address.clone()
+                    } else {
+                        ilp_address.with_suffix(stored.username.as_bytes()).unwrap()
+                    };
+                    routes.remove(stored.ilp_address.as_bytes())?;
+                    routes.insert(new_address.as_bytes(), &id.as_bytes()[..])?;
+                    stored.ilp_address = new_address.to_string();
+                    accounts.insert(&id.as_bytes()[..], stored.to_vec().or_else(abort)?)?;
+                }
+                Ok(())
+            },
+        )?;
+        self.update_routes()
+    }
+
+    fn notify(&self, payment: PaymentNotification) {
+        let account_id = match self.usernames.get(payment.to_username.as_bytes()) {
+            Ok(Some(id)) => match id_from_bytes(&id) {
+                Ok(id) => id,
+                Err(err) => {
+                    error!("Failed to load account ID: {}", err);
+                    return;
+                }
+            },
+            _ => {
+                error!(
+                    "Failed to find account ID corresponding to username: {}",
+                    payment.to_username
+                );
+                return;
+            }
+        };
+        trace!(
+            "Publishing payment notification for account {}: {:?}",
+            account_id,
+            payment
+        );
+
+        if self.payment_publisher.receiver_count() > 0 {
+            if let Err(err) = self.payment_publisher.send(payment.clone()) {
+                error!("Failed to send a node-wide payment notification: {:?}", err);
+            }
+        }
+        match self.subscriptions.lock().get_mut(&account_id) {
+            Some(senders) => {
+                senders.retain(|sender| {
+                    if let Err(err) = sender.unbounded_send(payment.clone()) {
+                        debug!("Failed to send message: {}", err);
+                        false
+                    } else {
+                        true
+                    }
+                });
+            }
+            None => trace!(
+                "Ignoring message for account {} because there were no open subscriptions",
+                account_id
+            ),
+        }
+    }
+
+    fn get_balance_data(&self, account_id: Uuid) -> Result<i64, SledStoreError> {
+        match self.balances.get(&account_id.as_bytes()[..])? {
+            Some(bytes) => Ok(Balance::from_bytes(&bytes)?.total()),
+            None => Err(SledStoreError::AccountNotFound(account_id)),
+        }
+    }
+
+    fn prepare_data(
+        &self,
+        from_account_id: Uuid,
+        incoming_amount: u64,
+    ) -> Result<Balance, SledStoreError> {
+        let amount = signed_amount(from_account_id, incoming_amount)?;
+        let out_of_range = || SledStoreError::AmountOutOfRange {
+            account_id: from_account_id,
+            amount: incoming_amount,
+        };
+        let balance = (&self.accounts, &self.balances).transaction(|(accounts, balances)| {
+            let account = tx_get_account(accounts, from_account_id)?;
+            let mut balance = tx_get_balance(balances, from_account_id)?;
+            let total = match balance.total().checked_sub(amount) {
+                Some(total) => total,
+                None => return abort(out_of_range()),
+            };
+
+            // Check that the prepare wouldn't go under the account's minimum balance
+            if let Some(min_balance) = account.min_balance {
+                if total < min_balance {
+                    return abort(SledStoreError::MinBalanceExceeded {
+                        account_id: from_account_id,
+                        amount: incoming_amount,
+                        balance: balance.balance,
+                        min_balance,
+                    });
+                }
+            }
+
+            // Deduct the amount from the prepaid_amount and/or the balance
+            if balance.prepaid_amount >= amount {
+                balance.prepaid_amount -= amount;
+            } else if balance.prepaid_amount > 0 {
+                balance.balance = match balance.balance.checked_sub(amount - balance.prepaid_amount)
+                {
+                    Some(debited) => debited,
+                    None => return abort(out_of_range()),
+                };
+                balance.prepaid_amount = 0;
+            } else {
+                balance.balance = match balance.balance.checked_sub(amount) {
+                    Some(debited) => debited,
+                    None => return abort(out_of_range()),
+                };
+            }
+            balances.insert(&from_account_id.as_bytes()[..], &balance.to_bytes()[..])?;
+            Ok(balance)
+        })?;
+        Ok(balance)
+    }
+
+    /// Adds the amount to the balance and settles down to `settle_to` if
+    /// `settle` returns true for the account's settle threshold, settle_to and new balance.
+    fn add_and_settle<F>(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        settle: F,
+    ) -> Result<(Balance, u64), SledStoreError>
+    where
+        F: Fn(i64, i64, i64) -> bool,
+    {
+        let signed = signed_amount(account_id, amount)?;
+        let result = (&self.accounts, &self.balances).transaction(|(accounts, balances)| {
+            let account = tx_get_account(accounts, account_id)?;
+            let mut balance = tx_get_balance(balances, account_id)?;
+            balance.balance = match balance.balance.checked_add(signed) {
+                Some(credited) => credited,
+                None => return abort(SledStoreError::AmountOutOfRange { account_id, amount }),
+            };
+
+            let mut amount_to_settle = 0;
+            if let (Some(settle_threshold), Some(settle_to)) =
+                (account.settle_threshold, account.settle_to)
+            {
+                if settle(settle_threshold, settle_to, balance.balance) {
+                    amount_to_settle = (balance.balance - settle_to) as u64;
+                    balance.balance = settle_to;
+                }
+            }
+            balances.insert(&account_id.as_bytes()[..], &balance.to_bytes()[..])?;
+            Ok((balance, amount_to_settle))
+        })?;
+        Ok(result)
+    }
+
+    fn add_to_balance(&self, account_id: Uuid, amount: u64) -> Result<Balance, SledStoreError> {
+        let signed = signed_amount(account_id, amount)?;
+        let balance = self.balances.transaction(|balances| {
+            let mut balance = tx_get_balance(balances, account_id)?;
+            balance.balance = match balance.balance.checked_add(signed) {
+                Some(credited) => credited,
+                None => return abort(SledStoreError::AmountOutOfRange { account_id, amount }),
+            };
+            balances.insert(&account_id.as_bytes()[..], &balance.to_bytes()[..])?;
+            Ok(balance)
+        })?;
+        Ok(balance)
+    }
+
+    fn incoming_settlement_data(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: Option<String>,
+    ) -> Result<Option<Balance>, SledStoreError> {
+        let signed = signed_amount(account_id, amount)?;
+        let out_of_range = || SledStoreError::AmountOutOfRange { account_id, amount };
+        let now = now_secs();
+        let balance = (&self.balances, &self.settlement_idempotency_keys).transaction(
+            |(balances, keys)| {
+                let mut balance = tx_get_balance(balances, account_id)?;
+
+                // If the idempotency key has been used, then do not perform any operations
+                if let Some(ref idempotency_key) = idempotency_key {
+                    if let Some(expires_at) = keys.get(idempotency_key.as_bytes())? {
+                        if expires_at.len() == 8
+                            && u64::from_be_bytes(expires_at[..].try_into().unwrap()) > now
+                        {
+                            return Ok(None);
+                        }
+                    }
+                    let expires_at = now + IDEMPOTENCY_KEY_TTL.as_secs();
+                    keys.insert(idempotency_key.as_bytes(), &expires_at.to_be_bytes()[..])?;
+                }
+
+                // Credit the incoming settlement to the balance and/or prepaid amount,
+                // depending on whether that account currently owes money or not
+                if balance.balance >= 0 {
+                    balance.prepaid_amount = match balance.prepaid_amount.checked_add(signed) {
+                        Some(credited) => credited,
+                        None => return abort(out_of_range()),
+                    };
+                } else {
+                    // Whatever is left once the balance is back to 0 is prepaid
+                    let credited = balance.balance + signed;
+                    if credited <= 0 {
+                        balance.balance = credited;
+                    } else {
+                        balance.prepaid_amount = match balance.prepaid_amount.checked_add(credited)
+                        {
+                            Some(prepaid) => prepaid,
+                            None => return abort(out_of_range()),
+                        };
+                        balance.balance = 0;
+                    }
+                }
+                balances.insert(&account_id.as_bytes()[..], &balance.to_bytes()[..])?;
+                Ok(Some(balance))
+            },
+        )?;
+        Ok(balance)
+    }
+
+    fn take_uncredited_amounts(
+        &self,
+        account_id: Uuid,
+    ) -> Result<UncreditedAmounts, SledStoreError> {
+        match self.uncredited_amounts.remove(&account_id.as_bytes()[..])? {
+            Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn peek_uncredited_amounts(
+        &self,
+        account_id: Uuid,
+    ) -> Result<UncreditedAmounts, SledStoreError> {
+        match self.uncredited_amounts.get(&account_id.as_bytes()[..])? {
+            Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn push_uncredited_amount(
+        &self,
+        account_id: Uuid,
+        amount: (BigUint, u8),
+    ) -> Result<(), SledStoreError> {
+        let entry = (amount.0.to_string(), amount.1);
+        self.uncredited_amounts.transaction(|uncredited| {
+            let mut amounts: UncreditedAmounts = match uncredited.get(&account_id.as_bytes()[..])? {
+                Some(bytes) => serde_json::from_slice(&bytes).or_else(abort)?,
+                None => Vec::new(),
+            };
+            amounts.push(entry.clone());
+            uncredited.insert(
+                &account_id.as_bytes()[..],
+                serde_json::to_vec(&amounts).or_else(abort)?,
+            )?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    fn load_uncredited_data(
+        &self,
+        account_id: Uuid,
+        local_scale: u8,
+    ) -> Result<BigUint, SledStoreError> {
+        // Take the leftovers and save back the precision loss in one transaction,
+        // so that a crash in between cannot lose them
+        let scaled_amount = self.uncredited_amounts.transaction(|uncredited| {
+            let amounts: UncreditedAmounts = match uncredited.remove(&account_id.as_bytes()[..])? {
+                Some(bytes) => serde_json::from_slice(&bytes).or_else(abort)?,
+                None => Vec::new(),
+            };
+            let amount = sum_uncredited_amounts(amounts).or_else(abort)?;
+            // scale the amount from the max scale to the local scale, and then
+            // save any potential leftovers to the store
+            let (scaled_amount, precision_loss) =
+                scale_with_precision_loss(amount.0, local_scale, amount.1);
+            if precision_loss > BigUint::from(0u32) {
+                let leftovers: UncreditedAmounts = vec![(
+                    precision_loss.to_string(),
+                    std::cmp::max(local_scale, amount.1),
+                )];
+                uncredited.insert(
+                    &account_id.as_bytes()[..],
+                    serde_json::to_vec(&leftovers).or_else(abort)?,
+                )?;
+            }
+            Ok(scaled_amount)
+        })?;
+        Ok(scaled_amount)
+    }
+
+    /// Applies the update to the record of a STREAM connection (or `None` if
+    /// there is none yet) in a transaction, and saves the record if there is one
+    fn update_stream_connection<T, F>(&self, token: &str, update: F) -> Result<T, SledStoreError>
+    where
+        F: Fn(&mut Option<StreamConnectionRecord>) -> Result<T, SledStoreError>,
+    {
+        let result = self.stream_connections.transaction(|connections| {
+            let mut record = match connections.get(token.as_bytes())? {
+                Some(bytes) => Some(serde_json::from_slice(&bytes).or_else(abort)?),
+                None => None,
+            };
+            let result = update(&mut record).or_else(abort)?;
+            if let Some(record) = record {
+                connections.insert(
+                    token.as_bytes(),
+                    serde_json::to_vec(&record).or_else(abort)?,
+                )?;
+            }
+            Ok(result)
+        })?;
+        Ok(result)
+    }
+
+    fn record_transactions_data(
+        &self,
+        transactions: Vec<Transaction>,
+    ) -> Result<(), SledStoreError> {
+        let mut records = Vec::with_capacity(transactions.len());
+        for transaction in transactions {
+            records.push((self.db.generate_id()?, transaction));
+        }
+        let max = self.max_transactions_per_account;
+        let over_limit = (&self.transactions, &self.transaction_history).transaction(
+            |(transactions, history)| {
+                let mut recorded: HashMap<Uuid, usize> = HashMap::new();
+                for (id, transaction) in records.iter() {
+                    transactions.insert(
+                        &transaction_key(transaction.account_id, *id)[..],
+                        serde_json::to_vec(transaction).or_else(abort)?,
+                    )?;
+                    *recorded.entry(transaction.account_id).or_default() += 1;
+                }
+                let mut over_limit = Vec::new();
+                for (account_id, count) in recorded {
+                    let mut stored = tx_get_transaction_history(history, account_id)?;
+                    stored.retained += count;
+                    if stored.retained > max {
+                        over_limit.push(account_id);
+                    }
+                    history.insert(
+                        &account_id.as_bytes()[..],
+                        serde_json::to_vec(&stored).or_else(abort)?,
+                    )?;
+                }
+                Ok(over_limit)
+            },
+        )?;
+        for account_id in over_limit {
+            self.trim_transactions(account_id)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the oldest transactions of the account past the maximum, adding them
+    /// to the totals of its trimmed transactions
+    fn trim_transactions(&self, account_id: Uuid) -> Result<(), SledStoreError> {
+        let retained = self.transaction_history_data(account_id)?.retained;
+        let excess = retained.saturating_sub(self.max_transactions_per_account);
+        // The keys are read outside of the sled transaction, which only counts
+        // the transactions that are still there in case of a concurrent trim
+        let keys = self
+            .transactions
+            .scan_prefix(account_id.as_bytes())
+            .keys()
+            .take(excess)
+            .collect::<Result<Vec<_>, _>>()?;
+        (&self.transactions, &self.transaction_history).transaction(
+            |(transactions, history)| {
+                let mut stored = tx_get_transaction_history(history, account_id)?;
+                for key in keys.iter() {
+                    if let Some(value) = transactions.remove(key.clone())? {
+                        let transaction: Transaction =
+                            serde_json::from_slice(&value).or_else(abort)?;
+                        stored.trimmed.add(&transaction);
+                        stored.retained = stored.retained.saturating_sub(1);
+                    }
+                }
+                history.insert(
+                    &account_id.as_bytes()[..],
+                    serde_json::to_vec(&stored).or_else(abort)?,
+                )?;
+                Ok(())
+            },
+        )?;
+        trace!(
+            "Trimmed {} transactions from the history of account {}",
+            keys.len(),
+            account_id
+        );
+        Ok(())
+    }
+
+    fn transaction_history_data(
+        &self,
+        account_id: Uuid,
+    ) -> Result<TransactionHistory, SledStoreError> {
+        match self.transaction_history.get(account_id.as_bytes())? {
+            Some(value) => Ok(serde_json::from_slice(&value)?),
+            None => Ok(TransactionHistory::default()),
+        }
+    }
+
+    fn transactions_data(
+        &self,
+        account_id: Uuid,
+        query: TransactionQuery,
+    ) -> Result<Vec<TransactionRecord>, SledStoreError> {
+        // The bounds are transaction IDs, which follow the order the transactions
+        // were recorded in (unlike their timestamps)
+        let start = transaction_key(
+            account_id,
+            query.after.map_or(0, |after| after.saturating_add(1)),
+        );
+        let end = transaction_key(account_id, query.before.unwrap_or(u64::MAX));
+        if start >= end {
+            return Ok(Vec::new());
+        }
+        let mut records = Vec::new();
+        for entry in self.transactions.range(start..end).rev() {
+            if records.len() >= query.limit {
+                break;
+            }
+            let (key, value) = entry?;
+            let id = u64::from_be_bytes(key[16..].try_into().map_err(|_| {
+                SledStoreError::Corrupted(format!("transaction key has {} bytes", key.len()))
+            })?);
+            let transaction: Transaction = serde_json::from_slice(&value)?;
+            if query.matches(&transaction) {
+                records.push(TransactionRecord { id, transaction });
+            }
+        }
+        Ok(records)
+    }
+
+    fn record_exchange_rates_data(
+        &self,
+        timestamp: u64,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), SledStoreError> {
+        let cutoff = timestamp.saturating_sub(self.rate_history_retention);
+        let mut batch = Batch::default();
+        for (asset_code, rate) in rates {
+            let expired = rate_history_key(&asset_code, 0)..rate_history_key(&asset_code, cutoff);
+            // Keep the last rate before the cutoff, which was still in effect
+            for entry in self.rate_history.range(expired).rev().skip(1) {
+                let (key, _) = entry?;
+                batch.remove(key);
+            }
+            batch.insert(
+                rate_history_key(&asset_code, timestamp),
+                &rate.to_be_bytes()[..],
+            );
+        }
+        self.rate_history.apply_batch(batch)?;
+        Ok(())
+    }
+
+    fn exchange_rate_history_data(
+        &self,
+        asset_code: &str,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<RateSnapshot>, SledStoreError> {
+        if from >= to {
+            return Ok(Vec::new());
+        }
+        let start = rate_history_key(asset_code, from);
+        let end = rate_history_key(asset_code, to);
+        self.rate_history
+            .range(start..end)
+            .take(limit)
+            .map(|entry| {
+                let (key, value) = entry?;
+                let corrupted = || {
+                    SledStoreError::Corrupted(format!(
+                        "rate history entry has a {} byte key and a {} byte value",
+                        key.len(),
+                        value.len()
+                    ))
+                };
+                let timestamp = key[key.len().saturating_sub(8)..]
+                    .try_into()
+                    .map_err(|_| corrupted())?;
+                let rate = value[..].try_into().map_err(|_| corrupted())?;
+                Ok(RateSnapshot {
+                    timestamp: u64::from_be_bytes(timestamp),
+                    rate: f64::from_be_bytes(rate),
+                })
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl ExchangeRateHistoryStore for SledStore {
+    async fn record_exchange_rates(
+        &self,
+        timestamp: u64,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        self.record_exchange_rates_data(timestamp, rates)?;
+        Ok(())
+    }
+
+    async fn get_exchange_rate_history(
+        &self,
+        asset_code: &str,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<RateSnapshot>, ExchangeRateStoreError> {
+        Ok(self.exchange_rate_history_data(asset_code, from, to, limit)?)
+    }
+}
+
+#[async_trait]
+impl TransactionStore for SledStore {
+    async fn record_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+    ) -> Result<(), TransactionStoreError> {
+        self.record_transactions_data(transactions)?;
+        Ok(())
+    }
+
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        query: TransactionQuery,
+    ) -> Result<Vec<TransactionRecord>, TransactionStoreError> {
+        Ok(self.transactions_data(account_id, query)?)
+    }
+
+    async fn get_trimmed_transaction_totals(
+        &self,
+        account_id: Uuid,
+    ) -> Result<TransactionTotals, TransactionStoreError> {
+        Ok(self.transaction_history_data(account_id)?.trimmed)
+    }
+}
+
+#[async_trait]
+impl AccountStore for SledStore {
+    type Account = Account;
+
+    async fn get_accounts(
+        &self,
+        account_ids: Vec<Uuid>,
+    ) -> Result<Vec<Account>, AccountStoreError> {
+        let mut accounts = Vec::with_capacity(account_ids.len());
+        for id in account_ids.iter() {
+            if let Some(account) = self.get_account(*id)? {
+                accounts.push(account);
+            }
+        }
+        if accounts.len() == account_ids.len() {
+            Ok(accounts)
+        } else {
+            Err(AccountStoreError::WrongLength {
+                expected: account_ids.len(),
+                actual: accounts.len(),
+            })
+        }
+    }
+
+    async fn get_account_id_from_username(
+        &self,
+        username: &Username,
+    ) -> Result<Uuid, AccountStoreError> {
+        match self
+            .usernames
+            .get(username.as_bytes())
+            .map_err(SledStoreError::from)?
+        {
+            Some(id) => Ok(id_from_bytes(&id)?),
+            None => {
+                debug!("Username not found: {}", username);
+                Err(AccountStoreError::AccountNotFound(username.to_string()))
+            }
+        }
+    }
+}
+
+impl StreamNotificationsStore for SledStore {
+    type Account = Account;
+
+    fn add_payment_notification_subscription(
+        &self,
+        id: Uuid,
+        sender: UnboundedSender<PaymentNotification>,
+    ) {
+        trace!("Added payment notification listener for {}", id);
+        self.subscriptions
+            .lock()
+            .entry(id)
+            .or_insert_with(Vec::new)
+            .push(sender);
+    }
+
+    fn publish_payment_notification(&self, payment: PaymentNotification) {
+        self.notify(payment)
+    }
+
+    fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification> {
+        self.payment_publisher.subscribe()
+    }
+}
+
+#[async_trait]
+impl StreamConnectionStore for SledStore {
+    async fn set_stream_receive_max(
+        &self,
+        token: &str,
+        receive_max: u64,
+    ) -> Result<(), StreamConnectionStoreError> {
+        self.update_stream_connection(token, |record| {
+            record.get_or_insert_with(Default::default).receive_max = Some(receive_max);
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    async fn get_stream_connection(
+        &self,
+        token: &str,
+    ) -> Result<Option<StreamConnectionRecord>, StreamConnectionStoreError> {
+        match self
+            .stream_connections
+            .get(token.as_bytes())
+            .map_err(SledStoreError::from)?
+        {
+            Some(bytes) => Ok(Some(
+                serde_json::from_slice(&bytes).map_err(SledStoreError::from)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn add_stream_received(
+        &self,
+        token: &str,
+        amount: u64,
+    ) -> Result<StreamConnectionRecord, StreamConnectionStoreError> {
+        let record = self.update_stream_connection(token, |record| {
+            let record = record.get_or_insert_with(Default::default);
+            record
+                .add_received(token, amount)
+                .map_err(SledStoreError::Stream)?;
+            Ok(*record)
+        })?;
+        trace!(
+            "STREAM connection {} received {} (total: {})",
+            token,
+            amount,
+            record.total_received
+        );
+        Ok(record)
+    }
+
+    async fn close_stream_connection(
+        &self,
+        token: &str,
+    ) -> Result<Option<StreamConnectionRecord>, StreamConnectionStoreError> {
+        let record = self.update_stream_connection(token, |record| match record {
+            Some(record) if !record.closed => {
+                record.closed = true;
+                Ok(Some(*record))
+            }
+            _ => Ok(None),
+        })?;
+        Ok(record)
+    }
+}
+
+#[async_trait]
+impl BalanceStore for SledStore {
+    /// Returns the balance **from the account holder's perspective**, meaning the sum of
+    /// the Payable Balance and Pending Outgoing minus the Receivable Balance and the Pending Incoming.
+    async fn get_balance(&self, account_id: Uuid) -> Result<i64, BalanceStoreError> {
+        Ok(self.get_balance_data(account_id)?)
+    }
+
+    async fn update_balances_for_prepare(
+        &self,
+        from_account_id: Uuid,
+        incoming_amount: u64,
+    ) -> Result<(), BalanceStoreError> {
+        // Don't do anything if the amount was 0
+        if incoming_amount == 0 {
+            return Ok(());
+        }
+
+        let balance = self.prepare_data(from_account_id, incoming_amount)?;
+        // Balances decide when to settle, so they must survive a crash
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+        trace!(
+            "Processed prepare with incoming amount: {}. Account {} has balance (including prepaid amount): {} ",
+            incoming_amount, from_account_id, balance.total()
+        );
+        Ok(())
+    }
+
+    async fn update_balances_for_fulfill(
+        &self,
+        to_account_id: Uuid,
+        outgoing_amount: u64,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        // Settlement is triggered if both the threshold and settle_to are set, the balance
+        // reached the threshold and the threshold is greater than settle_to
+        let (balance, amount_to_settle) = self.add_and_settle(
+            to_account_id,
+            outgoing_amount,
+            |settle_threshold, settle_to, balance| {
+                balance >= settle_threshold && settle_threshold > settle_to
+            },
+        )?;
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+
+        trace!(
+            "Processed fulfill for account {} for outgoing amount {}. Fulfill call result: {} {}",
+            to_account_id,
+            outgoing_amount,
+            balance.total(),
+            amount_to_settle,
+        );
+        Ok((balance.total(), amount_to_settle))
+    }
+
+    async fn update_balances_for_reject(
+        &self,
+        from_account_id: Uuid,
+        incoming_amount: u64,
+    ) -> Result<(), BalanceStoreError> {
+        if incoming_amount == 0 {
+            return Ok(());
+        }
+
+        let balance = self.add_to_balance(from_account_id, incoming_amount)?;
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+        trace!(
+            "Processed reject for incoming amount: {}. Account {} has balance (including prepaid amount): {}",
+            incoming_amount, from_account_id, balance.total()
+        );
+        Ok(())
+    }
+
+    async fn update_balances_for_delayed_settlement(
+        &self,
+        to_account_id: Uuid,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        // Upon completion the balance is at the level of settle_to
+        let (balance, amount_to_settle) =
+            self.add_and_settle(to_account_id, 0, |settle_threshold, settle_to, balance| {
+                settle_threshold > settle_to && balance >= settle_to
+            })?;
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+
+        trace!(
+            "Processed account {} for delayed settlement, balance: {}, to_settle: {}",
+            to_account_id,
+            balance.total(),
+            amount_to_settle
+        );
+        Ok((balance.total(), amount_to_settle))
+    }
+}
+
+impl ExchangeRateStore for SledStore {
+    fn get_exchange_rates(&self, asset_codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError> {
+        let rates: Vec<f64> = asset_codes
+            .iter()
+            .filter_map(|code| (*self.exchange_rates.read()).get(*code).cloned())
+            .collect();
+        if rates.len() == asset_codes.len() {
+            Ok(rates)
+        } else {
+            Err(ExchangeRateStoreError::PairNotFound {
+                from: asset_codes[0].to_string(),
+                to: asset_codes[1].to_string(),
+            })
+        }
+    }
+
+    fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        Ok((*self.exchange_rates.read()).clone())
+    }
+
+    fn set_exchange_rates(
+        &self,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        (*self.exchange_rates.write()) = rates;
+        self.exchange_rates_fetched_at.write().clear();
+        Ok(())
+    }
+
+    fn set_exchange_rates_fetched_at(
+        &self,
+        rates: HashMap<String, f64>,
+        fetched_at: HashMap<String, u64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        let mut exchange_rates = self.exchange_rates.write();
+        *exchange_rates = rates;
+        (*self.exchange_rates_fetched_at.write()) = fetched_at;
+        Ok(())
+    }
+
+    fn get_exchange_rates_fetched_at(
+        &self,
+        asset_codes: &[&str],
+    ) -> Result<Vec<Option<u64>>, ExchangeRateStoreError> {
+        let fetched_at = self.exchange_rates_fetched_at.read();
+        Ok(asset_codes
+            .iter()
+            .map(|code| fetched_at.get(*code).cloned())
+            .collect())
+    }
+}
+
+impl FeePolicyStore for SledStore {
+    fn get_fee_policy(&self) -> Arc<FeePolicy> {
+        self.fee_policy.read().clone()
+    }
+
+    fn set_fee_policy(&self, policy: FeePolicy) {
+        *self.fee_policy.write() = Arc::new(policy);
+    }
+}
+
+#[async_trait]
+impl BtpStore for SledStore {
+    type Account = Account;
+
+    async fn get_account_from_btp_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, BtpStoreError> {
+        if let Some(account) = self.get_account_by_username(username)? {
+            match account.ilp_over_btp_incoming_token {
+                Some(ref t) if t.expose_secret().as_ref() == token.as_bytes() => Ok(account),
+                Some(_) => {
+                    debug!(
+                        "Found account {} but BTP auth token was wrong",
+                        account.username
+                    );
+                    Err(BtpStoreError::Unauthorized(username.to_string()))
+                }
+                None => {
+                    debug!(
+                        "Account {} does not have an incoming btp token configured",
+                        account.username
+                    );
+                    Err(BtpStoreError::Unauthorized(username.to_string()))
+                }
+            }
+        } else {
+            warn!("No account found with BTP token");
+            Err(BtpStoreError::AccountNotFound(username.to_string()))
+        }
+    }
+
+    async fn get_btp_outgoing_accounts(&self) -> Result<Vec<Self::Account>, BtpStoreError> {
+        Ok(self.filter_accounts(|account| account.ilp_over_btp_url.is_some())?)
+    }
+}
+
+#[async_trait]
+impl HttpStore for SledStore {
+    type Account = Account;
+
+    /// Checks if the stored token for the provided account id matches the
+    /// provided token, and if so, returns the account associated with that token
+    async fn get_account_from_http_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, HttpStoreError> {
+        if let Some(account) = self.get_account_by_username(username)? {
+            match account.ilp_over_http_incoming_token {
+                Some(ref t) if t.expose_secret().as_ref() == token.as_bytes() => Ok(account),
+                _ => Err(HttpStoreError::Unauthorized(username.to_string())),
+            }
+        } else {
+            warn!("No account found with given HTTP auth");
+            Err(HttpStoreError::AccountNotFound(username.to_string()))
+        }
+    }
+}
+
+impl RouterStore for SledStore {
+    fn routing_table(&self) -> Arc<RoutingTable<Route>> {
+        self.routes.read().clone()
+    }
+}
+
+#[async_trait]
+impl NodeStore for SledStore {
+    type Account = Account;
+
+    async fn insert_account(
+        &self,
+        account: AccountDetails,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let id = Uuid::new_v4();
+        let account = Account::try_from(id, account, self.get_ilp_address())
+            .map_err(NodeStoreError::InvalidAccount)?;
+        debug!(
+            "Generated account id for {}: {}",
+            account.username, account.id
+        );
+        let account = self.insert_account_data(account)?;
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+        Ok(account)
+    }
+
+    async fn delete_account(&self, id: Uuid) -> Result<Account, NodeStoreError> {
+        let account = self.delete_account_data(id)?;
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+        Ok(account)
+    }
+
+    async fn update_account(
+        &self,
+        id: Uuid,
+        account: AccountDetails,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let account = Account::try_from(id, account, self.get_ilp_address())
+            .map_err(NodeStoreError::InvalidAccount)?;
+        let account = self.update_account_data(account)?;
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+        Ok(account)
+    }
+
+    async fn modify_account_settings(
+        &self,
+        id: Uuid,
+        settings: AccountSettings,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let account = self.modify_account_data(id, settings)?;
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+        Ok(account)
+    }
+
+    async fn get_all_accounts(&self) -> Result<Vec<Self::Account>, NodeStoreError> {
+        Ok(self.all_accounts()?)
+    }
+
+    async fn set_static_routes<R, T>(&self, routes: R) -> Result<(), NodeStoreError>
+    where
+        R: IntoIterator<Item = (String, T)> + Send + 'async_trait,
+        T: Into<Route> + Send + 'async_trait,
+    {
+        let routes: HashMap<String, Route> = routes
+            .into_iter()
+            .map(|(prefix, route)| (prefix, route.into()))
+            .collect();
+        let old_prefixes: Vec<String> = self
+            .static_routes
+            .iter()
+            .keys()
+            .map(|key| string_from_bytes(&key?))
+            .collect::<Result<_, _>>()?;
+        (&self.accounts, &self.static_routes)
+            .transaction(|(accounts, static_routes)| {
+                for id in routes.values().flat_map(|route| route.account_ids()) {
+                    if accounts.get(&id.as_bytes()[..])?.is_none() {
+                        return abort(SledStoreError::Node(NodeStoreError::MissingAccounts));
+                    }
+                }
+                for prefix in &old_prefixes {
+                    static_routes.remove(prefix.as_bytes())?;
+                }
+                for (prefix, route) in &routes {
+                    static_routes.insert(prefix.as_bytes(), route.to_string().as_bytes())?;
+                }
+                Ok(())
+            })
+            .map_err(|err| {
+                error!("Error setting static routes: {:?}", err);
+                SledStoreError::from(err)
+            })?;
+        self.update_routes()?;
+        Ok(())
+    }
+
+    async fn set_static_route(
+        &self,
+        prefix: String,
+        account_id: Uuid,
+    ) -> Result<(), NodeStoreError> {
+        (&self.accounts, &self.static_routes)
+            .transaction(|(accounts, static_routes)| {
+                if accounts.get(&account_id.as_bytes()[..])?.is_none() {
+                    return abort(SledStoreError::AccountNotFound(account_id));
+                }
+                static_routes.insert(
+                    prefix.as_bytes(),
+                    Route::from(account_id).to_string().as_bytes(),
+                )?;
+                Ok(())
+            })
+            .map_err(|err| {
+                error!("Cannot set static route for prefix: {}: {:?}", prefix, err);
+                SledStoreError::from(err)
+            })?;
+        self.update_routes()?;
+        Ok(())
+    }
+
+    async fn set_default_route(&self, account_id: Uuid) -> Result<(), NodeStoreError> {
+        (&self.accounts, &*self.db)
+            .transaction(|(accounts, meta)| {
+                if accounts.get(&account_id.as_bytes()[..])?.is_none() {
+                    return abort(SledStoreError::AccountNotFound(account_id));
+                }
+                meta.insert(DEFAULT_ROUTE_KEY, &account_id.as_bytes()[..])?;
+                Ok(())
+            })
+            .map_err(|err| {
+                error!("Cannot set default route: {:?}", err);
+                SledStoreError::from(err)
+            })?;
+        debug!("Set default route to account id: {}", account_id);
+        self.update_routes()?;
+        Ok(())
+    }
+
+    async fn set_settlement_engines(
+        &self,
+        asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
+    ) -> Result<(), NodeStoreError> {
+        let asset_to_url_map: Vec<(String, Url)> = asset_to_url_map.into_iter().collect();
+        debug!("Setting settlement engines to {:?}", asset_to_url_map);
+        self.settlement_engines
+            .transaction(|engines| {
+                for (asset_code, url) in &asset_to_url_map {
+                    engines.insert(asset_code.as_bytes(), url.as_str().as_bytes())?;
+                }
+                Ok(())
+            })
+            .map_err(SledStoreError::from)?;
+        Ok(())
+    }
+
+    async fn get_asset_settlement_engine(
+        &self,
+        asset_code: &str,
+    ) -> Result<Option<Url>, NodeStoreError> {
+        match self
+            .settlement_engines
+            .get(asset_code.as_bytes())
+            .map_err(SledStoreError::from)?
+        {
+            Some(url) => Ok(Url::parse(&string_from_bytes(&url)?).ok()),
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl AddressStore for SledStore {
+    // Updates the ILP address of the store & iterates over all children and
+    // updates their ILP Address to match the new address.
+    async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
+        debug!("Setting ILP address to: {}", ilp_address);
+        self.set_ilp_address_data(ilp_address)?;
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+        Ok(())
+    }
+
+    async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+        self.db
+            .remove(PARENT_ILP_KEY)
+            .map_err(|err| AddressStoreError::from(SledStoreError::from(err)))?;
+        // overwrite the ilp address with the default value
+        *(self.ilp_address.write()) = DEFAULT_ILP_ADDRESS.clone();
+        Ok(())
+    }
+
+    fn get_ilp_address(&self) -> Address {
+        self.ilp_address.read().clone()
+    }
+}
+
+type Routes<A> = HashMap<String, A>;
+
+#[async_trait]
+impl CcpRoutingStore for SledStore {
+    type Account = Account;
+
+    async fn get_accounts_to_send_routes_to(
+        &self,
+        ignore_accounts: Vec<Uuid>,
+    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        Ok(self.filter_accounts(|account| {
+            account.should_send_routes() && !ignore_accounts.contains(&account.id)
+        })?)
+    }
+
+    async fn get_accounts_to_receive_routes_from(
+        &self,
+    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        Ok(self.filter_accounts(|account| account.should_receive_routes())?)
+    }
+
+    async fn get_local_and_configured_routes(
+        &self,
+    ) -> Result<(Routes<Account>, Routes<Account>), CcpRoutingStoreError> {
+        let local_table: HashMap<String, Account> = self
+            .all_accounts()?
+            .into_iter()
+            .map(|account| (account.ilp_address.to_string(), account))
+            .collect();
+
+        let mut configured_table = HashMap::new();
+        'routes: for (prefix, route) in read_route_map(&self.static_routes)? {
+            // Advertise the route through the first of its next hops which still exists
+            for account_id in route.account_ids() {
+                if let Some(account) = self.get_account(account_id)? {
+                    configured_table.insert(prefix, account);
+                    continue 'routes;
+                }
+            }
+            warn!(
+                "No account for route: {}, ignoring configured route for prefix: {}",
+                route, prefix
+            );
+        }
+
+        Ok((local_table, configured_table))
+    }
+
+    async fn set_routes(
+        &mut self,
+        routes: impl IntoIterator<Item = (String, Account)> + Send + 'async_trait,
+    ) -> Result<(), CcpRoutingStoreError> {
+        let routes: Vec<(String, Uuid)> = routes
+            .into_iter()
+            .map(|(prefix, account)| (prefix, account.id))
+            .collect();
+        let num_routes = routes.len();
+        let old_prefixes: Vec<String> = read_id_map(&self.routes_tree)?.keys().cloned().collect();
+        self.routes_tree
+            .transaction(|routes_tree| {
+                for prefix in &old_prefixes {
+                    routes_tree.remove(prefix.as_bytes())?;
+                }
+                for (prefix, id) in &routes {
+                    routes_tree.insert(prefix.as_bytes(), &id.as_bytes()[..])?;
+                }
+                Ok(())
+            })
+            .map_err(SledStoreError::from)?;
+        trace!("Saved {} routes to sled", num_routes);
+        self.update_routes()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for SledStore {
+    type Account = Account;
+
+    /// Apply rate limits for number of packets per minute and amount of money per minute
+    ///
+    /// This uses the same generic cell rate algorithm as [redis-cell](https://github.com/brandur/redis-cell)
+    async fn apply_rate_limits(
+        &self,
+        account: Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        // Both limits are always applied, even if the first one is exceeded
+        let packets_limited = account.packets_per_minute_limit.map(|limit| {
+            let limit = u64::from(limit.saturating_sub(1));
+            self.rate_limiter
+                .throttle(format!("limit:packets:{}", account.id), limit, limit, 1)
+        });
+        let amount_limited = account.amount_per_minute_limit.map(|limit| {
+            let limit = limit.saturating_sub(1);
+            self.rate_limiter.throttle(
+                format!("limit:throughput:{}", account.id),
+                limit,
+                limit,
+                prepare_amount,
+            )
+        });
+
+        if packets_limited == Some(true) {
+            Err(RateLimitError::PacketLimitExceeded)
+        } else if amount_limited == Some(true) {
+            Err(RateLimitError::ThroughputLimitExceeded)
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn refund_throughput_limit(
+        &self,
+        account: Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        if let Some(limit) = account.amount_per_minute_limit {
+            self.rate_limiter.refund(
+                &format!("limit:throughput:{}", account.id),
+                limit.saturating_sub(1),
+                prepare_amount,
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IdempotentStore for SledStore {
+    async fn load_idempotent_data(
+        &self,
+        idempotency_key: String,
+    ) -> Result<Option<IdempotentData>, IdempotentStoreError> {
+        let bytes = match self
+            .idempotency_keys
+            .get(idempotency_key.as_bytes())
+            .map_err(SledStoreError::from)?
+        {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        let data: StoredIdempotentData =
+            serde_json::from_slice(&bytes).map_err(SledStoreError::from)?;
+        if data.expires_at <= now_secs() {
+            self.idempotency_keys
+                .remove(idempotency_key.as_bytes())
+                .map_err(SledStoreError::from)?;
+            return Ok(None);
+        }
+        let status = StatusCode::from_u16(data.status)
+            .map_err(|err| SledStoreError::Corrupted(err.to_string()))?;
+        let data = IdempotentData::new(status, Bytes::from(data.body), data.input_hash);
+        trace!("Loaded idempotency key {:?} - {:?}", idempotency_key, data);
+        Ok(Some(data))
+    }
+
+    async fn save_idempotent_data(
+        &self,
+        idempotency_key: String,
+        input_hash: [u8; 32],
+        status_code: StatusCode,
+        data: Bytes,
+    ) -> Result<(), IdempotentStoreError> {
+        let stored = StoredIdempotentData {
+            status: status_code.as_u16(),
+            body: data.to_vec(),
+            input_hash,
+            expires_at: now_secs() + IDEMPOTENCY_KEY_TTL.as_secs(),
+        };
+        self.idempotency_keys
+            .insert(
+                idempotency_key.as_bytes(),
+                serde_json::to_vec(&stored).map_err(SledStoreError::from)?,
+            )
+            .map_err(SledStoreError::from)?;
+        trace!(
+            "Cached {:?}: {:?}, {:?}",
+            idempotency_key,
+            status_code,
+            data,
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SettlementStore for SledStore {
+    type Account = Account;
+
+    async fn update_balance_for_incoming_settlement(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: Option<String>,
+    ) -> Result<(), SettlementStoreError> {
+        let balance = self.incoming_settlement_data(account_id, amount, idempotency_key)?;
+        if balance.is_some() {
+            let transaction =
+                Transaction::settlement(account_id, TransactionKind::IncomingSettlement, amount);
+            if let Err(err) = self.record_transactions_data(vec![transaction]) {
+                warn!(
+                    "Could not record incoming settlement from account {}: {}",
+                    account_id, err
+                );
+            }
+        }
+        // Settlements move money outside of ILP, so make sure they are on disk
+        // before acknowledging them
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+        if let Some(balance) = balance {
+            trace!(
+                "Processed incoming settlement from account: {} for amount: {}. Balance is now: {}",
+                account_id,
+                amount,
+                balance.total()
+            );
+        }
+        Ok(())
+    }
+
+    async fn refund_settlement(
+        &self,
+        account_id: Uuid,
+        settle_amount: u64,
+    ) -> Result<(), SettlementStoreError> {
+        trace!(
+            "Refunding settlement for account: {} of amount: {}",
+            account_id,
+            settle_amount
+        );
+        let balance = self.add_to_balance(account_id, settle_amount)?;
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+        trace!(
+            "Refunded settlement for account: {} of amount: {}. Balance is now: {}",
+            account_id,
+            settle_amount,
+            balance.balance
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LeftoversStore for SledStore {
+    type AccountId = Uuid;
+    type AssetType = BigUint;
+
+    async fn get_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        let amounts = self.take_uncredited_amounts(account_id)?;
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+        Ok(sum_uncredited_amounts(amounts)?)
+    }
+
+    async fn peek_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        Ok(sum_uncredited_amounts(
+            self.peek_uncredited_amounts(account_id)?,
+        )?)
+    }
+
+    async fn save_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+        uncredited_settlement_amount: (Self::AssetType, u8),
+    ) -> Result<(), LeftoversStoreError> {
+        trace!(
+            "Saving uncredited_settlement_amount {:?} {:?}",
+            account_id,
+            uncredited_settlement_amount
+        );
+        self.push_uncredited_amount(account_id, uncredited_settlement_amount)?;
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+        Ok(())
+    }
+
+    async fn load_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+        local_scale: u8,
+    ) -> Result<Self::AssetType, LeftoversStoreError> {
+        trace!("Loading uncredited_settlement_amount {:?}", account_id);
+        let amount = self.load_uncredited_data(account_id, local_scale)?;
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+        Ok(amount)
+    }
+
+    async fn clear_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(), LeftoversStoreError> {
+        trace!("Clearing uncredited_settlement_amount {:?}", account_id);
+        self.uncredited_amounts
+            .remove(&account_id.as_bytes()[..])
+            .map_err(SledStoreError::from)?;
+        self.db.flush_async().await.map_err(SledStoreError::from)?;
+        Ok(())
+    }
+}
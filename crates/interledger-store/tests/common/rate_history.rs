@@ -0,0 +1,126 @@
+// Tests of the exchange rate history, which are run against each store
+use interledger_rates::{ExchangeRateHistoryStore, RateSnapshot, DEFAULT_RATE_HISTORY_RETENTION};
+use std::collections::HashMap;
+
+fn rates(rates: &[(&str, f64)]) -> HashMap<String, f64> {
+    rates
+        .iter()
+        .map(|(asset_code, rate)| (asset_code.to_string(), *rate))
+        .collect()
+}
+
+pub async fn keeps_history_of_each_asset<S: ExchangeRateHistoryStore>(store: &S) {
+    store
+        .record_exchange_rates(1000, rates(&[("ABC", 1.0), ("ABCD", 10.0)]))
+        .await
+        .unwrap();
+    store
+        .record_exchange_rates(2000, rates(&[("ABC", 2.0)]))
+        .await
+        .unwrap();
+    store
+        .record_exchange_rates(3000, rates(&[("ABC", 3.0), ("ABCD", 30.0)]))
+        .await
+        .unwrap();
+
+    let history = store
+        .get_exchange_rate_history("ABC", 0, u64::MAX, 100)
+        .await
+        .unwrap();
+    assert_eq!(
+        history,
+        vec![
+            RateSnapshot {
+                timestamp: 1000,
+                rate: 1.0
+            },
+            RateSnapshot {
+                timestamp: 2000,
+                rate: 2.0
+            },
+            RateSnapshot {
+                timestamp: 3000,
+                rate: 3.0
+            },
+        ]
+    );
+    let history = store
+        .get_exchange_rate_history("ABCD", 0, u64::MAX, 100)
+        .await
+        .unwrap();
+    let timestamps: Vec<u64> = history.iter().map(|snapshot| snapshot.timestamp).collect();
+    assert_eq!(timestamps, vec![1000, 3000]);
+    assert!(store
+        .get_exchange_rate_history("XYZ", 0, u64::MAX, 100)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+pub async fn selects_rates_by_time<S: ExchangeRateHistoryStore>(store: &S) {
+    for i in 1..=5 {
+        store
+            .record_exchange_rates(i * 1000, rates(&[("ABC", i as f64)]))
+            .await
+            .unwrap();
+    }
+    // A rate recorded again at the same time replaces the previous one
+    store
+        .record_exchange_rates(4000, rates(&[("ABC", 40.0)]))
+        .await
+        .unwrap();
+
+    // from is inclusive and to is exclusive
+    let history = store
+        .get_exchange_rate_history("ABC", 2000, 5000, 100)
+        .await
+        .unwrap();
+    let rates: Vec<f64> = history.iter().map(|snapshot| snapshot.rate).collect();
+    assert_eq!(rates, vec![2.0, 3.0, 40.0]);
+
+    let history = store
+        .get_exchange_rate_history("ABC", 2000, 5000, 2)
+        .await
+        .unwrap();
+    let timestamps: Vec<u64> = history.iter().map(|snapshot| snapshot.timestamp).collect();
+    assert_eq!(timestamps, vec![2000, 3000]);
+
+    assert!(store
+        .get_exchange_rate_history("ABC", 5000, 2000, 100)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+pub async fn removes_the_rates_past_the_retention<S: ExchangeRateHistoryStore>(store: &S) {
+    for i in 1..=3 {
+        store
+            .record_exchange_rates(i * 1000, rates(&[("ABC", i as f64), ("XYZ", 1.0)]))
+            .await
+            .unwrap();
+    }
+    store
+        .record_exchange_rates(
+            DEFAULT_RATE_HISTORY_RETENTION + 2500,
+            rates(&[("ABC", 4.0)]),
+        )
+        .await
+        .unwrap();
+
+    // The rate recorded at 2000 was still in effect at the cutoff
+    let history = store
+        .get_exchange_rate_history("ABC", 0, u64::MAX, 100)
+        .await
+        .unwrap();
+    let timestamps: Vec<u64> = history.iter().map(|snapshot| snapshot.timestamp).collect();
+    assert_eq!(
+        timestamps,
+        vec![2000, 3000, DEFAULT_RATE_HISTORY_RETENTION + 2500]
+    );
+    // The history of the other assets is trimmed when they are recorded again
+    let history = store
+        .get_exchange_rate_history("XYZ", 0, u64::MAX, 100)
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 3);
+}
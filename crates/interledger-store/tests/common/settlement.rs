@@ -0,0 +1,38 @@
+// Tests of the settlement data, which are run against each store
+use interledger_settlement::core::types::LeftoversStore;
+use num_bigint::BigUint;
+use uuid::Uuid;
+
+pub async fn peeks_uncredited_settlement_amount<S>(store: &S)
+where
+    S: LeftoversStore<AccountId = Uuid, AssetType = BigUint>,
+{
+    let acc = Uuid::new_v4();
+    assert_eq!(
+        store.peek_uncredited_settlement_amount(acc).await.unwrap(),
+        (BigUint::from(0u32), 0)
+    );
+    store
+        .save_uncredited_settlement_amount(acc, (BigUint::from(5u32), 11))
+        .await
+        .unwrap();
+    store
+        .save_uncredited_settlement_amount(acc, (BigUint::from(855u32), 12))
+        .await
+        .unwrap();
+
+    let expected = (BigUint::from(905u32), 12);
+    assert_eq!(
+        store.peek_uncredited_settlement_amount(acc).await.unwrap(),
+        expected
+    );
+    // Peeking leaves the amounts in the store
+    assert_eq!(
+        store.peek_uncredited_settlement_amount(acc).await.unwrap(),
+        expected
+    );
+    assert_eq!(
+        store.get_uncredited_settlement_amount(acc).await.unwrap(),
+        expected
+    );
+}
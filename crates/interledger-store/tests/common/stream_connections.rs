@@ -0,0 +1,66 @@
+// Tests of the state of STREAM connections, which are run against each store
+use interledger_errors::StreamConnectionStoreError;
+use interledger_stream::{StreamConnectionRecord, StreamConnectionStore};
+
+pub async fn tracks_total_received_per_connection<S: StreamConnectionStore>(store: &S) {
+    assert_eq!(store.get_stream_connection("abc").await.unwrap(), None);
+
+    store.add_stream_received("abc", 100).await.unwrap();
+    let record = store.add_stream_received("abc", 50).await.unwrap();
+    assert_eq!(
+        record,
+        StreamConnectionRecord {
+            total_received: 150,
+            receive_max: None,
+            closed: false,
+        }
+    );
+    assert_eq!(
+        store.get_stream_connection("abc").await.unwrap(),
+        Some(record)
+    );
+    // Other connections are separate
+    assert_eq!(store.get_stream_connection("def").await.unwrap(), None);
+}
+
+pub async fn enforces_receive_max<S: StreamConnectionStore>(store: &S) {
+    store.set_stream_receive_max("abc", 150).await.unwrap();
+    store.add_stream_received("abc", 100).await.unwrap();
+
+    let err = store.add_stream_received("abc", 51).await.unwrap_err();
+    assert!(matches!(
+        err,
+        StreamConnectionStoreError::ReceiveMaxExceeded {
+            total_received: 100,
+            receive_max: 150,
+            ..
+        }
+    ));
+    let record = store.add_stream_received("abc", 50).await.unwrap();
+    assert_eq!(record.total_received, 150);
+    assert_eq!(record.receive_max, Some(150));
+}
+
+pub async fn closes_connections_once<S: StreamConnectionStore>(store: &S) {
+    assert_eq!(store.close_stream_connection("abc").await.unwrap(), None);
+
+    store.add_stream_received("abc", 100).await.unwrap();
+    let record = store.close_stream_connection("abc").await.unwrap().unwrap();
+    assert_eq!(record.total_received, 100);
+    assert!(record.closed);
+    assert_eq!(store.close_stream_connection("abc").await.unwrap(), None);
+
+    let err = store.add_stream_received("abc", 1).await.unwrap_err();
+    assert!(matches!(
+        err,
+        StreamConnectionStoreError::ConnectionClosed(_)
+    ));
+    assert!(
+        store
+            .get_stream_connection("abc")
+            .await
+            .unwrap()
+            .unwrap()
+            .closed
+    );
+}
@@ -0,0 +1,216 @@
+// Tests of the transaction history, which are run against each store
+use interledger_api::NodeStore;
+use interledger_service::Account as AccountTrait;
+use interledger_service_util::{
+    PacketOutcome, Transaction, TransactionKind, TransactionQuery, TransactionStore,
+    TransactionTotals,
+};
+use interledger_settlement::core::types::SettlementStore;
+use interledger_store::account::Account;
+use uuid::Uuid;
+
+fn packet(account_id: Uuid, amount: u64, timestamp: u64) -> Transaction {
+    Transaction {
+        account_id,
+        kind: TransactionKind::IncomingPacket,
+        amount,
+        counterparty: None,
+        destination: Some("example.destination".to_string()),
+        outcome: Some(PacketOutcome::Fulfilled),
+        error_code: None,
+        timestamp,
+    }
+}
+
+pub async fn pages_through_transactions_newest_first<S: TransactionStore>(
+    store: &S,
+    accs: &[Account],
+) {
+    let id = accs[0].id();
+    store
+        .record_transactions((1..=5).map(|i| packet(id, i, i * 1000)).collect())
+        .await
+        .unwrap();
+    store
+        .record_transactions(vec![packet(accs[1].id(), 100, 3000)])
+        .await
+        .unwrap();
+
+    let all = store
+        .get_transactions(id, TransactionQuery::default())
+        .await
+        .unwrap();
+    let amounts: Vec<u64> = all.iter().map(|record| record.transaction.amount).collect();
+    assert_eq!(amounts, vec![5, 4, 3, 2, 1]);
+    assert!(all.windows(2).all(|pair| pair[0].id > pair[1].id));
+    assert_eq!(all[0].transaction, packet(id, 5, 5000));
+
+    let first_page = store
+        .get_transactions(
+            id,
+            TransactionQuery {
+                limit: 2,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(first_page, all[..2].to_vec());
+    let second_page = store
+        .get_transactions(
+            id,
+            TransactionQuery {
+                limit: 2,
+                before: Some(first_page[1].id),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(second_page, all[2..4].to_vec());
+
+    let in_range = store
+        .get_transactions(
+            id,
+            TransactionQuery {
+                from: Some(2000),
+                to: Some(4000),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(in_range, all[2..4].to_vec());
+}
+
+pub async fn records_incoming_settlements_once<S: TransactionStore + SettlementStore>(
+    store: &S,
+    accs: &[Account],
+) {
+    let id = accs[0].id();
+    for _ in 0..2 {
+        store
+            .update_balance_for_incoming_settlement(id, 100, Some("settlement".to_string()))
+            .await
+            .unwrap();
+    }
+
+    let transactions = store
+        .get_transactions(id, TransactionQuery::default())
+        .await
+        .unwrap();
+    assert_eq!(transactions.len(), 1);
+    assert_eq!(
+        transactions[0].transaction.kind,
+        TransactionKind::IncomingSettlement
+    );
+    assert_eq!(transactions[0].transaction.amount, 100);
+}
+
+pub async fn deletes_transactions_with_the_account<S: TransactionStore + NodeStore>(
+    store: &S,
+    accs: &[Account],
+) {
+    let id = accs[0].id();
+    store
+        .record_transactions(vec![packet(id, 1, 1000)])
+        .await
+        .unwrap();
+    store.delete_account(id).await.unwrap();
+    assert!(store
+        .get_transactions(id, TransactionQuery::default())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+pub async fn pages_on_ids_when_timestamps_are_out_of_order<S: TransactionStore>(
+    store: &S,
+    accs: &[Account],
+) {
+    let id = accs[0].id();
+    // Transactions recorded later may have been timestamped earlier
+    for timestamp in &[3000, 1000, 2000] {
+        store
+            .record_transactions(vec![packet(id, *timestamp, *timestamp)])
+            .await
+            .unwrap();
+    }
+
+    let since_2000 = store
+        .get_transactions(
+            id,
+            TransactionQuery {
+                from: Some(2000),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    let amounts: Vec<u64> = since_2000
+        .iter()
+        .map(|record| record.transaction.amount)
+        .collect();
+    assert_eq!(amounts, vec![2000, 3000]);
+
+    let after_first = store
+        .get_transactions(
+            id,
+            TransactionQuery {
+                after: Some(since_2000[1].id),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    let amounts: Vec<u64> = after_first
+        .iter()
+        .map(|record| record.transaction.amount)
+        .collect();
+    assert_eq!(amounts, vec![2000, 1000]);
+}
+
+/// Needs a store which keeps 3 transactions per account
+pub async fn trims_the_oldest_transactions<S: TransactionStore + Sync>(
+    store: &S,
+    accs: &[Account],
+) {
+    let id = accs[0].id();
+    store
+        .record_transactions((1..=4).map(|i| packet(id, i, i * 1000)).collect())
+        .await
+        .unwrap();
+    store
+        .record_transactions(vec![packet(id, 5, 5000), packet(accs[1].id(), 100, 5000)])
+        .await
+        .unwrap();
+
+    let kept = store
+        .get_transactions(id, TransactionQuery::default())
+        .await
+        .unwrap();
+    let amounts: Vec<u64> = kept
+        .iter()
+        .map(|record| record.transaction.amount)
+        .collect();
+    assert_eq!(amounts, vec![5, 4, 3]);
+    assert_eq!(
+        store.get_trimmed_transaction_totals(id).await.unwrap(),
+        TransactionTotals {
+            packets_in: 3,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        TransactionTotals::load(store, id).await.unwrap().packets_in,
+        15
+    );
+    assert_eq!(
+        store
+            .get_transactions(accs[1].id(), TransactionQuery::default())
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+}
@@ -0,0 +1,75 @@
+use super::store_helpers::*;
+
+use interledger_btp::{BtpAccount, BtpStore};
+use interledger_http::{HttpAccount, HttpStore};
+use interledger_packet::Address;
+use interledger_service::{Account as AccountTrait, Username};
+
+use secrecy::ExposeSecret;
+use std::str::FromStr;
+
+#[tokio::test]
+async fn gets_account_from_btp_auth() {
+    let (store, _) = test_store().await.unwrap();
+    let account = store
+        .get_account_from_btp_auth(&Username::from_str("bob").unwrap(), "other_btp_token")
+        .await
+        .unwrap();
+    assert_eq!(
+        *account.ilp_address(),
+        Address::from_str("example.alice.user1.bob").unwrap()
+    );
+    assert_eq!(
+        &account.get_ilp_over_btp_outgoing_token().unwrap(),
+        b"btp_token"
+    );
+}
+
+#[tokio::test]
+async fn errors_on_wrong_btp_token() {
+    let (store, _) = test_store().await.unwrap();
+    let err = store
+        .get_account_from_btp_auth(&Username::from_str("bob").unwrap(), "wrong_token")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "account `bob` is not authorized for this action"
+    );
+
+    let err = store
+        .get_account_from_btp_auth(&Username::from_str("asdf").unwrap(), "other_btp_token")
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "account `asdf` was not found");
+}
+
+#[tokio::test]
+async fn gets_account_from_http_bearer_token() {
+    let (store, _) = test_store().await.unwrap();
+    let account = store
+        .get_account_from_http_auth(&Username::from_str("alice").unwrap(), "incoming_auth_token")
+        .await
+        .unwrap();
+    assert_eq!(
+        *account.ilp_address(),
+        Address::from_str("example.alice").unwrap()
+    );
+    assert_eq!(
+        account.get_http_auth_token().unwrap().expose_secret(),
+        "outgoing_auth_token",
+    );
+}
+
+#[tokio::test]
+async fn errors_on_wrong_http_token() {
+    let (store, _) = test_store().await.unwrap();
+    let err = store
+        .get_account_from_http_auth(&Username::from_str("alice").unwrap(), "unauthorized")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "account `alice` is not authorized for this action"
+    );
+}
@@ -0,0 +1,161 @@
+use super::{fixtures::*, store_helpers::*};
+
+use interledger_api::NodeStore;
+use interledger_packet::Address;
+use interledger_service::{Account as AccountTrait, Username};
+use interledger_service_util::BalanceStore;
+use interledger_settlement::core::types::SettlementStore;
+use std::str::FromStr;
+
+#[tokio::test]
+async fn prepare_then_fulfill_with_settlement() {
+    let (store, accs) = test_store().await.unwrap();
+    let account0_id = accs[0].id();
+    let account1_id = accs[1].id();
+    // reduce account 0's balance by 100
+    store
+        .update_balances_for_prepare(account0_id, 100)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(account0_id).await.unwrap(), -100);
+    assert_eq!(store.get_balance(account1_id).await.unwrap(), 0);
+
+    // account 1 has settle_threshold 0 and settle_to -1000
+    let (balance, amount_to_settle) = store
+        .update_balances_for_fulfill(account1_id, 100)
+        .await
+        .unwrap();
+    assert_eq!(balance, -1000);
+    assert_eq!(amount_to_settle, 1100);
+    assert_eq!(store.get_balance(account0_id).await.unwrap(), -100);
+    assert_eq!(store.get_balance(account1_id).await.unwrap(), -1000);
+}
+
+#[tokio::test]
+async fn process_fulfill_settle_to_over_threshold() {
+    // account misconfigured with settle_to >= settle_threshold does not get settlements
+    let acc = {
+        let mut acc = ACCOUNT_DETAILS_1.clone();
+        acc.username = Username::from_str("charlie").unwrap();
+        acc.ilp_address = Some(Address::from_str("example.b").unwrap());
+        acc.settle_to = Some(101);
+        acc.settle_threshold = Some(100);
+        acc
+    };
+    let (store, _) = test_store().await.unwrap();
+    let acc = store.insert_account(acc).await.unwrap();
+    let (balance, amount_to_settle) = store
+        .update_balances_for_fulfill(acc.id(), 1000)
+        .await
+        .unwrap();
+    assert_eq!(balance, 1000);
+    assert_eq!(amount_to_settle, 0);
+}
+
+#[tokio::test]
+async fn process_fulfill_ok() {
+    // account with settle to = 0 (not falsy) with settle_threshold > 0, gets settlements
+    let acc = {
+        let mut acc = ACCOUNT_DETAILS_1.clone();
+        acc.username = Username::from_str("charlie").unwrap();
+        acc.ilp_address = Some(Address::from_str("example.c").unwrap());
+        acc.settle_to = Some(0);
+        acc.settle_threshold = Some(100);
+        acc
+    };
+    let (store, _) = test_store().await.unwrap();
+    let account = store.insert_account(acc).await.unwrap();
+    let (balance, amount_to_settle) = store
+        .update_balances_for_fulfill(account.id(), 101)
+        .await
+        .unwrap();
+    assert_eq!(balance, 0);
+    assert_eq!(amount_to_settle, 101);
+}
+
+#[tokio::test]
+async fn prepare_then_reject() {
+    let (store, accs) = test_store().await.unwrap();
+    let acc0 = accs[0].id();
+    store.update_balances_for_prepare(acc0, 100).await.unwrap();
+    assert_eq!(store.get_balance(acc0).await.unwrap(), -100);
+    store.update_balances_for_reject(acc0, 100).await.unwrap();
+    assert_eq!(store.get_balance(acc0).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn enforces_minimum_balance() {
+    let (store, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    let err = store
+        .update_balances_for_prepare(id, 10000)
+        .await
+        .unwrap_err();
+    let expected = format!("Incoming prepare of 10000 would bring account {} under its minimum balance. Current balance: 0, min balance: -1000", id);
+    assert!(err.to_string().contains(&expected));
+}
+
+#[tokio::test]
+async fn prepares_use_prepaid_amount_first() {
+    let (store, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    store
+        .update_balance_for_incoming_settlement(id, 100, None)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), 100);
+
+    store.update_balances_for_prepare(id, 150).await.unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), -50);
+    // the prepaid amount was used up, so the min balance of -1000 now applies
+    // to the balance alone
+    assert!(store.update_balances_for_prepare(id, 951).await.is_err());
+    store.update_balances_for_prepare(id, 950).await.unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), -1000);
+}
+
+#[tokio::test]
+async fn rejects_amounts_out_of_range() {
+    // without a minimum balance, nothing but the range of the balance limits the prepares
+    let acc = {
+        let mut acc = ACCOUNT_DETAILS_1.clone();
+        acc.username = Username::from_str("charlie").unwrap();
+        acc.ilp_address = Some(Address::from_str("example.c").unwrap());
+        acc.min_balance = None;
+        acc
+    };
+    let (store, accs) = test_store().await.unwrap();
+    let account = store.insert_account(acc).await.unwrap();
+    for &id in &[accs[0].id(), account.id()] {
+        let err = store
+            .update_balances_for_prepare(id, u64::MAX)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("does not fit in the balance"));
+        assert_eq!(store.get_balance(id).await.unwrap(), 0);
+    }
+
+    let id = account.id();
+    assert!(store
+        .update_balances_for_fulfill(id, u64::MAX)
+        .await
+        .is_err());
+    assert!(store
+        .update_balances_for_reject(id, u64::MAX)
+        .await
+        .is_err());
+    assert!(store
+        .update_balance_for_incoming_settlement(id, u64::MAX, None)
+        .await
+        .is_err());
+    assert!(store.refund_settlement(id, u64::MAX).await.is_err());
+    assert_eq!(store.get_balance(id).await.unwrap(), 0);
+
+    // amounts which fit can still not take the balance past its range
+    store
+        .update_balances_for_prepare(id, i64::MAX as u64)
+        .await
+        .unwrap();
+    assert!(store.update_balances_for_prepare(id, 2).await.is_err());
+    assert_eq!(store.get_balance(id).await.unwrap(), -i64::MAX);
+}
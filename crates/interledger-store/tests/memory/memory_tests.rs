@@ -0,0 +1,66 @@
+mod accounts_test;
+mod auth_test;
+mod balances_test;
+mod rate_history_test;
+mod rate_limiting_test;
+mod routing_test;
+mod settlement_test;
+mod stream_connections_test;
+mod transactions_test;
+
+#[path = "../common/fixtures.rs"]
+mod fixtures;
+#[path = "../common/rate_history.rs"]
+mod rate_history;
+#[path = "../common/settlement.rs"]
+mod settlement;
+#[path = "../common/stream_connections.rs"]
+mod stream_connections;
+#[path = "../common/transactions.rs"]
+mod transactions;
+
+mod store_helpers {
+    use super::fixtures::*;
+
+    use interledger_api::NodeStore;
+    use interledger_packet::Address;
+    use interledger_service::{Account as AccountTrait, AddressStore};
+    use interledger_service_util::DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT;
+    use interledger_store::{
+        account::Account,
+        memory::{InMemoryStore, InMemoryStoreBuilder},
+    };
+    use std::str::FromStr;
+
+    pub async fn test_store() -> Result<(InMemoryStore, Vec<Account>), ()> {
+        test_store_with_max_transactions(DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT).await
+    }
+
+    pub async fn test_store_with_max_transactions(
+        max_transactions_per_account: usize,
+    ) -> Result<(InMemoryStore, Vec<Account>), ()> {
+        let store = InMemoryStoreBuilder::new()
+            .node_ilp_address(Address::from_str("example.node").unwrap())
+            .max_transactions_per_account(max_transactions_per_account)
+            .build();
+        let mut accs = Vec::new();
+        let acc = store
+            .insert_account(ACCOUNT_DETAILS_0.clone())
+            .await
+            .unwrap();
+        accs.push(acc.clone());
+        // alice is a Parent, so the store's ilp address is updated to
+        // the value that would be received by the ILDCP request
+        store
+            .set_ilp_address(acc.ilp_address().with_suffix(b"user1").unwrap())
+            .await
+            .unwrap();
+
+        let acc = store
+            .insert_account(ACCOUNT_DETAILS_1.clone())
+            .await
+            .unwrap();
+        accs.push(acc);
+        Ok((store, accs))
+    }
+}
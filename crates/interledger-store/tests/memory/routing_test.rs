@@ -0,0 +1,136 @@
+use super::{fixtures::*, store_helpers::*};
+
+use interledger_api::NodeStore;
+use interledger_ccp::CcpRoutingStore;
+use interledger_router::{NextHop, Route, RouterStore};
+use interledger_service::{Account as AccountTrait, AddressStore};
+use interledger_store::account::Account;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn inserting_accounts_updates_routing_table() {
+    let (store, accs) = test_store().await.unwrap();
+    let routes = store.routing_table();
+    assert_eq!(routes.len(), 2);
+    assert_eq!(routes["example.alice"], Route::from(accs[0].id()));
+    assert_eq!(routes["example.alice.user1.bob"], Route::from(accs[1].id()));
+
+    store.delete_account(accs[1].id()).await.unwrap();
+    let routes = store.routing_table();
+    assert_eq!(routes.len(), 1);
+    assert!(routes.get("example.alice.user1.bob").is_none());
+}
+
+#[tokio::test]
+async fn gets_accounts_to_send_routes_to() {
+    let (store, accs) = test_store().await.unwrap();
+    let accounts = store
+        .get_accounts_to_send_routes_to(Vec::new())
+        .await
+        .unwrap();
+    // We send to child accounts but not parents
+    assert_eq!(accounts[0].username().as_ref(), "bob");
+    assert_eq!(accounts.len(), 1);
+
+    let accounts = store
+        .get_accounts_to_send_routes_to(vec![accs[1].id()])
+        .await
+        .unwrap();
+    assert!(accounts.is_empty());
+}
+
+#[tokio::test]
+async fn gets_accounts_to_receive_routes_from() {
+    let (store, accs) = test_store().await.unwrap();
+    let accounts = store.get_accounts_to_receive_routes_from().await.unwrap();
+    assert_eq!(accounts.len(), 1);
+    assert_eq!(accounts[0].id(), accs[0].id());
+}
+
+#[tokio::test]
+async fn static_routes_override_others() {
+    let (store, accs) = test_store().await.unwrap();
+    store
+        .set_static_routes(vec![
+            ("example.a".to_string(), accs[0].id()),
+            ("example.b".to_string(), accs[0].id()),
+        ])
+        .await
+        .unwrap();
+
+    let account1_id = Uuid::new_v4();
+    let account1 = Account::try_from(
+        account1_id,
+        ACCOUNT_DETAILS_1.clone(),
+        store.get_ilp_address(),
+    )
+    .unwrap();
+    store
+        .clone()
+        .set_routes(vec![
+            ("example.a".to_string(), account1.clone()),
+            ("example.b".to_string(), account1.clone()),
+            ("example.c".to_string(), account1),
+        ])
+        .await
+        .unwrap();
+
+    let routes = store.routing_table();
+    assert_eq!(routes["example.a"], Route::from(accs[0].id()));
+    assert_eq!(routes["example.b"], Route::from(accs[0].id()));
+    assert_eq!(routes["example.c"], Route::from(account1_id));
+    assert_eq!(routes.len(), 3);
+
+    let (_, configured) = store.get_local_and_configured_routes().await.unwrap();
+    assert_eq!(configured.len(), 2);
+}
+
+#[tokio::test]
+async fn default_route() {
+    let (store, accs) = test_store().await.unwrap();
+    store.set_default_route(accs[0].id()).await.unwrap();
+    let routes = store.routing_table();
+    assert_eq!(routes[""], Route::from(accs[0].id()));
+    assert_eq!(routes.len(), 3);
+}
+
+#[tokio::test]
+async fn static_routes_require_existing_accounts() {
+    let (store, accs) = test_store().await.unwrap();
+    let err = store
+        .set_static_routes(vec![
+            ("example.a".to_string(), accs[0].id()),
+            ("example.b".to_string(), Uuid::new_v4()),
+        ])
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "not all of the given accounts exist");
+    assert!(store.routing_table().get("example.a").is_none());
+}
+
+#[tokio::test]
+async fn static_routes_can_have_several_next_hops() {
+    let (store, accs) = test_store().await.unwrap();
+    let route = Route::new(vec![
+        NextHop {
+            account_id: accs[0].id(),
+            weight: Some(3),
+        },
+        NextHop {
+            account_id: accs[1].id(),
+            weight: Some(1),
+        },
+    ]);
+    store
+        .set_static_routes(vec![("example.multi".to_string(), route.clone())])
+        .await
+        .unwrap();
+    assert_eq!(store.routing_table()["example.multi"], route);
+
+    // The route is advertised through the first next hop which still exists
+    let (_, configured) = store.get_local_and_configured_routes().await.unwrap();
+    assert_eq!(configured["example.multi"].id(), accs[0].id());
+    store.delete_account(accs[0].id()).await.unwrap();
+    let (_, configured) = store.get_local_and_configured_routes().await.unwrap();
+    assert_eq!(configured["example.multi"].id(), accs[1].id());
+}
@@ -0,0 +1,162 @@
+use super::{fixtures::*, store_helpers::*};
+use interledger_api::{AccountSettings, NodeStore};
+use interledger_btp::{BtpAccount, BtpStore};
+use interledger_http::{HttpAccount, HttpClientSettings, HttpStore};
+use interledger_packet::Address;
+use interledger_service::Account as AccountTrait;
+use interledger_service::{AccountStore, AddressStore, Username};
+use secrecy::{ExposeSecret, SecretString};
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn insert_accounts() {
+    let (store, _db, _) = test_store().await.unwrap();
+    let account = store
+        .insert_account(ACCOUNT_DETAILS_2.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        *account.ilp_address(),
+        Address::from_str("example.alice.user1.charlie").unwrap()
+    );
+
+    // cannot insert duplicate accounts
+    let err = store
+        .insert_account(ACCOUNT_DETAILS_2.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "account `charlie` already exists");
+}
+
+#[tokio::test]
+async fn persists_accounts_across_restarts() {
+    let (store, db, accs) = test_store().await.unwrap();
+    drop(store);
+
+    let store = db.open().await;
+    // the address we got from our parent is picked up again
+    assert_eq!(
+        store.get_ilp_address(),
+        Address::from_str("example.alice.user1").unwrap()
+    );
+    let accounts = store
+        .get_accounts(vec![accs[0].id(), accs[1].id()])
+        .await
+        .unwrap();
+    assert_eq!(accounts[0].username().as_ref(), "alice");
+    assert_eq!(accounts[1].ilp_address(), accs[1].ilp_address());
+    assert_eq!(
+        accounts[0].get_http_auth_token().unwrap().expose_secret(),
+        "outgoing_auth_token",
+    );
+    let account = store
+        .get_account_from_btp_auth(&Username::from_str("bob").unwrap(), "other_btp_token")
+        .await
+        .unwrap();
+    assert_eq!(account.id(), accs[1].id());
+}
+
+#[tokio::test]
+async fn tokens_cannot_be_read_with_another_secret() {
+    let (store, db, accs) = test_store().await.unwrap();
+    drop(store);
+
+    let store = db.open_with_secret([1; 32]).await;
+    let accounts = store.get_accounts(vec![accs[0].id()]).await.unwrap();
+    assert!(accounts[0].get_http_auth_token().is_none());
+    assert!(accounts[0].get_ilp_over_btp_outgoing_token().is_none());
+    let err = store
+        .get_account_from_http_auth(&Username::from_str("alice").unwrap(), "incoming_auth_token")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "account `alice` is not authorized for this action"
+    );
+}
+
+#[tokio::test]
+async fn encrypts_client_identity_password() {
+    let (store, db, _) = test_store().await.unwrap();
+    let mut details = ACCOUNT_DETAILS_2.clone();
+    details.ilp_over_http_client = Some(HttpClientSettings {
+        client_identity_password: Some(SecretString::new("identity password".to_string())),
+        ..Default::default()
+    });
+    let account = store.insert_account(details).await.unwrap();
+
+    let row = db
+        .client()
+        .await
+        .query_one(
+            "SELECT ilp_over_http_client FROM accounts WHERE id = $1",
+            &[&account.id()],
+        )
+        .await
+        .unwrap();
+    let stored: String = row.get(0);
+    assert!(stored.contains("client_identity_password"));
+    assert!(!stored.contains("identity password"));
+
+    let accounts = store.get_accounts(vec![account.id()]).await.unwrap();
+    let settings = accounts[0].get_http_client_settings().unwrap();
+    assert_eq!(
+        settings
+            .client_identity_password
+            .as_ref()
+            .unwrap()
+            .expose_secret(),
+        "identity password"
+    );
+}
+
+#[tokio::test]
+async fn delete_and_update_accounts() {
+    let (store, _db, accs) = test_store().await.unwrap();
+    let mut new = ACCOUNT_DETAILS_0.clone();
+    new.asset_code = String::from("TUV");
+    let account = store.update_account(accs[0].id(), new).await.unwrap();
+    assert_eq!(account.asset_code(), "TUV");
+
+    store.delete_account(accs[1].id()).await.unwrap();
+    let accounts = store.get_all_accounts().await.unwrap();
+    assert_eq!(accounts.len(), 1);
+    assert_eq!(accounts[0].asset_code(), "TUV");
+
+    let err = store.delete_account(accs[1].id()).await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!("account `{}` was not found", accs[1].id())
+    );
+}
+
+#[tokio::test]
+async fn modify_account_settings() {
+    let (store, _db, accounts) = test_store().await.unwrap();
+    let settings = AccountSettings {
+        ilp_over_http_outgoing_token: Some(SecretString::new("test_token".to_owned())),
+        ilp_over_btp_outgoing_token: Some(SecretString::new("dylan:test".to_owned())),
+        settle_to: Some(100),
+        ..Default::default()
+    };
+    let ret = store
+        .modify_account_settings(accounts[0].id(), settings.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        ret.get_http_auth_token().unwrap().expose_secret(),
+        "test_token",
+    );
+    assert_eq!(
+        ret.get_ilp_over_btp_outgoing_token().unwrap(),
+        &b"dylan:test"[..],
+    );
+
+    let id = Uuid::new_v4();
+    let err = store
+        .modify_account_settings(id, settings)
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), format!("account `{}` was not found", id));
+}
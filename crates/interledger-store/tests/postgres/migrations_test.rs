@@ -0,0 +1,58 @@
+use super::store_helpers::*;
+
+use interledger_store::postgres::PostgresStoreBuilder;
+
+#[tokio::test]
+async fn applies_migrations_once() {
+    let db = TestDb::new();
+    let store = db.open().await;
+    drop(store);
+    // connecting again does not try to apply the migrations again
+    let _store = db.open().await;
+
+    let rows = db
+        .client()
+        .await
+        .query(
+            "SELECT version, name FROM schema_migrations ORDER BY version",
+            &[],
+        )
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 8);
+    assert_eq!(rows[0].get::<_, i32>(0), 1);
+    assert_eq!(rows[0].get::<_, String>(1), "initial");
+    assert_eq!(rows[1].get::<_, i32>(0), 2);
+    assert_eq!(rows[1].get::<_, String>(1), "static_route_hops");
+    assert_eq!(rows[2].get::<_, i32>(0), 3);
+    assert_eq!(rows[2].get::<_, String>(1), "stream_connections");
+    assert_eq!(rows[3].get::<_, i32>(0), 4);
+    assert_eq!(rows[3].get::<_, String>(1), "http_client_settings");
+    assert_eq!(rows[4].get::<_, i32>(0), 5);
+    assert_eq!(rows[4].get::<_, String>(1), "transactions");
+    assert_eq!(rows[5].get::<_, i32>(0), 6);
+    assert_eq!(rows[5].get::<_, String>(1), "rate_history");
+    assert_eq!(rows[6].get::<_, i32>(0), 7);
+    assert_eq!(rows[6].get::<_, String>(1), "transaction_history");
+    assert_eq!(rows[7].get::<_, i32>(0), 8);
+    assert_eq!(rows[7].get::<_, String>(1), "settlement_acceptance");
+}
+
+#[tokio::test]
+async fn refuses_newer_schema() {
+    let db = TestDb::new();
+    drop(db.open().await);
+    db.client()
+        .await
+        .execute(
+            "INSERT INTO schema_migrations (version, name) VALUES (1000, 'from_the_future')",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let result = PostgresStoreBuilder::new(db.server.config(), [0; 32])
+        .connect()
+        .await;
+    assert!(result.is_err());
+}
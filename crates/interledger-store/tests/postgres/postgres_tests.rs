@@ -0,0 +1,200 @@
+mod accounts_test;
+mod balances_test;
+mod migrations_test;
+mod rate_history_test;
+mod routing_test;
+mod settlement_test;
+mod stream_connections_test;
+mod transactions_test;
+
+#[path = "../common/fixtures.rs"]
+mod fixtures;
+#[path = "../common/rate_history.rs"]
+mod rate_history;
+#[path = "../common/settlement.rs"]
+mod settlement;
+#[path = "../common/stream_connections.rs"]
+mod stream_connections;
+#[path = "../common/transactions.rs"]
+mod transactions;
+
+mod postgres_helpers {
+    use std::{
+        env, fs,
+        net::TcpListener,
+        path::PathBuf,
+        process::{self, Command, Stdio},
+        thread::sleep,
+        time::Duration,
+    };
+    use uuid::Uuid;
+
+    /// A throwaway Postgres server which is initialized in a temporary directory
+    /// and stopped (and removed) when dropped.
+    ///
+    /// The `initdb` and `postgres` binaries are looked up in `PG_BIN_DIR` if it is set,
+    /// and on the `PATH` otherwise.
+    pub struct PostgresServer {
+        process: process::Child,
+        data_dir: PathBuf,
+        pub port: u16,
+    }
+
+    fn pg_command(name: &str) -> Command {
+        match env::var("PG_BIN_DIR") {
+            Ok(dir) => Command::new(PathBuf::from(dir).join(name)),
+            Err(_) => Command::new(name),
+        }
+    }
+
+    impl PostgresServer {
+        pub fn new() -> Self {
+            let mut data_dir = env::temp_dir();
+            data_dir.push(format!("ilp-postgres-test-{}", Uuid::new_v4()));
+
+            let status = pg_command("initdb")
+                .arg("--pgdata")
+                .arg(&data_dir)
+                .args(&["--username", "postgres", "--auth", "trust"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .expect("could not run initdb, is Postgres installed?");
+            assert!(status.success(), "initdb failed");
+
+            // this is technically a race but we can't do better with
+            // the tools that postgres gives us
+            let port = TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            let process = pg_command("postgres")
+                .arg("-D")
+                .arg(&data_dir)
+                .args(&["-p", &port.to_string(), "-h", "127.0.0.1", "-k"])
+                .arg(&data_dir)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap();
+            let server = PostgresServer {
+                process,
+                data_dir,
+                port,
+            };
+
+            // Wait until the server accepts connections
+            for _ in 0..500 {
+                if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                    return server;
+                }
+                sleep(Duration::from_millis(10));
+            }
+            panic!("Postgres did not start listening on port {}", port);
+        }
+
+        pub fn config(&self) -> postgres_crate::Config {
+            let mut config = postgres_crate::Config::new();
+            config
+                .host("127.0.0.1")
+                .port(self.port)
+                .user("postgres")
+                .dbname("postgres");
+            config
+        }
+    }
+
+    impl Drop for PostgresServer {
+        fn drop(&mut self) {
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+            fs::remove_dir_all(&self.data_dir).ok();
+        }
+    }
+}
+
+mod store_helpers {
+    use super::fixtures::*;
+    use super::postgres_helpers::PostgresServer;
+
+    use interledger_api::NodeStore;
+    use interledger_packet::Address;
+    use interledger_service::{Account as AccountTrait, AddressStore};
+    use interledger_service_util::DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT;
+    use interledger_store::{
+        account::Account,
+        postgres::{PostgresStore, PostgresStoreBuilder},
+    };
+    use postgres_crate::NoTls;
+    use std::str::FromStr;
+
+    /// A database on a throwaway server
+    pub struct TestDb {
+        pub server: PostgresServer,
+        max_transactions_per_account: usize,
+    }
+
+    impl TestDb {
+        pub fn new() -> Self {
+            Self::with_max_transactions(DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT)
+        }
+
+        pub fn with_max_transactions(max_transactions_per_account: usize) -> Self {
+            TestDb {
+                server: PostgresServer::new(),
+                max_transactions_per_account,
+            }
+        }
+
+        pub async fn open(&self) -> PostgresStore {
+            self.open_with_secret([0; 32]).await
+        }
+
+        pub async fn open_with_secret(&self, secret: [u8; 32]) -> PostgresStore {
+            PostgresStoreBuilder::new(self.server.config(), secret)
+                .node_ilp_address(Address::from_str("example.node").unwrap())
+                .max_transactions_per_account(self.max_transactions_per_account)
+                .connect()
+                .await
+                .unwrap()
+        }
+
+        /// A plain connection, used to inspect the tables
+        pub async fn client(&self) -> postgres_crate::Client {
+            let (client, connection) = self.server.config().connect(NoTls).await.unwrap();
+            tokio::spawn(connection);
+            client
+        }
+    }
+
+    pub async fn test_store() -> Result<(PostgresStore, TestDb, Vec<Account>), ()> {
+        test_store_with_max_transactions(DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT).await
+    }
+
+    pub async fn test_store_with_max_transactions(
+        max_transactions_per_account: usize,
+    ) -> Result<(PostgresStore, TestDb, Vec<Account>), ()> {
+        let db = TestDb::with_max_transactions(max_transactions_per_account);
+        let store = db.open().await;
+        let mut accs = Vec::new();
+        let acc = store
+            .insert_account(ACCOUNT_DETAILS_0.clone())
+            .await
+            .unwrap();
+        accs.push(acc.clone());
+        // alice is a Parent, so the store's ilp address is updated to
+        // the value that would be received by the ILDCP request
+        store
+            .set_ilp_address(acc.ilp_address().with_suffix(b"user1").unwrap())
+            .await
+            .unwrap();
+
+        let acc = store
+            .insert_account(ACCOUNT_DETAILS_1.clone())
+            .await
+            .unwrap();
+        accs.push(acc);
+        Ok((store, db, accs))
+    }
+}
@@ -0,0 +1,32 @@
+use super::store_helpers::*;
+use super::transactions;
+
+#[tokio::test]
+async fn pages_through_transactions_newest_first() {
+    let (store, _db, accs) = test_store().await.unwrap();
+    transactions::pages_through_transactions_newest_first(&store, &accs).await;
+}
+
+#[tokio::test]
+async fn records_incoming_settlements_once() {
+    let (store, _db, accs) = test_store().await.unwrap();
+    transactions::records_incoming_settlements_once(&store, &accs).await;
+}
+
+#[tokio::test]
+async fn deletes_transactions_with_the_account() {
+    let (store, _db, accs) = test_store().await.unwrap();
+    transactions::deletes_transactions_with_the_account(&store, &accs).await;
+}
+
+#[tokio::test]
+async fn pages_on_ids_when_timestamps_are_out_of_order() {
+    let (store, _db, accs) = test_store().await.unwrap();
+    transactions::pages_on_ids_when_timestamps_are_out_of_order(&store, &accs).await;
+}
+
+#[tokio::test]
+async fn trims_the_oldest_transactions() {
+    let (store, _db, accs) = test_store_with_max_transactions(3).await.unwrap();
+    transactions::trims_the_oldest_transactions(&store, &accs).await;
+}
@@ -2,13 +2,13 @@ use super::{fixtures::*, redis_helpers::*, store_helpers::*};
 use interledger_api::{AccountSettings, NodeStore};
 use interledger_btp::BtpAccount;
 use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
-use interledger_http::HttpAccount;
+use interledger_http::{HttpAccount, HttpClientSettings};
 use interledger_packet::Address;
 use interledger_service::Account as AccountTrait;
 use interledger_service::{AccountStore, AddressStore, Username};
 use interledger_service_util::BalanceStore;
 use interledger_store::redis::RedisStoreBuilder;
-use redis_crate::Client;
+use redis_crate::{AsyncCommands, Client};
 use secrecy::ExposeSecret;
 use secrecy::SecretString;
 use std::default::Default;
@@ -337,6 +337,36 @@ async fn decrypts_outgoing_tokens_acc() {
     );
 }
 
+#[tokio::test]
+async fn encrypts_client_identity_password() {
+    let (store, context, _) = test_store().await.unwrap();
+    let mut details = ACCOUNT_DETAILS_2.clone();
+    details.ilp_over_http_client = Some(HttpClientSettings {
+        client_identity_password: Some(SecretString::new("identity password".to_string())),
+        ..Default::default()
+    });
+    let account = store.insert_account(details).await.unwrap();
+
+    let mut connection = context.shared_async_connection().await.unwrap();
+    let stored: String = connection
+        .hget(format!("accounts:{}", account.id()), "ilp_over_http_client")
+        .await
+        .unwrap();
+    assert!(stored.contains("client_identity_password"));
+    assert!(!stored.contains("identity password"));
+
+    let accounts = store.get_accounts(vec![account.id()]).await.unwrap();
+    let settings = accounts[0].get_http_client_settings().unwrap();
+    assert_eq!(
+        settings
+            .client_identity_password
+            .as_ref()
+            .unwrap()
+            .expose_secret(),
+        "identity password"
+    );
+}
+
 #[tokio::test]
 async fn errors_for_unknown_accounts() {
     let (store, _context, _) = test_store().await.unwrap();
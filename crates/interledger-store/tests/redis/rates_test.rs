@@ -1,6 +1,7 @@
 use super::store_helpers::*;
 
 use interledger_rates::ExchangeRateStore;
+use interledger_service_util::{FeePolicy, FeePolicyStore, PairSpread};
 
 #[tokio::test]
 async fn set_rates() {
@@ -20,3 +21,42 @@ async fn set_rates() {
     assert_eq!(rates[0].to_string(), "0.005");
     assert_eq!(rates[1].to_string(), "500");
 }
+
+#[tokio::test]
+async fn keeps_fetch_times() {
+    let (store, _context, _) = test_store().await.unwrap();
+    store
+        .set_exchange_rates_fetched_at(
+            [("ABC".to_string(), 500.0), ("XYZ".to_string(), 0.005)]
+                .iter()
+                .cloned()
+                .collect(),
+            [("ABC".to_string(), 1000)].iter().cloned().collect(),
+        )
+        .unwrap();
+    let fetched_at = store
+        .get_exchange_rates_fetched_at(&["ABC", "XYZ"])
+        .unwrap();
+    assert_eq!(fetched_at, vec![Some(1000), None]);
+
+    // Rates set without a fetch time do not keep the previous ones
+    store
+        .set_exchange_rates([("ABC".to_string(), 400.0)].iter().cloned().collect())
+        .unwrap();
+    let fetched_at = store.get_exchange_rates_fetched_at(&["ABC"]).unwrap();
+    assert_eq!(fetched_at, vec![None]);
+}
+
+#[tokio::test]
+async fn sets_fee_policy() {
+    let (store, _context, _) = test_store().await.unwrap();
+    assert_eq!(*store.get_fee_policy(), FeePolicy::default());
+    let mut policy = FeePolicy::default();
+    policy.pairs.push(PairSpread {
+        from: "ABC".to_string(),
+        to: "XYZ".to_string(),
+        spread: 0.01,
+    });
+    store.set_fee_policy(policy.clone());
+    assert_eq!(*store.clone().get_fee_policy(), policy);
+}
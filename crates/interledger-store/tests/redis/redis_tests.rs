@@ -2,10 +2,22 @@ mod accounts_test;
 mod balances_test;
 mod btp_test;
 mod http_test;
+mod rate_history_test;
 mod rate_limiting_test;
 mod rates_test;
 mod routing_test;
 mod settlement_test;
+mod stream_connections_test;
+mod transactions_test;
+
+#[path = "../common/rate_history.rs"]
+mod rate_history;
+#[path = "../common/settlement.rs"]
+mod settlement;
+#[path = "../common/stream_connections.rs"]
+mod stream_connections;
+#[path = "../common/transactions.rs"]
+mod transactions;
 
 mod fixtures {
 
@@ -25,6 +37,7 @@ mod fixtures {
         max_packet_amount: 1000,
         min_balance: Some(-1000),
         ilp_over_http_url: Some("http://example.com/accounts/dylan/ilp".to_string()),
+        ilp_over_http_client: None,
         ilp_over_http_incoming_token: Some(SecretString::new("incoming_auth_token".to_string())),
         ilp_over_http_outgoing_token: Some(SecretString::new("outgoing_auth_token".to_string())),
         ilp_over_btp_url: Some("btp+ws://example.com/accounts/dylan/ilp/btp".to_string()),
@@ -46,6 +59,7 @@ mod fixtures {
         max_packet_amount: 1_000_000,
         min_balance: Some(0),
         ilp_over_http_url: Some("http://example.com/accounts/dylan/ilp".to_string()),
+        ilp_over_http_client: None,
         // incoming token has is the account's username concatenated wiht the password
         ilp_over_http_incoming_token: Some(SecretString::new("incoming_auth_token".to_string())),
         ilp_over_http_outgoing_token: Some(SecretString::new("outgoing_auth_token".to_string())),
@@ -68,6 +82,7 @@ mod fixtures {
         max_packet_amount: 1000,
         min_balance: Some(0),
         ilp_over_http_url: None,
+        ilp_over_http_client: None,
         ilp_over_http_incoming_token: None,
         ilp_over_http_outgoing_token: None,
         ilp_over_btp_url: None,
@@ -269,6 +284,7 @@ mod store_helpers {
     use interledger_api::NodeStore;
     use interledger_packet::Address;
     use interledger_service::{Account as AccountTrait, AddressStore};
+    use interledger_service_util::DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT;
     use interledger_store::{
         account::Account,
         redis::{RedisStore, RedisStoreBuilder},
@@ -276,9 +292,16 @@ mod store_helpers {
     use std::str::FromStr;
 
     pub async fn test_store() -> Result<(RedisStore, TestContext, Vec<Account>), ()> {
+        test_store_with_max_transactions(DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT).await
+    }
+
+    pub async fn test_store_with_max_transactions(
+        max_transactions_per_account: usize,
+    ) -> Result<(RedisStore, TestContext, Vec<Account>), ()> {
         let context = TestContext::new();
         let store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
             .node_ilp_address(Address::from_str("example.node").unwrap())
+            .max_transactions_per_account(max_transactions_per_account)
             .connect()
             .await
             .unwrap();
@@ -3,7 +3,7 @@ use super::{fixtures::*, redis_helpers::*, store_helpers::*};
 use interledger_api::{AccountDetails, NodeStore};
 use interledger_ccp::CcpRoutingStore;
 use interledger_packet::Address;
-use interledger_router::RouterStore;
+use interledger_router::{NextHop, Route, RouterStore};
 use interledger_service::{Account as AccountTrait, AddressStore, Username};
 use interledger_store::{account::Account, redis::RedisStoreBuilder};
 use std::str::FromStr;
@@ -30,7 +30,10 @@ async fn polls_for_route_updates() {
         .unwrap();
     let routing_table = store_clone_1.routing_table();
     assert_eq!(routing_table.len(), 1);
-    assert_eq!(*routing_table.get("example.alice").unwrap(), alice.id());
+    assert_eq!(
+        *routing_table.get("example.alice").unwrap(),
+        Route::from(alice.id())
+    );
     let bob = store_clone_1
         .insert_account(AccountDetails {
             ilp_address: Some(Address::from_str("example.bob").unwrap()),
@@ -40,6 +43,7 @@ async fn polls_for_route_updates() {
             max_packet_amount: 1000,
             min_balance: Some(-1000),
             ilp_over_http_url: None,
+            ilp_over_http_client: None,
             ilp_over_http_incoming_token: None,
             ilp_over_http_outgoing_token: None,
             ilp_over_btp_url: None,
@@ -58,7 +62,10 @@ async fn polls_for_route_updates() {
 
     let routing_table = store_clone_2.routing_table();
     assert_eq!(routing_table.len(), 2);
-    assert_eq!(*routing_table.get("example.bob").unwrap(), bob.id(),);
+    assert_eq!(
+        *routing_table.get("example.bob").unwrap(),
+        Route::from(bob.id())
+    );
     let alice_id = alice.id();
     let bob_id = bob.id();
     let mut connection = connection.await.unwrap();
@@ -75,9 +82,18 @@ async fn polls_for_route_updates() {
     tokio::time::delay_for(Duration::from_millis(10)).await;
     let routing_table = store_clone_2.routing_table();
     assert_eq!(routing_table.len(), 3);
-    assert_eq!(*routing_table.get("example.alice").unwrap(), bob_id);
-    assert_eq!(*routing_table.get("example.bob").unwrap(), bob.id(),);
-    assert_eq!(*routing_table.get("example.charlie").unwrap(), alice_id,);
+    assert_eq!(
+        *routing_table.get("example.alice").unwrap(),
+        Route::from(bob_id)
+    );
+    assert_eq!(
+        *routing_table.get("example.bob").unwrap(),
+        Route::from(bob.id())
+    );
+    assert_eq!(
+        *routing_table.get("example.charlie").unwrap(),
+        Route::from(alice_id)
+    );
     assert!(routing_table.get("example.other").is_none());
 }
 
@@ -164,9 +180,9 @@ async fn saves_routes_to_db() {
 
     // local routing table routes are also updated
     let routes = store.routing_table();
-    assert_eq!(routes["example.a"], account0_id);
-    assert_eq!(routes["example.b"], account0_id);
-    assert_eq!(routes["example.c"], account1_id);
+    assert_eq!(routes["example.a"], Route::from(account0_id));
+    assert_eq!(routes["example.b"], Route::from(account0_id));
+    assert_eq!(routes["example.c"], Route::from(account1_id));
     assert_eq!(routes.len(), 3);
 }
 
@@ -223,9 +239,9 @@ async fn static_routes_override_others() {
         .unwrap();
 
     let routes = store.routing_table();
-    assert_eq!(routes["example.a"], accs[0].id());
-    assert_eq!(routes["example.b"], accs[0].id());
-    assert_eq!(routes["example.c"], account1_id);
+    assert_eq!(routes["example.a"], Route::from(accs[0].id()));
+    assert_eq!(routes["example.b"], Route::from(accs[0].id()));
+    assert_eq!(routes["example.c"], Route::from(account1_id));
     assert_eq!(routes.len(), 3);
 }
 
@@ -250,9 +266,9 @@ async fn default_route() {
         .unwrap();
 
     let routes = store.routing_table();
-    assert_eq!(routes[""], accs[0].id());
-    assert_eq!(routes["example.a"], account1_id);
-    assert_eq!(routes["example.b"], account1_id);
+    assert_eq!(routes[""], Route::from(accs[0].id()));
+    assert_eq!(routes["example.a"], Route::from(account1_id));
+    assert_eq!(routes["example.b"], Route::from(account1_id));
     assert_eq!(routes.len(), 3);
 }
 
@@ -271,3 +287,34 @@ async fn returns_configured_routes_for_route_manager() {
     assert_eq!(configured["example.a"].id(), accs[0].id());
     assert_eq!(configured["example.b"].id(), accs[1].id());
 }
+
+#[tokio::test]
+async fn stores_static_routes_with_several_next_hops() {
+    let (store, context, accs) = test_store().await.unwrap();
+    let get_connection = context.async_connection();
+    let route = Route::new(vec![
+        NextHop {
+            account_id: accs[0].id(),
+            weight: Some(3),
+        },
+        NextHop {
+            account_id: accs[1].id(),
+            weight: Some(1),
+        },
+    ]);
+    store
+        .set_static_routes(vec![("example.multi".to_string(), route.clone())])
+        .await
+        .unwrap();
+    let mut connection = get_connection.await.unwrap();
+    let routes: HashMap<String, String> = redis_crate::cmd("HGETALL")
+        .arg("routes:static")
+        .query_async(&mut connection)
+        .await
+        .unwrap();
+    assert_eq!(
+        routes["example.multi"],
+        format!("{}:3,{}:1", accs[0].id(), accs[1].id())
+    );
+    assert_eq!(store.routing_table()["example.multi"], route);
+}
@@ -1,3 +1,4 @@
+use super::settlement;
 use super::store_helpers::*;
 use bytes::Bytes;
 
@@ -236,3 +237,9 @@ async fn loads_globally_configured_settlement_engine_url() {
         "http://settle-abc.example/"
     );
 }
+
+#[tokio::test]
+async fn peeks_uncredited_settlement_amount() {
+    let (store, _context, _accs) = test_store().await.unwrap();
+    settlement::peeks_uncredited_settlement_amount(&store).await;
+}
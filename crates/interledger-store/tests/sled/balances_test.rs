@@ -0,0 +1,133 @@
+use super::{fixtures::*, store_helpers::*};
+
+use interledger_api::NodeStore;
+use interledger_packet::Address;
+use interledger_service::{Account as AccountTrait, Username};
+use interledger_service_util::BalanceStore;
+use interledger_settlement::core::types::SettlementStore;
+use std::str::FromStr;
+
+#[tokio::test]
+async fn prepare_then_fulfill_with_settlement() {
+    let (store, _db, accs) = test_store().await.unwrap();
+    let account0_id = accs[0].id();
+    let account1_id = accs[1].id();
+    store
+        .update_balances_for_prepare(account0_id, 100)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(account0_id).await.unwrap(), -100);
+
+    // account 1 has settle_threshold 0 and settle_to -1000
+    let (balance, amount_to_settle) = store
+        .update_balances_for_fulfill(account1_id, 100)
+        .await
+        .unwrap();
+    assert_eq!(balance, -1000);
+    assert_eq!(amount_to_settle, 1100);
+}
+
+#[tokio::test]
+async fn prepare_then_reject() {
+    let (store, _db, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    store.update_balances_for_prepare(id, 100).await.unwrap();
+    store.update_balances_for_reject(id, 100).await.unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn enforces_minimum_balance() {
+    let (store, _db, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    let err = store
+        .update_balances_for_prepare(id, 10000)
+        .await
+        .unwrap_err();
+    let expected = format!("Incoming prepare of 10000 would bring account {} under its minimum balance. Current balance: 0, min balance: -1000", id);
+    assert!(err.to_string().contains(&expected));
+}
+
+#[tokio::test]
+async fn persists_balances_across_restarts() {
+    let (store, db, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    store.update_balances_for_prepare(id, 300).await.unwrap();
+    drop(store);
+
+    let store = db.open();
+    assert_eq!(store.get_balance(id).await.unwrap(), -300);
+}
+
+#[tokio::test]
+async fn persists_fulfills_and_settlements_across_restarts() {
+    let (store, db, accs) = test_store().await.unwrap();
+    let account0_id = accs[0].id();
+    let account1_id = accs[1].id();
+    store
+        .update_balances_for_prepare(account0_id, 100)
+        .await
+        .unwrap();
+    store
+        .update_balances_for_fulfill(account1_id, 100)
+        .await
+        .unwrap();
+    drop(store);
+
+    let store = db.open();
+    assert_eq!(store.get_balance(account0_id).await.unwrap(), -100);
+    // the settlement which was triggered by the fulfill is not sent again
+    assert_eq!(store.get_balance(account1_id).await.unwrap(), -1000);
+    let (balance, amount_to_settle) = store
+        .update_balances_for_delayed_settlement(account1_id)
+        .await
+        .unwrap();
+    assert_eq!(balance, -1000);
+    assert_eq!(amount_to_settle, 0);
+}
+
+#[tokio::test]
+async fn rejects_amounts_out_of_range() {
+    // without a minimum balance, nothing but the range of the balance limits the prepares
+    let acc = {
+        let mut acc = ACCOUNT_DETAILS_1.clone();
+        acc.username = Username::from_str("charlie").unwrap();
+        acc.ilp_address = Some(Address::from_str("example.c").unwrap());
+        acc.min_balance = None;
+        acc
+    };
+    let (store, _db, accs) = test_store().await.unwrap();
+    let account = store.insert_account(acc).await.unwrap();
+    for &id in &[accs[0].id(), account.id()] {
+        let err = store
+            .update_balances_for_prepare(id, u64::MAX)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("does not fit in the balance"));
+        assert_eq!(store.get_balance(id).await.unwrap(), 0);
+    }
+
+    let id = account.id();
+    assert!(store
+        .update_balances_for_fulfill(id, u64::MAX)
+        .await
+        .is_err());
+    assert!(store
+        .update_balances_for_reject(id, u64::MAX)
+        .await
+        .is_err());
+    assert!(store
+        .update_balance_for_incoming_settlement(id, u64::MAX, None)
+        .await
+        .is_err());
+    assert!(store.refund_settlement(id, u64::MAX).await.is_err());
+    assert_eq!(store.get_balance(id).await.unwrap(), 0);
+
+    // amounts which fit can still not take the balance past its range
+    store
+        .update_balances_for_prepare(id, i64::MAX as u64)
+        .await
+        .unwrap();
+    assert!(store.update_balances_for_prepare(id, 2).await.is_err());
+    assert_eq!(store.get_balance(id).await.unwrap(), -i64::MAX);
+}
@@ -0,0 +1,20 @@
+use super::rate_history;
+use super::store_helpers::*;
+
+#[tokio::test]
+async fn keeps_history_of_each_asset() {
+    let (store, _db, _accs) = test_store().await.unwrap();
+    rate_history::keeps_history_of_each_asset(&store).await;
+}
+
+#[tokio::test]
+async fn selects_rates_by_time() {
+    let (store, _db, _accs) = test_store().await.unwrap();
+    rate_history::selects_rates_by_time(&store).await;
+}
+
+#[tokio::test]
+async fn removes_the_rates_past_the_retention() {
+    let (store, _db, _accs) = test_store().await.unwrap();
+    rate_history::removes_the_rates_past_the_retention(&store).await;
+}
@@ -0,0 +1,75 @@
+use super::store_helpers::*;
+
+use interledger_api::NodeStore;
+use interledger_router::{NextHop, Route, RouterStore};
+use interledger_service::Account as AccountTrait;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn loads_routing_table_on_open() {
+    let (store, db, accs) = test_store().await.unwrap();
+    store
+        .set_static_route("example.static".to_string(), accs[1].id())
+        .await
+        .unwrap();
+    store.set_default_route(accs[0].id()).await.unwrap();
+    let routes = store.routing_table();
+    drop(store);
+
+    let store = db.open();
+    assert_eq!(store.routing_table(), routes);
+    assert_eq!(routes.len(), 4);
+    assert_eq!(routes[""], Route::from(accs[0].id()));
+    assert_eq!(routes["example.static"], Route::from(accs[1].id()));
+    assert_eq!(routes["example.alice.user1.bob"], Route::from(accs[1].id()));
+}
+
+#[tokio::test]
+async fn static_routes_require_existing_accounts() {
+    let (store, _db, accs) = test_store().await.unwrap();
+    store
+        .set_static_routes(vec![("example.a".to_string(), accs[0].id())])
+        .await
+        .unwrap();
+    let err = store
+        .set_static_routes(vec![
+            ("example.b".to_string(), accs[0].id()),
+            ("example.c".to_string(), Uuid::new_v4()),
+        ])
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "not all of the given accounts exist");
+    // the previous static routes are left in place
+    let routes = store.routing_table();
+    assert_eq!(routes["example.a"], Route::from(accs[0].id()));
+    assert!(routes.get("example.b").is_none());
+}
+
+#[tokio::test]
+async fn persists_static_routes_with_several_next_hops() {
+    let (store, db, accs) = test_store().await.unwrap();
+    let route = Route::new(vec![
+        NextHop {
+            account_id: accs[0].id(),
+            weight: Some(3),
+        },
+        NextHop {
+            account_id: accs[1].id(),
+            weight: Some(1),
+        },
+    ]);
+    store
+        .set_static_routes(vec![("example.multi".to_string(), route.clone())])
+        .await
+        .unwrap();
+    store
+        .set_static_route("example.single".to_string(), accs[1].id())
+        .await
+        .unwrap();
+    drop(store);
+
+    let store = db.open();
+    let routes = store.routing_table();
+    assert_eq!(routes["example.multi"], route);
+    assert_eq!(routes["example.single"], Route::from(accs[1].id()));
+}
@@ -0,0 +1,131 @@
+use super::settlement;
+use super::store_helpers::*;
+use bytes::Bytes;
+
+use http::StatusCode;
+use interledger_service::Account;
+use interledger_service_util::BalanceStore;
+use interledger_settlement::core::{
+    idempotency::{IdempotentData, IdempotentStore},
+    types::{LeftoversStore, SettlementStore},
+};
+use num_bigint::BigUint;
+use once_cell::sync::Lazy;
+use uuid::Uuid;
+
+static IDEMPOTENCY_KEY: Lazy<String> = Lazy::new(|| String::from("AJKJNUjM0oyiAN46"));
+
+#[tokio::test]
+async fn saves_gets_clears_uncredited_settlement_amount_properly() {
+    let (store, _db, _accs) = test_store().await.unwrap();
+    let amounts: Vec<(BigUint, u8)> = vec![
+        (BigUint::from(5u32), 11),   // 5
+        (BigUint::from(855u32), 12), // 905
+        (BigUint::from(1u32), 10),   // 1005 total
+    ];
+    let acc = Uuid::new_v4();
+    for a in amounts {
+        store
+            .save_uncredited_settlement_amount(acc, a)
+            .await
+            .unwrap();
+    }
+    let ret = store
+        .load_uncredited_settlement_amount(acc, 9u8)
+        .await
+        .unwrap();
+    // 1 uncredited unit for scale 9
+    assert_eq!(ret, BigUint::from(1u32));
+    // rest should be in the leftovers store
+    let ret = store.get_uncredited_settlement_amount(acc).await.unwrap();
+    assert_eq!(ret, (BigUint::from(5u32), 12));
+
+    store.clear_uncredited_settlement_amount(acc).await.unwrap();
+    let ret = store.get_uncredited_settlement_amount(acc).await.unwrap();
+    assert_eq!(ret, (BigUint::from(0u32), 0));
+}
+
+#[tokio::test]
+async fn saves_and_loads_idempotency_key_data_properly() {
+    let (store, _db, _) = test_store().await.unwrap();
+    let input_hash: [u8; 32] = Default::default();
+    store
+        .save_idempotent_data(
+            IDEMPOTENCY_KEY.clone(),
+            input_hash,
+            StatusCode::OK,
+            Bytes::from("TEST"),
+        )
+        .await
+        .unwrap();
+    let data = store
+        .load_idempotent_data(IDEMPOTENCY_KEY.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        data.unwrap(),
+        IdempotentData::new(StatusCode::OK, Bytes::from("TEST"), input_hash)
+    );
+    let data = store
+        .load_idempotent_data("asdf".to_string())
+        .await
+        .unwrap();
+    assert!(data.is_none());
+}
+
+#[tokio::test]
+async fn idempotent_settlement_calls() {
+    let (store, db, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    store
+        .update_balance_for_incoming_settlement(id, 100, Some(IDEMPOTENCY_KEY.clone()))
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), 100);
+    drop(store);
+
+    // the idempotency key survives a restart
+    let store = db.open();
+    store
+        .update_balance_for_incoming_settlement(id, 100, Some(IDEMPOTENCY_KEY.clone()))
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), 100);
+}
+
+#[tokio::test]
+async fn credits_balance_owed_then_prepaid_amount() {
+    let (store, _db, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    store.update_balances_for_prepare(id, 200).await.unwrap();
+    store
+        .update_balance_for_incoming_settlement(id, 100, None)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), -100);
+    store
+        .update_balance_for_incoming_settlement(id, 160, None)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), 60);
+
+    // prepares use up the prepaid amount first
+    store.update_balances_for_prepare(id, 1060).await.unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), -1000);
+}
+
+#[tokio::test]
+async fn refunds_settlement() {
+    let (store, _db, accs) = test_store().await.unwrap();
+    let id = accs[1].id();
+    let (_, amount_to_settle) = store.update_balances_for_fulfill(id, 10).await.unwrap();
+    assert_eq!(amount_to_settle, 1010);
+    store.refund_settlement(id, amount_to_settle).await.unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), 10);
+}
+
+#[tokio::test]
+async fn peeks_uncredited_settlement_amount() {
+    let (store, _db, _accs) = test_store().await.unwrap();
+    settlement::peeks_uncredited_settlement_amount(&store).await;
+}
@@ -0,0 +1,102 @@
+mod accounts_test;
+mod balances_test;
+mod rate_history_test;
+mod routing_test;
+mod settlement_test;
+mod stream_connections_test;
+mod transactions_test;
+
+#[path = "../common/fixtures.rs"]
+mod fixtures;
+#[path = "../common/rate_history.rs"]
+mod rate_history;
+#[path = "../common/settlement.rs"]
+mod settlement;
+#[path = "../common/stream_connections.rs"]
+mod stream_connections;
+#[path = "../common/transactions.rs"]
+mod transactions;
+
+mod store_helpers {
+    use super::fixtures::*;
+
+    use interledger_api::NodeStore;
+    use interledger_packet::Address;
+    use interledger_service::{Account as AccountTrait, AddressStore};
+    use interledger_service_util::DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT;
+    use interledger_store::{
+        account::Account,
+        sled::{SledStore, SledStoreBuilder},
+    };
+    use std::{env, fs, path::PathBuf, str::FromStr};
+    use uuid::Uuid;
+
+    /// A temporary database directory which is removed when dropped
+    pub struct TestDb {
+        pub path: PathBuf,
+        max_transactions_per_account: usize,
+    }
+
+    impl TestDb {
+        pub fn new() -> Self {
+            Self::with_max_transactions(DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT)
+        }
+
+        pub fn with_max_transactions(max_transactions_per_account: usize) -> Self {
+            let mut path = env::temp_dir();
+            path.push(format!("ilp-sled-test-{}", Uuid::new_v4()));
+            TestDb {
+                path,
+                max_transactions_per_account,
+            }
+        }
+
+        pub fn open(&self) -> SledStore {
+            self.open_with_secret([0; 32])
+        }
+
+        pub fn open_with_secret(&self, secret: [u8; 32]) -> SledStore {
+            SledStoreBuilder::new(&self.path, secret)
+                .node_ilp_address(Address::from_str("example.node").unwrap())
+                .max_transactions_per_account(self.max_transactions_per_account)
+                .open()
+                .unwrap()
+        }
+    }
+
+    impl Drop for TestDb {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.path).ok();
+        }
+    }
+
+    pub async fn test_store() -> Result<(SledStore, TestDb, Vec<Account>), ()> {
+        test_store_with_max_transactions(DEFAULT_MAX_TRANSACTIONS_PER_ACCOUNT).await
+    }
+
+    pub async fn test_store_with_max_transactions(
+        max_transactions_per_account: usize,
+    ) -> Result<(SledStore, TestDb, Vec<Account>), ()> {
+        let db = TestDb::with_max_transactions(max_transactions_per_account);
+        let store = db.open();
+        let mut accs = Vec::new();
+        let acc = store
+            .insert_account(ACCOUNT_DETAILS_0.clone())
+            .await
+            .unwrap();
+        accs.push(acc.clone());
+        // alice is a Parent, so the store's ilp address is updated to
+        // the value that would be received by the ILDCP request
+        store
+            .set_ilp_address(acc.ilp_address().with_suffix(b"user1").unwrap())
+            .await
+            .unwrap();
+
+        let acc = store
+            .insert_account(ACCOUNT_DETAILS_1.clone())
+            .await
+            .unwrap();
+        accs.push(acc);
+        Ok((store, db, accs))
+    }
+}
@@ -0,0 +1,20 @@
+use super::store_helpers::*;
+use super::stream_connections;
+
+#[tokio::test]
+async fn tracks_total_received_per_connection() {
+    let (store, _db, _accs) = test_store().await.unwrap();
+    stream_connections::tracks_total_received_per_connection(&store).await;
+}
+
+#[tokio::test]
+async fn enforces_receive_max() {
+    let (store, _db, _accs) = test_store().await.unwrap();
+    stream_connections::enforces_receive_max(&store).await;
+}
+
+#[tokio::test]
+async fn closes_connections_once() {
+    let (store, _db, _accs) = test_store().await.unwrap();
+    stream_connections::closes_connections_once(&store).await;
+}
@@ -0,0 +1,259 @@
+use bytes::{Bytes, BytesMut};
+use std::collections::BTreeMap;
+
+/// Reassembles the data received on a stream so it can be read in order.
+///
+/// Data may arrive out of order or more than once, so chunks are kept by offset
+/// until everything before them has been read. Data beyond the receive window
+/// (what was read so far plus `window` bytes) is refused.
+pub(crate) struct ReceiveBuffer {
+    /// Chunks which have not been read yet, keyed by their offset
+    chunks: BTreeMap<u64, Bytes>,
+    /// Offset up to which the application has read
+    read_offset: u64,
+    /// Number of bytes we are willing to buffer past the read offset
+    window: u64,
+}
+
+/// Error returned when the remote sends data beyond the window it was given
+#[derive(Debug, PartialEq)]
+pub(crate) struct WindowExceeded;
+
+impl ReceiveBuffer {
+    pub fn new(window: u64) -> Self {
+        ReceiveBuffer {
+            chunks: BTreeMap::new(),
+            read_offset: 0,
+            window,
+        }
+    }
+
+    /// The offset up to which the remote may send data
+    pub fn max_offset(&self) -> u64 {
+        self.read_offset.saturating_add(self.window)
+    }
+
+    /// Stores a chunk of data received at the given offset
+    pub fn push(&mut self, offset: u64, data: &[u8]) -> Result<(), WindowExceeded> {
+        let end = offset.saturating_add(data.len() as u64);
+        if end > self.max_offset() {
+            return Err(WindowExceeded);
+        }
+        if end <= self.read_offset || data.is_empty() {
+            // Already read (the remote resent it)
+            return Ok(());
+        }
+        let (offset, data) = if offset < self.read_offset {
+            let skip = (self.read_offset - offset) as usize;
+            (self.read_offset, &data[skip..])
+        } else {
+            (offset, data)
+        };
+        // Only store the parts which no chunk covers yet, so chunks never overlap
+        // and at most a window's worth of data is buffered
+        let mut start = offset;
+        while start < end {
+            if let Some((chunk_offset, chunk)) = self.chunks.range(..=start).next_back() {
+                let chunk_end = chunk_offset + chunk.len() as u64;
+                if chunk_end > start {
+                    start = chunk_end;
+                    continue;
+                }
+            }
+            let gap_end = self
+                .chunks
+                .range(start..)
+                .next()
+                .map(|(next_offset, _)| (*next_offset).min(end))
+                .unwrap_or(end);
+            let chunk = &data[(start - offset) as usize..(gap_end - offset) as usize];
+            self.chunks.insert(start, Bytes::copy_from_slice(chunk));
+            start = gap_end;
+        }
+        Ok(())
+    }
+
+    /// Whether there is data which can be read right away
+    pub fn is_readable(&self) -> bool {
+        self.chunks
+            .keys()
+            .next()
+            .map(|offset| *offset <= self.read_offset)
+            .unwrap_or(false)
+    }
+
+    /// Copies the contiguous data after the read offset into `buf`
+    /// and returns the number of bytes copied
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut copied = 0;
+        while copied < buf.len() {
+            let (offset, chunk) = match self.chunks.iter().next() {
+                Some((offset, chunk)) if *offset <= self.read_offset => (*offset, chunk),
+                _ => break,
+            };
+            let start = (self.read_offset - offset) as usize;
+            if start >= chunk.len() {
+                // Overlaps data which was already read
+                self.chunks.remove(&offset);
+                continue;
+            }
+            let len = (chunk.len() - start).min(buf.len() - copied);
+            buf[copied..copied + len].copy_from_slice(&chunk[start..start + len]);
+            copied += len;
+            self.read_offset += len as u64;
+            if start + len == chunk.len() {
+                self.chunks.remove(&offset);
+            }
+        }
+        copied
+    }
+}
+
+/// Data written to a stream which the remote has not acknowledged yet
+pub(crate) struct SendBuffer {
+    /// Unacknowledged data, starting at `acked_offset`
+    data: BytesMut,
+    /// Offset up to which the remote acknowledged the data
+    acked_offset: u64,
+    /// Offset up to which data was put in packets
+    sent_offset: u64,
+}
+
+impl SendBuffer {
+    pub fn new() -> Self {
+        SendBuffer {
+            data: BytesMut::new(),
+            acked_offset: 0,
+            sent_offset: 0,
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) {
+        self.data.extend_from_slice(data);
+    }
+
+    /// Number of bytes written but not yet acknowledged
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Whether all of the written data was put in packets
+    pub fn is_sent(&self) -> bool {
+        self.sent_offset == self.acked_offset + self.data.len() as u64
+    }
+
+    /// Takes the next chunk of at most `max_len` bytes which fits below the remote's `max_offset`
+    pub fn next_chunk(&mut self, max_offset: u64, max_len: usize) -> Option<(u64, Bytes)> {
+        let end = (self.acked_offset + self.data.len() as u64)
+            .min(max_offset)
+            .min(self.sent_offset + max_len as u64);
+        if end <= self.sent_offset {
+            return None;
+        }
+        let start = (self.sent_offset - self.acked_offset) as usize;
+        let stop = (end - self.acked_offset) as usize;
+        let chunk = Bytes::copy_from_slice(&self.data[start..stop]);
+        let offset = self.sent_offset;
+        self.sent_offset = end;
+        Some((offset, chunk))
+    }
+
+    /// Drops the data which was sent, because the remote received it
+    pub fn acknowledge(&mut self) {
+        let sent = (self.sent_offset - self.acked_offset) as usize;
+        let _ = self.data.split_to(sent);
+        self.acked_offset = self.sent_offset;
+    }
+
+    /// Marks the data which was sent but not acknowledged as unsent, so it will be sent again
+    pub fn rewind(&mut self) {
+        self.sent_offset = self.acked_offset;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_data_in_order() {
+        let mut buffer = ReceiveBuffer::new(100);
+        buffer.push(5, b"world").unwrap();
+        assert!(!buffer.is_readable());
+        buffer.push(0, b"hello").unwrap();
+        // Resent data is ignored
+        buffer.push(0, b"hello").unwrap();
+
+        let mut buf = [0; 7];
+        assert_eq!(buffer.read(&mut buf), 7);
+        assert_eq!(&buf, b"hellowo");
+        assert_eq!(buffer.max_offset(), 107);
+        // Partially read data which overlaps the read offset
+        buffer.push(5, b"world!").unwrap();
+        assert_eq!(buffer.read(&mut buf), 4);
+        assert_eq!(&buf[..4], b"rld!");
+        assert!(!buffer.is_readable());
+    }
+
+    #[test]
+    fn refuses_data_beyond_the_window() {
+        let mut buffer = ReceiveBuffer::new(4);
+        assert_eq!(buffer.push(2, b"abc"), Err(WindowExceeded));
+        buffer.push(0, b"abcd").unwrap();
+        assert_eq!(buffer.read(&mut [0; 2]), 2);
+        buffer.push(4, b"ef").unwrap();
+    }
+
+    #[test]
+    fn overlapping_data_is_buffered_once() {
+        let mut buffer = ReceiveBuffer::new(100);
+        buffer.push(2, b"cd").unwrap();
+        buffer.push(6, b"gh").unwrap();
+        // Spans both chunks and the gaps around them
+        buffer.push(1, b"bcdefghi").unwrap();
+        for offset in 1..50 {
+            buffer
+                .push(offset, &[b'x'; 10][..10 - (offset % 10) as usize])
+                .unwrap();
+        }
+        let buffered: usize = buffer.chunks.values().map(|chunk| chunk.len()).sum();
+        // Offsets 1 to 49, each stored once
+        assert_eq!(buffered, 49);
+
+        buffer.push(0, b"a").unwrap();
+        let mut buf = [0; 9];
+        assert_eq!(buffer.read(&mut buf), 9);
+        assert_eq!(&buf, b"abcdefghi");
+    }
+
+    #[test]
+    fn resends_unacknowledged_data() {
+        let mut buffer = SendBuffer::new();
+        buffer.write(b"hello world");
+        assert_eq!(
+            buffer.next_chunk(100, 5),
+            Some((0, Bytes::from_static(b"hello")))
+        );
+        buffer.acknowledge();
+        assert_eq!(buffer.len(), 6);
+
+        // Limited by the remote's max offset
+        assert_eq!(
+            buffer.next_chunk(8, 100),
+            Some((5, Bytes::from_static(b" wo")))
+        );
+        assert_eq!(buffer.next_chunk(8, 100), None);
+        buffer.rewind();
+        assert_eq!(
+            buffer.next_chunk(100, 100),
+            Some((5, Bytes::from_static(b" world")))
+        );
+        assert!(buffer.is_sent());
+        buffer.acknowledge();
+        assert!(buffer.is_empty());
+    }
+}
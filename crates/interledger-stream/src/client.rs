@@ -2,6 +2,7 @@ use super::congestion::CongestionController;
 use super::crypto::*;
 use super::error::Error;
 use super::packet::*;
+use super::receipt::Receipt;
 use bytes::Bytes;
 use bytes::BytesMut;
 use futures::stream::{FuturesUnordered, StreamExt};
@@ -18,7 +19,8 @@ use num::traits::ops::checked::CheckedDiv;
 use num::traits::pow::pow;
 use num::BigInt;
 use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, watch, Mutex};
+use tokio::task::JoinHandle;
 use tokio::time::timeout_at;
 use tokio::time::{Duration, Instant};
 use tracing::{debug, error, warn};
@@ -39,6 +41,13 @@ const FAIL_FAST_MINIMUM_PACKET_ATTEMPTS: u64 = 200;
 /// Minimum rate of rejected packets in order to terminate the payment
 const FAIL_FAST_MINIMUM_FAILURE_RATE: f64 = 0.99;
 
+/// Source amount of the first unfulfillable test packet sent to discover the exchange rate.
+/// Smaller test packets are tried if it doesn't reach the recipient.
+const MAX_PROBE_AMOUNT: u64 = 1_000_000_000_000;
+
+/// Number of progress events kept for subscribers that fall behind
+const PROGRESS_CHANNEL_CAPACITY: usize = 64;
+
 /// Receipt for STREAM payment to account for how much and what assets were sent & delivered
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct StreamDelivery {
@@ -64,6 +73,11 @@ pub struct StreamDelivery {
     /// Receiver's asset code
     /// Updated after we received a `ConnectionAssetDetails` frame.
     pub destination_asset_code: Option<String>,
+    /// The STREAM receipt with the highest total received, if the receiver sent any.
+    /// It can be checked with [`Receipt::verify`](./struct.Receipt.html#method.verify)
+    /// by whoever knows the connection's receipt secret.
+    #[serde(default, with = "serde_base64_option")]
+    pub stream_receipt: Option<Vec<u8>>,
 }
 
 impl StreamDelivery {
@@ -79,10 +93,127 @@ impl StreamDelivery {
             destination_asset_scale: None,
             destination_asset_code: None,
             delivered_amount: 0,
+            stream_receipt: None,
         }
     }
 }
 
+/// Quote for delivering a fixed destination amount, based on the exchange rate
+/// discovered by sending unfulfillable test packets to the recipient
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct StreamQuote {
+    /// Receiver's ILP Address
+    pub to: Address,
+    /// Exact amount to deliver to the recipient, in destination units
+    pub destination_amount: u64,
+    /// Maximum amount that may be sent to deliver the destination amount, in source units.
+    /// The payment fails instead of sending more than this.
+    pub max_source_amount: u64,
+    /// Source amount of the test packet used to discover the exchange rate
+    pub probe_source_amount: u64,
+    /// Amount the recipient said it would have received for the test packet, in destination units
+    pub probe_destination_amount: u64,
+    /// Receiver's asset scale, if it told us
+    pub destination_asset_scale: Option<u8>,
+    /// Receiver's asset code, if it told us
+    pub destination_asset_code: Option<String>,
+}
+
+impl StreamQuote {
+    /// Source amount expected to deliver the given destination amount at the probed exchange rate
+    #[inline]
+    fn expected_source_amount(&self, destination_amount: u64) -> u64 {
+        let source_amount = (u128::from(destination_amount) * u128::from(self.probe_source_amount)
+            + u128::from(self.probe_destination_amount)
+            - 1)
+            / u128::from(self.probe_destination_amount);
+        min(source_amount, u128::from(std::u64::MAX)) as u64
+    }
+
+    /// Lowest exchange rate each packet must get for the maximum source amount to cover the delivery
+    #[inline]
+    fn min_rate(&self) -> BigRational {
+        BigRational::new(
+            BigInt::from(self.destination_amount),
+            BigInt::from(self.max_source_amount),
+        )
+    }
+}
+
+/// Quote for sending a fixed source amount, based on the exchange rate
+/// discovered by sending unfulfillable test packets to the recipient
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StreamSourceQuote {
+    /// Receiver's ILP Address
+    pub to: Address,
+    /// Amount to send, in source units
+    pub source_amount: u64,
+    /// Amount the source amount would deliver at the probed exchange rate, in destination units
+    pub estimated_destination_amount: u64,
+    /// Amount the source amount delivers at least at the minimum exchange rate, in destination units
+    pub min_destination_amount: u64,
+    /// Probed exchange rate minus slippage, in destination units per source unit.
+    /// A payment sent with this minimum rate fails instead of accepting a worse one
+    pub min_exchange_rate: f64,
+    /// Source amount of the test packet used to discover the exchange rate
+    pub probe_source_amount: u64,
+    /// Amount the recipient said it would have received for the test packet, in destination units
+    pub probe_destination_amount: u64,
+    /// Receiver's asset scale, if it told us
+    pub destination_asset_scale: Option<u8>,
+    /// Receiver's asset code, if it told us
+    pub destination_asset_code: Option<String>,
+}
+
+/// Progress of a STREAM payment, emitted each time a packet is fulfilled
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PaymentProgress {
+    /// Number of packets fulfilled so far
+    pub fulfilled_packets: u64,
+    /// Number of packets rejected so far
+    pub rejected_packets: u64,
+    /// Amount fulfilled or currently in-flight, in source units
+    pub sent_amount: u64,
+    /// Amount fulfilled and received by the recipient, in destination units
+    pub delivered_amount: u64,
+    /// Exchange rate of the last fulfilled packet, in destination units per source unit
+    pub rate: f64,
+}
+
+/// Lifecycle of a payment started with [`start_payment`](./fn.start_payment.html)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentStatus {
+    /// Packets are being sent
+    Sending,
+    /// No new packets are sent until the payment is resumed
+    Paused,
+    /// The full amount was delivered
+    Completed,
+    /// The payment was cancelled before the full amount was delivered
+    Cancelled,
+    /// The payment stopped because of an error
+    Failed,
+}
+
+/// Snapshot of a payment started with [`start_payment`](./fn.start_payment.html)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PaymentState {
+    pub status: PaymentStatus,
+    /// Current receipt, which may be persisted to [resume](./fn.resume_payment.html) the payment later
+    pub delivery: StreamDelivery,
+    /// Why the payment failed, if it did
+    pub error: Option<String>,
+}
+
+/// Requested by the payment's handle, checked before sending each packet
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum PaymentControl {
+    Send,
+    Pause,
+    Cancel,
+}
+
 /// Stream payment mutable state: amounts & assets sent and received, sequence, packet counts, and flow control parameters
 struct StreamPayment {
     /// The [congestion controller](./../congestion/struct.CongestionController.html) to adjust flow control and the in-flight amount
@@ -101,9 +232,60 @@ struct StreamPayment {
     fail_fast_rejects: u64,
     /// Timestamp when a packet was last fulfilled for this payment
     last_fulfill_time: Instant,
+    /// Did the recipient close the connection, for example because it received all it accepts?
+    closed_by_receiver: bool,
+    /// For fixed-delivery payments, the quote whose destination amount must be delivered
+    quote: Option<StreamQuote>,
+    /// Lowest exchange rate the packets must get, such as a quoted one,
+    /// instead of the rate of the store minus slippage
+    min_rate: Option<BigRational>,
+    /// Where the payment is in its lifecycle
+    status: PaymentStatus,
+    /// Why the payment failed, if it did
+    error: Option<String>,
 }
 
 impl StreamPayment {
+    fn new<A: Account>(from_account: &A, destination: Address, source_amount: u64) -> Self {
+        StreamPayment {
+            // TODO Make configurable to get money flowing ASAP vs as much as possible per-packet
+            congestion_controller: CongestionController::new(
+                source_amount,
+                source_amount / 10,
+                2.0,
+            ),
+            receipt: StreamDelivery::new(from_account, destination, source_amount),
+            should_send_source_account: true,
+            sequence: 1,
+            fulfilled_packets: 0,
+            rejected_packets: 0,
+            fail_fast_rejects: 0,
+            last_fulfill_time: Instant::now(),
+            closed_by_receiver: false,
+            quote: None,
+            min_rate: None,
+            status: PaymentStatus::Sending,
+            error: None,
+        }
+    }
+
+    /// Switch to delivering the quoted destination amount, spending at most its maximum source amount.
+    /// Packets sent before, such as the quote's test packets, don't count towards the payment.
+    fn set_quote(&mut self, quote: StreamQuote) {
+        let source_amount = quote.max_source_amount;
+        self.congestion_controller =
+            CongestionController::new(source_amount, source_amount / 10, 2.0);
+        self.receipt.source_amount = source_amount;
+        self.receipt.sent_amount = 0;
+        self.receipt.in_flight_amount = 0;
+        self.receipt.delivered_amount = 0;
+        self.fulfilled_packets = 0;
+        self.rejected_packets = 0;
+        self.fail_fast_rejects = 0;
+        self.last_fulfill_time = Instant::now();
+        self.quote = Some(quote);
+    }
+
     /// Determine amount to load in next Prepare and account for it.
     /// Return the source packet amount and minimum destination amount
     #[inline]
@@ -111,15 +293,20 @@ impl StreamPayment {
         // Determine scaled rate with slippage used for enforcing minimum destination amount
         // and computing its corresponding minimum source amount,
         // where source_amount * scaled_rate = dest_amount.
-        let rate = get_rate(
-            store,
-            self.receipt.source_asset_scale,
-            &self.receipt.source_asset_code,
-            self.receipt.destination_asset_scale,
-            self.receipt.destination_asset_code.as_deref(),
-            slippage,
-        )
-        .unwrap_or_else(BigRational::zero);
+        // Fixed-delivery payments and payments with a minimum rate enforce that rate instead.
+        let rate = match (&self.quote, &self.min_rate) {
+            (Some(quote), _) => quote.min_rate(),
+            (None, Some(min_rate)) => min_rate.clone(),
+            (None, None) => get_rate(
+                store,
+                self.receipt.source_asset_scale,
+                &self.receipt.source_asset_code,
+                self.receipt.destination_asset_scale,
+                self.receipt.destination_asset_code.as_deref(),
+                slippage,
+            )
+            .unwrap_or_else(BigRational::zero),
+        };
 
         // Margin of error is the minimum difference between our scaled rate and scaled rate of intermediaries.
         // This should probably be much smaller than the slippage we're willing to accept.
@@ -184,6 +371,24 @@ impl StreamPayment {
         (source_amount, min_destination_amount)
     }
 
+    /// Continue from a persisted receipt.
+    /// Amounts that were in flight count as sent, but not delivered
+    fn set_delivery(&mut self, delivery: StreamDelivery) {
+        self.receipt = delivery;
+        self.receipt.in_flight_amount = 0;
+    }
+
+    /// Summarize the payment's progress after a packet was fulfilled with the given amounts
+    fn progress(&self, source_amount: u64, destination_amount: u64) -> PaymentProgress {
+        PaymentProgress {
+            fulfilled_packets: self.fulfilled_packets,
+            rejected_packets: self.rejected_packets,
+            sent_amount: self.receipt.sent_amount,
+            delivered_amount: self.receipt.delivered_amount,
+            rate: destination_amount as f64 / source_amount as f64,
+        }
+    }
+
     /// Account for a fulfilled packet and update flow control
     #[inline]
     fn apply_fulfill(&mut self, source_amount: u64, destination_amount: u64) {
@@ -229,6 +434,28 @@ impl StreamPayment {
         self.receipt.destination_asset_scale = Some(asset_scale);
     }
 
+    /// Keep the receipt if it proves a higher total than the one we have
+    #[inline]
+    fn apply_receipt(&mut self, receipt: &[u8]) {
+        let total_received = match Receipt::decode(receipt) {
+            Ok(decoded) => decoded.total_received,
+            Err(err) => {
+                warn!("Ignoring invalid STREAM receipt: {}", err);
+                return;
+            }
+        };
+        let is_latest = self
+            .receipt
+            .stream_receipt
+            .as_ref()
+            .and_then(|latest| Receipt::decode(latest).ok())
+            .map(|latest| latest.total_received < total_received)
+            .unwrap_or(true);
+        if is_latest {
+            self.receipt.stream_receipt = Some(receipt.to_vec());
+        }
+    }
+
     /// Return the current sequence number and increment the value for subsequent packets
     #[inline]
     fn next_sequence(&mut self) -> u64 {
@@ -254,18 +481,46 @@ impl StreamPayment {
     }
 
     /// Has the entire intended source amount been fulfilled by the recipient?
+    /// (For fixed-delivery payments: has the quoted destination amount been delivered?)
     #[inline]
     fn is_complete(&self) -> bool {
-        self.get_remaining_amount() == 0
+        match &self.quote {
+            Some(quote) => self.receipt.delivered_amount >= quote.destination_amount,
+            None => self.get_remaining_amount() == 0,
+        }
     }
 
     /// Return the amount of money available to be sent in the payment (amount remaining minus in-flight)
     #[inline]
     fn get_amount_available_to_send(&self) -> u64 {
         // Sent amount also includes the amount in-flight, which should be subtracted from the amount available
-        self.receipt
+        let available = self
+            .receipt
             .source_amount
-            .saturating_sub(self.receipt.sent_amount)
+            .saturating_sub(self.receipt.sent_amount);
+
+        // Don't send more than what is expected to deliver the rest of the quoted amount
+        match &self.quote {
+            Some(quote) => {
+                let remaining_destination_amount = quote
+                    .destination_amount
+                    .saturating_sub(self.receipt.delivered_amount);
+                let expected_source_amount = quote
+                    .expected_source_amount(remaining_destination_amount)
+                    .saturating_sub(self.receipt.in_flight_amount);
+                min(available, expected_source_amount)
+            }
+            None => available,
+        }
+    }
+
+    /// Has the maximum source amount been spent without completing the payment?
+    /// (Only possible for fixed-delivery payments, if the amounts delivered are below the expected rate)
+    #[inline]
+    fn is_budget_exhausted(&self) -> bool {
+        self.receipt.in_flight_amount == 0
+            && self.get_amount_available_to_send() == 0
+            && !self.is_complete()
     }
 
     /// Is as much money as possible in-flight?
@@ -303,40 +558,330 @@ where
     A: Account + Send + Sync + 'static,
     S: ExchangeRateStore + Send + Sync + 'static,
 {
-    let shared_secret = Bytes::from(shared_secret);
-
-    let from = from_account.ilp_address();
-    if from.scheme() != destination_account.scheme() {
-        warn!(
-            "Destination ILP address starts with a different scheme prefix (\"{}\') than ours (\"{}\'), this probably won't work",
-            destination_account.scheme(),
-            from.scheme()
-        );
+    let sender = StreamSender::new(
+        service,
+        from_account,
+        store,
+        destination_account,
+        shared_secret,
+        source_amount,
+        slippage,
+    );
+
+    let (_control, control) = watch::channel(PaymentControl::Send);
+    run_payment(sender, control).await
+}
+
+/// Discover the exchange rate to the recipient with unfulfillable test packets
+/// and quote the maximum source amount needed to deliver the given destination amount.
+/// No money is sent and the connection is left open, so the quote may be paid with the same shared secret.
+pub async fn quote_fixed_delivery<I, A, S>(
+    service: I,
+    from_account: &A,
+    store: S,
+    destination_account: Address,
+    shared_secret: Vec<u8>,
+    destination_amount: u64,
+    slippage: f64,
+) -> Result<StreamQuote, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    let mut sender = StreamSender::new(
+        service,
+        from_account,
+        store,
+        destination_account,
+        shared_secret,
+        0,
+        slippage,
+    );
+    sender.quote(destination_amount).await
+}
+
+/// Discover the exchange rate to the recipient with unfulfillable test packets
+/// and quote what sending the given source amount should deliver.
+/// No money is sent. The quote's minimum exchange rate can then be enforced by paying it
+/// with [`send_money_with_min_rate`](./fn.send_money_with_min_rate.html)
+pub async fn quote_fixed_source<I, A, S>(
+    service: I,
+    from_account: &A,
+    store: S,
+    destination_account: Address,
+    shared_secret: Vec<u8>,
+    source_amount: u64,
+    slippage: f64,
+) -> Result<StreamSourceQuote, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    let mut sender = StreamSender::new(
+        service,
+        from_account,
+        store,
+        destination_account,
+        shared_secret,
+        0,
+        slippage,
+    );
+    sender.quote_source(source_amount).await
+}
+
+/// Send the given source amount like [`send_money`](./fn.send_money.html), but enforce the given
+/// exchange rate, in destination units per source unit, instead of the rate of the store minus slippage.
+/// The payment fails instead of accepting a worse rate, for example to honor the minimum rate of a quote
+pub async fn send_money_with_min_rate<I, A, S>(
+    service: I,
+    from_account: &A,
+    store: S,
+    destination_account: Address,
+    shared_secret: Vec<u8>,
+    source_amount: u64,
+    min_exchange_rate: f64,
+) -> Result<StreamDelivery, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    let sender = StreamSender::with_min_rate(
+        service,
+        from_account,
+        store,
+        destination_account,
+        shared_secret,
+        source_amount,
+        min_exchange_rate,
+    )?;
+
+    let (_control, control) = watch::channel(PaymentControl::Send);
+    run_payment(sender, control).await
+}
+
+/// Deliver exactly the given destination amount with packetized Interledger payments using the STREAM transport protocol.
+/// The exchange rate is first discovered with unfulfillable test packets, and the payment then fails
+/// instead of accepting a rate worse than the quoted one (minus slippage).
+/// Returns the receipt, where the source amount is the quoted maximum source amount
+pub async fn send_money_fixed_delivery<I, A, S>(
+    service: I,
+    from_account: &A,
+    store: S,
+    destination_account: Address,
+    shared_secret: Vec<u8>,
+    destination_amount: u64,
+    slippage: f64,
+) -> Result<StreamDelivery, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    let mut sender = StreamSender::new(
+        service,
+        from_account,
+        store,
+        destination_account,
+        shared_secret,
+        0,
+        slippage,
+    );
+    let quote = sender.quote(destination_amount).await?;
+    debug!(
+        "Quoted a maximum of {} to deliver {}",
+        quote.max_source_amount, quote.destination_amount
+    );
+    sender.payment.lock().await.set_quote(quote);
+
+    let (_control, control) = watch::channel(PaymentControl::Send);
+    run_payment(sender, control).await
+}
+
+/// Handle to a STREAM payment running in the background, to follow its progress,
+/// pause, resume or cancel it, and get the receipt once it's finished
+pub struct PaymentHandle {
+    /// Pauses, resumes or cancels the payment
+    control: watch::Sender<PaymentControl>,
+    /// Publishes the payment's progress
+    progress: broadcast::Sender<PaymentProgress>,
+    /// Mutable payment state shared with the task sending the packets
+    payment: Arc<Mutex<StreamPayment>>,
+    /// Task sending the packets
+    task: JoinHandle<Result<StreamDelivery, Error>>,
+}
+
+impl PaymentHandle {
+    /// Subscribe to the progress events emitted each time a packet is fulfilled
+    pub fn subscribe(&self) -> broadcast::Receiver<PaymentProgress> {
+        self.progress.subscribe()
     }
 
-    let mut sender = StreamSender {
-        next: service,
-        from_account: from_account.clone(),
+    /// Stop sending new packets once the ones in flight are done, until the payment is resumed
+    pub fn pause(&self) {
+        self.control.broadcast(PaymentControl::Pause).ok();
+    }
+
+    /// Continue sending a paused payment
+    pub fn resume(&self) {
+        self.control.broadcast(PaymentControl::Send).ok();
+    }
+
+    /// Stop the payment and close the connection once the packets in flight are done.
+    /// The payment then finishes with the receipt of what was delivered until then
+    pub fn cancel(&self) {
+        self.control.broadcast(PaymentControl::Cancel).ok();
+    }
+
+    /// Current status and receipt of the payment
+    pub async fn state(&self) -> PaymentState {
+        let payment = self.payment.lock().await;
+        PaymentState {
+            status: payment.status,
+            delivery: payment.receipt.clone(),
+            error: payment.error.clone(),
+        }
+    }
+
+    /// Wait for the payment to complete, fail or be cancelled and return its receipt
+    pub async fn finish(self) -> Result<StreamDelivery, Error> {
+        self.task
+            .await
+            .map_err(|err| Error::SendMoneyError(format!("Payment task failed: {}", err)))?
+    }
+}
+
+/// Start sending the given source amount in the background, like [`send_money`](./fn.send_money.html),
+/// and return a handle to control the payment and follow its progress
+pub fn start_payment<I, A, S>(
+    service: I,
+    from_account: &A,
+    store: S,
+    destination_account: Address,
+    shared_secret: Vec<u8>,
+    source_amount: u64,
+    slippage: f64,
+) -> PaymentHandle
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    let sender = StreamSender::new(
+        service,
+        from_account,
+        store,
+        destination_account,
         shared_secret,
+        source_amount,
+        slippage,
+    );
+    spawn_payment(sender)
+}
+
+/// Start sending the given source amount in the background, like
+/// [`send_money_with_min_rate`](./fn.send_money_with_min_rate.html),
+/// and return a handle to control the payment and follow its progress
+pub fn start_payment_with_min_rate<I, A, S>(
+    service: I,
+    from_account: &A,
+    store: S,
+    destination_account: Address,
+    shared_secret: Vec<u8>,
+    source_amount: u64,
+    min_exchange_rate: f64,
+) -> Result<PaymentHandle, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    let sender = StreamSender::with_min_rate(
+        service,
+        from_account,
         store,
+        destination_account,
+        shared_secret,
+        source_amount,
+        min_exchange_rate,
+    )?;
+    Ok(spawn_payment(sender))
+}
+
+/// Continue a payment from its persisted receipt, for example after the sending process restarted.
+/// Money that was in flight when the receipt was saved is not sent again, so the total
+/// sent never exceeds the receipt's source amount, even if some of it was not delivered.
+pub fn resume_payment<I, A, S>(
+    service: I,
+    from_account: &A,
+    store: S,
+    shared_secret: Vec<u8>,
+    delivery: StreamDelivery,
+    slippage: f64,
+) -> PaymentHandle
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    let mut sender = StreamSender::new(
+        service,
+        from_account,
+        store,
+        delivery.to.clone(),
+        shared_secret,
+        delivery.source_amount,
         slippage,
-        payment: Arc::new(Mutex::new(StreamPayment {
-            // TODO Make configurable to get money flowing ASAP vs as much as possible per-packet
-            congestion_controller: CongestionController::new(
-                source_amount,
-                source_amount / 10,
-                2.0,
-            ),
-            receipt: StreamDelivery::new(from_account, destination_account, source_amount),
-            should_send_source_account: true,
-            sequence: 1,
-            fulfilled_packets: 0,
-            rejected_packets: 0,
-            fail_fast_rejects: 0,
-            last_fulfill_time: Instant::now(),
-        })),
-    };
+    );
+    let mut payment = StreamPayment::new(from_account, delivery.to.clone(), delivery.source_amount);
+    payment.set_delivery(delivery);
+    sender.payment = Arc::new(Mutex::new(payment));
+    spawn_payment(sender)
+}
 
+/// Run the payment in a background task and return its handle
+fn spawn_payment<I, A, S>(mut sender: StreamSender<I, A, S>) -> PaymentHandle
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    let (control_tx, control) = watch::channel(PaymentControl::Send);
+    let (progress, _) = broadcast::channel(PROGRESS_CHANNEL_CAPACITY);
+    sender.progress = Some(progress.clone());
+    let payment = sender.payment.clone();
+
+    let task = tokio::spawn(async move {
+        let payment = sender.payment.clone();
+        let result = run_payment(sender, control).await;
+        if let Err(ref err) = result {
+            let mut payment = payment.lock().await;
+            payment.status = PaymentStatus::Failed;
+            payment.error = Some(err.to_string());
+        }
+        result
+    });
+
+    PaymentHandle {
+        control: control_tx,
+        progress,
+        payment,
+        task,
+    }
+}
+
+/// Send packets until the payment completes, fails or is cancelled, and return its receipt
+async fn run_payment<I, A, S>(
+    mut sender: StreamSender<I, A, S>,
+    mut control: watch::Receiver<PaymentControl>,
+) -> Result<StreamDelivery, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
     let mut pending_requests = FuturesUnordered::new();
 
     /// Actions corresponding to the state of the payment
@@ -351,6 +896,14 @@ where
         Timeout,
         /// Too many packets are rejected, such as if the exchange rate is too low: terminate the payment
         FailFast,
+        /// The recipient closed the connection before the full amount was delivered
+        ClosedByReceiver,
+        /// Spent the quoted maximum source amount without delivering the full amount
+        BudgetExhausted,
+        /// The handle paused the payment: wait until it's resumed or cancelled
+        Paused,
+        /// The handle cancelled the payment: close the connection and return what was delivered
+        Cancelled,
     }
 
     loop {
@@ -363,6 +916,14 @@ where
                 PaymentEvent::FailFast
             } else if payment.is_complete() {
                 PaymentEvent::CloseConnection
+            } else if *control.borrow() == PaymentControl::Cancel {
+                PaymentEvent::Cancelled
+            } else if *control.borrow() == PaymentControl::Pause {
+                PaymentEvent::Paused
+            } else if payment.closed_by_receiver {
+                PaymentEvent::ClosedByReceiver
+            } else if payment.is_budget_exhausted() {
+                PaymentEvent::BudgetExhausted
             } else if payment.is_max_in_flight() {
                 let deadline = payment
                     .last_fulfill_time
@@ -399,7 +960,8 @@ where
                 sender.try_send_connection_close().await;
 
                 // Return final receipt
-                let payment = sender.payment.lock().await;
+                let mut payment = sender.payment.lock().await;
+                payment.status = PaymentStatus::Completed;
                 debug!(
                     "Send money future finished. Delivered: {} ({} packets fulfilled, {} packets rejected)",
                     payment.receipt.delivered_amount,
@@ -422,6 +984,73 @@ where
                     payment.rejected_packets,
                 )));
             }
+            PaymentEvent::ClosedByReceiver => {
+                let payment = sender.payment.lock().await;
+                return Err(Error::SendMoneyError(format!(
+                    "Recipient closed the connection after receiving {} of {}",
+                    payment.receipt.delivered_amount, payment.receipt.source_amount,
+                )));
+            }
+            PaymentEvent::Paused => {
+                sender.payment.lock().await.status = PaymentStatus::Paused;
+                debug!("Payment paused");
+
+                // Let the packets in flight finish before waiting to be resumed
+                while let Some(result) = pending_requests.next().await {
+                    if let Ok(Err(error)) = result {
+                        error!("Send money stopped because of error: {:?}", error);
+                        return Err(error);
+                    }
+                }
+
+                // If the handle is dropped, nobody can resume the payment anymore
+                loop {
+                    match control.recv().await {
+                        Some(PaymentControl::Pause) => continue,
+                        Some(_) => break,
+                        None => {
+                            let mut payment = sender.payment.lock().await;
+                            payment.status = PaymentStatus::Cancelled;
+                            return Ok(payment.receipt.clone());
+                        }
+                    }
+                }
+
+                // Time spent paused doesn't count towards the timeout
+                let mut payment = sender.payment.lock().await;
+                payment.status = PaymentStatus::Sending;
+                payment.last_fulfill_time = Instant::now();
+                debug!("Payment resumed");
+            }
+            PaymentEvent::Cancelled => {
+                // Wait for all pending requests to complete before closing the connection
+                pending_requests.map(|_| ()).collect::<()>().await;
+                sender.try_send_connection_close().await;
+
+                let mut payment = sender.payment.lock().await;
+                payment.status = PaymentStatus::Cancelled;
+                debug!(
+                    "Payment cancelled. Delivered: {} ({} packets fulfilled, {} packets rejected)",
+                    payment.receipt.delivered_amount,
+                    payment.fulfilled_packets,
+                    payment.rejected_packets,
+                );
+                return Ok(payment.receipt.clone());
+            }
+            PaymentEvent::BudgetExhausted => {
+                let payment = sender.payment.lock().await;
+                let destination_amount = payment
+                    .quote
+                    .as_ref()
+                    .map(|quote| quote.destination_amount)
+                    .unwrap_or_default();
+                return Err(Error::SendMoneyError(format!(
+                    "Sent the quoted maximum of {} but only delivered {} of {}",
+                    payment.receipt.source_amount,
+                    payment.receipt.delivered_amount,
+                    destination_amount,
+                )));
+            }
         }
     }
 }
@@ -441,6 +1070,8 @@ struct StreamSender<I, A, S> {
     slippage: f64,
     /// Mutable payment state
     payment: Arc<Mutex<StreamPayment>>,
+    /// Publishes the payment's progress, if it was started with a handle
+    progress: Option<broadcast::Sender<PaymentProgress>>,
 }
 
 impl<I, A, S> StreamSender<I, A, S>
@@ -449,6 +1080,214 @@ where
     A: Account,
     S: ExchangeRateStore,
 {
+    fn new(
+        next: I,
+        from_account: &A,
+        store: S,
+        destination_account: Address,
+        shared_secret: Vec<u8>,
+        source_amount: u64,
+        slippage: f64,
+    ) -> Self {
+        let from = from_account.ilp_address();
+        if from.scheme() != destination_account.scheme() {
+            warn!(
+                "Destination ILP address starts with a different scheme prefix (\"{}\') than ours (\"{}\'), this probably won't work",
+                destination_account.scheme(),
+                from.scheme()
+            );
+        }
+
+        StreamSender {
+            next,
+            from_account: from_account.clone(),
+            shared_secret: Bytes::from(shared_secret),
+            store,
+            slippage,
+            payment: Arc::new(Mutex::new(StreamPayment::new(
+                from_account,
+                destination_account,
+                source_amount,
+            ))),
+            progress: None,
+        }
+    }
+
+    /// Sender whose payment enforces the given exchange rate, in destination units per source unit
+    fn with_min_rate(
+        next: I,
+        from_account: &A,
+        store: S,
+        destination_account: Address,
+        shared_secret: Vec<u8>,
+        source_amount: u64,
+        min_exchange_rate: f64,
+    ) -> Result<Self, Error> {
+        let min_rate = BigRational::from_f64(min_exchange_rate)
+            .filter(|rate| *rate > BigRational::zero())
+            .ok_or_else(|| {
+                Error::SendMoneyError(format!(
+                    "Invalid minimum exchange rate: {}",
+                    min_exchange_rate
+                ))
+            })?;
+        let mut sender = StreamSender::new(
+            next,
+            from_account,
+            store,
+            destination_account.clone(),
+            shared_secret,
+            source_amount,
+            0.0,
+        );
+        let mut payment = StreamPayment::new(from_account, destination_account, source_amount);
+        payment.min_rate = Some(min_rate);
+        sender.payment = Arc::new(Mutex::new(payment));
+        Ok(sender)
+    }
+
+    /// Discover the exchange rate with test packets and quote delivering the given destination amount
+    async fn quote(&mut self, destination_amount: u64) -> Result<StreamQuote, Error> {
+        let (probe_source_amount, probe_destination_amount) = self.probe().await?;
+        let quoted_rate = self.quoted_rate(probe_source_amount, probe_destination_amount)?;
+
+        let payment = self.payment.lock().await;
+        let max_source_amount = BigRational::from_u64(destination_amount)
+            .and_then(|amount| amount.checked_div(&quoted_rate))
+            .and_then(|amount| amount.ceil().to_integer().to_u64())
+            .ok_or_else(|| {
+                Error::SendMoneyError(format!(
+                    "Unable to quote a source amount to deliver {}",
+                    destination_amount
+                ))
+            })?;
+
+        Ok(StreamQuote {
+            to: payment.receipt.to.clone(),
+            destination_amount,
+            max_source_amount,
+            probe_source_amount,
+            probe_destination_amount,
+            destination_asset_scale: payment.receipt.destination_asset_scale,
+            destination_asset_code: payment.receipt.destination_asset_code.clone(),
+        })
+    }
+
+    /// Discover the exchange rate with test packets and quote what sending the given source amount delivers
+    async fn quote_source(&mut self, source_amount: u64) -> Result<StreamSourceQuote, Error> {
+        let (probe_source_amount, probe_destination_amount) = self.probe().await?;
+        let quoted_rate = self.quoted_rate(probe_source_amount, probe_destination_amount)?;
+
+        let payment = self.payment.lock().await;
+        let estimated_destination_amount = u128::from(source_amount)
+            * u128::from(probe_destination_amount)
+            / u128::from(probe_source_amount);
+        let min_destination_amount = (BigRational::from_integer(BigInt::from(source_amount))
+            * quoted_rate.clone())
+        .floor()
+        .to_integer()
+        .to_u64()
+        .unwrap_or(std::u64::MAX);
+        Ok(StreamSourceQuote {
+            to: payment.receipt.to.clone(),
+            source_amount,
+            estimated_destination_amount: min(
+                estimated_destination_amount,
+                u128::from(std::u64::MAX),
+            ) as u64,
+            min_destination_amount,
+            min_exchange_rate: quoted_rate.numer().to_f64().unwrap_or(0.0)
+                / quoted_rate.denom().to_f64().unwrap_or(1.0),
+            probe_source_amount,
+            probe_destination_amount,
+            destination_asset_scale: payment.receipt.destination_asset_scale,
+            destination_asset_code: payment.receipt.destination_asset_code.clone(),
+        })
+    }
+
+    /// Discover the exchange rate with test packets, and check that it is not worse
+    /// than what our own rates allow. Return the probe's source and destination amounts
+    async fn probe(&mut self) -> Result<(u64, u64), Error> {
+        let (probe_source_amount, probe_destination_amount) = self.probe_rate().await?;
+        let probed_rate = BigRational::new(
+            BigInt::from(probe_destination_amount),
+            BigInt::from(probe_source_amount),
+        );
+
+        let payment = self.payment.lock().await;
+
+        // Don't accept paths with a worse rate than what our own rates allow
+        let min_rate = get_rate(
+            &self.store,
+            payment.receipt.source_asset_scale,
+            &payment.receipt.source_asset_code,
+            payment.receipt.destination_asset_scale,
+            payment.receipt.destination_asset_code.as_deref(),
+            self.slippage,
+        );
+        if let Some(min_rate) = min_rate {
+            if probed_rate < min_rate {
+                return Err(Error::SendMoneyError(format!(
+                    "Exchange rate is too low: test packet of {} would have delivered only {}",
+                    probe_source_amount, probe_destination_amount
+                )));
+            }
+        }
+
+        Ok((probe_source_amount, probe_destination_amount))
+    }
+
+    /// The probed rate minus slippage, which is what the quotes budget for
+    fn quoted_rate(
+        &self,
+        probe_source_amount: u64,
+        probe_destination_amount: u64,
+    ) -> Result<BigRational, Error> {
+        let probed_rate = BigRational::new(
+            BigInt::from(probe_destination_amount),
+            BigInt::from(probe_source_amount),
+        );
+        let slippage = BigRational::from_f64(self.slippage)
+            .ok_or_else(|| Error::SendMoneyError(format!("Invalid slippage: {}", self.slippage)))?;
+        Ok(probed_rate * (BigRational::one() - slippage))
+    }
+
+    /// Send test packets of decreasing amounts until one reaches the recipient.
+    /// Return its source amount and the amount the recipient would have received.
+    async fn probe_rate(&mut self) -> Result<(u64, u64), Error> {
+        let mut probe_amount = MAX_PROBE_AMOUNT;
+        while probe_amount > 0 {
+            let received = self.send_test_packet(probe_amount).await?;
+            if received > 0 {
+                debug!(
+                    "Test packet of {} would have delivered {}",
+                    probe_amount, received
+                );
+                return Ok((probe_amount, received));
+            }
+            probe_amount /= 10;
+        }
+
+        Err(Error::SendMoneyError(
+            "Unable to discover the exchange rate: no test packet reached the recipient"
+                .to_string(),
+        ))
+    }
+
+    /// Send an unfulfillable Prepare for the given source amount.
+    /// Return the amount the recipient says it would have received, or 0 if it didn't reach the recipient
+    async fn send_test_packet(&mut self, source_amount: u64) -> Result<u64, Error> {
+        let (reply, claimed_amount) = self.send_packet(source_amount, 0).await;
+        match reply {
+            // The condition is random, so this should never be fulfilled
+            Ok(_) => Ok(claimed_amount),
+            Err(reject) if reject.code() == IlpErrorCode::F99_APPLICATION_ERROR => {
+                Ok(claimed_amount)
+            }
+            Err(reject) => check_reject(&reject).map(|_| 0),
+        }
+    }
+
     /// Send a Prepare for the given source amount and apply the resulting Fulfill or Reject
     #[inline]
     pub async fn send_money_packet(
@@ -456,6 +1295,70 @@ where
         source_amount: u64,
         min_destination_amount: u64,
     ) -> Result<(), Error> {
+        let (reply, claimed_amount) = self
+            .send_packet(source_amount, min_destination_amount)
+            .await;
+
+        let mut payment = self.payment.lock().await;
+
+        match reply {
+            // Handle ILP Fulfill
+            Ok(_) => {
+                // Delivered amount must be *at least* the minimum acceptable amount we told the receiver
+                // Even if the data was invalid, since it was fulfilled, we must assume they got at least the minimum
+                let delivered_amount = max(min_destination_amount, claimed_amount);
+
+                payment.apply_fulfill(source_amount, delivered_amount);
+                if let Some(progress) = &self.progress {
+                    // Nobody may be listening
+                    progress
+                        .send(payment.progress(source_amount, delivered_amount))
+                        .ok();
+                }
+
+                debug!(
+                    "Prepare with amount {} was fulfilled ({} left to send)",
+                    source_amount,
+                    payment.get_remaining_amount()
+                );
+
+                Ok(())
+            }
+            // Handle ILP Reject
+            Err(reject) => {
+                payment.apply_reject(source_amount, &reject);
+
+                debug!(
+                    "Prepare with amount {} was rejected with code: {} ({} left to send)",
+                    source_amount,
+                    reject.code(),
+                    payment.get_remaining_amount()
+                );
+
+                // Fixed-delivery payments and payments with a minimum rate stop as soon as
+                // the path no longer provides that rate. Allow one unit for rounding by the connectors.
+                let rate_violated = (payment.quote.is_some() || payment.min_rate.is_some())
+                    && reject.code() == IlpErrorCode::F99_APPLICATION_ERROR
+                    && claimed_amount.saturating_add(1) < min_destination_amount;
+                if rate_violated {
+                    return Err(Error::SendMoneyError(format!(
+                        "Exchange rate fell below the quoted rate: packet of {} would have delivered {}, but at least {} was required",
+                        source_amount, claimed_amount, min_destination_amount
+                    )));
+                }
+
+                check_reject(&reject)
+            }
+        }
+    }
+
+    /// Send a Prepare for the given source amount and process the frames in the recipient's reply.
+    /// Return the Fulfill or Reject and the amount the recipient claims it received
+    async fn send_packet(
+        &mut self,
+        source_amount: u64,
+        min_destination_amount: u64,
+    ) -> (IlpResult, u64) {
         let (prepare, sequence) = {
             let mut payment = self.payment.lock().await;
 
@@ -563,6 +1466,20 @@ where
                         }
                     }
 
+                    for frame in stream_reply_packet.frames() {
+                        match frame {
+                            Frame::StreamReceipt(frame) => payment.apply_receipt(frame.receipt),
+                            Frame::ConnectionClose(frame) => {
+                                debug!(
+                                    "Recipient closed the connection: {:?} {}",
+                                    frame.code, frame.message
+                                );
+                                payment.closed_by_receiver = true;
+                            }
+                            _ => {}
+                        }
+                    }
+
                     stream_reply_packet.prepare_amount()
                 }
             }
@@ -575,52 +1492,7 @@ where
             }
         };
 
-        match reply {
-            // Handle ILP Fulfill
-            Ok(_) => {
-                // Delivered amount must be *at least* the minimum acceptable amount we told the receiver
-                // Even if the data was invalid, since it was fulfilled, we must assume they got at least the minimum
-                let delivered_amount = max(min_destination_amount, claimed_amount);
-
-                payment.apply_fulfill(source_amount, delivered_amount);
-
-                debug!(
-                    "Prepare {} with amount {} was fulfilled ({} left to send)",
-                    sequence,
-                    source_amount,
-                    payment.get_remaining_amount()
-                );
-
-                Ok(())
-            }
-            // Handle ILP Reject
-            Err(reject) => {
-                payment.apply_reject(source_amount, &reject);
-
-                debug!(
-                    "Prepare {} with amount {} was rejected with code: {} ({} left to send)",
-                    sequence,
-                    source_amount,
-                    reject.code(),
-                    payment.get_remaining_amount()
-                );
-
-                match (reject.code().class(), reject.code()) {
-                    (ErrorClass::Temporary, _) => Ok(()),
-                    (_, IlpErrorCode::F08_AMOUNT_TOO_LARGE) => Ok(()),
-                    (_, IlpErrorCode::F99_APPLICATION_ERROR) => Ok(()),
-                    // R01 is triggered by connector when the amount rounds to 0, so keep retrying
-                    // Other Rxx errors such as timeouts are likely terminal
-                    (_, IlpErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT) => Ok(()),
-                    // Any other error will stop the rest of the payment
-                    _ => Err(Error::SendMoneyError(format!(
-                        "Packet was rejected with error: {} {}",
-                        reject.code(),
-                        str::from_utf8(reject.message()).unwrap_or_default(),
-                    ))),
-                }
-            }
-        }
+        (reply, claimed_amount)
     }
 
     /// Send an unfulfillable Prepare with a ConnectionClose frame to the peer
@@ -667,6 +1539,48 @@ where
     }
 }
 
+/// Decide whether the payment may continue after a rejected packet
+fn check_reject(reject: &Reject) -> Result<(), Error> {
+    match (reject.code().class(), reject.code()) {
+        (ErrorClass::Temporary, _) => Ok(()),
+        (_, IlpErrorCode::F08_AMOUNT_TOO_LARGE) => Ok(()),
+        (_, IlpErrorCode::F99_APPLICATION_ERROR) => Ok(()),
+        // R01 is triggered by connector when the amount rounds to 0, so keep retrying
+        // Other Rxx errors such as timeouts are likely terminal
+        (_, IlpErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT) => Ok(()),
+        // Any other error will stop the rest of the payment
+        _ => Err(Error::SendMoneyError(format!(
+            "Packet was rejected with error: {} {}",
+            reject.code(),
+            str::from_utf8(reject.message()).unwrap_or_default(),
+        ))),
+    }
+}
+
+/// Serializes optional bytes as a base64 string
+mod serde_base64_option {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match bytes {
+            Some(bytes) => serializer.serialize_some(&base64::encode(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| base64::decode(&s).map_err(de::Error::custom))
+            .transpose()
+    }
+}
+
 // TODO Abstract duplicated conversion logic from interledger-settlement &
 //      exchange rate service into interledger-rates
 
@@ -0,0 +1,957 @@
+use super::buffer::{ReceiveBuffer, SendBuffer};
+use super::crypto::*;
+use super::error::Error;
+use super::packet::*;
+use bytes::{Bytes, BytesMut};
+use futures::channel::mpsc::{self, Receiver, Sender, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use interledger_packet::{Address, ErrorClass, PacketType as IlpPacketType, PrepareBuilder};
+use interledger_service::{Account, IncomingRequest, IncomingService};
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::pin::Pin;
+use std::str;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::SystemTime;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::task::JoinHandle;
+use tokio::time::{delay_for, timeout, Duration};
+use tracing::{debug, warn};
+
+/// Number of bytes a stream buffers for the application before the remote has to wait.
+/// This is also the window we assume the remote gives us until it tells us otherwise.
+const DEFAULT_WINDOW: u64 = 65_536;
+/// Number of bytes written to a stream which are buffered before writes have to wait
+const SEND_BUFFER_SIZE: usize = 65_536;
+/// Maximum number of data bytes sent in a single Prepare packet
+const MAX_PREPARE_DATA: usize = 16_384;
+/// Maximum number of data bytes the receiver sends back in a Fulfill or Reject packet
+pub(crate) const MAX_REPLY_DATA: usize = 4_096;
+/// How often the sender polls the receiver while it waits for the receiver
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Number of consecutive packets which may fail temporarily before the connection is closed
+const MAX_TEMPORARY_FAILURES: u32 = 20;
+/// Number of streams the remote may open at once, which is also the limit we assume the
+/// remote gives us until it raises it with a ConnectionMaxStreamId frame
+const MAX_OPEN_STREAMS: u64 = 16;
+
+/// A frame we want to send, owning its data so it can be put in a packet later
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum OutgoingFrame {
+    Data {
+        stream_id: u64,
+        offset: u64,
+        data: Bytes,
+    },
+    MaxData {
+        stream_id: u64,
+        max_offset: u64,
+    },
+    Close {
+        stream_id: u64,
+        code: ErrorCode,
+    },
+    MaxStreamId {
+        max_stream_id: u64,
+    },
+    ConnectionClose {
+        code: ErrorCode,
+    },
+}
+
+impl OutgoingFrame {
+    pub(crate) fn as_frame(&self) -> Frame<'_> {
+        match self {
+            OutgoingFrame::Data {
+                stream_id,
+                offset,
+                data,
+            } => Frame::StreamData(StreamDataFrame {
+                stream_id: *stream_id,
+                offset: *offset,
+                data: &data[..],
+            }),
+            OutgoingFrame::MaxData {
+                stream_id,
+                max_offset,
+            } => Frame::StreamMaxData(StreamMaxDataFrame {
+                stream_id: *stream_id,
+                max_offset: *max_offset,
+            }),
+            OutgoingFrame::Close { stream_id, code } => Frame::StreamClose(StreamCloseFrame {
+                stream_id: *stream_id,
+                code: *code,
+                message: "",
+            }),
+            OutgoingFrame::MaxStreamId { max_stream_id } => {
+                Frame::ConnectionMaxStreamId(ConnectionMaxStreamIdFrame {
+                    max_stream_id: *max_stream_id,
+                })
+            }
+            OutgoingFrame::ConnectionClose { code } => {
+                Frame::ConnectionClose(ConnectionCloseFrame {
+                    code: *code,
+                    message: "",
+                })
+            }
+        }
+    }
+}
+
+/// State of a single stream of a connection
+struct StreamState {
+    incoming: ReceiveBuffer,
+    outgoing: SendBuffer,
+    /// The offset up to which the remote is willing to receive data
+    remote_max_offset: u64,
+    /// The max offset we last told the remote about
+    advertised_max_offset: u64,
+    /// Set when we are done writing, a StreamClose is sent once the data is sent
+    close_code: Option<ErrorCode>,
+    close_sent: bool,
+    /// Set once the remote closed the stream; the error is kept if it was not a clean close
+    remote_closed: Option<Result<(), String>>,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+impl StreamState {
+    fn new() -> Self {
+        StreamState {
+            incoming: ReceiveBuffer::new(DEFAULT_WINDOW),
+            outgoing: SendBuffer::new(),
+            remote_max_offset: DEFAULT_WINDOW,
+            advertised_max_offset: DEFAULT_WINDOW,
+            close_code: None,
+            close_sent: false,
+            remote_closed: None,
+            read_waker: None,
+            write_waker: None,
+        }
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Whether all of our data and the StreamClose frame (if any) were sent
+    fn is_sent(&self) -> bool {
+        self.outgoing.is_sent() && (self.close_code.is_none() || self.close_sent)
+    }
+
+    /// Whether the stream is closed in both directions and was fully read
+    fn is_finished(&self) -> bool {
+        self.close_sent
+            && self.outgoing.is_empty()
+            && self.remote_closed.is_some()
+            && !self.incoming.is_readable()
+    }
+}
+
+/// State of a STREAM connection shared between the connection, its data streams
+/// and whatever sends and receives the connection's packets
+struct ConnectionState {
+    /// Kept in order so that the data of lower streams is sent first
+    streams: BTreeMap<u64, StreamState>,
+    /// Clients open odd-numbered streams and servers even-numbered ones
+    next_stream_id: u64,
+    /// The highest stream ID the remote allows us to open
+    max_outgoing_stream_id: u64,
+    /// The remote's streams below this ID are all closed
+    incoming_stream_floor: u64,
+    /// The remote's streams from the floor up which are closed
+    closed_incoming_streams: BTreeSet<u64>,
+    /// The highest stream ID we last told the remote it may open
+    advertised_max_stream_id: u64,
+    /// The frames of the receiver's last reply and the sequence of the packet it replied to.
+    /// They are only known to have arrived once the client sends a packet with a higher sequence.
+    last_reply: Option<(u64, Vec<OutgoingFrame>)>,
+    /// Streams opened by the remote, which the application has yet to accept
+    incoming_streams: UnboundedSender<DataStream>,
+    /// Tells the client's sending task that there is something to send
+    notify: Option<Sender<()>>,
+    /// Set when the application closes the connection
+    closing: bool,
+    /// Set once the connection is closed; contains the error if it was not closed cleanly
+    closed: Option<Result<(), String>>,
+    /// Set when we close the connection because of the remote, to tell it why
+    error_code: Option<ErrorCode>,
+}
+
+impl ConnectionState {
+    fn notify(&mut self) {
+        if let Some(notify) = self.notify.as_mut() {
+            // The channel being full means the sender will look for frames anyway
+            let _ = notify.try_send(());
+        }
+    }
+
+    fn is_local_stream(&self, stream_id: u64) -> bool {
+        stream_id % 2 == self.next_stream_id % 2
+    }
+
+    /// The highest stream ID the remote may open, which moves up as its streams close
+    fn max_incoming_stream_id(&self) -> u64 {
+        self.incoming_stream_floor + 2 * (MAX_OPEN_STREAMS - 1)
+    }
+
+    /// Whether the remote's stream was closed and forgotten
+    fn is_closed_incoming_stream(&self, stream_id: u64) -> bool {
+        stream_id < self.incoming_stream_floor || self.closed_incoming_streams.contains(&stream_id)
+    }
+
+    /// Closes the connection because the remote broke the protocol
+    fn fail(&mut self, code: ErrorCode, message: &str) {
+        warn!("Closing STREAM connection: {}", message);
+        self.error_code = Some(code);
+        self.close(Err(format!("{:?}: {}", code, message)));
+    }
+
+    fn close(&mut self, result: Result<(), String>) {
+        if self.closed.is_none() {
+            debug!("STREAM connection closed: {:?}", result);
+            self.closed = Some(result);
+        }
+        self.incoming_streams.close_channel();
+        for stream in self.streams.values_mut() {
+            stream.wake();
+        }
+    }
+
+    fn outgoing_frames(&mut self, max_data: usize) -> Vec<OutgoingFrame> {
+        if self.closed.is_some() {
+            // Tell the remote once why we closed the connection
+            return self
+                .error_code
+                .take()
+                .map(|code| vec![OutgoingFrame::ConnectionClose { code }])
+                .unwrap_or_default();
+        }
+        let mut frames = Vec::new();
+        let max_incoming_stream_id = self.max_incoming_stream_id();
+        if max_incoming_stream_id != self.advertised_max_stream_id {
+            frames.push(OutgoingFrame::MaxStreamId {
+                max_stream_id: max_incoming_stream_id,
+            });
+            self.advertised_max_stream_id = max_incoming_stream_id;
+        }
+        let local_parity = self.next_stream_id % 2;
+        let max_outgoing_stream_id = self.max_outgoing_stream_id;
+        let mut budget = max_data;
+        for (stream_id, stream) in self.streams.iter_mut() {
+            if *stream_id % 2 == local_parity && *stream_id > max_outgoing_stream_id {
+                // The remote does not allow us to open this stream yet
+                continue;
+            }
+            let max_offset = stream.incoming.max_offset();
+            if max_offset != stream.advertised_max_offset && stream.remote_closed.is_none() {
+                frames.push(OutgoingFrame::MaxData {
+                    stream_id: *stream_id,
+                    max_offset,
+                });
+                stream.advertised_max_offset = max_offset;
+            }
+            while budget > 0 {
+                match stream.outgoing.next_chunk(stream.remote_max_offset, budget) {
+                    Some((offset, data)) => {
+                        budget -= data.len();
+                        frames.push(OutgoingFrame::Data {
+                            stream_id: *stream_id,
+                            offset,
+                            data,
+                        });
+                    }
+                    None => break,
+                }
+            }
+            if let Some(code) = stream.close_code {
+                if !stream.close_sent && stream.outgoing.is_sent() {
+                    frames.push(OutgoingFrame::Close {
+                        stream_id: *stream_id,
+                        code,
+                    });
+                    stream.close_sent = true;
+                }
+            }
+        }
+        if self.closing && self.streams.values().all(StreamState::is_sent) {
+            frames.push(OutgoingFrame::ConnectionClose {
+                code: ErrorCode::NoError,
+            });
+        }
+        frames
+    }
+
+    fn acknowledge(&mut self, frames: &[OutgoingFrame]) {
+        for stream in self.streams.values_mut() {
+            stream.outgoing.acknowledge();
+            if let Some(waker) = stream.write_waker.take() {
+                waker.wake();
+            }
+        }
+        if frames
+            .iter()
+            .any(|frame| matches!(frame, OutgoingFrame::ConnectionClose { .. }))
+        {
+            self.close(Ok(()));
+        }
+        // Forget about streams which are closed in both directions and fully read,
+        // which lets the remote open new streams in place of its own
+        let finished: Vec<u64> = self
+            .streams
+            .iter()
+            .filter(|(_, stream)| stream.is_finished())
+            .map(|(stream_id, _)| *stream_id)
+            .collect();
+        for stream_id in finished {
+            self.streams.remove(&stream_id);
+            if !self.is_local_stream(stream_id) {
+                self.closed_incoming_streams.insert(stream_id);
+            }
+        }
+        while self
+            .closed_incoming_streams
+            .remove(&self.incoming_stream_floor)
+        {
+            self.incoming_stream_floor += 2;
+        }
+    }
+
+    fn retransmit(&mut self, frames: &[OutgoingFrame]) {
+        for frame in frames {
+            match frame {
+                OutgoingFrame::Data { stream_id, .. } => {
+                    if let Some(stream) = self.streams.get_mut(stream_id) {
+                        stream.outgoing.rewind();
+                    }
+                }
+                OutgoingFrame::MaxData { stream_id, .. } => {
+                    if let Some(stream) = self.streams.get_mut(stream_id) {
+                        // Makes sure the max offset is sent again
+                        stream.advertised_max_offset = 0;
+                    }
+                }
+                OutgoingFrame::Close { stream_id, .. } => {
+                    if let Some(stream) = self.streams.get_mut(stream_id) {
+                        stream.close_sent = false;
+                    }
+                }
+                OutgoingFrame::MaxStreamId { .. } => self.advertised_max_stream_id = 0,
+                OutgoingFrame::ConnectionClose { .. } => {}
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct SharedConnection(Arc<Mutex<ConnectionState>>);
+
+impl SharedConnection {
+    fn new(
+        first_stream_id: u64,
+        notify: Option<Sender<()>>,
+    ) -> (Self, UnboundedReceiver<DataStream>) {
+        let (incoming_streams, incoming) = mpsc::unbounded();
+        // The remote's streams have the other parity
+        let first_incoming_stream_id = 1 + first_stream_id % 2;
+        let max_incoming_stream_id = first_incoming_stream_id + 2 * (MAX_OPEN_STREAMS - 1);
+        let connection = SharedConnection(Arc::new(Mutex::new(ConnectionState {
+            streams: BTreeMap::new(),
+            next_stream_id: first_stream_id,
+            max_outgoing_stream_id: first_stream_id + 2 * (MAX_OPEN_STREAMS - 1),
+            incoming_stream_floor: first_incoming_stream_id,
+            closed_incoming_streams: BTreeSet::new(),
+            advertised_max_stream_id: max_incoming_stream_id,
+            last_reply: None,
+            incoming_streams,
+            notify,
+            closing: false,
+            closed: None,
+            error_code: None,
+        })));
+        (connection, incoming)
+    }
+
+    /// Applies the frames the remote sent us.
+    /// Returns true if the frames carried any data.
+    pub(crate) fn handle_frames<'a>(&self, frames: impl Iterator<Item = Frame<'a>>) -> bool {
+        let mut state = self.0.lock();
+        let mut received_data = false;
+        // Streams are handed to the application after releasing the lock,
+        // because dropping them needs it
+        let mut accepted = Vec::new();
+        for frame in frames {
+            match frame {
+                Frame::StreamData(frame) => {
+                    if state.closed.is_some() {
+                        continue;
+                    }
+                    if !state.streams.contains_key(&frame.stream_id) {
+                        if state.is_local_stream(frame.stream_id)
+                            || state.is_closed_incoming_stream(frame.stream_id)
+                            || state.closing
+                        {
+                            debug!("Ignoring data for unknown stream {}", frame.stream_id);
+                            continue;
+                        }
+                        if frame.stream_id > state.max_incoming_stream_id() {
+                            state.fail(
+                                ErrorCode::StreamIdError,
+                                "Remote opened more streams than allowed",
+                            );
+                            continue;
+                        }
+                        debug!("Remote opened stream {}", frame.stream_id);
+                        state.streams.insert(frame.stream_id, StreamState::new());
+                        accepted.push(DataStream {
+                            id: frame.stream_id,
+                            connection: self.clone(),
+                        });
+                    }
+                    let stream = state.streams.get_mut(&frame.stream_id).unwrap();
+                    if stream.incoming.push(frame.offset, frame.data).is_err() {
+                        warn!(
+                            "Closing stream {} because the remote exceeded its window",
+                            frame.stream_id
+                        );
+                        stream.close_code = Some(ErrorCode::FlowControlError);
+                        stream.remote_closed = Some(Err("Flow control error".to_string()));
+                    }
+                    received_data |= !frame.data.is_empty();
+                    stream.wake();
+                }
+                Frame::StreamMaxData(frame) => {
+                    if let Some(stream) = state.streams.get_mut(&frame.stream_id) {
+                        stream.remote_max_offset = stream.remote_max_offset.max(frame.max_offset);
+                    }
+                }
+                Frame::ConnectionMaxStreamId(frame) => {
+                    state.max_outgoing_stream_id =
+                        state.max_outgoing_stream_id.max(frame.max_stream_id);
+                }
+                Frame::StreamClose(frame) => {
+                    if let Some(stream) = state.streams.get_mut(&frame.stream_id) {
+                        stream.remote_closed = Some(match frame.code {
+                            ErrorCode::NoError => Ok(()),
+                            code => Err(format!("{:?}: {}", code, frame.message)),
+                        });
+                        stream.wake();
+                    }
+                }
+                Frame::ConnectionClose(frame) => {
+                    let result = match frame.code {
+                        ErrorCode::NoError => Ok(()),
+                        code => Err(format!("{:?}: {}", code, frame.message)),
+                    };
+                    state.close(result);
+                }
+                _ => {}
+            }
+        }
+        let incoming_streams = state.incoming_streams.clone();
+        drop(state);
+        for stream in accepted {
+            // If the application isn't accepting streams, the stream is closed when dropped
+            let _ = incoming_streams.unbounded_send(stream);
+        }
+        received_data
+    }
+
+    /// Collects the frames we need to send, with at most `max_data` bytes of stream data
+    fn outgoing_frames(&self, max_data: usize) -> Vec<OutgoingFrame> {
+        self.0.lock().outgoing_frames(max_data)
+    }
+
+    /// Called once the remote received the frames returned by `outgoing_frames`
+    fn acknowledge(&self, frames: &[OutgoingFrame]) {
+        self.0.lock().acknowledge(frames)
+    }
+
+    /// Called if the frames returned by `outgoing_frames` did not reach the remote
+    fn retransmit(&self, frames: &[OutgoingFrame]) {
+        self.0.lock().retransmit(frames)
+    }
+
+    /// Applies the frames of a client's packet on the receiver's side and returns
+    /// the frames to reply with, with at most `max_data` bytes of stream data.
+    ///
+    /// The client only moves on to the next sequence once it got a reply, so a packet
+    /// with a higher sequence than the last one acknowledges our last reply, while the
+    /// same sequence means the reply was lost and its frames need to be sent again.
+    pub(crate) fn reply<'a>(
+        &self,
+        sequence: u64,
+        frames: impl Iterator<Item = Frame<'a>>,
+        max_data: usize,
+    ) -> Vec<OutgoingFrame> {
+        {
+            let mut state = self.0.lock();
+            match state.last_reply.take() {
+                Some((last_sequence, last_frames)) if sequence < last_sequence => {
+                    debug!("Ignoring old STREAM packet {}", sequence);
+                    state.last_reply = Some((last_sequence, last_frames));
+                    return Vec::new();
+                }
+                Some((last_sequence, last_frames)) if sequence == last_sequence => {
+                    state.retransmit(&last_frames)
+                }
+                Some((_, last_frames)) => state.acknowledge(&last_frames),
+                None => {}
+            }
+        }
+        self.handle_frames(frames);
+
+        let mut state = self.0.lock();
+        if state.closed.is_some() {
+            // Also answers the client if our reply with the ConnectionClose got lost
+            let code = state.error_code.unwrap_or(ErrorCode::NoError);
+            return vec![OutgoingFrame::ConnectionClose { code }];
+        }
+        let reply = state.outgoing_frames(max_data);
+        if reply
+            .iter()
+            .any(|frame| matches!(frame, OutgoingFrame::ConnectionClose { .. }))
+        {
+            // The client stops sending packets once it reads the ConnectionClose
+            state.acknowledge(&reply);
+        } else {
+            state.last_reply = Some((sequence, reply.clone()));
+        }
+        reply
+    }
+
+    /// Whether we are waiting for the remote, because the application is reading a stream
+    /// or opened more streams than the remote allows so far
+    fn is_waiting(&self) -> bool {
+        let state = self.0.lock();
+        let local_parity = state.next_stream_id % 2;
+        state.streams.iter().any(|(stream_id, stream)| {
+            (stream.read_waker.is_some() && stream.remote_closed.is_none())
+                || (*stream_id % 2 == local_parity && *stream_id > state.max_outgoing_stream_id)
+        })
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.0.lock().closed.is_some()
+    }
+
+    pub(crate) fn close(&self, result: Result<(), String>) {
+        self.0.lock().close(result)
+    }
+}
+
+/// A STREAM connection for sending and receiving data.
+///
+/// Each connection can carry many [`DataStream`](./struct.DataStream.html)s, which
+/// are ordered, flow-controlled byte streams implementing `AsyncRead` and `AsyncWrite`.
+/// Clients create connections with [`connect`](#method.connect) while receivers get them
+/// from [`StreamReceiverService::incoming_connections`](./struct.StreamReceiverService.html#method.incoming_connections).
+///
+/// Money for the same connection can be sent with [`send_money`](./fn.send_money.html)
+/// using the same destination and shared secret, which lets the receiver match the
+/// data (for example an invoice ID) to the payment.
+///
+/// The receiver can only send data in its replies to the client's packets, so the
+/// client polls the receiver while any of its streams are being read. The receiver
+/// sends the frames of a reply which got lost on the way back again.
+///
+/// Each side may have up to 16 streams opened by the remote at once, and lets the
+/// remote open more as those are closed. Data written to streams beyond that limit
+/// is sent once the remote allows it.
+pub struct StreamConnection {
+    connection: SharedConnection,
+    incoming_streams: UnboundedReceiver<DataStream>,
+    destination: Address,
+    sender: Option<JoinHandle<()>>,
+}
+
+impl StreamConnection {
+    /// Opens a connection to the receiver at `destination_account`, sending packets
+    /// through the given service.
+    ///
+    /// This sends a first packet to make sure the receiver can be reached and
+    /// then sends the streams' data in the background.
+    pub async fn connect<I, A>(
+        service: I,
+        from_account: A,
+        destination_account: Address,
+        shared_secret: Vec<u8>,
+    ) -> Result<Self, Error>
+    where
+        I: IncomingService<A> + Send + Sync + 'static,
+        A: Account + Send + Sync + 'static,
+    {
+        let (notify, notifications) = mpsc::channel(1);
+        let (connection, incoming_streams) = SharedConnection::new(1, Some(notify));
+        let mut sender = DataSender {
+            next: service,
+            from_account,
+            destination: destination_account.clone(),
+            shared_secret: Bytes::from(shared_secret),
+            connection: connection.clone(),
+            sequence: 1,
+        };
+
+        let source_account = sender.from_account.ilp_address().clone();
+        sender
+            .send_packet(&[], Some(source_account))
+            .await
+            .map_err(|err| Error::ConnectionError(err.to_string()))?;
+
+        Ok(StreamConnection {
+            connection,
+            incoming_streams,
+            destination: destination_account,
+            sender: Some(tokio::spawn(sender.run(notifications))),
+        })
+    }
+
+    /// Creates the receiver's side of a connection
+    pub(crate) fn new_receiver(destination: Address) -> (Self, SharedConnection) {
+        let (connection, incoming_streams) = SharedConnection::new(2, None);
+        let stream_connection = StreamConnection {
+            connection: connection.clone(),
+            incoming_streams,
+            destination,
+            sender: None,
+        };
+        (stream_connection, connection)
+    }
+
+    /// The connection's destination address, which is also the `destination`
+    /// of the payment notifications for money sent over this connection
+    pub fn destination(&self) -> &Address {
+        &self.destination
+    }
+
+    /// Opens a new stream to send data to the remote
+    pub fn open_stream(&self) -> DataStream {
+        let mut state = self.connection.0.lock();
+        let id = state.next_stream_id;
+        state.next_stream_id += 2;
+        state.streams.insert(id, StreamState::new());
+        DataStream {
+            id,
+            connection: self.connection.clone(),
+        }
+    }
+
+    /// Waits for the remote to open a stream.
+    /// Returns `None` once the connection is closed.
+    pub async fn accept_stream(&mut self) -> Option<DataStream> {
+        self.incoming_streams.next().await
+    }
+
+    /// Closes the connection once the data written to its streams was sent
+    pub async fn close(mut self) -> Result<(), Error> {
+        {
+            let mut state = self.connection.0.lock();
+            state.closing = true;
+            state.notify();
+        }
+        if let Some(sender) = self.sender.take() {
+            let _ = sender.await;
+        }
+        match self.connection.0.lock().closed {
+            Some(Err(ref err)) => Err(Error::ConnectionError(err.clone())),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for StreamConnection {
+    fn drop(&mut self) {
+        let mut state = self.connection.0.lock();
+        state.closing = true;
+        state.notify();
+    }
+}
+
+/// An ordered stream of bytes within a [`StreamConnection`](./struct.StreamConnection.html).
+///
+/// Reads return the data the remote sent on this stream, in order, and return 0 bytes
+/// once the remote closed the stream. Writes are buffered and wait when the buffer is full;
+/// flushing waits until the remote received everything written so far. Shutting down or
+/// dropping the stream closes it once its data was sent.
+pub struct DataStream {
+    id: u64,
+    connection: SharedConnection,
+}
+
+impl DataStream {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl AsyncRead for DataStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut state = self.connection.0.lock();
+        let closed = state.closed.clone();
+        let stream = match state.streams.get_mut(&self.id) {
+            Some(stream) => stream,
+            None => return Poll::Ready(Ok(0)),
+        };
+        if stream.incoming.is_readable() {
+            let read = stream.incoming.read(buf);
+            // Let the remote know it can send more
+            state.notify();
+            return Poll::Ready(Ok(read));
+        }
+        match stream.remote_closed.clone().or(closed) {
+            Some(Ok(())) => Poll::Ready(Ok(0)),
+            Some(Err(err)) => Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, err))),
+            None => {
+                let started_reading = stream.read_waker.is_none();
+                stream.read_waker = Some(cx.waker().clone());
+                if started_reading {
+                    // The client polls the receiver while a stream is being read.
+                    // Notifying it again on every poll would keep it from waiting.
+                    state.notify();
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl AsyncWrite for DataStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut state = self.connection.0.lock();
+        if let Some(result) = state.closed.clone() {
+            return Poll::Ready(Err(closed_error(result)));
+        }
+        let stream = match state.streams.get_mut(&self.id) {
+            Some(stream) if stream.close_code.is_none() => stream,
+            _ => {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "stream is closed",
+                )))
+            }
+        };
+        let available = SEND_BUFFER_SIZE.saturating_sub(stream.outgoing.len());
+        if available == 0 {
+            stream.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let written = available.min(buf.len());
+        stream.outgoing.write(&buf[..written]);
+        state.notify();
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut state = self.connection.0.lock();
+        let closed = state.closed.clone();
+        match state.streams.get_mut(&self.id) {
+            Some(stream) if !stream.outgoing.is_empty() => match closed {
+                Some(result) => Poll::Ready(Err(closed_error(result))),
+                None => {
+                    stream.write_waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            },
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        {
+            let mut state = self.connection.0.lock();
+            if let Some(stream) = state.streams.get_mut(&self.id) {
+                if stream.close_code.is_none() {
+                    stream.close_code = Some(ErrorCode::NoError);
+                }
+            }
+            state.notify();
+        }
+        self.as_mut().poll_flush(cx)
+    }
+}
+
+impl Drop for DataStream {
+    fn drop(&mut self) {
+        let mut state = self.connection.0.lock();
+        if let Some(stream) = state.streams.get_mut(&self.id) {
+            if stream.close_code.is_none() {
+                stream.close_code = Some(ErrorCode::NoError);
+            }
+            // Nobody will read the rest of the data
+            if stream.remote_closed.is_none() {
+                stream.remote_closed = Some(Ok(()));
+            }
+        }
+        state.notify();
+    }
+}
+
+fn closed_error(result: Result<(), String>) -> io::Error {
+    match result {
+        Ok(()) => io::Error::new(io::ErrorKind::BrokenPipe, "connection is closed"),
+        Err(err) => io::Error::new(io::ErrorKind::Other, err),
+    }
+}
+
+/// Why a packet with stream frames did not get through
+#[derive(Debug, thiserror::Error)]
+enum SendError {
+    #[error("Temporary error: {0}")]
+    Temporary(String),
+    #[error("Final error: {0}")]
+    Final(String),
+}
+
+/// Sends the packets of the client's side of a connection
+struct DataSender<I, A> {
+    next: I,
+    from_account: A,
+    destination: Address,
+    shared_secret: Bytes,
+    connection: SharedConnection,
+    /// Only moves on once the receiver replied, which tells it that its last reply arrived
+    sequence: u64,
+}
+
+impl<I, A> DataSender<I, A>
+where
+    I: IncomingService<A>,
+    A: Account,
+{
+    /// Sends the connection's frames until it is closed
+    async fn run(mut self, mut notifications: Receiver<()>) {
+        let mut failures = 0;
+        // Set when the receiver sent data, because it may have more
+        let mut poll_now = false;
+        loop {
+            let frames = self.connection.outgoing_frames(MAX_PREPARE_DATA);
+            if frames.is_empty() && !(poll_now && self.connection.is_waiting()) {
+                if self.connection.is_closed() {
+                    return;
+                }
+                // Wait until there is something to send. While a stream is being read
+                // or waits to be opened, poll the receiver every now and then.
+                if self.connection.is_waiting() {
+                    if let Ok(notification) = timeout(POLL_INTERVAL, notifications.next()).await {
+                        if notification.is_some() {
+                            continue;
+                        }
+                    }
+                } else if notifications.next().await.is_some() {
+                    continue;
+                }
+            }
+
+            poll_now = false;
+            match self.send_packet(&frames, None).await {
+                Ok(received_data) => {
+                    failures = 0;
+                    poll_now = received_data;
+                }
+                Err(SendError::Final(err)) => {
+                    warn!("Closing STREAM connection: {}", err);
+                    self.connection.close(Err(err));
+                    return;
+                }
+                Err(SendError::Temporary(err)) => {
+                    failures += 1;
+                    if failures >= MAX_TEMPORARY_FAILURES {
+                        warn!("Closing STREAM connection after {} failures", failures);
+                        self.connection.close(Err(err));
+                        return;
+                    }
+                    delay_for(POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    /// Sends an unfulfillable Prepare carrying the frames and applies the frames of the reply.
+    /// Returns whether the reply carried any data.
+    async fn send_packet(
+        &mut self,
+        frames: &[OutgoingFrame],
+        source_account: Option<Address>,
+    ) -> Result<bool, SendError> {
+        let sequence = self.sequence;
+        let mut stream_frames: Vec<Frame> = frames.iter().map(OutgoingFrame::as_frame).collect();
+        if let Some(source_account) = source_account {
+            stream_frames.push(Frame::ConnectionNewAddress(ConnectionNewAddressFrame {
+                source_account,
+            }));
+        }
+        let stream_packet = StreamPacketBuilder {
+            ilp_packet_type: IlpPacketType::Prepare,
+            prepare_amount: 0,
+            sequence,
+            frames: &stream_frames,
+        }
+        .build();
+        debug!("Sending STREAM data packet: {:?}", stream_packet);
+        let data = stream_packet.into_encrypted(&self.shared_secret);
+        let prepare = PrepareBuilder {
+            destination: self.destination.clone(),
+            amount: 0,
+            // Data packets carry no money, so they are never fulfilled
+            execution_condition: &random_condition(),
+            expires_at: SystemTime::now() + Duration::from_secs(30),
+            data: &data[..],
+        }
+        .build();
+
+        let reply = self
+            .next
+            .handle_request(IncomingRequest {
+                from: self.from_account.clone(),
+                prepare,
+            })
+            .await;
+        let reply_data = match &reply {
+            Ok(fulfill) => fulfill.data(),
+            Err(reject) => reject.data(),
+        };
+
+        // The receiver only replies with a STREAM packet if it read ours
+        match StreamPacket::from_encrypted(&self.shared_secret, BytesMut::from(reply_data)) {
+            Ok(reply_packet) if reply_packet.sequence() == sequence => {
+                self.sequence += 1;
+                self.connection.acknowledge(frames);
+                Ok(self.connection.handle_frames(reply_packet.frames()))
+            }
+            _ => {
+                self.connection.retransmit(frames);
+                match reply {
+                    Err(reject) if reject.code().class() == ErrorClass::Final => {
+                        Err(SendError::Final(format!(
+                            "Packet was rejected with error: {} {}",
+                            reject.code(),
+                            str::from_utf8(reject.message()).unwrap_or_default(),
+                        )))
+                    }
+                    Err(reject) => Err(SendError::Temporary(format!(
+                        "Packet was rejected with error: {}",
+                        reject.code()
+                    ))),
+                    Ok(_) => Err(SendError::Temporary(
+                        "Unable to parse STREAM packet from the reply".to_string(),
+                    )),
+                }
+            }
+        }
+    }
+}
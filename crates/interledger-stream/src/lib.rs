@@ -4,23 +4,37 @@
 //!
 //! STREAM is responsible for splitting larger payments and messages into smaller chunks of money and data, and sending them over ILP.
 
+/// Buffers for the data sent and received on a stream
+mod buffer;
 /// Stream client
 mod client;
 /// Congestion controller consumed by the [stream client](./client/fn.send_money.html)
 mod congestion;
+/// Connections for sending and receiving data over STREAM
+mod connection;
 /// Cryptographic utilities for generating fulfillments and encrypting/decrypting STREAM packets
 mod crypto;
 /// Stream errors
 mod error;
 /// Stream Packet implementation, [as specified in the RFC](https://interledger.org/rfcs/0029-stream/#5-packet-and-frame-specification)
 mod packet;
+/// [STREAM receipts](https://interledger.org/rfcs/0039-stream-receipts/), signed by the receiver to prove how much it received
+mod receipt;
 /// A stream server implementing an [Outgoing Service](../interledger_service/trait.OutgoingService.html) for receiving STREAM payments from peers
 mod server;
 
-pub use client::{send_money, StreamDelivery};
+pub use client::{
+    quote_fixed_delivery, quote_fixed_source, resume_payment, send_money,
+    send_money_fixed_delivery, send_money_with_min_rate, start_payment,
+    start_payment_with_min_rate, PaymentHandle, PaymentProgress, PaymentState, PaymentStatus,
+    StreamDelivery, StreamQuote, StreamSourceQuote,
+};
+pub use connection::{DataStream, StreamConnection};
 pub use error::Error;
+pub use receipt::{Receipt, ReceiptDetails, ReceiptError};
 pub use server::{
-    ConnectionGenerator, PaymentNotification, StreamNotificationsStore, StreamReceiverService,
+    connection_token, ConnectionGenerator, PaymentNotification, StreamConnectionRecord,
+    StreamConnectionStore, StreamNotificationsStore, StreamReceiverService,
 };
 
 #[cfg(fuzzing)]
@@ -49,13 +63,17 @@ pub mod test_helpers {
     use super::*;
     use async_trait::async_trait;
     use futures::channel::mpsc::UnboundedSender;
-    use interledger_errors::{AccountStoreError, AddressStoreError, ExchangeRateStoreError};
+    use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
+    use interledger_errors::{
+        AccountStoreError, AddressStoreError, ExchangeRateStoreError, StreamConnectionStoreError,
+    };
     use interledger_packet::Address;
     use interledger_rates::ExchangeRateStore;
-    use interledger_router::RouterStore;
+    use interledger_router::{Route, RouterStore, RoutingTable};
     use interledger_service::{Account, AccountStore, AddressStore, Username};
-    use interledger_service_util::MaxPacketAmountAccount;
+    use interledger_service_util::{FeePolicy, FeePolicyStore, MaxPacketAmountAccount};
     use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
     use std::collections::HashMap;
     use std::str::FromStr;
     use std::sync::Arc;
@@ -105,6 +123,12 @@ pub mod test_helpers {
         }
     }
 
+    impl CcpRoutingAccount for TestAccount {
+        fn routing_relation(&self) -> RoutingRelation {
+            RoutingRelation::NonRoutingAccount
+        }
+    }
+
     #[derive(Clone)]
     pub struct DummyStore;
 
@@ -125,6 +149,79 @@ pub mod test_helpers {
         }
     }
 
+    /// Keeps the connections' state and the published notifications in memory
+    #[derive(Clone, Default)]
+    pub struct ConnectionStateStore {
+        pub connections: Arc<Mutex<HashMap<String, StreamConnectionRecord>>>,
+        pub notifications: Arc<Mutex<Vec<PaymentNotification>>>,
+    }
+
+    impl super::StreamNotificationsStore for ConnectionStateStore {
+        type Account = TestAccount;
+
+        fn add_payment_notification_subscription(
+            &self,
+            _account_id: Uuid,
+            _sender: UnboundedSender<PaymentNotification>,
+        ) {
+        }
+
+        fn publish_payment_notification(&self, payment: PaymentNotification) {
+            self.notifications.lock().push(payment);
+        }
+
+        fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification> {
+            broadcast::channel(1).1
+        }
+    }
+
+    #[async_trait]
+    impl StreamConnectionStore for ConnectionStateStore {
+        async fn set_stream_receive_max(
+            &self,
+            token: &str,
+            receive_max: u64,
+        ) -> Result<(), StreamConnectionStoreError> {
+            self.connections
+                .lock()
+                .entry(token.to_string())
+                .or_default()
+                .receive_max = Some(receive_max);
+            Ok(())
+        }
+
+        async fn get_stream_connection(
+            &self,
+            token: &str,
+        ) -> Result<Option<StreamConnectionRecord>, StreamConnectionStoreError> {
+            Ok(self.connections.lock().get(token).cloned())
+        }
+
+        async fn add_stream_received(
+            &self,
+            token: &str,
+            amount: u64,
+        ) -> Result<StreamConnectionRecord, StreamConnectionStoreError> {
+            let mut connections = self.connections.lock();
+            let record = connections.entry(token.to_string()).or_default();
+            record.add_received(token, amount)?;
+            Ok(*record)
+        }
+
+        async fn close_stream_connection(
+            &self,
+            token: &str,
+        ) -> Result<Option<StreamConnectionRecord>, StreamConnectionStoreError> {
+            match self.connections.lock().get_mut(token) {
+                Some(record) if !record.closed => {
+                    record.closed = true;
+                    Ok(Some(*record))
+                }
+                _ => Ok(None),
+            }
+        }
+    }
+
     #[derive(Clone)]
     pub struct TestStore {
         pub route: Option<(String, TestAccount)>,
@@ -153,11 +250,11 @@ pub mod test_helpers {
     }
 
     impl RouterStore for TestStore {
-        fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
+        fn routing_table(&self) -> Arc<RoutingTable<Route>> {
             Arc::new(
                 vec![(
                     self.route.clone().unwrap().0,
-                    self.route.clone().unwrap().1.id(),
+                    Route::from(self.route.clone().unwrap().1.id()),
                 )]
                 .into_iter()
                 .collect(),
@@ -205,19 +302,34 @@ pub mod test_helpers {
             unimplemented!("Cannot get all exchange rates")
         }
     }
+
+    impl FeePolicyStore for TestStore {
+        fn get_fee_policy(&self) -> Arc<FeePolicy> {
+            Arc::new(FeePolicy::default())
+        }
+
+        fn set_fee_policy(&self, _policy: FeePolicy) {
+            unimplemented!("Cannot set the fee policy")
+        }
+    }
 }
 
 #[cfg(test)]
 mod send_money_to_receiver {
     use super::test_helpers::*;
     use super::*;
+    use async_trait::async_trait;
     use bytes::Bytes;
     use interledger_packet::Address;
     use interledger_packet::{ErrorCode, RejectBuilder};
     use interledger_router::Router;
-    use interledger_service::outgoing_service_fn;
+    use interledger_service::{
+        outgoing_service_fn, Account, IlpResult, IncomingRequest, IncomingService,
+    };
     use interledger_service_util::ExchangeRateService;
     use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
     use uuid::Uuid;
 
     #[tokio::test]
@@ -347,4 +459,638 @@ mod send_money_to_receiver {
             _ => panic!("Payment should fail fast due to poor exchange rates"),
         }
     }
+
+    #[tokio::test]
+    async fn delivers_fixed_destination_amount() {
+        let server_secret = Bytes::from(&[0; 32][..]);
+        let source_address = Address::from_str("example.sender").unwrap();
+        let destination_address = Address::from_str("example.receiver").unwrap();
+
+        let sender_account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: source_address.clone(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 6,
+            max_packet_amount: None,
+        };
+
+        let recipient_account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: destination_address.clone(),
+            asset_code: "ABC".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+        };
+
+        let store = TestStore {
+            route: Some((destination_address.to_string(), recipient_account)),
+            price_1: Some(1.0),
+            price_2: Some(1.0),
+        };
+
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let server = StreamReceiverService::new(
+            server_secret,
+            DummyStore,
+            outgoing_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other outgoing handler",
+                    triggered_by: Some(&EXAMPLE_RECEIVER),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+
+        let server = ExchangeRateService::new(0.0, store.clone(), server);
+        let server = Router::new(store.clone(), server);
+
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&destination_address);
+
+        let quote = quote_fixed_delivery(
+            server.clone(),
+            &sender_account,
+            store.clone(),
+            destination_account.clone(),
+            shared_secret.to_vec(),
+            1_000_000,
+            0.01,
+        )
+        .await
+        .unwrap();
+
+        // Each source unit delivers 1000 destination units, so 1000 units are expected
+        // and the budget allows for 1% slippage
+        assert_eq!(
+            quote.probe_destination_amount,
+            quote.probe_source_amount * 1000
+        );
+        assert_eq!(quote.max_source_amount, 1011);
+        assert_eq!(quote.destination_asset_code, Some("ABC".to_string()));
+
+        let receipt = send_money_fixed_delivery(
+            server,
+            &sender_account,
+            store,
+            destination_account,
+            shared_secret.to_vec(),
+            1_000_000,
+            0.01,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(receipt.delivered_amount, 1_000_000);
+        assert_eq!(receipt.sent_amount, 1000);
+        assert_eq!(receipt.source_amount, 1011);
+    }
+
+    #[tokio::test]
+    async fn pays_quoted_source_amount() {
+        let server_secret = Bytes::from(&[0; 32][..]);
+        let source_address = Address::from_str("example.sender").unwrap();
+        let destination_address = Address::from_str("example.receiver").unwrap();
+
+        let sender_account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: source_address.clone(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 6,
+            max_packet_amount: None,
+        };
+
+        let recipient_account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: destination_address.clone(),
+            asset_code: "ABC".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+        };
+
+        let store = TestStore {
+            route: Some((destination_address.to_string(), recipient_account)),
+            price_1: Some(1.0),
+            price_2: Some(1.0),
+        };
+
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let server = StreamReceiverService::new(
+            server_secret,
+            DummyStore,
+            outgoing_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other outgoing handler",
+                    triggered_by: Some(&EXAMPLE_RECEIVER),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+
+        let server = ExchangeRateService::new(0.0, store.clone(), server);
+        let server = Router::new(store.clone(), server);
+
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&destination_address);
+
+        let quote = quote_fixed_source(
+            server.clone(),
+            &sender_account,
+            store.clone(),
+            destination_account.clone(),
+            shared_secret.to_vec(),
+            1000,
+            0.25,
+        )
+        .await
+        .unwrap();
+
+        // Each source unit delivers 1000 destination units, and the minimum rate allows for 25% slippage
+        assert_eq!(quote.estimated_destination_amount, 1_000_000);
+        assert_eq!(quote.min_destination_amount, 750_000);
+        assert_eq!(quote.min_exchange_rate, 750.0);
+        assert_eq!(quote.destination_asset_code, Some("ABC".to_string()));
+
+        let receipt = send_money_with_min_rate(
+            server.clone(),
+            &sender_account,
+            store.clone(),
+            destination_account.clone(),
+            shared_secret.to_vec(),
+            1000,
+            quote.min_exchange_rate,
+        )
+        .await
+        .unwrap();
+        assert_eq!(receipt.delivered_amount, 1_000_000);
+
+        // The path cannot provide a better rate than the one it was quoted
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&destination_address);
+        let result = send_money_with_min_rate(
+            server,
+            &sender_account,
+            store,
+            destination_account,
+            shared_secret.to_vec(),
+            1000,
+            1001.0,
+        )
+        .await;
+        match result {
+            Err(Error::SendMoneyError(message)) => {
+                assert!(message.starts_with("Exchange rate fell below the quoted rate"))
+            }
+            _ => panic!("Payment should stop when the minimum rate is violated"),
+        }
+    }
+
+    #[tokio::test]
+    async fn fixed_delivery_stops_if_rate_drops() {
+        /// Halves the amount of every packet after the first one, as if the rate dropped after the quote
+        #[derive(Clone)]
+        struct RateDropService<I> {
+            next: I,
+            num_requests: Arc<AtomicUsize>,
+        }
+
+        #[async_trait]
+        impl<I, A> IncomingService<A> for RateDropService<I>
+        where
+            I: IncomingService<A> + Send + Sync,
+            A: Account + 'static,
+        {
+            async fn handle_request(&mut self, mut request: IncomingRequest<A>) -> IlpResult {
+                if self.num_requests.fetch_add(1, Ordering::Relaxed) > 0 {
+                    let amount = request.prepare.amount();
+                    request.prepare.set_amount(amount / 2);
+                }
+                self.next.handle_request(request).await
+            }
+        }
+
+        let server_secret = Bytes::from(&[0; 32][..]);
+        let destination_address = Address::from_str("example.receiver").unwrap();
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: destination_address.clone(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+        };
+        let store = TestStore {
+            route: Some((destination_address.to_string(), account.clone())),
+            price_1: None,
+            price_2: None,
+        };
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let server = StreamReceiverService::new(
+            server_secret,
+            DummyStore,
+            outgoing_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other outgoing handler",
+                    triggered_by: Some(&EXAMPLE_RECEIVER),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        let server = RateDropService {
+            next: Router::new(store.clone(), server),
+            num_requests: Arc::new(AtomicUsize::new(0)),
+        };
+
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&destination_address);
+
+        let result = send_money_fixed_delivery(
+            server,
+            &account,
+            store,
+            destination_account,
+            shared_secret.to_vec(),
+            1000,
+            0.01,
+        )
+        .await;
+
+        match result {
+            Err(Error::SendMoneyError(message)) => {
+                assert!(message.starts_with("Exchange rate fell below the quoted rate"))
+            }
+            _ => panic!("Payment should stop when the quoted rate is violated"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod payment_handles {
+    use super::test_helpers::*;
+    use super::*;
+    use bytes::Bytes;
+    use interledger_packet::{Address, ErrorCode, RejectBuilder};
+    use interledger_router::Router;
+    use interledger_service::{outgoing_service_fn, IncomingService};
+    use std::str::FromStr;
+    use tokio::time::{delay_for, Duration};
+    use uuid::Uuid;
+
+    fn test_receiver() -> (
+        TestAccount,
+        Address,
+        [u8; 32],
+        impl IncomingService<TestAccount> + Clone + Send + Sync + 'static,
+    ) {
+        let server_secret = Bytes::from(&[0; 32][..]);
+        let destination_address = Address::from_str("example.receiver").unwrap();
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: destination_address.clone(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+        };
+        let store = TestStore {
+            route: Some((destination_address.to_string(), account.clone())),
+            price_1: None,
+            price_2: None,
+        };
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let server = StreamReceiverService::new(
+            server_secret,
+            DummyStore,
+            outgoing_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other outgoing handler",
+                    triggered_by: Some(&EXAMPLE_RECEIVER),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        let server = Router::new(store, server);
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&destination_address);
+        (account, destination_account, shared_secret, server)
+    }
+
+    fn no_rates() -> TestStore {
+        TestStore {
+            route: None,
+            price_1: None,
+            price_2: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn emits_progress_events() {
+        let (account, destination_account, shared_secret, server) = test_receiver();
+        let handle = start_payment(
+            server,
+            &account,
+            no_rates(),
+            destination_account,
+            shared_secret.to_vec(),
+            100,
+            0.0,
+        );
+        let mut progress = handle.subscribe();
+
+        let receipt = handle.finish().await.unwrap();
+        assert_eq!(receipt.delivered_amount, 100);
+
+        let mut last = None;
+        while let Ok(event) = progress.recv().await {
+            assert_eq!(event.rate, 1.0);
+            last = Some(event);
+        }
+        let last = last.unwrap();
+        assert_eq!(last.delivered_amount, 100);
+        assert_eq!(last.sent_amount, 100);
+    }
+
+    #[tokio::test]
+    async fn pauses_and_resumes() {
+        let (account, destination_account, shared_secret, server) = test_receiver();
+        let handle = start_payment(
+            server,
+            &account,
+            no_rates(),
+            destination_account,
+            shared_secret.to_vec(),
+            100,
+            0.0,
+        );
+        handle.pause();
+        delay_for(Duration::from_millis(50)).await;
+
+        let state = handle.state().await;
+        assert_eq!(state.status, PaymentStatus::Paused);
+        assert_eq!(state.delivery.delivered_amount, 0);
+
+        handle.resume();
+        let receipt = handle.finish().await.unwrap();
+        assert_eq!(receipt.delivered_amount, 100);
+    }
+
+    #[tokio::test]
+    async fn cancels_payment() {
+        let (account, destination_account, shared_secret, server) = test_receiver();
+        let handle = start_payment(
+            server,
+            &account,
+            no_rates(),
+            destination_account,
+            shared_secret.to_vec(),
+            100,
+            0.0,
+        );
+        handle.pause();
+        delay_for(Duration::from_millis(50)).await;
+        handle.cancel();
+        delay_for(Duration::from_millis(50)).await;
+
+        let state = handle.state().await;
+        assert_eq!(state.status, PaymentStatus::Cancelled);
+        let receipt = handle.finish().await.unwrap();
+        assert_eq!(receipt.delivered_amount, 0);
+    }
+
+    #[tokio::test]
+    async fn resumes_from_persisted_delivery() {
+        let (account, destination_account, shared_secret, server) = test_receiver();
+        // Saved while 10 were in flight, which count as sent but not delivered
+        let mut delivery = StreamDelivery::new(&account, destination_account, 100);
+        delivery.sent_amount = 50;
+        delivery.in_flight_amount = 10;
+        delivery.delivered_amount = 40;
+
+        let handle = resume_payment(
+            server,
+            &account,
+            no_rates(),
+            shared_secret.to_vec(),
+            delivery,
+            0.0,
+        );
+        let receipt = handle.finish().await.unwrap();
+        assert_eq!(receipt.sent_amount, 100);
+        assert_eq!(receipt.in_flight_amount, 0);
+        assert_eq!(receipt.delivered_amount, 90);
+    }
+}
+
+#[cfg(test)]
+mod stream_data_to_receiver {
+    use super::packet::{Frame, StreamPacket};
+    use super::test_helpers::*;
+    use super::*;
+    use async_trait::async_trait;
+    use bytes::{Bytes, BytesMut};
+    use futures::channel::mpsc::UnboundedReceiver;
+    use futures::StreamExt;
+    use interledger_packet::{Address, ErrorCode, RejectBuilder};
+    use interledger_router::Router;
+    use interledger_service::{
+        outgoing_service_fn, Account, IlpResult, IncomingRequest, IncomingService,
+    };
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use uuid::Uuid;
+
+    fn test_account() -> TestAccount {
+        TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: Address::from_str("example.receiver").unwrap(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+        }
+    }
+
+    /// Returns the receiver routed to from the test account, the connections it accepts,
+    /// and the destination and shared secret of a connection to it
+    fn test_receiver() -> (
+        impl IncomingService<TestAccount> + Clone + Send + Sync + 'static,
+        UnboundedReceiver<StreamConnection>,
+        Address,
+        [u8; 32],
+    ) {
+        let server_secret = Bytes::from(&[0; 32][..]);
+        let account = test_account();
+        let store = TestStore {
+            route: Some((account.ilp_address.to_string(), account.clone())),
+            price_1: None,
+            price_2: None,
+        };
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let mut server = StreamReceiverService::new(
+            server_secret,
+            DummyStore,
+            outgoing_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other outgoing handler",
+                    triggered_by: Some(&EXAMPLE_RECEIVER),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        let connections = server.incoming_connections();
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&account.ilp_address);
+        (
+            Router::new(store, server),
+            connections,
+            destination_account,
+            shared_secret,
+        )
+    }
+
+    #[tokio::test]
+    async fn sends_data_both_ways() {
+        let account = test_account();
+        let (server, mut connections, destination_account, shared_secret) = test_receiver();
+
+        let connection = StreamConnection::connect(
+            server,
+            account,
+            destination_account.clone(),
+            shared_secret.to_vec(),
+        )
+        .await
+        .unwrap();
+        let mut stream = connection.open_stream();
+        stream.write_all(b"invoice 42").await.unwrap();
+        stream.flush().await.unwrap();
+
+        let mut incoming = connections.next().await.unwrap();
+        assert_eq!(incoming.destination(), &destination_account);
+        let mut incoming_stream = incoming.accept_stream().await.unwrap();
+        assert_eq!(incoming_stream.id(), stream.id());
+        let mut buf = [0; 10];
+        incoming_stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"invoice 42");
+
+        // The receiver's data goes back in its replies to the sender's packets
+        incoming_stream.write_all(b"thanks").await.unwrap();
+        drop(incoming_stream);
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(reply, b"thanks");
+
+        connection.close().await.unwrap();
+        assert!(incoming.accept_stream().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn resends_data_when_a_reply_is_lost() {
+        /// Turns the first reply carrying data into a temporary error, as if it got lost
+        #[derive(Clone)]
+        struct LoseReplyService<I> {
+            next: I,
+            shared_secret: [u8; 32],
+            lost: Arc<AtomicBool>,
+        }
+
+        #[async_trait]
+        impl<I, A> IncomingService<A> for LoseReplyService<I>
+        where
+            I: IncomingService<A> + Send + Sync,
+            A: Account + 'static,
+        {
+            async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+                let reply = self.next.handle_request(request).await;
+                let data = match reply {
+                    Ok(ref fulfill) => fulfill.data(),
+                    Err(ref reject) => reject.data(),
+                };
+                let has_data =
+                    StreamPacket::from_encrypted(&self.shared_secret, BytesMut::from(data))
+                        .map(|packet| {
+                            packet
+                                .frames()
+                                .any(|frame| matches!(frame, Frame::StreamData(_)))
+                        })
+                        .unwrap_or(false);
+                if has_data && !self.lost.swap(true, Ordering::SeqCst) {
+                    return Err(RejectBuilder {
+                        code: ErrorCode::T00_INTERNAL_ERROR,
+                        message: b"Reply lost",
+                        triggered_by: None,
+                        data: &[],
+                    }
+                    .build());
+                }
+                reply
+            }
+        }
+
+        let account = test_account();
+        let (server, mut connections, destination_account, shared_secret) = test_receiver();
+        let lost = Arc::new(AtomicBool::new(false));
+        let server = LoseReplyService {
+            next: server,
+            shared_secret,
+            lost: lost.clone(),
+        };
+
+        let connection =
+            StreamConnection::connect(server, account, destination_account, shared_secret.to_vec())
+                .await
+                .unwrap();
+        let mut stream = connection.open_stream();
+        stream.write_all(b"ping").await.unwrap();
+        stream.flush().await.unwrap();
+
+        let mut incoming = connections.next().await.unwrap();
+        let mut incoming_stream = incoming.accept_stream().await.unwrap();
+        let mut buf = [0; 4];
+        incoming_stream.read_exact(&mut buf).await.unwrap();
+        incoming_stream.write_all(b"pong").await.unwrap();
+        drop(incoming_stream);
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await.unwrap();
+        assert!(lost.load(Ordering::SeqCst));
+        assert_eq!(reply, b"pong");
+        connection.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn opens_more_streams_as_the_receiver_closes_them() {
+        let account = test_account();
+        let (server, mut connections, destination_account, shared_secret) = test_receiver();
+
+        let connection =
+            StreamConnection::connect(server, account, destination_account, shared_secret.to_vec())
+                .await
+                .unwrap();
+        // More than the receiver lets the sender open at once
+        for i in 0..20 {
+            let mut stream = connection.open_stream();
+            stream
+                .write_all(format!("stream {}", i).as_bytes())
+                .await
+                .unwrap();
+        }
+
+        let mut incoming = connections.next().await.unwrap();
+        for i in 0..20 {
+            let mut incoming_stream = incoming.accept_stream().await.unwrap();
+            let mut data = Vec::new();
+            incoming_stream.read_to_end(&mut data).await.unwrap();
+            assert_eq!(data, format!("stream {}", i).as_bytes());
+        }
+        connection.close().await.unwrap();
+    }
 }
@@ -98,6 +98,10 @@ impl<'a> StreamPacketBuilder<'a> {
                     buffer_unencrypted.put_u8(FrameType::StreamDataBlocked as u8);
                     frame.put_contents(&mut contents);
                 }
+                Frame::StreamReceipt(ref frame) => {
+                    buffer_unencrypted.put_u8(FrameType::StreamReceipt as u8);
+                    frame.put_contents(&mut contents);
+                }
                 Frame::Unknown => continue,
             }
             buffer_unencrypted.put_var_octet_string(&*contents);
@@ -288,6 +292,9 @@ impl<'a> FrameIterator<'a> {
             FrameType::StreamDataBlocked => {
                 Frame::StreamDataBlocked(StreamDataBlockedFrame::read_contents(&contents)?)
             }
+            FrameType::StreamReceipt => {
+                Frame::StreamReceipt(StreamReceiptFrame::read_contents(&contents)?)
+            }
             FrameType::Unknown => {
                 warn!(
                     "Ignoring unknown frame of type {}: {:x?}",
@@ -350,6 +357,7 @@ pub enum Frame<'a> {
     StreamData(StreamDataFrame<'a>),
     StreamMaxData(StreamMaxDataFrame),
     StreamDataBlocked(StreamDataBlockedFrame),
+    StreamReceipt(StreamReceiptFrame<'a>),
     Unknown,
 }
 
@@ -370,6 +378,7 @@ impl<'a> fmt::Debug for Frame<'a> {
             Frame::StreamData(frame) => write!(f, "{:?}", frame),
             Frame::StreamMaxData(frame) => write!(f, "{:?}", frame),
             Frame::StreamDataBlocked(frame) => write!(f, "{:?}", frame),
+            Frame::StreamReceipt(frame) => write!(f, "{:?}", frame),
             Frame::Unknown => write!(f, "UnknownFrame"),
         }
     }
@@ -393,6 +402,7 @@ pub enum FrameType {
     StreamData = 0x14,
     StreamMaxData = 0x15,
     StreamDataBlocked = 0x16,
+    StreamReceipt = 0x17,
     Unknown,
 }
 
@@ -413,6 +423,7 @@ impl From<u8> for FrameType {
             0x14 => FrameType::StreamData,
             0x15 => FrameType::StreamMaxData,
             0x16 => FrameType::StreamDataBlocked,
+            0x17 => FrameType::StreamReceipt,
             _ => FrameType::Unknown,
         }
     }
@@ -855,6 +866,30 @@ impl<'a> SerializableFrame<'a> for StreamDataBlockedFrame {
     }
 }
 
+/// Frame carrying a [STREAM receipt](https://interledger.org/rfcs/0039-stream-receipts/)
+/// for the money received on a stream so far
+#[derive(Debug, PartialEq, Clone)]
+pub struct StreamReceiptFrame<'a> {
+    /// Identifier of the stream this frame refers to.
+    pub stream_id: u64,
+    /// The receipt, signed by the receiver with the connection's receipt secret
+    pub receipt: &'a [u8],
+}
+
+impl<'a> SerializableFrame<'a> for StreamReceiptFrame<'a> {
+    fn read_contents(mut reader: &'a [u8]) -> Result<Self, ParseError> {
+        let stream_id = reader.read_var_uint()?;
+        let receipt = reader.read_var_octet_string()?;
+
+        Ok(StreamReceiptFrame { stream_id, receipt })
+    }
+
+    fn put_contents(&self, buf: &mut impl MutBufOerExt) {
+        buf.put_var_uint(self.stream_id);
+        buf.put_var_octet_string(self.receipt);
+    }
+}
+
 /// See: https://github.com/interledger/rfcs/blob/master/0029-stream/0029-stream.md#514-maximum-varuint-size
 fn saturating_read_var_uint<'a>(reader: &mut impl BufOerExt<'a>) -> Result<u64, ParseError> {
     if reader.peek_var_octet_string()?.len() > 8 {
@@ -1041,6 +1076,31 @@ mod serialization {
         assert_eq!(iter.count(), 12);
     }
 
+    #[test]
+    fn it_roundtrips_receipt_frames() {
+        let receipt = [3; 58];
+        let packet = StreamPacketBuilder {
+            sequence: 2,
+            ilp_packet_type: IlpPacketType::Fulfill,
+            prepare_amount: 10,
+            frames: &[Frame::StreamReceipt(StreamReceiptFrame {
+                stream_id: 1,
+                receipt: &receipt,
+            })],
+        }
+        .build();
+        let parsed =
+            StreamPacket::from_bytes_unencrypted(packet.buffer_unencrypted.clone()).unwrap();
+        assert_eq!(parsed, packet);
+        assert_eq!(
+            parsed.frames().next().unwrap(),
+            Frame::StreamReceipt(StreamReceiptFrame {
+                stream_id: 1,
+                receipt: &receipt,
+            })
+        );
+    }
+
     #[test]
     fn it_saturates_max_money_frame_receive_max() {
         let mut buffer = BytesMut::new();
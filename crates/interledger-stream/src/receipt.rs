@@ -0,0 +1,175 @@
+use super::crypto::{hmac_sha256, verify_hmac_sha256};
+use byteorder::{BigEndian, ReadBytesExt};
+use bytes::{BufMut, Bytes};
+use interledger_packet::oer::{BufOerExt, MutBufOerExt};
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// The version of the receipt format
+const RECEIPT_VERSION: u8 = 1;
+pub const RECEIPT_NONCE_LENGTH: usize = 16;
+pub const RECEIPT_SECRET_LENGTH: usize = 32;
+const SIGNATURE_LENGTH: usize = 32;
+
+/// The nonce and secret a receiver uses to sign the receipts of a STREAM connection.
+///
+/// These are usually provided by the party that wants to verify the receipts
+/// (for example in the SPSP query), and are encoded in the connection's
+/// destination address so that the receiver can recover them from each packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReceiptDetails {
+    pub nonce: [u8; RECEIPT_NONCE_LENGTH],
+    pub secret: [u8; RECEIPT_SECRET_LENGTH],
+}
+
+impl ReceiptDetails {
+    /// Generates a random nonce and secret
+    pub fn random() -> Self {
+        let rng = SystemRandom::new();
+        let mut nonce = [0; RECEIPT_NONCE_LENGTH];
+        let mut secret = [0; RECEIPT_SECRET_LENGTH];
+        rng.fill(&mut nonce)
+            .and_then(|_| rng.fill(&mut secret))
+            .expect("Failed to securely generate receipt details!");
+        ReceiptDetails { nonce, secret }
+    }
+}
+
+/// A [STREAM receipt](https://interledger.org/rfcs/0039-stream-receipts/), proving that
+/// the receiver got `total_received` units on the given stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Receipt {
+    pub nonce: [u8; RECEIPT_NONCE_LENGTH],
+    pub stream_id: u64,
+    /// Total amount received on the stream so far, in the receiver's units
+    pub total_received: u64,
+}
+
+/// Errors returned when a receipt cannot be decoded or verified
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum ReceiptError {
+    #[error("Invalid receipt: {0}")]
+    InvalidReceipt(String),
+    #[error("Unsupported receipt version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("Invalid receipt signature")]
+    InvalidSignature,
+}
+
+impl Receipt {
+    /// Serializes the receipt and appends the HMAC-SHA256 signature made with the receipt secret
+    pub fn sign(&self, secret: &[u8]) -> Bytes {
+        let mut buffer = Vec::with_capacity(RECEIPT_NONCE_LENGTH + SIGNATURE_LENGTH + 18);
+        buffer.put_u8(RECEIPT_VERSION);
+        buffer.put_slice(&self.nonce);
+        buffer.put_var_uint(self.stream_id);
+        buffer.put_u64(self.total_received);
+        let signature = hmac_sha256(secret, &buffer);
+        buffer.put_slice(&signature);
+        Bytes::from(buffer)
+    }
+
+    /// Decodes a receipt **without** checking its signature.
+    ///
+    /// This is meant for senders, which do not know the receipt secret but
+    /// want to keep the receipt with the highest total.
+    pub fn decode(receipt: &[u8]) -> Result<Self, ReceiptError> {
+        Self::decode_with_signature(receipt).map(|(receipt, _, _)| receipt)
+    }
+
+    /// Decodes the receipt and checks that it was signed with the given receipt secret
+    pub fn verify(receipt: &[u8], secret: &[u8]) -> Result<Self, ReceiptError> {
+        let (decoded, message, signature) = Self::decode_with_signature(receipt)?;
+        if verify_hmac_sha256(secret, message, signature) {
+            Ok(decoded)
+        } else {
+            Err(ReceiptError::InvalidSignature)
+        }
+    }
+
+    /// Returns the receipt together with the signed bytes and the signature
+    fn decode_with_signature(receipt: &[u8]) -> Result<(Self, &[u8], &[u8]), ReceiptError> {
+        let invalid = |err: std::io::Error| ReceiptError::InvalidReceipt(err.to_string());
+        let mut reader = receipt;
+
+        let version = reader.read_u8().map_err(invalid)?;
+        if version != RECEIPT_VERSION {
+            return Err(ReceiptError::UnsupportedVersion(version));
+        }
+        if reader.len() < RECEIPT_NONCE_LENGTH {
+            return Err(ReceiptError::InvalidReceipt("too short".to_string()));
+        }
+        let mut nonce = [0; RECEIPT_NONCE_LENGTH];
+        nonce.copy_from_slice(&reader[..RECEIPT_NONCE_LENGTH]);
+        reader = &reader[RECEIPT_NONCE_LENGTH..];
+        let stream_id = reader
+            .read_var_uint()
+            .map_err(|err| ReceiptError::InvalidReceipt(err.to_string()))?;
+        let total_received = reader.read_u64::<BigEndian>().map_err(invalid)?;
+
+        if reader.len() != SIGNATURE_LENGTH {
+            return Err(ReceiptError::InvalidReceipt(format!(
+                "expected a {} byte signature, got {} bytes",
+                SIGNATURE_LENGTH,
+                reader.len()
+            )));
+        }
+        let message = &receipt[..receipt.len() - SIGNATURE_LENGTH];
+
+        Ok((
+            Receipt {
+                nonce,
+                stream_id,
+                total_received,
+            },
+            message,
+            reader,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: [u8; 32] = [7; 32];
+
+    fn receipt() -> Receipt {
+        Receipt {
+            nonce: [1; 16],
+            stream_id: 1,
+            total_received: 500,
+        }
+    }
+
+    #[test]
+    fn signs_and_verifies_receipts() {
+        let signed = receipt().sign(&SECRET);
+        // version, nonce, stream id, total received and signature
+        assert_eq!(signed.len(), 1 + 16 + 2 + 8 + 32);
+        assert_eq!(Receipt::verify(&signed, &SECRET).unwrap(), receipt());
+        assert_eq!(Receipt::decode(&signed).unwrap(), receipt());
+    }
+
+    #[test]
+    fn rejects_receipts_signed_with_another_secret() {
+        let signed = receipt().sign(&[8; 32]);
+        assert_eq!(
+            Receipt::verify(&signed, &SECRET).unwrap_err(),
+            ReceiptError::InvalidSignature
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_receipts() {
+        let signed = receipt().sign(&SECRET);
+        assert!(Receipt::verify(&signed[..signed.len() - 1], &SECRET).is_err());
+        assert!(Receipt::decode(&[]).is_err());
+
+        let mut other_version = signed.to_vec();
+        other_version[0] = 2;
+        assert_eq!(
+            Receipt::decode(&other_version).unwrap_err(),
+            ReceiptError::UnsupportedVersion(2)
+        );
+    }
+}
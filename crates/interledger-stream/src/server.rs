@@ -1,19 +1,25 @@
+use super::connection::{OutgoingFrame, SharedConnection, StreamConnection, MAX_REPLY_DATA};
 use super::crypto::*;
-use super::packet::*;
+use super::packet::{ErrorCode as StreamErrorCode, *};
+use super::receipt::*;
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
 use chrono::{DateTime, Utc};
-use futures::channel::mpsc::UnboundedSender;
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use interledger_errors::StreamConnectionStoreError;
 use interledger_packet::{
     hex::HexString, Address, ErrorCode, Fulfill, FulfillBuilder, PacketType as IlpPacketType,
     Prepare, Reject, RejectBuilder,
 };
 use interledger_service::{Account, IlpResult, OutgoingRequest, OutgoingService, Username};
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::marker::PhantomData;
-use std::time::SystemTime;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::broadcast;
-use tracing::debug;
+use tracing::{debug, error};
 use uuid::Uuid;
 
 // Note we are using the same magic bytes as the Javascript
@@ -24,6 +30,18 @@ use uuid::Uuid;
 // running the same STREAM implementation so it doesn't matter what
 // this string is.
 const STREAM_SERVER_SECRET_GENERATOR: &[u8] = b"ilp_stream_shared_secret";
+/// Used to derive the key which encrypts the receipt nonce and secret in the connection token
+const STREAM_SERVER_RECEIPT_KEY_GENERATOR: &[u8] = b"ilp_stream_receipt_details";
+/// Length of the random part of the connection token
+const TOKEN_LENGTH: usize = 18;
+/// Number of data connections the receiver keeps at once
+const MAX_DATA_CONNECTIONS: usize = 1024;
+/// Data connections which got no packet for this long are closed
+const DATA_CONNECTION_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// Number of connections whose stream totals are kept for their receipts
+const MAX_RECEIPT_CONNECTIONS: usize = 10_000;
+/// The stream totals of connections which received nothing for this long may be dropped
+const RECEIPT_TOTALS_TTL: Duration = Duration::from_secs(3600);
 
 /// A STREAM connection generator that creates `destination_account` and `shared_secret` values
 /// based on a single root secret.
@@ -33,6 +51,7 @@ const STREAM_SERVER_SECRET_GENERATOR: &[u8] = b"ilp_stream_shared_secret";
 #[derive(Clone)]
 pub struct ConnectionGenerator {
     secret_generator: [u8; 32],
+    receipt_key: [u8; 32],
 }
 
 impl ConnectionGenerator {
@@ -40,9 +59,11 @@ impl ConnectionGenerator {
         assert_eq!(server_secret.len(), 32, "Server secret must be 32 bytes");
 
         let secret = hmac_sha256(&server_secret[..], STREAM_SERVER_SECRET_GENERATOR);
+        let receipt_key = hmac_sha256(&server_secret[..], STREAM_SERVER_RECEIPT_KEY_GENERATOR);
 
         ConnectionGenerator {
             secret_generator: secret,
+            receipt_key,
         }
     }
 
@@ -51,7 +72,34 @@ impl ConnectionGenerator {
     /// The `destination_account` is generated such that the `shared_secret` can be re-derived
     /// from a Prepare packet's destination and the same server secret.
     pub fn generate_address_and_secret(&self, base_address: &Address) -> (Address, [u8; 32]) {
-        let token = base64::encode_config(&generate_token(), base64::URL_SAFE_NO_PAD);
+        self.generate_address_and_secret_from_token(base_address, &generate_token())
+    }
+
+    /// Like [`generate_address_and_secret`](#method.generate_address_and_secret), but the
+    /// receiver will attach STREAM receipts signed with the given details to the packets it
+    /// fulfills for this connection.
+    ///
+    /// The receipt nonce and secret are encrypted and appended to the connection token,
+    /// so they can be recovered with [`receipt_details`](#method.receipt_details).
+    pub fn generate_address_and_secret_with_receipts(
+        &self,
+        base_address: &Address,
+        receipt_details: &ReceiptDetails,
+    ) -> (Address, [u8; 32]) {
+        let mut plaintext = BytesMut::with_capacity(RECEIPT_NONCE_LENGTH + RECEIPT_SECRET_LENGTH);
+        plaintext.extend_from_slice(&receipt_details.nonce);
+        plaintext.extend_from_slice(&receipt_details.secret);
+        let mut token = BytesMut::from(&generate_token()[..]);
+        token.unsplit(encrypt(&self.receipt_key, plaintext));
+        self.generate_address_and_secret_from_token(base_address, &token)
+    }
+
+    fn generate_address_and_secret_from_token(
+        &self,
+        base_address: &Address,
+        token: &[u8],
+    ) -> (Address, [u8; 32]) {
+        let token = base64::encode_config(token, base64::URL_SAFE_NO_PAD);
         // Note the shared secret is generated from the base64-encoded version of the token,
         // rather than from the unencoded bytes
         let shared_secret = hmac_sha256(&self.secret_generator[..], token.as_bytes());
@@ -82,6 +130,28 @@ impl ConnectionGenerator {
         // rather than decoding the base64 first.
         hmac_sha256(&self.secret_generator[..], local_part.as_bytes())
     }
+
+    /// Recover the receipt nonce and secret from a `destination_account` generated by
+    /// [`generate_address_and_secret_with_receipts`](#method.generate_address_and_secret_with_receipts).
+    ///
+    /// Returns `None` if the connection was set up without receipts.
+    pub fn receipt_details(&self, destination_account: &Address) -> Option<ReceiptDetails> {
+        let local_part = destination_account.segments().rev().next()?;
+        let mut token =
+            BytesMut::from(&base64::decode_config(local_part, base64::URL_SAFE_NO_PAD).ok()?[..]);
+        if token.len() <= TOKEN_LENGTH {
+            return None;
+        }
+        let details = decrypt(&self.receipt_key, token.split_off(TOKEN_LENGTH)).ok()?;
+        if details.len() != RECEIPT_NONCE_LENGTH + RECEIPT_SECRET_LENGTH {
+            return None;
+        }
+        let mut nonce = [0; RECEIPT_NONCE_LENGTH];
+        let mut secret = [0; RECEIPT_SECRET_LENGTH];
+        nonce.copy_from_slice(&details[..RECEIPT_NONCE_LENGTH]);
+        secret.copy_from_slice(&details[RECEIPT_NONCE_LENGTH..]);
+        Some(ReceiptDetails { nonce, secret })
+    }
 }
 
 /// Notification that STREAM fulfilled a packet and received a single Interledger payment, used by Pubsub API consumers
@@ -109,6 +179,8 @@ pub struct PaymentNotification {
 struct ReceiveOk {
     fulfill: Fulfill,
     sequence: u64,
+    /// Whether we closed the connection because it received all it may receive
+    connection_closed: bool,
 }
 
 #[derive(Debug)]
@@ -138,18 +210,181 @@ pub trait StreamNotificationsStore {
     fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification>;
 }
 
+/// What a stateful receiver knows about a STREAM connection
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StreamConnectionRecord {
+    /// Total amount received on the connection so far
+    pub total_received: u64,
+    /// The most the connection may receive, if it is limited
+    pub receive_max: Option<u64>,
+    /// Whether the connection was closed, after which it does not receive anything
+    pub closed: bool,
+}
+
+impl StreamConnectionRecord {
+    /// How much more the connection may receive
+    pub fn remaining(&self) -> Option<u64> {
+        self.receive_max
+            .map(|max| max.saturating_sub(self.total_received))
+    }
+
+    /// Adds the amount to the total received, unless the connection is closed
+    /// or the amount is more than it may still receive.
+    ///
+    /// Stores can use this to implement
+    /// [`add_stream_received`](./trait.StreamConnectionStore.html#tymethod.add_stream_received).
+    pub fn add_received(
+        &mut self,
+        token: &str,
+        amount: u64,
+    ) -> Result<(), StreamConnectionStoreError> {
+        if self.closed {
+            return Err(StreamConnectionStoreError::ConnectionClosed(
+                token.to_string(),
+            ));
+        }
+        match self.receive_max {
+            Some(receive_max) if self.total_received.saturating_add(amount) > receive_max => {
+                Err(StreamConnectionStoreError::ReceiveMaxExceeded {
+                    token: token.to_string(),
+                    total_received: self.total_received,
+                    receive_max,
+                })
+            }
+            _ => {
+                self.total_received = self.total_received.saturating_add(amount);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A store which keeps the state of STREAM connections, so that a receiver
+/// can track and limit how much each connection receives.
+///
+/// Connections are identified by their token, the last segment of
+/// their destination address (see [`connection_token`](./fn.connection_token.html)).
+/// Connections which were not registered with a `receive_max` are created
+/// on the first amount they receive and are not limited.
+#[async_trait]
+pub trait StreamConnectionStore {
+    /// Limits how much the connection may receive in total, for example
+    /// to accept exactly the amount of an invoice
+    async fn set_stream_receive_max(
+        &self,
+        token: &str,
+        receive_max: u64,
+    ) -> Result<(), StreamConnectionStoreError>;
+
+    /// Loads the state of the connection, if it received anything or was registered
+    async fn get_stream_connection(
+        &self,
+        token: &str,
+    ) -> Result<Option<StreamConnectionRecord>, StreamConnectionStoreError>;
+
+    /// Atomically adds the amount to the connection's total and returns the new state.
+    ///
+    /// Fails without changing anything if the connection is closed
+    /// or if the total would go over its `receive_max`.
+    async fn add_stream_received(
+        &self,
+        token: &str,
+        amount: u64,
+    ) -> Result<StreamConnectionRecord, StreamConnectionStoreError>;
+
+    /// Marks the connection as closed and returns its final state,
+    /// or `None` if it was unknown or already closed
+    async fn close_stream_connection(
+        &self,
+        token: &str,
+    ) -> Result<Option<StreamConnectionRecord>, StreamConnectionStoreError>;
+}
+
+/// Returns the token which identifies the connection of a STREAM destination address
+pub fn connection_token(destination: &Address) -> &str {
+    destination.segments().rev().next().unwrap_or_default()
+}
+
 /// An OutgoingService that fulfills incoming STREAM packets.
 ///
 /// Note this does **not** maintain STREAM state, but instead fulfills
 /// all incoming packets to collect the money.
 ///
-/// This does not currently support handling data sent via STREAM.
+/// The only state kept is the total received on each stream of the connections
+/// which were set up with receipts, so that the receipts can carry it. It is
+/// dropped when the sender closes the connection. It is kept for at most 10,000
+/// connections at once, dropping the ones which were paid the longest time ago first.
+///
+/// Data sent via STREAM is ignored unless the application asks for the
+/// [`incoming_connections`](#method.incoming_connections).
+///
+/// With [`with_connection_state`](#method.with_connection_state), the receiver
+/// instead keeps the total received by each connection in the store. It then
+/// enforces the connection's `receive_max`, closes the connection once it is
+/// reached, and publishes a single notification per connection when it closes.
 #[derive(Clone)]
 pub struct StreamReceiverService<S, O: OutgoingService<A>, A: Account> {
     connection_generator: ConnectionGenerator,
     next: O,
     account_type: PhantomData<A>,
     store: S,
+    receipt_totals: Arc<Mutex<ReceiptTotals>>,
+    data_connections: Option<DataConnections>,
+    /// Set in stateful mode, where the connections' totals are kept in the store
+    connection_store: Option<Arc<dyn StreamConnectionStore + Send + Sync>>,
+}
+
+/// Total amount received on each stream of a connection
+type StreamTotals = HashMap<u64, u64>;
+
+/// Total received per stream of the connections with receipts, keyed by connection token.
+///
+/// When there are too many connections, those which received nothing for a while are
+/// dropped, or else the one which received something the longest time ago. The receipts of
+/// a dropped connection which receives more only carry what it received since.
+struct ReceiptTotals {
+    connections: HashMap<String, (StreamTotals, Instant)>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl ReceiptTotals {
+    fn new(capacity: usize, ttl: Duration) -> Self {
+        ReceiptTotals {
+            connections: HashMap::new(),
+            capacity,
+            ttl,
+        }
+    }
+
+    /// The totals of the connection's streams, making room for them if the connection is new
+    fn get_mut(&mut self, token: &str) -> &mut StreamTotals {
+        if !self.connections.contains_key(token) && self.connections.len() >= self.capacity {
+            let ttl = self.ttl;
+            self.connections
+                .retain(|_, (_, last_received)| last_received.elapsed() < ttl);
+            if self.connections.len() >= self.capacity {
+                let oldest = self
+                    .connections
+                    .iter()
+                    .min_by_key(|(_, (_, last_received))| *last_received)
+                    .map(|(token, _)| token.clone());
+                if let Some(oldest) = oldest {
+                    self.connections.remove(&oldest);
+                }
+            }
+        }
+        let (totals, last_received) = self
+            .connections
+            .entry(token.to_string())
+            .or_insert_with(|| (StreamTotals::new(), Instant::now()));
+        *last_received = Instant::now();
+        totals
+    }
+
+    fn remove(&mut self, token: &str) {
+        self.connections.remove(token);
+    }
 }
 
 impl<S, O, A> StreamReceiverService<S, O, A>
@@ -165,8 +400,138 @@ where
             next,
             account_type: PhantomData,
             store,
+            receipt_totals: Arc::new(Mutex::new(ReceiptTotals::new(
+                MAX_RECEIPT_CONNECTIONS,
+                RECEIPT_TOTALS_TTL,
+            ))),
+            data_connections: None,
+            connection_store: None,
         }
     }
+
+    /// Keeps the state of each connection in the store, instead of fulfilling
+    /// every packet the receiver can decrypt.
+    ///
+    /// The amount of a packet is added to the connection's total in the store before
+    /// the packet is fulfilled. Packets which would take a connection over its
+    /// `receive_max` are rejected with the connection's limit in a `StreamMaxMoney` frame,
+    /// and the receiver replies with a `ConnectionClose` frame once the connection
+    /// received exactly that much or after it was closed. Payment notifications
+    /// are only published when a connection closes, with the total it received.
+    pub fn with_connection_state(mut self) -> Self
+    where
+        S: StreamConnectionStore + Clone + Send + Sync + 'static,
+    {
+        self.connection_store = Some(Arc::new(self.store.clone()));
+        self
+    }
+
+    /// Starts handling the data sent via STREAM and returns the connections
+    /// which senders open, once they send their first data.
+    ///
+    /// Calling this again replaces the previous receiver of connections.
+    pub fn incoming_connections(&mut self) -> UnboundedReceiver<StreamConnection> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.data_connections = Some(DataConnections {
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            incoming: sender,
+        });
+        receiver
+    }
+}
+
+/// The connections over which senders sent data, keyed by connection token
+#[derive(Clone)]
+struct DataConnections {
+    connections: Arc<Mutex<HashMap<String, DataConnection>>>,
+    incoming: UnboundedSender<StreamConnection>,
+}
+
+struct DataConnection {
+    connection: SharedConnection,
+    last_packet: Instant,
+}
+
+impl DataConnections {
+    /// Applies the data frames of a packet to its connection and returns the
+    /// frames to send back
+    fn handle_packet(
+        &self,
+        destination: &Address,
+        stream_packet: &StreamPacket,
+    ) -> Vec<OutgoingFrame> {
+        // Money is sent by `send_money`, whose packets are not part of the
+        // data connection's sequence
+        let has_money = stream_packet
+            .frames()
+            .any(|frame| matches!(frame, Frame::StreamMoney(_)));
+        if has_money {
+            return Vec::new();
+        }
+        let token = connection_token(destination);
+        let mut connections = self.connections.lock();
+        let connection = match connections.get_mut(token) {
+            Some(existing) => {
+                existing.last_packet = Instant::now();
+                existing.connection.clone()
+            }
+            None => {
+                let has_data = stream_packet
+                    .frames()
+                    .any(|frame| matches!(frame, Frame::StreamData(_)));
+                if !has_data {
+                    return Vec::new();
+                }
+                connections.retain(|_, existing| {
+                    let idle = existing.last_packet.elapsed() >= DATA_CONNECTION_IDLE_TIMEOUT;
+                    if idle {
+                        existing
+                            .connection
+                            .close(Err("Connection timed out".to_string()));
+                    }
+                    !idle
+                });
+                if connections.len() >= MAX_DATA_CONNECTIONS {
+                    // Closed connections are only kept to answer the senders again
+                    // in case our ConnectionClose got lost
+                    connections.retain(|_, existing| !existing.connection.is_closed());
+                }
+                if connections.len() >= MAX_DATA_CONNECTIONS {
+                    debug!("Refusing STREAM data connection because there are too many");
+                    return vec![OutgoingFrame::ConnectionClose {
+                        code: StreamErrorCode::EndpointBusy,
+                    }];
+                }
+                let (stream_connection, connection) =
+                    StreamConnection::new_receiver(destination.clone());
+                if self.incoming.unbounded_send(stream_connection).is_err() {
+                    debug!("Ignoring STREAM data because no one is accepting connections");
+                    return Vec::new();
+                }
+                connections.insert(
+                    token.to_string(),
+                    DataConnection {
+                        connection: connection.clone(),
+                        last_packet: Instant::now(),
+                    },
+                );
+                connection
+            }
+        };
+        drop(connections);
+
+        connection.reply(
+            stream_packet.sequence(),
+            stream_packet.frames(),
+            MAX_REPLY_DATA,
+        )
+    }
+}
+
+/// The receiver's data connections and the destination of the packet being handled
+struct ConnectionData<'a> {
+    connections: &'a DataConnections,
+    destination: &'a Address,
 }
 
 #[async_trait]
@@ -190,13 +555,101 @@ where
         // The case where the request is bound for this server
         if dest.starts_with(to_address.as_ref()) {
             let shared_secret = self.connection_generator.rederive_secret(&destination);
-            let response = receive_money(
-                &shared_secret,
-                &to_address,
-                request.to.asset_code(),
-                request.to.asset_scale(),
-                &request.prepare,
-            );
+            let receipt_details = self.connection_generator.receipt_details(&destination);
+            let token = connection_token(&destination).to_string();
+
+            let connection_state = match self.connection_store {
+                Some(ref store) => {
+                    match reserve_amount(store.as_ref(), &token, &shared_secret, &request.prepare)
+                        .await
+                    {
+                        Ok(record) => Some(record),
+                        Err(err) => {
+                            error!("Error loading STREAM connection {}: {}", token, err);
+                            return Err(RejectBuilder {
+                                code: ErrorCode::T00_INTERNAL_ERROR,
+                                message: &[],
+                                triggered_by: Some(&to_address),
+                                data: &[],
+                            }
+                            .build());
+                        }
+                    }
+                }
+                None => None,
+            };
+
+            let response = {
+                // Only the connections set up with receipts have their totals kept
+                let mut receipt_totals = match receipt_details {
+                    Some(_) => Some(self.receipt_totals.lock()),
+                    None => None,
+                };
+                let receipts = match (receipt_details, receipt_totals.as_mut()) {
+                    (Some(details), Some(totals)) => Some(ConnectionReceipts {
+                        details,
+                        totals: totals.get_mut(&token),
+                    }),
+                    _ => None,
+                };
+                let response = receive_money(
+                    &shared_secret,
+                    &to_address,
+                    request.to.asset_code(),
+                    request.to.asset_scale(),
+                    &request.prepare,
+                    receipts,
+                    self.data_connections
+                        .as_ref()
+                        .map(|connections| ConnectionData {
+                            connections,
+                            destination: &destination,
+                        }),
+                    connection_state,
+                );
+                let closed = match response {
+                    Ok(ref ok) => ok.connection_closed,
+                    Err(ref err) => err.connection_closed,
+                };
+                if let (true, Some(receipt_totals)) = (closed, receipt_totals.as_mut()) {
+                    receipt_totals.remove(&token);
+                }
+                response
+            };
+
+            if let Some(store) = self.connection_store.clone() {
+                if let Err(ref err) = response {
+                    if err.reject.code() == ErrorCode::F06_UNEXPECTED_PAYMENT {
+                        return self.next.send_request(request).await;
+                    }
+                }
+                let (closed, sequence) = match response {
+                    Ok(ref ok) => (ok.connection_closed, ok.sequence),
+                    Err(ref err) => (err.connection_closed, err.sequence),
+                };
+                if closed {
+                    // Publish a single notification with everything the connection received
+                    match store.close_stream_connection(&token).await {
+                        Ok(Some(record)) => {
+                            self.store
+                                .publish_payment_notification(PaymentNotification {
+                                    to_username,
+                                    from_username,
+                                    amount: record.total_received,
+                                    destination,
+                                    timestamp: DateTime::<Utc>::from(SystemTime::now())
+                                        .to_rfc3339(),
+                                    sequence,
+                                    connection_closed: true,
+                                })
+                        }
+                        Ok(None) => {}
+                        Err(err) => error!("Error closing STREAM connection {}: {}", token, err),
+                    }
+                }
+                return response.map(|x| x.fulfill).map_err(|x| x.reject);
+            }
+
             match response {
                 Ok(ref ok) => self
                     .store
@@ -238,8 +691,68 @@ where
     }
 }
 
+/// Loads the state of a stateful receiver's connection to handle the packet with.
+///
+/// If the packet is one we would fulfill, its amount is added to the connection's total
+/// in the store first, so that it is only fulfilled (and counted in receipts) once the store
+/// took it. This returns the state from before the amount was added or, if the store refused
+/// it because another packet got there first, the state which gets the packet rejected.
+async fn reserve_amount(
+    store: &(dyn StreamConnectionStore + Send + Sync),
+    token: &str,
+    shared_secret: &[u8; 32],
+    prepare: &Prepare,
+) -> Result<StreamConnectionRecord, StreamConnectionStoreError> {
+    let amount = prepare.amount();
+    if amount == 0 || !is_fulfillable(shared_secret, prepare) {
+        return Ok(store
+            .get_stream_connection(token)
+            .await?
+            .unwrap_or_default());
+    }
+    match store.add_stream_received(token, amount).await {
+        Ok(record) => Ok(StreamConnectionRecord {
+            total_received: record.total_received.saturating_sub(amount),
+            ..record
+        }),
+        Err(StreamConnectionStoreError::ReceiveMaxExceeded {
+            total_received,
+            receive_max,
+            ..
+        }) => Ok(StreamConnectionRecord {
+            total_received,
+            receive_max: Some(receive_max),
+            closed: false,
+        }),
+        Err(StreamConnectionStoreError::ConnectionClosed(_)) => Ok(StreamConnectionRecord {
+            closed: true,
+            ..store
+                .get_stream_connection(token)
+                .await?
+                .unwrap_or_default()
+        }),
+        Err(err) => Err(err),
+    }
+}
+
+/// Whether the packet's condition was generated with the shared secret and the packet
+/// carries at least the amount its sender asks us to receive
+fn is_fulfillable(shared_secret: &[u8; 32], prepare: &Prepare) -> bool {
+    let fulfillment = generate_fulfillment(&shared_secret[..], prepare.data());
+    hash_sha256(&fulfillment) == prepare.execution_condition()
+        && StreamPacket::from_encrypted(shared_secret, BytesMut::from(prepare.data()))
+            .map(|stream_packet| prepare.amount() >= stream_packet.prepare_amount())
+            .unwrap_or(false)
+}
+
+/// Receipt details of a connection together with the totals of its streams
+struct ConnectionReceipts<'a> {
+    details: ReceiptDetails,
+    totals: &'a mut StreamTotals,
+}
+
 // TODO send asset code and scale back to sender also
-#[allow(clippy::cognitive_complexity)]
+#[allow(clippy::cognitive_complexity, clippy::too_many_arguments)]
 fn receive_money(
     shared_secret: &[u8; 32],
     // Our node's ILP Address ( we are the receiver, so we should return that
@@ -248,6 +761,10 @@ fn receive_money(
     asset_code: &str,
     asset_scale: u8,
     prepare: &Prepare,
+    receipts: Option<ConnectionReceipts>,
+    data: Option<ConnectionData>,
+    // The state of the connection, if the receiver keeps it
+    connection_state: Option<StreamConnectionRecord>,
 ) -> Result<ReceiveOk, ReceiveErr> {
     // Generate fulfillment
     let fulfillment = generate_fulfillment(&shared_secret[..], prepare.data());
@@ -283,20 +800,67 @@ fn receive_money(
         }
     })?;
 
+    let mut will_fulfill = is_fulfillable && prepare_amount >= stream_packet.prepare_amount();
+    // Whether we tell the sender that the connection is closed
+    let mut close_connection = false;
+    if let Some(state) = connection_state {
+        if state.closed {
+            debug!("Rejecting packet for a closed connection");
+            will_fulfill = false;
+            close_connection = true;
+        } else if let Some(remaining) = state.remaining() {
+            if prepare_amount > remaining {
+                debug!(
+                    "Rejecting packet for {} because the connection may only receive {} more",
+                    prepare_amount, remaining
+                );
+                will_fulfill = false;
+            } else if will_fulfill && prepare_amount == remaining {
+                close_connection = true;
+            }
+        }
+    }
+    // Receipts are only issued for money we actually receive
+    let signed_receipts = match receipts {
+        Some(receipts) if will_fulfill => sign_receipts(receipts, prepare_amount, &stream_packet),
+        _ => Vec::new(),
+    };
+
+    let data_frames = data
+        .map(|data| {
+            data.connections
+                .handle_packet(data.destination, &stream_packet)
+        })
+        .unwrap_or_default();
+
     let mut response_frames: Vec<Frame> = Vec::new();
     let mut connection_closed = false;
 
     // Handle STREAM frames
     // TODO reject if they send data?
     for frame in stream_packet.frames() {
-        // Tell the sender the stream can handle lots of money
         if let Frame::StreamMoney(ref frame) = frame {
-            response_frames.push(Frame::StreamMaxMoney(StreamMaxMoneyFrame {
-                stream_id: frame.stream_id,
-                // TODO will returning zero here cause problems?
-                total_received: 0,
-                receive_max: u64::max_value(),
-            }));
+            let max_money = match connection_state {
+                // Limits are kept per connection, so the sender gets the connection's
+                // totals for each of its streams
+                Some(state) => StreamMaxMoneyFrame {
+                    stream_id: frame.stream_id,
+                    total_received: if will_fulfill {
+                        state.total_received.saturating_add(prepare_amount)
+                    } else {
+                        state.total_received
+                    },
+                    receive_max: state.receive_max.unwrap_or_else(u64::max_value),
+                },
+                // Tell the sender the stream can handle lots of money
+                None => StreamMaxMoneyFrame {
+                    stream_id: frame.stream_id,
+                    // TODO will returning zero here cause problems?
+                    total_received: 0,
+                    receive_max: u64::max_value(),
+                },
+            };
+            response_frames.push(Frame::StreamMaxMoney(max_money));
         }
 
         // If we receive a ConnectionNewAddress frame, then send them our asset
@@ -318,8 +882,24 @@ fn receive_money(
         }
     }
 
+    response_frames.extend(data_frames.iter().map(OutgoingFrame::as_frame));
+
+    if close_connection {
+        response_frames.push(Frame::ConnectionClose(ConnectionCloseFrame {
+            code: StreamErrorCode::NoError,
+            message: "",
+        }));
+    }
+
+    for (stream_id, receipt) in &signed_receipts {
+        response_frames.push(Frame::StreamReceipt(StreamReceiptFrame {
+            stream_id: *stream_id,
+            receipt: &receipt[..],
+        }));
+    }
+
     // Return Fulfill or Reject Packet
-    if is_fulfillable && prepare_amount >= stream_packet.prepare_amount() {
+    if will_fulfill {
         let response_packet = StreamPacketBuilder {
             sequence: stream_packet.sequence(),
             ilp_packet_type: IlpPacketType::Fulfill,
@@ -342,6 +922,7 @@ fn receive_money(
         Ok(ReceiveOk {
             fulfill,
             sequence: stream_packet.sequence(),
+            connection_closed: connection_closed || close_connection,
         })
     } else {
         let response_packet = StreamPacketBuilder {
@@ -375,11 +956,55 @@ fn receive_money(
         Err(ReceiveErr {
             reject,
             sequence: stream_packet.sequence(),
-            connection_closed,
+            connection_closed: connection_closed || close_connection,
         })
     }
 }
 
+/// Splits the amount between the packet's streams in proportion to their shares,
+/// adds it to the streams' totals and returns a signed receipt for each stream
+fn sign_receipts(
+    receipts: ConnectionReceipts,
+    amount: u64,
+    stream_packet: &StreamPacket,
+) -> Vec<(u64, Bytes)> {
+    let ConnectionReceipts { details, totals } = receipts;
+    let streams: Vec<(u64, u64)> = stream_packet
+        .frames()
+        .filter_map(|frame| match frame {
+            Frame::StreamMoney(frame) => Some((frame.stream_id, frame.shares)),
+            _ => None,
+        })
+        .collect();
+    let total_shares: u128 = streams.iter().map(|(_, shares)| u128::from(*shares)).sum();
+
+    let mut remaining = amount;
+    streams
+        .iter()
+        .enumerate()
+        .map(|(i, (stream_id, shares))| {
+            // The last stream gets whatever is left after rounding down the others' amounts
+            let stream_amount = if i + 1 == streams.len() {
+                remaining
+            } else {
+                (u128::from(amount) * u128::from(*shares))
+                    .checked_div(total_shares)
+                    .unwrap_or(0) as u64
+            };
+            remaining -= stream_amount;
+
+            let total_received = totals.entry(*stream_id).or_insert(0);
+            *total_received = total_received.saturating_add(stream_amount);
+            let receipt = Receipt {
+                nonce: details.nonce,
+                stream_id: *stream_id,
+                total_received: *total_received,
+            };
+            (*stream_id, receipt.sign(&details.secret))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod connection_generator {
     use super::*;
@@ -402,6 +1027,33 @@ mod connection_generator {
             connection_generator.rederive_secret(&destination_account),
             shared_secret
         );
+        assert!(connection_generator
+            .receipt_details(&destination_account)
+            .is_none());
+    }
+
+    #[test]
+    fn encodes_receipt_details_in_address() {
+        let receiver_address = Address::from_str("example.receiver").unwrap();
+        let connection_generator = ConnectionGenerator::new(Bytes::from(&[9; 32][..]));
+        let receipt_details = ReceiptDetails::random();
+        let (destination_account, shared_secret) = connection_generator
+            .generate_address_and_secret_with_receipts(&receiver_address, &receipt_details);
+
+        assert_eq!(
+            connection_generator.rederive_secret(&destination_account),
+            shared_secret
+        );
+        assert_eq!(
+            connection_generator.receipt_details(&destination_account),
+            Some(receipt_details)
+        );
+
+        // Another server secret can't recover the details
+        let other_generator = ConnectionGenerator::new(Bytes::from(&[8; 32][..]));
+        assert!(other_generator
+            .receipt_details(&destination_account)
+            .is_none());
     }
 }
 
@@ -450,7 +1102,16 @@ mod receiving_money {
         .build();
 
         let shared_secret = connection_generator.rederive_secret(&prepare.destination());
-        let result = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare);
+        let result = receive_money(
+            &shared_secret,
+            &ilp_address,
+            "ABC",
+            9,
+            &prepare,
+            None,
+            None,
+            None,
+        );
         assert!(result.is_ok());
     }
 
@@ -475,7 +1136,16 @@ mod receiving_money {
         .build();
 
         let shared_secret = connection_generator.rederive_secret(&prepare.destination());
-        let result = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare);
+        let result = receive_money(
+            &shared_secret,
+            &ilp_address,
+            "ABC",
+            9,
+            &prepare,
+            None,
+            None,
+            None,
+        );
         assert!(result.is_ok());
     }
 
@@ -501,7 +1171,16 @@ mod receiving_money {
         .build();
 
         let shared_secret = connection_generator.rederive_secret(&prepare.destination());
-        let result = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare);
+        let result = receive_money(
+            &shared_secret,
+            &ilp_address,
+            "ABC",
+            9,
+            &prepare,
+            None,
+            None,
+            None,
+        );
         assert!(result.is_err());
     }
 
@@ -537,7 +1216,16 @@ mod receiving_money {
         .build();
 
         let shared_secret = connection_generator.rederive_secret(&prepare.destination());
-        let result = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare);
+        let result = receive_money(
+            &shared_secret,
+            &ilp_address,
+            "ABC",
+            9,
+            &prepare,
+            None,
+            None,
+            None,
+        );
         assert!(result.is_err());
     }
 
@@ -555,9 +1243,18 @@ mod receiving_money {
             &hex!("b7d09d2e16e6f83c55b60e42fcd7c2b8ed49624a1df73c59b383dbe2e8690309")[..],
             "did not regenerate the same shared secret",
         );
-        let fulfill = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare)
-            .expect("Receiver should be able to generate the fulfillment")
-            .fulfill;
+        let fulfill = receive_money(
+            &shared_secret,
+            &ilp_address,
+            "ABC",
+            9,
+            &prepare,
+            None,
+            None,
+            None,
+        )
+        .expect("Receiver should be able to generate the fulfillment")
+        .fulfill;
         assert_eq!(
             &hash_sha256(fulfill.fulfillment())[..],
             &condition[..],
@@ -625,6 +1322,8 @@ mod stream_receiver_service {
             })
             .await;
         assert!(result.is_ok());
+        // Nothing is kept for the connections without receipts
+        assert!(service.receipt_totals.lock().connections.is_empty());
     }
 
     #[tokio::test]
@@ -750,4 +1449,410 @@ mod stream_receiver_service {
             Address::from_str("example.other-receiver").unwrap(),
         );
     }
+
+    #[tokio::test]
+    async fn attaches_receipts_with_the_total_received() {
+        let ilp_address = Address::from_str("example.destination").unwrap();
+        let server_secret = Bytes::from(&[1; 32][..]);
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let receipt_details = ReceiptDetails::random();
+        let (destination_account, shared_secret) = connection_generator
+            .generate_address_and_secret_with_receipts(&ilp_address, &receipt_details);
+
+        let mut service = StreamReceiverService::new(
+            server_secret,
+            DummyStore,
+            outgoing_service_fn(|_: OutgoingRequest<TestAccount>| -> IlpResult {
+                panic!("shouldn't get here")
+            }),
+        );
+
+        for (sequence, expected_total) in [(1, 100), (2, 200)].iter() {
+            let data = StreamPacketBuilder {
+                ilp_packet_type: IlpPacketType::Prepare,
+                prepare_amount: 0,
+                sequence: *sequence,
+                frames: &[Frame::StreamMoney(StreamMoneyFrame {
+                    stream_id: 1,
+                    shares: 1,
+                })],
+            }
+            .build()
+            .into_encrypted(&shared_secret[..]);
+            let prepare = PrepareBuilder {
+                destination: destination_account.clone(),
+                amount: 100,
+                expires_at: UNIX_EPOCH,
+                data: &data[..],
+                execution_condition: &generate_condition(&shared_secret[..], &data),
+            }
+            .build();
+
+            let account = TestAccount {
+                id: Uuid::new_v4(),
+                ilp_address: ilp_address.clone(),
+                asset_code: "XYZ".to_string(),
+                asset_scale: 9,
+                max_packet_amount: None,
+            };
+            let fulfill = service
+                .send_request(OutgoingRequest {
+                    from: account.clone(),
+                    to: account,
+                    original_amount: prepare.amount(),
+                    prepare,
+                })
+                .await
+                .unwrap();
+
+            let response =
+                StreamPacket::from_encrypted(&shared_secret, BytesMut::from(fulfill.data()))
+                    .unwrap();
+            let receipt = response
+                .frames()
+                .find_map(|frame| match frame {
+                    Frame::StreamReceipt(frame) => Some(frame.receipt.to_vec()),
+                    _ => None,
+                })
+                .unwrap();
+            let receipt = Receipt::verify(&receipt, &receipt_details.secret).unwrap();
+            assert_eq!(receipt.nonce, receipt_details.nonce);
+            assert_eq!(receipt.stream_id, 1);
+            assert_eq!(receipt.total_received, *expected_total);
+        }
+        assert_eq!(service.receipt_totals.lock().connections.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn limits_connections_in_stateful_mode() {
+        let ilp_address = Address::from_str("example.destination").unwrap();
+        let server_secret = Bytes::from(&[1; 32][..]);
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&ilp_address);
+
+        let store = ConnectionStateStore::default();
+        store
+            .set_stream_receive_max(connection_token(&destination_account), 150)
+            .await
+            .unwrap();
+        let service = StreamReceiverService::new(
+            server_secret,
+            store.clone(),
+            outgoing_service_fn(|_: OutgoingRequest<TestAccount>| -> IlpResult {
+                panic!("shouldn't get here")
+            }),
+        )
+        .with_connection_state();
+
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: ilp_address.clone(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+        };
+        let send = |sequence: u64, amount: u64| {
+            let data = StreamPacketBuilder {
+                ilp_packet_type: IlpPacketType::Prepare,
+                prepare_amount: 0,
+                sequence,
+                frames: &[Frame::StreamMoney(StreamMoneyFrame {
+                    stream_id: 1,
+                    shares: 1,
+                })],
+            }
+            .build()
+            .into_encrypted(&shared_secret[..]);
+            let prepare = PrepareBuilder {
+                destination: destination_account.clone(),
+                amount,
+                expires_at: UNIX_EPOCH,
+                data: &data[..],
+                execution_condition: &generate_condition(&shared_secret[..], &data),
+            }
+            .build();
+            let request = OutgoingRequest {
+                from: account.clone(),
+                to: account.clone(),
+                original_amount: prepare.amount(),
+                prepare,
+            };
+            let mut service = service.clone();
+            async move {
+                let reply = service.send_request(request).await;
+                let data = match reply {
+                    Ok(ref fulfill) => fulfill.data(),
+                    Err(ref reject) => reject.data(),
+                };
+                let packet =
+                    StreamPacket::from_encrypted(&shared_secret, BytesMut::from(data)).unwrap();
+                let max_money = packet.frames().find_map(|frame| match frame {
+                    Frame::StreamMaxMoney(frame) => Some((frame.total_received, frame.receive_max)),
+                    _ => None,
+                });
+                let closed = packet
+                    .frames()
+                    .any(|frame| matches!(frame, Frame::ConnectionClose(_)));
+                (reply.is_ok(), max_money, closed)
+            }
+        };
+
+        assert_eq!(send(1, 100).await, (true, Some((100, 150)), false));
+        // Would go over the connection's receive max
+        assert_eq!(send(2, 100).await, (false, Some((100, 150)), false));
+        assert!(store.notifications.lock().is_empty());
+        // Receiving exactly the rest closes the connection
+        assert_eq!(send(3, 50).await, (true, Some((150, 150)), true));
+        assert_eq!(send(4, 0).await, (false, Some((150, 150)), true));
+
+        let notifications = store.notifications.lock();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].amount, 150);
+        assert!(notifications[0].connection_closed);
+    }
+
+    #[tokio::test]
+    async fn reserves_the_amount_before_fulfilling() {
+        /// Reads a connection as if the packets of other receivers had not been stored yet
+        #[derive(Clone)]
+        struct StaleStore(ConnectionStateStore);
+
+        impl StreamNotificationsStore for StaleStore {
+            type Account = TestAccount;
+
+            fn add_payment_notification_subscription(
+                &self,
+                account_id: Uuid,
+                sender: UnboundedSender<PaymentNotification>,
+            ) {
+                self.0
+                    .add_payment_notification_subscription(account_id, sender)
+            }
+
+            fn publish_payment_notification(&self, payment: PaymentNotification) {
+                self.0.publish_payment_notification(payment)
+            }
+
+            fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification> {
+                self.0.all_payment_subscription()
+            }
+        }
+
+        #[async_trait]
+        impl StreamConnectionStore for StaleStore {
+            async fn set_stream_receive_max(
+                &self,
+                token: &str,
+                receive_max: u64,
+            ) -> Result<(), StreamConnectionStoreError> {
+                self.0.set_stream_receive_max(token, receive_max).await
+            }
+
+            async fn get_stream_connection(
+                &self,
+                _token: &str,
+            ) -> Result<Option<StreamConnectionRecord>, StreamConnectionStoreError> {
+                Ok(None)
+            }
+
+            async fn add_stream_received(
+                &self,
+                token: &str,
+                amount: u64,
+            ) -> Result<StreamConnectionRecord, StreamConnectionStoreError> {
+                self.0.add_stream_received(token, amount).await
+            }
+
+            async fn close_stream_connection(
+                &self,
+                token: &str,
+            ) -> Result<Option<StreamConnectionRecord>, StreamConnectionStoreError> {
+                self.0.close_stream_connection(token).await
+            }
+        }
+
+        let ilp_address = Address::from_str("example.destination").unwrap();
+        let server_secret = Bytes::from(&[1; 32][..]);
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let receipt_details = ReceiptDetails::random();
+        let (destination_account, shared_secret) = connection_generator
+            .generate_address_and_secret_with_receipts(&ilp_address, &receipt_details);
+
+        let store = StaleStore(ConnectionStateStore::default());
+        store
+            .set_stream_receive_max(connection_token(&destination_account), 150)
+            .await
+            .unwrap();
+        let service = StreamReceiverService::new(
+            server_secret,
+            store.clone(),
+            outgoing_service_fn(|_: OutgoingRequest<TestAccount>| -> IlpResult {
+                panic!("shouldn't get here")
+            }),
+        )
+        .with_connection_state();
+
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: ilp_address.clone(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+        };
+        let send = |sequence: u64, amount: u64| {
+            let data = StreamPacketBuilder {
+                ilp_packet_type: IlpPacketType::Prepare,
+                prepare_amount: 0,
+                sequence,
+                frames: &[Frame::StreamMoney(StreamMoneyFrame {
+                    stream_id: 1,
+                    shares: 1,
+                })],
+            }
+            .build()
+            .into_encrypted(&shared_secret[..]);
+            let prepare = PrepareBuilder {
+                destination: destination_account.clone(),
+                amount,
+                expires_at: UNIX_EPOCH,
+                data: &data[..],
+                execution_condition: &generate_condition(&shared_secret[..], &data),
+            }
+            .build();
+            let request = OutgoingRequest {
+                from: account.clone(),
+                to: account.clone(),
+                original_amount: prepare.amount(),
+                prepare,
+            };
+            let mut service = service.clone();
+            let receipt_secret = receipt_details.secret;
+            async move {
+                let reply = service.send_request(request).await;
+                let data = match reply {
+                    Ok(ref fulfill) => fulfill.data(),
+                    Err(ref reject) => reject.data(),
+                };
+                let packet =
+                    StreamPacket::from_encrypted(&shared_secret, BytesMut::from(data)).unwrap();
+                let max_money = packet.frames().find_map(|frame| match frame {
+                    Frame::StreamMaxMoney(frame) => Some((frame.total_received, frame.receive_max)),
+                    _ => None,
+                });
+                let receipt_total = packet.frames().find_map(|frame| match frame {
+                    Frame::StreamReceipt(frame) => Some(
+                        Receipt::verify(frame.receipt, &receipt_secret)
+                            .unwrap()
+                            .total_received,
+                    ),
+                    _ => None,
+                });
+                (reply.is_ok(), max_money, receipt_total)
+            }
+        };
+
+        assert_eq!(send(1, 100).await, (true, Some((100, 150)), Some(100)));
+        // The stale state lets the packet through, but the store refuses it
+        assert_eq!(send(2, 100).await, (false, Some((100, 150)), None));
+        assert_eq!(send(3, 50).await, (true, Some((150, 150)), Some(150)));
+        assert_eq!(
+            store.0.connections.lock()[connection_token(&destination_account)].total_received,
+            150
+        );
+    }
+}
+
+#[cfg(test)]
+mod receipt_totals {
+    use super::*;
+
+    #[test]
+    fn drops_the_least_recently_paid_connection() {
+        let mut totals = ReceiptTotals::new(2, Duration::from_secs(3600));
+        totals.get_mut("first").insert(1, 100);
+        totals.get_mut("second").insert(1, 100);
+        // Paying the first connection again makes the second one the oldest
+        *totals.get_mut("first").get_mut(&1).unwrap() += 100;
+        totals.get_mut("third").insert(1, 100);
+
+        assert_eq!(totals.connections.len(), 2);
+        assert!(!totals.connections.contains_key("second"));
+        assert_eq!(totals.get_mut("first")[&1], 200);
+        assert_eq!(totals.get_mut("third")[&1], 100);
+    }
+
+    #[test]
+    fn drops_expired_connections_when_full() {
+        let mut totals = ReceiptTotals::new(2, Duration::from_secs(0));
+        totals.get_mut("first").insert(1, 100);
+        totals.get_mut("second").insert(1, 100);
+        totals.get_mut("third").insert(1, 100);
+
+        assert_eq!(totals.connections.len(), 1);
+        assert!(totals.connections.contains_key("third"));
+    }
+}
+
+#[cfg(test)]
+mod data_connections {
+    use super::*;
+    use std::str::FromStr;
+
+    fn data_packet(sequence: u64, frames: &[Frame]) -> StreamPacket {
+        StreamPacketBuilder {
+            ilp_packet_type: IlpPacketType::Prepare,
+            prepare_amount: 0,
+            sequence,
+            frames,
+        }
+        .build()
+    }
+
+    #[test]
+    fn refuses_connections_beyond_the_limit() {
+        let (incoming, _accepted) = mpsc::unbounded();
+        let data_connections = DataConnections {
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            incoming,
+        };
+        let destination =
+            |i: usize| Address::from_str(&format!("example.receiver.connection{}", i)).unwrap();
+        let data = [Frame::StreamData(StreamDataFrame {
+            stream_id: 1,
+            offset: 0,
+            data: b"hello",
+        })];
+
+        for i in 0..MAX_DATA_CONNECTIONS {
+            let reply = data_connections.handle_packet(&destination(i), &data_packet(1, &data));
+            assert!(reply.is_empty());
+        }
+        let busy = vec![OutgoingFrame::ConnectionClose {
+            code: StreamErrorCode::EndpointBusy,
+        }];
+        let reply = data_connections
+            .handle_packet(&destination(MAX_DATA_CONNECTIONS), &data_packet(1, &data));
+        assert_eq!(reply, busy);
+
+        // A connection which the sender closed makes room for another one
+        let close = [Frame::ConnectionClose(ConnectionCloseFrame {
+            code: StreamErrorCode::NoError,
+            message: "",
+        })];
+        let reply = data_connections.handle_packet(&destination(0), &data_packet(2, &close));
+        assert_eq!(
+            reply,
+            vec![OutgoingFrame::ConnectionClose {
+                code: StreamErrorCode::NoError,
+            }]
+        );
+        let reply = data_connections
+            .handle_packet(&destination(MAX_DATA_CONNECTIONS), &data_packet(1, &data));
+        assert!(reply.is_empty());
+        assert_eq!(
+            data_connections.connections.lock().len(),
+            MAX_DATA_CONNECTIONS
+        );
+    }
 }
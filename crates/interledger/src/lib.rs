@@ -82,7 +82,7 @@ pub mod rates {
     //! # interledger-rates
     //!
     //! Utilities for fetching and caching exchange rates from external APIs,
-    //! which supports CoinCap and CryptoCompare rate backends.
+    //! which supports CoinCap, CryptoCompare, generic HTTP/JSON and file rate backends.
     pub use interledger_rates::*;
 }
 